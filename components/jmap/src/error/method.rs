@@ -34,6 +34,7 @@ pub enum MethodError {
     RequestTooLarge,
     StateMismatch,
     AnchorNotFound,
+    CannotCalculateChanges,
     UnsupportedFilter(String),
     UnsupportedSort(String),
     ServerFail(StoreError),
@@ -69,6 +70,7 @@ impl Display for MethodError {
             MethodError::RequestTooLarge => write!(f, "Request too large"),
             MethodError::StateMismatch => write!(f, "State mismatch"),
             MethodError::AnchorNotFound => write!(f, "Anchor not found"),
+            MethodError::CannotCalculateChanges => write!(f, "Cannot calculate changes"),
             MethodError::UnsupportedFilter(err) => write!(f, "Unsupported filter: {}", err),
             MethodError::UnsupportedSort(err) => write!(f, "Unsupported sort: {}", err),
             MethodError::ServerFail(err) => write!(f, "Server error: {}", err),
@@ -121,6 +123,13 @@ impl Serialize for MethodError {
                     "cannot be found in the results of the query."
                 ),
             ),
+            MethodError::CannotCalculateChanges => (
+                "cannotCalculateChanges",
+                concat!(
+                    "The server cannot calculate the changes ",
+                    "from the state string given by the client."
+                ),
+            ),
             MethodError::UnsupportedFilter(description) => {
                 ("unsupportedFilter", description.as_str())
             }
@@ -80,6 +80,10 @@ pub enum SetErrorType {
     ForbiddenToSend,
     #[serde(rename = "cannotUnsend")]
     CannotUnsend,
+    #[serde(rename = "addressBookHasContents")]
+    AddressBookHasContents,
+    #[serde(rename = "calendarHasEvent")]
+    CalendarHasEvent,
 }
 
 impl SetErrorType {
@@ -107,6 +111,8 @@ impl SetErrorType {
             SetErrorType::ForbiddenMailFrom => "forbiddenMailFrom",
             SetErrorType::ForbiddenToSend => "forbiddenToSend",
             SetErrorType::CannotUnsend => "cannotUnsend",
+            SetErrorType::AddressBookHasContents => "addressBookHasContents",
+            SetErrorType::CalendarHasEvent => "calendarHasEvent",
         }
     }
 }
@@ -28,6 +28,7 @@ use super::Object;
 use crate::error::set::SetError;
 use crate::request::set::SetResponse;
 use crate::request::{ArgumentDeserializer, MaybeIdReference, ResultReference};
+use crate::share_notification::notify::ShareChange;
 use crate::types::jmap::JMAPId;
 use crate::types::state::JMAPState;
 use crate::types::type_state::TypeState;
@@ -72,6 +73,8 @@ where
 
     pub change_id: ChangeId,
     pub state_changes: Vec<(TypeState, ChangeId)>,
+    pub linked_state_changes: Vec<(AccountId, Vec<(TypeState, ChangeId)>)>,
+    pub share_changes: Vec<ShareChange>,
 
     pub request: SetRequest<O>,
     pub response: SetResponse<O>,
@@ -86,6 +89,9 @@ where
         let collection = O::collection();
         let account_id = request.account_id.get_document_id();
 
+        // The state is read and compared while holding the collection lock,
+        // so no other write can happen between the check and this one.
+        let lock = store.lock_collection(account_id, collection);
         let old_state = store.get_state(account_id, collection)?;
         if let Some(if_in_state) = request.if_in_state.take() {
             if old_state != if_in_state {
@@ -99,7 +105,7 @@ where
             .unwrap_or_default();
         Ok(SetHelper {
             store,
-            lock: store.lock_collection(account_id, collection),
+            lock,
             changes: WriteBatch::new(account_id),
             document_ids: store
                 .get_document_ids(account_id, collection)?
@@ -109,6 +115,8 @@ where
             collection,
             change_id: ChangeId::MAX,
             state_changes: Vec::new(),
+            linked_state_changes: Vec::new(),
+            share_changes: Vec::new(),
             batch_writes: true,
             response: SetResponse {
                 account_id: request.account_id.into(),
@@ -123,6 +131,7 @@ where
                 next_call: None,
                 change_id: None,
                 state_changes: None,
+                linked_state_changes: Vec::new(),
             },
             will_destroy,
             request,
@@ -187,16 +196,18 @@ where
 
             match create_fnc(&create_id, item, self, &mut document) {
                 Ok(result) => {
+                    let id = *result.id().unwrap();
                     self.document_ids.insert(document.document_id);
                     self.changes.insert_document(document);
-                    self.changes
-                        .log_insert(self.collection, result.id().unwrap());
+                    self.changes.log_insert(self.collection, id);
+                    self.write_share_notifications(id)?;
                     if !self.batch_writes {
                         self.write()?;
                     }
                     self.response.created.insert(create_id, result);
                 }
                 Err(err) => {
+                    self.share_changes.clear();
                     self.response.not_created.append(create_id, err);
                 }
             };
@@ -235,9 +246,11 @@ where
                         self.changes.update_document(document);
                         self.changes.log_update(self.collection, id);
                     }
+                    self.write_share_notifications(id)?;
                     self.response.updated.append(id, result);
                 }
                 Err(err) => {
+                    self.share_changes.clear();
                     self.response.not_updated.append(id, err);
                 }
             };
@@ -288,6 +301,30 @@ where
                     }
                 }
             }
+
+            // Keep track of changes written to other accounts
+            for (account_id, linked) in changes.linked {
+                let pos = if let Some(pos) = self
+                    .linked_state_changes
+                    .iter()
+                    .position(|e| e.0 == account_id)
+                {
+                    pos
+                } else {
+                    self.linked_state_changes.push((account_id, Vec::new()));
+                    self.linked_state_changes.len() - 1
+                };
+                let state_changes = &mut self.linked_state_changes[pos].1;
+                for collection in linked.collections {
+                    if let Ok(type_state) = TypeState::try_from(collection) {
+                        if let Some(entry) = state_changes.iter_mut().find(|e| e.0 == type_state) {
+                            entry.1 = linked.change_id;
+                        } else {
+                            state_changes.push((type_state, linked.change_id));
+                        }
+                    }
+                }
+            }
         }
         Ok(())
     }
@@ -302,6 +339,7 @@ where
             if !self.state_changes.is_empty() {
                 self.response.state_changes = self.state_changes.into();
             }
+            self.response.linked_state_changes = self.linked_state_changes;
         }
 
         Ok(self.response)
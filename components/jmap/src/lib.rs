@@ -27,6 +27,7 @@ pub mod orm;
 pub mod principal;
 pub mod push_subscription;
 pub mod request;
+pub mod share_notification;
 pub mod types;
 
 pub use base64;
@@ -50,6 +51,10 @@ pub enum URI {
     Calendars,
     #[serde(rename(serialize = "urn:ietf:params:jmap:websocket"))]
     WebSocket,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:principals"))]
+    Principals,
+    #[serde(rename(serialize = "urn:ietf:params:jmap:smimeverify"))]
+    SmimeVerify,
 }
 
 pub type Result<T> = std::result::Result<T, MethodError>;
@@ -123,6 +123,33 @@ where
             .map(|acl| (acl.id, acl.acl.clone().into_iter().collect()))
     }
 
+    pub fn get_acl_changes(
+        &self,
+        current: Option<&Self>,
+    ) -> Vec<(AccountId, Bitmap<ACL>, Bitmap<ACL>)> {
+        let current_acls = current.map_or(&[][..], |current| &current.acls[..]);
+        let mut changes = Vec::new();
+
+        for permission in current_acls {
+            let new_acl = self
+                .acls
+                .iter()
+                .find(|p| p.id == permission.id)
+                .map(|p| p.acl.clone())
+                .unwrap_or_default();
+            if new_acl != permission.acl {
+                changes.push((permission.id, permission.acl.clone(), new_acl));
+            }
+        }
+        for permission in &self.acls {
+            if !current_acls.iter().any(|p| p.id == permission.id) {
+                changes.push((permission.id, Bitmap::new(), permission.acl.clone()));
+            }
+        }
+
+        changes
+    }
+
     pub fn get_changed_acls(&self, changes: Option<&Self>) -> Option<Vec<Permission>> {
         if let Some(changes) = changes {
             if changes.acls != self.acls {
@@ -108,6 +108,10 @@ where
     fn index_as(&self) -> Index;
     fn is_empty(&self) -> bool;
     fn len(&self) -> usize;
+
+    fn as_text(&self) -> Option<&str> {
+        None
+    }
 }
 
 impl Value for () {
@@ -50,6 +50,10 @@ impl Display for Property {
             Property::Members => f.write_str("members"),
             Property::Aliases => f.write_str("aliases"),
             Property::ACL => f.write_str("acl"),
+            Property::SpamFilter => f.write_str("spamFilter"),
+            Property::DuplicateWindow => f.write_str("duplicateWindow"),
+            Property::EncryptionKey => f.write_str("encryptionKey"),
+            Property::LegalHold => f.write_str("legalHold"),
             Property::Invalid => Ok(()),
         }
     }
@@ -78,6 +82,10 @@ impl From<FieldId> for Property {
             11 => Property::Picture,
             12 => Property::Members,
             13 => Property::ACL,
+            14 => Property::SpamFilter,
+            15 => Property::DuplicateWindow,
+            16 => Property::EncryptionKey,
+            17 => Property::LegalHold,
             _ => Property::Invalid,
         }
     }
@@ -111,6 +119,10 @@ impl Property {
             "picture" => Property::Picture,
             "members" => Property::Members,
             "acl" => Property::ACL,
+            "spamFilter" => Property::SpamFilter,
+            "duplicateWindow" => Property::DuplicateWindow,
+            "encryptionKey" => Property::EncryptionKey,
+            "legalHold" => Property::LegalHold,
             _ => Property::Invalid,
         }
     }
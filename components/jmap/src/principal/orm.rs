@@ -27,13 +27,15 @@ use store::{
 };
 
 use crate::{
-    jmap_store::{get::GetObject, query::QueryObject, set::SetObject, Object},
+    jmap_store::{
+        changes::ChangesObject, get::GetObject, query::QueryObject, set::SetObject, Object,
+    },
     orm,
     request::ResultReference,
     types::{blob::JMAPBlob, jmap::JMAPId},
 };
 
-use super::schema::{Comparator, Filter, Patch, Principal, Property, Type, Value};
+use super::schema::{Comparator, Filter, Patch, Principal, Property, SpamFilter, Type, Value};
 
 impl orm::Value for Value {
     fn index_as(&self) -> orm::Index {
@@ -91,14 +93,23 @@ impl orm::Value for Value {
                 value.dkim_selector.as_ref().map(|s| s.len()).unwrap_or(0)
                     + std::mem::size_of::<i64>()
             }
+            Value::SpamFilter { .. } => std::mem::size_of::<SpamFilter>(),
             Value::Members { value } => value.len() * std::mem::size_of::<JMAPId>(),
             Value::ACL(value) => value.iter().fold(0, |acc, (k, v)| {
                 acc + k.len() + v.len() * std::mem::size_of::<ACL>()
             }),
             Value::Patch(_) => std::mem::size_of::<Patch>(),
+            Value::Bool { .. } => std::mem::size_of::<bool>(),
             Value::Null => 0,
         }
     }
+
+    fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text { value } => Some(value),
+            _ => None,
+        }
+    }
 }
 
 impl Default for Value {
@@ -169,6 +180,7 @@ impl Object for Principal {
             (Property::Timezone, 100),
             (Property::Secret, 2048),
             (Property::DKIM, 100),
+            (Property::EncryptionKey, 100 * 1024),
         ]
     }
 }
@@ -207,3 +219,7 @@ impl QueryObject for Principal {
 
     type Comparator = Comparator;
 }
+
+impl ChangesObject for Principal {
+    type ChangesResponse = ();
+}
@@ -51,7 +51,11 @@ pub enum Property {
     Picture = 11,
     Members = 12,
     ACL = 13,
-    Invalid = 14,
+    SpamFilter = 14,
+    DuplicateWindow = 15,
+    EncryptionKey = 16,
+    LegalHold = 17,
+    Invalid = 18,
 }
 
 pub const ACCOUNTS_TO_DELETE: u8 = u8::MAX;
@@ -82,6 +86,16 @@ pub struct DKIM {
     pub dkim_expiration: Option<i64>,
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpamFilter {
+    #[serde(rename = "junkScore")]
+    pub junk_score: Option<f64>,
+    #[serde(rename = "rejectScore")]
+    pub reject_score: Option<f64>,
+}
+
+impl Eq for SpamFilter {}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Value {
     Id { value: JMAPId },
@@ -91,10 +105,12 @@ pub enum Value {
     Number { value: i64 },
     Type { value: Type },
     DKIM { value: DKIM },
+    SpamFilter { value: SpamFilter },
     Members { value: Vec<JMAPId> },
     ACL(VecMap<String, Vec<ACL>>),
     Patch(Patch),
     Null,
+    Bool { value: bool },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
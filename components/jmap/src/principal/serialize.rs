@@ -32,7 +32,7 @@ use crate::{
     types::{blob::JMAPBlob, jmap::JMAPId, json_pointer::JSONPointer},
 };
 
-use super::schema::{Filter, Patch, Principal, Property, Type, Value, DKIM};
+use super::schema::{Filter, Patch, Principal, Property, SpamFilter, Type, Value, DKIM};
 
 // Principal de/serialization
 impl Serialize for Principal {
@@ -49,10 +49,12 @@ impl Serialize for Principal {
                 Value::Null => map.serialize_entry(name, &None::<&str>)?,
                 Value::TextList { value } => map.serialize_entry(name, value)?,
                 Value::Number { value } => map.serialize_entry(name, value)?,
+                Value::Bool { value } => map.serialize_entry(name, value)?,
                 Value::Type { value } => map.serialize_entry(name, value)?,
                 Value::Members { value } => map.serialize_entry(name, value)?,
                 Value::Blob { value } => map.serialize_entry(name, value)?,
                 Value::DKIM { value } => map.serialize_entry(name, value)?,
+                Value::SpamFilter { value } => map.serialize_entry(name, value)?,
                 Value::ACL(value) => map.serialize_entry(name, value)?,
                 Value::Patch(_) => (),
             }
@@ -170,6 +172,16 @@ impl<'de> serde::de::Visitor<'de> for PrincipalVisitor {
                         },
                     );
                 }
+                "spamFilter" => {
+                    properties.append(
+                        Property::SpamFilter,
+                        if let Some(value) = map.next_value::<Option<SpamFilter>>()? {
+                            Value::SpamFilter { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
                 "members" => {
                     properties.append(
                         Property::Members,
@@ -192,6 +204,38 @@ impl<'de> serde::de::Visitor<'de> for PrincipalVisitor {
                         },
                     );
                 }
+                "duplicateWindow" => {
+                    properties.append(
+                        Property::DuplicateWindow,
+                        if let Some(value) = map.next_value::<Option<u64>>()? {
+                            Value::Number {
+                                value: value as i64,
+                            }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                "legalHold" => {
+                    properties.append(
+                        Property::LegalHold,
+                        if let Some(value) = map.next_value::<Option<bool>>()? {
+                            Value::Bool { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                "encryptionKey" => {
+                    properties.append(
+                        Property::EncryptionKey,
+                        if let Some(value) = map.next_value::<Option<String>>()? {
+                            Value::Text { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
                 "picture" => {
                     properties.append(
                         Property::Picture,
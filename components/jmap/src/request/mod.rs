@@ -177,6 +177,7 @@ pub enum Method {
     SetMailbox,
     GetThread,
     ChangesThread,
+    QueryThread,
     GetEmail,
     ChangesEmail,
     QueryEmail,
@@ -196,9 +197,36 @@ pub enum Method {
     SetEmailSubmission,
     GetVacationResponse,
     SetVacationResponse,
+    GetSavedSearch,
+    ChangesSavedSearch,
+    SetSavedSearch,
+    GetAddressBook,
+    ChangesAddressBook,
+    SetAddressBook,
+    GetContactCard,
+    ChangesContactCard,
+    QueryContactCard,
+    QueryChangesContactCard,
+    SetContactCard,
+    CopyContactCard,
+    GetCalendar,
+    ChangesCalendar,
+    SetCalendar,
+    GetCalendarEvent,
+    ChangesCalendarEvent,
+    QueryCalendarEvent,
+    QueryChangesCalendarEvent,
+    SetCalendarEvent,
     GetPrincipal,
     SetPrincipal,
     QueryPrincipal,
+    ChangesPrincipal,
+    QueryChangesPrincipal,
+    GetShareNotification,
+    ChangesShareNotification,
+    QueryShareNotification,
+    QueryChangesShareNotification,
+    SetShareNotification,
     Error,
 }
 
@@ -220,6 +248,7 @@ impl serde::Serialize for Method {
             Method::SetMailbox => "Mailbox/set",
             Method::GetThread => "Thread/get",
             Method::ChangesThread => "Thread/changes",
+            Method::QueryThread => "Thread/query",
             Method::GetEmail => "Email/get",
             Method::ChangesEmail => "Email/changes",
             Method::QueryEmail => "Email/query",
@@ -239,9 +268,36 @@ impl serde::Serialize for Method {
             Method::SetEmailSubmission => "EmailSubmission/set",
             Method::GetVacationResponse => "VacationResponse/get",
             Method::SetVacationResponse => "VacationResponse/set",
+            Method::GetSavedSearch => "SavedSearch/get",
+            Method::ChangesSavedSearch => "SavedSearch/changes",
+            Method::SetSavedSearch => "SavedSearch/set",
+            Method::GetAddressBook => "AddressBook/get",
+            Method::ChangesAddressBook => "AddressBook/changes",
+            Method::SetAddressBook => "AddressBook/set",
+            Method::GetContactCard => "ContactCard/get",
+            Method::ChangesContactCard => "ContactCard/changes",
+            Method::QueryContactCard => "ContactCard/query",
+            Method::QueryChangesContactCard => "ContactCard/queryChanges",
+            Method::SetContactCard => "ContactCard/set",
+            Method::CopyContactCard => "ContactCard/copy",
+            Method::GetCalendar => "Calendar/get",
+            Method::ChangesCalendar => "Calendar/changes",
+            Method::SetCalendar => "Calendar/set",
+            Method::GetCalendarEvent => "CalendarEvent/get",
+            Method::ChangesCalendarEvent => "CalendarEvent/changes",
+            Method::QueryCalendarEvent => "CalendarEvent/query",
+            Method::QueryChangesCalendarEvent => "CalendarEvent/queryChanges",
+            Method::SetCalendarEvent => "CalendarEvent/set",
             Method::GetPrincipal => "Principal/get",
             Method::SetPrincipal => "Principal/set",
             Method::QueryPrincipal => "Principal/query",
+            Method::ChangesPrincipal => "Principal/changes",
+            Method::QueryChangesPrincipal => "Principal/queryChanges",
+            Method::GetShareNotification => "ShareNotification/get",
+            Method::ChangesShareNotification => "ShareNotification/changes",
+            Method::QueryShareNotification => "ShareNotification/query",
+            Method::QueryChangesShareNotification => "ShareNotification/queryChanges",
+            Method::SetShareNotification => "ShareNotification/set",
             Method::Error => "error",
         })
     }
@@ -272,6 +328,7 @@ impl<'de> serde::de::Visitor<'de> for MethodVisitor {
             "Mailbox/set" => Method::SetMailbox,
             "Thread/get" => Method::GetThread,
             "Thread/changes" => Method::ChangesThread,
+            "Thread/query" => Method::QueryThread,
             "Email/get" => Method::GetEmail,
             "Email/changes" => Method::ChangesEmail,
             "Email/query" => Method::QueryEmail,
@@ -291,9 +348,36 @@ impl<'de> serde::de::Visitor<'de> for MethodVisitor {
             "EmailSubmission/set" => Method::SetEmailSubmission,
             "VacationResponse/get" => Method::GetVacationResponse,
             "VacationResponse/set" => Method::SetVacationResponse,
+            "SavedSearch/get" => Method::GetSavedSearch,
+            "SavedSearch/changes" => Method::ChangesSavedSearch,
+            "SavedSearch/set" => Method::SetSavedSearch,
+            "AddressBook/get" => Method::GetAddressBook,
+            "AddressBook/changes" => Method::ChangesAddressBook,
+            "AddressBook/set" => Method::SetAddressBook,
+            "ContactCard/get" => Method::GetContactCard,
+            "ContactCard/changes" => Method::ChangesContactCard,
+            "ContactCard/query" => Method::QueryContactCard,
+            "ContactCard/queryChanges" => Method::QueryChangesContactCard,
+            "ContactCard/set" => Method::SetContactCard,
+            "ContactCard/copy" => Method::CopyContactCard,
+            "Calendar/get" => Method::GetCalendar,
+            "Calendar/changes" => Method::ChangesCalendar,
+            "Calendar/set" => Method::SetCalendar,
+            "CalendarEvent/get" => Method::GetCalendarEvent,
+            "CalendarEvent/changes" => Method::ChangesCalendarEvent,
+            "CalendarEvent/query" => Method::QueryCalendarEvent,
+            "CalendarEvent/queryChanges" => Method::QueryChangesCalendarEvent,
+            "CalendarEvent/set" => Method::SetCalendarEvent,
             "Principal/get" => Method::GetPrincipal,
             "Principal/set" => Method::SetPrincipal,
             "Principal/query" => Method::QueryPrincipal,
+            "Principal/changes" => Method::ChangesPrincipal,
+            "Principal/queryChanges" => Method::QueryChangesPrincipal,
+            "ShareNotification/get" => Method::GetShareNotification,
+            "ShareNotification/changes" => Method::ChangesShareNotification,
+            "ShareNotification/query" => Method::QueryShareNotification,
+            "ShareNotification/queryChanges" => Method::QueryChangesShareNotification,
+            "ShareNotification/set" => Method::SetShareNotification,
             _ => Method::Error,
         })
     }
@@ -0,0 +1,102 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::fmt::Display;
+
+use ::store::FieldId;
+
+use self::schema::Property;
+
+pub mod notify;
+pub mod orm;
+pub mod raft;
+pub mod schema;
+pub mod serialize;
+
+impl Display for Property {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Property::Id => f.write_str("id"),
+            Property::Created => f.write_str("created"),
+            Property::ChangedBy => f.write_str("changedBy"),
+            Property::ObjectType => f.write_str("objectType"),
+            Property::ObjectAccountId => f.write_str("objectAccountId"),
+            Property::ObjectId => f.write_str("objectId"),
+            Property::OldRights => f.write_str("oldRights"),
+            Property::NewRights => f.write_str("newRights"),
+            Property::Name => f.write_str("name"),
+            Property::Invalid => Ok(()),
+        }
+    }
+}
+
+impl From<Property> for FieldId {
+    fn from(property: Property) -> Self {
+        property as FieldId
+    }
+}
+
+impl From<FieldId> for Property {
+    fn from(field: FieldId) -> Self {
+        match field {
+            0 => Property::Id,
+            1 => Property::Created,
+            2 => Property::ChangedBy,
+            3 => Property::ObjectType,
+            4 => Property::ObjectAccountId,
+            5 => Property::ObjectId,
+            6 => Property::OldRights,
+            7 => Property::NewRights,
+            8 => Property::Name,
+            _ => Property::Invalid,
+        }
+    }
+}
+
+impl TryFrom<&str> for Property {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match Property::parse(value) {
+            Property::Invalid => Err(()),
+            property => Ok(property),
+        }
+    }
+}
+
+impl Property {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "id" => Property::Id,
+            "created" => Property::Created,
+            "changedBy" => Property::ChangedBy,
+            "objectType" => Property::ObjectType,
+            "objectAccountId" => Property::ObjectAccountId,
+            "objectId" => Property::ObjectId,
+            "oldRights" => Property::OldRights,
+            "newRights" => Property::NewRights,
+            "name" => Property::Name,
+            _ => Property::Invalid,
+        }
+    }
+}
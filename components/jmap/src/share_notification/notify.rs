@@ -0,0 +1,192 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, time::SystemTime};
+
+use store::{
+    core::{
+        acl::{ACLToken, ACL},
+        bitmap::Bitmap,
+        collection::Collection,
+        document::Document,
+    },
+    write::batch::WriteBatch,
+    AccountId, JMAPStore, Store,
+};
+
+use crate::{
+    jmap_store::set::{SetHelper, SetObject},
+    orm::{serialize::JMAPOrm, TinyORM, Value as _},
+    principal::schema::{Principal, Property as PrincipalProperty, Value as PrincipalValue},
+    request::ACLEnforce,
+    types::{date::JMAPDate, jmap::JMAPId, type_state::TypeState},
+    SUPERUSER_ID,
+};
+
+use super::schema::{ChangedBy, Property, ShareNotification, Value};
+
+#[derive(Debug, Clone)]
+pub struct ShareChange {
+    pub account_id: AccountId,
+    pub old_rights: Bitmap<ACL>,
+    pub new_rights: Bitmap<ACL>,
+    pub name: Option<String>,
+}
+
+impl<'y, O, T> SetHelper<'y, O, T>
+where
+    T: for<'x> Store<'x> + 'static,
+    O: SetObject,
+{
+    // Queues a notification for each account that was granted rights on the
+    // object, or had them changed or revoked. The object is named after the
+    // first of the given properties that is set. Notifications are only
+    // written once the create or update succeeds.
+    pub fn notify_acl_changes(
+        &mut self,
+        fields: &TinyORM<O>,
+        current_fields: Option<&TinyORM<O>>,
+        name_properties: &[O::Property],
+    ) {
+        let changes = fields.get_acl_changes(current_fields);
+        if changes.is_empty() {
+            return;
+        }
+        let name = name_properties.iter().find_map(|property| {
+            fields
+                .get(property)
+                .or_else(|| current_fields.and_then(|f| f.get(property)))
+                .and_then(|value| value.as_text())
+                .filter(|name| !name.is_empty())
+        });
+
+        for (account_id, old_rights, new_rights) in changes {
+            self.share_changes.push(ShareChange {
+                account_id,
+                old_rights,
+                new_rights,
+                name: name.map(|name| name.to_string()),
+            });
+        }
+    }
+
+    pub(crate) fn write_share_notifications(&mut self, object_id: JMAPId) -> crate::Result<()> {
+        if self.share_changes.is_empty() {
+            return Ok(());
+        }
+
+        let object_type = TypeState::try_from(self.collection)
+            .map(|t| t.to_string())
+            .unwrap_or_default();
+        let changed_by = changed_by(self.store, &self.acl)?;
+        let created = JMAPDate::from_timestamp(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0) as i64,
+        );
+
+        for change in std::mem::take(&mut self.share_changes) {
+            let mut document = Document::new(
+                Collection::ShareNotification,
+                self.store
+                    .assign_document_id(change.account_id, Collection::ShareNotification)?,
+            );
+            let mut fields = TinyORM::<ShareNotification>::new();
+            fields.set(
+                Property::Created,
+                Value::Date {
+                    value: created.clone(),
+                },
+            );
+            fields.set(
+                Property::ChangedBy,
+                Value::ChangedBy {
+                    value: changed_by.clone(),
+                },
+            );
+            fields.set(
+                Property::ObjectType,
+                Value::Text {
+                    value: object_type.clone(),
+                },
+            );
+            fields.set(
+                Property::ObjectAccountId,
+                Value::Id {
+                    value: self.account_id.into(),
+                },
+            );
+            fields.set(Property::ObjectId, Value::Id { value: object_id });
+            for (property, rights) in [
+                (Property::OldRights, change.old_rights),
+                (Property::NewRights, change.new_rights),
+            ] {
+                fields.set(
+                    property,
+                    if !rights.is_empty() {
+                        Value::Rights {
+                            value: rights.into_iter().collect(),
+                        }
+                    } else {
+                        Value::Null
+                    },
+                );
+            }
+            if let Some(name) = change.name {
+                fields.set(Property::Name, Value::Text { value: name });
+            }
+            fields.insert(&mut document)?;
+
+            let mut batch = WriteBatch::new(change.account_id);
+            batch.log_insert(Collection::ShareNotification, document.document_id);
+            batch.insert_document(document);
+            self.changes.add_linked_batch(batch);
+        }
+
+        Ok(())
+    }
+}
+
+fn changed_by<T>(store: &JMAPStore<T>, acl: &Arc<ACLToken>) -> crate::Result<ChangedBy>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let principal_id = acl.primary_id();
+    let (mut name, mut email) = (None, None);
+
+    if let Some(mut fields) = store.get_orm::<Principal>(SUPERUSER_ID, principal_id)? {
+        if let Some(PrincipalValue::Text { value }) = fields.remove(&PrincipalProperty::Name) {
+            name = Some(value);
+        }
+        if let Some(PrincipalValue::Text { value }) = fields.remove(&PrincipalProperty::Email) {
+            email = Some(value);
+        }
+    }
+
+    Ok(ChangedBy {
+        name: name.or_else(|| email.clone()).unwrap_or_default(),
+        email,
+        principal_id: JMAPId::from(principal_id).into(),
+    })
+}
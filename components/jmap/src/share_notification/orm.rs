@@ -0,0 +1,170 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::{
+    core::{acl::ACL, collection::Collection},
+    write::options::Options,
+};
+
+use crate::{
+    jmap_store::{
+        changes::ChangesObject, get::GetObject, query::QueryObject, set::SetObject, Object,
+    },
+    orm,
+    request::ResultReference,
+    types::{date::JMAPDate, jmap::JMAPId},
+};
+
+use super::schema::{Comparator, Filter, Property, ShareNotification, Value};
+
+impl orm::Value for Value {
+    fn index_as(&self) -> orm::Index {
+        match self {
+            Value::Text { value } => value.to_string().into(),
+            Value::Id { value } => value.get_document_id().into(),
+            Value::Date { value } => (value.timestamp() as u64).into(),
+            _ => orm::Index::Null,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::Text { value } => value.is_empty(),
+            Value::Null => true,
+            _ => false,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Value::Id { .. } => std::mem::size_of::<JMAPId>(),
+            Value::Text { value } => value.len(),
+            Value::Date { .. } => std::mem::size_of::<JMAPDate>(),
+            Value::ChangedBy { value } => {
+                value.name.len()
+                    + value.email.as_ref().map_or(0, |e| e.len())
+                    + std::mem::size_of::<JMAPId>()
+            }
+            Value::Rights { value } => value.len() * std::mem::size_of::<ACL>(),
+            Value::Null => 0,
+        }
+    }
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Value::Null
+    }
+}
+
+impl Object for ShareNotification {
+    type Property = Property;
+
+    type Value = Value;
+
+    fn new(id: JMAPId) -> Self {
+        let mut item = ShareNotification::default();
+        item.properties
+            .append(Property::Id, Value::Id { value: id });
+        item
+    }
+
+    fn id(&self) -> Option<&JMAPId> {
+        self.properties.get(&Property::Id).and_then(|id| match id {
+            Value::Id { value } => Some(value),
+            _ => None,
+        })
+    }
+
+    fn required() -> &'static [Self::Property] {
+        &[
+            Property::Created,
+            Property::ChangedBy,
+            Property::ObjectType,
+            Property::ObjectAccountId,
+            Property::ObjectId,
+        ]
+    }
+
+    fn indexed() -> &'static [(Self::Property, u64)] {
+        &[
+            (Property::Created, <u64 as Options>::F_INDEX),
+            (Property::ObjectType, <u64 as Options>::F_KEYWORD),
+            (Property::ObjectAccountId, <u64 as Options>::F_INDEX),
+        ]
+    }
+
+    fn max_len() -> &'static [(Self::Property, usize)] {
+        &[(Property::ObjectType, 255), (Property::Name, 255)]
+    }
+
+    fn collection() -> Collection {
+        Collection::ShareNotification
+    }
+}
+
+impl GetObject for ShareNotification {
+    type GetArguments = ();
+
+    fn default_properties() -> Vec<Self::Property> {
+        vec![
+            Property::Id,
+            Property::Created,
+            Property::ChangedBy,
+            Property::ObjectType,
+            Property::ObjectAccountId,
+            Property::ObjectId,
+            Property::OldRights,
+            Property::NewRights,
+            Property::Name,
+        ]
+    }
+
+    fn get_as_id(&self, property: &Self::Property) -> Option<Vec<JMAPId>> {
+        match self.properties.get(property)? {
+            Value::Id { value } => Some(vec![*value]),
+            _ => None,
+        }
+    }
+}
+
+impl SetObject for ShareNotification {
+    type SetArguments = ();
+
+    type NextCall = ();
+
+    fn eval_id_references(&mut self, _fnc: impl FnMut(&str) -> Option<JMAPId>) {}
+    fn eval_result_references(&mut self, _fnc: impl FnMut(&ResultReference) -> Option<Vec<u64>>) {}
+}
+
+impl QueryObject for ShareNotification {
+    type QueryArguments = ();
+
+    type Filter = Filter;
+
+    type Comparator = Comparator;
+}
+
+impl ChangesObject for ShareNotification {
+    type ChangesResponse = ();
+}
@@ -0,0 +1,83 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use serde::{Deserialize, Serialize};
+use store::core::{acl::ACL, vec_map::VecMap};
+
+use crate::types::{date::JMAPDate, jmap::JMAPId};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShareNotification {
+    pub properties: VecMap<Property, Value>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[repr(u8)]
+pub enum Property {
+    Id = 0,
+    Created = 1,
+    ChangedBy = 2,
+    ObjectType = 3,
+    ObjectAccountId = 4,
+    ObjectId = 5,
+    OldRights = 6,
+    NewRights = 7,
+    Name = 8,
+    Invalid = 9,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangedBy {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "email")]
+    pub email: Option<String>,
+    #[serde(rename = "principalId")]
+    pub principal_id: Option<JMAPId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Value {
+    Id { value: JMAPId },
+    Text { value: String },
+    Date { value: JMAPDate },
+    ChangedBy { value: ChangedBy },
+    Rights { value: Vec<ACL> },
+    Null,
+}
+
+#[derive(Clone, Debug)]
+pub enum Filter {
+    After { value: JMAPDate },
+    Before { value: JMAPDate },
+    ObjectType { value: String },
+    ObjectAccountId { value: JMAPId },
+    Unsupported { value: String },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "property")]
+pub enum Comparator {
+    #[serde(rename = "created")]
+    Created,
+}
@@ -0,0 +1,154 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, fmt};
+
+use serde::{de::IgnoredAny, ser::SerializeMap, Deserialize, Serialize};
+use store::core::vec_map::VecMap;
+
+use crate::request::query::FilterDeserializer;
+
+use super::schema::{Filter, Property, ShareNotification, Value};
+
+// ShareNotification de/serialization
+impl Serialize for ShareNotification {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(self.properties.len().into())?;
+
+        for (name, value) in &self.properties {
+            match value {
+                Value::Id { value } => map.serialize_entry(name, value)?,
+                Value::Text { value } => map.serialize_entry(name, value)?,
+                Value::Date { value } => map.serialize_entry(name, value)?,
+                Value::ChangedBy { value } => map.serialize_entry(name, value)?,
+                Value::Rights { value } => map.serialize_entry(name, value)?,
+                Value::Null => map.serialize_entry(name, &None::<&str>)?,
+            }
+        }
+
+        map.end()
+    }
+}
+
+struct ShareNotificationVisitor;
+
+impl<'de> serde::de::Visitor<'de> for ShareNotificationVisitor {
+    type Value = ShareNotification;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a valid JMAP ShareNotification object")
+    }
+
+    // Share notifications are only created by the server, client supplied
+    // values are discarded and the request is rejected by ShareNotification/set.
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let mut properties: VecMap<Property, Value> = VecMap::new();
+
+        while let Some(key) = map.next_key::<Cow<str>>()? {
+            map.next_value::<IgnoredAny>()?;
+            if let Ok(property) = Property::try_from(key.as_ref()) {
+                properties.append(property, Value::Null);
+            }
+        }
+
+        Ok(ShareNotification { properties })
+    }
+}
+
+impl<'de> Deserialize<'de> for ShareNotification {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(ShareNotificationVisitor)
+    }
+}
+
+// Property de/serialization
+impl Serialize for Property {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+struct PropertyVisitor;
+
+impl<'de> serde::de::Visitor<'de> for PropertyVisitor {
+    type Value = Property;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a valid JMAP ShareNotification property")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Property::parse(v))
+    }
+}
+
+impl<'de> Deserialize<'de> for Property {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(PropertyVisitor)
+    }
+}
+
+// Filter deserializer
+impl FilterDeserializer for Filter {
+    fn deserialize<'x>(property: &str, map: &mut impl serde::de::MapAccess<'x>) -> Option<Self> {
+        match property {
+            "after" => Filter::After {
+                value: map.next_value().ok()?,
+            },
+            "before" => Filter::Before {
+                value: map.next_value().ok()?,
+            },
+            "objectType" => Filter::ObjectType {
+                value: map.next_value().ok()?,
+            },
+            "objectAccountId" => Filter::ObjectAccountId {
+                value: map.next_value().ok()?,
+            },
+            unsupported => {
+                map.next_value::<IgnoredAny>().ok()?;
+                Filter::Unsupported {
+                    value: unsupported.to_string(),
+                }
+            }
+        }
+        .into()
+    }
+}
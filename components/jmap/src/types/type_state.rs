@@ -35,7 +35,14 @@ pub enum TypeState {
     Mailbox = 3,
     Thread = 4,
     Identity = 5,
-    None = 6,
+    SavedSearch = 6,
+    AddressBook = 7,
+    ContactCard = 8,
+    Calendar = 9,
+    CalendarEvent = 10,
+    Principal = 11,
+    ShareNotification = 12,
+    None = 13,
 }
 
 impl From<u64> for TypeState {
@@ -47,6 +54,13 @@ impl From<u64> for TypeState {
             3 => TypeState::Mailbox,
             4 => TypeState::Thread,
             5 => TypeState::Identity,
+            6 => TypeState::SavedSearch,
+            7 => TypeState::AddressBook,
+            8 => TypeState::ContactCard,
+            9 => TypeState::Calendar,
+            10 => TypeState::CalendarEvent,
+            11 => TypeState::Principal,
+            12 => TypeState::ShareNotification,
             _ => {
                 debug_assert!(false, "Invalid type_state value: {}", value);
                 TypeState::None
@@ -81,6 +95,13 @@ impl TryFrom<Collection> for TypeState {
             Collection::Thread => Ok(TypeState::Thread),
             Collection::Identity => Ok(TypeState::Identity),
             Collection::EmailSubmission => Ok(TypeState::EmailSubmission),
+            Collection::SavedSearch => Ok(TypeState::SavedSearch),
+            Collection::AddressBook => Ok(TypeState::AddressBook),
+            Collection::ContactCard => Ok(TypeState::ContactCard),
+            Collection::Calendar => Ok(TypeState::Calendar),
+            Collection::CalendarEvent => Ok(TypeState::CalendarEvent),
+            Collection::Principal => Ok(TypeState::Principal),
+            Collection::ShareNotification => Ok(TypeState::ShareNotification),
             _ => Err(()),
         }
     }
@@ -95,6 +116,13 @@ impl TypeState {
             "Mailbox" => TypeState::Mailbox,
             "Thread" => TypeState::Thread,
             "Identity" => TypeState::Identity,
+            "SavedSearch" => TypeState::SavedSearch,
+            "AddressBook" => TypeState::AddressBook,
+            "ContactCard" => TypeState::ContactCard,
+            "Calendar" => TypeState::Calendar,
+            "CalendarEvent" => TypeState::CalendarEvent,
+            "Principal" => TypeState::Principal,
+            "ShareNotification" => TypeState::ShareNotification,
             _ => TypeState::None,
         }
     }
@@ -109,6 +137,13 @@ impl Display for TypeState {
             TypeState::Mailbox => write!(f, "Mailbox"),
             TypeState::Thread => write!(f, "Thread"),
             TypeState::Identity => write!(f, "Identity"),
+            TypeState::SavedSearch => write!(f, "SavedSearch"),
+            TypeState::AddressBook => write!(f, "AddressBook"),
+            TypeState::ContactCard => write!(f, "ContactCard"),
+            TypeState::Calendar => write!(f, "Calendar"),
+            TypeState::CalendarEvent => write!(f, "CalendarEvent"),
+            TypeState::Principal => write!(f, "Principal"),
+            TypeState::ShareNotification => write!(f, "ShareNotification"),
             TypeState::None => Ok(()),
         }
     }
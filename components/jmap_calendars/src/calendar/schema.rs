@@ -0,0 +1,271 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::fmt::Display;
+
+use jmap::{
+    orm::{self, acl::ACLUpdate},
+    types::jmap::JMAPId,
+};
+use serde::{Deserialize, Serialize};
+use store::{
+    core::{acl::ACL, bitmap::Bitmap, vec_map::VecMap},
+    AccountId, FieldId,
+};
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Calendar {
+    pub properties: VecMap<Property, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Value {
+    Id { value: JMAPId },
+    Text { value: String },
+    Bool { value: bool },
+    Number { value: u32 },
+    Json { value: String },
+    Subscriptions { value: Vec<AccountId> },
+    CalendarRights { value: CalendarRights },
+    ACLSet(Vec<ACLUpdate>),
+    ACLGet(VecMap<String, Vec<ACL>>),
+    Null,
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Value::Null
+    }
+}
+
+impl orm::Value for Value {
+    fn index_as(&self) -> orm::Index {
+        match self {
+            Value::Text { value } => value.to_string().into(),
+            Value::Number { value } => (*value).into(),
+            Value::Subscriptions { value } => {
+                if !value.is_empty() {
+                    value.to_vec().into()
+                } else {
+                    orm::Index::Null
+                }
+            }
+            _ => orm::Index::Null,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::Text { value } | Value::Json { value } => value.is_empty(),
+            Value::Null => true,
+            _ => false,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Value::Id { .. } => std::mem::size_of::<JMAPId>(),
+            Value::Text { value } | Value::Json { value } => value.len(),
+            Value::Bool { .. } => std::mem::size_of::<bool>(),
+            Value::Number { .. } => std::mem::size_of::<u32>(),
+            Value::Subscriptions { value } => value.len() * std::mem::size_of::<u32>(),
+            Value::CalendarRights { .. } => std::mem::size_of::<CalendarRights>(),
+            Value::ACLSet(value) => value.len() * std::mem::size_of::<ACLUpdate>(),
+            Value::ACLGet(value) => value.iter().fold(0, |acc, (k, v)| {
+                acc + k.len() + v.len() * std::mem::size_of::<ACL>()
+            }),
+            Value::Null => 0,
+        }
+    }
+
+    fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text { value } => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CalendarRights {
+    #[serde(rename = "mayReadFreeBusy")]
+    may_read_free_busy: bool,
+
+    #[serde(rename = "mayReadItems")]
+    may_read_items: bool,
+
+    #[serde(rename = "mayWriteAll")]
+    may_write_all: bool,
+
+    #[serde(rename = "mayWriteOwn")]
+    may_write_own: bool,
+
+    #[serde(rename = "mayUpdatePrivate")]
+    may_update_private: bool,
+
+    #[serde(rename = "mayRSVP")]
+    may_rsvp: bool,
+
+    #[serde(rename = "mayAdmin")]
+    may_admin: bool,
+
+    #[serde(rename = "mayDelete")]
+    may_delete: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
+#[repr(u8)]
+pub enum Property {
+    Id = 0,
+    Name = 1,
+    Description = 2,
+    Color = 3,
+    SortOrder = 4,
+    IsSubscribed = 5,
+    IsVisible = 6,
+    IsDefault = 7,
+    IncludeInAvailability = 8,
+    DefaultAlertsWithTime = 9,
+    DefaultAlertsWithoutTime = 10,
+    TimeZone = 11,
+    MyRights = 12,
+    ACL = 13,
+    Invalid = 14,
+}
+
+impl Display for Property {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Property::Id => write!(f, "id"),
+            Property::Name => write!(f, "name"),
+            Property::Description => write!(f, "description"),
+            Property::Color => write!(f, "color"),
+            Property::SortOrder => write!(f, "sortOrder"),
+            Property::IsSubscribed => write!(f, "isSubscribed"),
+            Property::IsVisible => write!(f, "isVisible"),
+            Property::IsDefault => write!(f, "isDefault"),
+            Property::IncludeInAvailability => write!(f, "includeInAvailability"),
+            Property::DefaultAlertsWithTime => write!(f, "defaultAlertsWithTime"),
+            Property::DefaultAlertsWithoutTime => write!(f, "defaultAlertsWithoutTime"),
+            Property::TimeZone => write!(f, "timeZone"),
+            Property::MyRights => write!(f, "myRights"),
+            Property::ACL => write!(f, "acl"),
+            Property::Invalid => Ok(()),
+        }
+    }
+}
+
+impl Property {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "id" => Property::Id,
+            "name" => Property::Name,
+            "description" => Property::Description,
+            "color" => Property::Color,
+            "sortOrder" => Property::SortOrder,
+            "isSubscribed" => Property::IsSubscribed,
+            "isVisible" => Property::IsVisible,
+            "isDefault" => Property::IsDefault,
+            "includeInAvailability" => Property::IncludeInAvailability,
+            "defaultAlertsWithTime" => Property::DefaultAlertsWithTime,
+            "defaultAlertsWithoutTime" => Property::DefaultAlertsWithoutTime,
+            "timeZone" => Property::TimeZone,
+            "myRights" => Property::MyRights,
+            "acl" => Property::ACL,
+            _ => Property::Invalid,
+        }
+    }
+}
+
+impl From<Property> for FieldId {
+    fn from(field: Property) -> Self {
+        field as FieldId
+    }
+}
+
+impl From<FieldId> for Property {
+    fn from(field: FieldId) -> Self {
+        match field {
+            0 => Property::Id,
+            1 => Property::Name,
+            2 => Property::Description,
+            3 => Property::Color,
+            4 => Property::SortOrder,
+            5 => Property::IsSubscribed,
+            6 => Property::IsVisible,
+            7 => Property::IsDefault,
+            8 => Property::IncludeInAvailability,
+            9 => Property::DefaultAlertsWithTime,
+            10 => Property::DefaultAlertsWithoutTime,
+            11 => Property::TimeZone,
+            12 => Property::MyRights,
+            13 => Property::ACL,
+            _ => Property::Invalid,
+        }
+    }
+}
+
+impl TryFrom<&str> for Property {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match Property::parse(value) {
+            Property::Invalid => Err(()),
+            property => Ok(property),
+        }
+    }
+}
+
+// Calendar rights are mapped to the store ACLs as follows:
+// mayReadFreeBusy = Read, mayReadItems = ReadItems, mayWriteAll = AddItems +
+// ModifyItems + RemoveItems, mayWriteOwn = AddItems, mayUpdatePrivate = ReadItems,
+// mayRSVP = Submit, mayAdmin = Administer and mayDelete = Delete.
+impl CalendarRights {
+    pub fn owner() -> Self {
+        CalendarRights {
+            may_read_free_busy: true,
+            may_read_items: true,
+            may_write_all: true,
+            may_write_own: true,
+            may_update_private: true,
+            may_rsvp: true,
+            may_admin: true,
+            may_delete: true,
+        }
+    }
+
+    pub fn shared(acl: Bitmap<ACL>) -> Self {
+        CalendarRights {
+            may_read_free_busy: acl.contains(ACL::Read),
+            may_read_items: acl.contains(ACL::ReadItems),
+            may_write_all: acl.contains(ACL::AddItems)
+                && acl.contains(ACL::ModifyItems)
+                && acl.contains(ACL::RemoveItems),
+            may_write_own: acl.contains(ACL::AddItems),
+            may_update_private: acl.contains(ACL::ReadItems),
+            may_rsvp: acl.contains(ACL::Submit),
+            may_admin: acl.contains(ACL::Administer),
+            may_delete: acl.contains(ACL::Delete),
+        }
+    }
+}
@@ -0,0 +1,404 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use super::schema::{Calendar, Property, Value};
+use crate::calendar_event::schema::{self as calendar_event, CalendarEvent};
+use crate::calendar_event::sharing::JMAPShareCalendars;
+use jmap::error::set::{SetError, SetErrorType};
+use jmap::jmap_store::set::{SetHelper, SetObject};
+use jmap::jmap_store::Object;
+use jmap::orm::acl::ACLUpdate;
+use jmap::orm::{serialize::JMAPOrm, TinyORM};
+use jmap::principal::store::JMAPPrincipals;
+use jmap::request::set::{SetRequest, SetResponse};
+use jmap::request::{ACLEnforce, ResultReference};
+use jmap::types::jmap::JMAPId;
+use store::core::acl::ACL;
+use store::core::collection::Collection;
+use store::core::document::Document;
+use store::core::error::StoreError;
+use store::core::tag::Tag;
+use store::tracing::debug;
+use store::{AccountId, JMAPStore, SharedResource};
+use store::{SharedBitmap, Store};
+
+#[derive(Debug, Clone, Default)]
+pub struct SetArguments {
+    pub on_destroy_remove_events: Option<bool>,
+}
+
+impl SetObject for Calendar {
+    type SetArguments = SetArguments;
+
+    type NextCall = ();
+
+    fn eval_id_references(&mut self, _fnc: impl FnMut(&str) -> Option<JMAPId>) {}
+    fn eval_result_references(&mut self, _fnc: impl FnMut(&ResultReference) -> Option<Vec<u64>>) {}
+}
+
+pub trait JMAPSetCalendar<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn calendar_set(&self, request: SetRequest<Calendar>) -> jmap::Result<SetResponse<Calendar>>;
+    fn calendar_delete(&self, account_id: AccountId, document: &mut Document) -> store::Result<()>;
+}
+
+impl<T> JMAPSetCalendar<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn calendar_set(&self, request: SetRequest<Calendar>) -> jmap::Result<SetResponse<Calendar>> {
+        let mut helper = SetHelper::new(self, request)?;
+        let on_destroy_remove_events = helper
+            .request
+            .arguments
+            .on_destroy_remove_events
+            .unwrap_or(false);
+
+        helper.create(|_create_id, calendar, helper, document| {
+            // Shared accounts may not create new calendars
+            if helper.acl.is_shared(helper.account_id) {
+                return Err(SetError::forbidden(
+                    "You are not allowed to create calendars in this account.",
+                ));
+            }
+
+            let calendar = TinyORM::<Calendar>::new().calendar_set(helper, calendar, None)?;
+            calendar.insert_validate(document)?;
+
+            Ok(Calendar::new(document.document_id.into()))
+        })?;
+
+        helper.update(|id, calendar, helper, document| {
+            let document_id = id.get_document_id();
+            let current_fields = self
+                .get_orm::<Calendar>(helper.account_id, document_id)?
+                .ok_or_else(|| SetError::new_err(SetErrorType::NotFound))?;
+
+            let fields = TinyORM::track_changes(&current_fields).calendar_set(
+                helper,
+                calendar,
+                Some(&current_fields),
+            )?;
+
+            // Check ACLs
+            if helper.acl.is_shared(helper.account_id) {
+                if !helper
+                    .store
+                    .calendars_shared_calendars(
+                        helper.account_id,
+                        &helper.acl.member_of,
+                        ACL::Modify,
+                    )?
+                    .has_access(document_id)
+                {
+                    return Err(SetError::forbidden(
+                        "You are not allowed to modify this calendar.",
+                    ));
+                }
+
+                if fields.has_property(&Property::ACL)
+                    && !helper
+                        .store
+                        .calendars_shared_calendars(
+                            helper.account_id,
+                            &helper.acl.member_of,
+                            ACL::Administer,
+                        )?
+                        .has_access(document_id)
+                {
+                    return Err(SetError::forbidden(
+                        "You are not allowed to change the permissions of this calendar.",
+                    ));
+                }
+            }
+
+            // Merge changes
+            current_fields.merge_validate(document, fields)?;
+
+            Ok(None)
+        })?;
+
+        helper.destroy(|id, helper, document| {
+            let document_id = id.get_document_id();
+
+            // Check ACLs
+            if helper.acl.is_shared(helper.account_id) {
+                if !helper
+                    .store
+                    .calendars_shared_calendars(
+                        helper.account_id,
+                        &helper.acl.member_of,
+                        ACL::Delete,
+                    )?
+                    .has_access(document_id)
+                {
+                    return Err(SetError::forbidden(
+                        "You are not allowed to delete this calendar.",
+                    ));
+                }
+                if on_destroy_remove_events
+                    && !helper
+                        .store
+                        .calendars_shared_calendars(
+                            helper.account_id,
+                            &helper.acl.member_of,
+                            ACL::RemoveItems,
+                        )?
+                        .has_access(document_id)
+                {
+                    return Err(SetError::forbidden(
+                        "You are not allowed to delete events from this calendar.",
+                    ));
+                }
+            }
+
+            // Verify that the calendar is empty
+            if let Some(event_doc_ids) = self.get_tag(
+                helper.account_id,
+                Collection::CalendarEvent,
+                calendar_event::Property::CalendarIds.into(),
+                Tag::Id(document_id),
+            )? {
+                if on_destroy_remove_events {
+                    // Try locking the collection before deleting the events
+                    let _lock = match self.try_lock_collection(
+                        helper.account_id,
+                        Collection::CalendarEvent,
+                        Duration::from_secs(1),
+                    ) {
+                        Some(lock) => lock,
+                        None => {
+                            return Err(SetError::new(
+                                SetErrorType::RateLimit,
+                                "Resource busy, please try again in a few moments.",
+                            ));
+                        }
+                    };
+
+                    for event_document_id in event_doc_ids {
+                        let mut document =
+                            Document::new(Collection::CalendarEvent, event_document_id);
+                        let current_fields = if let Some(current_fields) =
+                            self.get_orm::<CalendarEvent>(helper.account_id, event_document_id)?
+                        {
+                            current_fields
+                        } else {
+                            debug!(
+                                "CalendarEvent ORM for {}:{} not found",
+                                helper.account_id, event_document_id
+                            );
+                            continue;
+                        };
+
+                        // Events that belong to other calendars are only untagged
+                        match current_fields.get_tags(&calendar_event::Property::CalendarIds) {
+                            Some(tags) if tags.len() > 1 => {
+                                let mut fields = TinyORM::track_changes(&current_fields);
+                                fields.untag(
+                                    &calendar_event::Property::CalendarIds,
+                                    &Tag::Id(document_id),
+                                );
+                                current_fields.merge(&mut document, fields)?;
+                                helper.changes.update_document(document);
+                                helper
+                                    .changes
+                                    .log_update(Collection::CalendarEvent, event_document_id);
+                            }
+                            _ => {
+                                current_fields.delete(&mut document);
+                                helper.changes.delete_document(document);
+                                helper
+                                    .changes
+                                    .log_delete(Collection::CalendarEvent, event_document_id);
+                            }
+                        }
+                    }
+                } else {
+                    return Err(SetError::new(
+                        SetErrorType::CalendarHasEvent,
+                        "Calendar contains events.",
+                    ));
+                }
+            }
+
+            // Delete ORM and index
+            if let Some(orm) = helper
+                .store
+                .get_orm::<Calendar>(helper.account_id, document_id)?
+            {
+                orm.delete(document);
+            }
+
+            Ok(())
+        })?;
+
+        helper.into_response()
+    }
+
+    fn calendar_delete(&self, account_id: AccountId, document: &mut Document) -> store::Result<()> {
+        // Delete ORM
+        self.get_orm::<Calendar>(account_id, document.document_id)?
+            .ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Failed to fetch Calendar ORM for {}:{}.",
+                    account_id, document.document_id
+                ))
+            })?
+            .delete(document);
+
+        Ok(())
+    }
+}
+
+trait CalendarSet<T>: Sized
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn calendar_set(
+        self,
+        helper: &mut SetHelper<Calendar, T>,
+        calendar: Calendar,
+        current_fields: Option<&TinyORM<Calendar>>,
+    ) -> jmap::error::set::Result<Self, Property>;
+}
+
+impl<T> CalendarSet<T> for TinyORM<Calendar>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn calendar_set(
+        mut self,
+        helper: &mut SetHelper<Calendar, T>,
+        calendar: Calendar,
+        current_fields: Option<&TinyORM<Calendar>>,
+    ) -> jmap::error::set::Result<Self, Property> {
+        for (property, value) in calendar.properties {
+            let value = match (property, value) {
+                (Property::Name, value @ Value::Text { .. }) => value,
+                (
+                    Property::Description | Property::Color | Property::TimeZone,
+                    value @ (Value::Text { .. } | Value::Null),
+                ) => value,
+                (Property::IncludeInAvailability, Value::Text { value })
+                    if ["all", "attending", "none"].contains(&value.as_str()) =>
+                {
+                    Value::Text { value }
+                }
+                (
+                    Property::DefaultAlertsWithTime | Property::DefaultAlertsWithoutTime,
+                    value @ (Value::Json { .. } | Value::Null),
+                ) => value,
+                (Property::IsVisible, value @ Value::Bool { .. }) => value,
+                (Property::SortOrder, value @ Value::Number { .. }) => value,
+                (Property::IsSubscribed, Value::Bool { value: subscribe }) => {
+                    let account_id = helper.acl.primary_id();
+                    let mut subscriptions = match current_fields
+                        .and_then(|fields| fields.get(&Property::IsSubscribed))
+                    {
+                        Some(Value::Subscriptions { value }) => value.clone(),
+                        _ => Vec::new(),
+                    };
+                    if subscribe {
+                        if subscriptions.contains(&account_id) {
+                            continue;
+                        }
+                        subscriptions.push(account_id);
+                    } else if subscriptions.contains(&account_id) {
+                        subscriptions.retain(|&id| id != account_id);
+                    } else {
+                        continue;
+                    }
+                    if !subscriptions.is_empty() {
+                        Value::Subscriptions {
+                            value: subscriptions,
+                        }
+                    } else {
+                        Value::Null
+                    }
+                }
+                (Property::ACL, Value::ACLSet(value)) => {
+                    for acl_update in &value {
+                        match acl_update {
+                            ACLUpdate::Replace { acls } => {
+                                self.acl_clear();
+                                for (account_id, acls) in acls {
+                                    self.acl_update(
+                                        helper.store.principal_to_id(account_id)?,
+                                        acls,
+                                    );
+                                }
+                            }
+                            ACLUpdate::Update { account_id, acls } => {
+                                self.acl_update(helper.store.principal_to_id(account_id)?, acls);
+                            }
+                            ACLUpdate::Set {
+                                account_id,
+                                acl,
+                                is_set,
+                            } => {
+                                self.acl_set(
+                                    helper.store.principal_to_id(account_id)?,
+                                    *acl,
+                                    *is_set,
+                                );
+                            }
+                        }
+                    }
+                    self.acl_finish();
+                    continue;
+                }
+                (_, _) => {
+                    return Err(SetError::invalid_property(
+                        property,
+                        "Field could not be set.",
+                    ));
+                }
+            };
+
+            self.set(property, value);
+        }
+
+        // Invalidate cache for changed ACLs
+        if let Some(permissions) = self.get_changed_acls(current_fields) {
+            for permission in permissions {
+                helper.store.acl_tokens.invalidate(&permission.id);
+                for acl in permission.acl {
+                    for collection in [Collection::CalendarEvent, Collection::Calendar] {
+                        let key =
+                            SharedResource::new(helper.account_id, permission.id, collection, acl);
+                        helper.store.shared_documents.invalidate(&key);
+                    }
+                }
+            }
+        }
+
+        // Notify the accounts whose rights were granted, changed or revoked
+        helper.notify_acl_changes(&self, current_fields, &[Property::Name]);
+
+        Ok(self)
+    }
+}
@@ -0,0 +1,392 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::index::participant_email;
+use super::recurrence::{
+    format_local, get_override, parse_duration, parse_local, to_timestamp, Recurrence,
+};
+use super::schema::{CalendarEvent, Property, Value};
+use super::set::{apply_patch, json_to_value};
+use super::sharing::JMAPShareCalendars;
+use jmap::jmap_store::get::{default_mapper, GetHelper, GetObject};
+use jmap::orm::serialize::JMAPOrm;
+use jmap::orm::TinyORM;
+use jmap::request::get::{GetRequest, GetResponse};
+use jmap::request::{ACLEnforce, MaybeIdReference};
+use jmap::types::date::JMAPDate;
+use jmap::types::jmap::JMAPId;
+use jmap_sharing::principal::account::JMAPAccountStore;
+use serde_json::Value as JsonValue;
+use store::chrono::NaiveDateTime;
+use store::core::acl::ACL;
+use store::core::error::StoreError;
+use store::core::vec_map::VecMap;
+use store::{AccountId, JMAPStore, Store};
+
+impl GetObject for CalendarEvent {
+    type GetArguments = ();
+
+    fn default_properties() -> Vec<Self::Property> {
+        vec![
+            Property::Id,
+            Property::BaseEventId,
+            Property::CalendarIds,
+            Property::IsDraft,
+            Property::IsOrigin,
+            Property::UtcStart,
+            Property::UtcEnd,
+            Property::Type,
+            Property::Uid,
+            Property::RelatedTo,
+            Property::ProdId,
+            Property::Created,
+            Property::Updated,
+            Property::Sequence,
+            Property::Method,
+            Property::Title,
+            Property::Description,
+            Property::DescriptionContentType,
+            Property::ShowWithoutTime,
+            Property::Locations,
+            Property::VirtualLocations,
+            Property::Links,
+            Property::Locale,
+            Property::Keywords,
+            Property::Categories,
+            Property::Color,
+            Property::RecurrenceId,
+            Property::RecurrenceIdTimeZone,
+            Property::RecurrenceRules,
+            Property::ExcludedRecurrenceRules,
+            Property::RecurrenceOverrides,
+            Property::Excluded,
+            Property::Priority,
+            Property::FreeBusyStatus,
+            Property::Privacy,
+            Property::ReplyTo,
+            Property::SentBy,
+            Property::Participants,
+            Property::UseDefaultAlerts,
+            Property::Alerts,
+            Property::Localizations,
+            Property::Start,
+            Property::Duration,
+            Property::TimeZone,
+            Property::TimeZones,
+            Property::Status,
+        ]
+    }
+
+    fn get_as_id(&self, property: &Self::Property) -> Option<Vec<JMAPId>> {
+        match self.properties.get(property)? {
+            Value::Id { value } => Some(vec![*value]),
+            Value::CalendarIds { value, .. } => {
+                Some(value.keys().filter_map(|id| id.value().copied()).collect())
+            }
+            _ => None,
+        }
+    }
+}
+
+pub trait JMAPGetCalendarEvent<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn calendar_event_get(
+        &self,
+        request: GetRequest<CalendarEvent>,
+    ) -> jmap::Result<GetResponse<CalendarEvent>>;
+}
+
+impl<T> JMAPGetCalendarEvent<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn calendar_event_get(
+        &self,
+        request: GetRequest<CalendarEvent>,
+    ) -> jmap::Result<GetResponse<CalendarEvent>> {
+        let mut helper = GetHelper::new(
+            self,
+            request,
+            default_mapper.into(),
+            (|account_id: AccountId, member_of: &[AccountId]| {
+                self.calendars_shared_events(account_id, member_of, ACL::ReadItems)
+            })
+            .into(),
+        )?;
+        let account_id = helper.account_id;
+
+        // Sharees see their own copy of the per-user properties
+        let per_user_id = if helper.acl.is_shared(account_id) {
+            JMAPId::from(helper.acl.primary_id()).to_string().into()
+        } else {
+            None
+        };
+        let mut account_email = None;
+
+        // Add Id Property
+        if !helper.properties.contains(&Property::Id) {
+            helper.properties.push(Property::Id);
+        }
+
+        helper.get(|id, properties| {
+            let document_id = id.get_document_id();
+            let mut fields = self
+                .get_orm::<CalendarEvent>(account_id, document_id)?
+                .ok_or_else(|| StoreError::NotFound("CalendarEvent data not found".to_string()))?;
+
+            // Apply the sharee's per-user properties
+            if let Some(Value::Json { value }) = fields.remove(&Property::PerUser) {
+                if let Some(JsonValue::Object(mut per_user)) =
+                    per_user_id.as_ref().and_then(|per_user_id| {
+                        serde_json::from_str::<JsonValue>(&value)
+                            .ok()?
+                            .as_object_mut()?
+                            .remove(per_user_id)
+                    })
+                {
+                    for (property, value) in per_user.iter_mut() {
+                        let property = Property::parse(property);
+                        if property.is_per_user() {
+                            if let Some(value) = json_to_value(property, value.take()) {
+                                fields.set(property, value);
+                            }
+                        }
+                    }
+                }
+            }
+
+            // Instances of recurring events are identified by the
+            // position of the occurrence in the id's prefix.
+            let mut recurrence_id = None;
+            let instance = id.get_prefix_id() as usize;
+            if instance > 0 {
+                let occurrence = fields
+                    .get(&Property::Start)
+                    .and_then(|start| parse_local(start.as_text()?))
+                    .and_then(|start| {
+                        Recurrence::new(
+                            start,
+                            fields
+                                .get(&Property::RecurrenceRules)
+                                .and_then(|v| v.as_json())
+                                .as_ref(),
+                            fields
+                                .get(&Property::ExcludedRecurrenceRules)
+                                .and_then(|v| v.as_json())
+                                .as_ref(),
+                            fields
+                                .get(&Property::RecurrenceOverrides)
+                                .and_then(|v| v.as_json())
+                                .as_ref(),
+                        )
+                    })
+                    .and_then(|recurrence| {
+                        recurrence
+                            .expand(None, instance)
+                            .0
+                            .get(instance - 1)
+                            .copied()
+                    });
+
+                if let Some(occurrence) = occurrence {
+                    expand_instance(&mut fields, &occurrence);
+                    recurrence_id = occurrence.into();
+                } else {
+                    return Ok(None);
+                }
+            }
+
+            let mut calendar_event = VecMap::with_capacity(properties.len());
+            for property in properties {
+                let value = match property {
+                    Property::Id => Value::Id { value: id },
+                    Property::BaseEventId => {
+                        if recurrence_id.is_some() {
+                            Value::Id {
+                                value: JMAPId::from(document_id),
+                            }
+                        } else {
+                            Value::Null
+                        }
+                    }
+                    Property::CalendarIds => Value::CalendarIds {
+                        value: fields
+                            .get_tags(&Property::CalendarIds)
+                            .map(|tags| {
+                                tags.iter()
+                                    .map(|tag| (MaybeIdReference::Value(tag.as_id().into()), true))
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                        set: true,
+                    },
+                    Property::Type => fields.remove(property).unwrap_or_else(|| Value::Text {
+                        value: "Event".to_string(),
+                    }),
+                    Property::UtcStart | Property::UtcEnd => {
+                        let time_zone = fields
+                            .get(&Property::TimeZone)
+                            .and_then(|tz| tz.as_text())
+                            .map(String::from);
+                        if let Some(start) = fields
+                            .get(&Property::Start)
+                            .and_then(|start| parse_local(start.as_text()?))
+                        {
+                            let mut timestamp = to_timestamp(&start, time_zone.as_deref());
+                            if *property == Property::UtcEnd {
+                                timestamp += fields
+                                    .get(&Property::Duration)
+                                    .and_then(|duration| parse_duration(duration.as_text()?))
+                                    .map(|duration| duration.num_seconds())
+                                    .unwrap_or(0);
+                            }
+                            Value::Date {
+                                value: JMAPDate::from_timestamp(timestamp),
+                            }
+                        } else {
+                            Value::Null
+                        }
+                    }
+                    Property::IsOrigin => {
+                        if account_email.is_none() {
+                            account_email = self
+                                .get_account_details(account_id)?
+                                .map(|(email, _, _)| email.to_lowercase())
+                                .unwrap_or_default()
+                                .into();
+                        }
+                        Value::Bool {
+                            value: is_origin(&fields, account_email.as_deref().unwrap_or("")),
+                        }
+                    }
+                    Property::RecurrenceId if recurrence_id.is_some() => Value::Text {
+                        value: format_local(recurrence_id.as_ref().unwrap()),
+                    },
+                    Property::RecurrenceRules
+                    | Property::ExcludedRecurrenceRules
+                    | Property::RecurrenceOverrides
+                        if recurrence_id.is_some() =>
+                    {
+                        Value::Null
+                    }
+                    Property::IndexText
+                    | Property::IndexLocation
+                    | Property::IndexOwner
+                    | Property::IndexAttendee
+                    | Property::IndexFirst
+                    | Property::IndexLast
+                    | Property::PerUser
+                    | Property::Invalid => continue,
+                    _ => fields.remove(property).unwrap_or_default(),
+                };
+
+                calendar_event.append(*property, value);
+            }
+            Ok(Some(CalendarEvent {
+                properties: calendar_event,
+            }))
+        })
+    }
+}
+
+// Turns the master event into one of its occurrences by moving it to the
+// occurrence's start and applying the matching override, if any.
+fn expand_instance(fields: &mut TinyORM<CalendarEvent>, recurrence_id: &NaiveDateTime) {
+    let overrides = fields
+        .get(&Property::RecurrenceOverrides)
+        .and_then(|v| v.as_json());
+    if let Some(time_zone) = fields.get(&Property::TimeZone).cloned() {
+        fields.set(Property::RecurrenceIdTimeZone, time_zone);
+    }
+    fields.set(
+        Property::Start,
+        Value::Text {
+            value: format_local(recurrence_id),
+        },
+    );
+
+    if let Some(patch) = get_override(overrides.as_ref(), recurrence_id) {
+        for (path, value) in patch {
+            let mut path = path.split('/').map(String::from).collect::<Vec<_>>();
+            let property = Property::parse(&path.remove(0));
+            if property == Property::Invalid
+                || property == Property::Id
+                || property == Property::Uid
+                || property == Property::DavName
+                || property.is_internal()
+            {
+                continue;
+            } else if path.is_empty() {
+                if let Some(value) = json_to_value(property, value.clone()) {
+                    fields.set(property, value);
+                }
+            } else if let Some(mut json) = fields.get(&property).and_then(|v| v.as_json()) {
+                if apply_patch(&mut json, &path, value.clone()) {
+                    fields.set(
+                        property,
+                        Value::Json {
+                            value: json.to_string(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+}
+
+// The account is the scheduling origin of an event unless someone else
+// organizes it.
+fn is_origin(fields: &TinyORM<CalendarEvent>, account_email: &str) -> bool {
+    let reply_to = if let Some(reply_to) = fields
+        .get(&Property::ReplyTo)
+        .and_then(|v| v.as_json())
+        .and_then(|v| v.get("imip").and_then(|v| v.as_str()).map(String::from))
+    {
+        reply_to
+    } else {
+        return true;
+    };
+    let reply_to = reply_to
+        .strip_prefix("mailto:")
+        .unwrap_or(&reply_to)
+        .to_lowercase();
+    reply_to == account_email
+        || fields
+            .get(&Property::Participants)
+            .and_then(|v| v.as_json())
+            .and_then(|participants| {
+                participants.as_object().map(|participants| {
+                    participants.values().any(|participant| {
+                        participant_email(participant).as_deref() == Some(account_email)
+                            && participant
+                                .get("roles")
+                                .and_then(|roles| roles.get("owner"))
+                                .and_then(|v| v.as_bool())
+                                .unwrap_or(false)
+                    })
+                })
+            })
+            .unwrap_or(false)
+}
@@ -0,0 +1,1459 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use serde_json::{json, Map, Value as JsonValue};
+use store::chrono::{NaiveDate, NaiveDateTime};
+
+use super::index::participant_email;
+use super::recurrence::{
+    format_duration, format_local, from_timestamp, parse_duration, parse_local, to_timestamp,
+};
+
+// A parsed iCalendar object (RFC 5545) with its events converted to
+// JSCalendar. Overrides of recurring events are merged into their master
+// event as recurrence overrides.
+#[derive(Debug, Clone, Default)]
+pub struct ICalendar {
+    pub method: Option<String>,
+    pub events: Vec<Map<String, JsonValue>>,
+}
+
+#[derive(Debug, Default)]
+struct Component {
+    name: String,
+    properties: Vec<ContentLine>,
+    components: Vec<Component>,
+}
+
+#[derive(Debug)]
+struct ContentLine {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl ICalendar {
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut stack: Vec<Component> = Vec::new();
+        let mut calendar = None;
+
+        for line in unfold(text) {
+            let line = if let Some(line) = ContentLine::parse(&line) {
+                line
+            } else {
+                continue;
+            };
+            match line.name.as_str() {
+                "BEGIN" => stack.push(Component {
+                    name: line.value.to_ascii_uppercase(),
+                    ..Default::default()
+                }),
+                "END" => {
+                    let component = stack.pop()?;
+                    if let Some(parent) = stack.last_mut() {
+                        parent.components.push(component);
+                    } else if component.name == "VCALENDAR" {
+                        calendar = component.into();
+                        break;
+                    }
+                }
+                _ => stack.last_mut()?.properties.push(line),
+            }
+        }
+
+        let calendar = calendar?;
+        let mut result = ICalendar {
+            method: calendar
+                .get("METHOD")
+                .map(|method| method.value.to_ascii_uppercase()),
+            events: Vec::new(),
+        };
+
+        // Convert events, overrides are applied once all masters are known
+        let mut overrides = Vec::new();
+        for component in &calendar.components {
+            if component.name == "VEVENT" {
+                if let Some(event) = component.to_event() {
+                    if component.get("RECURRENCE-ID").is_some() {
+                        overrides.push(event);
+                    } else {
+                        result.events.push(event);
+                    }
+                }
+            }
+        }
+        for mut instance in overrides {
+            let uid = instance.get("uid").cloned();
+            if let Some(master) = result
+                .events
+                .iter_mut()
+                .find(|event| event.get("uid") == uid.as_ref())
+            {
+                let recurrence_id = if let Some(JsonValue::String(recurrence_id)) =
+                    instance.remove("recurrenceId")
+                {
+                    recurrence_id
+                } else {
+                    continue;
+                };
+                let mut patch = Map::new();
+                for (key, value) in instance {
+                    if !matches!(
+                        key.as_str(),
+                        "@type"
+                            | "uid"
+                            | "recurrenceIdTimeZone"
+                            | "recurrenceRules"
+                            | "excludedRecurrenceRules"
+                            | "recurrenceOverrides"
+                            | "created"
+                            | "updated"
+                    ) && master.get(&key) != Some(&value)
+                    {
+                        patch.insert(key, value);
+                    }
+                }
+                master
+                    .entry("recurrenceOverrides")
+                    .or_insert_with(|| JsonValue::Object(Map::new()))
+                    .as_object_mut()?
+                    .insert(recurrence_id, JsonValue::Object(patch));
+            } else {
+                // Invitations to a single occurrence
+                result.events.push(instance);
+            }
+        }
+
+        Some(result)
+    }
+}
+
+impl Component {
+    fn get(&self, name: &str) -> Option<&ContentLine> {
+        self.properties.iter().find(|line| line.name == name)
+    }
+
+    fn get_all<'x>(&'x self, name: &'x str) -> impl Iterator<Item = &'x ContentLine> + 'x {
+        self.properties.iter().filter(move |line| line.name == name)
+    }
+
+    fn to_event(&self) -> Option<Map<String, JsonValue>> {
+        let mut event = Map::new();
+        event.insert("@type".to_string(), "Event".into());
+        event.insert("uid".to_string(), self.get("UID")?.value.clone().into());
+
+        // Start, time zone and duration
+        let dtstart = self.get("DTSTART")?;
+        let time_zone = dtstart.param("TZID").map(String::from).or_else(|| {
+            if dtstart.value.ends_with('Z') || dtstart.value.ends_with('z') {
+                Some("Etc/UTC".to_string())
+            } else {
+                None
+            }
+        });
+        let start = parse_datetime(dtstart, time_zone.as_deref())?;
+        let show_without_time = dtstart.is_date();
+        event.insert("start".to_string(), format_local(&start).into());
+        if let Some(time_zone) = &time_zone {
+            event.insert("timeZone".to_string(), time_zone.clone().into());
+        }
+        if show_without_time {
+            event.insert("showWithoutTime".to_string(), true.into());
+        }
+        if let Some(duration) = self
+            .get("DURATION")
+            .and_then(|duration| parse_duration(&duration.value))
+            .or_else(|| {
+                let end = self.get("DTEND")?;
+                let end_tz = end.param("TZID");
+                let end = parse_datetime(end, end_tz.or(time_zone.as_deref()))?;
+                Some(
+                    store::chrono::Duration::seconds(
+                        to_timestamp(&end, time_zone.as_deref())
+                            - to_timestamp(&start, time_zone.as_deref()),
+                    )
+                    .max(store::chrono::Duration::zero()),
+                )
+            })
+        {
+            event.insert("duration".to_string(), format_duration(&duration).into());
+        }
+        if let Some(recurrence_id) = self.get("RECURRENCE-ID") {
+            event.insert(
+                "recurrenceId".to_string(),
+                format_local(&parse_datetime(recurrence_id, time_zone.as_deref())?).into(),
+            );
+        }
+
+        // Text properties
+        for (name, property) in [("SUMMARY", "title"), ("DESCRIPTION", "description")] {
+            if let Some(line) = self.get(name) {
+                event.insert(property.to_string(), unescape(&line.value).into());
+            }
+        }
+        if let Some(location) = self.get("LOCATION") {
+            event.insert(
+                "locations".to_string(),
+                json!({"1": {"@type": "Location", "name": unescape(&location.value)}}),
+            );
+        }
+        let urls = self
+            .get_all("URL")
+            .enumerate()
+            .map(|(pos, url)| {
+                (
+                    (pos + 1).to_string(),
+                    json!({"@type": "Link", "href": url.value}),
+                )
+            })
+            .collect::<Map<_, _>>();
+        if !urls.is_empty() {
+            event.insert("links".to_string(), urls.into());
+        }
+        let categories = self
+            .get_all("CATEGORIES")
+            .flat_map(|line| split_list(&line.value))
+            .map(|category| (category, JsonValue::Bool(true)))
+            .collect::<Map<_, _>>();
+        if !categories.is_empty() {
+            event.insert("keywords".to_string(), categories.into());
+        }
+
+        // Enumerated properties
+        if let Some(status) = self.get("STATUS") {
+            let status = status.value.to_ascii_lowercase();
+            if matches!(status.as_str(), "tentative" | "confirmed" | "cancelled") {
+                event.insert("status".to_string(), status.into());
+            }
+        }
+        if let Some(transp) = self.get("TRANSP") {
+            event.insert(
+                "freeBusyStatus".to_string(),
+                if transp.value.eq_ignore_ascii_case("TRANSPARENT") {
+                    "free"
+                } else {
+                    "busy"
+                }
+                .into(),
+            );
+        }
+        if let Some(class) = self.get("CLASS") {
+            event.insert(
+                "privacy".to_string(),
+                match class.value.to_ascii_uppercase().as_str() {
+                    "PRIVATE" => "private",
+                    "CONFIDENTIAL" => "secret",
+                    _ => "public",
+                }
+                .into(),
+            );
+        }
+        for (name, property) in [("PRIORITY", "priority"), ("SEQUENCE", "sequence")] {
+            if let Some(value) = self
+                .get(name)
+                .and_then(|line| line.value.trim().parse::<u32>().ok())
+            {
+                event.insert(property.to_string(), value.into());
+            }
+        }
+        for (name, property) in [("CREATED", "created"), ("LAST-MODIFIED", "updated")] {
+            if let Some(value) = self
+                .get(name)
+                .and_then(|line| parse_datetime(line, Some("Etc/UTC")))
+            {
+                event.insert(
+                    property.to_string(),
+                    format!("{}Z", format_local(&value)).into(),
+                );
+            }
+        }
+
+        // Organizer and attendees
+        let mut participants = Map::new();
+        if let Some(organizer) = self.get("ORGANIZER") {
+            if let Some(email) = organizer.email() {
+                event.insert(
+                    "replyTo".to_string(),
+                    json!({ "imip": format!("mailto:{}", email) }),
+                );
+                let mut participant = organizer.to_participant(&email);
+                participant.insert("roles".to_string(), json!({"owner": true}));
+                participants.insert(participant_id(&email), participant.into());
+            }
+        }
+        for attendee in self.get_all("ATTENDEE") {
+            if let Some(email) = attendee.email() {
+                let mut participant = attendee.to_participant(&email);
+                let mut roles = Map::new();
+                match attendee.param("ROLE").map(|role| role.to_ascii_uppercase()) {
+                    Some(role) if role == "CHAIR" => {
+                        roles.insert("chair".to_string(), true.into());
+                        roles.insert("attendee".to_string(), true.into());
+                    }
+                    Some(role) if role == "OPT-PARTICIPANT" => {
+                        roles.insert("optional".to_string(), true.into());
+                        roles.insert("attendee".to_string(), true.into());
+                    }
+                    Some(role) if role == "NON-PARTICIPANT" => {
+                        roles.insert("informational".to_string(), true.into());
+                    }
+                    _ => {
+                        roles.insert("attendee".to_string(), true.into());
+                    }
+                }
+                if let Some(partstat) = attendee.param("PARTSTAT") {
+                    participant.insert(
+                        "participationStatus".to_string(),
+                        partstat.to_ascii_lowercase().into(),
+                    );
+                }
+                if attendee
+                    .param("RSVP")
+                    .map_or(false, |rsvp| rsvp.eq_ignore_ascii_case("TRUE"))
+                {
+                    participant.insert("expectReply".to_string(), true.into());
+                }
+                if let Some(kind) = attendee.param("CUTYPE") {
+                    let kind = kind.to_ascii_lowercase();
+                    if matches!(
+                        kind.as_str(),
+                        "individual" | "group" | "resource" | "location"
+                    ) {
+                        participant.insert("kind".to_string(), kind.into());
+                    }
+                }
+
+                // The organizer can also be an attendee
+                let id = participant_id(&email);
+                if let Some(JsonValue::Object(existing)) = participants.get_mut(&id) {
+                    if let Some(JsonValue::Object(existing_roles)) = existing.get_mut("roles") {
+                        existing_roles.extend(roles);
+                    }
+                    for (key, value) in participant {
+                        existing.entry(key).or_insert(value);
+                    }
+                } else {
+                    participant.insert("roles".to_string(), roles.into());
+                    participants.insert(id, participant.into());
+                }
+            }
+        }
+        if !participants.is_empty() {
+            event.insert("participants".to_string(), participants.into());
+        }
+
+        // Recurrence rules, exceptions and additional dates
+        let rules = self
+            .get_all("RRULE")
+            .filter_map(|rule| parse_rrule(&rule.value, time_zone.as_deref()))
+            .collect::<Vec<_>>();
+        if !rules.is_empty() {
+            event.insert("recurrenceRules".to_string(), rules.into());
+        }
+        let mut overrides = Map::new();
+        for (name, excluded) in [("RDATE", false), ("EXDATE", true)] {
+            for line in self.get_all(name) {
+                let tz = line.param("TZID").or(time_zone.as_deref());
+                for value in line.value.split(',') {
+                    if let Some(date) = parse_datetime_value(value, line.is_date(), tz)
+                        .map(|date| convert_time_zone(date, tz, time_zone.as_deref()))
+                    {
+                        overrides.insert(
+                            format_local(&date),
+                            if excluded {
+                                json!({"excluded": true})
+                            } else {
+                                json!({})
+                            },
+                        );
+                    }
+                }
+            }
+        }
+        if !overrides.is_empty() {
+            event.insert("recurrenceOverrides".to_string(), overrides.into());
+        }
+
+        // Alarms
+        let mut alerts = Map::new();
+        for alarm in self.components.iter().filter(|c| c.name == "VALARM") {
+            let trigger = if let Some(trigger) = alarm.get("TRIGGER") {
+                trigger
+            } else {
+                continue;
+            };
+            let trigger = if trigger
+                .param("VALUE")
+                .map_or(false, |v| v.eq_ignore_ascii_case("DATE-TIME"))
+            {
+                if let Some(when) = parse_datetime_value(&trigger.value, false, Some("Etc/UTC")) {
+                    json!({"@type": "AbsoluteTrigger", "when": format!("{}Z", format_local(&when))})
+                } else {
+                    continue;
+                }
+            } else if let Some(offset) = parse_duration(&trigger.value) {
+                let mut offset =
+                    json!({"@type": "OffsetTrigger", "offset": format_duration(&offset)});
+                if trigger
+                    .param("RELATED")
+                    .map_or(false, |v| v.eq_ignore_ascii_case("END"))
+                {
+                    offset["relativeTo"] = "end".into();
+                }
+                offset
+            } else {
+                continue;
+            };
+            let action = if alarm
+                .get("ACTION")
+                .map_or(false, |action| action.value.eq_ignore_ascii_case("EMAIL"))
+            {
+                "email"
+            } else {
+                "display"
+            };
+            alerts.insert(
+                (alerts.len() + 1).to_string(),
+                json!({"@type": "Alert", "trigger": trigger, "action": action}),
+            );
+        }
+        if !alerts.is_empty() {
+            event.insert("alerts".to_string(), alerts.into());
+        }
+
+        Some(event)
+    }
+}
+
+impl ContentLine {
+    fn parse(line: &str) -> Option<Self> {
+        let mut name = String::new();
+        let mut params = Vec::new();
+        let mut chars = line.char_indices().peekable();
+
+        // Property name
+        let mut delimiter = None;
+        for (_, ch) in &mut chars {
+            if ch == ';' || ch == ':' {
+                delimiter = ch.into();
+                break;
+            }
+            name.push(ch.to_ascii_uppercase());
+        }
+
+        // Parameters
+        while delimiter == Some(';') {
+            let mut param_name = String::new();
+            let mut param_value = String::new();
+            let mut in_value = false;
+            let mut in_quotes = false;
+            delimiter = None;
+            for (_, ch) in &mut chars {
+                match ch {
+                    '"' if in_value => in_quotes = !in_quotes,
+                    '=' if !in_value => in_value = true,
+                    ';' | ':' if !in_quotes => {
+                        delimiter = ch.into();
+                        break;
+                    }
+                    _ if in_value => param_value.push(ch),
+                    _ => param_name.push(ch.to_ascii_uppercase()),
+                }
+            }
+            params.push((param_name, param_value));
+        }
+
+        if delimiter == Some(':') && !name.is_empty() {
+            Some(ContentLine {
+                name,
+                params,
+                value: chars
+                    .peek()
+                    .map(|(pos, _)| line[*pos..].to_string())
+                    .unwrap_or_default(),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn is_date(&self) -> bool {
+        self.param("VALUE")
+            .map_or(false, |value| value.eq_ignore_ascii_case("DATE"))
+            || self.value.trim().len() == 8
+    }
+
+    fn email(&self) -> Option<String> {
+        let value = self.value.trim();
+        let email = match value.get(..7) {
+            Some(prefix) if prefix.eq_ignore_ascii_case("mailto:") => &value[7..],
+            _ => value,
+        };
+        if email.contains('@') {
+            Some(email.to_lowercase())
+        } else {
+            None
+        }
+    }
+
+    fn to_participant(&self, email: &str) -> Map<String, JsonValue> {
+        let mut participant = Map::new();
+        participant.insert("@type".to_string(), "Participant".into());
+        if let Some(name) = self.param("CN") {
+            participant.insert("name".to_string(), name.into());
+        }
+        participant.insert("email".to_string(), email.into());
+        participant.insert(
+            "sendTo".to_string(),
+            json!({ "imip": format!("mailto:{}", email) }),
+        );
+        participant
+    }
+}
+
+// Builds an iTIP REPLY (RFC 5546) with the participation status of an
+// attendee of a JSCalendar event.
+pub fn build_reply(event: &Map<String, JsonValue>, attendee_email: &str) -> Option<String> {
+    let participant = event
+        .get("participants")?
+        .as_object()?
+        .values()
+        .find(|participant| participant_email(participant).as_deref() == Some(attendee_email))?;
+    let organizer = event
+        .get("replyTo")
+        .and_then(|reply_to| reply_to.get("imip"))
+        .and_then(|v| v.as_str())?;
+    let time_zone = event.get("timeZone").and_then(|v| v.as_str());
+    let show_without_time = event
+        .get("showWithoutTime")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+
+    let mut ical = String::with_capacity(512);
+    write_line(&mut ical, "BEGIN", &[], "VCALENDAR");
+    write_line(&mut ical, "VERSION", &[], "2.0");
+    write_line(
+        &mut ical,
+        "PRODID",
+        &[],
+        "-//Stalwart Labs//JMAP Server//EN",
+    );
+    write_line(&mut ical, "METHOD", &[], "REPLY");
+    write_line(&mut ical, "BEGIN", &[], "VEVENT");
+    write_line(&mut ical, "UID", &[], event.get("uid")?.as_str()?);
+    write_line(
+        &mut ical,
+        "DTSTAMP",
+        &[],
+        &format!("{}Z", format_datetime(&from_timestamp(now(), None)?)),
+    );
+    write_line(
+        &mut ical,
+        "SEQUENCE",
+        &[],
+        &event
+            .get("sequence")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0)
+            .to_string(),
+    );
+    for (name, property) in [("DTSTART", "start"), ("RECURRENCE-ID", "recurrenceId")] {
+        if let Some(value) = event
+            .get(property)
+            .and_then(|v| v.as_str())
+            .and_then(parse_local)
+        {
+            write_datetime(&mut ical, name, &value, time_zone, show_without_time);
+        }
+    }
+    if let Some(title) = event.get("title").and_then(|v| v.as_str()) {
+        write_line(&mut ical, "SUMMARY", &[], &escape(title));
+    }
+    write_line(&mut ical, "ORGANIZER", &[], organizer);
+    let mut params = vec![(
+        "PARTSTAT",
+        participant
+            .get("participationStatus")
+            .and_then(|v| v.as_str())
+            .unwrap_or("needs-action")
+            .to_ascii_uppercase(),
+    )];
+    if let Some(name) = participant.get("name").and_then(|v| v.as_str()) {
+        params.push(("CN", format!("\"{}\"", name.replace('"', "'"))));
+    }
+    write_line(
+        &mut ical,
+        "ATTENDEE",
+        &params,
+        &format!("mailto:{}", attendee_email),
+    );
+    write_line(&mut ical, "END", &[], "VEVENT");
+    write_line(&mut ical, "END", &[], "VCALENDAR");
+
+    Some(ical)
+}
+
+// Properties of a JSCalendar event that are represented in iCalendar, any
+// of them missing from an updated iCalendar object has been removed.
+pub const ICALENDAR_PROPERTIES: [&str; 20] = [
+    "title",
+    "description",
+    "start",
+    "duration",
+    "timeZone",
+    "showWithoutTime",
+    "status",
+    "freeBusyStatus",
+    "privacy",
+    "priority",
+    "sequence",
+    "locations",
+    "links",
+    "keywords",
+    "replyTo",
+    "participants",
+    "recurrenceRules",
+    "recurrenceOverrides",
+    "alerts",
+    "recurrenceId",
+];
+
+// Builds an iCalendar object containing a JSCalendar event, its recurrence
+// overrides are written as separate VEVENT components. Time zones are
+// referenced by their IANA name, no VTIMEZONE components are included.
+pub fn build_event(event: &Map<String, JsonValue>) -> Option<String> {
+    let mut ical = String::with_capacity(1024);
+    write_line(&mut ical, "BEGIN", &[], "VCALENDAR");
+    write_line(&mut ical, "VERSION", &[], "2.0");
+    write_line(
+        &mut ical,
+        "PRODID",
+        &[],
+        "-//Stalwart Labs//JMAP Server//EN",
+    );
+    write_event(&mut ical, event, None)?;
+
+    if let Some(overrides) = event.get("recurrenceOverrides").and_then(|v| v.as_object()) {
+        for (recurrence_id, patch) in overrides {
+            let patch = match patch.as_object() {
+                Some(patch)
+                    if !patch.is_empty()
+                        && !patch
+                            .get("excluded")
+                            .and_then(|v| v.as_bool())
+                            .unwrap_or(false) =>
+                {
+                    patch
+                }
+                _ => continue,
+            };
+            let mut instance = event.clone();
+            for property in [
+                "recurrenceRules",
+                "excludedRecurrenceRules",
+                "recurrenceOverrides",
+            ] {
+                instance.remove(property);
+            }
+            instance.insert("start".to_string(), recurrence_id.clone().into());
+            for (key, value) in patch {
+                if !key.contains('/') {
+                    instance.insert(key.to_string(), value.clone());
+                }
+            }
+            write_event(&mut ical, &instance, recurrence_id.as_str().into())?;
+        }
+    }
+
+    write_line(&mut ical, "END", &[], "VCALENDAR");
+    Some(ical)
+}
+
+fn write_event(
+    ical: &mut String,
+    event: &Map<String, JsonValue>,
+    recurrence_id: Option<&str>,
+) -> Option<()> {
+    let time_zone = event.get("timeZone").and_then(|v| v.as_str());
+    let show_without_time = event
+        .get("showWithoutTime")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    let get_text = |property: &str| event.get(property).and_then(|v| v.as_str());
+
+    write_line(ical, "BEGIN", &[], "VEVENT");
+    write_line(ical, "UID", &[], get_text("uid")?);
+    let get_utc =
+        |property: &str| get_text(property).and_then(|v| parse_local(v.trim_end_matches('Z')));
+    write_line(
+        ical,
+        "DTSTAMP",
+        &[],
+        &format!(
+            "{}Z",
+            format_datetime(&get_utc("updated").or_else(|| from_timestamp(now(), None))?)
+        ),
+    );
+    for (name, property) in [("CREATED", "created"), ("LAST-MODIFIED", "updated")] {
+        if let Some(value) = get_utc(property) {
+            write_line(ical, name, &[], &format!("{}Z", format_datetime(&value)));
+        }
+    }
+    if let Some(sequence) = event.get("sequence").and_then(|v| v.as_u64()) {
+        write_line(ical, "SEQUENCE", &[], &sequence.to_string());
+    }
+    let start = parse_local(get_text("start")?)?;
+    write_datetime(ical, "DTSTART", &start, time_zone, show_without_time);
+    if let Some(duration) = get_text("duration").and_then(parse_duration) {
+        write_line(ical, "DURATION", &[], &format_duration(&duration));
+    }
+    if let Some(recurrence_id) = recurrence_id.and_then(parse_local) {
+        write_datetime(
+            ical,
+            "RECURRENCE-ID",
+            &recurrence_id,
+            time_zone,
+            show_without_time,
+        );
+    }
+
+    // Text properties
+    for (name, property) in [("SUMMARY", "title"), ("DESCRIPTION", "description")] {
+        if let Some(value) = get_text(property).filter(|v| !v.is_empty()) {
+            write_line(ical, name, &[], &escape(value));
+        }
+    }
+    if let Some(location) = event
+        .get("locations")
+        .and_then(|v| v.as_object())
+        .and_then(|locations| locations.values().find_map(|l| l.get("name")?.as_str()))
+    {
+        write_line(ical, "LOCATION", &[], &escape(location));
+    }
+    for link in event
+        .get("links")
+        .and_then(|v| v.as_object())
+        .into_iter()
+        .flat_map(|links| links.values())
+    {
+        if let Some(href) = link.get("href").and_then(|v| v.as_str()) {
+            write_line(ical, "URL", &[], href);
+        }
+    }
+    if let Some(keywords) = event
+        .get("keywords")
+        .and_then(|v| v.as_object())
+        .filter(|k| !k.is_empty())
+    {
+        write_line(
+            ical,
+            "CATEGORIES",
+            &[],
+            &keywords
+                .keys()
+                .map(|keyword| escape(keyword))
+                .collect::<Vec<_>>()
+                .join(","),
+        );
+    }
+
+    // Enumerated properties
+    if let Some(status) = get_text("status") {
+        write_line(ical, "STATUS", &[], &status.to_ascii_uppercase());
+    }
+    if let Some(free_busy) = get_text("freeBusyStatus") {
+        write_line(
+            ical,
+            "TRANSP",
+            &[],
+            if free_busy == "free" {
+                "TRANSPARENT"
+            } else {
+                "OPAQUE"
+            },
+        );
+    }
+    if let Some(privacy) = get_text("privacy") {
+        write_line(
+            ical,
+            "CLASS",
+            &[],
+            match privacy {
+                "private" => "PRIVATE",
+                "secret" => "CONFIDENTIAL",
+                _ => "PUBLIC",
+            },
+        );
+    }
+    if let Some(priority) = event.get("priority").and_then(|v| v.as_u64()) {
+        write_line(ical, "PRIORITY", &[], &priority.to_string());
+    }
+
+    // Organizer and attendees
+    let participants = event
+        .get("participants")
+        .and_then(|v| v.as_object())
+        .into_iter()
+        .flat_map(|participants| participants.values())
+        .collect::<Vec<_>>();
+    if let Some(organizer) = event
+        .get("replyTo")
+        .and_then(|reply_to| reply_to.get("imip"))
+        .and_then(|v| v.as_str())
+    {
+        let mut params = Vec::new();
+        if let Some(name) = participants
+            .iter()
+            .find(|p| p.get("roles").and_then(|r| r.get("owner")).is_some())
+            .and_then(|p| p.get("name")?.as_str())
+        {
+            params.push(("CN", format!("\"{}\"", name.replace('"', "'"))));
+        }
+        write_line(ical, "ORGANIZER", &params, organizer);
+    }
+    for participant in participants {
+        let roles = participant.get("roles").and_then(|v| v.as_object());
+        let has_role = |role: &str| roles.map_or(false, |roles| roles.contains_key(role));
+        let role = if has_role("chair") {
+            "CHAIR"
+        } else if has_role("optional") {
+            "OPT-PARTICIPANT"
+        } else if has_role("attendee") {
+            "REQ-PARTICIPANT"
+        } else if has_role("informational") {
+            "NON-PARTICIPANT"
+        } else {
+            continue;
+        };
+        let email = if let Some(email) = participant_email(participant) {
+            email
+        } else {
+            continue;
+        };
+        let mut params = vec![
+            ("ROLE", role.to_string()),
+            (
+                "PARTSTAT",
+                participant
+                    .get("participationStatus")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("needs-action")
+                    .to_ascii_uppercase(),
+            ),
+        ];
+        if let Some(name) = participant.get("name").and_then(|v| v.as_str()) {
+            params.push(("CN", format!("\"{}\"", name.replace('"', "'"))));
+        }
+        if let Some(kind) = participant.get("kind").and_then(|v| v.as_str()) {
+            params.push(("CUTYPE", kind.to_ascii_uppercase()));
+        }
+        if participant
+            .get("expectReply")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+        {
+            params.push(("RSVP", "TRUE".to_string()));
+        }
+        write_line(ical, "ATTENDEE", &params, &format!("mailto:{}", email));
+    }
+
+    // Recurrence rules, exceptions and additional dates
+    for rule in event
+        .get("recurrenceRules")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+    {
+        if let Some(rule) = build_rrule(rule, time_zone) {
+            write_line(ical, "RRULE", &[], &rule);
+        }
+    }
+    if let Some(overrides) = event.get("recurrenceOverrides").and_then(|v| v.as_object()) {
+        for (date, patch) in overrides {
+            let is_excluded = patch
+                .get("excluded")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false);
+            if is_excluded || patch.as_object().map_or(false, |p| p.is_empty()) {
+                if let Some(date) = parse_local(date) {
+                    write_datetime(
+                        ical,
+                        if is_excluded { "EXDATE" } else { "RDATE" },
+                        &date,
+                        time_zone,
+                        show_without_time,
+                    );
+                }
+            }
+        }
+    }
+
+    // Alarms
+    for alert in event
+        .get("alerts")
+        .and_then(|v| v.as_object())
+        .into_iter()
+        .flat_map(|alerts| alerts.values())
+    {
+        let trigger = if let Some(trigger) = alert.get("trigger") {
+            trigger
+        } else {
+            continue;
+        };
+        let (params, value) = if let Some(offset) = trigger.get("offset").and_then(|v| v.as_str()) {
+            (
+                if trigger.get("relativeTo").and_then(|v| v.as_str()) == Some("end") {
+                    vec![("RELATED", "END".to_string())]
+                } else {
+                    vec![]
+                },
+                offset.to_string(),
+            )
+        } else if let Some(when) = trigger
+            .get("when")
+            .and_then(|v| v.as_str())
+            .and_then(|v| parse_local(v.trim_end_matches('Z')))
+        {
+            (
+                vec![("VALUE", "DATE-TIME".to_string())],
+                format!("{}Z", format_datetime(&when)),
+            )
+        } else {
+            continue;
+        };
+        write_line(ical, "BEGIN", &[], "VALARM");
+        write_line(
+            ical,
+            "ACTION",
+            &[],
+            if alert.get("action").and_then(|v| v.as_str()) == Some("email") {
+                "EMAIL"
+            } else {
+                "DISPLAY"
+            },
+        );
+        write_line(ical, "TRIGGER", &params, &value);
+        write_line(
+            ical,
+            "DESCRIPTION",
+            &[],
+            &escape(get_text("title").unwrap_or("Reminder")),
+        );
+        write_line(ical, "END", &[], "VALARM");
+    }
+
+    write_line(ical, "END", &[], "VEVENT");
+    Some(())
+}
+
+// Participant ids are derived from the email address so that they remain
+// stable across updates received from the organizer.
+pub fn participant_id(email: &str) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in email.as_bytes() {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:x}", hash)
+}
+
+fn now() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some(continuation) = line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')) {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(continuation);
+                continue;
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line.to_string());
+        }
+    }
+    lines
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            match chars.next() {
+                Some('n' | 'N') => result.push('\n'),
+                Some(ch) => result.push(ch),
+                None => (),
+            }
+        } else {
+            result.push(ch);
+        }
+    }
+    result
+}
+
+fn escape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\\' | ';' | ',' => {
+                result.push('\\');
+                result.push(ch);
+            }
+            '\n' => result.push_str("\\n"),
+            '\r' => (),
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => {
+                if let Some(ch) = chars.next() {
+                    item.push(ch);
+                }
+            }
+            ',' => {
+                if !item.trim().is_empty() {
+                    items.push(item.trim().to_string());
+                }
+                item.clear();
+            }
+            _ => item.push(ch),
+        }
+    }
+    if !item.trim().is_empty() {
+        items.push(item.trim().to_string());
+    }
+    items
+}
+
+// Lines are folded at 75 octets as required by RFC 5545.
+fn write_line(ical: &mut String, name: &str, params: &[(&str, String)], value: &str) {
+    let mut line = String::with_capacity(name.len() + value.len() + 1);
+    line.push_str(name);
+    for (param, value) in params {
+        line.push(';');
+        line.push_str(param);
+        line.push('=');
+        line.push_str(value);
+    }
+    line.push(':');
+    line.push_str(value);
+
+    let mut line_len = 0;
+    for ch in line.chars() {
+        if line_len + ch.len_utf8() > 75 {
+            ical.push_str("\r\n ");
+            line_len = 1;
+        }
+        ical.push(ch);
+        line_len += ch.len_utf8();
+    }
+    ical.push_str("\r\n");
+}
+
+fn write_datetime(
+    ical: &mut String,
+    name: &str,
+    value: &NaiveDateTime,
+    time_zone: Option<&str>,
+    is_date: bool,
+) {
+    if is_date {
+        write_line(
+            ical,
+            name,
+            &[("VALUE", "DATE".to_string())],
+            &value.format("%Y%m%d").to_string(),
+        );
+    } else {
+        match time_zone {
+            Some("Etc/UTC" | "UTC") => {
+                write_line(ical, name, &[], &format!("{}Z", format_datetime(value)))
+            }
+            Some(time_zone) => write_line(
+                ical,
+                name,
+                &[("TZID", time_zone.to_string())],
+                &format_datetime(value),
+            ),
+            None => write_line(ical, name, &[], &format_datetime(value)),
+        }
+    }
+}
+
+fn format_datetime(value: &NaiveDateTime) -> String {
+    value.format("%Y%m%dT%H%M%S").to_string()
+}
+
+fn parse_datetime(line: &ContentLine, time_zone: Option<&str>) -> Option<NaiveDateTime> {
+    let tz = line.param("TZID").or(time_zone);
+    parse_datetime_value(&line.value, line.is_date(), tz)
+        .map(|value| convert_time_zone(value, tz, time_zone))
+}
+
+// Parses a DATE or DATE-TIME value, UTC times are converted to the local
+// time of the specified time zone.
+fn parse_datetime_value(
+    value: &str,
+    is_date: bool,
+    time_zone: Option<&str>,
+) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    if is_date && value.len() == 8 {
+        NaiveDate::parse_from_str(value, "%Y%m%d")
+            .ok()?
+            .and_hms_opt(0, 0, 0)
+    } else if let Some(value) = value.strip_suffix('Z').or_else(|| value.strip_suffix('z')) {
+        let value = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+        from_timestamp(value.timestamp(), time_zone)
+    } else {
+        NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()
+    }
+}
+
+fn convert_time_zone(value: NaiveDateTime, from: Option<&str>, to: Option<&str>) -> NaiveDateTime {
+    if from != to {
+        from_timestamp(to_timestamp(&value, from), to).unwrap_or(value)
+    } else {
+        value
+    }
+}
+
+fn parse_rrule(value: &str, time_zone: Option<&str>) -> Option<JsonValue> {
+    let mut rule = Map::new();
+    rule.insert("@type".to_string(), "RecurrenceRule".into());
+
+    for part in value.split(';') {
+        let (name, value) = part.split_once('=')?;
+        let numbers = || {
+            value
+                .split(',')
+                .filter_map(|v| v.trim().parse::<i64>().ok())
+                .collect::<Vec<_>>()
+        };
+        match name.trim().to_ascii_uppercase().as_str() {
+            "FREQ" => {
+                rule.insert("frequency".to_string(), value.to_ascii_lowercase().into());
+            }
+            "INTERVAL" => {
+                rule.insert("interval".to_string(), value.parse::<u64>().ok()?.into());
+            }
+            "COUNT" => {
+                rule.insert("count".to_string(), value.parse::<u64>().ok()?.into());
+            }
+            "UNTIL" => {
+                rule.insert(
+                    "until".to_string(),
+                    format_local(&parse_datetime_value(value, value.len() == 8, time_zone)?).into(),
+                );
+            }
+            "WKST" => {
+                rule.insert(
+                    "firstDayOfWeek".to_string(),
+                    value.to_ascii_lowercase().into(),
+                );
+            }
+            "RSCALE" => {
+                rule.insert("rscale".to_string(), value.to_ascii_lowercase().into());
+            }
+            "BYDAY" => {
+                let mut days = Vec::new();
+                for day in value.split(',') {
+                    let day = day.trim();
+                    let (nth, weekday) = day.split_at(day.len().checked_sub(2)?);
+                    let mut day = Map::new();
+                    day.insert("@type".to_string(), "NDay".into());
+                    day.insert("day".to_string(), weekday.to_ascii_lowercase().into());
+                    if !nth.is_empty() {
+                        day.insert(
+                            "nthOfPeriod".to_string(),
+                            nth.trim_start_matches('+').parse::<i64>().ok()?.into(),
+                        );
+                    }
+                    days.push(JsonValue::Object(day));
+                }
+                rule.insert("byDay".to_string(), days.into());
+            }
+            "BYMONTH" => {
+                rule.insert(
+                    "byMonth".to_string(),
+                    value
+                        .split(',')
+                        .map(|v| JsonValue::String(v.trim().to_string()))
+                        .collect::<Vec<_>>()
+                        .into(),
+                );
+            }
+            "BYMONTHDAY" => {
+                rule.insert("byMonthDay".to_string(), numbers().into());
+            }
+            "BYYEARDAY" => {
+                rule.insert("byYearDay".to_string(), numbers().into());
+            }
+            "BYWEEKNO" => {
+                rule.insert("byWeekNo".to_string(), numbers().into());
+            }
+            "BYHOUR" => {
+                rule.insert("byHour".to_string(), numbers().into());
+            }
+            "BYMINUTE" => {
+                rule.insert("byMinute".to_string(), numbers().into());
+            }
+            "BYSECOND" => {
+                rule.insert("bySecond".to_string(), numbers().into());
+            }
+            "BYSETPOS" => {
+                rule.insert("bySetPosition".to_string(), numbers().into());
+            }
+            _ => (),
+        }
+    }
+
+    if rule.contains_key("frequency") {
+        Some(rule.into())
+    } else {
+        None
+    }
+}
+
+fn build_rrule(rule: &JsonValue, time_zone: Option<&str>) -> Option<String> {
+    let rule = rule.as_object()?;
+    let mut parts = vec![format!(
+        "FREQ={}",
+        rule.get("frequency")?.as_str()?.to_ascii_uppercase()
+    )];
+    let numbers = |value: &JsonValue| {
+        value
+            .as_array()
+            .map(|values| {
+                values
+                    .iter()
+                    .filter_map(|v| v.as_i64())
+                    .map(|v| v.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            })
+            .filter(|v| !v.is_empty())
+    };
+
+    for (key, value) in rule {
+        let part = match key.as_str() {
+            "interval" | "count" => {
+                format!("{}={}", key.to_ascii_uppercase(), value.as_u64()?)
+            }
+            "until" => {
+                let until = parse_local(value.as_str()?)?;
+                if let Some(time_zone) = time_zone {
+                    format!(
+                        "UNTIL={}Z",
+                        format_datetime(&from_timestamp(
+                            to_timestamp(&until, Some(time_zone)),
+                            None
+                        )?)
+                    )
+                } else {
+                    format!("UNTIL={}", format_datetime(&until))
+                }
+            }
+            "firstDayOfWeek" => format!("WKST={}", value.as_str()?.to_ascii_uppercase()),
+            "rscale" => format!("RSCALE={}", value.as_str()?.to_ascii_uppercase()),
+            "byDay" => format!(
+                "BYDAY={}",
+                value
+                    .as_array()?
+                    .iter()
+                    .filter_map(|day| {
+                        let weekday = day.get("day")?.as_str()?.to_ascii_uppercase();
+                        Some(
+                            if let Some(nth) = day.get("nthOfPeriod").and_then(|v| v.as_i64()) {
+                                format!("{}{}", nth, weekday)
+                            } else {
+                                weekday
+                            },
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            "byMonth" => format!(
+                "BYMONTH={}",
+                value
+                    .as_array()?
+                    .iter()
+                    .filter_map(|v| v.as_str())
+                    .collect::<Vec<_>>()
+                    .join(",")
+            ),
+            "byMonthDay" => format!("BYMONTHDAY={}", numbers(value)?),
+            "byYearDay" => format!("BYYEARDAY={}", numbers(value)?),
+            "byWeekNo" => format!("BYWEEKNO={}", numbers(value)?),
+            "byHour" => format!("BYHOUR={}", numbers(value)?),
+            "byMinute" => format!("BYMINUTE={}", numbers(value)?),
+            "bySecond" => format!("BYSECOND={}", numbers(value)?),
+            "bySetPosition" => format!("BYSETPOS={}", numbers(value)?),
+            _ => continue,
+        };
+        parts.push(part);
+    }
+
+    Some(parts.join(";"))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{build_event, build_reply, participant_id, ICalendar};
+
+    const INVITE: &str = concat!(
+        "BEGIN:VCALENDAR\r\n",
+        "VERSION:2.0\r\n",
+        "PRODID:-//Example//Calendar//EN\r\n",
+        "METHOD:REQUEST\r\n",
+        "BEGIN:VEVENT\r\n",
+        "UID:meeting-1@example.org\r\n",
+        "SEQUENCE:2\r\n",
+        "DTSTART;TZID=Europe/Madrid:20221018T100000\r\n",
+        "DTEND;TZID=Europe/Madrid:20221018T113000\r\n",
+        "SUMMARY:Weekly sync\\, planning\r\n",
+        "LOCATION:Room 1\r\n",
+        "RRULE:FREQ=WEEKLY;BYDAY=TU;COUNT=10\r\n",
+        "EXDATE;TZID=Europe/Madrid:20221025T100000\r\n",
+        "ORGANIZER;CN=Jane Doe:mailto:jane@example.org\r\n",
+        "ATTENDEE;CN=\"Doe, John\";PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:jo\r\n",
+        " hn@example.org\r\n",
+        "END:VEVENT\r\n",
+        "BEGIN:VEVENT\r\n",
+        "UID:meeting-1@example.org\r\n",
+        "RECURRENCE-ID;TZID=Europe/Madrid:20221101T100000\r\n",
+        "DTSTART;TZID=Europe/Madrid:20221101T120000\r\n",
+        "DTEND;TZID=Europe/Madrid:20221101T133000\r\n",
+        "SUMMARY:Weekly sync\\, planning\r\n",
+        "END:VEVENT\r\n",
+        "END:VCALENDAR\r\n",
+    );
+
+    #[test]
+    fn parse_invite() {
+        let ical = ICalendar::parse(INVITE).unwrap();
+        assert_eq!(ical.method.as_deref(), Some("REQUEST"));
+        assert_eq!(ical.events.len(), 1);
+        let event = serde_json::Value::Object(ical.events[0].clone());
+
+        assert_eq!(event["title"], "Weekly sync, planning");
+        assert_eq!(event["start"], "2022-10-18T10:00:00");
+        assert_eq!(event["timeZone"], "Europe/Madrid");
+        assert_eq!(event["duration"], "PT1H30M");
+        assert_eq!(event["sequence"], 2);
+        assert_eq!(event["locations"]["1"]["name"], "Room 1");
+        assert_eq!(
+            event["recurrenceRules"],
+            json!([{"@type": "RecurrenceRule", "frequency": "weekly",
+                    "byDay": [{"@type": "NDay", "day": "tu"}], "count": 10}])
+        );
+        assert_eq!(
+            event["recurrenceOverrides"],
+            json!({
+                "2022-10-25T10:00:00": {"excluded": true},
+                "2022-11-01T10:00:00": {"start": "2022-11-01T12:00:00"}
+            })
+        );
+        assert_eq!(event["replyTo"]["imip"], "mailto:jane@example.org");
+
+        let attendee = &event["participants"][participant_id("john@example.org")];
+        assert_eq!(attendee["name"], "Doe, John");
+        assert_eq!(attendee["participationStatus"], "needs-action");
+        assert_eq!(attendee["expectReply"], true);
+        assert_eq!(attendee["roles"], json!({"attendee": true}));
+        assert_eq!(
+            event["participants"][participant_id("jane@example.org")]["roles"],
+            json!({"owner": true})
+        );
+    }
+
+    #[test]
+    fn reply() {
+        let mut event = ICalendar::parse(INVITE).unwrap().events.pop().unwrap();
+        event["participants"][participant_id("john@example.org")]["participationStatus"] =
+            "accepted".into();
+        let reply = build_reply(&event, "john@example.org").unwrap();
+
+        assert!(reply.contains("METHOD:REPLY\r\n"));
+        assert!(reply.contains("UID:meeting-1@example.org\r\n"));
+        assert!(reply.contains("SEQUENCE:2\r\n"));
+        assert!(reply.contains("DTSTART;TZID=Europe/Madrid:20221018T100000\r\n"));
+        assert!(reply.contains("ORGANIZER:mailto:jane@example.org\r\n"));
+        assert!(reply.contains("ATTENDEE;PARTSTAT=ACCEPTED;CN=\"Doe, John\":mailto:john@e"));
+        assert!(reply.lines().all(|line| line.len() <= 75));
+
+        let reply = ICalendar::parse(&reply).unwrap();
+        assert_eq!(reply.method.as_deref(), Some("REPLY"));
+        assert_eq!(
+            reply.events[0]["participants"][participant_id("john@example.org")]
+                ["participationStatus"],
+            "accepted"
+        );
+        assert!(build_reply(&reply.events[0], "nobody@example.org").is_none());
+    }
+
+    #[test]
+    fn build() {
+        let mut event = ICalendar::parse(INVITE).unwrap().events.pop().unwrap();
+        event.insert(
+            "alerts".to_string(),
+            json!({"1": {"@type": "Alert", "action": "display",
+                         "trigger": {"@type": "OffsetTrigger", "offset": "-PT15M"}}}),
+        );
+        let ical = build_event(&event).unwrap();
+
+        assert!(ical.contains("RRULE:FREQ=WEEKLY;BYDAY=TU;COUNT=10\r\n"));
+        assert!(ical.contains("EXDATE;TZID=Europe/Madrid:20221025T100000\r\n"));
+        assert!(ical.contains("RECURRENCE-ID;TZID=Europe/Madrid:20221101T100000\r\n"));
+        assert!(ical.contains("TRIGGER:-PT15M\r\n"));
+        assert!(ical.lines().all(|line| line.len() <= 75));
+
+        let ical = ICalendar::parse(&ical).unwrap();
+        assert_eq!(ical.events, vec![event]);
+    }
+}
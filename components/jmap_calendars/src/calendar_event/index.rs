@@ -0,0 +1,240 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::orm::TinyORM;
+use jmap::types::date::JMAPDate;
+use serde_json::Value as JsonValue;
+
+use super::recurrence::{
+    parse_duration, parse_local, to_timestamp, Recurrence, MAX_OCCURRENCES, UNBOUNDED_END,
+};
+use super::schema::{CalendarEvent, Property, Value};
+
+// Recomputes the internal properties used by CalendarEvent/query from the
+// effective values of the event, which are the ones being changed in
+// `fields` or, if untouched, the ones in `current_fields`.
+pub fn build_index(
+    fields: &mut TinyORM<CalendarEvent>,
+    current_fields: Option<&TinyORM<CalendarEvent>>,
+) {
+    let get_value = |property: Property| -> Option<&Value> {
+        match fields.get(&property) {
+            Some(Value::Null) => None,
+            Some(value) => Some(value),
+            None => current_fields.and_then(|f| f.get(&property)),
+        }
+    };
+    let get_text =
+        |property: Property| -> Option<String> { get_value(property)?.as_text().map(String::from) };
+    let get_json = |property: Property| -> Option<JsonValue> { get_value(property)?.as_json() };
+
+    let mut text = Vec::new();
+    let mut locations = Vec::new();
+    let mut owners = Vec::new();
+    let mut attendees = Vec::new();
+
+    add_text(&mut text, get_text(Property::Title).as_deref());
+    add_text(&mut text, get_text(Property::Description).as_deref());
+    if let Some(items) = get_json(Property::Locations) {
+        for location in iter_map(&items) {
+            add_text(
+                &mut locations,
+                location.get("name").and_then(|v| v.as_str()),
+            );
+            add_text(
+                &mut locations,
+                location.get("description").and_then(|v| v.as_str()),
+            );
+        }
+    }
+    if let Some(items) = get_json(Property::VirtualLocations) {
+        for location in iter_map(&items) {
+            add_text(
+                &mut locations,
+                location.get("name").and_then(|v| v.as_str()),
+            );
+        }
+    }
+    if let Some(items) = get_json(Property::Participants) {
+        for participant in iter_map(&items) {
+            let roles = participant.get("roles").and_then(|v| v.as_object());
+            let has_role = |role: &str| {
+                roles
+                    .and_then(|roles| roles.get(role))
+                    .and_then(|v| v.as_bool())
+                    .unwrap_or(false)
+            };
+            let list = if has_role("owner") {
+                &mut owners
+            } else if has_role("attendee") || roles.is_none() {
+                &mut attendees
+            } else {
+                continue;
+            };
+            add_text(list, participant.get("name").and_then(|v| v.as_str()));
+            add_text(list, participant_email(participant).as_deref());
+        }
+    }
+    for property in [Property::Keywords, Property::Categories] {
+        if let Some(JsonValue::Object(items)) = get_json(property) {
+            for item in items.keys() {
+                add_text(&mut text, Some(item));
+            }
+        }
+    }
+
+    // The text index covers all indexed properties
+    for value in locations
+        .iter()
+        .chain(owners.iter())
+        .chain(attendees.iter())
+    {
+        if !text.contains(value) {
+            text.push(value.to_string());
+        }
+    }
+
+    // Time range covered by the event, from the start of the first
+    // occurrence to the end of the last one.
+    let mut first = None;
+    let mut last = None;
+    if let Some(start) = get_text(Property::Start).and_then(|start| parse_local(&start)) {
+        let time_zone = get_text(Property::TimeZone);
+        let duration = get_text(Property::Duration)
+            .and_then(|duration| parse_duration(&duration))
+            .map(|duration| duration.num_seconds())
+            .unwrap_or(0)
+            .max(0);
+        first = to_timestamp(&start, time_zone.as_deref()).into();
+        last = if let Some(recurrence) = Recurrence::new(
+            start,
+            get_json(Property::RecurrenceRules).as_ref(),
+            get_json(Property::ExcludedRecurrenceRules).as_ref(),
+            get_json(Property::RecurrenceOverrides).as_ref(),
+        ) {
+            let (occurrences, has_more) = recurrence.expand(None, MAX_OCCURRENCES);
+            if has_more {
+                UNBOUNDED_END.into()
+            } else {
+                occurrences.last().map(|last| {
+                    (to_timestamp(last, time_zone.as_deref()) + duration).min(UNBOUNDED_END)
+                })
+            }
+        } else {
+            first.map(|first| first + duration)
+        };
+    }
+
+    set_text(fields, current_fields, Property::IndexText, text);
+    set_text(fields, current_fields, Property::IndexLocation, locations);
+    set_text(fields, current_fields, Property::IndexOwner, owners);
+    set_text(fields, current_fields, Property::IndexAttendee, attendees);
+    set_date(fields, current_fields, Property::IndexFirst, first);
+    set_date(fields, current_fields, Property::IndexLast, last);
+}
+
+// Returns the email address of a participant, obtained from its iMIP
+// scheduling address when available.
+pub fn participant_email(participant: &JsonValue) -> Option<String> {
+    participant
+        .get("sendTo")
+        .and_then(|send_to| send_to.get("imip"))
+        .and_then(|v| v.as_str())
+        .map(|address| {
+            address
+                .strip_prefix("mailto:")
+                .or_else(|| address.strip_prefix("MAILTO:"))
+                .unwrap_or(address)
+        })
+        .or_else(|| participant.get("email").and_then(|v| v.as_str()))
+        .map(|address| address.trim().to_lowercase())
+        .filter(|address| !address.is_empty())
+}
+
+fn iter_map(value: &JsonValue) -> impl Iterator<Item = &JsonValue> {
+    value.as_object().into_iter().flat_map(|map| map.values())
+}
+
+fn add_text(list: &mut Vec<String>, value: Option<&str>) {
+    if let Some(value) = value {
+        let value = value.trim();
+        if !value.is_empty() && !list.iter().any(|v| v == value) {
+            list.push(value.to_string());
+        }
+    }
+}
+
+fn set_text(
+    fields: &mut TinyORM<CalendarEvent>,
+    current_fields: Option<&TinyORM<CalendarEvent>>,
+    property: Property,
+    value: Vec<String>,
+) {
+    set_value(
+        fields,
+        current_fields,
+        property,
+        if !value.is_empty() {
+            Value::Text {
+                value: value.join(" "),
+            }
+            .into()
+        } else {
+            None
+        },
+    );
+}
+
+fn set_date(
+    fields: &mut TinyORM<CalendarEvent>,
+    current_fields: Option<&TinyORM<CalendarEvent>>,
+    property: Property,
+    value: Option<i64>,
+) {
+    set_value(
+        fields,
+        current_fields,
+        property,
+        value.map(|value| Value::Date {
+            value: JMAPDate::from_timestamp(value),
+        }),
+    );
+}
+
+fn set_value(
+    fields: &mut TinyORM<CalendarEvent>,
+    current_fields: Option<&TinyORM<CalendarEvent>>,
+    property: Property,
+    value: Option<Value>,
+) {
+    let current_value = current_fields.and_then(|f| f.get(&property));
+    match value {
+        Some(value) if current_value != Some(&value) => {
+            fields.set(property, value);
+        }
+        None if current_value.is_some() => {
+            fields.set(property, Value::Null);
+        }
+        _ => (),
+    }
+}
@@ -0,0 +1,422 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::orm::{serialize::JMAPOrm, TinyORM};
+use jmap::request::MaybeIdReference;
+use jmap::types::jmap::JMAPId;
+use mail_builder::headers::address::Address;
+use mail_builder::headers::content_type::ContentType;
+use mail_builder::mime::{BodyPart, MimePart};
+use mail_builder::MessageBuilder;
+use serde_json::{Map, Value as JsonValue};
+use store::core::collection::Collection;
+use store::core::document::Document;
+use store::core::JMAPIdPrefix;
+use store::read::comparator::Comparator;
+use store::read::filter::{Filter, Query};
+use store::read::FilterMapper;
+use store::tracing::debug;
+use store::write::batch::WriteBatch;
+use store::write::update::Changes;
+use store::{AccountId, JMAPStore, Store};
+
+use super::ical::ICalendar;
+use super::index::participant_email;
+use super::schema::{CalendarEvent, Property, Value};
+use super::set::CalendarEventSet;
+
+// Properties controlled by the organizer, these are replaced on every update
+// received from them.
+const ORGANIZER_PROPERTIES: [Property; 19] = [
+    Property::Title,
+    Property::Description,
+    Property::Start,
+    Property::Duration,
+    Property::TimeZone,
+    Property::ShowWithoutTime,
+    Property::Status,
+    Property::Privacy,
+    Property::Priority,
+    Property::Sequence,
+    Property::Locations,
+    Property::VirtualLocations,
+    Property::Links,
+    Property::ReplyTo,
+    Property::Participants,
+    Property::RecurrenceId,
+    Property::RecurrenceRules,
+    Property::ExcludedRecurrenceRules,
+    Property::RecurrenceOverrides,
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct SchedulingMessage {
+    pub from: String,
+    pub to: String,
+    pub message: Vec<u8>,
+}
+
+impl SchedulingMessage {
+    // Builds an iMIP message (RFC 6047) containing an iTIP reply.
+    pub fn reply(
+        from_name: Option<&str>,
+        from: &str,
+        to: &str,
+        title: &str,
+        status: &str,
+        ical: String,
+    ) -> Self {
+        let summary = format!(
+            "{} has {} the invitation to {}.",
+            from_name.unwrap_or(from),
+            match status {
+                "accepted" => "accepted",
+                "declined" => "declined",
+                "tentative" => "tentatively accepted",
+                "delegated" => "delegated",
+                _ => "replied to",
+            },
+            title
+        );
+        let mut builder = MessageBuilder::new()
+            .from(
+                from_name
+                    .map(|from_name| Address::from((from_name, from)))
+                    .unwrap_or_else(|| Address::from(from)),
+            )
+            .to(to)
+            .subject(format!("Reply: {}", title));
+        builder.body = MimePart {
+            headers: vec![(
+                "Content-Type".into(),
+                ContentType::new("multipart/alternative").into(),
+            )],
+            contents: BodyPart::Multipart(vec![
+                MimePart {
+                    headers: vec![(
+                        "Content-Type".into(),
+                        ContentType::new("text/plain")
+                            .attribute("charset", "utf-8")
+                            .into(),
+                    )],
+                    contents: BodyPart::Text(summary.into()),
+                },
+                MimePart {
+                    headers: vec![(
+                        "Content-Type".into(),
+                        ContentType::new("text/calendar")
+                            .attribute("charset", "utf-8")
+                            .attribute("method", "REPLY")
+                            .into(),
+                    )],
+                    contents: BodyPart::Text(ical.into()),
+                },
+            ]),
+        }
+        .into();
+
+        SchedulingMessage {
+            from: from.to_string(),
+            to: to.to_string(),
+            message: builder.write_to_vec().unwrap_or_default(),
+        }
+    }
+}
+
+pub trait JMAPCalendarITip<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn calendar_itip_ingest(
+        &self,
+        account_id: AccountId,
+        sender: &str,
+        calendars: &[ICalendar],
+    ) -> store::Result<Option<Changes>>;
+}
+
+impl<T> JMAPCalendarITip<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn calendar_itip_ingest(
+        &self,
+        account_id: AccountId,
+        sender: &str,
+        calendars: &[ICalendar],
+    ) -> store::Result<Option<Changes>> {
+        // New invitations are added to the account's first calendar
+        let calendar_ids = self
+            .get_document_ids(account_id, Collection::Calendar)?
+            .unwrap_or_default();
+        let default_calendar_id = if let Some(calendar_id) = calendar_ids.min() {
+            calendar_id
+        } else {
+            debug!(
+                "Ignoring scheduling message for account {} without calendars.",
+                account_id
+            );
+            return Ok(None);
+        };
+        let sender = sender.to_lowercase();
+        let mut batch = WriteBatch::new(account_id);
+        let _lock = self.lock_collection(account_id, Collection::CalendarEvent);
+
+        for calendar in calendars {
+            let method = if let Some(method) = &calendar.method {
+                method.as_str()
+            } else {
+                continue;
+            };
+
+            for event in &calendar.events {
+                let uid = if let Some(uid) = event.get("uid").and_then(|v| v.as_str()) {
+                    uid
+                } else {
+                    continue;
+                };
+                let organizer = organizer_email(event);
+                let document_id = self
+                    .query_store::<FilterMapper>(
+                        account_id,
+                        Collection::CalendarEvent,
+                        Filter::eq(Property::Uid.into(), Query::Keyword(uid.to_string())),
+                        Comparator::None,
+                    )?
+                    .into_iter()
+                    .next()
+                    .map(|id| id.get_document_id());
+                let current_fields = if let Some(document_id) = document_id {
+                    self.get_orm::<CalendarEvent>(account_id, document_id)?
+                } else {
+                    None
+                };
+
+                // Only the organizer can send invitations and cancellations,
+                // and only attendees can reply to them.
+                let mut changes = match (method, &current_fields) {
+                    ("REQUEST", current) if organizer.as_deref() == Some(sender.as_str()) => {
+                        if let Some(current) = current {
+                            let current_sequence = match current.get(&Property::Sequence) {
+                                Some(Value::Number { value }) => *value as u64,
+                                _ => 0,
+                            };
+                            if organizer_email_orm(current).as_deref() != Some(sender.as_str())
+                                || event.get("sequence").and_then(|v| v.as_u64()).unwrap_or(0)
+                                    < current_sequence
+                            {
+                                continue;
+                            }
+                        }
+
+                        let mut event = event.clone();
+                        if current.is_some() {
+                            // Remove organizer properties absent in the update
+                            event.remove("uid");
+                            for property in ORGANIZER_PROPERTIES {
+                                let name = property.to_string();
+                                if !event.contains_key(&name)
+                                    && current
+                                        .as_ref()
+                                        .map_or(false, |c| c.has_property(&property))
+                                {
+                                    event.insert(name, JsonValue::Null);
+                                }
+                            }
+                        } else {
+                            event.insert(
+                                Property::CalendarIds.to_string(),
+                                JsonValue::Object(Map::from_iter([(
+                                    JMAPId::from(default_calendar_id).to_string(),
+                                    JsonValue::Bool(true),
+                                )])),
+                            );
+                        }
+                        event
+                    }
+                    ("CANCEL", Some(current))
+                        if organizer.as_deref() == Some(sender.as_str())
+                            && organizer_email_orm(current).as_deref() == Some(sender.as_str()) =>
+                    {
+                        let mut changes = Map::new();
+                        if let Some(recurrence_id) =
+                            event.get("recurrenceId").and_then(|v| v.as_str())
+                        {
+                            let mut overrides = current
+                                .get(&Property::RecurrenceOverrides)
+                                .and_then(|v| v.as_json())
+                                .and_then(|v| match v {
+                                    JsonValue::Object(v) => Some(v),
+                                    _ => None,
+                                })
+                                .unwrap_or_default();
+                            overrides.insert(
+                                recurrence_id.to_string(),
+                                serde_json::json!({"excluded": true}),
+                            );
+                            changes.insert(
+                                Property::RecurrenceOverrides.to_string(),
+                                overrides.into(),
+                            );
+                        } else {
+                            changes.insert(Property::Status.to_string(), "cancelled".into());
+                        }
+                        changes
+                    }
+                    ("REPLY", Some(current))
+                        if organizer_email_orm(current).as_deref() != Some(sender.as_str()) =>
+                    {
+                        let participation_status = event
+                            .get("participants")
+                            .and_then(|v| v.as_object())
+                            .and_then(|participants| {
+                                participants.values().find(|participant| {
+                                    participant_email(participant).as_deref()
+                                        == Some(sender.as_str())
+                                })
+                            })
+                            .and_then(|participant| participant.get("participationStatus"))
+                            .cloned();
+                        let participant_id = current
+                            .get(&Property::Participants)
+                            .and_then(|v| v.as_json())
+                            .and_then(|participants| {
+                                participants
+                                    .as_object()?
+                                    .iter()
+                                    .find_map(|(id, participant)| {
+                                        if participant_email(participant).as_deref()
+                                            == Some(sender.as_str())
+                                        {
+                                            Some(id.to_string())
+                                        } else {
+                                            None
+                                        }
+                                    })
+                            });
+                        if let (Some(participation_status), Some(participant_id)) =
+                            (participation_status, participant_id)
+                        {
+                            Map::from_iter([(
+                                format!("participants/{}/participationStatus", participant_id),
+                                participation_status,
+                            )])
+                        } else {
+                            continue;
+                        }
+                    }
+                    _ => {
+                        debug!(
+                            "Ignoring iTIP {} for event {:?} from {:?}.",
+                            method, uid, sender
+                        );
+                        continue;
+                    }
+                };
+
+                changes.remove(&Property::Id.to_string());
+                let changes =
+                    match serde_json::from_value::<CalendarEvent>(JsonValue::Object(changes)) {
+                        Ok(changes) => changes,
+                        Err(err) => {
+                            debug!("Failed to convert iTIP message for {:?}: {}", uid, err);
+                            continue;
+                        }
+                    };
+                let resolve_id = |id: &MaybeIdReference| {
+                    id.value().copied().ok_or_else(|| {
+                        jmap::error::set::SetError::invalid_property(
+                            Property::CalendarIds,
+                            "Unexpected reference.",
+                        )
+                    })
+                };
+
+                if let (Some(current), Some(document_id)) = (current_fields, document_id) {
+                    let mut document = Document::new(Collection::CalendarEvent, document_id);
+                    match TinyORM::track_changes(&current).calendar_event_set(
+                        changes,
+                        &calendar_ids,
+                        Some(&current),
+                        resolve_id,
+                    ) {
+                        Ok(fields) => {
+                            if current
+                                .merge_validate(&mut document, fields)
+                                .unwrap_or(false)
+                            {
+                                batch.update_document(document);
+                                batch.log_update(Collection::CalendarEvent, document_id);
+                            }
+                        }
+                        Err(err) => {
+                            debug!("Failed to update event {:?}: {:?}", uid, err);
+                        }
+                    }
+                } else {
+                    let document_id =
+                        self.assign_document_id(account_id, Collection::CalendarEvent)?;
+                    let mut document = Document::new(Collection::CalendarEvent, document_id);
+                    match TinyORM::<CalendarEvent>::new()
+                        .calendar_event_set(changes, &calendar_ids, None, resolve_id)
+                        .and_then(|fields| fields.insert_validate(&mut document))
+                    {
+                        Ok(_) => {
+                            batch.insert_document(document);
+                            batch.log_insert(Collection::CalendarEvent, document_id);
+                        }
+                        Err(err) => {
+                            debug!("Failed to add event {:?}: {:?}", uid, err);
+                        }
+                    }
+                }
+            }
+        }
+
+        if !batch.is_empty() {
+            self.write(batch)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+fn organizer_email(event: &Map<String, JsonValue>) -> Option<String> {
+    reply_to_email(event.get("replyTo")?)
+}
+
+fn organizer_email_orm(fields: &TinyORM<CalendarEvent>) -> Option<String> {
+    reply_to_email(&fields.get(&Property::ReplyTo)?.as_json()?)
+}
+
+fn reply_to_email(reply_to: &JsonValue) -> Option<String> {
+    reply_to
+        .get("imip")
+        .and_then(|v| v.as_str())
+        .map(|address| {
+            address
+                .strip_prefix("mailto:")
+                .unwrap_or(address)
+                .to_lowercase()
+        })
+}
@@ -0,0 +1,251 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::recurrence::{
+    from_timestamp, parse_duration, parse_local, to_timestamp, Recurrence, MAX_OCCURRENCES,
+};
+use super::schema::{CalendarEvent, Comparator, Filter, Property, Value};
+use super::sharing::JMAPShareCalendars;
+use jmap::error::method::MethodError;
+use jmap::jmap_store::query::{ExtraFilterFnc, QueryHelper, QueryObject};
+use jmap::orm::serialize::JMAPOrm;
+use jmap::request::query::{QueryRequest, QueryResponse};
+use jmap::types::jmap::JMAPId;
+use store::core::acl::ACL;
+use store::core::tag::Tag;
+use store::read::comparator::{self, FieldComparator};
+use store::read::default_filter_mapper;
+use store::read::filter::{self, Query};
+use store::{AccountId, JMAPStore, LongInteger, Store};
+
+#[derive(Debug, Clone, serde::Deserialize, Default)]
+pub struct QueryArguments {
+    #[serde(rename = "expandRecurrences")]
+    pub expand_recurrences: Option<bool>,
+    #[serde(rename = "timeZone")]
+    pub time_zone: Option<String>,
+}
+
+impl QueryObject for CalendarEvent {
+    type QueryArguments = QueryArguments;
+
+    type Filter = Filter;
+
+    type Comparator = Comparator;
+}
+
+pub trait JMAPCalendarEventQuery<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn calendar_event_query(
+        &self,
+        request: QueryRequest<CalendarEvent>,
+    ) -> jmap::Result<QueryResponse>;
+}
+
+impl<T> JMAPCalendarEventQuery<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn calendar_event_query(
+        &self,
+        request: QueryRequest<CalendarEvent>,
+    ) -> jmap::Result<QueryResponse> {
+        let mut helper = QueryHelper::new(
+            self,
+            request,
+            (|account_id: AccountId, member_of: &[AccountId]| {
+                self.calendars_shared_events(account_id, member_of, ACL::ReadItems)
+            })
+            .into(),
+        )?;
+        let account_id = helper.account_id;
+        let expand_recurrences = helper.request.arguments.expand_recurrences.unwrap_or(false);
+        let mut after = None;
+        let mut before = None;
+
+        helper.parse_filter(|filter| {
+            Ok(match filter {
+                Filter::InCalendars { value } => filter::Filter::or(
+                    value
+                        .into_iter()
+                        .map(|calendar_id| {
+                            filter::Filter::eq(
+                                Property::CalendarIds.into(),
+                                Query::Tag(Tag::Id(calendar_id.get_document_id())),
+                            )
+                        })
+                        .collect(),
+                ),
+                // Events that end after the specified date
+                Filter::After { value } => {
+                    after = value.timestamp().into();
+                    filter::Filter::gt(
+                        Property::IndexLast.into(),
+                        Query::LongInteger(value.timestamp() as LongInteger),
+                    )
+                }
+                // Events that start before the specified date
+                Filter::Before { value } => {
+                    before = value.timestamp().into();
+                    filter::Filter::lt(
+                        Property::IndexFirst.into(),
+                        Query::LongInteger(value.timestamp() as LongInteger),
+                    )
+                }
+                Filter::Text { value } => {
+                    filter::Filter::eq(Property::IndexText.into(), Query::Tokenize(value))
+                }
+                Filter::Title { value } => {
+                    filter::Filter::eq(Property::Title.into(), Query::Tokenize(value))
+                }
+                Filter::Description { value } => {
+                    filter::Filter::eq(Property::Description.into(), Query::Tokenize(value))
+                }
+                Filter::Location { value } => {
+                    filter::Filter::eq(Property::IndexLocation.into(), Query::Tokenize(value))
+                }
+                Filter::Owner { value } => {
+                    filter::Filter::eq(Property::IndexOwner.into(), Query::Tokenize(value))
+                }
+                Filter::Attendee { value } => {
+                    filter::Filter::eq(Property::IndexAttendee.into(), Query::Tokenize(value))
+                }
+                Filter::Uid { value } => {
+                    filter::Filter::eq(Property::Uid.into(), Query::Keyword(value))
+                }
+                Filter::DavName { value } => {
+                    filter::Filter::eq(Property::DavName.into(), Query::Keyword(value))
+                }
+                Filter::Unsupported { value } => {
+                    return Err(MethodError::UnsupportedFilter(value));
+                }
+            })
+        })?;
+
+        helper.parse_comparator(|comparator| {
+            Ok(comparator::Comparator::Field(FieldComparator {
+                field: {
+                    match comparator.property {
+                        Comparator::Start => Property::IndexFirst,
+                        Comparator::Created => Property::Created,
+                        Comparator::Updated => Property::Updated,
+                    }
+                }
+                .into(),
+                ascending: comparator.is_ascending,
+            }))
+        })?;
+
+        if expand_recurrences {
+            if after.is_none() || before.is_none() {
+                return Err(MethodError::InvalidArguments(
+                    "Expanding recurrences requires both \"after\" and \"before\" filters."
+                        .to_string(),
+                ));
+            }
+
+            // Recurring events are replaced by their occurrences within the
+            // requested time range, sorted by start.
+            helper
+                .query(
+                    default_filter_mapper,
+                    Some(|ids: Vec<JMAPId>| {
+                        let mut results = Vec::with_capacity(ids.len());
+                        for id in ids {
+                            let document_id = id.get_document_id();
+                            if let Some(fields) =
+                                self.get_orm::<CalendarEvent>(account_id, document_id)?
+                            {
+                                let text = |property: Property| match fields.get(&property) {
+                                    Some(Value::Text { value }) => Some(value.as_str()),
+                                    _ => None,
+                                };
+                                let start = if let Some(start) =
+                                    text(Property::Start).and_then(parse_local)
+                                {
+                                    start
+                                } else {
+                                    continue;
+                                };
+                                let time_zone = text(Property::TimeZone);
+                                let duration = text(Property::Duration)
+                                    .and_then(parse_duration)
+                                    .map(|d| d.num_seconds())
+                                    .unwrap_or(0);
+                                let after = after.unwrap();
+                                let before = before.unwrap();
+
+                                if let Some(recurrence) = Recurrence::new(
+                                    start,
+                                    fields
+                                        .get(&Property::RecurrenceRules)
+                                        .and_then(|v| v.as_json())
+                                        .as_ref(),
+                                    fields
+                                        .get(&Property::ExcludedRecurrenceRules)
+                                        .and_then(|v| v.as_json())
+                                        .as_ref(),
+                                    fields
+                                        .get(&Property::RecurrenceOverrides)
+                                        .and_then(|v| v.as_json())
+                                        .as_ref(),
+                                ) {
+                                    // Expand one day past the range to account
+                                    // for time zone offsets.
+                                    let until = from_timestamp(before + 86400, None);
+                                    for (pos, occurrence) in recurrence
+                                        .expand(until, MAX_OCCURRENCES)
+                                        .0
+                                        .into_iter()
+                                        .enumerate()
+                                    {
+                                        let occurrence_start = to_timestamp(&occurrence, time_zone);
+                                        if occurrence_start < before
+                                            && occurrence_start + duration > after
+                                        {
+                                            results.push((
+                                                occurrence_start,
+                                                JMAPId::from_parts(pos as u32 + 1, document_id),
+                                            ));
+                                        }
+                                    }
+                                } else {
+                                    results.push((to_timestamp(&start, time_zone), id));
+                                }
+                            }
+                        }
+                        results.sort_by_key(|(start, _)| *start);
+                        Ok(results.into_iter().map(|(_, id)| id).collect())
+                    }),
+                )
+                .map(|mut response| {
+                    response.can_calculate_changes = false;
+                    response
+                })
+        } else {
+            helper.query(default_filter_mapper, None::<ExtraFilterFnc>)
+        }
+    }
+}
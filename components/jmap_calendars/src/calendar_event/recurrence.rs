@@ -0,0 +1,909 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::collections::BTreeSet;
+
+use chrono_tz::Tz;
+use serde_json::{Map, Value as JsonValue};
+use store::chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone, Timelike, Weekday};
+
+// Maximum number of occurrences generated for a single event.
+pub const MAX_OCCURRENCES: usize = 10000;
+
+// Maximum number of recurrence periods examined for a single rule, prevents
+// looping forever on rules that never produce an occurrence.
+const MAX_PERIODS: i64 = 100000;
+
+// Used as the end of events that repeat forever (9999-12-31T23:59:59Z).
+pub const UNBOUNDED_END: i64 = 253402300799;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Frequency {
+    Yearly,
+    Monthly,
+    Weekly,
+    Daily,
+    Hourly,
+    Minutely,
+    Secondly,
+}
+
+#[derive(Debug, Clone)]
+pub struct RecurrenceRule {
+    pub frequency: Frequency,
+    pub interval: u32,
+    pub first_day_of_week: Weekday,
+    pub by_day: Vec<(Weekday, Option<i32>)>,
+    pub by_month_day: Vec<i32>,
+    pub by_month: Vec<u32>,
+    pub by_year_day: Vec<i32>,
+    pub by_hour: Vec<u32>,
+    pub by_minute: Vec<u32>,
+    pub by_second: Vec<u32>,
+    pub by_set_position: Vec<i32>,
+    pub count: Option<usize>,
+    pub until: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Recurrence {
+    pub start: NaiveDateTime,
+    pub rules: Vec<RecurrenceRule>,
+    pub excluded_rules: Vec<RecurrenceRule>,
+    pub added: Vec<NaiveDateTime>,
+    pub excluded: Vec<NaiveDateTime>,
+}
+
+impl Default for RecurrenceRule {
+    fn default() -> Self {
+        RecurrenceRule {
+            frequency: Frequency::Daily,
+            interval: 1,
+            first_day_of_week: Weekday::Mon,
+            by_day: Vec::new(),
+            by_month_day: Vec::new(),
+            by_month: Vec::new(),
+            by_year_day: Vec::new(),
+            by_hour: Vec::new(),
+            by_minute: Vec::new(),
+            by_second: Vec::new(),
+            by_set_position: Vec::new(),
+            count: None,
+            until: None,
+        }
+    }
+}
+
+impl Recurrence {
+    // Builds the recurrence set of a JSCalendar event, returns None if the event
+    // does not repeat.
+    pub fn new(
+        start: NaiveDateTime,
+        rules: Option<&JsonValue>,
+        excluded_rules: Option<&JsonValue>,
+        overrides: Option<&JsonValue>,
+    ) -> Option<Self> {
+        let rules = rules
+            .and_then(|rules| rules.as_array())
+            .map(|rules| {
+                rules
+                    .iter()
+                    .filter_map(RecurrenceRule::parse)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        let mut recurrence = Recurrence {
+            start,
+            rules,
+            excluded_rules: excluded_rules
+                .and_then(|rules| rules.as_array())
+                .map(|rules| {
+                    rules
+                        .iter()
+                        .filter_map(RecurrenceRule::parse)
+                        .collect::<Vec<_>>()
+                })
+                .unwrap_or_default(),
+            added: Vec::new(),
+            excluded: Vec::new(),
+        };
+
+        if let Some(overrides) = overrides.and_then(|overrides| overrides.as_object()) {
+            for (recurrence_id, patch) in overrides {
+                if let Some(recurrence_id) = parse_local(recurrence_id) {
+                    if is_excluded(patch) {
+                        recurrence.excluded.push(recurrence_id);
+                    } else {
+                        recurrence.added.push(recurrence_id);
+                    }
+                }
+            }
+        }
+
+        if !recurrence.rules.is_empty() || !recurrence.added.is_empty() {
+            Some(recurrence)
+        } else {
+            None
+        }
+    }
+
+    // Returns the recurrence ids in chronological order, starting with the
+    // event's start, up to 'until' or 'max' occurrences. The flag returned
+    // indicates whether there are more occurrences after the last one.
+    pub fn expand(&self, until: Option<NaiveDateTime>, max: usize) -> (Vec<NaiveDateTime>, bool) {
+        let mut has_more = false;
+        let mut occurrences = BTreeSet::new();
+        occurrences.insert(self.start);
+
+        for rule in &self.rules {
+            let (rule_occurrences, rule_has_more) = rule.expand(self.start, until, max);
+            occurrences.extend(rule_occurrences);
+            has_more |= rule_has_more;
+        }
+        for added in &self.added {
+            if until.map_or(true, |until| *added <= until) {
+                occurrences.insert(*added);
+            }
+        }
+
+        // Remove exclusions
+        if let Some(last) = occurrences.iter().next_back().copied() {
+            for rule in &self.excluded_rules {
+                for excluded in rule.expand(self.start, Some(last), usize::MAX).0 {
+                    occurrences.remove(&excluded);
+                }
+            }
+        }
+        for excluded in &self.excluded {
+            occurrences.remove(excluded);
+        }
+
+        if occurrences.len() > max {
+            has_more = true;
+        }
+
+        (occurrences.into_iter().take(max).collect(), has_more)
+    }
+}
+
+impl RecurrenceRule {
+    pub fn parse(rule: &JsonValue) -> Option<Self> {
+        let rule = rule.as_object()?;
+
+        // Only the Gregorian calendar is supported, rules using other calendar
+        // scales or week numbers are ignored.
+        if rule
+            .get("rscale")
+            .and_then(|v| v.as_str())
+            .map_or(false, |rscale| !rscale.eq_ignore_ascii_case("gregorian"))
+            || rule.contains_key("byWeekNo")
+        {
+            return None;
+        }
+
+        Some(RecurrenceRule {
+            frequency: match rule.get("frequency")?.as_str()? {
+                "yearly" => Frequency::Yearly,
+                "monthly" => Frequency::Monthly,
+                "weekly" => Frequency::Weekly,
+                "daily" => Frequency::Daily,
+                "hourly" => Frequency::Hourly,
+                "minutely" => Frequency::Minutely,
+                "secondly" => Frequency::Secondly,
+                _ => return None,
+            },
+            interval: rule
+                .get("interval")
+                .and_then(|v| v.as_u64())
+                .unwrap_or(1)
+                .clamp(1, u32::MAX as u64) as u32,
+            first_day_of_week: rule
+                .get("firstDayOfWeek")
+                .and_then(|v| v.as_str())
+                .and_then(parse_weekday)
+                .unwrap_or(Weekday::Mon),
+            by_day: rule
+                .get("byDay")
+                .and_then(|v| v.as_array())
+                .map(|days| {
+                    days.iter()
+                        .filter_map(|day| {
+                            Some((
+                                parse_weekday(day.get("day")?.as_str()?)?,
+                                day.get("nthOfPeriod")
+                                    .and_then(|v| v.as_i64())
+                                    .map(|v| v as i32),
+                            ))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            by_month_day: parse_numbers(rule.get("byMonthDay"), -31, 31),
+            by_month: rule
+                .get("byMonth")
+                .and_then(|v| v.as_array())
+                .map(|months| {
+                    months
+                        .iter()
+                        .filter_map(|month| month.as_str()?.parse::<u32>().ok())
+                        .filter(|month| (1..=12).contains(month))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            by_year_day: parse_numbers(rule.get("byYearDay"), -366, 366),
+            by_hour: parse_numbers(rule.get("byHour"), 0, 23)
+                .into_iter()
+                .map(|v| v as u32)
+                .collect(),
+            by_minute: parse_numbers(rule.get("byMinute"), 0, 59)
+                .into_iter()
+                .map(|v| v as u32)
+                .collect(),
+            by_second: parse_numbers(rule.get("bySecond"), 0, 59)
+                .into_iter()
+                .map(|v| v as u32)
+                .collect(),
+            by_set_position: parse_numbers(rule.get("bySetPosition"), -366, 366),
+            count: rule
+                .get("count")
+                .and_then(|v| v.as_u64())
+                .map(|v| v as usize),
+            until: rule
+                .get("until")
+                .and_then(|v| v.as_str())
+                .and_then(parse_local),
+        })
+    }
+
+    // Expands the rule from 'start', the flag returned indicates whether the
+    // rule has occurrences after the last one returned.
+    pub fn expand(
+        &self,
+        start: NaiveDateTime,
+        until: Option<NaiveDateTime>,
+        max: usize,
+    ) -> (Vec<NaiveDateTime>, bool) {
+        let mut occurrences = Vec::new();
+        let mut total = 0;
+
+        for period in 0..MAX_PERIODS {
+            let candidates = if let Some(candidates) = self.candidates(start, period) {
+                candidates
+            } else {
+                return (occurrences, false);
+            };
+
+            for candidate in candidates {
+                if candidate < start {
+                    continue;
+                } else if matches!(self.until, Some(rule_until) if candidate > rule_until)
+                    || matches!(self.count, Some(count) if total >= count)
+                {
+                    return (occurrences, false);
+                } else if matches!(until, Some(until) if candidate > until)
+                    || occurrences.len() >= max
+                {
+                    return (occurrences, true);
+                }
+                total += 1;
+                occurrences.push(candidate);
+            }
+        }
+
+        (occurrences, true)
+    }
+
+    // Returns the candidate occurrences for a period in chronological order,
+    // or None once the period is beyond the supported date range.
+    fn candidates(&self, start: NaiveDateTime, period: i64) -> Option<Vec<NaiveDateTime>> {
+        let offset = period * self.interval as i64;
+        let date = start.date();
+        let mut dates = Vec::new();
+
+        let mut candidates = match self.frequency {
+            Frequency::Yearly => {
+                let year = date.year() + i32::try_from(offset).ok()?;
+                if year > 9999 {
+                    return None;
+                }
+                if !self.by_year_day.is_empty() {
+                    let days_in_year = days_in_year(year);
+                    for &day in &self.by_year_day {
+                        let day = if day > 0 { day } else { days_in_year + 1 + day };
+                        if let Some(date) = NaiveDate::from_yo_opt(year, day.max(0) as u32) {
+                            if self.by_month.is_empty() || self.by_month.contains(&date.month()) {
+                                dates.push(date);
+                            }
+                        }
+                    }
+                    self.filter_weekdays(&mut dates);
+                } else if self.by_month.is_empty()
+                    && self.by_month_day.is_empty()
+                    && !self.by_day.is_empty()
+                {
+                    let first = NaiveDate::from_ymd_opt(year, 1, 1)?;
+                    let last = NaiveDate::from_ymd_opt(year, 12, 31)?;
+                    self.expand_weekdays(first, last, &mut dates);
+                } else {
+                    let months = if !self.by_month.is_empty() {
+                        self.by_month.clone()
+                    } else if !self.by_month_day.is_empty() || !self.by_day.is_empty() {
+                        (1..=12).collect()
+                    } else {
+                        vec![date.month()]
+                    };
+                    for month in months {
+                        self.expand_month(year, month, date.day(), &mut dates);
+                    }
+                }
+                self.expand_times(&dates, start)
+            }
+            Frequency::Monthly => {
+                let months = (date.year() as i64) * 12 + (date.month0() as i64) + offset;
+                let year = i32::try_from(months.div_euclid(12)).ok()?;
+                let month = months.rem_euclid(12) as u32 + 1;
+                if year > 9999 {
+                    return None;
+                }
+                if self.by_month.is_empty() || self.by_month.contains(&month) {
+                    self.expand_month(year, month, date.day(), &mut dates);
+                }
+                self.expand_times(&dates, start)
+            }
+            Frequency::Weekly => {
+                let week_start = date
+                    - Duration::days(days_since(date.weekday(), self.first_day_of_week))
+                    + Duration::days(offset.checked_mul(7)?);
+                if week_start.year() > 9999 {
+                    return None;
+                }
+                for day in 0..7 {
+                    let date = week_start + Duration::days(day);
+                    if (self.by_day.is_empty() && date.weekday() == start.weekday())
+                        || self.by_day.iter().any(|(wd, _)| *wd == date.weekday())
+                    {
+                        dates.push(date);
+                    }
+                }
+                self.filter_months(&mut dates);
+                self.expand_times(&dates, start)
+            }
+            Frequency::Daily => {
+                let date = date + Duration::days(offset);
+                if date.year() > 9999 {
+                    return None;
+                }
+                dates.push(date);
+                self.filter_months(&mut dates);
+                self.filter_month_days(&mut dates);
+                self.filter_weekdays(&mut dates);
+                self.expand_times(&dates, start)
+            }
+            Frequency::Hourly | Frequency::Minutely | Frequency::Secondly => {
+                let candidate = start
+                    + match self.frequency {
+                        Frequency::Hourly => Duration::hours(offset),
+                        Frequency::Minutely => Duration::minutes(offset),
+                        _ => Duration::seconds(offset),
+                    };
+                if candidate.year() > 9999 {
+                    return None;
+                }
+                dates.push(candidate.date());
+                self.filter_months(&mut dates);
+                self.filter_month_days(&mut dates);
+                self.filter_weekdays(&mut dates);
+                if !dates.is_empty()
+                    && (self.by_hour.is_empty() || self.by_hour.contains(&candidate.hour()))
+                    && (self.by_minute.is_empty() || self.by_minute.contains(&candidate.minute()))
+                    && (self.by_second.is_empty() || self.by_second.contains(&candidate.second()))
+                {
+                    vec![candidate]
+                } else {
+                    vec![]
+                }
+            }
+        };
+
+        candidates.sort_unstable();
+        candidates.dedup();
+
+        if !self.by_set_position.is_empty() && !candidates.is_empty() {
+            let total = candidates.len() as i32;
+            let mut positions = self
+                .by_set_position
+                .iter()
+                .filter_map(|&pos| {
+                    let pos = if pos > 0 { pos - 1 } else { total + pos };
+                    if (0..total).contains(&pos) {
+                        Some(pos as usize)
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            positions.sort_unstable();
+            positions.dedup();
+            candidates = positions.into_iter().map(|pos| candidates[pos]).collect();
+        }
+
+        Some(candidates)
+    }
+
+    fn expand_month(&self, year: i32, month: u32, default_day: u32, dates: &mut Vec<NaiveDate>) {
+        let first = if let Some(first) = NaiveDate::from_ymd_opt(year, month, 1) {
+            first
+        } else {
+            return;
+        };
+        let days_in_month = days_in_month(year, month) as i32;
+        let last = first + Duration::days(days_in_month as i64 - 1);
+
+        if !self.by_month_day.is_empty() {
+            for &day in &self.by_month_day {
+                let day = if day > 0 {
+                    day
+                } else {
+                    days_in_month + 1 + day
+                };
+                if (1..=days_in_month).contains(&day) {
+                    dates.push(first + Duration::days(day as i64 - 1));
+                }
+            }
+            if !self.by_day.is_empty() {
+                let mut weekdays = Vec::new();
+                self.expand_weekdays(first, last, &mut weekdays);
+                dates.retain(|date| weekdays.contains(date));
+            }
+        } else if !self.by_day.is_empty() {
+            self.expand_weekdays(first, last, dates);
+        } else if default_day <= days_in_month as u32 {
+            // Months without the start's day of the month are skipped
+            dates.push(first + Duration::days(default_day as i64 - 1));
+        }
+    }
+
+    fn expand_weekdays(&self, first: NaiveDate, last: NaiveDate, dates: &mut Vec<NaiveDate>) {
+        for &(weekday, nth) in &self.by_day {
+            let first_match = first + Duration::days(days_since(weekday, first.weekday()));
+            let mut matches = Vec::new();
+            let mut date = first_match;
+            while date <= last {
+                matches.push(date);
+                date += Duration::days(7);
+            }
+            match nth {
+                Some(nth) if nth > 0 => {
+                    if let Some(date) = matches.get(nth as usize - 1) {
+                        dates.push(*date);
+                    }
+                }
+                Some(nth) if nth < 0 => {
+                    if let Some(pos) = matches.len().checked_sub(nth.unsigned_abs() as usize) {
+                        dates.push(matches[pos]);
+                    }
+                }
+                _ => dates.extend(matches),
+            }
+        }
+    }
+
+    fn expand_times(&self, dates: &[NaiveDate], start: NaiveDateTime) -> Vec<NaiveDateTime> {
+        let hours = if !self.by_hour.is_empty() {
+            self.by_hour.clone()
+        } else {
+            vec![start.hour()]
+        };
+        let minutes = if !self.by_minute.is_empty() {
+            self.by_minute.clone()
+        } else {
+            vec![start.minute()]
+        };
+        let seconds = if !self.by_second.is_empty() {
+            self.by_second.clone()
+        } else {
+            vec![start.second()]
+        };
+
+        let mut result = Vec::with_capacity(dates.len());
+        for date in dates {
+            for &hour in &hours {
+                for &minute in &minutes {
+                    for &second in &seconds {
+                        if let Some(dt) = date.and_hms_opt(hour, minute, second) {
+                            result.push(dt);
+                        }
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    fn filter_months(&self, dates: &mut Vec<NaiveDate>) {
+        if !self.by_month.is_empty() {
+            dates.retain(|date| self.by_month.contains(&date.month()));
+        }
+    }
+
+    fn filter_month_days(&self, dates: &mut Vec<NaiveDate>) {
+        if !self.by_month_day.is_empty() {
+            dates.retain(|date| {
+                let days_in_month = days_in_month(date.year(), date.month()) as i32;
+                self.by_month_day.iter().any(|&day| {
+                    (if day > 0 {
+                        day
+                    } else {
+                        days_in_month + 1 + day
+                    }) == date.day() as i32
+                })
+            });
+        }
+    }
+
+    fn filter_weekdays(&self, dates: &mut Vec<NaiveDate>) {
+        if !self.by_day.is_empty() {
+            dates.retain(|date| self.by_day.iter().any(|(wd, _)| *wd == date.weekday()));
+        }
+    }
+}
+
+pub fn is_excluded(patch: &JsonValue) -> bool {
+    patch
+        .get("excluded")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+// Returns the patch of a recurrence override, if any.
+pub fn get_override<'x>(
+    overrides: Option<&'x JsonValue>,
+    recurrence_id: &NaiveDateTime,
+) -> Option<&'x Map<String, JsonValue>> {
+    overrides?
+        .as_object()?
+        .get(&format_local(recurrence_id))?
+        .as_object()
+}
+
+pub fn parse_local(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S").ok()
+}
+
+pub fn format_local(value: &NaiveDateTime) -> String {
+    value.format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+// Converts a local date-time to a UTC timestamp, floating times and unknown
+// time zones are treated as UTC.
+pub fn to_timestamp(value: &NaiveDateTime, time_zone: Option<&str>) -> i64 {
+    if let Some(tz) = time_zone.and_then(|tz| tz.parse::<Tz>().ok()) {
+        // Times that fall in a DST gap are moved forward by one hour
+        if let Some(dt) = tz.from_local_datetime(value).earliest().or_else(|| {
+            tz.from_local_datetime(&(*value + Duration::hours(1)))
+                .earliest()
+        }) {
+            return dt.timestamp();
+        }
+    }
+    value.timestamp()
+}
+
+// Converts a UTC timestamp to local time in the specified time zone.
+pub fn from_timestamp(timestamp: i64, time_zone: Option<&str>) -> Option<NaiveDateTime> {
+    let value = NaiveDateTime::from_timestamp_opt(timestamp, 0)?;
+    if let Some(tz) = time_zone.and_then(|tz| tz.parse::<Tz>().ok()) {
+        Some(tz.from_utc_datetime(&value).naive_local())
+    } else {
+        Some(value)
+    }
+}
+
+// Parses an ISO 8601 duration such as "P1DT2H30M" or "-PT15M".
+pub fn parse_duration(value: &str) -> Option<Duration> {
+    let (is_negative, value) = if let Some(value) = value.strip_prefix('-') {
+        (true, value)
+    } else {
+        (false, value.strip_prefix('+').unwrap_or(value))
+    };
+    let mut value = value.strip_prefix('P')?.chars();
+    let mut seconds = 0i64;
+    let mut num = None;
+    let mut is_time = false;
+    let mut has_values = false;
+
+    for ch in &mut value {
+        match ch {
+            '0'..='9' => {
+                num = Some(
+                    num.unwrap_or(0i64)
+                        .checked_mul(10)?
+                        .checked_add(ch as i64 - '0' as i64)?,
+                );
+            }
+            'T' if !is_time && num.is_none() => {
+                is_time = true;
+            }
+            _ => {
+                let multiplier = match (ch, is_time) {
+                    ('W', false) => 7 * 86400,
+                    ('D', false) => 86400,
+                    ('H', true) => 3600,
+                    ('M', true) => 60,
+                    ('S', true) => 1,
+                    _ => return None,
+                };
+                seconds = seconds.checked_add(num.take()?.checked_mul(multiplier)?)?;
+                has_values = true;
+            }
+        }
+    }
+
+    if has_values && num.is_none() {
+        Some(Duration::seconds(if is_negative {
+            -seconds
+        } else {
+            seconds
+        }))
+    } else {
+        None
+    }
+}
+
+pub fn format_duration(value: &Duration) -> String {
+    let mut seconds = value.num_seconds();
+    let mut result = String::with_capacity(16);
+    if seconds < 0 {
+        result.push('-');
+        seconds = -seconds;
+    }
+    result.push('P');
+    if seconds >= 86400 {
+        result.push_str(&format!("{}D", seconds / 86400));
+        seconds %= 86400;
+    }
+    if seconds > 0 || result.ends_with('P') {
+        result.push('T');
+        if seconds >= 3600 {
+            result.push_str(&format!("{}H", seconds / 3600));
+            seconds %= 3600;
+        }
+        if seconds >= 60 {
+            result.push_str(&format!("{}M", seconds / 60));
+            seconds %= 60;
+        }
+        if seconds > 0 || result.ends_with('T') {
+            result.push_str(&format!("{}S", seconds));
+        }
+    }
+    result
+}
+
+pub fn parse_weekday(value: &str) -> Option<Weekday> {
+    match value.to_ascii_lowercase().as_str() {
+        "mo" => Some(Weekday::Mon),
+        "tu" => Some(Weekday::Tue),
+        "we" => Some(Weekday::Wed),
+        "th" => Some(Weekday::Thu),
+        "fr" => Some(Weekday::Fri),
+        "sa" => Some(Weekday::Sat),
+        "su" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+pub fn format_weekday(value: &Weekday) -> &'static str {
+    match value {
+        Weekday::Mon => "mo",
+        Weekday::Tue => "tu",
+        Weekday::Wed => "we",
+        Weekday::Thu => "th",
+        Weekday::Fri => "fr",
+        Weekday::Sat => "sa",
+        Weekday::Sun => "su",
+    }
+}
+
+fn parse_numbers(value: Option<&JsonValue>, min: i64, max: i64) -> Vec<i32> {
+    value
+        .and_then(|v| v.as_array())
+        .map(|values| {
+            values
+                .iter()
+                .filter_map(|v| v.as_i64())
+                .filter(|v| (min..=max).contains(v) && *v != 0 || (min == 0 && *v == 0))
+                .map(|v| v as i32)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn days_since(weekday: Weekday, since: Weekday) -> i64 {
+    (7 + weekday.num_days_from_monday() as i64 - since.num_days_from_monday() as i64) % 7
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let (next_year, next_month) = if month == 12 {
+        (year + 1, 1)
+    } else {
+        (year, month + 1)
+    };
+    NaiveDate::from_ymd_opt(next_year, next_month, 1)
+        .and_then(|next| {
+            NaiveDate::from_ymd_opt(year, month, 1).map(|first| (next - first).num_days() as u32)
+        })
+        .unwrap_or(30)
+}
+
+fn days_in_year(year: i32) -> i32 {
+    if NaiveDate::from_ymd_opt(year, 2, 29).is_some() {
+        366
+    } else {
+        365
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{format_duration, parse_duration, parse_local, Recurrence};
+
+    fn expand(start: &str, rules: serde_json::Value, max: usize) -> Vec<String> {
+        Recurrence::new(parse_local(start).unwrap(), Some(&rules), None, None)
+            .unwrap()
+            .expand(None, max)
+            .0
+            .into_iter()
+            .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn expand_rules() {
+        for (start, rules, max, expected) in [
+            (
+                "2022-01-31T10:00:00",
+                json!([{"frequency": "monthly", "count": 4}]),
+                10,
+                vec![
+                    "2022-01-31T10:00:00",
+                    "2022-03-31T10:00:00",
+                    "2022-05-31T10:00:00",
+                    "2022-07-31T10:00:00",
+                ],
+            ),
+            (
+                "2022-10-03T09:00:00",
+                json!([{"frequency": "weekly", "interval": 2,
+                        "byDay": [{"day": "mo"}, {"day": "fr"}],
+                        "until": "2022-10-31T00:00:00"}]),
+                10,
+                vec![
+                    "2022-10-03T09:00:00",
+                    "2022-10-07T09:00:00",
+                    "2022-10-17T09:00:00",
+                    "2022-10-21T09:00:00",
+                ],
+            ),
+            (
+                "2022-01-25T18:00:00",
+                json!([{"frequency": "monthly",
+                        "byDay": [{"day": "tu", "nthOfPeriod": -1}]}]),
+                3,
+                vec![
+                    "2022-01-25T18:00:00",
+                    "2022-02-22T18:00:00",
+                    "2022-03-29T18:00:00",
+                ],
+            ),
+            (
+                "2022-01-31T08:00:00",
+                json!([{"frequency": "monthly",
+                        "byDay": [{"day": "mo"}, {"day": "tu"}, {"day": "we"},
+                                  {"day": "th"}, {"day": "fr"}],
+                        "bySetPosition": [-1], "count": 3}]),
+                10,
+                vec![
+                    "2022-01-31T08:00:00",
+                    "2022-02-28T08:00:00",
+                    "2022-03-31T08:00:00",
+                ],
+            ),
+            (
+                "2020-02-29T00:00:00",
+                json!([{"frequency": "yearly", "count": 2}]),
+                10,
+                vec!["2020-02-29T00:00:00", "2024-02-29T00:00:00"],
+            ),
+            (
+                "2022-11-24T12:00:00",
+                json!([{"frequency": "yearly", "byMonth": ["11"],
+                        "byDay": [{"day": "th", "nthOfPeriod": 4}]}]),
+                3,
+                vec![
+                    "2022-11-24T12:00:00",
+                    "2023-11-23T12:00:00",
+                    "2024-11-28T12:00:00",
+                ],
+            ),
+            (
+                "2022-10-18T08:00:00",
+                json!([{"frequency": "daily", "byHour": [8, 20], "count": 3}]),
+                10,
+                vec![
+                    "2022-10-18T08:00:00",
+                    "2022-10-18T20:00:00",
+                    "2022-10-19T08:00:00",
+                ],
+            ),
+        ] {
+            assert_eq!(expand(start, rules, max), expected, "{}", start);
+        }
+    }
+
+    #[test]
+    fn expand_overrides() {
+        let recurrence = Recurrence::new(
+            parse_local("2022-10-17T10:00:00").unwrap(),
+            Some(&json!([{"frequency": "daily", "count": 3}])),
+            None,
+            Some(&json!({
+                "2022-10-18T10:00:00": {"excluded": true},
+                "2022-10-25T10:00:00": {},
+            })),
+        )
+        .unwrap();
+        let (occurrences, has_more) = recurrence.expand(None, 10);
+        assert!(!has_more);
+        assert_eq!(
+            occurrences
+                .into_iter()
+                .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "2022-10-17T10:00:00",
+                "2022-10-19T10:00:00",
+                "2022-10-25T10:00:00"
+            ]
+        );
+    }
+
+    #[test]
+    fn durations() {
+        for (duration, seconds, formatted) in [
+            ("PT1H", 3600, "PT1H"),
+            ("P1DT2H30M", 95400, "P1DT2H30M"),
+            ("P2W", 1209600, "P14D"),
+            ("-PT15M", -900, "-PT15M"),
+            ("PT0S", 0, "PT0S"),
+        ] {
+            let parsed = parse_duration(duration).unwrap();
+            assert_eq!(parsed.num_seconds(), seconds, "{}", duration);
+            assert_eq!(format_duration(&parsed), formatted);
+        }
+        for invalid in ["", "P", "PT", "1H", "PT1D", "P1H"] {
+            assert!(parse_duration(invalid).is_none(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,491 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::fmt::Display;
+
+use jmap::{
+    orm,
+    request::{MaybeIdReference, ResultReference},
+    types::{date::JMAPDate, jmap::JMAPId},
+};
+use serde::{Deserialize, Serialize};
+use store::{core::vec_map::VecMap, FieldId};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CalendarEvent {
+    pub properties: VecMap<Property, Value>,
+}
+
+// Structured JSCalendar properties are stored as JSON text, the values used
+// for searching and for time-range queries are derived from them into the
+// internal index properties on every update.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Value {
+    Id {
+        value: JMAPId,
+    },
+    Text {
+        value: String,
+    },
+    Bool {
+        value: bool,
+    },
+    Number {
+        value: u32,
+    },
+    Date {
+        value: JMAPDate,
+    },
+    Json {
+        value: String,
+    },
+    Patch {
+        value: Vec<(Vec<String>, String)>,
+    },
+    CalendarIds {
+        value: VecMap<MaybeIdReference, bool>,
+        set: bool,
+    },
+    ResultReference {
+        value: ResultReference,
+    },
+    Null,
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Value::Null
+    }
+}
+
+impl orm::Value for Value {
+    fn index_as(&self) -> orm::Index {
+        match self {
+            Value::Text { value } => value.to_string().into(),
+            Value::Number { value } => (*value).into(),
+            Value::Date { value } => (value.timestamp() as u64).into(),
+            _ => orm::Index::Null,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::Text { value } | Value::Json { value } => value.is_empty(),
+            Value::Null => true,
+            _ => false,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Value::Id { .. } => std::mem::size_of::<JMAPId>(),
+            Value::Text { value } | Value::Json { value } => value.len(),
+            Value::Bool { .. } => std::mem::size_of::<bool>(),
+            Value::Number { .. } => std::mem::size_of::<u32>(),
+            Value::Date { .. } => std::mem::size_of::<JMAPDate>(),
+            Value::Patch { value } => value.iter().fold(0, |acc, (path, value)| {
+                acc + path.iter().fold(0, |acc, item| acc + item.len()) + value.len()
+            }),
+            Value::CalendarIds { value, .. } => value.len() * std::mem::size_of::<JMAPId>(),
+            Value::ResultReference { .. } => std::mem::size_of::<ResultReference>(),
+            Value::Null => 0,
+        }
+    }
+}
+
+impl Value {
+    pub fn get_calendar_ids(&mut self) -> Option<&mut VecMap<MaybeIdReference, bool>> {
+        match self {
+            Value::CalendarIds { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text { value } => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_json(&self) -> Option<serde_json::Value> {
+        match self {
+            Value::Json { value } => serde_json::from_str(value).ok(),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[repr(u8)]
+pub enum Property {
+    Id = 0,
+    CalendarIds = 1,
+    Type = 2,
+    Uid = 3,
+    Created = 4,
+    Updated = 5,
+    Sequence = 6,
+    Method = 7,
+    Title = 8,
+    Description = 9,
+    DescriptionContentType = 10,
+    ShowWithoutTime = 11,
+    Start = 12,
+    Duration = 13,
+    TimeZone = 14,
+    Status = 15,
+    FreeBusyStatus = 16,
+    Privacy = 17,
+    Priority = 18,
+    Color = 19,
+    Locale = 20,
+    ProdId = 21,
+    RecurrenceId = 22,
+    RecurrenceIdTimeZone = 23,
+    Excluded = 24,
+    UseDefaultAlerts = 25,
+    IsDraft = 26,
+    SentBy = 27,
+    HideAttendees = 28,
+    MayInviteSelf = 29,
+    MayInviteOthers = 30,
+    RelatedTo = 31,
+    Keywords = 32,
+    Categories = 33,
+    Locations = 34,
+    VirtualLocations = 35,
+    Links = 36,
+    ReplyTo = 37,
+    Participants = 38,
+    RecurrenceRules = 39,
+    ExcludedRecurrenceRules = 40,
+    RecurrenceOverrides = 41,
+    Alerts = 42,
+    Localizations = 43,
+    TimeZones = 44,
+    UtcStart = 45,
+    UtcEnd = 46,
+    IsOrigin = 47,
+    BaseEventId = 48,
+    IndexText = 49,
+    IndexLocation = 50,
+    IndexOwner = 51,
+    IndexAttendee = 52,
+    IndexFirst = 53,
+    IndexLast = 54,
+    PerUser = 55,
+    DavName = 56,
+    Invalid = 57,
+}
+
+impl Property {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "id" => Property::Id,
+            "calendarIds" => Property::CalendarIds,
+            "@type" => Property::Type,
+            "uid" => Property::Uid,
+            "created" => Property::Created,
+            "updated" => Property::Updated,
+            "sequence" => Property::Sequence,
+            "method" => Property::Method,
+            "title" => Property::Title,
+            "description" => Property::Description,
+            "descriptionContentType" => Property::DescriptionContentType,
+            "showWithoutTime" => Property::ShowWithoutTime,
+            "start" => Property::Start,
+            "duration" => Property::Duration,
+            "timeZone" => Property::TimeZone,
+            "status" => Property::Status,
+            "freeBusyStatus" => Property::FreeBusyStatus,
+            "privacy" => Property::Privacy,
+            "priority" => Property::Priority,
+            "color" => Property::Color,
+            "locale" => Property::Locale,
+            "prodId" => Property::ProdId,
+            "recurrenceId" => Property::RecurrenceId,
+            "recurrenceIdTimeZone" => Property::RecurrenceIdTimeZone,
+            "excluded" => Property::Excluded,
+            "useDefaultAlerts" => Property::UseDefaultAlerts,
+            "isDraft" => Property::IsDraft,
+            "sentBy" => Property::SentBy,
+            "hideAttendees" => Property::HideAttendees,
+            "mayInviteSelf" => Property::MayInviteSelf,
+            "mayInviteOthers" => Property::MayInviteOthers,
+            "relatedTo" => Property::RelatedTo,
+            "keywords" => Property::Keywords,
+            "categories" => Property::Categories,
+            "locations" => Property::Locations,
+            "virtualLocations" => Property::VirtualLocations,
+            "links" => Property::Links,
+            "replyTo" => Property::ReplyTo,
+            "participants" => Property::Participants,
+            "recurrenceRules" => Property::RecurrenceRules,
+            "excludedRecurrenceRules" => Property::ExcludedRecurrenceRules,
+            "recurrenceOverrides" => Property::RecurrenceOverrides,
+            "alerts" => Property::Alerts,
+            "localizations" => Property::Localizations,
+            "timeZones" => Property::TimeZones,
+            "utcStart" => Property::UtcStart,
+            "utcEnd" => Property::UtcEnd,
+            "isOrigin" => Property::IsOrigin,
+            "baseEventId" => Property::BaseEventId,
+            "davName" => Property::DavName,
+            _ => Property::Invalid,
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        (*self as u8) >= Property::RelatedTo as u8 && (*self as u8) <= Property::TimeZones as u8
+    }
+
+    pub fn is_text(&self) -> bool {
+        matches!(
+            self,
+            Property::Type
+                | Property::Uid
+                | Property::Method
+                | Property::Title
+                | Property::Description
+                | Property::DescriptionContentType
+                | Property::Start
+                | Property::Duration
+                | Property::TimeZone
+                | Property::Status
+                | Property::FreeBusyStatus
+                | Property::Privacy
+                | Property::Color
+                | Property::Locale
+                | Property::ProdId
+                | Property::RecurrenceId
+                | Property::RecurrenceIdTimeZone
+                | Property::SentBy
+                | Property::DavName
+        )
+    }
+
+    pub fn is_bool(&self) -> bool {
+        matches!(
+            self,
+            Property::ShowWithoutTime
+                | Property::Excluded
+                | Property::UseDefaultAlerts
+                | Property::IsDraft
+                | Property::HideAttendees
+                | Property::MayInviteSelf
+                | Property::MayInviteOthers
+        )
+    }
+
+    // Properties that every user the event is shared with keeps a
+    // separate copy of.
+    pub fn is_per_user(&self) -> bool {
+        matches!(
+            self,
+            Property::Keywords
+                | Property::Color
+                | Property::FreeBusyStatus
+                | Property::UseDefaultAlerts
+                | Property::Alerts
+        )
+    }
+
+    pub fn is_internal(&self) -> bool {
+        (Property::IndexText as u8..=Property::PerUser as u8).contains(&(*self as u8))
+    }
+}
+
+impl Display for Property {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Property::Id => write!(f, "id"),
+            Property::CalendarIds => write!(f, "calendarIds"),
+            Property::Type => write!(f, "@type"),
+            Property::Uid => write!(f, "uid"),
+            Property::Created => write!(f, "created"),
+            Property::Updated => write!(f, "updated"),
+            Property::Sequence => write!(f, "sequence"),
+            Property::Method => write!(f, "method"),
+            Property::Title => write!(f, "title"),
+            Property::Description => write!(f, "description"),
+            Property::DescriptionContentType => write!(f, "descriptionContentType"),
+            Property::ShowWithoutTime => write!(f, "showWithoutTime"),
+            Property::Start => write!(f, "start"),
+            Property::Duration => write!(f, "duration"),
+            Property::TimeZone => write!(f, "timeZone"),
+            Property::Status => write!(f, "status"),
+            Property::FreeBusyStatus => write!(f, "freeBusyStatus"),
+            Property::Privacy => write!(f, "privacy"),
+            Property::Priority => write!(f, "priority"),
+            Property::Color => write!(f, "color"),
+            Property::Locale => write!(f, "locale"),
+            Property::ProdId => write!(f, "prodId"),
+            Property::RecurrenceId => write!(f, "recurrenceId"),
+            Property::RecurrenceIdTimeZone => write!(f, "recurrenceIdTimeZone"),
+            Property::Excluded => write!(f, "excluded"),
+            Property::UseDefaultAlerts => write!(f, "useDefaultAlerts"),
+            Property::IsDraft => write!(f, "isDraft"),
+            Property::SentBy => write!(f, "sentBy"),
+            Property::HideAttendees => write!(f, "hideAttendees"),
+            Property::MayInviteSelf => write!(f, "mayInviteSelf"),
+            Property::MayInviteOthers => write!(f, "mayInviteOthers"),
+            Property::RelatedTo => write!(f, "relatedTo"),
+            Property::Keywords => write!(f, "keywords"),
+            Property::Categories => write!(f, "categories"),
+            Property::Locations => write!(f, "locations"),
+            Property::VirtualLocations => write!(f, "virtualLocations"),
+            Property::Links => write!(f, "links"),
+            Property::ReplyTo => write!(f, "replyTo"),
+            Property::Participants => write!(f, "participants"),
+            Property::RecurrenceRules => write!(f, "recurrenceRules"),
+            Property::ExcludedRecurrenceRules => write!(f, "excludedRecurrenceRules"),
+            Property::RecurrenceOverrides => write!(f, "recurrenceOverrides"),
+            Property::Alerts => write!(f, "alerts"),
+            Property::Localizations => write!(f, "localizations"),
+            Property::TimeZones => write!(f, "timeZones"),
+            Property::UtcStart => write!(f, "utcStart"),
+            Property::UtcEnd => write!(f, "utcEnd"),
+            Property::IsOrigin => write!(f, "isOrigin"),
+            Property::BaseEventId => write!(f, "baseEventId"),
+            Property::DavName => write!(f, "davName"),
+            Property::IndexText
+            | Property::IndexLocation
+            | Property::IndexOwner
+            | Property::IndexAttendee
+            | Property::IndexFirst
+            | Property::IndexLast
+            | Property::PerUser
+            | Property::Invalid => Ok(()),
+        }
+    }
+}
+
+impl From<Property> for FieldId {
+    fn from(property: Property) -> Self {
+        property as FieldId
+    }
+}
+
+impl From<FieldId> for Property {
+    fn from(field: FieldId) -> Self {
+        match field {
+            0 => Property::Id,
+            1 => Property::CalendarIds,
+            2 => Property::Type,
+            3 => Property::Uid,
+            4 => Property::Created,
+            5 => Property::Updated,
+            6 => Property::Sequence,
+            7 => Property::Method,
+            8 => Property::Title,
+            9 => Property::Description,
+            10 => Property::DescriptionContentType,
+            11 => Property::ShowWithoutTime,
+            12 => Property::Start,
+            13 => Property::Duration,
+            14 => Property::TimeZone,
+            15 => Property::Status,
+            16 => Property::FreeBusyStatus,
+            17 => Property::Privacy,
+            18 => Property::Priority,
+            19 => Property::Color,
+            20 => Property::Locale,
+            21 => Property::ProdId,
+            22 => Property::RecurrenceId,
+            23 => Property::RecurrenceIdTimeZone,
+            24 => Property::Excluded,
+            25 => Property::UseDefaultAlerts,
+            26 => Property::IsDraft,
+            27 => Property::SentBy,
+            28 => Property::HideAttendees,
+            29 => Property::MayInviteSelf,
+            30 => Property::MayInviteOthers,
+            31 => Property::RelatedTo,
+            32 => Property::Keywords,
+            33 => Property::Categories,
+            34 => Property::Locations,
+            35 => Property::VirtualLocations,
+            36 => Property::Links,
+            37 => Property::ReplyTo,
+            38 => Property::Participants,
+            39 => Property::RecurrenceRules,
+            40 => Property::ExcludedRecurrenceRules,
+            41 => Property::RecurrenceOverrides,
+            42 => Property::Alerts,
+            43 => Property::Localizations,
+            44 => Property::TimeZones,
+            45 => Property::UtcStart,
+            46 => Property::UtcEnd,
+            47 => Property::IsOrigin,
+            48 => Property::BaseEventId,
+            49 => Property::IndexText,
+            50 => Property::IndexLocation,
+            51 => Property::IndexOwner,
+            52 => Property::IndexAttendee,
+            53 => Property::IndexFirst,
+            54 => Property::IndexLast,
+            55 => Property::PerUser,
+            56 => Property::DavName,
+            _ => Property::Invalid,
+        }
+    }
+}
+
+impl TryFrom<&str> for Property {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match Property::parse(value) {
+            Property::Invalid => Err(()),
+            property => Ok(property),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Filter {
+    InCalendars { value: Vec<JMAPId> },
+    After { value: JMAPDate },
+    Before { value: JMAPDate },
+    Text { value: String },
+    Title { value: String },
+    Description { value: String },
+    Location { value: String },
+    Owner { value: String },
+    Attendee { value: String },
+    Uid { value: String },
+    DavName { value: String },
+    Unsupported { value: String },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "property")]
+pub enum Comparator {
+    #[serde(rename = "start")]
+    Start,
+    #[serde(rename = "created")]
+    Created,
+    #[serde(rename = "updated")]
+    Updated,
+}
@@ -0,0 +1,320 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, fmt};
+
+use jmap::{
+    request::{query::FilterDeserializer, ArgumentDeserializer, MaybeIdReference},
+    types::{date::JMAPDate, jmap::JMAPId, json_pointer::JSONPointer},
+};
+use serde::{
+    de::IgnoredAny,
+    ser::{Error, SerializeMap},
+    Deserialize, Serialize,
+};
+use store::core::vec_map::VecMap;
+
+use super::{
+    schema::{CalendarEvent, Filter, Property, Value},
+    set::SetArguments,
+};
+
+// Property de/serialization
+impl Serialize for Property {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+struct PropertyVisitor;
+
+impl<'de> serde::de::Visitor<'de> for PropertyVisitor {
+    type Value = Property;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a valid JMAP CalendarEvent property")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Property::parse(v))
+    }
+}
+
+impl<'de> Deserialize<'de> for Property {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(PropertyVisitor)
+    }
+}
+
+// CalendarEvent de/serialization
+impl Serialize for CalendarEvent {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(self.properties.len().into())?;
+
+        for (name, value) in &self.properties {
+            match value {
+                Value::Id { value } => map.serialize_entry(name, value)?,
+                Value::Text { value } => map.serialize_entry(name, value)?,
+                Value::Bool { value } => map.serialize_entry(name, value)?,
+                Value::Number { value } => map.serialize_entry(name, value)?,
+                Value::Date { value } => map.serialize_entry(name, value)?,
+                Value::Json { value } => map.serialize_entry(
+                    name,
+                    &serde_json::from_str::<serde_json::Value>(value).map_err(S::Error::custom)?,
+                )?,
+                Value::CalendarIds { value, .. } => map.serialize_entry(name, value)?,
+                Value::Null => map.serialize_entry(name, &())?,
+                Value::Patch { .. } | Value::ResultReference { .. } => (),
+            }
+        }
+
+        map.end()
+    }
+}
+
+struct CalendarEventVisitor;
+
+impl<'de> serde::de::Visitor<'de> for CalendarEventVisitor {
+    type Value = CalendarEvent;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a valid JMAP CalendarEvent object")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let mut properties: VecMap<Property, Value> = VecMap::new();
+
+        while let Some(key) = map.next_key::<Cow<str>>()? {
+            match Property::parse(key.as_ref()) {
+                Property::CalendarIds => {
+                    if let Some(value) =
+                        map.next_value::<Option<VecMap<MaybeIdReference, bool>>>()?
+                    {
+                        properties.append(
+                            Property::CalendarIds,
+                            Value::CalendarIds { value, set: true },
+                        );
+                    }
+                }
+                property if property.is_text() => {
+                    properties.append(
+                        property,
+                        if let Some(value) = map.next_value::<Option<String>>()? {
+                            Value::Text { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                property if property.is_bool() => {
+                    properties.append(
+                        property,
+                        if let Some(value) = map.next_value::<Option<bool>>()? {
+                            Value::Bool { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                property @ (Property::Sequence | Property::Priority) => {
+                    properties.append(
+                        property,
+                        if let Some(value) = map.next_value::<Option<u32>>()? {
+                            Value::Number { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                property @ (Property::Created | Property::Updated) => {
+                    properties.append(
+                        property,
+                        if let Some(value) = map.next_value::<Option<JMAPDate>>()? {
+                            Value::Date { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                property if property.is_json() => {
+                    properties.append(
+                        property,
+                        if let Some(value) = map.next_value::<Option<serde_json::Value>>()? {
+                            Value::Json {
+                                value: value.to_string(),
+                            }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                _ if key.starts_with('#') => {
+                    if let Some(property) = key.get(1..) {
+                        properties.append(
+                            Property::parse(property),
+                            Value::ResultReference {
+                                value: map.next_value()?,
+                            },
+                        );
+                    }
+                }
+                _ => match JSONPointer::parse(key.as_ref()) {
+                    Some(JSONPointer::Path(path)) if path.len() >= 2 => {
+                        let mut path = path
+                            .into_iter()
+                            .map(|item| match item {
+                                JSONPointer::String(value) => value,
+                                JSONPointer::Number(value) => value.to_string(),
+                                _ => "*".to_string(),
+                            })
+                            .collect::<Vec<_>>();
+                        match Property::parse(&path.remove(0)) {
+                            Property::CalendarIds if path.len() == 1 => {
+                                let value = map.next_value::<Option<bool>>()?.unwrap_or(false);
+                                if let Some(id) = JMAPId::parse(&path[0]) {
+                                    properties
+                                        .get_mut_or_insert_with(Property::CalendarIds, || {
+                                            Value::CalendarIds {
+                                                value: VecMap::new(),
+                                                set: false,
+                                            }
+                                        })
+                                        .get_calendar_ids()
+                                        .unwrap()
+                                        .append(MaybeIdReference::Value(id), value);
+                                }
+                            }
+                            property if property.is_json() => {
+                                let value = map.next_value::<serde_json::Value>()?.to_string();
+                                if let Value::Patch { value: patches } = properties
+                                    .get_mut_or_insert_with(property, || Value::Patch {
+                                        value: Vec::new(),
+                                    })
+                                {
+                                    patches.push((path, value));
+                                }
+                            }
+                            _ => {
+                                map.next_value::<IgnoredAny>()?;
+                            }
+                        }
+                    }
+                    _ => {
+                        map.next_value::<IgnoredAny>()?;
+                    }
+                },
+            }
+        }
+
+        Ok(CalendarEvent { properties })
+    }
+}
+
+impl<'de> Deserialize<'de> for CalendarEvent {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(CalendarEventVisitor)
+    }
+}
+
+// Filter deserializer
+impl FilterDeserializer for Filter {
+    fn deserialize<'x>(property: &str, map: &mut impl serde::de::MapAccess<'x>) -> Option<Self> {
+        match property {
+            "inCalendars" => Filter::InCalendars {
+                value: map.next_value().ok()?,
+            },
+            "after" => Filter::After {
+                value: map.next_value().ok()?,
+            },
+            "before" => Filter::Before {
+                value: map.next_value().ok()?,
+            },
+            "text" => Filter::Text {
+                value: map.next_value().ok()?,
+            },
+            "title" => Filter::Title {
+                value: map.next_value().ok()?,
+            },
+            "description" => Filter::Description {
+                value: map.next_value().ok()?,
+            },
+            "location" => Filter::Location {
+                value: map.next_value().ok()?,
+            },
+            "owner" => Filter::Owner {
+                value: map.next_value().ok()?,
+            },
+            "attendee" => Filter::Attendee {
+                value: map.next_value().ok()?,
+            },
+            "uid" => Filter::Uid {
+                value: map.next_value().ok()?,
+            },
+            "davName" => Filter::DavName {
+                value: map.next_value().ok()?,
+            },
+            unsupported => {
+                map.next_value::<IgnoredAny>().ok()?;
+                Filter::Unsupported {
+                    value: unsupported.to_string(),
+                }
+            }
+        }
+        .into()
+    }
+}
+
+// Argument serializer
+impl ArgumentDeserializer for SetArguments {
+    fn deserialize<'x: 'y, 'y, 'z>(
+        &'y mut self,
+        property: &'z str,
+        value: &mut impl serde::de::MapAccess<'x>,
+    ) -> Result<(), String> {
+        if property == "sendSchedulingMessages" {
+            self.send_scheduling_messages = value.next_value().map_err(|err| err.to_string())?;
+        } else {
+            value
+                .next_value::<IgnoredAny>()
+                .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,833 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use super::ical::build_reply;
+use super::index::{build_index, participant_email};
+use super::itip::SchedulingMessage;
+use super::recurrence::{parse_duration, parse_local};
+use super::schema::{CalendarEvent, Property, Value};
+use super::sharing::JMAPShareCalendars;
+use chrono_tz::Tz;
+use jmap::error::set::{SetError, SetErrorType};
+use jmap::jmap_store::set::{SetHelper, SetObject};
+use jmap::jmap_store::Object;
+use jmap::orm::{serialize::JMAPOrm, TinyORM};
+use jmap::request::set::{SetRequest, SetResponse};
+use jmap::request::{ACLEnforce, MaybeIdReference, ResultReference};
+use jmap::types::date::JMAPDate;
+use jmap::types::jmap::JMAPId;
+use jmap_sharing::principal::account::JMAPAccountStore;
+use serde_json::{Map, Value as JsonValue};
+use store::core::acl::ACL;
+use store::core::collection::Collection;
+use store::core::document::Document;
+use store::core::error::StoreError;
+use store::core::tag::Tag;
+use store::core::vec_map::VecMap;
+use store::rand;
+use store::roaring::RoaringBitmap;
+use store::{AccountId, JMAPStore, SharedBitmap, Store};
+
+#[derive(Debug, Clone, Default)]
+pub struct SetArguments {
+    pub send_scheduling_messages: Option<bool>,
+}
+
+impl SetObject for CalendarEvent {
+    type SetArguments = SetArguments;
+
+    type NextCall = Vec<SchedulingMessage>;
+
+    fn eval_id_references(&mut self, mut fnc: impl FnMut(&str) -> Option<JMAPId>) {
+        if let Some(Value::CalendarIds { value, .. }) =
+            self.properties.get_mut(&Property::CalendarIds)
+        {
+            if value
+                .keys()
+                .any(|k| matches!(k, MaybeIdReference::Reference(_)))
+            {
+                let mut new_values = VecMap::with_capacity(value.len());
+
+                for (id, value) in std::mem::take(value).into_iter() {
+                    if let MaybeIdReference::Reference(id) = &id {
+                        if let Some(id) = fnc(id) {
+                            new_values.append(MaybeIdReference::Value(id), value);
+                            continue;
+                        }
+                    }
+                    new_values.append(id, value);
+                }
+
+                *value = new_values;
+            }
+        }
+    }
+
+    fn eval_result_references(
+        &mut self,
+        mut fnc: impl FnMut(&ResultReference) -> Option<Vec<u64>>,
+    ) {
+        for (property, entry) in self.properties.iter_mut() {
+            if let (Property::CalendarIds, Value::ResultReference { value }) = (property, &entry) {
+                if let Some(value) = fnc(value) {
+                    *entry = Value::CalendarIds {
+                        value: value
+                            .into_iter()
+                            .map(|v| (MaybeIdReference::Value(v.into()), true))
+                            .collect(),
+                        set: true,
+                    };
+                }
+            }
+        }
+    }
+}
+
+pub trait JMAPSetCalendarEvent<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn calendar_event_set(
+        &self,
+        request: SetRequest<CalendarEvent>,
+    ) -> jmap::Result<SetResponse<CalendarEvent>>;
+    fn calendar_event_delete(
+        &self,
+        account_id: AccountId,
+        document: &mut Document,
+    ) -> store::Result<()>;
+    fn calendar_event_check_acl(
+        &self,
+        account_id: AccountId,
+        member_of: &[AccountId],
+        calendar_ids: impl Iterator<Item = Tag>,
+        acl: ACL,
+    ) -> jmap::error::set::Result<(), Property>;
+}
+
+impl<T> JMAPSetCalendarEvent<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn calendar_event_set(
+        &self,
+        request: SetRequest<CalendarEvent>,
+    ) -> jmap::Result<SetResponse<CalendarEvent>> {
+        let mut helper = SetHelper::new(self, request)?;
+        let calendar_ids = self
+            .get_document_ids(helper.account_id, Collection::Calendar)?
+            .unwrap_or_default();
+        let send_scheduling_messages = helper
+            .request
+            .arguments
+            .send_scheduling_messages
+            .unwrap_or(false);
+        let mut scheduling_messages = Vec::new();
+
+        // Replies are sent on behalf of the user making the request
+        let user_id = helper.acl.primary_id();
+        let user_details = if send_scheduling_messages || helper.acl.is_shared(helper.account_id) {
+            self.get_account_details(user_id)?
+                .map(|(email, name, _)| (email.to_lowercase(), name))
+        } else {
+            None
+        };
+
+        helper.create(|_create_id, item, helper, document| {
+            let fields = TinyORM::<CalendarEvent>::new().calendar_event_set(
+                item,
+                &calendar_ids,
+                None,
+                |id| helper.unwrap_id_reference(Property::CalendarIds, id),
+            )?;
+
+            // Check ACLs
+            if helper.acl.is_shared(helper.account_id) {
+                self.calendar_event_check_acl(
+                    helper.account_id,
+                    &helper.acl.member_of,
+                    fields
+                        .get_tags(&Property::CalendarIds)
+                        .unwrap()
+                        .iter()
+                        .cloned(),
+                    ACL::AddItems,
+                )?;
+            }
+
+            let uid = fields
+                .get(&Property::Uid)
+                .and_then(|uid| uid.as_text())
+                .unwrap()
+                .to_string();
+            fields.insert_validate(document)?;
+
+            let mut event = CalendarEvent::new(document.document_id.into());
+            event
+                .properties
+                .append(Property::Uid, Value::Text { value: uid });
+            Ok(event)
+        })?;
+
+        helper.update(|id, mut item, helper, document| {
+            if id.get_prefix_id() != 0 {
+                return Err(SetError::new(
+                    SetErrorType::InvalidProperties,
+                    "Occurrences cannot be modified directly, update the recurrenceOverrides of the base event instead.",
+                ));
+            }
+            let document_id = id.get_document_id();
+            let current_fields = self
+                .get_orm::<CalendarEvent>(helper.account_id, document_id)?
+                .ok_or_else(|| SetError::new_err(SetErrorType::NotFound))?;
+            let user_email = user_details.as_ref().map(|(email, _)| email.as_str());
+
+            // Sharees keep their own copy of the per-user properties
+            let mut per_user = None;
+            let is_shared = helper.acl.is_shared(helper.account_id);
+            if is_shared {
+                let mut per_user_properties = Vec::new();
+                for (property, value) in std::mem::take(&mut item.properties) {
+                    if property.is_per_user() {
+                        per_user_properties.push((property, value));
+                    } else {
+                        item.properties.append(property, value);
+                    }
+                }
+                if !per_user_properties.is_empty() {
+                    per_user = set_per_user(
+                        &current_fields,
+                        JMAPId::from(user_id).to_string(),
+                        per_user_properties,
+                    )?
+                    .into();
+                }
+
+                // Changing the participation status of the user's own
+                // participant only requires the RSVP right.
+                let acl = if item.properties.is_empty() {
+                    ACL::ReadItems
+                } else if user_email.map_or(false, |email| {
+                    is_participation_change(&item, &current_fields, email)
+                }) {
+                    ACL::Submit
+                } else {
+                    ACL::ModifyItems
+                };
+                if !self
+                    .calendars_shared_events(helper.account_id, &helper.acl.member_of, acl)?
+                    .has_access(document_id)
+                {
+                    return Err(SetError::forbidden(
+                        "You are not allowed to modify this event.",
+                    ));
+                }
+            }
+
+            let mut fields = TinyORM::track_changes(&current_fields).calendar_event_set(
+                item,
+                &calendar_ids,
+                Some(&current_fields),
+                |id| helper.unwrap_id_reference(Property::CalendarIds, id),
+            )?;
+            if let Some(per_user) = per_user {
+                fields.set(Property::PerUser, per_user);
+            }
+
+            // Check ACLs
+            if is_shared {
+                self.calendar_event_check_acl(
+                    helper.account_id,
+                    &helper.acl.member_of,
+                    current_fields
+                        .get_added_tags(&fields, &Property::CalendarIds)
+                        .into_iter(),
+                    ACL::AddItems,
+                )?;
+                self.calendar_event_check_acl(
+                    helper.account_id,
+                    &helper.acl.member_of,
+                    current_fields
+                        .get_removed_tags(&fields, &Property::CalendarIds)
+                        .into_iter(),
+                    ACL::RemoveItems,
+                )?;
+            }
+
+            // Notify the organizer when the user's participation status changes
+            if send_scheduling_messages {
+                if let Some((email, name)) = &user_details {
+                    if let Some(message) = build_scheduling_reply(
+                        &current_fields,
+                        &fields,
+                        email,
+                        name.as_str().into(),
+                    ) {
+                        scheduling_messages.push(message);
+                    }
+                }
+            }
+
+            // Merge changes
+            current_fields.merge_validate(document, fields)?;
+
+            Ok(None)
+        })?;
+
+        helper.destroy(|id, helper, document| {
+            if id.get_prefix_id() != 0 {
+                return Err(SetError::new(
+                    SetErrorType::InvalidProperties,
+                    "Occurrences cannot be destroyed directly, exclude them using the recurrenceOverrides of the base event instead.",
+                ));
+            }
+            let document_id = id.get_document_id();
+
+            if let Some(orm) = self.get_orm::<CalendarEvent>(helper.account_id, document_id)? {
+                // Check ACLs
+                if helper.acl.is_shared(helper.account_id) {
+                    self.calendar_event_check_acl(
+                        helper.account_id,
+                        &helper.acl.member_of,
+                        orm.get_tags(&Property::CalendarIds)
+                            .into_iter()
+                            .flatten()
+                            .cloned(),
+                        ACL::RemoveItems,
+                    )?;
+                }
+                orm.delete(document);
+            }
+            Ok(())
+        })?;
+
+        helper.into_response().map(|mut r| {
+            if !scheduling_messages.is_empty() {
+                r.next_call = scheduling_messages.into();
+            }
+            r
+        })
+    }
+
+    fn calendar_event_delete(
+        &self,
+        account_id: AccountId,
+        document: &mut Document,
+    ) -> store::Result<()> {
+        // Delete ORM
+        self.get_orm::<CalendarEvent>(account_id, document.document_id)?
+            .ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Failed to fetch CalendarEvent ORM for {}:{}.",
+                    account_id, document.document_id
+                ))
+            })?
+            .delete(document);
+
+        Ok(())
+    }
+
+    fn calendar_event_check_acl(
+        &self,
+        account_id: AccountId,
+        member_of: &[AccountId],
+        calendar_ids: impl Iterator<Item = Tag>,
+        acl: ACL,
+    ) -> jmap::error::set::Result<(), Property> {
+        let allowed_calendars = self.calendars_shared_calendars(account_id, member_of, acl)?;
+        for calendar_id in calendar_ids {
+            let calendar_id = calendar_id.as_id();
+            if !allowed_calendars.has_access(calendar_id) {
+                return Err(SetError::forbidden(format!(
+                    "You are not allowed to {} events in calendar {}.",
+                    if acl == ACL::RemoveItems {
+                        "remove"
+                    } else {
+                        "add"
+                    },
+                    JMAPId::from(calendar_id)
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub trait CalendarEventSet: Sized {
+    fn calendar_event_set(
+        self,
+        event: CalendarEvent,
+        calendar_ids: &RoaringBitmap,
+        current_fields: Option<&TinyORM<CalendarEvent>>,
+        resolve_id: impl Fn(&MaybeIdReference) -> jmap::error::set::Result<JMAPId, Property>,
+    ) -> jmap::error::set::Result<Self, Property>;
+}
+
+impl CalendarEventSet for TinyORM<CalendarEvent> {
+    fn calendar_event_set(
+        mut self,
+        event: CalendarEvent,
+        calendar_ids: &RoaringBitmap,
+        current_fields: Option<&TinyORM<CalendarEvent>>,
+        resolve_id: impl Fn(&MaybeIdReference) -> jmap::error::set::Result<JMAPId, Property>,
+    ) -> jmap::error::set::Result<Self, Property> {
+        for (property, value) in event.properties {
+            let value = match (property, value) {
+                (Property::CalendarIds, Value::CalendarIds { value, set }) => {
+                    if set {
+                        self.untag_all(&Property::CalendarIds);
+                    }
+                    for (calendar_id, is_set) in value {
+                        let calendar_id = resolve_id(&calendar_id)?;
+                        if !calendar_ids.contains(calendar_id.get_document_id()) {
+                            return Err(SetError::invalid_property(
+                                Property::CalendarIds,
+                                format!("calendarId {} does not exist.", calendar_id),
+                            ));
+                        }
+                        let tag = Tag::Id(calendar_id.get_document_id());
+                        if is_set {
+                            self.tag(Property::CalendarIds, tag);
+                        } else if !set {
+                            self.untag(&Property::CalendarIds, &tag);
+                        }
+                    }
+                    continue;
+                }
+                (Property::Type, Value::Text { value }) => {
+                    if value == "Event" {
+                        continue;
+                    } else {
+                        return Err(SetError::invalid_property(
+                            property,
+                            "Unsupported JSCalendar type.",
+                        ));
+                    }
+                }
+                (Property::Uid, Value::Text { value }) => {
+                    if current_fields.is_none() {
+                        Value::Text { value }
+                    } else {
+                        return Err(SetError::invalid_property(
+                            property,
+                            "The uid of an event cannot be changed.",
+                        ));
+                    }
+                }
+                (Property::Start | Property::RecurrenceId, Value::Text { value }) => {
+                    if parse_local(&value).is_some() {
+                        Value::Text { value }
+                    } else {
+                        return Err(SetError::invalid_property(
+                            property,
+                            "Expected a LocalDateTime value.",
+                        ));
+                    }
+                }
+                (Property::Duration, Value::Text { value }) => {
+                    if parse_duration(&value).map_or(false, |d| d.num_seconds() >= 0) {
+                        Value::Text { value }
+                    } else {
+                        return Err(SetError::invalid_property(
+                            property,
+                            "Expected a positive duration.",
+                        ));
+                    }
+                }
+                (Property::TimeZone | Property::RecurrenceIdTimeZone, Value::Text { value }) => {
+                    if value.parse::<Tz>().is_ok() {
+                        Value::Text { value }
+                    } else {
+                        return Err(SetError::invalid_property(
+                            property,
+                            format!("Unknown time zone {:?}.", value),
+                        ));
+                    }
+                }
+                (Property::Priority, Value::Number { value }) => {
+                    if value <= 9 {
+                        Value::Number { value }
+                    } else {
+                        return Err(SetError::invalid_property(
+                            property,
+                            "Priority has to be between 0 and 9.",
+                        ));
+                    }
+                }
+                (property, value @ (Value::Text { .. } | Value::Null)) if property.is_text() => {
+                    value
+                }
+                (property, value @ (Value::Bool { .. } | Value::Null)) if property.is_bool() => {
+                    value
+                }
+                (Property::Sequence, value @ Value::Number { .. }) => value,
+                (Property::Created | Property::Updated, value @ Value::Date { .. }) => value,
+                (property, value @ (Value::Json { .. } | Value::Null)) if property.is_json() => {
+                    value
+                }
+                (property, Value::Patch { value: patches }) if property.is_json() => {
+                    let mut json = match self
+                        .get(&property)
+                        .or_else(|| current_fields.and_then(|f| f.get(&property)))
+                    {
+                        Some(Value::Json { value }) => serde_json::from_str::<JsonValue>(value)
+                            .unwrap_or_else(|_| JsonValue::Object(Default::default())),
+                        _ => JsonValue::Object(Default::default()),
+                    };
+                    for (path, value) in patches {
+                        if !apply_patch(
+                            &mut json,
+                            &path,
+                            serde_json::from_str(&value).unwrap_or(JsonValue::Null),
+                        ) {
+                            return Err(SetError::new(
+                                SetErrorType::InvalidPatch,
+                                format!("Failed to patch {}/{}.", property, path.join("/")),
+                            ));
+                        }
+                    }
+                    Value::Json {
+                        value: json.to_string(),
+                    }
+                }
+                (_, _) => {
+                    return Err(SetError::invalid_property(
+                        property,
+                        "Field could not be set.",
+                    ));
+                }
+            };
+
+            self.set(property, value);
+        }
+
+        // Events have to belong to at least one calendar
+        if !self.has_tags(&Property::CalendarIds) {
+            return Err(SetError::invalid_property(
+                Property::CalendarIds,
+                "Event has to belong to at least one calendar.",
+            ));
+        }
+
+        // Set server defaults
+        let now = JMAPDate::from_timestamp(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0) as i64,
+        );
+        if current_fields.is_none() {
+            if !self.has_property(&Property::Uid) {
+                self.set(Property::Uid, Value::Text { value: new_uid() });
+            }
+            if !self.has_property(&Property::Created) {
+                self.set(Property::Created, Value::Date { value: now.clone() });
+            }
+            if !self.has_property(&Property::Sequence) {
+                self.set(Property::Sequence, Value::Number { value: 0 });
+            }
+        }
+        if !self.has_property(&Property::Updated) {
+            self.set(Property::Updated, Value::Date { value: now });
+        }
+
+        build_index(&mut self, current_fields);
+
+        Ok(self)
+    }
+}
+
+// Applies the per-user changes of a sharee to their copy of the per-user
+// properties, which is stored as a JSON object keyed by account id.
+fn set_per_user(
+    current_fields: &TinyORM<CalendarEvent>,
+    user_id: String,
+    properties: Vec<(Property, Value)>,
+) -> jmap::error::set::Result<Value, Property> {
+    let mut per_user = match current_fields.get(&Property::PerUser) {
+        Some(Value::Json { value }) => {
+            serde_json::from_str::<Map<String, JsonValue>>(value).unwrap_or_default()
+        }
+        _ => Map::new(),
+    };
+    let user = per_user
+        .entry(user_id)
+        .or_insert_with(|| JsonValue::Object(Map::new()));
+    let user = if let JsonValue::Object(user) = user {
+        user
+    } else {
+        return Err(SetError::forbidden("Invalid per-user data."));
+    };
+
+    for (property, value) in properties {
+        let name = property.to_string();
+        match value {
+            Value::Patch { value: patches } => {
+                let json = user
+                    .entry(name)
+                    .or_insert_with(|| JsonValue::Object(Map::new()));
+                for (path, value) in patches {
+                    if !apply_patch(
+                        json,
+                        &path,
+                        serde_json::from_str(&value).unwrap_or(JsonValue::Null),
+                    ) {
+                        return Err(SetError::new(
+                            SetErrorType::InvalidPatch,
+                            format!("Failed to patch {}/{}.", property, path.join("/")),
+                        ));
+                    }
+                }
+            }
+            Value::Null => {
+                user.remove(&name);
+            }
+            value => {
+                if let Some(value) = value_to_json(value) {
+                    user.insert(name, value);
+                } else {
+                    return Err(SetError::invalid_property(
+                        property,
+                        "Field could not be set.",
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(Value::Json {
+        value: JsonValue::Object(per_user).to_string(),
+    })
+}
+
+// Returns true if the changes only update the participation status of the
+// user's own participant.
+fn is_participation_change(
+    event: &CalendarEvent,
+    current_fields: &TinyORM<CalendarEvent>,
+    email: &str,
+) -> bool {
+    let participants = if let Some(JsonValue::Object(participants)) = current_fields
+        .get(&Property::Participants)
+        .and_then(|v| v.as_json())
+    {
+        participants
+    } else {
+        return false;
+    };
+
+    event.properties.iter().all(|(property, value)| {
+        matches!((property, value), (Property::Participants, Value::Patch { value: patches })
+        if patches.iter().all(|(path, _)| {
+            path.len() == 2
+                && matches!(path[1].as_str(), "participationStatus" | "participationComment")
+                && participants
+                    .get(&path[0])
+                    .map_or(false, |p| participant_email(p).as_deref() == Some(email))
+        }))
+    })
+}
+
+fn build_scheduling_reply(
+    current_fields: &TinyORM<CalendarEvent>,
+    fields: &TinyORM<CalendarEvent>,
+    email: &str,
+    name: Option<&str>,
+) -> Option<SchedulingMessage> {
+    let get_json = |property: Property| {
+        fields
+            .get(&property)
+            .or_else(|| current_fields.get(&property))
+            .and_then(|value| value.as_json())
+    };
+    let participation_status = |participants: Option<JsonValue>| {
+        participants?
+            .as_object()?
+            .values()
+            .find(|participant| participant_email(participant).as_deref() == Some(email))?
+            .get("participationStatus")
+            .and_then(|v| v.as_str())
+            .map(String::from)
+    };
+
+    // Only replies to events organized by someone else are sent
+    let organizer = get_json(Property::ReplyTo)?
+        .get("imip")?
+        .as_str()?
+        .trim_start_matches("mailto:")
+        .to_lowercase();
+    let new_status = participation_status(get_json(Property::Participants))?;
+    if organizer == email
+        || participation_status(
+            current_fields
+                .get(&Property::Participants)
+                .and_then(|v| v.as_json()),
+        )
+        .as_deref()
+            == Some(new_status.as_str())
+    {
+        return None;
+    }
+
+    let mut event = Map::new();
+    for property in [
+        Property::Uid,
+        Property::Sequence,
+        Property::Title,
+        Property::Start,
+        Property::TimeZone,
+        Property::ShowWithoutTime,
+        Property::ReplyTo,
+        Property::Participants,
+    ] {
+        if let Some(value) = fields
+            .get(&property)
+            .or_else(|| current_fields.get(&property))
+            .cloned()
+            .and_then(value_to_json)
+        {
+            event.insert(property.to_string(), value);
+        }
+    }
+    let title = event
+        .get("title")
+        .and_then(|v| v.as_str())
+        .unwrap_or("Untitled event")
+        .to_string();
+
+    Some(SchedulingMessage::reply(
+        name.filter(|name| !name.is_empty()),
+        email,
+        &organizer,
+        &title,
+        &new_status,
+        build_reply(&event, email)?,
+    ))
+}
+
+// Converts a JSON value to the value type used by a property.
+pub fn json_to_value(property: Property, value: JsonValue) -> Option<Value> {
+    match value {
+        JsonValue::Null => Value::Null.into(),
+        value if property.is_json() => Value::Json {
+            value: value.to_string(),
+        }
+        .into(),
+        JsonValue::String(value) if property.is_text() => Value::Text { value }.into(),
+        JsonValue::String(value) if matches!(property, Property::Created | Property::Updated) => {
+            Value::Date {
+                value: JMAPDate::parse(&value)?,
+            }
+            .into()
+        }
+        JsonValue::Bool(value) if property.is_bool() => Value::Bool { value }.into(),
+        JsonValue::Number(value) if matches!(property, Property::Sequence | Property::Priority) => {
+            Value::Number {
+                value: value.as_u64()? as u32,
+            }
+            .into()
+        }
+        _ => None,
+    }
+}
+
+pub fn value_to_json(value: Value) -> Option<JsonValue> {
+    match value {
+        Value::Text { value } => JsonValue::String(value).into(),
+        Value::Bool { value } => JsonValue::Bool(value).into(),
+        Value::Number { value } => JsonValue::from(value).into(),
+        Value::Date { value } => serde_json::to_value(value).ok(),
+        Value::Json { value } => serde_json::from_str(&value).ok(),
+        Value::Null => JsonValue::Null.into(),
+        Value::Id { .. }
+        | Value::Patch { .. }
+        | Value::CalendarIds { .. }
+        | Value::ResultReference { .. } => None,
+    }
+}
+
+// Applies a JMAP patch to a structured JSCalendar property, all the path
+// components but the last one have to exist.
+pub fn apply_patch(json: &mut JsonValue, path: &[String], value: JsonValue) -> bool {
+    let (last, path) = if let Some(item) = path.split_last() {
+        item
+    } else {
+        return false;
+    };
+    let mut json = json;
+    for item in path {
+        json = match json {
+            JsonValue::Object(map) => {
+                if let Some(json) = map.get_mut(item) {
+                    json
+                } else {
+                    return false;
+                }
+            }
+            JsonValue::Array(list) => {
+                if let Some(json) = item.parse::<usize>().ok().and_then(|i| list.get_mut(i)) {
+                    json
+                } else {
+                    return false;
+                }
+            }
+            _ => return false,
+        };
+    }
+
+    match json {
+        JsonValue::Object(map) => {
+            if !value.is_null() {
+                map.insert(last.to_string(), value);
+            } else {
+                map.remove(last);
+            }
+            true
+        }
+        JsonValue::Array(list) => {
+            if let Some(item) = last.parse::<usize>().ok().and_then(|i| list.get_mut(i)) {
+                *item = value;
+                true
+            } else {
+                false
+            }
+        }
+        _ => false,
+    }
+}
+
+fn new_uid() -> String {
+    let mut uuid = rand::random::<u128>();
+    uuid = (uuid & !(0xf000 << 64)) | (0x4000 << 64); // Version 4
+    uuid = (uuid & !(0xc000 << 48)) | (0x8000 << 48); // RFC 4122 variant
+    format!(
+        "urn:uuid:{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        (uuid >> 96) as u32,
+        (uuid >> 80) as u16,
+        (uuid >> 64) as u16,
+        (uuid >> 48) as u16,
+        uuid & 0xffff_ffff_ffff
+    )
+}
@@ -0,0 +1,188 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::schema::{AddressBook, AddressBookRights, Property, Value};
+use crate::contact_card::sharing::JMAPShareContacts;
+use jmap::jmap_store::get::{default_mapper, GetHelper, GetObject};
+use jmap::orm::serialize::JMAPOrm;
+use jmap::principal::store::JMAPPrincipals;
+use jmap::request::get::{GetRequest, GetResponse};
+use jmap::request::ACLEnforce;
+use jmap::types::jmap::JMAPId;
+use store::core::acl::ACL;
+use store::core::collection::Collection;
+use store::core::error::StoreError;
+use store::core::vec_map::VecMap;
+use store::{AccountId, JMAPStore, SharedBitmap, Store};
+
+impl GetObject for AddressBook {
+    type GetArguments = ();
+
+    fn default_properties() -> Vec<Self::Property> {
+        vec![
+            Property::Id,
+            Property::Name,
+            Property::Description,
+            Property::SortOrder,
+            Property::IsDefault,
+            Property::IsSubscribed,
+            Property::MyRights,
+        ]
+    }
+
+    fn get_as_id(&self, property: &Self::Property) -> Option<Vec<JMAPId>> {
+        match self.properties.get(property)? {
+            Value::Id { value } => Some(vec![*value]),
+            _ => None,
+        }
+    }
+}
+
+pub trait JMAPGetAddressBook<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn address_book_get(
+        &self,
+        request: GetRequest<AddressBook>,
+    ) -> jmap::Result<GetResponse<AddressBook>>;
+}
+
+impl<T> JMAPGetAddressBook<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn address_book_get(
+        &self,
+        request: GetRequest<AddressBook>,
+    ) -> jmap::Result<GetResponse<AddressBook>> {
+        let mut helper = GetHelper::new(
+            self,
+            request,
+            default_mapper.into(),
+            (|account_id: AccountId, member_of: &[AccountId]| {
+                self.contacts_shared_books(account_id, member_of, ACL::ReadItems)
+            })
+            .into(),
+        )?;
+        let fetch_fields = helper.properties.iter().any(|p| {
+            matches!(
+                p,
+                Property::Name
+                    | Property::Description
+                    | Property::SortOrder
+                    | Property::IsSubscribed
+                    | Property::ACL
+            )
+        });
+        let account_id = helper.account_id;
+        let acl = helper.acl.clone();
+
+        // The default address book is the oldest one in the account
+        let default_id = self
+            .get_document_ids(account_id, Collection::AddressBook)?
+            .and_then(|document_ids| document_ids.min());
+
+        // Add Id Property
+        if !helper.properties.contains(&Property::Id) {
+            helper.properties.push(Property::Id);
+        }
+
+        helper.get(|id, properties| {
+            let document_id = id.get_document_id();
+            let mut fields = if fetch_fields {
+                Some(
+                    self.get_orm::<AddressBook>(account_id, document_id)?
+                        .ok_or_else(|| {
+                            StoreError::NotFound("AddressBook data not found".to_string())
+                        })?,
+                )
+            } else {
+                None
+            };
+            let mut address_book = VecMap::with_capacity(properties.len());
+
+            for property in properties {
+                let value = match property {
+                    Property::Id => Value::Id { value: id },
+                    Property::Name | Property::Description => fields
+                        .as_mut()
+                        .unwrap()
+                        .remove(property)
+                        .unwrap_or_default(),
+                    Property::SortOrder => fields
+                        .as_mut()
+                        .unwrap()
+                        .remove(property)
+                        .unwrap_or(Value::Number { value: 0 }),
+                    Property::IsDefault => Value::Bool {
+                        value: default_id == Some(document_id),
+                    },
+                    Property::IsSubscribed => fields
+                        .as_ref()
+                        .unwrap()
+                        .get(property)
+                        .map(|subscriptions| match subscriptions {
+                            Value::Subscriptions { value } if value.contains(&acl.primary_id()) => {
+                                Value::Bool { value: true }
+                            }
+                            _ => Value::Bool { value: false },
+                        })
+                        .unwrap_or(Value::Bool { value: false }),
+                    Property::MyRights => Value::AddressBookRights {
+                        value: if acl.is_shared(account_id) {
+                            AddressBookRights::shared(self.get_acl(
+                                &acl.member_of,
+                                account_id,
+                                Collection::AddressBook,
+                                document_id,
+                            )?)
+                        } else {
+                            AddressBookRights::owner()
+                        },
+                    },
+                    Property::ACL
+                        if acl.is_member(account_id)
+                            || self
+                                .contacts_shared_books(account_id, &acl.member_of, ACL::Administer)?
+                                .has_access(document_id) =>
+                    {
+                        let mut acl_get = VecMap::new();
+                        for (account_id, acls) in fields.as_ref().unwrap().get_acls() {
+                            if let Some(email) = self.principal_to_email(account_id)? {
+                                acl_get.append(email, acls);
+                            }
+                        }
+                        Value::ACLGet(acl_get)
+                    }
+                    _ => Value::Null,
+                };
+
+                address_book.append(*property, value);
+            }
+            Ok(Some(AddressBook {
+                properties: address_book,
+            }))
+        })
+    }
+}
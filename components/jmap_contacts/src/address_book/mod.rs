@@ -0,0 +1,78 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod changes;
+pub mod get;
+pub mod raft;
+pub mod schema;
+pub mod serialize;
+pub mod set;
+
+use jmap::jmap_store::Object;
+use jmap::types::jmap::JMAPId;
+use store::core::collection::Collection;
+use store::write::options::Options;
+
+use self::schema::{AddressBook, Property, Value};
+
+impl Object for AddressBook {
+    type Property = Property;
+
+    type Value = Value;
+
+    fn id(&self) -> Option<&JMAPId> {
+        self.properties.get(&Property::Id).and_then(|id| match id {
+            Value::Id { value } => Some(value),
+            _ => None,
+        })
+    }
+
+    fn required() -> &'static [Self::Property] {
+        &[Property::Name]
+    }
+
+    fn indexed() -> &'static [(Self::Property, u64)] {
+        &[
+            (
+                Property::Name,
+                <u64 as Options>::F_TOKENIZE | <u64 as Options>::F_INDEX,
+            ),
+            (Property::SortOrder, <u64 as Options>::F_INDEX),
+        ]
+    }
+
+    fn max_len() -> &'static [(Self::Property, usize)] {
+        &[(Property::Name, 255), (Property::Description, 1024)]
+    }
+
+    fn collection() -> Collection {
+        Collection::AddressBook
+    }
+
+    fn new(id: JMAPId) -> Self {
+        let mut item = AddressBook::default();
+        item.properties
+            .append(Property::Id, Value::Id { value: id });
+        item
+    }
+}
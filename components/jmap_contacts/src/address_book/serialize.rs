@@ -0,0 +1,237 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, fmt};
+
+use jmap::{orm::acl::ACLUpdate, request::ArgumentDeserializer, types::json_pointer::JSONPointer};
+use serde::{de::IgnoredAny, ser::SerializeMap, Deserialize, Serialize};
+use store::core::{acl::ACL, vec_map::VecMap};
+
+use super::{
+    schema::{AddressBook, Property, Value},
+    set::SetArguments,
+};
+
+// Property de/serialization
+impl Serialize for Property {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+struct PropertyVisitor;
+
+impl<'de> serde::de::Visitor<'de> for PropertyVisitor {
+    type Value = Property;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a valid JMAP AddressBook property")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Property::parse(v))
+    }
+}
+
+impl<'de> Deserialize<'de> for Property {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(PropertyVisitor)
+    }
+}
+
+// AddressBook de/serialization
+impl Serialize for AddressBook {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(self.properties.len().into())?;
+
+        for (name, value) in &self.properties {
+            match value {
+                Value::Id { value } => map.serialize_entry(name, value)?,
+                Value::Text { value } => map.serialize_entry(name, value)?,
+                Value::Bool { value } => map.serialize_entry(name, value)?,
+                Value::Number { value } => map.serialize_entry(name, value)?,
+                Value::AddressBookRights { value } => map.serialize_entry(name, value)?,
+                Value::Null => map.serialize_entry(name, &None::<&str>)?,
+                Value::ACLGet(value) => map.serialize_entry(name, value)?,
+                Value::Subscriptions { .. } | Value::ACLSet(_) => (),
+            }
+        }
+
+        map.end()
+    }
+}
+
+struct AddressBookVisitor;
+
+impl<'de> serde::de::Visitor<'de> for AddressBookVisitor {
+    type Value = AddressBook;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a valid JMAP AddressBook object")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let mut properties: VecMap<Property, Value> = VecMap::new();
+        let mut acls = Vec::new();
+
+        while let Some(key) = map.next_key::<Cow<str>>()? {
+            match key.as_ref() {
+                "name" => {
+                    properties.append(
+                        Property::Name,
+                        if let Some(value) = map.next_value::<Option<String>>()? {
+                            Value::Text { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                "description" => {
+                    properties.append(
+                        Property::Description,
+                        if let Some(value) = map.next_value::<Option<String>>()? {
+                            Value::Text { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                "sortOrder" => {
+                    properties.append(
+                        Property::SortOrder,
+                        if let Some(value) = map.next_value::<Option<u32>>()? {
+                            Value::Number { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                "isSubscribed" => {
+                    properties.append(
+                        Property::IsSubscribed,
+                        Value::Bool {
+                            value: map.next_value::<Option<bool>>()?.unwrap_or(false),
+                        },
+                    );
+                }
+                "acl" => {
+                    acls.push(ACLUpdate::Replace {
+                        acls: map
+                            .next_value::<Option<VecMap<String, Vec<ACL>>>>()?
+                            .unwrap_or_default(),
+                    });
+                }
+                key => match JSONPointer::parse(key) {
+                    Some(JSONPointer::Path(path))
+                        if path.len() >= 2
+                            && path
+                                .get(0)
+                                .and_then(|p| p.to_string())
+                                .map(Property::parse)
+                                .unwrap_or(Property::Invalid)
+                                == Property::ACL =>
+                    {
+                        if let Some(account_id) = path
+                            .get(1)
+                            .and_then(|p| p.to_string())
+                            .map(|p| p.to_string())
+                        {
+                            if path.len() > 2 {
+                                if let Some(acl) =
+                                    path.get(2).and_then(|p| p.to_string()).map(ACL::parse)
+                                {
+                                    if acl != ACL::None_ {
+                                        acls.push(ACLUpdate::Set {
+                                            account_id,
+                                            acl,
+                                            is_set: map
+                                                .next_value::<Option<bool>>()?
+                                                .unwrap_or(false),
+                                        });
+                                    }
+                                }
+                            } else {
+                                acls.push(ACLUpdate::Update {
+                                    account_id,
+                                    acls: map.next_value::<Option<Vec<ACL>>>()?.unwrap_or_default(),
+                                });
+                            }
+                        } else {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                    _ => {
+                        map.next_value::<IgnoredAny>()?;
+                    }
+                },
+            }
+        }
+
+        if !acls.is_empty() {
+            properties.append(Property::ACL, Value::ACLSet(acls));
+        }
+
+        Ok(AddressBook { properties })
+    }
+}
+
+impl<'de> Deserialize<'de> for AddressBook {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(AddressBookVisitor)
+    }
+}
+
+// Argument serializer
+impl ArgumentDeserializer for SetArguments {
+    fn deserialize<'x: 'y, 'y, 'z>(
+        &'y mut self,
+        property: &'z str,
+        value: &mut impl serde::de::MapAccess<'x>,
+    ) -> Result<(), String> {
+        if property == "onDestroyRemoveContents" {
+            self.on_destroy_remove_contents = value.next_value().map_err(|err| err.to_string())?;
+        } else {
+            value
+                .next_value::<IgnoredAny>()
+                .map_err(|err| err.to_string())?;
+        }
+        Ok(())
+    }
+}
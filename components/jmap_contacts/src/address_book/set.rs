@@ -0,0 +1,397 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use super::schema::{AddressBook, Property, Value};
+use crate::contact_card::schema::{self as contact_card, ContactCard};
+use crate::contact_card::sharing::JMAPShareContacts;
+use jmap::error::set::{SetError, SetErrorType};
+use jmap::jmap_store::set::{SetHelper, SetObject};
+use jmap::jmap_store::Object;
+use jmap::orm::acl::ACLUpdate;
+use jmap::orm::{serialize::JMAPOrm, TinyORM};
+use jmap::principal::store::JMAPPrincipals;
+use jmap::request::set::{SetRequest, SetResponse};
+use jmap::request::{ACLEnforce, ResultReference};
+use jmap::types::jmap::JMAPId;
+use store::core::acl::ACL;
+use store::core::collection::Collection;
+use store::core::document::Document;
+use store::core::error::StoreError;
+use store::core::tag::Tag;
+use store::tracing::debug;
+use store::{AccountId, JMAPStore, SharedResource};
+use store::{SharedBitmap, Store};
+
+#[derive(Debug, Clone, Default)]
+pub struct SetArguments {
+    pub on_destroy_remove_contents: Option<bool>,
+}
+
+impl SetObject for AddressBook {
+    type SetArguments = SetArguments;
+
+    type NextCall = ();
+
+    fn eval_id_references(&mut self, _fnc: impl FnMut(&str) -> Option<JMAPId>) {}
+    fn eval_result_references(&mut self, _fnc: impl FnMut(&ResultReference) -> Option<Vec<u64>>) {}
+}
+
+pub trait JMAPSetAddressBook<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn address_book_set(
+        &self,
+        request: SetRequest<AddressBook>,
+    ) -> jmap::Result<SetResponse<AddressBook>>;
+    fn address_book_delete(
+        &self,
+        account_id: AccountId,
+        document: &mut Document,
+    ) -> store::Result<()>;
+}
+
+impl<T> JMAPSetAddressBook<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn address_book_set(
+        &self,
+        request: SetRequest<AddressBook>,
+    ) -> jmap::Result<SetResponse<AddressBook>> {
+        let mut helper = SetHelper::new(self, request)?;
+        let on_destroy_remove_contents = helper
+            .request
+            .arguments
+            .on_destroy_remove_contents
+            .unwrap_or(false);
+
+        helper.create(|_create_id, address_book, helper, document| {
+            // Shared accounts may not create new address books
+            if helper.acl.is_shared(helper.account_id) {
+                return Err(SetError::forbidden(
+                    "You are not allowed to create address books in this account.",
+                ));
+            }
+
+            let address_book =
+                TinyORM::<AddressBook>::new().address_book_set(helper, address_book, None)?;
+            address_book.insert_validate(document)?;
+
+            Ok(AddressBook::new(document.document_id.into()))
+        })?;
+
+        helper.update(|id, address_book, helper, document| {
+            let document_id = id.get_document_id();
+            let current_fields = self
+                .get_orm::<AddressBook>(helper.account_id, document_id)?
+                .ok_or_else(|| SetError::new_err(SetErrorType::NotFound))?;
+
+            let fields = TinyORM::track_changes(&current_fields).address_book_set(
+                helper,
+                address_book,
+                Some(&current_fields),
+            )?;
+
+            // Check ACLs
+            if helper.acl.is_shared(helper.account_id) {
+                if !helper
+                    .store
+                    .contacts_shared_books(helper.account_id, &helper.acl.member_of, ACL::Modify)?
+                    .has_access(document_id)
+                {
+                    return Err(SetError::forbidden(
+                        "You are not allowed to modify this address book.",
+                    ));
+                }
+
+                if fields.has_property(&Property::ACL)
+                    && !helper
+                        .store
+                        .contacts_shared_books(
+                            helper.account_id,
+                            &helper.acl.member_of,
+                            ACL::Administer,
+                        )?
+                        .has_access(document_id)
+                {
+                    return Err(SetError::forbidden(
+                        "You are not allowed to change the permissions of this address book.",
+                    ));
+                }
+            }
+
+            // Merge changes
+            current_fields.merge_validate(document, fields)?;
+
+            Ok(None)
+        })?;
+
+        helper.destroy(|id, helper, document| {
+            let document_id = id.get_document_id();
+
+            // Check ACLs
+            if helper.acl.is_shared(helper.account_id) {
+                if !helper
+                    .store
+                    .contacts_shared_books(helper.account_id, &helper.acl.member_of, ACL::Delete)?
+                    .has_access(document_id)
+                {
+                    return Err(SetError::forbidden(
+                        "You are not allowed to delete this address book.",
+                    ));
+                }
+                if on_destroy_remove_contents
+                    && !helper
+                        .store
+                        .contacts_shared_books(
+                            helper.account_id,
+                            &helper.acl.member_of,
+                            ACL::RemoveItems,
+                        )?
+                        .has_access(document_id)
+                {
+                    return Err(SetError::forbidden(
+                        "You are not allowed to delete contacts from this address book.",
+                    ));
+                }
+            }
+
+            // Verify that the address book is empty
+            if let Some(card_doc_ids) = self.get_tag(
+                helper.account_id,
+                Collection::ContactCard,
+                contact_card::Property::AddressBookIds.into(),
+                Tag::Id(document_id),
+            )? {
+                if on_destroy_remove_contents {
+                    // Try locking the collection before deleting the cards
+                    let _lock = match self.try_lock_collection(
+                        helper.account_id,
+                        Collection::ContactCard,
+                        Duration::from_secs(1),
+                    ) {
+                        Some(lock) => lock,
+                        None => {
+                            return Err(SetError::new(
+                                SetErrorType::RateLimit,
+                                "Resource busy, please try again in a few moments.",
+                            ));
+                        }
+                    };
+
+                    for card_document_id in card_doc_ids {
+                        let mut document = Document::new(Collection::ContactCard, card_document_id);
+                        let current_fields = if let Some(current_fields) =
+                            self.get_orm::<ContactCard>(helper.account_id, card_document_id)?
+                        {
+                            current_fields
+                        } else {
+                            debug!(
+                                "ContactCard ORM for {}:{} not found",
+                                helper.account_id, card_document_id
+                            );
+                            continue;
+                        };
+
+                        // Cards that belong to other address books are only untagged
+                        match current_fields.get_tags(&contact_card::Property::AddressBookIds) {
+                            Some(tags) if tags.len() > 1 => {
+                                let mut fields = TinyORM::track_changes(&current_fields);
+                                fields.untag(
+                                    &contact_card::Property::AddressBookIds,
+                                    &Tag::Id(document_id),
+                                );
+                                current_fields.merge(&mut document, fields)?;
+                                helper.changes.update_document(document);
+                                helper
+                                    .changes
+                                    .log_update(Collection::ContactCard, card_document_id);
+                            }
+                            _ => {
+                                current_fields.delete(&mut document);
+                                helper.changes.delete_document(document);
+                                helper
+                                    .changes
+                                    .log_delete(Collection::ContactCard, card_document_id);
+                            }
+                        }
+                    }
+                } else {
+                    return Err(SetError::new(
+                        SetErrorType::AddressBookHasContents,
+                        "Address book is not empty.",
+                    ));
+                }
+            }
+
+            // Delete ORM and index
+            if let Some(orm) = helper
+                .store
+                .get_orm::<AddressBook>(helper.account_id, document_id)?
+            {
+                orm.delete(document);
+            }
+
+            Ok(())
+        })?;
+
+        helper.into_response()
+    }
+
+    fn address_book_delete(
+        &self,
+        account_id: AccountId,
+        document: &mut Document,
+    ) -> store::Result<()> {
+        // Delete ORM
+        self.get_orm::<AddressBook>(account_id, document.document_id)?
+            .ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Failed to fetch AddressBook ORM for {}:{}.",
+                    account_id, document.document_id
+                ))
+            })?
+            .delete(document);
+
+        Ok(())
+    }
+}
+
+trait AddressBookSet<T>: Sized
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn address_book_set(
+        self,
+        helper: &mut SetHelper<AddressBook, T>,
+        address_book: AddressBook,
+        current_fields: Option<&TinyORM<AddressBook>>,
+    ) -> jmap::error::set::Result<Self, Property>;
+}
+
+impl<T> AddressBookSet<T> for TinyORM<AddressBook>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn address_book_set(
+        mut self,
+        helper: &mut SetHelper<AddressBook, T>,
+        address_book: AddressBook,
+        current_fields: Option<&TinyORM<AddressBook>>,
+    ) -> jmap::error::set::Result<Self, Property> {
+        for (property, value) in address_book.properties {
+            let value = match (property, value) {
+                (Property::Name, value @ Value::Text { .. }) => value,
+                (Property::Description, value @ (Value::Text { .. } | Value::Null)) => value,
+                (Property::SortOrder, value @ Value::Number { .. }) => value,
+                (Property::IsSubscribed, Value::Bool { value: subscribe }) => {
+                    let account_id = helper.acl.primary_id();
+                    let mut subscriptions = match current_fields
+                        .and_then(|fields| fields.get(&Property::IsSubscribed))
+                    {
+                        Some(Value::Subscriptions { value }) => value.clone(),
+                        _ => Vec::new(),
+                    };
+                    if subscribe {
+                        if subscriptions.contains(&account_id) {
+                            continue;
+                        }
+                        subscriptions.push(account_id);
+                    } else if subscriptions.contains(&account_id) {
+                        subscriptions.retain(|&id| id != account_id);
+                    } else {
+                        continue;
+                    }
+                    if !subscriptions.is_empty() {
+                        Value::Subscriptions {
+                            value: subscriptions,
+                        }
+                    } else {
+                        Value::Null
+                    }
+                }
+                (Property::ACL, Value::ACLSet(value)) => {
+                    for acl_update in &value {
+                        match acl_update {
+                            ACLUpdate::Replace { acls } => {
+                                self.acl_clear();
+                                for (account_id, acls) in acls {
+                                    self.acl_update(
+                                        helper.store.principal_to_id(account_id)?,
+                                        acls,
+                                    );
+                                }
+                            }
+                            ACLUpdate::Update { account_id, acls } => {
+                                self.acl_update(helper.store.principal_to_id(account_id)?, acls);
+                            }
+                            ACLUpdate::Set {
+                                account_id,
+                                acl,
+                                is_set,
+                            } => {
+                                self.acl_set(
+                                    helper.store.principal_to_id(account_id)?,
+                                    *acl,
+                                    *is_set,
+                                );
+                            }
+                        }
+                    }
+                    self.acl_finish();
+                    continue;
+                }
+                (_, _) => {
+                    return Err(SetError::invalid_property(
+                        property,
+                        "Field could not be set.",
+                    ));
+                }
+            };
+
+            self.set(property, value);
+        }
+
+        // Invalidate cache for changed ACLs
+        if let Some(permissions) = self.get_changed_acls(current_fields) {
+            for permission in permissions {
+                helper.store.acl_tokens.invalidate(&permission.id);
+                for acl in permission.acl {
+                    for collection in [Collection::ContactCard, Collection::AddressBook] {
+                        let key =
+                            SharedResource::new(helper.account_id, permission.id, collection, acl);
+                        helper.store.shared_documents.invalidate(&key);
+                    }
+                }
+            }
+        }
+
+        // Notify the accounts whose rights were granted, changed or revoked
+        helper.notify_acl_changes(&self, current_fields, &[Property::Name]);
+
+        Ok(self)
+    }
+}
@@ -0,0 +1,168 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::schema::{ContactCard, Property, Value};
+use super::set::{ContactCardSet, JMAPSetContactCard};
+use super::sharing::JMAPShareContacts;
+use jmap::{
+    error::set::{SetError, SetErrorType},
+    jmap_store::{copy::CopyHelper, get::GetObject, Object},
+    orm::{serialize::JMAPOrm, TinyORM},
+    request::{
+        copy::{CopyRequest, CopyResponse},
+        set::SetRequest,
+        ACLEnforce, MaybeResultReference,
+    },
+};
+use store::core::{acl::ACL, collection::Collection};
+use store::{JMAPStore, SharedBitmap, Store};
+
+pub trait JMAPCopyContactCard<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn contact_card_copy(
+        &self,
+        request: CopyRequest<ContactCard>,
+    ) -> jmap::Result<CopyResponse<ContactCard>>;
+}
+
+impl<T> JMAPCopyContactCard<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn contact_card_copy(
+        &self,
+        request: CopyRequest<ContactCard>,
+    ) -> jmap::Result<CopyResponse<ContactCard>> {
+        let mut helper = CopyHelper::new(self, request)?;
+        let address_book_ids = self
+            .get_document_ids(helper.account_id, Collection::AddressBook)?
+            .unwrap_or_default();
+        let on_success_delete = helper
+            .request
+            .on_success_destroy_original
+            .as_ref()
+            .copied()
+            .unwrap_or(false);
+        let destroy_from_if_in_state = helper.request.destroy_from_if_in_state.take();
+        let mut destroy_ids = Vec::new();
+
+        let is_shared_source = helper.acl.is_shared(helper.from_account_id);
+        let is_shared_target = helper.acl.is_shared(helper.account_id);
+
+        helper.create(|copy_id, item, helper, document| {
+            // Check ACL on source account
+            let document_id = copy_id.get_document_id();
+            if is_shared_source
+                && !helper
+                    .store
+                    .contacts_shared_cards(
+                        helper.from_account_id,
+                        &helper.acl.member_of,
+                        ACL::ReadItems,
+                    )?
+                    .has_access(document_id)
+            {
+                return Err(SetError::forbidden(
+                    "You do not have access to this contact.",
+                ));
+            }
+
+            // Copy the properties of the original card, address books and
+            // internal index properties are not copied.
+            let mut source = self
+                .get_orm::<ContactCard>(helper.from_account_id, document_id)?
+                .ok_or_else(|| SetError::new_err(SetErrorType::NotFound))?;
+            let mut fields = TinyORM::<ContactCard>::new();
+            for property in ContactCard::default_properties() {
+                if !matches!(
+                    property,
+                    Property::Id | Property::AddressBookIds | Property::Type
+                ) {
+                    if let Some(value) = source.remove(&property) {
+                        fields.set(property, value);
+                    }
+                }
+            }
+
+            // Apply the properties of the copy request
+            let fields = fields.contact_card_set(item, &address_book_ids, None, |id| {
+                id.value().copied().ok_or_else(|| {
+                    SetError::new(
+                        SetErrorType::InvalidProperties,
+                        "Invalid reference used on addressBookIds.",
+                    )
+                })
+            })?;
+
+            // Check ACL on target account
+            if is_shared_target {
+                self.contact_card_check_acl(
+                    helper.account_id,
+                    &helper.acl.member_of,
+                    fields
+                        .get_tags(&Property::AddressBookIds)
+                        .unwrap()
+                        .iter()
+                        .cloned(),
+                    ACL::AddItems,
+                )?;
+            }
+
+            let uid = fields
+                .get(&Property::Uid)
+                .and_then(|uid| uid.as_text())
+                .unwrap_or_default()
+                .to_string();
+            fields.insert_validate(document)?;
+
+            // Add to destroy list
+            if on_success_delete {
+                destroy_ids.push(*copy_id);
+            }
+
+            let mut card = ContactCard::new(document.document_id.into());
+            card.properties
+                .append(Property::Uid, Value::Text { value: uid });
+            Ok((card, None))
+        })?;
+
+        let acl = helper.acl.clone();
+        helper.into_response().map(|mut r| {
+            if on_success_delete && !destroy_ids.is_empty() {
+                r.next_call = SetRequest {
+                    acl: acl.into(),
+                    account_id: r.from_account_id,
+                    if_in_state: destroy_from_if_in_state,
+                    create: None,
+                    update: None,
+                    destroy: Some(MaybeResultReference::Value(destroy_ids)),
+                    arguments: (),
+                }
+                .into()
+            }
+            r
+        })
+    }
+}
@@ -0,0 +1,195 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::orm::TinyORM;
+use serde_json::Value as JsonValue;
+
+use super::schema::{ContactCard, Property, Value};
+
+// Recomputes the internal properties used by ContactCard/query from the
+// effective values of the card, which are the ones being changed in
+// `fields` or, if untouched, the ones in `current_fields`.
+pub fn build_index(
+    fields: &mut TinyORM<ContactCard>,
+    current_fields: Option<&TinyORM<ContactCard>>,
+) {
+    let get_json = |property: Property| -> Option<JsonValue> {
+        match fields
+            .get(&property)
+            .or_else(|| current_fields.and_then(|f| f.get(&property)))?
+        {
+            Value::Json { value } => serde_json::from_str(value).ok(),
+            _ => None,
+        }
+    };
+
+    let mut names = Vec::new();
+    let mut emails = Vec::new();
+    let mut phones = Vec::new();
+    let mut text = Vec::new();
+    let mut given = None;
+    let mut surname = None;
+
+    if let Some(name) = get_json(Property::Name) {
+        add_text(&mut names, name.get("full"));
+        for component in iter_list(name.get("components")) {
+            let value = component.get("value").and_then(|v| v.as_str());
+            match component.get("kind").and_then(|v| v.as_str()) {
+                Some("given") if given.is_none() => given = value.map(|v| v.to_lowercase()),
+                Some("surname") if surname.is_none() => surname = value.map(|v| v.to_lowercase()),
+                _ => (),
+            }
+            add_text(&mut names, component.get("value"));
+        }
+    }
+    if let Some(nicknames) = get_json(Property::Nicknames) {
+        for nickname in iter_map(&nicknames) {
+            add_text(&mut names, nickname.get("name"));
+        }
+    }
+    if let Some(items) = get_json(Property::Emails) {
+        for email in iter_map(&items) {
+            add_text(&mut emails, email.get("address"));
+        }
+    }
+    if let Some(items) = get_json(Property::Phones) {
+        for phone in iter_map(&items) {
+            add_text(&mut phones, phone.get("number"));
+        }
+    }
+    if let Some(items) = get_json(Property::Organizations) {
+        for organization in iter_map(&items) {
+            add_text(&mut text, organization.get("name"));
+            for unit in iter_list(organization.get("units")) {
+                add_text(&mut text, unit.get("name"));
+            }
+        }
+    }
+    if let Some(items) = get_json(Property::Titles) {
+        for title in iter_map(&items) {
+            add_text(&mut text, title.get("name"));
+        }
+    }
+    if let Some(items) = get_json(Property::Notes) {
+        for note in iter_map(&items) {
+            add_text(&mut text, note.get("note"));
+        }
+    }
+    if let Some(items) = get_json(Property::Addresses) {
+        for address in iter_map(&items) {
+            add_text(&mut text, address.get("full"));
+            for component in iter_list(address.get("components")) {
+                add_text(&mut text, component.get("value"));
+            }
+        }
+    }
+    if let Some(JsonValue::Object(keywords)) = get_json(Property::Keywords) {
+        for keyword in keywords.keys() {
+            text.push(keyword.to_string());
+        }
+    }
+
+    // The text index covers all indexed properties
+    for value in names.iter().chain(emails.iter()).chain(phones.iter()) {
+        if !text.contains(value) {
+            text.push(value.to_string());
+        }
+    }
+
+    set_text(fields, current_fields, Property::SortGiven, given);
+    set_text(fields, current_fields, Property::SortSurname, surname);
+    set_list(fields, current_fields, Property::IndexName, names);
+    set_list(fields, current_fields, Property::IndexEmail, emails);
+    set_list(fields, current_fields, Property::IndexPhone, phones);
+    set_list(fields, current_fields, Property::IndexText, text);
+}
+
+fn iter_map(value: &JsonValue) -> impl Iterator<Item = &JsonValue> {
+    value.as_object().into_iter().flat_map(|map| map.values())
+}
+
+fn iter_list(value: Option<&JsonValue>) -> impl Iterator<Item = &JsonValue> {
+    value
+        .and_then(|value| value.as_array())
+        .into_iter()
+        .flat_map(|list| list.iter())
+}
+
+fn add_text(list: &mut Vec<String>, value: Option<&JsonValue>) {
+    if let Some(value) = value.and_then(|v| v.as_str()) {
+        let value = value.trim();
+        if !value.is_empty() && !list.iter().any(|v| v == value) {
+            list.push(value.to_string());
+        }
+    }
+}
+
+fn set_text(
+    fields: &mut TinyORM<ContactCard>,
+    current_fields: Option<&TinyORM<ContactCard>>,
+    property: Property,
+    value: Option<String>,
+) {
+    set_value(
+        fields,
+        current_fields,
+        property,
+        value.map(|value| Value::Text { value }),
+    );
+}
+
+fn set_list(
+    fields: &mut TinyORM<ContactCard>,
+    current_fields: Option<&TinyORM<ContactCard>>,
+    property: Property,
+    value: Vec<String>,
+) {
+    set_value(
+        fields,
+        current_fields,
+        property,
+        if !value.is_empty() {
+            Value::TextList { value }.into()
+        } else {
+            None
+        },
+    );
+}
+
+fn set_value(
+    fields: &mut TinyORM<ContactCard>,
+    current_fields: Option<&TinyORM<ContactCard>>,
+    property: Property,
+    value: Option<Value>,
+) {
+    let current_value = current_fields.and_then(|f| f.get(&property));
+    match value {
+        Some(value) if current_value != Some(&value) => {
+            fields.set(property, value);
+        }
+        None if current_value.is_some() => {
+            fields.set(property, Value::Null);
+        }
+        _ => (),
+    }
+}
@@ -0,0 +1,96 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod changes;
+pub mod copy;
+pub mod get;
+pub mod index;
+pub mod query;
+pub mod raft;
+pub mod schema;
+pub mod serialize;
+pub mod set;
+pub mod sharing;
+pub mod vcard;
+
+use jmap::jmap_store::Object;
+use jmap::types::jmap::JMAPId;
+use store::core::collection::Collection;
+use store::write::options::Options;
+
+use self::schema::{ContactCard, Property, Value};
+
+impl Object for ContactCard {
+    type Property = Property;
+
+    type Value = Value;
+
+    fn id(&self) -> Option<&JMAPId> {
+        self.properties.get(&Property::Id).and_then(|id| match id {
+            Value::Id { value } => Some(value),
+            _ => None,
+        })
+    }
+
+    fn required() -> &'static [Self::Property] {
+        &[Property::Uid]
+    }
+
+    fn indexed() -> &'static [(Self::Property, u64)] {
+        &[
+            (Property::Uid, <u64 as Options>::F_KEYWORD),
+            (Property::DavName, <u64 as Options>::F_KEYWORD),
+            (Property::Kind, <u64 as Options>::F_KEYWORD),
+            (Property::Created, <u64 as Options>::F_INDEX),
+            (Property::Updated, <u64 as Options>::F_INDEX),
+            (Property::IndexName, <u64 as Options>::F_TOKENIZE),
+            (Property::IndexEmail, <u64 as Options>::F_TOKENIZE),
+            (Property::IndexPhone, <u64 as Options>::F_TOKENIZE),
+            (Property::IndexText, <u64 as Options>::F_TOKENIZE),
+            (Property::SortGiven, <u64 as Options>::F_INDEX),
+            (Property::SortSurname, <u64 as Options>::F_INDEX),
+        ]
+    }
+
+    fn max_len() -> &'static [(Self::Property, usize)] {
+        &[
+            (Property::Uid, 255),
+            (Property::DavName, 255),
+            (Property::Kind, 255),
+            (Property::Language, 255),
+            (Property::ProdId, 255),
+            (Property::Version, 255),
+        ]
+    }
+
+    fn collection() -> Collection {
+        Collection::ContactCard
+    }
+
+    fn new(id: JMAPId) -> Self {
+        let mut item = ContactCard::default();
+        item.properties
+            .append(Property::Id, Value::Id { value: id });
+        item
+    }
+}
@@ -0,0 +1,135 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::schema::{Comparator, ContactCard, Filter, Property};
+use super::sharing::JMAPShareContacts;
+use jmap::error::method::MethodError;
+use jmap::jmap_store::query::{ExtraFilterFnc, QueryHelper, QueryObject};
+use jmap::request::query::{QueryRequest, QueryResponse};
+use store::core::acl::ACL;
+use store::core::tag::Tag;
+use store::read::comparator::{self, FieldComparator};
+use store::read::default_filter_mapper;
+use store::read::filter::{self, Query};
+use store::{AccountId, JMAPStore, LongInteger, Store};
+
+impl QueryObject for ContactCard {
+    type QueryArguments = ();
+
+    type Filter = Filter;
+
+    type Comparator = Comparator;
+}
+
+pub trait JMAPContactCardQuery<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn contact_card_query(&self, request: QueryRequest<ContactCard>)
+        -> jmap::Result<QueryResponse>;
+}
+
+impl<T> JMAPContactCardQuery<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn contact_card_query(
+        &self,
+        request: QueryRequest<ContactCard>,
+    ) -> jmap::Result<QueryResponse> {
+        let mut helper = QueryHelper::new(
+            self,
+            request,
+            (|account_id: AccountId, member_of: &[AccountId]| {
+                self.contacts_shared_cards(account_id, member_of, ACL::ReadItems)
+            })
+            .into(),
+        )?;
+
+        helper.parse_filter(|filter| {
+            Ok(match filter {
+                Filter::InAddressBook { value } => filter::Filter::eq(
+                    Property::AddressBookIds.into(),
+                    Query::Tag(Tag::Id(value.get_document_id())),
+                ),
+                Filter::Uid { value } => {
+                    filter::Filter::eq(Property::Uid.into(), Query::Keyword(value))
+                }
+                Filter::DavName { value } => {
+                    filter::Filter::eq(Property::DavName.into(), Query::Keyword(value))
+                }
+                Filter::Kind { value } => {
+                    filter::Filter::eq(Property::Kind.into(), Query::Keyword(value))
+                }
+                Filter::Text { value } => {
+                    filter::Filter::eq(Property::IndexText.into(), Query::Tokenize(value))
+                }
+                Filter::Name { value } => {
+                    filter::Filter::eq(Property::IndexName.into(), Query::Tokenize(value))
+                }
+                Filter::Email { value } => {
+                    filter::Filter::eq(Property::IndexEmail.into(), Query::Tokenize(value))
+                }
+                Filter::Phone { value } => {
+                    filter::Filter::eq(Property::IndexPhone.into(), Query::Tokenize(value))
+                }
+                Filter::CreatedBefore { value } => filter::Filter::lt(
+                    Property::Created.into(),
+                    Query::LongInteger(value.timestamp() as LongInteger),
+                ),
+                Filter::CreatedAfter { value } => filter::Filter::gt(
+                    Property::Created.into(),
+                    Query::LongInteger(value.timestamp() as LongInteger),
+                ),
+                Filter::UpdatedBefore { value } => filter::Filter::lt(
+                    Property::Updated.into(),
+                    Query::LongInteger(value.timestamp() as LongInteger),
+                ),
+                Filter::UpdatedAfter { value } => filter::Filter::gt(
+                    Property::Updated.into(),
+                    Query::LongInteger(value.timestamp() as LongInteger),
+                ),
+                Filter::Unsupported { value } => {
+                    return Err(MethodError::UnsupportedFilter(value));
+                }
+            })
+        })?;
+
+        helper.parse_comparator(|comparator| {
+            Ok(comparator::Comparator::Field(FieldComparator {
+                field: {
+                    match comparator.property {
+                        Comparator::Created => Property::Created,
+                        Comparator::Updated => Property::Updated,
+                        Comparator::GivenName => Property::SortGiven,
+                        Comparator::Surname => Property::SortSurname,
+                    }
+                }
+                .into(),
+                ascending: comparator.is_ascending,
+            }))
+        })?;
+
+        helper.query(default_filter_mapper, None::<ExtraFilterFnc>)
+    }
+}
@@ -0,0 +1,368 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::fmt::Display;
+
+use jmap::{
+    orm,
+    request::{MaybeIdReference, ResultReference},
+    types::{date::JMAPDate, jmap::JMAPId},
+};
+use serde::{Deserialize, Serialize};
+use store::{core::vec_map::VecMap, FieldId};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContactCard {
+    pub properties: VecMap<Property, Value>,
+}
+
+// Structured JSContact properties are kept as the JSON text received from
+// the client, the values used for searching and sorting are extracted into
+// the internal index properties on every update.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Value {
+    Id {
+        value: JMAPId,
+    },
+    Text {
+        value: String,
+    },
+    TextList {
+        value: Vec<String>,
+    },
+    Date {
+        value: JMAPDate,
+    },
+    Json {
+        value: String,
+    },
+    Patch {
+        value: Vec<(Vec<String>, String)>,
+    },
+    AddressBookIds {
+        value: VecMap<MaybeIdReference, bool>,
+        set: bool,
+    },
+    ResultReference {
+        value: ResultReference,
+    },
+    Null,
+}
+
+impl Default for Value {
+    fn default() -> Self {
+        Value::Null
+    }
+}
+
+impl orm::Value for Value {
+    fn index_as(&self) -> orm::Index {
+        match self {
+            Value::Text { value } => value.to_string().into(),
+            Value::TextList { value } => {
+                if !value.is_empty() {
+                    value.to_vec().into()
+                } else {
+                    orm::Index::Null
+                }
+            }
+            Value::Date { value } => (value.timestamp() as u64).into(),
+            _ => orm::Index::Null,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            Value::Text { value } | Value::Json { value } => value.is_empty(),
+            Value::TextList { value } => value.is_empty(),
+            Value::Null => true,
+            _ => false,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Value::Id { .. } => std::mem::size_of::<JMAPId>(),
+            Value::Text { value } | Value::Json { value } => value.len(),
+            Value::TextList { value } => value.iter().fold(0, |acc, item| acc + item.len()),
+            Value::Date { .. } => std::mem::size_of::<JMAPDate>(),
+            Value::Patch { value } => value.iter().fold(0, |acc, (path, value)| {
+                acc + path.iter().fold(0, |acc, item| acc + item.len()) + value.len()
+            }),
+            Value::AddressBookIds { value, .. } => value.len() * std::mem::size_of::<JMAPId>(),
+            Value::ResultReference { .. } => std::mem::size_of::<ResultReference>(),
+            Value::Null => 0,
+        }
+    }
+}
+
+impl Value {
+    pub fn get_address_book_ids(&mut self) -> Option<&mut VecMap<MaybeIdReference, bool>> {
+        match self {
+            Value::AddressBookIds { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text { value } => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+#[repr(u8)]
+pub enum Property {
+    Id = 0,
+    AddressBookIds = 1,
+    Type = 2,
+    Version = 3,
+    Created = 4,
+    Kind = 5,
+    Language = 6,
+    ProdId = 7,
+    Uid = 8,
+    Updated = 9,
+    Members = 10,
+    RelatedTo = 11,
+    Name = 12,
+    Nicknames = 13,
+    Organizations = 14,
+    SpeakToAs = 15,
+    Titles = 16,
+    Emails = 17,
+    OnlineServices = 18,
+    Phones = 19,
+    PreferredLanguages = 20,
+    Calendars = 21,
+    SchedulingAddresses = 22,
+    Addresses = 23,
+    CryptoKeys = 24,
+    Directories = 25,
+    Links = 26,
+    Media = 27,
+    Localizations = 28,
+    Anniversaries = 29,
+    Keywords = 30,
+    Notes = 31,
+    PersonalInfo = 32,
+    IndexName = 33,
+    IndexEmail = 34,
+    IndexPhone = 35,
+    IndexText = 36,
+    SortGiven = 37,
+    SortSurname = 38,
+    DavName = 39,
+    Invalid = 40,
+}
+
+impl Property {
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "id" => Property::Id,
+            "addressBookIds" => Property::AddressBookIds,
+            "@type" => Property::Type,
+            "version" => Property::Version,
+            "created" => Property::Created,
+            "kind" => Property::Kind,
+            "language" => Property::Language,
+            "prodId" => Property::ProdId,
+            "uid" => Property::Uid,
+            "updated" => Property::Updated,
+            "members" => Property::Members,
+            "relatedTo" => Property::RelatedTo,
+            "name" => Property::Name,
+            "nicknames" => Property::Nicknames,
+            "organizations" => Property::Organizations,
+            "speakToAs" => Property::SpeakToAs,
+            "titles" => Property::Titles,
+            "emails" => Property::Emails,
+            "onlineServices" => Property::OnlineServices,
+            "phones" => Property::Phones,
+            "preferredLanguages" => Property::PreferredLanguages,
+            "calendars" => Property::Calendars,
+            "schedulingAddresses" => Property::SchedulingAddresses,
+            "addresses" => Property::Addresses,
+            "cryptoKeys" => Property::CryptoKeys,
+            "directories" => Property::Directories,
+            "links" => Property::Links,
+            "media" => Property::Media,
+            "localizations" => Property::Localizations,
+            "anniversaries" => Property::Anniversaries,
+            "keywords" => Property::Keywords,
+            "notes" => Property::Notes,
+            "personalInfo" => Property::PersonalInfo,
+            "davName" => Property::DavName,
+            _ => Property::Invalid,
+        }
+    }
+
+    pub fn is_json(&self) -> bool {
+        (*self as u8) >= Property::Members as u8 && (*self as u8) <= Property::PersonalInfo as u8
+    }
+}
+
+impl Display for Property {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Property::Id => write!(f, "id"),
+            Property::AddressBookIds => write!(f, "addressBookIds"),
+            Property::Type => write!(f, "@type"),
+            Property::Version => write!(f, "version"),
+            Property::Created => write!(f, "created"),
+            Property::Kind => write!(f, "kind"),
+            Property::Language => write!(f, "language"),
+            Property::ProdId => write!(f, "prodId"),
+            Property::Uid => write!(f, "uid"),
+            Property::Updated => write!(f, "updated"),
+            Property::Members => write!(f, "members"),
+            Property::RelatedTo => write!(f, "relatedTo"),
+            Property::Name => write!(f, "name"),
+            Property::Nicknames => write!(f, "nicknames"),
+            Property::Organizations => write!(f, "organizations"),
+            Property::SpeakToAs => write!(f, "speakToAs"),
+            Property::Titles => write!(f, "titles"),
+            Property::Emails => write!(f, "emails"),
+            Property::OnlineServices => write!(f, "onlineServices"),
+            Property::Phones => write!(f, "phones"),
+            Property::PreferredLanguages => write!(f, "preferredLanguages"),
+            Property::Calendars => write!(f, "calendars"),
+            Property::SchedulingAddresses => write!(f, "schedulingAddresses"),
+            Property::Addresses => write!(f, "addresses"),
+            Property::CryptoKeys => write!(f, "cryptoKeys"),
+            Property::Directories => write!(f, "directories"),
+            Property::Links => write!(f, "links"),
+            Property::Media => write!(f, "media"),
+            Property::Localizations => write!(f, "localizations"),
+            Property::Anniversaries => write!(f, "anniversaries"),
+            Property::Keywords => write!(f, "keywords"),
+            Property::Notes => write!(f, "notes"),
+            Property::PersonalInfo => write!(f, "personalInfo"),
+            Property::DavName => write!(f, "davName"),
+            Property::IndexName
+            | Property::IndexEmail
+            | Property::IndexPhone
+            | Property::IndexText
+            | Property::SortGiven
+            | Property::SortSurname
+            | Property::Invalid => Ok(()),
+        }
+    }
+}
+
+impl From<Property> for FieldId {
+    fn from(property: Property) -> Self {
+        property as FieldId
+    }
+}
+
+impl From<FieldId> for Property {
+    fn from(field: FieldId) -> Self {
+        match field {
+            0 => Property::Id,
+            1 => Property::AddressBookIds,
+            2 => Property::Type,
+            3 => Property::Version,
+            4 => Property::Created,
+            5 => Property::Kind,
+            6 => Property::Language,
+            7 => Property::ProdId,
+            8 => Property::Uid,
+            9 => Property::Updated,
+            10 => Property::Members,
+            11 => Property::RelatedTo,
+            12 => Property::Name,
+            13 => Property::Nicknames,
+            14 => Property::Organizations,
+            15 => Property::SpeakToAs,
+            16 => Property::Titles,
+            17 => Property::Emails,
+            18 => Property::OnlineServices,
+            19 => Property::Phones,
+            20 => Property::PreferredLanguages,
+            21 => Property::Calendars,
+            22 => Property::SchedulingAddresses,
+            23 => Property::Addresses,
+            24 => Property::CryptoKeys,
+            25 => Property::Directories,
+            26 => Property::Links,
+            27 => Property::Media,
+            28 => Property::Localizations,
+            29 => Property::Anniversaries,
+            30 => Property::Keywords,
+            31 => Property::Notes,
+            32 => Property::PersonalInfo,
+            33 => Property::IndexName,
+            34 => Property::IndexEmail,
+            35 => Property::IndexPhone,
+            36 => Property::IndexText,
+            37 => Property::SortGiven,
+            38 => Property::SortSurname,
+            39 => Property::DavName,
+            _ => Property::Invalid,
+        }
+    }
+}
+
+impl TryFrom<&str> for Property {
+    type Error = ();
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match Property::parse(value) {
+            Property::Invalid => Err(()),
+            property => Ok(property),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Filter {
+    InAddressBook { value: JMAPId },
+    Uid { value: String },
+    DavName { value: String },
+    Kind { value: String },
+    Text { value: String },
+    Name { value: String },
+    Email { value: String },
+    Phone { value: String },
+    CreatedBefore { value: JMAPDate },
+    CreatedAfter { value: JMAPDate },
+    UpdatedBefore { value: JMAPDate },
+    UpdatedAfter { value: JMAPDate },
+    Unsupported { value: String },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "property")]
+pub enum Comparator {
+    #[serde(rename = "created")]
+    Created,
+    #[serde(rename = "updated")]
+    Updated,
+    #[serde(rename = "name/given")]
+    GivenName,
+    #[serde(rename = "name/surname")]
+    Surname,
+}
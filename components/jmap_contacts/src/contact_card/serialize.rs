@@ -0,0 +1,286 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, fmt};
+
+use jmap::{
+    request::{query::FilterDeserializer, MaybeIdReference},
+    types::{date::JMAPDate, jmap::JMAPId, json_pointer::JSONPointer},
+};
+use serde::{
+    de::IgnoredAny,
+    ser::{Error, SerializeMap},
+    Deserialize, Serialize,
+};
+use store::core::vec_map::VecMap;
+
+use super::schema::{ContactCard, Filter, Property, Value};
+
+// Property de/serialization
+impl Serialize for Property {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+struct PropertyVisitor;
+
+impl<'de> serde::de::Visitor<'de> for PropertyVisitor {
+    type Value = Property;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a valid JMAP ContactCard property")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Property::parse(v))
+    }
+}
+
+impl<'de> Deserialize<'de> for Property {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(PropertyVisitor)
+    }
+}
+
+// ContactCard de/serialization
+impl Serialize for ContactCard {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(self.properties.len().into())?;
+
+        for (name, value) in &self.properties {
+            match value {
+                Value::Id { value } => map.serialize_entry(name, value)?,
+                Value::Text { value } => map.serialize_entry(name, value)?,
+                Value::Date { value } => map.serialize_entry(name, value)?,
+                Value::Json { value } => map.serialize_entry(
+                    name,
+                    &serde_json::from_str::<serde_json::Value>(value).map_err(S::Error::custom)?,
+                )?,
+                Value::AddressBookIds { value, .. } => map.serialize_entry(name, value)?,
+                Value::Null => map.serialize_entry(name, &())?,
+                Value::TextList { .. } | Value::Patch { .. } | Value::ResultReference { .. } => (),
+            }
+        }
+
+        map.end()
+    }
+}
+
+struct ContactCardVisitor;
+
+impl<'de> serde::de::Visitor<'de> for ContactCardVisitor {
+    type Value = ContactCard;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a valid JMAP ContactCard object")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let mut properties: VecMap<Property, Value> = VecMap::new();
+
+        while let Some(key) = map.next_key::<Cow<str>>()? {
+            match Property::parse(key.as_ref()) {
+                Property::AddressBookIds => {
+                    if let Some(value) =
+                        map.next_value::<Option<VecMap<MaybeIdReference, bool>>>()?
+                    {
+                        properties.append(
+                            Property::AddressBookIds,
+                            Value::AddressBookIds { value, set: true },
+                        );
+                    }
+                }
+                property @ (Property::Type
+                | Property::Version
+                | Property::Kind
+                | Property::Language
+                | Property::ProdId
+                | Property::Uid
+                | Property::DavName) => {
+                    properties.append(
+                        property,
+                        if let Some(value) = map.next_value::<Option<String>>()? {
+                            Value::Text { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                property @ (Property::Created | Property::Updated) => {
+                    properties.append(
+                        property,
+                        if let Some(value) = map.next_value::<Option<JMAPDate>>()? {
+                            Value::Date { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                property if property.is_json() => {
+                    properties.append(
+                        property,
+                        if let Some(value) = map.next_value::<Option<serde_json::Value>>()? {
+                            Value::Json {
+                                value: value.to_string(),
+                            }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                _ if key.starts_with('#') => {
+                    if let Some(property) = key.get(1..) {
+                        properties.append(
+                            Property::parse(property),
+                            Value::ResultReference {
+                                value: map.next_value()?,
+                            },
+                        );
+                    }
+                }
+                _ => match JSONPointer::parse(key.as_ref()) {
+                    Some(JSONPointer::Path(path)) if path.len() >= 2 => {
+                        let mut path = path
+                            .into_iter()
+                            .map(|item| match item {
+                                JSONPointer::String(value) => value,
+                                JSONPointer::Number(value) => value.to_string(),
+                                _ => "*".to_string(),
+                            })
+                            .collect::<Vec<_>>();
+                        match Property::parse(&path.remove(0)) {
+                            Property::AddressBookIds if path.len() == 1 => {
+                                let value = map.next_value::<Option<bool>>()?.unwrap_or(false);
+                                if let Some(id) = JMAPId::parse(&path[0]) {
+                                    properties
+                                        .get_mut_or_insert_with(Property::AddressBookIds, || {
+                                            Value::AddressBookIds {
+                                                value: VecMap::new(),
+                                                set: false,
+                                            }
+                                        })
+                                        .get_address_book_ids()
+                                        .unwrap()
+                                        .append(MaybeIdReference::Value(id), value);
+                                }
+                            }
+                            property if property.is_json() => {
+                                let value = map.next_value::<serde_json::Value>()?.to_string();
+                                if let Value::Patch { value: patches } = properties
+                                    .get_mut_or_insert_with(property, || Value::Patch {
+                                        value: Vec::new(),
+                                    })
+                                {
+                                    patches.push((path, value));
+                                }
+                            }
+                            _ => {
+                                map.next_value::<IgnoredAny>()?;
+                            }
+                        }
+                    }
+                    _ => {
+                        map.next_value::<IgnoredAny>()?;
+                    }
+                },
+            }
+        }
+
+        Ok(ContactCard { properties })
+    }
+}
+
+impl<'de> Deserialize<'de> for ContactCard {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(ContactCardVisitor)
+    }
+}
+
+// Filter deserializer
+impl FilterDeserializer for Filter {
+    fn deserialize<'x>(property: &str, map: &mut impl serde::de::MapAccess<'x>) -> Option<Self> {
+        match property {
+            "inAddressBook" => Filter::InAddressBook {
+                value: map.next_value().ok()?,
+            },
+            "uid" => Filter::Uid {
+                value: map.next_value().ok()?,
+            },
+            "davName" => Filter::DavName {
+                value: map.next_value().ok()?,
+            },
+            "kind" => Filter::Kind {
+                value: map.next_value().ok()?,
+            },
+            "text" => Filter::Text {
+                value: map.next_value().ok()?,
+            },
+            "name" => Filter::Name {
+                value: map.next_value().ok()?,
+            },
+            "email" => Filter::Email {
+                value: map.next_value().ok()?,
+            },
+            "phone" => Filter::Phone {
+                value: map.next_value().ok()?,
+            },
+            "createdBefore" => Filter::CreatedBefore {
+                value: map.next_value().ok()?,
+            },
+            "createdAfter" => Filter::CreatedAfter {
+                value: map.next_value().ok()?,
+            },
+            "updatedBefore" => Filter::UpdatedBefore {
+                value: map.next_value().ok()?,
+            },
+            "updatedAfter" => Filter::UpdatedAfter {
+                value: map.next_value().ok()?,
+            },
+            unsupported => {
+                map.next_value::<IgnoredAny>().ok()?;
+                Filter::Unsupported {
+                    value: unsupported.to_string(),
+                }
+            }
+        }
+        .into()
+    }
+}
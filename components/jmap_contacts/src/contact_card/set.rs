@@ -0,0 +1,492 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use super::index::build_index;
+use super::schema::{ContactCard, Property, Value};
+use super::sharing::JMAPShareContacts;
+use jmap::error::set::{SetError, SetErrorType};
+use jmap::jmap_store::set::{SetHelper, SetObject};
+use jmap::jmap_store::Object;
+use jmap::orm::{serialize::JMAPOrm, TinyORM};
+use jmap::request::set::{SetRequest, SetResponse};
+use jmap::request::{ACLEnforce, MaybeIdReference, ResultReference};
+use jmap::types::date::JMAPDate;
+use jmap::types::jmap::JMAPId;
+use serde_json::Value as JsonValue;
+use store::core::acl::ACL;
+use store::core::collection::Collection;
+use store::core::document::Document;
+use store::core::error::StoreError;
+use store::core::tag::Tag;
+use store::core::vec_map::VecMap;
+use store::rand;
+use store::roaring::RoaringBitmap;
+use store::{AccountId, JMAPStore, SharedBitmap, Store};
+
+impl SetObject for ContactCard {
+    type SetArguments = ();
+
+    type NextCall = SetRequest<ContactCard>;
+
+    fn eval_id_references(&mut self, mut fnc: impl FnMut(&str) -> Option<JMAPId>) {
+        if let Some(Value::AddressBookIds { value, .. }) =
+            self.properties.get_mut(&Property::AddressBookIds)
+        {
+            if value
+                .keys()
+                .any(|k| matches!(k, MaybeIdReference::Reference(_)))
+            {
+                let mut new_values = VecMap::with_capacity(value.len());
+
+                for (id, value) in std::mem::take(value).into_iter() {
+                    if let MaybeIdReference::Reference(id) = &id {
+                        if let Some(id) = fnc(id) {
+                            new_values.append(MaybeIdReference::Value(id), value);
+                            continue;
+                        }
+                    }
+                    new_values.append(id, value);
+                }
+
+                *value = new_values;
+            }
+        }
+    }
+
+    fn eval_result_references(
+        &mut self,
+        mut fnc: impl FnMut(&ResultReference) -> Option<Vec<u64>>,
+    ) {
+        for (property, entry) in self.properties.iter_mut() {
+            if let (Property::AddressBookIds, Value::ResultReference { value }) = (property, &entry)
+            {
+                if let Some(value) = fnc(value) {
+                    *entry = Value::AddressBookIds {
+                        value: value
+                            .into_iter()
+                            .map(|v| (MaybeIdReference::Value(v.into()), true))
+                            .collect(),
+                        set: true,
+                    };
+                }
+            }
+        }
+    }
+}
+
+pub trait JMAPSetContactCard<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn contact_card_set(
+        &self,
+        request: SetRequest<ContactCard>,
+    ) -> jmap::Result<SetResponse<ContactCard>>;
+    fn contact_card_delete(
+        &self,
+        account_id: AccountId,
+        document: &mut Document,
+    ) -> store::Result<()>;
+    fn contact_card_check_acl(
+        &self,
+        account_id: AccountId,
+        member_of: &[AccountId],
+        address_book_ids: impl Iterator<Item = Tag>,
+        acl: ACL,
+    ) -> jmap::error::set::Result<(), Property>;
+}
+
+impl<T> JMAPSetContactCard<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn contact_card_set(
+        &self,
+        request: SetRequest<ContactCard>,
+    ) -> jmap::Result<SetResponse<ContactCard>> {
+        let mut helper = SetHelper::new(self, request)?;
+        let address_book_ids = self
+            .get_document_ids(helper.account_id, Collection::AddressBook)?
+            .unwrap_or_default();
+
+        helper.create(|_create_id, item, helper, document| {
+            let fields = TinyORM::<ContactCard>::new().contact_card_set(
+                item,
+                &address_book_ids,
+                None,
+                |id| helper.unwrap_id_reference(Property::AddressBookIds, id),
+            )?;
+
+            // Check ACLs
+            if helper.acl.is_shared(helper.account_id) {
+                self.contact_card_check_acl(
+                    helper.account_id,
+                    &helper.acl.member_of,
+                    fields
+                        .get_tags(&Property::AddressBookIds)
+                        .unwrap()
+                        .iter()
+                        .cloned(),
+                    ACL::AddItems,
+                )?;
+            }
+
+            let uid = fields
+                .get(&Property::Uid)
+                .and_then(|uid| uid.as_text())
+                .unwrap()
+                .to_string();
+            fields.insert_validate(document)?;
+
+            let mut card = ContactCard::new(document.document_id.into());
+            card.properties
+                .append(Property::Uid, Value::Text { value: uid });
+            Ok(card)
+        })?;
+
+        helper.update(|id, item, helper, document| {
+            let document_id = id.get_document_id();
+            let current_fields = self
+                .get_orm::<ContactCard>(helper.account_id, document_id)?
+                .ok_or_else(|| SetError::new_err(SetErrorType::NotFound))?;
+
+            let fields = TinyORM::track_changes(&current_fields).contact_card_set(
+                item,
+                &address_book_ids,
+                Some(&current_fields),
+                |id| helper.unwrap_id_reference(Property::AddressBookIds, id),
+            )?;
+
+            // Check ACLs
+            if helper.acl.is_shared(helper.account_id) {
+                if !self
+                    .contacts_shared_cards(
+                        helper.account_id,
+                        &helper.acl.member_of,
+                        ACL::ModifyItems,
+                    )?
+                    .has_access(document_id)
+                {
+                    return Err(SetError::forbidden(
+                        "You are not allowed to modify this contact.",
+                    ));
+                }
+                self.contact_card_check_acl(
+                    helper.account_id,
+                    &helper.acl.member_of,
+                    current_fields
+                        .get_added_tags(&fields, &Property::AddressBookIds)
+                        .into_iter(),
+                    ACL::AddItems,
+                )?;
+                self.contact_card_check_acl(
+                    helper.account_id,
+                    &helper.acl.member_of,
+                    current_fields
+                        .get_removed_tags(&fields, &Property::AddressBookIds)
+                        .into_iter(),
+                    ACL::RemoveItems,
+                )?;
+            }
+
+            // Merge changes
+            current_fields.merge_validate(document, fields)?;
+
+            Ok(None)
+        })?;
+
+        helper.destroy(|id, helper, document| {
+            let document_id = id.get_document_id();
+
+            if let Some(orm) = self.get_orm::<ContactCard>(helper.account_id, document_id)? {
+                // Check ACLs
+                if helper.acl.is_shared(helper.account_id) {
+                    self.contact_card_check_acl(
+                        helper.account_id,
+                        &helper.acl.member_of,
+                        orm.get_tags(&Property::AddressBookIds)
+                            .into_iter()
+                            .flatten()
+                            .cloned(),
+                        ACL::RemoveItems,
+                    )?;
+                }
+                orm.delete(document);
+            }
+            Ok(())
+        })?;
+
+        helper.into_response()
+    }
+
+    fn contact_card_delete(
+        &self,
+        account_id: AccountId,
+        document: &mut Document,
+    ) -> store::Result<()> {
+        // Delete ORM
+        self.get_orm::<ContactCard>(account_id, document.document_id)?
+            .ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Failed to fetch ContactCard ORM for {}:{}.",
+                    account_id, document.document_id
+                ))
+            })?
+            .delete(document);
+
+        Ok(())
+    }
+
+    fn contact_card_check_acl(
+        &self,
+        account_id: AccountId,
+        member_of: &[AccountId],
+        address_book_ids: impl Iterator<Item = Tag>,
+        acl: ACL,
+    ) -> jmap::error::set::Result<(), Property> {
+        let allowed_books = self.contacts_shared_books(account_id, member_of, acl)?;
+        for address_book_id in address_book_ids {
+            let address_book_id = address_book_id.as_id();
+            if !allowed_books.has_access(address_book_id) {
+                return Err(SetError::forbidden(format!(
+                    "You are not allowed to {} contacts in address book {}.",
+                    if acl == ACL::RemoveItems {
+                        "remove"
+                    } else {
+                        "add"
+                    },
+                    JMAPId::from(address_book_id)
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub trait ContactCardSet: Sized {
+    fn contact_card_set(
+        self,
+        card: ContactCard,
+        address_book_ids: &RoaringBitmap,
+        current_fields: Option<&TinyORM<ContactCard>>,
+        resolve_id: impl Fn(&MaybeIdReference) -> jmap::error::set::Result<JMAPId, Property>,
+    ) -> jmap::error::set::Result<Self, Property>;
+}
+
+impl ContactCardSet for TinyORM<ContactCard> {
+    fn contact_card_set(
+        mut self,
+        card: ContactCard,
+        address_book_ids: &RoaringBitmap,
+        current_fields: Option<&TinyORM<ContactCard>>,
+        resolve_id: impl Fn(&MaybeIdReference) -> jmap::error::set::Result<JMAPId, Property>,
+    ) -> jmap::error::set::Result<Self, Property> {
+        for (property, value) in card.properties {
+            let value = match (property, value) {
+                (Property::AddressBookIds, Value::AddressBookIds { value, set }) => {
+                    if set {
+                        self.untag_all(&Property::AddressBookIds);
+                    }
+                    for (address_book_id, is_set) in value {
+                        let address_book_id = resolve_id(&address_book_id)?;
+                        if !address_book_ids.contains(address_book_id.get_document_id()) {
+                            return Err(SetError::invalid_property(
+                                Property::AddressBookIds,
+                                format!("addressBookId {} does not exist.", address_book_id),
+                            ));
+                        }
+                        let tag = Tag::Id(address_book_id.get_document_id());
+                        if is_set {
+                            self.tag(Property::AddressBookIds, tag);
+                        } else if !set {
+                            self.untag(&Property::AddressBookIds, &tag);
+                        }
+                    }
+                    continue;
+                }
+                (Property::Type, Value::Text { value }) => {
+                    if value == "Card" {
+                        continue;
+                    } else {
+                        return Err(SetError::invalid_property(
+                            property,
+                            "Unsupported JSContact type.",
+                        ));
+                    }
+                }
+                (Property::Uid, Value::Text { value }) => {
+                    if current_fields.is_none() {
+                        Value::Text { value }
+                    } else {
+                        return Err(SetError::invalid_property(
+                            property,
+                            "The uid of a contact cannot be changed.",
+                        ));
+                    }
+                }
+                (
+                    Property::Version
+                    | Property::Kind
+                    | Property::Language
+                    | Property::ProdId
+                    | Property::DavName,
+                    value @ (Value::Text { .. } | Value::Null),
+                ) => value,
+                (Property::Created | Property::Updated, value @ Value::Date { .. }) => value,
+                (property, value @ (Value::Json { .. } | Value::Null)) if property.is_json() => {
+                    value
+                }
+                (property, Value::Patch { value: patches }) if property.is_json() => {
+                    let mut json = match self
+                        .get(&property)
+                        .or_else(|| current_fields.and_then(|f| f.get(&property)))
+                    {
+                        Some(Value::Json { value }) => serde_json::from_str::<JsonValue>(value)
+                            .unwrap_or_else(|_| JsonValue::Object(Default::default())),
+                        _ => JsonValue::Object(Default::default()),
+                    };
+                    for (path, value) in patches {
+                        if !apply_patch(
+                            &mut json,
+                            &path,
+                            serde_json::from_str(&value).unwrap_or(JsonValue::Null),
+                        ) {
+                            return Err(SetError::new(
+                                SetErrorType::InvalidPatch,
+                                format!("Failed to patch {}/{}.", property, path.join("/")),
+                            ));
+                        }
+                    }
+                    Value::Json {
+                        value: json.to_string(),
+                    }
+                }
+                (_, _) => {
+                    return Err(SetError::invalid_property(
+                        property,
+                        "Field could not be set.",
+                    ));
+                }
+            };
+
+            self.set(property, value);
+        }
+
+        // Contacts have to belong to at least one address book
+        if !self.has_tags(&Property::AddressBookIds) {
+            return Err(SetError::invalid_property(
+                Property::AddressBookIds,
+                "Contact has to belong to at least one address book.",
+            ));
+        }
+
+        // Set server defaults
+        let now = JMAPDate::from_timestamp(
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0) as i64,
+        );
+        if current_fields.is_none() {
+            if !self.has_property(&Property::Uid) {
+                self.set(Property::Uid, Value::Text { value: new_uid() });
+            }
+            if !self.has_property(&Property::Created) {
+                self.set(Property::Created, Value::Date { value: now.clone() });
+            }
+        }
+        if !self.has_property(&Property::Updated) {
+            self.set(Property::Updated, Value::Date { value: now });
+        }
+
+        build_index(&mut self, current_fields);
+
+        Ok(self)
+    }
+}
+
+// Applies a JMAP patch to a structured JSContact property, all the path
+// components but the last one have to exist.
+fn apply_patch(json: &mut JsonValue, path: &[String], value: JsonValue) -> bool {
+    let (last, path) = if let Some(item) = path.split_last() {
+        item
+    } else {
+        return false;
+    };
+    let mut json = json;
+    for item in path {
+        json = match json {
+            JsonValue::Object(map) => {
+                if let Some(json) = map.get_mut(item) {
+                    json
+                } else {
+                    return false;
+                }
+            }
+            JsonValue::Array(list) => {
+                if let Some(json) = item.parse::<usize>().ok().and_then(|i| list.get_mut(i)) {
+                    json
+                } else {
+                    return false;
+                }
+            }
+            _ => return false,
+        };
+    }
+
+    match json {
+        JsonValue::Object(map) => {
+            if !value.is_null() {
+                map.insert(last.to_string(), value);
+            } else {
+                map.remove(last);
+            }
+            true
+        }
+        JsonValue::Array(list) => {
+            if let Some(item) = last.parse::<usize>().ok().and_then(|i| list.get_mut(i)) {
+                *item = value;
+                true
+            } else {
+                false
+            }
+        }
+        _ => false,
+    }
+}
+
+fn new_uid() -> String {
+    let mut uuid = rand::random::<u128>();
+    uuid = (uuid & !(0xf000 << 64)) | (0x4000 << 64); // Version 4
+    uuid = (uuid & !(0xc000 << 48)) | (0x8000 << 48); // RFC 4122 variant
+    format!(
+        "urn:uuid:{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+        (uuid >> 96) as u32,
+        (uuid >> 80) as u16,
+        (uuid >> 64) as u16,
+        (uuid >> 48) as u16,
+        uuid & 0xffff_ffff_ffff
+    )
+}
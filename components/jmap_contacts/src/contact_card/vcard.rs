@@ -0,0 +1,771 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use serde_json::{json, Map, Value as JsonValue};
+
+// Properties that are populated from a vCard. When a card is replaced with
+// a new vCard, any of these that are missing from it are removed.
+pub const VCARD_PROPERTIES: [&str; 12] = [
+    "kind",
+    "name",
+    "nicknames",
+    "organizations",
+    "titles",
+    "emails",
+    "phones",
+    "addresses",
+    "links",
+    "anniversaries",
+    "keywords",
+    "notes",
+];
+
+#[derive(Debug)]
+struct ContentLine {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+// Converts a vCard (versions 3.0 and 4.0) to a JSContact card. Properties
+// without a JSContact counterpart are ignored.
+pub fn parse_vcard(text: &str) -> Option<Map<String, JsonValue>> {
+    let mut lines = unfold(text)
+        .into_iter()
+        .filter_map(|line| ContentLine::parse(&line));
+    if !lines.next()?.is_begin() {
+        return None;
+    }
+
+    let mut card = Map::new();
+    let mut name_components = Vec::new();
+    let mut nicknames = Map::new();
+    let mut organizations = Map::new();
+    let mut titles = Map::new();
+    let mut emails = Map::new();
+    let mut phones = Map::new();
+    let mut addresses = Map::new();
+    let mut links = Map::new();
+    let mut anniversaries = Map::new();
+    let mut keywords = Map::new();
+    let mut notes = Map::new();
+    card.insert("@type".to_string(), "Card".into());
+
+    for line in lines {
+        match line.name.as_str() {
+            "END" => break,
+            "UID" => {
+                card.insert("uid".to_string(), line.value.trim().into());
+            }
+            "KIND" | "X-ADDRESSBOOKSERVER-KIND" => {
+                card.insert("kind".to_string(), line.value.trim().to_lowercase().into());
+            }
+            "FN" => {
+                let full = unescape(&line.value);
+                if !full.trim().is_empty() {
+                    card.insert("name".to_string(), json!({"@type": "Name", "full": full}));
+                }
+            }
+            "N" => {
+                for (pos, values) in split_components(&line.value).into_iter().enumerate() {
+                    let kind = match pos {
+                        0 => "surname",
+                        1 => "given",
+                        2 => "given2",
+                        3 => "title",
+                        4 => "credential",
+                        _ => continue,
+                    };
+                    for value in values {
+                        name_components.push(json!({
+                            "@type": "NameComponent",
+                            "kind": kind,
+                            "value": value
+                        }));
+                    }
+                }
+            }
+            "NICKNAME" => {
+                for name in split_list(&line.value) {
+                    insert_item(&mut nicknames, json!({"@type": "Nickname", "name": name}));
+                }
+            }
+            "ORG" => {
+                let mut components = split_components(&line.value).into_iter().flatten();
+                if let Some(name) = components.next() {
+                    let units = components
+                        .map(|name| json!({"@type": "OrgUnit", "name": name}))
+                        .collect::<Vec<_>>();
+                    let mut organization = json!({"@type": "Organization", "name": name});
+                    if !units.is_empty() {
+                        organization["units"] = units.into();
+                    }
+                    insert_item(&mut organizations, organization);
+                }
+            }
+            "TITLE" | "ROLE" => {
+                insert_item(
+                    &mut titles,
+                    json!({
+                        "@type": "Title",
+                        "kind": if line.name == "TITLE" { "title" } else { "role" },
+                        "name": unescape(&line.value)
+                    }),
+                );
+            }
+            "EMAIL" => {
+                let mut email = line.to_item("EmailAddress");
+                email.insert("address".to_string(), line.value.trim().into());
+                insert_item(&mut emails, email.into());
+            }
+            "TEL" => {
+                let mut phone = line.to_item("Phone");
+                let features = line
+                    .types()
+                    .into_iter()
+                    .filter_map(|t| match t.as_str() {
+                        "cell" => Some("mobile".to_string()),
+                        "voice" | "fax" | "text" | "video" | "pager" | "textphone" => Some(t),
+                        _ => None,
+                    })
+                    .map(|feature| (feature, true.into()))
+                    .collect::<Map<_, _>>();
+                if !features.is_empty() {
+                    phone.insert("features".to_string(), features.into());
+                }
+                let number = line.value.trim();
+                let number = number.strip_prefix("tel:").unwrap_or(number);
+                phone.insert("number".to_string(), number.into());
+                insert_item(&mut phones, phone.into());
+            }
+            "ADR" => {
+                let mut address = line.to_item("Address");
+                let mut components = Vec::new();
+                for (pos, values) in split_components(&line.value).into_iter().enumerate() {
+                    let kind = match pos {
+                        0 => "postOfficeBox",
+                        1 => "apartment",
+                        2 => "name",
+                        3 => "locality",
+                        4 => "region",
+                        5 => "postcode",
+                        6 => "country",
+                        _ => continue,
+                    };
+                    for value in values {
+                        components.push(json!({
+                            "@type": "AddressComponent",
+                            "kind": kind,
+                            "value": value
+                        }));
+                    }
+                }
+                if let Some(label) = line.param("LABEL") {
+                    address.insert("full".to_string(), unescape(label).into());
+                }
+                if !components.is_empty() || address.contains_key("full") {
+                    address.insert("components".to_string(), components.into());
+                    insert_item(&mut addresses, address.into());
+                }
+            }
+            "URL" => {
+                let mut link = line.to_item("Link");
+                link.insert("uri".to_string(), line.value.trim().into());
+                insert_item(&mut links, link.into());
+            }
+            "BDAY" | "ANNIVERSARY" => {
+                if let Some(date) = parse_date(&line.value) {
+                    insert_item(
+                        &mut anniversaries,
+                        json!({
+                            "@type": "Anniversary",
+                            "kind": if line.name == "BDAY" { "birth" } else { "wedding" },
+                            "date": date
+                        }),
+                    );
+                }
+            }
+            "CATEGORIES" => {
+                for keyword in split_list(&line.value) {
+                    keywords.insert(keyword, true.into());
+                }
+            }
+            "NOTE" => {
+                insert_item(
+                    &mut notes,
+                    json!({"@type": "Note", "note": unescape(&line.value)}),
+                );
+            }
+            _ => (),
+        }
+    }
+
+    if !name_components.is_empty() {
+        let name = card
+            .entry("name")
+            .or_insert_with(|| json!({"@type": "Name"}));
+        name["components"] = name_components.into();
+    }
+    for (property, value) in [
+        ("nicknames", nicknames),
+        ("organizations", organizations),
+        ("titles", titles),
+        ("emails", emails),
+        ("phones", phones),
+        ("addresses", addresses),
+        ("links", links),
+        ("anniversaries", anniversaries),
+        ("keywords", keywords),
+        ("notes", notes),
+    ] {
+        if !value.is_empty() {
+            card.insert(property.to_string(), value.into());
+        }
+    }
+
+    if card.contains_key("uid") {
+        Some(card)
+    } else {
+        None
+    }
+}
+
+// Converts a JSContact card to a vCard 3.0, which is the version most DAV
+// clients expect.
+pub fn build_vcard(card: &Map<String, JsonValue>) -> Option<String> {
+    let mut vcard = String::with_capacity(512);
+    vcard.push_str(
+        "BEGIN:VCARD\r\nVERSION:3.0\r\nPRODID:-//Stalwart Labs Ltd.//JMAP Server//EN\r\n",
+    );
+    write_line(&mut vcard, "UID", &[], card.get("uid")?.as_str()?);
+    if let Some(kind @ ("group" | "org")) = card.get("kind").and_then(|v| v.as_str()) {
+        write_line(&mut vcard, "X-ADDRESSBOOKSERVER-KIND", &[], kind);
+    }
+
+    // FN and N are mandatory in vCard 3.0
+    let name = card.get("name");
+    let name_components = name
+        .and_then(|name| name.get("components"))
+        .and_then(|v| v.as_array())
+        .map(|v| v.as_slice())
+        .unwrap_or_default();
+    let mut n = vec![Vec::new(); 5];
+    for component in name_components {
+        let pos = match component.get("kind").and_then(|v| v.as_str()) {
+            Some("surname") => 0,
+            Some("given") => 1,
+            Some("given2") => 2,
+            Some("title") => 3,
+            Some("credential") => 4,
+            _ => continue,
+        };
+        if let Some(value) = component.get("value").and_then(|v| v.as_str()) {
+            n[pos].push(value);
+        }
+    }
+    let full = name
+        .and_then(|name| name.get("full"))
+        .and_then(|v| v.as_str())
+        .map(|full| full.to_string())
+        .unwrap_or_else(|| {
+            [&n[3], &n[1], &n[2], &n[0], &n[4]]
+                .iter()
+                .flat_map(|v| v.iter())
+                .copied()
+                .collect::<Vec<_>>()
+                .join(" ")
+        });
+    write_line(&mut vcard, "FN", &[], &escape(&full));
+    write_line(&mut vcard, "N", &[], &join_components(&n));
+
+    for nickname in iter_map(card.get("nicknames")) {
+        if let Some(name) = nickname.get("name").and_then(|v| v.as_str()) {
+            write_line(&mut vcard, "NICKNAME", &[], &escape(name));
+        }
+    }
+    for organization in iter_map(card.get("organizations")) {
+        let mut org = vec![vec![organization
+            .get("name")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()]];
+        for unit in organization
+            .get("units")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+        {
+            if let Some(name) = unit.get("name").and_then(|v| v.as_str()) {
+                org.push(vec![name]);
+            }
+        }
+        write_line(&mut vcard, "ORG", &[], &join_components(&org));
+    }
+    for title in iter_map(card.get("titles")) {
+        if let Some(name) = title.get("name").and_then(|v| v.as_str()) {
+            let property = match title.get("kind").and_then(|v| v.as_str()) {
+                Some("role") => "ROLE",
+                _ => "TITLE",
+            };
+            write_line(&mut vcard, property, &[], &escape(name));
+        }
+    }
+    for email in iter_map(card.get("emails")) {
+        if let Some(address) = email.get("address").and_then(|v| v.as_str()) {
+            write_line(
+                &mut vcard,
+                "EMAIL",
+                &item_params(email, &["internet"]),
+                address,
+            );
+        }
+    }
+    for phone in iter_map(card.get("phones")) {
+        if let Some(number) = phone.get("number").and_then(|v| v.as_str()) {
+            let features = phone
+                .get("features")
+                .and_then(|v| v.as_object())
+                .into_iter()
+                .flat_map(|features| features.keys())
+                .map(|feature| match feature.as_str() {
+                    "mobile" => "cell",
+                    feature => feature,
+                })
+                .collect::<Vec<_>>();
+            write_line(&mut vcard, "TEL", &item_params(phone, &features), number);
+        }
+    }
+    for address in iter_map(card.get("addresses")) {
+        let mut adr = vec![Vec::new(); 7];
+        for component in address
+            .get("components")
+            .and_then(|v| v.as_array())
+            .into_iter()
+            .flatten()
+        {
+            let pos = match component.get("kind").and_then(|v| v.as_str()) {
+                Some("postOfficeBox") => 0,
+                Some("apartment") => 1,
+                Some("name" | "number") => 2,
+                Some("locality") => 3,
+                Some("region") => 4,
+                Some("postcode") => 5,
+                Some("country") => 6,
+                _ => continue,
+            };
+            if let Some(value) = component.get("value").and_then(|v| v.as_str()) {
+                adr[pos].push(value);
+            }
+        }
+        let mut params = item_params(address, &[]);
+        if let Some(full) = address.get("full").and_then(|v| v.as_str()) {
+            params.push(("LABEL", format!("\"{}\"", escape(full).replace('"', "'"))));
+        }
+        write_line(&mut vcard, "ADR", &params, &join_components(&adr));
+    }
+    for link in iter_map(card.get("links")) {
+        if let Some(uri) = link.get("uri").and_then(|v| v.as_str()) {
+            write_line(&mut vcard, "URL", &item_params(link, &[]), uri);
+        }
+    }
+    for anniversary in iter_map(card.get("anniversaries")) {
+        let property = match anniversary.get("kind").and_then(|v| v.as_str()) {
+            Some("birth") => "BDAY",
+            Some("wedding") => "ANNIVERSARY",
+            _ => continue,
+        };
+        if let Some(date) = anniversary.get("date").and_then(format_date) {
+            write_line(&mut vcard, property, &[], &date);
+        }
+    }
+    if let Some(keywords) = card.get("keywords").and_then(|v| v.as_object()) {
+        if !keywords.is_empty() {
+            write_line(
+                &mut vcard,
+                "CATEGORIES",
+                &[],
+                &keywords
+                    .keys()
+                    .map(|keyword| escape(keyword))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+        }
+    }
+    for note in iter_map(card.get("notes")) {
+        if let Some(note) = note.get("note").and_then(|v| v.as_str()) {
+            write_line(&mut vcard, "NOTE", &[], &escape(note));
+        }
+    }
+
+    vcard.push_str("END:VCARD\r\n");
+    Some(vcard)
+}
+
+impl ContentLine {
+    fn parse(line: &str) -> Option<Self> {
+        let mut name = String::new();
+        let mut params = Vec::new();
+        let mut chars = line.char_indices().peekable();
+
+        // Property name, without its group prefix
+        let mut delimiter = None;
+        for (_, ch) in &mut chars {
+            match ch {
+                ';' | ':' => {
+                    delimiter = ch.into();
+                    break;
+                }
+                '.' => name.clear(),
+                _ => name.push(ch.to_ascii_uppercase()),
+            }
+        }
+
+        // Parameters, vCard 3.0 allows TYPE values without a parameter name
+        while delimiter == Some(';') {
+            let mut param_name = String::new();
+            let mut param_value = String::new();
+            let mut in_value = false;
+            let mut in_quotes = false;
+            delimiter = None;
+            for (_, ch) in &mut chars {
+                match ch {
+                    '"' if in_value => in_quotes = !in_quotes,
+                    '=' if !in_value => in_value = true,
+                    ';' | ':' if !in_quotes => {
+                        delimiter = ch.into();
+                        break;
+                    }
+                    _ if in_value => param_value.push(ch),
+                    _ => param_name.push(ch.to_ascii_uppercase()),
+                }
+            }
+            if in_value {
+                params.push((param_name, param_value));
+            } else {
+                params.push(("TYPE".to_string(), param_name));
+            }
+        }
+
+        if delimiter == Some(':') && !name.is_empty() {
+            Some(ContentLine {
+                name,
+                params,
+                value: chars
+                    .peek()
+                    .map(|(pos, _)| line[*pos..].to_string())
+                    .unwrap_or_default(),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn is_begin(&self) -> bool {
+        self.name == "BEGIN" && self.value.trim().eq_ignore_ascii_case("VCARD")
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(param, _)| param == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn types(&self) -> Vec<String> {
+        self.params
+            .iter()
+            .filter(|(param, _)| param == "TYPE")
+            .flat_map(|(_, value)| value.split(','))
+            .map(|value| value.trim().to_lowercase())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+
+    fn to_item(&self, item_type: &str) -> Map<String, JsonValue> {
+        let mut item = Map::new();
+        item.insert("@type".to_string(), item_type.into());
+
+        let types = self.types();
+        let contexts = types
+            .iter()
+            .filter_map(|t| match t.as_str() {
+                "home" => Some("private"),
+                "work" => Some("work"),
+                _ => None,
+            })
+            .map(|context| (context.to_string(), true.into()))
+            .collect::<Map<_, _>>();
+        if !contexts.is_empty() {
+            item.insert("contexts".to_string(), contexts.into());
+        }
+        let pref = self
+            .param("PREF")
+            .and_then(|pref| pref.parse::<u64>().ok())
+            .or_else(|| types.iter().any(|t| t == "pref").then_some(1));
+        if let Some(pref) = pref {
+            item.insert("pref".to_string(), pref.into());
+        }
+        item
+    }
+}
+
+fn item_params<'x>(item: &'x JsonValue, types: &[&'x str]) -> Vec<(&'static str, String)> {
+    let mut item_types = types.to_vec();
+    for context in item
+        .get("contexts")
+        .and_then(|v| v.as_object())
+        .into_iter()
+        .flat_map(|contexts| contexts.keys())
+    {
+        item_types.push(match context.as_str() {
+            "private" => "home",
+            context => context,
+        });
+    }
+    if item.get("pref").and_then(|v| v.as_u64()) == Some(1) {
+        item_types.push("pref");
+    }
+    if !item_types.is_empty() {
+        vec![("TYPE", item_types.join(",").to_uppercase())]
+    } else {
+        Vec::new()
+    }
+}
+
+fn insert_item(items: &mut Map<String, JsonValue>, item: JsonValue) {
+    items.insert((items.len() + 1).to_string(), item);
+}
+
+fn iter_map(value: Option<&JsonValue>) -> impl Iterator<Item = &JsonValue> {
+    value
+        .and_then(|value| value.as_object())
+        .into_iter()
+        .flat_map(|map| map.values())
+}
+
+// Dates are YYYYMMDD or YYYY-MM-DD, or --MMDD when the year is unknown.
+fn parse_date(value: &str) -> Option<JsonValue> {
+    let value = value.trim().split('T').next()?.replace('-', "");
+    let (year, month_day) = match value.len() {
+        8 => (value.get(..4)?.parse::<u64>().ok(), value.get(4..)?),
+        4 => (None, value.as_str()),
+        _ => return None,
+    };
+    let mut date = json!({
+        "@type": "PartialDate",
+        "month": month_day.get(..2)?.parse::<u64>().ok()?,
+        "day": month_day.get(2..)?.parse::<u64>().ok()?,
+    });
+    if let Some(year) = year {
+        date["year"] = year.into();
+    }
+    Some(date)
+}
+
+fn format_date(date: &JsonValue) -> Option<String> {
+    let month = date.get("month")?.as_u64()?;
+    let day = date.get("day")?.as_u64()?;
+    Some(match date.get("year").and_then(|v| v.as_u64()) {
+        Some(year) => format!("{:04}-{:02}-{:02}", year, month, day),
+        None => format!("--{:02}{:02}", month, day),
+    })
+}
+
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some(continuation) = line.strip_prefix(' ').or_else(|| line.strip_prefix('\t')) {
+            if let Some(last) = lines.last_mut() {
+                last.push_str(continuation);
+                continue;
+            }
+        }
+        if !line.is_empty() {
+            lines.push(line.to_string());
+        }
+    }
+    lines
+}
+
+fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            match chars.next() {
+                Some('n' | 'N') => result.push('\n'),
+                Some(ch) => result.push(ch),
+                None => (),
+            }
+        } else {
+            result.push(ch);
+        }
+    }
+    result
+}
+
+fn escape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '\\' | ';' | ',' => {
+                result.push('\\');
+                result.push(ch);
+            }
+            '\n' => result.push_str("\\n"),
+            '\r' => (),
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    split_components(value).into_iter().flatten().collect()
+}
+
+// Splits a structured value such as N or ADR into its ';' separated
+// components, each of them holding a ',' separated list of values.
+fn split_components(value: &str) -> Vec<Vec<String>> {
+    let mut components = vec![Vec::new()];
+    let mut item = String::new();
+    let mut chars = value.chars();
+    loop {
+        let ch = chars.next();
+        match ch {
+            Some('\\') => match chars.next() {
+                Some('n' | 'N') => item.push('\n'),
+                Some(ch) => item.push(ch),
+                None => (),
+            },
+            Some(',' | ';') | None => {
+                let component = components.last_mut().unwrap();
+                if !item.trim().is_empty() {
+                    component.push(item.trim().to_string());
+                }
+                item.clear();
+                match ch {
+                    Some(';') => components.push(Vec::new()),
+                    None => break,
+                    _ => (),
+                }
+            }
+            Some(ch) => item.push(ch),
+        }
+    }
+    components
+}
+
+fn join_components(components: &[Vec<&str>]) -> String {
+    components
+        .iter()
+        .map(|values| {
+            values
+                .iter()
+                .map(|value| escape(value))
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+// Lines are folded at 75 octets as required by RFC 6350.
+fn write_line(vcard: &mut String, name: &str, params: &[(&str, String)], value: &str) {
+    let mut line = String::with_capacity(name.len() + value.len() + 1);
+    line.push_str(name);
+    for (param, value) in params {
+        line.push(';');
+        line.push_str(param);
+        line.push('=');
+        line.push_str(value);
+    }
+    line.push(':');
+    line.push_str(value);
+
+    let mut line_len = 0;
+    for ch in line.chars() {
+        if line_len + ch.len_utf8() > 75 {
+            vcard.push_str("\r\n ");
+            line_len = 1;
+        }
+        vcard.push(ch);
+        line_len += ch.len_utf8();
+    }
+    vcard.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_vcard, parse_vcard};
+
+    const VCARD: &str = concat!(
+        "BEGIN:VCARD\r\n",
+        "VERSION:4.0\r\n",
+        "UID:urn:uuid:4fbe8971-0bc3-424c-9c26-36c3e1eff6b1\r\n",
+        "FN:Dr. John Doe\r\n",
+        "N:Doe;John;;Dr.;\r\n",
+        "NICKNAME:Johnny,JD\r\n",
+        "ORG:Example\\, Inc.;Marketing\r\n",
+        "item1.EMAIL;TYPE=work;PREF=1:john@example.org\r\n",
+        "TEL;TYPE=cell,home:+1-555-555-5555\r\n",
+        "ADR;TYPE=home:;;123 Main St;Springfield;IL;62701;USA\r\n",
+        "BDAY:1980-01-15\r\n",
+        "CATEGORIES:friends,work\r\n",
+        "NOTE:Met at the\\nconference\r\n",
+        "END:VCARD\r\n"
+    );
+
+    #[test]
+    fn parse() {
+        let card = parse_vcard(VCARD).unwrap();
+        assert_eq!(card["uid"], "urn:uuid:4fbe8971-0bc3-424c-9c26-36c3e1eff6b1");
+        assert_eq!(card["name"]["full"], "Dr. John Doe");
+        assert_eq!(card["name"]["components"][0]["kind"], "surname");
+        assert_eq!(card["nicknames"]["2"]["name"], "JD");
+        assert_eq!(card["organizations"]["1"]["name"], "Example, Inc.");
+        assert_eq!(card["organizations"]["1"]["units"][0]["name"], "Marketing");
+        assert_eq!(card["emails"]["1"]["address"], "john@example.org");
+        assert_eq!(card["emails"]["1"]["contexts"]["work"], true);
+        assert_eq!(card["emails"]["1"]["pref"], 1);
+        assert_eq!(card["phones"]["1"]["features"]["mobile"], true);
+        assert_eq!(card["phones"]["1"]["contexts"]["private"], true);
+        assert_eq!(card["addresses"]["1"]["components"][3]["value"], "62701");
+        assert_eq!(card["anniversaries"]["1"]["date"]["year"], 1980);
+        assert_eq!(card["keywords"]["friends"], true);
+        assert_eq!(card["notes"]["1"]["note"], "Met at the\nconference");
+    }
+
+    #[test]
+    fn build() {
+        let card = parse_vcard(VCARD).unwrap();
+        let vcard = build_vcard(&card).unwrap();
+        assert!(vcard.contains("N:Doe;John;;Dr.;\r\n"));
+        assert!(vcard.contains("ORG:Example\\, Inc.;Marketing\r\n"));
+        assert!(vcard.contains("EMAIL;TYPE=INTERNET,WORK,PREF:john@example.org\r\n"));
+        assert!(vcard.contains("TEL;TYPE=CELL,HOME:+1-555-555-5555\r\n"));
+        assert!(vcard.contains("BDAY:1980-01-15\r\n"));
+        assert_eq!(parse_vcard(&vcard).unwrap(), card);
+    }
+}
@@ -24,28 +24,55 @@
 use super::schema::{Address, EmailSubmission, Envelope, Property, Value};
 use crate::identity;
 use crate::identity::schema::Identity;
-use crate::mail::schema::Email;
+use crate::mail::import::JMAPMailImport;
+use crate::mail::schema::{Email, Keyword};
+use crate::mail::sharing::JMAPShareMail;
 use crate::mail::{MessageData, MessageField};
+use crate::mailbox;
 use jmap::error::set::{SetError, SetErrorType};
 use jmap::jmap_store::set::SetHelper;
 use jmap::jmap_store::Object;
 use jmap::orm::{serialize::JMAPOrm, TinyORM};
+use jmap::principal::schema::{Principal, Property as PrincipalProperty, Value as PrincipalValue};
 use jmap::request::set::SetResponse;
-use jmap::request::{MaybeIdReference, MaybeResultReference, ResultReference};
+use jmap::request::{ACLEnforce, MaybeIdReference, MaybeResultReference, ResultReference};
 use jmap::types::date::JMAPDate;
 use jmap::types::jmap::JMAPId;
-use jmap::{jmap_store::set::SetObject, request::set::SetRequest};
+use jmap::types::type_state::TypeState;
+use jmap::{jmap_store::set::SetObject, request::set::SetRequest, SUPERUSER_ID};
 use mail_parser::RfcHeader;
 use std::time::SystemTime;
 use store::ahash::{AHashMap, AHashSet};
 use store::blob::BlobId;
+use store::core::acl::ACL;
 use store::core::collection::Collection;
 use store::core::document::Document;
 use store::core::error::StoreError;
+use store::core::tag::Tag;
 use store::core::vec_map::VecMap;
+use store::log::changes::ChangeId;
+use store::read::comparator::Comparator;
+use store::read::filter::{Filter, Query};
+use store::read::FilterMapper;
 use store::serialize::{StoreDeserialize, StoreSerialize};
+use store::tracing::{debug, error};
 use store::write::options::{IndexOptions, Options};
-use store::{AccountId, JMAPStore, Store};
+use store::{AccountId, DocumentId, JMAPStore, SharedBitmap, Store};
+
+// How a delegate is allowed to send using an identity shared with them.
+enum Delegation {
+    SendAs,
+    OnBehalf { name: Option<String>, email: String },
+}
+
+// A delegated submission whose sent copy is filed in the delegate's account.
+pub struct SentCopy {
+    create_id: String,
+    account_id: AccountId,
+    mailbox_id: DocumentId,
+    blob_id: BlobId,
+    email_id: JMAPId,
+}
 
 #[derive(Debug, Clone, Default)]
 pub struct SetArguments {
@@ -98,6 +125,8 @@ where
         account_id: AccountId,
         document: &mut Document,
     ) -> store::Result<()>;
+
+    fn email_submission_file_copy(&self, sent_copy: &SentCopy) -> jmap::Result<Option<ChangeId>>;
 }
 
 impl<T> JMAPSetEmailSubmission<T> for JMAPStore<T>
@@ -124,6 +153,7 @@ where
                 .map_or(false, |p| !p.is_empty());
         let mut update_emails: VecMap<JMAPId, Email> = VecMap::new();
         let mut destroy_emails: Vec<JMAPId> = Vec::new();
+        let mut sent_copies: Vec<SentCopy> = Vec::new();
 
         helper.create(|create_id, item, helper, document| {
             let mut fields = TinyORM::<EmailSubmission>::new();
@@ -170,6 +200,46 @@ where
                 fields.set(property, value);
             }
 
+            // Delegates need to be granted permission to send using this identity,
+            // "submit" lets them send as the owner, as it did before identities
+            // could be shared, while "sendOnBehalf" adds them as the Sender.
+            let delegation = if helper.acl.is_shared(helper.account_id) {
+                let rights = helper.store.get_acl(
+                    &helper.acl.member_of,
+                    helper.account_id,
+                    Collection::Identity,
+                    identity_id,
+                )?;
+                if rights.contains(ACL::Submit) {
+                    Some(Delegation::SendAs)
+                } else if rights.contains(ACL::SendOnBehalf) {
+                    let mut principal = helper
+                        .store
+                        .get_orm::<Principal>(SUPERUSER_ID, helper.acl.primary_id())?
+                        .ok_or_else(|| SetError::forbidden("Delegate account not found."))?;
+                    let name = match principal.remove(&PrincipalProperty::Name) {
+                        Some(PrincipalValue::Text { value }) => Some(value),
+                        _ => None,
+                    };
+                    match principal.remove(&PrincipalProperty::Email) {
+                        Some(PrincipalValue::Text { value }) => {
+                            Some(Delegation::OnBehalf { name, email: value })
+                        }
+                        _ => {
+                            return Err(SetError::forbidden(
+                                "Delegate account does not have an e-mail address.",
+                            ));
+                        }
+                    }
+                } else {
+                    return Err(SetError::forbidden(
+                        "You are not allowed to send using this identity.",
+                    ));
+                }
+            } else {
+                None
+            };
+
             // Fetch mailFrom
             let mail_from = helper
                 .store
@@ -276,6 +346,74 @@ where
                 ))
             })?;
 
+            // Validate the From and Sender headers of delegated submissions
+            if let Some(delegation) = &delegation {
+                if !helper
+                    .store
+                    .mail_shared_messages(helper.account_id, &helper.acl.member_of, ACL::ReadItems)?
+                    .has_access(email_id.get_document_id())
+                {
+                    return Err(SetError::invalid_property(
+                        Property::EmailId,
+                        "Email not found.",
+                    ));
+                }
+
+                let from = header_addresses(&mut message_data, RfcHeader::From);
+                if !from
+                    .iter()
+                    .any(|addr| addr.eq_ignore_ascii_case(&mail_from))
+                {
+                    return Err(SetError::invalid_property(
+                        Property::EmailId,
+                        format!("The From header does not contain {}.", mail_from),
+                    ));
+                }
+                let sender = header_addresses(&mut message_data, RfcHeader::Sender);
+
+                match delegation {
+                    Delegation::SendAs => {
+                        if sender
+                            .iter()
+                            .any(|addr| !addr.eq_ignore_ascii_case(&mail_from))
+                        {
+                            return Err(SetError::invalid_property(
+                                Property::EmailId,
+                                format!("The Sender header must be empty or {}.", mail_from),
+                            ));
+                        }
+                    }
+                    Delegation::OnBehalf { name, email } => {
+                        if sender.is_empty() {
+                            // Add a Sender header identifying the delegate
+                            let mut raw_message =
+                                format!("Sender: {}\r\n", format_address(name.as_deref(), email))
+                                    .into_bytes();
+                            raw_message.extend_from_slice(
+                                &helper
+                                    .store
+                                    .blob_get(&message_data.raw_message)?
+                                    .ok_or_else(|| {
+                                        StoreError::NotFound(format!(
+                                            "Raw message for {}:{} not found.",
+                                            helper.account_id,
+                                            email_id.get_document_id()
+                                        ))
+                                    })?,
+                            );
+                            let blob_id = BlobId::new_external(&raw_message);
+                            helper.store.blob_store(&blob_id, raw_message)?;
+                            message_data.raw_message = blob_id;
+                        } else if sender.len() > 1 || !sender[0].eq_ignore_ascii_case(email) {
+                            return Err(SetError::invalid_property(
+                                Property::EmailId,
+                                format!("The Sender header must be {}.", email),
+                            ));
+                        }
+                    }
+                }
+            }
+
             // Obtain recipients from e-mail if missing
             if envelope.rcpt_to.is_empty() {
                 let mut rcpt_to = AHashSet::default();
@@ -316,6 +454,39 @@ where
                     .collect::<Vec<_>>();
             }
 
+            // File the sent copy in the delegate's own Sent mailbox, if they have
+            // one. The copy is only stored once the submission is.
+            if delegation.is_some() && helper.store.config.submission_sent_to_delegate {
+                let delegate_id = helper.acl.primary_id();
+                if let Some(sent_id) = helper
+                    .store
+                    .query_store::<FilterMapper>(
+                        delegate_id,
+                        Collection::Mailbox,
+                        Filter::eq(
+                            mailbox::schema::Property::Role.into(),
+                            Query::Keyword("sent".to_string()),
+                        ),
+                        Comparator::None,
+                    )?
+                    .into_iter()
+                    .next()
+                {
+                    sent_copies.push(SentCopy {
+                        create_id: create_id.to_string(),
+                        account_id: delegate_id,
+                        mailbox_id: sent_id.get_document_id(),
+                        blob_id: message_data.raw_message.clone(),
+                        email_id,
+                    });
+                } else {
+                    debug!(
+                        "Account {} has no Sent mailbox, keeping the sent copy in account {}.",
+                        delegate_id, helper.account_id
+                    );
+                }
+            }
+
             // Add and link blob
             document.binary(
                 Property::EmailId,
@@ -349,6 +520,7 @@ where
                     .on_success_destroy_email
                     .as_ref()
                     .map_or(false, |p| p.contains(&id_ref))
+                    && !destroy_emails.contains(&email_id)
                 {
                     destroy_emails.push(email_id);
                 }
@@ -358,6 +530,12 @@ where
         })?;
 
         helper.update(|id, mut item, helper, document| {
+            if helper.acl.is_shared(helper.account_id) {
+                return Err(SetError::forbidden(
+                    "Delegates cannot modify submissions once sent.",
+                ));
+            }
+
             // Only undoStatus can be changed
             if let Some(Value::UndoStatus { value }) = item.properties.remove(&Property::UndoStatus)
             {
@@ -376,35 +554,88 @@ where
         })?;
 
         helper.destroy(|_id, helper, document| {
+            if helper.acl.is_shared(helper.account_id) {
+                return Err(SetError::forbidden("Delegates cannot destroy submissions."));
+            }
             self.email_submission_delete(helper.account_id, document)
                 .map_err(|err| err.into())
         })?;
 
         let account_id = JMAPId::from(helper.account_id);
         let acl = helper.acl.clone();
-        helper.into_response().map(|mut r| {
-            if has_on_success && (!update_emails.is_empty() || !destroy_emails.is_empty()) {
-                r.next_call = SetRequest {
-                    acl: acl.into(),
-                    account_id,
-                    if_in_state: None,
-                    create: None,
-                    update: if !update_emails.is_empty() {
-                        update_emails.into()
-                    } else {
-                        None
-                    },
-                    destroy: if !destroy_emails.is_empty() {
-                        MaybeResultReference::Value(destroy_emails).into()
-                    } else {
-                        None
-                    },
-                    arguments: (),
+        let mut response = helper.into_response()?;
+
+        // Move the sent copies of the stored submissions to the delegates'
+        // accounts, the owner's copy is kept if this fails.
+        for sent_copy in sent_copies {
+            if !response.created.contains_key(&sent_copy.create_id) {
+                continue;
+            }
+            match self.email_submission_file_copy(&sent_copy) {
+                Ok(change_id) => {
+                    if let Some(change_id) = change_id {
+                        response.linked_state_changes.push((
+                            sent_copy.account_id,
+                            vec![
+                                (TypeState::Email, change_id),
+                                (TypeState::Mailbox, change_id),
+                                (TypeState::Thread, change_id),
+                            ],
+                        ));
+                    }
+                    update_emails.remove(&sent_copy.email_id);
+                    if !destroy_emails.contains(&sent_copy.email_id) {
+                        destroy_emails.push(sent_copy.email_id);
+                    }
+                }
+                Err(err) => {
+                    error!(
+                        "Failed to file the sent copy of {} in account {}: {}",
+                        sent_copy.create_id, sent_copy.account_id, err
+                    );
                 }
-                .into();
             }
-            r
-        })
+        }
+
+        if !update_emails.is_empty() || !destroy_emails.is_empty() {
+            response.next_call = SetRequest {
+                acl: acl.into(),
+                account_id,
+                if_in_state: None,
+                create: None,
+                update: if !update_emails.is_empty() {
+                    update_emails.into()
+                } else {
+                    None
+                },
+                destroy: if !destroy_emails.is_empty() {
+                    MaybeResultReference::Value(destroy_emails).into()
+                } else {
+                    None
+                },
+                arguments: (),
+            }
+            .into();
+        }
+        Ok(response)
+    }
+
+    fn email_submission_file_copy(&self, sent_copy: &SentCopy) -> jmap::Result<Option<ChangeId>> {
+        let raw_message = self.blob_get(&sent_copy.blob_id)?.ok_or_else(|| {
+            StoreError::NotFound(format!(
+                "Raw message for submission {} not found.",
+                sent_copy.create_id
+            ))
+        })?;
+        self.mail_import_item(
+            sent_copy.account_id,
+            BlobId::new_external(&raw_message),
+            &raw_message,
+            vec![sent_copy.mailbox_id],
+            vec![Tag::Static(Keyword::SEEN)],
+            None,
+        )?;
+        Ok(self.get_last_change_id(sent_copy.account_id, Collection::Mail)?)
     }
 
     fn email_submission_delete(
@@ -449,3 +680,29 @@ where
         }
     }
 }
+
+fn header_addresses(message_data: &mut MessageData, header: RfcHeader) -> Vec<String> {
+    message_data
+        .headers
+        .remove(&header)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|value| value.into_addresses())
+        .flatten()
+        .map(|addr| addr.email.trim().to_string())
+        .collect()
+}
+
+fn format_address(name: Option<&str>, email: &str) -> String {
+    match name
+        .map(|name| {
+            name.chars()
+                .filter(|ch| !matches!(ch, '"' | '\\' | '\r' | '\n'))
+                .collect::<String>()
+        })
+        .filter(|name| !name.trim().is_empty())
+    {
+        Some(name) => format!("\"{}\" <{}>", name.trim(), email),
+        None => format!("<{}>", email),
+    }
+}
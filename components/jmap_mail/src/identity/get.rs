@@ -21,17 +21,22 @@
  * for more details.
 */
 
-use jmap::jmap_store::get::{default_mapper, GetHelper, GetObject, SharedDocsFnc};
+use jmap::jmap_store::get::{default_mapper, GetHelper, GetObject};
 use jmap::orm::serialize::JMAPOrm;
+use jmap::principal::store::JMAPPrincipals;
 use jmap::request::get::{GetRequest, GetResponse};
+use jmap::request::ACLEnforce;
 use jmap::types::jmap::JMAPId;
 
+use store::core::acl::ACL;
+use store::core::collection::Collection;
 use store::core::error::StoreError;
 use store::core::vec_map::VecMap;
 use store::JMAPStore;
-use store::Store;
+use store::{AccountId, DocumentId, Store};
 
-use super::schema::{Identity, Property, Value};
+use super::schema::{Identity, IdentityRights, Property, Value};
+use super::sharing::JMAPShareIdentity;
 
 impl GetObject for Identity {
     type GetArguments = ();
@@ -46,6 +51,7 @@ impl GetObject for Identity {
             Property::TextSignature,
             Property::HtmlSignature,
             Property::MayDelete,
+            Property::MyRights,
         ]
     }
 
@@ -59,6 +65,12 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     fn identity_get(&self, request: GetRequest<Identity>) -> jmap::Result<GetResponse<Identity>>;
+
+    fn identity_find(
+        &self,
+        account_id: AccountId,
+        email: &str,
+    ) -> store::Result<Option<DocumentId>>;
 }
 
 impl<T> JMAPGetIdentity<T> for JMAPStore<T>
@@ -66,9 +78,17 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     fn identity_get(&self, request: GetRequest<Identity>) -> jmap::Result<GetResponse<Identity>> {
-        let mut helper =
-            GetHelper::new(self, request, default_mapper.into(), None::<SharedDocsFnc>)?;
+        let mut helper = GetHelper::new(
+            self,
+            request,
+            default_mapper.into(),
+            (|account_id: AccountId, member_of: &[AccountId]| {
+                self.identity_shared(account_id, member_of, ACL::Read)
+            })
+            .into(),
+        )?;
         let account_id = helper.account_id;
+        let acl = helper.acl.clone();
 
         // Add Id Property
         if !helper.properties.contains(&Property::Id) {
@@ -87,7 +107,31 @@ where
                     *property,
                     match property {
                         Property::Id => Value::Id { value: id },
-                        Property::MayDelete => Value::Bool { value: true },
+                        Property::MayDelete => Value::Bool {
+                            value: acl.is_member(account_id),
+                        },
+                        Property::MyRights => Value::IdentityRights {
+                            value: if acl.is_shared(account_id) {
+                                IdentityRights::shared(self.get_acl(
+                                    &acl.member_of,
+                                    account_id,
+                                    Collection::Identity,
+                                    document_id,
+                                )?)
+                            } else {
+                                IdentityRights::owner()
+                            },
+                        },
+                        Property::ACL if acl.is_member(account_id) => {
+                            let mut acl_get = VecMap::new();
+                            for (account_id, acls) in fields.get_acls() {
+                                if let Some(email) = self.principal_to_email(account_id)? {
+                                    acl_get.append(email, acls);
+                                }
+                            }
+                            Value::ACLGet(acl_get)
+                        }
+                        Property::ACL => Value::Null,
                         _ => fields.remove(property).unwrap_or_default(),
                     },
                 );
@@ -97,4 +141,26 @@ where
             }))
         })
     }
+
+    fn identity_find(
+        &self,
+        account_id: AccountId,
+        email: &str,
+    ) -> store::Result<Option<DocumentId>> {
+        for document_id in self
+            .get_document_ids(account_id, Collection::Identity)?
+            .unwrap_or_default()
+        {
+            if let Some(Value::Text { value }) = self
+                .get_orm::<Identity>(account_id, document_id)?
+                .and_then(|mut fields| fields.remove(&Property::Email))
+            {
+                if value.eq_ignore_ascii_case(email) {
+                    return Ok(Some(document_id));
+                }
+            }
+        }
+
+        Ok(None)
+    }
 }
@@ -32,6 +32,7 @@ pub mod raft;
 pub mod schema;
 pub mod serialize;
 pub mod set;
+pub mod sharing;
 
 impl Object for Identity {
     type Property = Property;
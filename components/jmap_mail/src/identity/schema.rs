@@ -23,9 +23,15 @@
 
 use std::fmt::Display;
 
-use jmap::{orm, types::jmap::JMAPId};
+use jmap::{
+    orm::{self, acl::ACLUpdate},
+    types::jmap::JMAPId,
+};
 use serde::{Deserialize, Serialize};
-use store::{core::vec_map::VecMap, FieldId};
+use store::{
+    core::{acl::ACL, bitmap::Bitmap, vec_map::VecMap},
+    FieldId,
+};
 
 use crate::mail::schema::EmailAddress;
 
@@ -40,6 +46,9 @@ pub enum Value {
     Text { value: String },
     Bool { value: bool },
     Addresses { value: Vec<EmailAddress> },
+    IdentityRights { value: IdentityRights },
+    ACLSet(Vec<ACLUpdate>),
+    ACLGet(VecMap<String, Vec<ACL>>),
     Null,
 }
 
@@ -70,9 +79,30 @@ impl orm::Value for Value {
             Value::Addresses { value } => value.iter().fold(0, |acc, x| {
                 acc + x.email.len() + x.name.as_ref().map(|n| n.len()).unwrap_or(0)
             }),
+            Value::IdentityRights { .. } => std::mem::size_of::<IdentityRights>(),
+            Value::ACLSet(value) => value.len() * std::mem::size_of::<ACLUpdate>(),
+            Value::ACLGet(value) => value.iter().fold(0, |acc, (k, v)| {
+                acc + k.len() + v.len() * std::mem::size_of::<ACL>()
+            }),
             Value::Null => 0,
         }
     }
+
+    fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text { value } => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct IdentityRights {
+    #[serde(rename = "maySendAs")]
+    may_send_as: bool,
+
+    #[serde(rename = "maySendOnBehalf")]
+    may_send_on_behalf: bool,
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
@@ -86,7 +116,9 @@ pub enum Property {
     TextSignature = 5,
     HtmlSignature = 6,
     MayDelete = 7,
-    Invalid = 8,
+    ACL = 8,
+    MyRights = 9,
+    Invalid = 10,
 }
 
 impl Property {
@@ -100,6 +132,8 @@ impl Property {
             "textSignature" => Property::TextSignature,
             "htmlSignature" => Property::HtmlSignature,
             "mayDelete" => Property::MayDelete,
+            "acl" => Property::ACL,
+            "myRights" => Property::MyRights,
             _ => Property::Invalid,
         }
     }
@@ -116,6 +150,8 @@ impl Display for Property {
             Property::TextSignature => write!(f, "textSignature"),
             Property::HtmlSignature => write!(f, "htmlSignature"),
             Property::MayDelete => write!(f, "mayDelete"),
+            Property::ACL => write!(f, "acl"),
+            Property::MyRights => write!(f, "myRights"),
             Property::Invalid => Ok(()),
         }
     }
@@ -138,6 +174,8 @@ impl From<FieldId> for Property {
             5 => Property::TextSignature,
             6 => Property::HtmlSignature,
             7 => Property::MayDelete,
+            8 => Property::ACL,
+            9 => Property::MyRights,
             _ => Property::Invalid,
         }
     }
@@ -153,3 +191,19 @@ impl TryFrom<&str> for Property {
         }
     }
 }
+
+impl IdentityRights {
+    pub fn owner() -> Self {
+        IdentityRights {
+            may_send_as: true,
+            may_send_on_behalf: true,
+        }
+    }
+
+    pub fn shared(acl: Bitmap<ACL>) -> Self {
+        IdentityRights {
+            may_send_as: acl.contains(ACL::Submit),
+            may_send_on_behalf: acl.contains(ACL::Submit) || acl.contains(ACL::SendOnBehalf),
+        }
+    }
+}
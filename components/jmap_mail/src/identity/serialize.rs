@@ -23,8 +23,9 @@
 
 use std::{borrow::Cow, fmt};
 
+use jmap::{orm::acl::ACLUpdate, types::json_pointer::JSONPointer};
 use serde::{de::IgnoredAny, ser::SerializeMap, Deserialize, Serialize};
-use store::core::vec_map::VecMap;
+use store::core::{acl::ACL, vec_map::VecMap};
 
 use crate::mail::schema::EmailAddress;
 
@@ -79,6 +80,9 @@ impl Serialize for Identity {
                 Value::Text { value } => map.serialize_entry(name, value)?,
                 Value::Bool { value } => map.serialize_entry(name, value)?,
                 Value::Addresses { value } => map.serialize_entry(name, value)?,
+                Value::IdentityRights { value } => map.serialize_entry(name, value)?,
+                Value::ACLGet(value) => map.serialize_entry(name, value)?,
+                Value::ACLSet(_) => (),
                 Value::Null => map.serialize_entry(name, &())?,
             }
         }
@@ -101,6 +105,7 @@ impl<'de> serde::de::Visitor<'de> for IdentityVisitor {
         A: serde::de::MapAccess<'de>,
     {
         let mut properties: VecMap<Property, Value> = VecMap::new();
+        let mut acls = Vec::new();
 
         while let Some(key) = map.next_key::<Cow<str>>()? {
             match key.as_ref() {
@@ -164,12 +169,63 @@ impl<'de> serde::de::Visitor<'de> for IdentityVisitor {
                         },
                     );
                 }
-                _ => {
-                    map.next_value::<IgnoredAny>()?;
+                "acl" => {
+                    acls.push(ACLUpdate::Replace {
+                        acls: map
+                            .next_value::<Option<VecMap<String, Vec<ACL>>>>()?
+                            .unwrap_or_default(),
+                    });
                 }
+                key => match JSONPointer::parse(key) {
+                    Some(JSONPointer::Path(path))
+                        if path.len() >= 2
+                            && path
+                                .get(0)
+                                .and_then(|p| p.to_string())
+                                .map(Property::parse)
+                                .unwrap_or(Property::Invalid)
+                                == Property::ACL =>
+                    {
+                        if let Some(account_id) = path
+                            .get(1)
+                            .and_then(|p| p.to_string())
+                            .map(|p| p.to_string())
+                        {
+                            if path.len() > 2 {
+                                if let Some(acl) =
+                                    path.get(2).and_then(|p| p.to_string()).map(ACL::parse)
+                                {
+                                    if acl != ACL::None_ {
+                                        acls.push(ACLUpdate::Set {
+                                            account_id,
+                                            acl,
+                                            is_set: map
+                                                .next_value::<Option<bool>>()?
+                                                .unwrap_or(false),
+                                        });
+                                    }
+                                }
+                            } else {
+                                acls.push(ACLUpdate::Update {
+                                    account_id,
+                                    acls: map.next_value::<Option<Vec<ACL>>>()?.unwrap_or_default(),
+                                });
+                            }
+                        } else {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                    _ => {
+                        map.next_value::<IgnoredAny>()?;
+                    }
+                },
             }
         }
 
+        if !acls.is_empty() {
+            properties.append(Property::ACL, Value::ACLSet(acls));
+        }
+
         Ok(Identity { properties })
     }
 }
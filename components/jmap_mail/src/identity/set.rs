@@ -25,7 +25,9 @@ use crate::identity::schema::Identity;
 use jmap::error::set::{SetError, SetErrorType};
 use jmap::jmap_store::set::SetHelper;
 use jmap::jmap_store::Object;
+use jmap::orm::acl::ACLUpdate;
 use jmap::orm::{serialize::JMAPOrm, TinyORM};
+use jmap::principal::store::JMAPPrincipals;
 use jmap::request::set::SetResponse;
 use jmap::request::ResultReference;
 use jmap::types::jmap::JMAPId;
@@ -38,7 +40,7 @@ use store::core::JMAPIdPrefix;
 use store::read::comparator::Comparator;
 use store::read::filter::{Filter, Query};
 use store::read::FilterMapper;
-use store::{AccountId, JMAPStore, Store};
+use store::{AccountId, JMAPStore, SharedResource, Store};
 
 use super::schema::{Property, Value};
 
@@ -78,6 +80,10 @@ where
                             Property::Name | Property::TextSignature | Property::HtmlSignature,
                             value @ Value::Text { .. },
                         ) => value,
+                        (Property::ACL, Value::ACLSet(value)) => {
+                            fields.identity_acl_set(helper, value)?;
+                            continue;
+                        }
 
                         (Property::Email, Value::Text { value }) => {
                             let value = sanitize_email(&value).ok_or_else(|| {
@@ -135,6 +141,7 @@ where
             }
 
             // Validate fields
+            fields.identity_acl_finish(helper, None);
             fields.insert_validate(document)?;
 
             Ok(Identity::new(document.document_id.into()))
@@ -154,6 +161,10 @@ where
                             Property::Name | Property::TextSignature | Property::HtmlSignature,
                             value @ Value::Text { .. },
                         ) => value,
+                        (Property::ACL, Value::ACLSet(value)) => {
+                            fields.identity_acl_set(helper, value)?;
+                            continue;
+                        }
 
                         (Property::ReplyTo | Property::Bcc, value @ Value::Addresses { .. }) => {
                             value
@@ -177,6 +188,7 @@ where
             }
 
             // Merge changes
+            fields.identity_acl_finish(helper, Some(&current_fields));
             current_fields.merge_validate(document, fields)?;
             Ok(None)
         })?;
@@ -205,3 +217,80 @@ where
         Ok(())
     }
 }
+
+trait IdentityACL<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn identity_acl_set(
+        &mut self,
+        helper: &SetHelper<Identity, T>,
+        acl_updates: Vec<ACLUpdate>,
+    ) -> jmap::error::set::Result<(), Property>;
+
+    fn identity_acl_finish(
+        &mut self,
+        helper: &mut SetHelper<Identity, T>,
+        current_fields: Option<&TinyORM<Identity>>,
+    );
+}
+
+impl<T> IdentityACL<T> for TinyORM<Identity>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn identity_acl_set(
+        &mut self,
+        helper: &SetHelper<Identity, T>,
+        acl_updates: Vec<ACLUpdate>,
+    ) -> jmap::error::set::Result<(), Property> {
+        for acl_update in acl_updates {
+            match acl_update {
+                ACLUpdate::Replace { acls } => {
+                    self.acl_clear();
+                    for (account_id, acls) in acls {
+                        self.acl_update(helper.store.principal_to_id(&account_id)?, &acls);
+                    }
+                }
+                ACLUpdate::Update { account_id, acls } => {
+                    self.acl_update(helper.store.principal_to_id(&account_id)?, &acls);
+                }
+                ACLUpdate::Set {
+                    account_id,
+                    acl,
+                    is_set,
+                } => {
+                    self.acl_set(helper.store.principal_to_id(&account_id)?, acl, is_set);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn identity_acl_finish(
+        &mut self,
+        helper: &mut SetHelper<Identity, T>,
+        current_fields: Option<&TinyORM<Identity>>,
+    ) {
+        self.acl_finish();
+
+        // Invalidate cache for changed ACLs
+        if let Some(permissions) = self.get_changed_acls(current_fields) {
+            for permission in permissions {
+                helper.store.acl_tokens.invalidate(&permission.id);
+                for acl in permission.acl {
+                    let key = SharedResource::new(
+                        helper.account_id,
+                        permission.id,
+                        Collection::Identity,
+                        acl,
+                    );
+                    helper.store.shared_documents.invalidate(&key);
+                }
+            }
+        }
+
+        // Notify the accounts whose rights were granted, changed or revoked
+        helper.notify_acl_changes(self, current_fields, &[Property::Name, Property::Email]);
+    }
+}
@@ -22,6 +22,7 @@
 */
 
 use jmap::{
+    error::method::MethodError,
     jmap_store::{
         changes::{ChangesObject, JMAPChanges},
         query_changes::QueryChangesHelper,
@@ -33,7 +34,10 @@ use jmap::{
 };
 use store::{JMAPStore, Store};
 
-use super::{query::JMAPMailQuery, schema::Email};
+use super::{
+    query::{has_saved_search_filter, JMAPMailQuery},
+    schema::Email,
+};
 
 impl ChangesObject for Email {
     type ChangesResponse = ();
@@ -59,6 +63,16 @@ where
         &self,
         request: QueryChangesRequest<Email>,
     ) -> jmap::Result<QueryChangesResponse> {
+        // The results of an inSavedSearch filter depend on the saved search
+        // definition, which is not covered by the Email query state.
+        if request
+            .filter
+            .as_ref()
+            .map_or(false, has_saved_search_filter)
+        {
+            return Err(MethodError::CannotCalculateChanges);
+        }
+
         let mut helper = QueryChangesHelper::new(self, request)?;
         let has_changes = helper.has_changes();
 
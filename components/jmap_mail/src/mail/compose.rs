@@ -0,0 +1,370 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::schema::{EmailAddress, EmailTemplate, Property, TemplateType};
+use super::sharing::JMAPShareMail;
+use super::{HeaderValue, MessageData, MessageField, MimePartType};
+use jmap::error::set::{SetError, SetErrorType};
+use jmap::request::ACLEnforce;
+use jmap::types::date::JMAPDate;
+use jmap::types::jmap::JMAPId;
+use mail_parser::decoders::html::html_to_text;
+use mail_parser::RfcHeader;
+use std::sync::Arc;
+use store::blob::BlobId;
+use store::core::acl::{ACLToken, ACL};
+use store::core::collection::Collection;
+use store::core::error::StoreError;
+use store::serialize::StoreDeserialize;
+use store::{AccountId, JMAPStore, SharedBitmap, Store};
+
+pub struct ComposeSource {
+    pub message_data: MessageData,
+    pub raw_message: Vec<u8>,
+}
+
+pub struct ComposePart {
+    pub contents: Vec<u8>,
+    pub type_: String,
+    pub charset: Option<String>,
+    pub name: Option<String>,
+    pub disposition: Option<String>,
+}
+
+#[derive(Default)]
+pub struct ComposeTemplate {
+    pub subject: Option<String>,
+    pub to: Vec<EmailAddress>,
+    pub cc: Vec<EmailAddress>,
+    pub in_reply_to: Vec<String>,
+    pub references: Vec<String>,
+    pub text: String,
+}
+
+pub trait JMAPMailCompose<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_compose_source(
+        &self,
+        account_id: AccountId,
+        acl: &Arc<ACLToken>,
+        email_id: JMAPId,
+    ) -> jmap::error::set::Result<ComposeSource, Property>;
+}
+
+impl<T> JMAPMailCompose<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_compose_source(
+        &self,
+        account_id: AccountId,
+        acl: &Arc<ACLToken>,
+        email_id: JMAPId,
+    ) -> jmap::error::set::Result<ComposeSource, Property> {
+        let document_id = email_id.get_document_id();
+        let metadata_blob_id = self
+            .get_document_value::<BlobId>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::Metadata.into(),
+            )?
+            .filter(|_| {
+                !acl.is_shared(account_id)
+                    || self
+                        .mail_shared_messages(account_id, &acl.member_of, ACL::ReadItems)
+                        .map_or(false, |shared_ids| shared_ids.has_access(document_id))
+            })
+            .ok_or_else(|| {
+                SetError::new(
+                    SetErrorType::NotFound,
+                    format!("Email {} not found.", email_id),
+                )
+            })?;
+
+        let message_data =
+            MessageData::deserialize(&self.blob_get(&metadata_blob_id)?.ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Message data blob for {}:{} not found.",
+                    account_id, document_id
+                ))
+            })?)
+            .ok_or_else(|| {
+                StoreError::DataCorruption(format!(
+                    "Failed to deserialize message data for {}:{}.",
+                    account_id, document_id
+                ))
+            })?;
+        let raw_message = self.blob_get(&message_data.raw_message)?.ok_or_else(|| {
+            StoreError::NotFound(format!(
+                "Raw message for {}:{} not found.",
+                account_id, document_id
+            ))
+        })?;
+
+        Ok(ComposeSource {
+            message_data,
+            raw_message,
+        })
+    }
+}
+
+impl ComposeSource {
+    pub fn part(&self, part_id: &str) -> Option<ComposePart> {
+        let mime_part = self
+            .message_data
+            .mime_parts
+            .get(part_id.parse::<usize>().ok()?)?;
+        let (part, default_type) = match &mime_part.mime_type {
+            MimePartType::Text { part } => (part, "text/plain"),
+            MimePartType::Html { part } => (part, "text/html"),
+            MimePartType::Other { part } => (part, "application/octet-stream"),
+            MimePartType::MultiPart { .. } => return None,
+        };
+
+        ComposePart {
+            contents: part.decode(&self.raw_message)?,
+            type_: mime_part
+                .type_
+                .clone()
+                .unwrap_or_else(|| default_type.to_string()),
+            charset: mime_part.charset.clone(),
+            name: mime_part.name.clone(),
+            disposition: mime_part.disposition.clone(),
+        }
+        .into()
+    }
+
+    pub fn into_attachment(self) -> ComposePart {
+        ComposePart {
+            name: format!(
+                "{}.eml",
+                self.text(RfcHeader::Subject)
+                    .filter(|subject| !subject.trim().is_empty())
+                    .unwrap_or_else(|| "message".to_string())
+                    .trim()
+            )
+            .into(),
+            contents: self.raw_message,
+            type_: "message/rfc822".to_string(),
+            charset: None,
+            disposition: "attachment".to_string().into(),
+        }
+    }
+
+    pub fn template(&self, type_: TemplateType, own_addresses: &[EmailAddress]) -> ComposeTemplate {
+        let subject = self.text(RfcHeader::Subject).unwrap_or_default();
+        let message_ids = self.text_list(RfcHeader::MessageId);
+        let mut template = ComposeTemplate {
+            subject: match type_ {
+                TemplateType::Reply | TemplateType::ReplyAll => add_prefix(&subject, "Re:", &[]),
+                TemplateType::Forward => add_prefix(&subject, "Fwd:", &["fw:"]),
+            }
+            .into(),
+            references: self.text_list(RfcHeader::References),
+            ..Default::default()
+        };
+        template.references.extend(message_ids.iter().cloned());
+
+        if type_ != TemplateType::Forward {
+            let reply_to = self.addresses(RfcHeader::ReplyTo);
+            template.to = if !reply_to.is_empty() {
+                reply_to
+            } else {
+                self.addresses(RfcHeader::From)
+            };
+            if type_ == TemplateType::ReplyAll {
+                for address in self
+                    .addresses(RfcHeader::To)
+                    .into_iter()
+                    .chain(self.addresses(RfcHeader::Cc))
+                {
+                    if !own_addresses
+                        .iter()
+                        .chain(template.to.iter())
+                        .chain(template.cc.iter())
+                        .any(|a| a.email.eq_ignore_ascii_case(&address.email))
+                    {
+                        template.cc.push(address);
+                    }
+                }
+            }
+            template.in_reply_to = message_ids;
+        }
+
+        let from = format_addresses(&self.addresses(RfcHeader::From));
+        let date = self
+            .timestamp(RfcHeader::Date)
+            .map(|date| JMAPDate::from_timestamp(date).to_string());
+        let body = self.text_body().unwrap_or_default();
+        let body = body.trim_end();
+
+        template.text = if type_ != TemplateType::Forward {
+            let mut text = match date {
+                Some(date) => format!("On {}, {} wrote:\n", date, from),
+                None => format!("{} wrote:\n", from),
+            };
+            text.push_str(&quote_text(body));
+            text
+        } else {
+            let mut text = "---------- Forwarded message ----------\n".to_string();
+            text.push_str(&format!("From: {}\n", from));
+            if let Some(date) = date {
+                text.push_str(&format!("Date: {}\n", date));
+            }
+            text.push_str(&format!("Subject: {}\n", subject));
+            let to = self.addresses(RfcHeader::To);
+            if !to.is_empty() {
+                text.push_str(&format!("To: {}\n", format_addresses(&to)));
+            }
+            text.push('\n');
+            text.push_str(body);
+            text.push('\n');
+            text
+        };
+
+        template
+    }
+
+    fn text_body(&self) -> Option<String> {
+        let mime_part = self
+            .message_data
+            .mime_parts
+            .get(*self.message_data.text_body.first()?)?;
+        match &mime_part.mime_type {
+            MimePartType::Text { part } => {
+                part.decode_text(&self.raw_message, mime_part.charset.as_deref(), true)
+            }
+            MimePartType::Html { part } => part
+                .decode_text(&self.raw_message, mime_part.charset.as_deref(), true)
+                .map(|html| html_to_text(&html)),
+            _ => None,
+        }
+    }
+
+    fn addresses(&self, header: RfcHeader) -> Vec<EmailAddress> {
+        self.message_data
+            .headers
+            .get(&header)
+            .and_then(|values| values.last())
+            .cloned()
+            .and_then(HeaderValue::into_addresses)
+            .unwrap_or_default()
+    }
+
+    fn text(&self, header: RfcHeader) -> Option<String> {
+        self.message_data
+            .headers
+            .get(&header)
+            .and_then(|values| values.last())
+            .cloned()
+            .and_then(HeaderValue::unwrap_text)
+    }
+
+    fn text_list(&self, header: RfcHeader) -> Vec<String> {
+        self.message_data
+            .headers
+            .get(&header)
+            .and_then(|values| values.last())
+            .cloned()
+            .and_then(HeaderValue::unwrap_textlist)
+            .unwrap_or_default()
+    }
+
+    fn timestamp(&self, header: RfcHeader) -> Option<i64> {
+        self.message_data
+            .headers
+            .get(&header)
+            .and_then(|values| values.last())
+            .cloned()
+            .and_then(HeaderValue::unwrap_timestamp)
+    }
+}
+
+fn add_prefix(subject: &str, prefix: &str, aliases: &[&str]) -> String {
+    let subject = subject.trim();
+    if [prefix].iter().chain(aliases.iter()).any(|prefix| {
+        subject
+            .get(..prefix.len())
+            .map_or(false, |s| s.eq_ignore_ascii_case(prefix))
+    }) {
+        subject.to_string()
+    } else if subject.is_empty() {
+        prefix.to_string()
+    } else {
+        format!("{} {}", prefix, subject)
+    }
+}
+
+fn quote_text(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 16);
+    for line in text.lines() {
+        if line.is_empty() || line.starts_with('>') {
+            quoted.push('>');
+        } else {
+            quoted.push_str("> ");
+        }
+        quoted.push_str(line);
+        quoted.push('\n');
+    }
+    quoted
+}
+
+fn format_addresses(addresses: &[EmailAddress]) -> String {
+    addresses
+        .iter()
+        .map(|address| match &address.name {
+            Some(name) => format!("{} <{}>", name, address.email),
+            None => format!("<{}>", address.email),
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_prefix, quote_text};
+
+    #[test]
+    fn reply_prefix() {
+        for (subject, prefix, aliases, expected) in [
+            ("Hello", "Re:", &[][..], "Re: Hello"),
+            ("RE: Hello", "Re:", &[][..], "RE: Hello"),
+            ("", "Re:", &[][..], "Re:"),
+            ("Fw: Hello", "Fwd:", &["fw:"][..], "Fw: Hello"),
+            ("Re: Hello", "Fwd:", &["fw:"][..], "Fwd: Re: Hello"),
+        ] {
+            assert_eq!(add_prefix(subject, prefix, aliases), expected);
+        }
+    }
+
+    #[test]
+    fn quote() {
+        assert_eq!(
+            quote_text("Hi,\n\nsee below.\n> earlier"),
+            "> Hi,\n>\n> see below.\n>> earlier\n"
+        );
+    }
+}
@@ -25,6 +25,7 @@ use super::{
     import::JMAPMailImport,
     schema::{Email, Property, Value},
     sharing::JMAPShareMail,
+    uid::JMAPMailUids,
     MessageData, MessageField,
 };
 use jmap::{
@@ -256,6 +257,9 @@ where
             // Lock collection
             let lock = self.lock_collection(helper.account_id, Collection::Mail);
 
+            // Assign UIDs
+            self.mail_assign_uids(&mut helper.changes, document)?;
+
             // Obtain thread Id
             let thread_id = self.mail_set_thread(&mut helper.changes, document)?;
 
@@ -0,0 +1,213 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use jmap::{orm::serialize::JMAPOrm, principal, SUPERUSER_ID};
+use store::{
+    blake3,
+    blob::BlobId,
+    core::{collection::Collection, document::Document, error::StoreError, JMAPIdPrefix},
+    nlp::Language,
+    read::{
+        comparator::Comparator,
+        filter::{Filter, Query},
+        FilterMapper,
+    },
+    serialize::StoreDeserialize,
+    write::options::{IndexOptions, Options},
+    AccountId, DocumentId, JMAPStore, LongInteger, Store,
+};
+
+use super::{MessageData, MessageField};
+
+pub trait JMAPMailDuplicate {
+    fn mail_duplicate_window(&self, account_id: AccountId) -> store::Result<u64>;
+    fn mail_find_duplicate(
+        &self,
+        account_id: AccountId,
+        document: &Document,
+    ) -> store::Result<Option<(DocumentId, BlobId)>>;
+}
+
+// Indexes the key used to detect copies of the same message, which is
+// derived from its Message-ID and body. Headers are excluded as these are
+// likely to differ between deliveries (i.e. Received or Authentication-Results).
+pub fn index_duplicate_key(document: &mut Document, message_id: &str, body: &[u8]) {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(message_id.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(body);
+    document.text(
+        MessageField::DuplicateKey,
+        hasher.finalize().to_hex().to_string(),
+        Language::Unknown,
+        IndexOptions::new().keyword().store(),
+    );
+}
+
+// Records the delivery time of a message received over LMTP. Only delivered
+// messages are considered when looking up duplicates, otherwise the Sent copy
+// of a message addressed to oneself would suppress its delivery. The time is
+// taken from the server clock, as the receivedAt property can be set by
+// clients on import.
+pub fn index_delivered_at(document: &mut Document) {
+    document.number(
+        MessageField::DeliveredAt,
+        now() as LongInteger,
+        IndexOptions::new().index().store(),
+    );
+}
+
+// Removes the duplicate detection entries of a deleted message.
+pub fn clear_duplicate_key<T>(
+    store: &JMAPStore<T>,
+    account_id: AccountId,
+    document: &mut Document,
+) -> store::Result<()>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    if let Some(key) = store.get_document_value::<String>(
+        account_id,
+        Collection::Mail,
+        document.document_id,
+        MessageField::DuplicateKey.into(),
+    )? {
+        document.text(
+            MessageField::DuplicateKey,
+            key,
+            Language::Unknown,
+            IndexOptions::new().keyword().store().clear(),
+        );
+    }
+    if let Some(delivered_at) = store.get_document_value::<LongInteger>(
+        account_id,
+        Collection::Mail,
+        document.document_id,
+        MessageField::DeliveredAt.into(),
+    )? {
+        document.number(
+            MessageField::DeliveredAt,
+            delivered_at,
+            IndexOptions::new().index().store().clear(),
+        );
+    }
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl<T> JMAPMailDuplicate for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Returns the account's duplicate detection window in seconds, or the
+    // server default if the account does not override it.
+    fn mail_duplicate_window(&self, account_id: AccountId) -> store::Result<u64> {
+        Ok(
+            match self
+                .get_orm::<principal::schema::Principal>(SUPERUSER_ID, account_id)?
+                .and_then(|mut fields| fields.remove(&principal::schema::Property::DuplicateWindow))
+            {
+                Some(principal::schema::Value::Number { value }) => value.max(0) as u64,
+                _ => self.config.mail_duplicate_window,
+            },
+        )
+    }
+
+    // Looks up a message with the same duplicate key that was delivered
+    // within the account's duplicate window.
+    fn mail_find_duplicate(
+        &self,
+        account_id: AccountId,
+        document: &Document,
+    ) -> store::Result<Option<(DocumentId, BlobId)>> {
+        let key = if let Some(field) = document
+            .text_fields
+            .iter()
+            .find(|field| field.field == MessageField::DuplicateKey as u8)
+        {
+            field.value.text.clone()
+        } else {
+            return Ok(None);
+        };
+        let window = self.mail_duplicate_window(account_id)?;
+        if window == 0 {
+            return Ok(None);
+        }
+        let since = now().saturating_sub(window);
+
+        let document_id = if let Some(id) = self
+            .query_store::<FilterMapper>(
+                account_id,
+                Collection::Mail,
+                Filter::and(vec![
+                    Filter::eq(MessageField::DuplicateKey.into(), Query::Keyword(key)),
+                    Filter::ge(
+                        MessageField::DeliveredAt.into(),
+                        Query::LongInteger(since as LongInteger),
+                    ),
+                ]),
+                Comparator::None,
+            )?
+            .into_iter()
+            .next()
+        {
+            id.get_document_id()
+        } else {
+            return Ok(None);
+        };
+
+        // Obtain the raw message of the existing copy
+        let metadata_blob_id = self
+            .get_document_value::<BlobId>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::Metadata.into(),
+            )?
+            .ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Email metadata blobId for {}/{} does not exist.",
+                    account_id, document_id
+                ))
+            })?;
+        let message_data = self
+            .blob_get(&metadata_blob_id)?
+            .and_then(|bytes| MessageData::deserialize(&bytes))
+            .ok_or_else(|| {
+                StoreError::DataCorruption(format!(
+                    "Failed to deserialize email metadata for {}/{}",
+                    account_id, document_id
+                ))
+            })?;
+
+        Ok(Some((document_id, message_data.raw_message)))
+    }
+}
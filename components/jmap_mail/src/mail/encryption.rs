@@ -0,0 +1,428 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::{duplicate::index_duplicate_key, import::JMAPMailImport, MessageField};
+use jmap::{orm::serialize::JMAPOrm, principal, SUPERUSER_ID};
+use mail_parser::Message;
+use openssl::{
+    pkcs7::{Pkcs7, Pkcs7Flags},
+    stack::Stack,
+    symm::Cipher,
+    x509::X509,
+};
+use pgp::{
+    composed::message::Message as PgpMessage, crypto::sym::SymmetricKeyAlgorithm, types::KeyTrait,
+    Deserializable, SignedPublicKey,
+};
+use std::io::Cursor;
+use store::{
+    blob::BlobId,
+    core::{document::Document, error::StoreError},
+    rand::{thread_rng, Rng},
+    AccountId, JMAPStore, Store,
+};
+
+// Public key material used to encrypt the messages stored for an account
+pub enum EncryptionParams {
+    PGP(Vec<SignedPublicKey>),
+    SMIME(Vec<X509>),
+}
+
+pub trait JMAPMailEncryption {
+    fn mail_encryption_params(
+        &self,
+        account_id: AccountId,
+    ) -> store::Result<Option<EncryptionParams>>;
+
+    fn mail_parse_encrypted_item(
+        &self,
+        document: &mut Document,
+        params: &EncryptionParams,
+        raw_message: &[u8],
+        received_at: Option<i64>,
+    ) -> store::Result<(BlobId, usize)>;
+}
+
+impl<T> JMAPMailEncryption for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_encryption_params(
+        &self,
+        account_id: AccountId,
+    ) -> store::Result<Option<EncryptionParams>> {
+        match self
+            .get_orm::<principal::schema::Principal>(SUPERUSER_ID, account_id)?
+            .and_then(|mut fields| fields.remove(&principal::schema::Property::EncryptionKey))
+        {
+            Some(principal::schema::Value::Text { value }) => {
+                EncryptionParams::parse(&value).map(Some).map_err(|err| {
+                    StoreError::DataCorruption(format!(
+                        "Invalid encryption key for account {}: {}",
+                        account_id, err
+                    ))
+                })
+            }
+            _ => Ok(None),
+        }
+    }
+
+    // Stores an encrypted copy of the message. The headers are left in the clear
+    // so they can be indexed and fetched, and the duplicate key is derived from
+    // the plaintext as every encryption produces a different body.
+    fn mail_parse_encrypted_item(
+        &self,
+        document: &mut Document,
+        params: &EncryptionParams,
+        raw_message: &[u8],
+        received_at: Option<i64>,
+    ) -> store::Result<(BlobId, usize)> {
+        let (message_id, body_offset) = {
+            let message = Message::parse(raw_message).ok_or_else(|| {
+                StoreError::InvalidArguments("Failed to parse e-mail message.".to_string())
+            })?;
+            (
+                message.get_message_id().map(|id| id.to_string()),
+                message.get_root_part().offset_body,
+            )
+        };
+
+        let encrypted = params.encrypt(raw_message).map_err(|err| {
+            StoreError::InternalError(format!("Failed to encrypt message: {}", err))
+        })?;
+        let blob_id = BlobId::new_external(&encrypted);
+        self.mail_parse_item(
+            document,
+            blob_id.clone(),
+            Message::parse(&encrypted).ok_or_else(|| {
+                StoreError::InternalError("Failed to parse encrypted message.".to_string())
+            })?,
+            received_at,
+        )?;
+
+        document
+            .text_fields
+            .retain(|field| field.field != MessageField::DuplicateKey as u8);
+        if let Some(message_id) = message_id {
+            index_duplicate_key(
+                document,
+                &message_id,
+                raw_message.get(body_offset..).unwrap_or_default(),
+            );
+        }
+
+        let size = encrypted.len();
+        self.blob_store(&blob_id, encrypted)?;
+
+        Ok((blob_id, size))
+    }
+}
+
+impl EncryptionParams {
+    pub fn parse(key: &str) -> Result<Self, String> {
+        let key = key.trim();
+        if key.starts_with("-----BEGIN PGP PUBLIC KEY BLOCK-----") {
+            let keys = SignedPublicKey::from_armor_many(Cursor::new(key.as_bytes()))
+                .map_err(|err| err.to_string())?
+                .0
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|err| err.to_string())?;
+            if keys
+                .iter()
+                .flat_map(|key| key.public_subkeys.iter())
+                .any(|subkey| subkey.is_encryption_key())
+            {
+                Ok(EncryptionParams::PGP(keys))
+            } else {
+                Err("OpenPGP key does not contain an encryption subkey.".to_string())
+            }
+        } else if key.starts_with("-----BEGIN CERTIFICATE-----") {
+            let certs = X509::stack_from_pem(key.as_bytes()).map_err(|err| err.to_string())?;
+            if !certs.is_empty() {
+                Ok(EncryptionParams::SMIME(certs))
+            } else {
+                Err("No certificates found.".to_string())
+            }
+        } else {
+            Err(
+                "Expected an ASCII armored OpenPGP public key or a PEM encoded certificate."
+                    .to_string(),
+            )
+        }
+    }
+
+    pub fn encrypt(&self, raw_message: &[u8]) -> Result<Vec<u8>, String> {
+        let (headers, mime_headers, body) = split_message(raw_message);
+
+        // Encrypt the MIME headers and body as a single entity
+        let mut inner = Vec::with_capacity(raw_message.len() + 2);
+        for header in mime_headers {
+            inner.extend_from_slice(header);
+        }
+        inner.extend_from_slice(b"\r\n");
+        inner.extend_from_slice(body);
+
+        let mut message = Vec::with_capacity(raw_message.len() * 2);
+        for header in headers {
+            message.extend_from_slice(header);
+        }
+        message.extend_from_slice(b"MIME-Version: 1.0\r\n");
+
+        match self {
+            EncryptionParams::PGP(keys) => {
+                let subkeys = keys
+                    .iter()
+                    .flat_map(|key| key.public_subkeys.iter())
+                    .filter(|subkey| subkey.is_encryption_key())
+                    .collect::<Vec<_>>();
+                let armored = PgpMessage::new_literal_bytes("message.eml", &inner)
+                    .encrypt_to_keys(&mut thread_rng(), SymmetricKeyAlgorithm::AES256, &subkeys)
+                    .and_then(|message| message.to_armored_string(None))
+                    .map_err(|err| err.to_string())?;
+                let boundary = format!("{:032x}", thread_rng().gen::<u128>());
+
+                message.extend_from_slice(
+                    format!(
+                        concat!(
+                            "Content-Type: multipart/encrypted;\r\n",
+                            "\tprotocol=\"application/pgp-encrypted\";\r\n",
+                            "\tboundary=\"{0}\"\r\n\r\n",
+                            "--{0}\r\n",
+                            "Content-Type: application/pgp-encrypted\r\n",
+                            "Content-Description: PGP/MIME version identification\r\n\r\n",
+                            "Version: 1\r\n\r\n",
+                            "--{0}\r\n",
+                            "Content-Type: application/octet-stream; name=\"encrypted.asc\"\r\n",
+                            "Content-Description: OpenPGP encrypted message\r\n",
+                            "Content-Disposition: inline; filename=\"encrypted.asc\"\r\n\r\n",
+                            "{1}\r\n",
+                            "--{0}--\r\n"
+                        ),
+                        boundary,
+                        armored
+                            .replace("\r\n", "\n")
+                            .replace('\n', "\r\n")
+                            .trim_end()
+                    )
+                    .as_bytes(),
+                );
+            }
+            EncryptionParams::SMIME(certs) => {
+                let mut stack = Stack::new().map_err(|err| err.to_string())?;
+                for cert in certs {
+                    stack.push(cert.clone()).map_err(|err| err.to_string())?;
+                }
+                let der = Pkcs7::encrypt(&stack, &inner, Cipher::aes_256_cbc(), Pkcs7Flags::BINARY)
+                    .and_then(|pkcs7| pkcs7.to_der())
+                    .map_err(|err| err.to_string())?;
+
+                message.extend_from_slice(
+                    concat!(
+                        "Content-Type: application/pkcs7-mime; smime-type=enveloped-data;\r\n",
+                        "\tname=\"smime.p7m\"\r\n",
+                        "Content-Disposition: attachment; filename=\"smime.p7m\"\r\n",
+                        "Content-Transfer-Encoding: base64\r\n\r\n"
+                    )
+                    .as_bytes(),
+                );
+                for line in openssl::base64::encode_block(&der).as_bytes().chunks(76) {
+                    message.extend_from_slice(line);
+                    message.extend_from_slice(b"\r\n");
+                }
+            }
+        }
+
+        Ok(message)
+    }
+}
+
+// Splits a raw message into its header fields, its MIME header fields
+// and its body. The MIME-Version header is dropped.
+fn split_message(raw_message: &[u8]) -> (Vec<&[u8]>, Vec<&[u8]>, &[u8]) {
+    let mut headers = Vec::new();
+    let mut mime_headers = Vec::new();
+    let mut is_mime = false;
+    let mut field_start = None;
+    let mut pos = 0;
+
+    while pos < raw_message.len() {
+        let line_end = raw_message[pos..]
+            .iter()
+            .position(|&ch| ch == b'\n')
+            .map(|end| pos + end + 1)
+            .unwrap_or(raw_message.len());
+        let line = &raw_message[pos..line_end];
+        let is_continuation = matches!(line.first(), Some(b' ' | b'\t'));
+
+        if !is_continuation {
+            if let Some(start) = field_start.take() {
+                let field = &raw_message[start..pos];
+                if is_mime {
+                    mime_headers.push(field);
+                } else if !starts_with_ignore_case(field, b"mime-version:") {
+                    headers.push(field);
+                }
+            }
+            if line == b"\r\n" || line == b"\n" {
+                return (headers, mime_headers, &raw_message[line_end..]);
+            }
+            is_mime = starts_with_ignore_case(line, b"content-");
+            field_start = Some(pos);
+        }
+        pos = line_end;
+    }
+
+    if let Some(start) = field_start {
+        let field = &raw_message[start..];
+        if is_mime {
+            mime_headers.push(field);
+        } else if !starts_with_ignore_case(field, b"mime-version:") {
+            headers.push(field);
+        }
+    }
+
+    (headers, mime_headers, &[])
+}
+
+fn starts_with_ignore_case(value: &[u8], prefix: &[u8]) -> bool {
+    value.len() >= prefix.len() && value[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split_message, EncryptionParams};
+    use openssl::{
+        asn1::Asn1Time,
+        hash::MessageDigest,
+        pkcs7::{Pkcs7, Pkcs7Flags},
+        pkey::PKey,
+        rsa::Rsa,
+        x509::{X509NameBuilder, X509},
+    };
+
+    #[test]
+    fn split_headers() {
+        let (headers, mime_headers, body) = split_message(
+            concat!(
+                "From: jdoe@example.org\r\n",
+                "Subject: hello\r\n",
+                " world\r\n",
+                "MIME-Version: 1.0\r\n",
+                "Content-Type: text/plain;\r\n",
+                "\tcharset=utf-8\r\n",
+                "To: jane@example.org\r\n",
+                "\r\n",
+                "Body\r\n"
+            )
+            .as_bytes(),
+        );
+        assert_eq!(
+            headers,
+            vec![
+                &b"From: jdoe@example.org\r\n"[..],
+                &b"Subject: hello\r\n world\r\n"[..],
+                &b"To: jane@example.org\r\n"[..],
+            ]
+        );
+        assert_eq!(
+            mime_headers,
+            vec![&b"Content-Type: text/plain;\r\n\tcharset=utf-8\r\n"[..]]
+        );
+        assert_eq!(body, b"Body\r\n");
+
+        let (headers, mime_headers, body) = split_message(b"Subject: no body");
+        assert_eq!(headers, vec![&b"Subject: no body"[..]]);
+        assert!(mime_headers.is_empty());
+        assert!(body.is_empty());
+    }
+
+    #[test]
+    fn reject_invalid_keys() {
+        assert!(EncryptionParams::parse("ssh-rsa AAAAB3NzaC1yc2E").is_err());
+        assert!(EncryptionParams::parse(
+            "-----BEGIN CERTIFICATE-----\nnot a cert\n-----END CERTIFICATE-----"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn smime_encrypt() {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "John Doe").unwrap();
+        let name = name.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        let cert = cert.build();
+
+        let params =
+            EncryptionParams::parse(std::str::from_utf8(&cert.to_pem().unwrap()).unwrap()).unwrap();
+        let message = String::from_utf8(
+            params
+                .encrypt(
+                    concat!(
+                        "From: jdoe@example.org\r\n",
+                        "Subject: hello\r\n",
+                        "MIME-Version: 1.0\r\n",
+                        "Content-Type: text/plain\r\n",
+                        "\r\n",
+                        "Secret body\r\n"
+                    )
+                    .as_bytes(),
+                )
+                .unwrap(),
+        )
+        .unwrap();
+
+        // Headers stay in the clear, the MIME headers and body are encrypted
+        let (headers, body) = message.split_once("\r\n\r\n").unwrap();
+        assert!(
+            headers.starts_with(concat!(
+                "From: jdoe@example.org\r\n",
+                "Subject: hello\r\n",
+                "MIME-Version: 1.0\r\n",
+                "Content-Type: application/pkcs7-mime; smime-type=enveloped-data;\r\n"
+            )),
+            "{}",
+            headers
+        );
+        assert!(!message.contains("Secret body"));
+
+        let der = openssl::base64::decode_block(&body.replace("\r\n", "")).unwrap();
+        assert_eq!(
+            Pkcs7::from_der(&der)
+                .unwrap()
+                .decrypt(&key, &cert, Pkcs7Flags::BINARY)
+                .unwrap(),
+            b"Content-Type: text/plain\r\n\r\nSecret body\r\n"
+        );
+    }
+}
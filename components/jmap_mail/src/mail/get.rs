@@ -24,10 +24,11 @@
 use super::{
     conv::IntoForm,
     schema::{
-        BodyProperty, Email, EmailBodyPart, EmailBodyValue, EmailHeader, HeaderForm,
-        HeaderProperty, Property, Value,
+        AuthResult, BodyProperty, Email, EmailBodyPart, EmailBodyValue, EmailHeader, HeaderForm,
+        HeaderProperty, Property, SignatureStatus, Value,
     },
     sharing::JMAPShareMail,
+    signature::{SignatureResult, SignatureVerifier},
     GetRawHeader, HeaderName, MessagePart,
 };
 use crate::mail::{MessageData, MessageField, MimePart, MimePartType};
@@ -44,9 +45,9 @@ use jmap::{
 };
 use mail_parser::{
     parsers::preview::{preview_html, preview_text, truncate_html, truncate_text},
-    Encoding, HeaderValue, RfcHeader,
+    Encoding, HeaderValue, Message, RfcHeader,
 };
-use std::{borrow::Cow, sync::Arc};
+use std::{borrow::Cow, sync::Arc, time::SystemTime};
 use store::{
     blob::BlobId,
     core::{
@@ -216,6 +217,7 @@ where
         // Check whether any parts of the raw message need to be fetched
         let mut fetch_raw = FetchRaw::None;
         let mut has_id = false;
+        let mut verify_signatures = false;
         for property in &helper.properties {
             match property {
                 Property::Header(HeaderProperty {
@@ -233,6 +235,15 @@ where
                 Property::BodyStructure | Property::BodyValues | Property::Preview => {
                     fetch_raw = FetchRaw::All;
                 }
+                Property::SmimeStatus
+                | Property::SmimeErrors
+                | Property::SmimeVerifiedAt
+                | Property::PgpStatus
+                | Property::PgpErrors
+                | Property::PgpVerifiedAt => {
+                    fetch_raw = FetchRaw::All;
+                    verify_signatures = true;
+                }
                 Property::Id => {
                     has_id = true;
                 }
@@ -253,6 +264,9 @@ where
             helper.properties.push(Property::Id);
         }
 
+        // Signatures are verified on demand against the configured trust material
+        let mut verifier = SignatureVerifier::new(&self.config);
+
         // Get items
         helper.get(|id, properties| {
             let document_id = id.get_document_id();
@@ -315,6 +329,25 @@ where
                 FetchRaw::None => None,
             };
 
+            // Verify S/MIME and OpenPGP signatures
+            let (smime, pgp, verified_at) = if verify_signatures {
+                let message = raw_message.as_deref().and_then(Message::parse);
+                (
+                    message
+                        .as_ref()
+                        .and_then(|message| verifier.verify_smime(message)),
+                    message
+                        .as_ref()
+                        .and_then(|message| verifier.verify_pgp(message)),
+                    SystemTime::now()
+                        .duration_since(SystemTime::UNIX_EPOCH)
+                        .map(|d| d.as_secs() as i64)
+                        .unwrap_or(0),
+                )
+            } else {
+                (None, None, 0)
+            };
+
             // Fetch ORM
             let fields = self
                 .get_orm::<Email>(account_id, document_id)?
@@ -556,6 +589,56 @@ where
                         .mime_parts
                         .as_body_structure(&body_properties, raw_message.as_deref(), &blob_id)
                         .map(|b| b.into()),
+                    Property::AuthResult => fields
+                        .get_tags(&Property::AuthResult)
+                        .and_then(|tags| tags.iter().find_map(AuthResult::from_tag))
+                        .map(|result| Value::Text {
+                            value: result.to_string(),
+                        }),
+                    Property::SmimeStatus => Value::Text {
+                        value: signature_status(&smime).to_string(),
+                    }
+                    .into(),
+                    Property::PgpStatus => Value::Text {
+                        value: signature_status(&pgp).to_string(),
+                    }
+                    .into(),
+                    Property::SmimeErrors | Property::PgpErrors => {
+                        if property == &Property::SmimeErrors {
+                            &smime
+                        } else {
+                            &pgp
+                        }
+                        .as_ref()
+                        .filter(|result| !result.errors.is_empty())
+                        .map(|result| Value::TextList {
+                            value: result.errors.clone(),
+                        })
+                    }
+                    Property::SmimeVerifiedAt | Property::PgpVerifiedAt => {
+                        if matches!(
+                            signature_status(if property == &Property::SmimeVerifiedAt {
+                                &smime
+                            } else {
+                                &pgp
+                            }),
+                            SignatureStatus::SignedVerified | SignatureStatus::SignedFailed
+                        ) {
+                            Value::Date {
+                                value: JMAPDate::from_timestamp(verified_at),
+                            }
+                            .into()
+                        } else {
+                            None
+                        }
+                    }
+                    Property::SmimeStatusAtDelivery | Property::PgpStatusAtDelivery => fields
+                        .get_tags(property)
+                        .and_then(|tags| tags.iter().find_map(SignatureStatus::from_tag))
+                        .map(|status| Value::Text {
+                            value: status.to_string(),
+                        }),
+                    Property::Template => None,
                     Property::Invalid(property) => {
                         return Err(MethodError::InvalidArguments(format!(
                             "Unknown property {:?}",
@@ -806,6 +889,7 @@ impl MimePart {
                         Value::BodyPartList { value: Vec::new() },
                     );
                 }
+                BodyProperty::EmailId => (),
             }
         }
 
@@ -955,3 +1039,10 @@ impl AsEmailHeaders for MimePart {
         headers
     }
 }
+
+fn signature_status(result: &Option<SignatureResult>) -> SignatureStatus {
+    result
+        .as_ref()
+        .map(|result| result.status)
+        .unwrap_or(SignatureStatus::Unknown)
+}
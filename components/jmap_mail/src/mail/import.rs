@@ -64,11 +64,14 @@ use store::{AccountId, JMAPStore, SharedBitmap, Store, ThreadId};
 use store::{DocumentId, Integer, LongInteger};
 
 use crate::mail::MessageField;
+use crate::thread::metadata::{JMAPThreadMetadata, ThreadMetadata};
 
 use super::conv::HeaderValueInto;
+use super::duplicate::{index_duplicate_key, JMAPMailDuplicate};
 use super::get::{BlobResult, JMAPGetMail};
 use super::schema::{Email, Keyword, Property};
 use super::sharing::JMAPShareMail;
+use super::uid::JMAPMailUids;
 use super::{MessageData, MessagePart, MimePart, MimePartType, MAX_MESSAGE_PARTS};
 
 #[derive(Debug, Clone, serde::Deserialize)]
@@ -128,6 +131,14 @@ pub trait JMAPMailImport {
         received_at: Option<i64>,
     ) -> jmap::Result<Email>;
 
+    fn mail_link_existing(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        blob_id: BlobId,
+        mailbox_ids: &[DocumentId],
+    ) -> jmap::Result<Option<Email>>;
+
     fn mail_parse_item(
         &self,
         document: &mut Document,
@@ -154,6 +165,9 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     fn mail_import(&self, request: EmailImportRequest) -> jmap::Result<EmailImportResponse> {
+        if request.emails.len() > self.config.mail_import_max_items {
+            return Err(MethodError::RequestTooLarge);
+        }
         let account_id = request.account_id.get_document_id();
         let mailbox_document_ids = self
             .get_document_ids(account_id, Collection::Mailbox)?
@@ -219,47 +233,15 @@ where
                         account_id,
                         Collection::Mail,
                     )? {
-                        if let (Some(thread_id), Some(current_fields)) = (
-                            self.get_document_value::<DocumentId>(
-                                account_id,
-                                Collection::Mail,
-                                document_id,
-                                MessageField::ThreadId.into(),
-                            )?,
-                            self.get_orm::<Email>(account_id, document_id)?,
-                        ) {
-                            let email_id = JMAPId::from_parts(thread_id, document_id);
-                            let mut fields = TinyORM::track_changes(&current_fields);
-                            for mailbox_id in mailbox_ids.keys() {
-                                fields.tag(
-                                    Property::MailboxIds,
-                                    Tag::Id(mailbox_id.get_document_id()),
-                                );
-                            }
-                            let added_mailboxes =
-                                current_fields.get_added_tags(&fields, &Property::MailboxIds);
-                            if !added_mailboxes.is_empty() {
-                                let mut batch = WriteBatch::new(account_id);
-                                let mut document = Document::new(Collection::Mail, document_id);
-
-                                for added_mailbox in added_mailboxes {
-                                    batch.log_child_update(
-                                        Collection::Mailbox,
-                                        added_mailbox.as_id(),
-                                    );
-                                }
-                                current_fields.merge(&mut document, fields)?;
-                                debug_assert!(!document.is_empty());
-                                batch.update_document(document);
-                                batch.log_update(Collection::Mail, email_id);
-                                self.write(batch)?;
-                            }
-
-                            let mut email = Email::default();
-                            email.insert(Property::Id, email_id);
-                            email.insert(Property::BlobId, JMAPBlob::new(item.blob_id.id));
-                            email.insert(Property::ThreadId, JMAPId::from(thread_id));
-
+                        if let Some(email) = self.mail_link_existing(
+                            account_id,
+                            document_id,
+                            item.blob_id.id.clone(),
+                            &mailbox_ids
+                                .keys()
+                                .map(|id| id.get_document_id())
+                                .collect::<Vec<_>>(),
+                        )? {
                             created.append(id, email);
                             continue 'outer;
                         }
@@ -358,9 +340,8 @@ where
         keywords: Vec<Tag>,
         received_at: Option<i64>,
     ) -> jmap::Result<Email> {
-        let document_id = self.assign_document_id(account_id, Collection::Mail)?;
         let mut batch = WriteBatch::new(account_id);
-        let mut document = Document::new(Collection::Mail, document_id);
+        let mut document = Document::new(Collection::Mail, DocumentId::MAX);
         let size = blob.len();
 
         // Parse message
@@ -374,6 +355,20 @@ where
             received_at,
         )?;
 
+        // Lock account while duplicates are looked up and threads are merged
+        let _lock = self.lock_collection(account_id, Collection::Mail);
+
+        // Link copies of recently received messages instead of storing them again
+        if let Some((document_id, blob_id)) = self.mail_find_duplicate(account_id, &document)? {
+            if let Some(email) =
+                self.mail_link_existing(account_id, document_id, blob_id, &mailbox_ids)?
+            {
+                return Ok(email);
+            }
+        }
+        let document_id = self.assign_document_id(account_id, Collection::Mail)?;
+        document.document_id = document_id;
+
         // Add keyword tags
         let mut orm = TinyORM::<Email>::new();
         for keyword in keywords {
@@ -388,9 +383,7 @@ where
 
         // Serialize ORM
         orm.insert(&mut document)?;
-
-        // Lock account while threads are merged
-        let _lock = self.lock_collection(batch.account_id, Collection::Mail);
+        self.mail_assign_uids(&mut batch, &mut document)?;
 
         // Obtain thread Id
         let thread_id = self.mail_set_thread(&mut batch, &mut document)?;
@@ -411,6 +404,58 @@ where
         Ok(email)
     }
 
+    // Adds an existing message to the requested mailboxes, the caller is
+    // expected to hold the collection lock.
+    fn mail_link_existing(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        blob_id: BlobId,
+        mailbox_ids: &[DocumentId],
+    ) -> jmap::Result<Option<Email>> {
+        let (thread_id, current_fields) = if let (Some(thread_id), Some(current_fields)) = (
+            self.get_document_value::<DocumentId>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::ThreadId.into(),
+            )?,
+            self.get_orm::<Email>(account_id, document_id)?,
+        ) {
+            (thread_id, current_fields)
+        } else {
+            return Ok(None);
+        };
+
+        let email_id = JMAPId::from_parts(thread_id, document_id);
+        let mut fields = TinyORM::track_changes(&current_fields);
+        for mailbox_id in mailbox_ids {
+            fields.tag(Property::MailboxIds, Tag::Id(*mailbox_id));
+        }
+        let added_mailboxes = current_fields.get_added_tags(&fields, &Property::MailboxIds);
+        if !added_mailboxes.is_empty() {
+            let mut batch = WriteBatch::new(account_id);
+            let mut document = Document::new(Collection::Mail, document_id);
+
+            for added_mailbox in added_mailboxes {
+                batch.log_child_update(Collection::Mailbox, added_mailbox.as_id());
+            }
+            current_fields.merge(&mut document, fields)?;
+            self.mail_assign_uids(&mut batch, &mut document)?;
+            debug_assert!(!document.is_empty());
+            batch.update_document(document);
+            batch.log_update(Collection::Mail, email_id);
+            self.write(batch)?;
+        }
+
+        let mut email = Email::default();
+        email.insert(Property::Id, email_id);
+        email.insert(Property::BlobId, JMAPBlob::new(blob_id));
+        email.insert(Property::ThreadId, JMAPId::from(thread_id));
+
+        Ok(Some(email))
+    }
+
     fn mail_parse_item(
         &self,
         document: &mut Document,
@@ -519,6 +564,24 @@ where
             }
         }
 
+        // Index the key used to detect duplicate deliveries
+        if let Some(super::HeaderValue::TextList(ids)) = message_data
+            .headers
+            .get(&RfcHeader::MessageId)
+            .and_then(|values| values.last())
+        {
+            if let Some(message_id) = ids.first() {
+                index_duplicate_key(
+                    document,
+                    message_id,
+                    message
+                        .raw_message
+                        .get(message_data.body_offset..)
+                        .unwrap_or_default(),
+                );
+            }
+        }
+
         for (part_id, message_part) in message.parts.into_iter().enumerate() {
             let part = MessagePart {
                 offset_start: message_part.offset_body,
@@ -705,6 +768,8 @@ where
             thread_id
         };
 
+        self.thread_metadata_update(batch, thread_id, ThreadMetadata::from_document(document))?;
+
         document.tag(
             MessageField::ThreadId,
             Tag::Id(thread_id),
@@ -783,6 +848,9 @@ where
                 batch.update_document(document);
             }
 
+            let metadata = self.thread_metadata(batch.account_id, delete_thread_id)?;
+            self.thread_metadata_update(batch, thread_id, metadata)?;
+            self.thread_metadata_delete(batch, delete_thread_id);
             batch.log_delete(Collection::Thread, delete_thread_id);
         }
 
@@ -22,8 +22,11 @@
 */
 
 pub mod changes;
+pub mod compose;
 pub mod conv;
 pub mod copy;
+pub mod duplicate;
+pub mod encryption;
 pub mod get;
 pub mod import;
 pub mod parse;
@@ -34,6 +37,8 @@ pub mod search_snippet;
 pub mod serialize;
 pub mod set;
 pub mod sharing;
+pub mod signature;
+pub mod uid;
 
 use jmap::{jmap_store::Object, types::jmap::JMAPId};
 use serde::{Deserialize, Serialize};
@@ -338,6 +343,12 @@ pub enum MessageField {
     ThreadId = 136,
     Mailbox = 137,
     HasHeader = 138,
+    AuthResult = 139,
+    DuplicateKey = 140,
+    DeliveredAt = 141,
+    SmimeStatus = 142,
+    PgpStatus = 143,
+    ImapUid = 144,
 }
 
 impl From<MessageField> for FieldId {
@@ -437,6 +437,16 @@ impl IntoParsedEmail for Message<'_> {
                 | Property::MailboxIds
                 | Property::Keywords
                 | Property::ReceivedAt
+                | Property::AuthResult
+                | Property::Template
+                | Property::SmimeStatus
+                | Property::SmimeStatusAtDelivery
+                | Property::SmimeErrors
+                | Property::SmimeVerifiedAt
+                | Property::PgpStatus
+                | Property::PgpStatusAtDelivery
+                | Property::PgpErrors
+                | Property::PgpVerifiedAt
                 | Property::Invalid(_) => None,
             };
 
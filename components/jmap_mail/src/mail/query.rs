@@ -21,12 +21,16 @@
  * for more details.
 */
 
-use super::schema::{Comparator, Email, Filter};
+use super::schema::{Comparator, Email, Filter, SignatureStatus};
 use super::sharing::JMAPShareMail;
 use crate::mail::MessageField;
+use crate::saved_search::get::JMAPGetSavedSearch;
 use jmap::error::method::MethodError;
 use jmap::jmap_store::query::{ExtraFilterFnc, QueryHelper, QueryObject};
-use jmap::request::query::{QueryRequest, QueryResponse};
+use jmap::request::query::{
+    Comparator as QueryComparator, Filter as QueryFilter, FilterOperator, QueryRequest,
+    QueryResponse,
+};
 use jmap::types::jmap::JMAPId;
 use mail_parser::parsers::header::{parse_header_name, HeaderParserResult};
 use mail_parser::RfcHeader;
@@ -41,10 +45,13 @@ use store::read::filter::{self, Query};
 use store::{roaring::RoaringBitmap, AccountId, JMAPStore, Store};
 use store::{FieldId, Integer, LongInteger};
 
+// Saved searches may reference other saved searches up to this depth.
+const MAX_SAVED_SEARCH_DEPTH: usize = 5;
+
 #[derive(Debug, Clone, serde::Deserialize, Default)]
 pub struct QueryArguments {
     #[serde(rename = "collapseThreads")]
-    collapse_threads: Option<bool>,
+    pub collapse_threads: Option<bool>,
 }
 
 impl QueryObject for Email {
@@ -60,6 +67,13 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     fn mail_query(&self, request: QueryRequest<Email>) -> jmap::Result<QueryResponse>;
+    fn mail_expand_saved_searches(
+        &self,
+        account_id: AccountId,
+        filter: QueryFilter<Filter>,
+        sort: &mut Option<Vec<QueryComparator<Comparator>>>,
+        depth: usize,
+    ) -> jmap::Result<QueryFilter<Filter>>;
     fn get_thread_keywords(
         &self,
         account_id: AccountId,
@@ -72,7 +86,26 @@ impl<T> JMAPMailQuery<T> for JMAPStore<T>
 where
     T: for<'x> Store<'x> + 'static,
 {
-    fn mail_query(&self, request: QueryRequest<Email>) -> jmap::Result<QueryResponse> {
+    fn mail_query(&self, mut request: QueryRequest<Email>) -> jmap::Result<QueryResponse> {
+        // Replace saved search conditions with their stored filters, the first
+        // saved search found also provides the sort criteria when none was given.
+        let mut uses_saved_search = false;
+        if let Some(filter) = request.filter.take() {
+            uses_saved_search = has_saved_search_filter(&filter);
+            let mut sort = None;
+            request.filter = self
+                .mail_expand_saved_searches(
+                    request.account_id.get_document_id(),
+                    filter,
+                    &mut sort,
+                    0,
+                )?
+                .into();
+            if request.sort.is_none() {
+                request.sort = sort;
+            }
+        }
+
         let mut helper = QueryHelper::new(
             self,
             request,
@@ -294,6 +327,30 @@ where
                     )
                 }
 
+                Filter::AuthResult { value } => {
+                    filter::Filter::eq(MessageField::AuthResult.into(), Query::Tag(value.into()))
+                }
+
+                // Signature filters match the status recorded at delivery
+                Filter::HasSmime { value } => {
+                    has_signature_status(MessageField::SmimeStatus, &SIGNATURE_ANY, value)
+                }
+                Filter::HasVerifiedSmimeAtDelivery { value } => {
+                    has_signature_status(MessageField::SmimeStatus, &SIGNATURE_VERIFIED, value)
+                }
+                Filter::HasPgp { value } => {
+                    has_signature_status(MessageField::PgpStatus, &SIGNATURE_ANY, value)
+                }
+                Filter::HasVerifiedPgpAtDelivery { value } => {
+                    has_signature_status(MessageField::PgpStatus, &SIGNATURE_VERIFIED, value)
+                }
+
+                Filter::InSavedSearch { .. } => {
+                    return Err(MethodError::InvalidArguments(
+                        "Too many nested saved searches.".to_string(),
+                    ));
+                }
+
                 Filter::Unsupported { value } => {
                     return Err(MethodError::UnsupportedFilter(value));
                 }
@@ -394,10 +451,46 @@ where
             )
             .map(|mut r| {
                 r.is_immutable = is_immutable_filter && is_immutable_sort;
+                r.can_calculate_changes = !uses_saved_search;
                 r
             })
     }
 
+    fn mail_expand_saved_searches(
+        &self,
+        account_id: AccountId,
+        filter: QueryFilter<Filter>,
+        sort: &mut Option<Vec<QueryComparator<Comparator>>>,
+        depth: usize,
+    ) -> jmap::Result<QueryFilter<Filter>> {
+        Ok(match filter {
+            QueryFilter::FilterCondition(Filter::InSavedSearch { value })
+                if depth < MAX_SAVED_SEARCH_DEPTH =>
+            {
+                let (filter, saved_sort) = self
+                    .saved_search_query(account_id, value.get_document_id())?
+                    .ok_or_else(|| {
+                        MethodError::InvalidArguments(format!("SavedSearch {} not found.", value))
+                    })?;
+                if sort.is_none() {
+                    *sort = saved_sort;
+                }
+                self.mail_expand_saved_searches(account_id, filter, sort, depth + 1)?
+            }
+            QueryFilter::FilterOperator(FilterOperator {
+                operator,
+                conditions,
+            }) => QueryFilter::FilterOperator(FilterOperator {
+                operator,
+                conditions: conditions
+                    .into_iter()
+                    .map(|filter| self.mail_expand_saved_searches(account_id, filter, sort, depth))
+                    .collect::<jmap::Result<Vec<_>>>()?,
+            }),
+            filter => filter,
+        })
+    }
+
     fn get_thread_keywords(
         &self,
         account_id: AccountId,
@@ -455,3 +548,39 @@ where
         }
     }
 }
+
+const SIGNATURE_ANY: [SignatureStatus; 4] = [
+    SignatureStatus::Signed,
+    SignatureStatus::SignedVerified,
+    SignatureStatus::SignedFailed,
+    SignatureStatus::Encrypted,
+];
+const SIGNATURE_VERIFIED: [SignatureStatus; 1] = [SignatureStatus::SignedVerified];
+
+fn has_signature_status(
+    field: MessageField,
+    statuses: &[SignatureStatus],
+    value: bool,
+) -> filter::Filter {
+    let filter = filter::Filter::or(
+        statuses
+            .iter()
+            .map(|status| filter::Filter::eq(field.into(), Query::Tag((*status).into())))
+            .collect(),
+    );
+    if value {
+        filter
+    } else {
+        filter::Filter::not(vec![filter])
+    }
+}
+
+pub(crate) fn has_saved_search_filter(filter: &QueryFilter<Filter>) -> bool {
+    match filter {
+        QueryFilter::FilterOperator(operator) => {
+            operator.conditions.iter().any(has_saved_search_filter)
+        }
+        QueryFilter::FilterCondition(Filter::InSavedSearch { .. }) => true,
+        QueryFilter::FilterCondition(_) | QueryFilter::Empty => false,
+    }
+}
@@ -35,8 +35,10 @@ use store::{
 };
 
 use super::schema::Email;
+use super::uid::JMAPMailUids;
 use super::MessageData;
 use super::MessageField;
+use crate::thread::metadata::{JMAPThreadMetadata, ThreadMetadata};
 
 impl<T> RaftObject<T> for Email
 where
@@ -49,6 +51,9 @@ where
         jmap_id: store::JMAPId,
         as_insert: Option<Vec<BlobId>>,
     ) -> store::Result<()> {
+        // UIDs are assigned locally, in the order updates are applied
+        store.mail_assign_uids(write_batch, document)?;
+
         if let Some(blobs) = as_insert {
             // First blobId contains the message metadata
             let metadata_blob_id = blobs.into_iter().next().ok_or_else(|| {
@@ -85,6 +90,11 @@ where
                 thread_id,
                 IndexOptions::new().store(),
             );
+            store.thread_metadata_update(
+                write_batch,
+                thread_id,
+                ThreadMetadata::from_document(document),
+            )?;
 
             // Link metadata blob
             document.binary(
@@ -125,7 +135,11 @@ where
                     thread_id,
                     IndexOptions::new().store(),
                 );
+                let metadata =
+                    store.thread_metadata_message(write_batch.account_id, document.document_id)?;
+                store.thread_metadata_update(write_batch, thread_id, metadata)?;
             }
+            store.thread_metadata_keywords(write_batch, thread_id, document)?;
         }
         Ok(())
     }
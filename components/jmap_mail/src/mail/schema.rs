@@ -70,6 +70,13 @@ impl EmailBodyPart {
             _ => None,
         })
     }
+
+    pub fn get_id(&self, property: BodyProperty) -> Option<JMAPId> {
+        self.properties.get(&property).and_then(|v| match v {
+            Value::Id { value } => Some(*value),
+            _ => None,
+        })
+    }
 }
 
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Eq, PartialEq)]
@@ -86,6 +93,25 @@ pub struct EmailBodyValue {
     pub is_truncated: Option<bool>,
 }
 
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Eq, PartialEq)]
+pub struct EmailTemplate {
+    #[serde(rename = "emailId")]
+    pub email_id: JMAPId,
+
+    #[serde(rename = "type")]
+    pub type_: TemplateType,
+}
+
+#[derive(Debug, Clone, Copy, serde::Serialize, serde::Deserialize, Eq, PartialEq)]
+pub enum TemplateType {
+    #[serde(rename = "reply")]
+    Reply,
+    #[serde(rename = "replyAll")]
+    ReplyAll,
+    #[serde(rename = "forward")]
+    Forward,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize, Eq, PartialEq)]
 pub struct EmailAddress {
     pub name: Option<String>,
@@ -208,6 +234,95 @@ impl Display for Keyword {
     }
 }
 
+// DMARC verdict of a message received over LMTP
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub enum AuthResult {
+    #[serde(rename = "pass")]
+    Pass = 0,
+    #[serde(rename = "fail")]
+    Fail = 1,
+    #[serde(rename = "none")]
+    None = 2,
+    #[serde(rename = "temperror")]
+    TempError = 3,
+    #[serde(rename = "permerror")]
+    PermError = 4,
+}
+
+impl AuthResult {
+    pub fn from_tag(tag: &Tag) -> Option<Self> {
+        match tag {
+            Tag::Static(0) => AuthResult::Pass,
+            Tag::Static(1) => AuthResult::Fail,
+            Tag::Static(2) => AuthResult::None,
+            Tag::Static(3) => AuthResult::TempError,
+            Tag::Static(4) => AuthResult::PermError,
+            _ => return None,
+        }
+        .into()
+    }
+}
+
+impl From<AuthResult> for Tag {
+    fn from(result: AuthResult) -> Self {
+        Tag::Static(result as u8)
+    }
+}
+
+impl Display for AuthResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthResult::Pass => write!(f, "pass"),
+            AuthResult::Fail => write!(f, "fail"),
+            AuthResult::None => write!(f, "none"),
+            AuthResult::TempError => write!(f, "temperror"),
+            AuthResult::PermError => write!(f, "permerror"),
+        }
+    }
+}
+
+// Outcome of verifying the S/MIME or OpenPGP signature of a message
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignatureStatus {
+    Unknown = 0,
+    Signed = 1,
+    SignedVerified = 2,
+    SignedFailed = 3,
+    Encrypted = 4,
+}
+
+impl SignatureStatus {
+    pub fn from_tag(tag: &Tag) -> Option<Self> {
+        match tag {
+            Tag::Static(0) => SignatureStatus::Unknown,
+            Tag::Static(1) => SignatureStatus::Signed,
+            Tag::Static(2) => SignatureStatus::SignedVerified,
+            Tag::Static(3) => SignatureStatus::SignedFailed,
+            Tag::Static(4) => SignatureStatus::Encrypted,
+            _ => return None,
+        }
+        .into()
+    }
+}
+
+impl From<SignatureStatus> for Tag {
+    fn from(status: SignatureStatus) -> Self {
+        Tag::Static(status as u8)
+    }
+}
+
+impl Display for SignatureStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SignatureStatus::Unknown => write!(f, "unknown"),
+            SignatureStatus::Signed => write!(f, "signed"),
+            SignatureStatus::SignedVerified => write!(f, "signed/verified"),
+            SignatureStatus::SignedFailed => write!(f, "signed/failed"),
+            SignatureStatus::Encrypted => write!(f, "encrypted"),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum Property {
     Id,
@@ -238,6 +353,20 @@ pub enum Property {
     Headers,
     Header(HeaderProperty),
     Invalid(String),
+
+    // S/MIME verification (RFC 9219)
+    SmimeStatus,
+    SmimeStatusAtDelivery,
+    SmimeErrors,
+    SmimeVerifiedAt,
+
+    // Non-standard
+    AuthResult,
+    Template,
+    PgpStatus,
+    PgpStatusAtDelivery,
+    PgpErrors,
+    PgpVerifiedAt,
 }
 
 impl Property {
@@ -269,6 +398,16 @@ impl Property {
             "attachments" => Property::Attachments,
             "bodyStructure" => Property::BodyStructure,
             "headers" => Property::Headers,
+            "smimeStatus" => Property::SmimeStatus,
+            "smimeStatusAtDelivery" => Property::SmimeStatusAtDelivery,
+            "smimeErrors" => Property::SmimeErrors,
+            "smimeVerifiedAt" => Property::SmimeVerifiedAt,
+            "authResult" => Property::AuthResult,
+            "template" => Property::Template,
+            "pgpStatus" => Property::PgpStatus,
+            "pgpStatusAtDelivery" => Property::PgpStatusAtDelivery,
+            "pgpErrors" => Property::PgpErrors,
+            "pgpVerifiedAt" => Property::PgpVerifiedAt,
             _ if value.starts_with("header:") => {
                 if let Some(header) = HeaderProperty::parse(value) {
                     Property::Header(header)
@@ -331,6 +470,16 @@ impl Display for Property {
             Property::Attachments => write!(f, "attachments"),
             Property::BodyStructure => write!(f, "bodyStructure"),
             Property::Headers => write!(f, "headers"),
+            Property::SmimeStatus => write!(f, "smimeStatus"),
+            Property::SmimeStatusAtDelivery => write!(f, "smimeStatusAtDelivery"),
+            Property::SmimeErrors => write!(f, "smimeErrors"),
+            Property::SmimeVerifiedAt => write!(f, "smimeVerifiedAt"),
+            Property::AuthResult => write!(f, "authResult"),
+            Property::Template => write!(f, "template"),
+            Property::PgpStatus => write!(f, "pgpStatus"),
+            Property::PgpStatusAtDelivery => write!(f, "pgpStatusAtDelivery"),
+            Property::PgpErrors => write!(f, "pgpErrors"),
+            Property::PgpVerifiedAt => write!(f, "pgpVerifiedAt"),
             Property::Header(header) => header.fmt(f),
             Property::Invalid(value) => write!(f, "{}", value),
         }
@@ -358,6 +507,9 @@ pub enum BodyProperty {
     Language,
     Location,
     Subparts,
+
+    // Non-standard
+    EmailId,
 }
 
 impl BodyProperty {
@@ -375,6 +527,7 @@ impl BodyProperty {
             "language" => Some(BodyProperty::Language),
             "location" => Some(BodyProperty::Location),
             "subParts" => Some(BodyProperty::Subparts),
+            "emailId" => Some(BodyProperty::EmailId),
             _ if value.starts_with("header:") => {
                 Some(BodyProperty::Header(HeaderProperty::parse(value)?))
             }
@@ -399,6 +552,7 @@ impl Display for BodyProperty {
             BodyProperty::Language => write!(f, "language"),
             BodyProperty::Location => write!(f, "location"),
             BodyProperty::Subparts => write!(f, "subParts"),
+            BodyProperty::EmailId => write!(f, "emailId"),
         }
     }
 }
@@ -577,6 +731,9 @@ pub enum Value {
     Headers {
         value: Vec<EmailHeader>,
     },
+    Template {
+        value: EmailTemplate,
+    },
     Null,
 }
 
@@ -672,6 +829,9 @@ impl From<Property> for FieldId {
             Property::ThreadId => MessageField::ThreadId.into(),
             Property::MailboxIds => MessageField::Mailbox.into(),
             Property::Keywords => MessageField::Keyword.into(),
+            Property::AuthResult => MessageField::AuthResult.into(),
+            Property::SmimeStatusAtDelivery => MessageField::SmimeStatus.into(),
+            Property::PgpStatusAtDelivery => MessageField::PgpStatus.into(),
             Property::Id => 0,
             Property::BlobId => 1,
             Property::Size => 2,
@@ -697,6 +857,13 @@ impl From<Property> for FieldId {
             Property::Headers => 22,
             Property::Header(_) => 23,
             Property::Invalid(_) => 24,
+            Property::Template => 25,
+            Property::SmimeStatus => 26,
+            Property::SmimeErrors => 27,
+            Property::SmimeVerifiedAt => 28,
+            Property::PgpStatus => 29,
+            Property::PgpErrors => 30,
+            Property::PgpVerifiedAt => 31,
         }
     }
 }
@@ -727,9 +894,19 @@ impl From<FieldId> for Property {
             20 => Property::Attachments,
             21 => Property::BodyStructure,
             22 => Property::Headers,
+            25 => Property::Template,
+            26 => Property::SmimeStatus,
+            27 => Property::SmimeErrors,
+            28 => Property::SmimeVerifiedAt,
+            29 => Property::PgpStatus,
+            30 => Property::PgpErrors,
+            31 => Property::PgpVerifiedAt,
             136 => Property::ThreadId,
             137 => Property::MailboxIds,
             132 => Property::Keywords,
+            139 => Property::AuthResult,
+            141 => Property::SmimeStatusAtDelivery,
+            142 => Property::PgpStatusAtDelivery,
             _ => Property::Invalid("".into()),
         }
     }
@@ -768,6 +945,8 @@ pub enum Filter {
     Subject { value: String },
     Body { value: String },
     Header { value: Vec<String> },
+    HasSmime { value: bool },
+    HasVerifiedSmimeAtDelivery { value: bool },
     Unsupported { value: String },
 
     // Non-standard
@@ -775,6 +954,10 @@ pub enum Filter {
     SentBefore { value: JMAPDate },
     SentAfter { value: JMAPDate },
     InThread { value: JMAPId },
+    AuthResult { value: AuthResult },
+    InSavedSearch { value: JMAPId },
+    HasPgp { value: bool },
+    HasVerifiedPgpAtDelivery { value: bool },
 }
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
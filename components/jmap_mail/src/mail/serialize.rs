@@ -37,8 +37,8 @@ use super::{
     get::GetArguments,
     import::EmailImport,
     schema::{
-        BodyProperty, Email, EmailAddress, EmailBodyPart, EmailHeader, Filter, HeaderForm,
-        HeaderProperty, Keyword, Property, Value,
+        BodyProperty, Email, EmailAddress, EmailBodyPart, EmailHeader, EmailTemplate, Filter,
+        HeaderForm, HeaderProperty, Keyword, Property, Value,
     },
     search_snippet::SearchSnippetGetRequest,
 };
@@ -73,6 +73,7 @@ impl Serialize for Email {
                 Value::GroupedAddresses { value } => map.serialize_entry(name, value)?,
                 Value::GroupedAddressesList { value } => map.serialize_entry(name, value)?,
                 Value::Headers { value } => map.serialize_entry(name, value)?,
+                Value::Template { value } => map.serialize_entry(name, value)?,
                 Value::Null => map.serialize_entry(name, &None::<&str>)?,
             }
         }
@@ -245,6 +246,11 @@ impl<'de> serde::de::Visitor<'de> for EmailVisitor {
                         properties.append(Property::Headers, Value::Headers { value });
                     }
                 }
+                "template" => {
+                    if let Some(value) = map.next_value::<Option<EmailTemplate>>()? {
+                        properties.append(Property::Template, Value::Template { value });
+                    }
+                }
                 _ if key.starts_with('#') => {
                     if let Some(property) = key.get(1..) {
                         properties.append(
@@ -419,6 +425,7 @@ impl Serialize for EmailBodyPart {
                 Value::GroupedAddresses { value } => map.serialize_entry(name, value)?,
                 Value::GroupedAddressesList { value } => map.serialize_entry(name, value)?,
                 Value::Headers { value } => map.serialize_entry(name, value)?,
+                Value::Template { value } => map.serialize_entry(name, value)?,
                 Value::Null => map.serialize_entry(name, &None::<&str>)?,
             }
         }
@@ -498,6 +505,11 @@ impl<'de> serde::de::Visitor<'de> for EmailBodyPartVisitor {
                         properties.append(BodyProperty::Subparts, Value::BodyPartList { value });
                     }
                 }
+                "emailId" => {
+                    if let Some(value) = map.next_value::<Option<JMAPId>>()? {
+                        properties.append(BodyProperty::EmailId, Value::Id { value });
+                    }
+                }
                 "headers" => {
                     if let Some(value) = map.next_value::<Option<Vec<EmailHeader>>>()? {
                         properties.append(BodyProperty::Headers, Value::Headers { value });
@@ -824,6 +836,12 @@ impl FilterDeserializer for Filter {
             "header" => Filter::Header {
                 value: map.next_value().ok()?,
             },
+            "hasSmime" => Filter::HasSmime {
+                value: map.next_value().ok()?,
+            },
+            "hasVerifiedSmimeAtDelivery" => Filter::HasVerifiedSmimeAtDelivery {
+                value: map.next_value().ok()?,
+            },
 
             // Non-standard
             "id" => Filter::Id {
@@ -838,6 +856,18 @@ impl FilterDeserializer for Filter {
             "inThread" => Filter::InThread {
                 value: map.next_value().ok()?,
             },
+            "authResult" => Filter::AuthResult {
+                value: map.next_value().ok()?,
+            },
+            "inSavedSearch" => Filter::InSavedSearch {
+                value: map.next_value().ok()?,
+            },
+            "hasPgp" => Filter::HasPgp {
+                value: map.next_value().ok()?,
+            },
+            "hasVerifiedPgpAtDelivery" => Filter::HasVerifiedPgpAtDelivery {
+                value: map.next_value().ok()?,
+            },
 
             unsupported => {
                 map.next_value::<IgnoredAny>().ok()?;
@@ -21,13 +21,17 @@
  * for more details.
 */
 
+use super::compose::JMAPMailCompose;
+use super::duplicate::clear_duplicate_key;
 use super::get::{BlobResult, JMAPGetMail};
 use super::schema::{
     BodyProperty, Email, EmailBodyPart, EmailBodyValue, HeaderForm, Keyword, Property, Value,
 };
 use super::sharing::JMAPShareMail;
+use super::uid::JMAPMailUids;
 use super::{HeaderName, MessageData, MessageField};
 use crate::mail::import::JMAPMailImport;
+use crate::thread::metadata::JMAPThreadMetadata;
 use jmap::error::set::{SetError, SetErrorType};
 use jmap::jmap_store::set::{SetHelper, SetObject};
 use jmap::orm::{serialize::JMAPOrm, TinyORM};
@@ -45,6 +49,7 @@ use mail_builder::headers::url::URL;
 use mail_builder::mime::{BodyPart, MimePart};
 use mail_builder::MessageBuilder;
 use mail_parser::{Message, RfcHeader};
+use std::borrow::Cow;
 use std::sync::Arc;
 use store::ahash::AHashSet;
 use store::blob::BlobId;
@@ -137,6 +142,21 @@ where
         helper.disable_write_batch();
 
         helper.create(|_create_id, item, helper, document| {
+            // Obtain the reply or forward template, if requested
+            let template = match item.properties.get(&Property::Template) {
+                Some(Value::Template { value }) => Some(
+                    self.mail_compose_source(account_id, &helper.acl, value.email_id)?
+                        .template(
+                            value.type_,
+                            match item.properties.get(&Property::From) {
+                                Some(Value::Addresses { value }) => value.as_slice(),
+                                _ => &[],
+                            },
+                        ),
+                ),
+                _ => None,
+            };
+
             let mut builder = MessageBuilder::new();
             let mut fields = TinyORM::<Email>::new();
 
@@ -148,7 +168,7 @@ where
                     Value::BodyValues { value } => Some(value),
                     _ => None,
                 });
-            let max_size_attachments = helper.store.config.mail_attachments_max_size;
+            let max_size_attachments = helper.store.config.mail_attachments_max_size.get();
             let mut size_attachments = 0;
 
             for (property, value) in &item.properties {
@@ -474,6 +494,59 @@ where
                 }
             }
 
+            // Add template headers not explicitly set and the quoted text
+            if let Some(template) = &template {
+                if let Some(subject) = template
+                    .subject
+                    .as_ref()
+                    .filter(|_| !item.properties.contains_key(&Property::Subject))
+                {
+                    builder = builder.subject(subject);
+                }
+                for (property, addresses) in
+                    [(Property::To, &template.to), (Property::Cc, &template.cc)]
+                {
+                    if !addresses.is_empty() && !item.properties.contains_key(&property) {
+                        builder = builder.header(
+                            property.as_rfc_header(),
+                            Address::new_list(addresses.iter().map(|x| x.into()).collect()),
+                        );
+                    }
+                }
+                for (property, ids) in [
+                    (Property::InReplyTo, &template.in_reply_to),
+                    (Property::References, &template.references),
+                ] {
+                    if !ids.is_empty() && !item.properties.contains_key(&property) {
+                        builder = builder
+                            .header(property.as_rfc_header(), MessageId::from(ids.as_slice()));
+                    }
+                }
+                if !item.properties.contains_key(&Property::BodyStructure) {
+                    match &mut builder.text_body {
+                        Some(MimePart {
+                            contents: BodyPart::Text(text),
+                            ..
+                        }) => {
+                            *text = format!("{}\n\n{}", text.trim_end(), template.text).into();
+                        }
+                        None if builder.html_body.is_none() => {
+                            builder.text_body = MimePart {
+                                headers: vec![(
+                                    "Content-Type".into(),
+                                    ContentType::new("text/plain")
+                                        .attribute("charset", "utf-8")
+                                        .into(),
+                                )],
+                                contents: BodyPart::Text(template.text.as_str().into()),
+                            }
+                            .into();
+                        }
+                        _ => (),
+                    }
+                }
+            }
+
             // Make sure the message is at least in one mailbox
             if !fields.has_tags(&Property::MailboxIds) {
                 return Err(SetError::new(
@@ -551,6 +624,7 @@ where
                 received_at,
             )?;
             fields.insert(document)?;
+            self.mail_assign_uids(&mut helper.changes, document)?;
 
             // Store blob
             self.blob_store(&blob_id, blob)?;
@@ -742,6 +816,19 @@ where
 
             // Merge changes
             current_fields.merge_validate(document, fields)?;
+            self.mail_assign_uids(&mut helper.changes, document)?;
+
+            // Update the thread's keyword counts
+            if !changed_tags.is_empty() {
+                if let Some(thread_id) = self.get_document_value::<DocumentId>(
+                    account_id,
+                    Collection::Mail,
+                    document.document_id,
+                    MessageField::ThreadId.into(),
+                )? {
+                    self.thread_metadata_keywords(&mut helper.changes, thread_id, document)?;
+                }
+            }
 
             Ok(None)
         })?;
@@ -802,6 +889,7 @@ where
             ))
         })?
         .build_index(document, false)?;
+        clear_duplicate_key(self, account_id, document)?;
 
         // Remove thread related data
         let thread_id = self
@@ -855,8 +943,10 @@ where
                 Tag::Id(thread_id),
             )? {
                 if message_doc_ids.len() > 1 {
+                    self.thread_metadata_rebuild(batch, thread_id, document_id)?;
                     batch.log_child_update(Collection::Thread, thread_id);
                 } else {
+                    self.thread_metadata_delete(batch, thread_id);
                     batch.log_delete(Collection::Thread, thread_id);
                 }
             } else {
@@ -871,6 +961,7 @@ where
 
         // Delete ORM
         fields.delete(document);
+        self.mail_clear_uids(document);
 
         Ok(JMAPId::from_parts(thread_id, document_id).into())
     }
@@ -888,9 +979,34 @@ impl EmailBodyPart {
     where
         T: for<'x> Store<'x> + 'static,
     {
+        // Parts can be inherited from another email, or the whole email attached
+        let mut inherited = if let Some(email_id) = self.get_id(BodyProperty::EmailId) {
+            if self.properties.contains_key(&BodyProperty::BlobId) {
+                return Err(SetError::new(
+                    SetErrorType::InvalidProperties,
+                    "Cannot specify both \"emailId\" and \"blobId\".".to_string(),
+                ));
+            }
+            let source = store.mail_compose_source(account_id, acl, email_id)?;
+            if let Some(part_id) = self.get_text(BodyProperty::PartId) {
+                source.part(part_id).ok_or_else(|| {
+                    SetError::new(
+                        SetErrorType::InvalidProperties,
+                        format!("Part {} not found in email {}.", part_id, email_id),
+                    )
+                })?
+            } else {
+                source.into_attachment()
+            }
+            .into()
+        } else {
+            None
+        };
+
         let content_type = self
             .get_text(BodyProperty::Type)
             .map(|v| v.to_string())
+            .or_else(|| inherited.as_ref().map(|part| part.type_.clone()))
             .unwrap_or_else(|| "text/plain".to_string());
 
         if matches!(strict_type, Some(strict_type) if strict_type != content_type) {
@@ -907,7 +1023,15 @@ impl EmailBodyPart {
         let mut mime_part = MimePart {
             headers: Vec::new(),
             contents: if is_multipart {
+                if inherited.is_some() {
+                    return Err(SetError::new(
+                        SetErrorType::InvalidProperties,
+                        "Cannot specify \"emailId\" in a multipart body part.".to_string(),
+                    ));
+                }
                 BodyPart::Multipart(vec![])
+            } else if let Some(inherited) = &mut inherited {
+                BodyPart::Binary(std::mem::take(&mut inherited.contents).into())
             } else if let Some(part_id) = self.get_text(BodyProperty::PartId) {
                 if self.properties.contains_key(&BodyProperty::BlobId) {
                     return Err(SetError::new(
@@ -978,16 +1102,31 @@ impl EmailBodyPart {
                     content_type
                         .attributes
                         .push(("charset".into(), "utf-8".into()));
-                } else if let Some(charset) = self.get_text(BodyProperty::Charset) {
+                } else if let Some(charset) = self
+                    .get_text(BodyProperty::Charset)
+                    .or_else(|| inherited.as_ref().and_then(|part| part.charset.as_deref()))
+                {
                     content_type
                         .attributes
-                        .push(("charset".into(), charset.into()));
+                        .push(("charset".into(), charset.to_string().into()));
                 };
             }
 
             match (
-                self.get_text(BodyProperty::Disposition),
-                self.get_text(BodyProperty::Name),
+                self.get_text(BodyProperty::Disposition)
+                    .map(Cow::from)
+                    .or_else(|| {
+                        inherited
+                            .as_ref()
+                            .and_then(|part| part.disposition.clone().map(Cow::from))
+                    }),
+                self.get_text(BodyProperty::Name)
+                    .map(Cow::from)
+                    .or_else(|| {
+                        inherited
+                            .as_ref()
+                            .and_then(|part| part.name.clone().map(Cow::from))
+                    }),
             ) {
                 (Some(disposition), Some(filename)) => {
                     mime_part.headers.push((
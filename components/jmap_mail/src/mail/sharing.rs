@@ -59,6 +59,7 @@ where
         shared_to: &[AccountId],
         acl: ACL,
     ) -> store::Result<Arc<Option<RoaringBitmap>>> {
+        self.cache_stats.shared_documents.request();
         self.shared_documents
             .try_get_with::<_, StoreError>(
                 SharedResource::new(
@@ -68,6 +69,7 @@ where
                     acl,
                 ),
                 || {
+                    self.cache_stats.shared_documents.miss();
                     Ok(Arc::new(self.get_shared_documents(
                         shared_to,
                         owner_id,
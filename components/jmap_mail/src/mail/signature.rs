@@ -0,0 +1,528 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::schema::SignatureStatus;
+use mail_parser::{
+    ContentType, HeaderName, HeaderValue, Message, MessagePart, PartType, RfcHeader,
+};
+use openssl::nid::Nid;
+use openssl::pkcs7::{Pkcs7, Pkcs7Flags};
+use openssl::stack::Stack;
+use openssl::x509::store::{X509Store, X509StoreBuilder};
+use openssl::x509::X509;
+use pgp::{Deserializable, SignedPublicKey, StandaloneSignature};
+use std::io::Cursor;
+use std::path::Path;
+use store::config::jmap::JMAPConfig;
+use store::tracing::error;
+
+pub struct SignatureResult {
+    pub status: SignatureStatus,
+    pub errors: Vec<String>,
+}
+
+// Verifies S/MIME and OpenPGP/MIME signatures, loading the trust
+// material the first time a signed message is seen.
+pub struct SignatureVerifier<'x> {
+    config: &'x JMAPConfig,
+    smime: Option<Option<X509Store>>,
+    pgp: Option<Vec<SignedPublicKey>>,
+}
+
+enum Envelope<'x> {
+    Detached {
+        content: Vec<u8>,
+        signature: &'x [u8],
+    },
+    Opaque {
+        data: &'x [u8],
+    },
+    Encrypted,
+}
+
+impl<'x> SignatureVerifier<'x> {
+    pub fn new(config: &'x JMAPConfig) -> Self {
+        SignatureVerifier {
+            config,
+            smime: None,
+            pgp: None,
+        }
+    }
+
+    pub fn verify_smime(&mut self, message: &Message) -> Option<SignatureResult> {
+        let envelope = smime_envelope(message)?;
+        if let Envelope::Encrypted = envelope {
+            return SignatureResult::new(SignatureStatus::Encrypted, Vec::new()).into();
+        }
+
+        let config = self.config;
+        let store = self.smime.get_or_insert_with(|| {
+            let path = config.smime_trust_store.as_ref()?;
+            load_trust_store(path)
+                .map_err(|err| {
+                    error!(
+                        "Failed to load S/MIME trust store {}: {}",
+                        path.display(),
+                        err
+                    );
+                })
+                .ok()
+        });
+
+        if let Some(store) = store {
+            match verify_pkcs7(store, &envelope, &from_addresses(message)) {
+                Ok(()) => SignatureResult::new(SignatureStatus::SignedVerified, Vec::new()),
+                Err(errors) => SignatureResult::new(SignatureStatus::SignedFailed, errors),
+            }
+        } else {
+            SignatureResult::new(SignatureStatus::Signed, Vec::new())
+        }
+        .into()
+    }
+
+    pub fn verify_pgp(&mut self, message: &Message) -> Option<SignatureResult> {
+        let (content, signature) = match pgp_envelope(message)? {
+            Envelope::Detached { content, signature } => (content, signature),
+            _ => return SignatureResult::new(SignatureStatus::Encrypted, Vec::new()).into(),
+        };
+
+        let config = self.config;
+        let keys = self.pgp.get_or_insert_with(|| {
+            if let Some(path) = config.pgp_keyring.as_ref() {
+                load_keyring(path).unwrap_or_else(|err| {
+                    error!("Failed to load OpenPGP keyring {}: {}", path.display(), err);
+                    Vec::new()
+                })
+            } else {
+                Vec::new()
+            }
+        });
+
+        if !keys.is_empty() {
+            match verify_openpgp(keys, &content, signature, &from_addresses(message)) {
+                Ok(()) => SignatureResult::new(SignatureStatus::SignedVerified, Vec::new()),
+                Err(errors) => SignatureResult::new(SignatureStatus::SignedFailed, errors),
+            }
+        } else {
+            SignatureResult::new(SignatureStatus::Signed, Vec::new())
+        }
+        .into()
+    }
+}
+
+impl SignatureResult {
+    pub fn new(status: SignatureStatus, errors: Vec<String>) -> Self {
+        SignatureResult { status, errors }
+    }
+}
+
+fn load_trust_store(path: &Path) -> Result<X509Store, String> {
+    let pem = std::fs::read(path).map_err(|err| err.to_string())?;
+    let mut builder = X509StoreBuilder::new().map_err(|err| err.to_string())?;
+    for cert in X509::stack_from_pem(&pem).map_err(|err| err.to_string())? {
+        builder.add_cert(cert).map_err(|err| err.to_string())?;
+    }
+    Ok(builder.build())
+}
+
+fn load_keyring(path: &Path) -> Result<Vec<SignedPublicKey>, String> {
+    let armored = std::fs::read(path).map_err(|err| err.to_string())?;
+    SignedPublicKey::from_armor_many(Cursor::new(armored))
+        .map_err(|err| err.to_string())?
+        .0
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| err.to_string())
+}
+
+fn verify_pkcs7(
+    store: &X509Store,
+    envelope: &Envelope,
+    from: &[String],
+) -> Result<(), Vec<String>> {
+    let (der, content) = match envelope {
+        Envelope::Detached { content, signature } => (*signature, Some(content.as_slice())),
+        Envelope::Opaque { data } => (*data, None),
+        Envelope::Encrypted => return Err(vec!["Message is encrypted.".to_string()]),
+    };
+    let pkcs7 =
+        Pkcs7::from_der(der).map_err(|err| vec![format!("Invalid S/MIME signature: {}", err)])?;
+    let certs = Stack::<X509>::new().map_err(|err| vec![err.to_string()])?;
+    let mut out = Vec::new();
+    pkcs7
+        .verify(
+            &certs,
+            store,
+            content,
+            if content.is_none() {
+                Some(&mut out)
+            } else {
+                None
+            },
+            Pkcs7Flags::BINARY,
+        )
+        .map_err(|err| {
+            err.errors()
+                .iter()
+                .map(|err| {
+                    err.reason()
+                        .unwrap_or("Signature verification failed.")
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        })?;
+
+    // The signing certificate has to belong to the sender
+    if from.is_empty() {
+        return Err(vec!["Message has no From address.".to_string()]);
+    }
+    let signers = pkcs7
+        .signers(&certs, Pkcs7Flags::empty())
+        .map_err(|err| vec![err.to_string()])?;
+    if signers.iter().any(|cert| {
+        cert.subject_alt_names()
+            .into_iter()
+            .flatten()
+            .filter_map(|name| name.email().map(|email| email.to_lowercase()))
+            .chain(
+                cert.subject_name()
+                    .entries_by_nid(Nid::PKCS9_EMAILADDRESS)
+                    .filter_map(|entry| entry.data().as_utf8().ok())
+                    .map(|email| email.to_lowercase()),
+            )
+            .any(|email| from.contains(&email))
+    }) {
+        Ok(())
+    } else {
+        Err(vec![
+            "Signer certificate does not match the From address.".to_string()
+        ])
+    }
+}
+
+fn verify_openpgp(
+    keys: &[SignedPublicKey],
+    content: &[u8],
+    signature: &[u8],
+    from: &[String],
+) -> Result<(), Vec<String>> {
+    let armored = std::str::from_utf8(signature)
+        .map_err(|_| vec!["OpenPGP signature is not ASCII armored.".to_string()])?;
+    let signature = StandaloneSignature::from_string(armored)
+        .map_err(|err| vec![format!("Invalid OpenPGP signature: {}", err)])?
+        .0;
+    let key = keys
+        .iter()
+        .find(|key| {
+            signature.verify(*key, content).is_ok()
+                || key
+                    .public_subkeys
+                    .iter()
+                    .any(|subkey| signature.verify(subkey, content).is_ok())
+        })
+        .ok_or_else(|| vec!["No trusted key verifies the OpenPGP signature.".to_string()])?;
+
+    // One of the key's user ids has to belong to the sender
+    if from.is_empty() {
+        return Err(vec!["Message has no From address.".to_string()]);
+    }
+    if key.details.users.iter().any(|user| {
+        let id = user.id.id().to_lowercase();
+        from.iter()
+            .any(|email| id == *email || id.contains(&format!("<{}>", email)))
+    }) {
+        Ok(())
+    } else {
+        Err(vec![
+            "Signing key does not match the From address.".to_string()
+        ])
+    }
+}
+
+// Returns true if the message is an S/MIME or OpenPGP/MIME encrypted envelope
+pub fn is_encrypted(message: &Message) -> bool {
+    matches!(smime_envelope(message), Some(Envelope::Encrypted))
+        || matches!(pgp_envelope(message), Some(Envelope::Encrypted))
+}
+
+fn smime_envelope<'x>(message: &'x Message) -> Option<Envelope<'x>> {
+    let root = message.parts.first()?;
+    let content_type = content_type(root)?;
+    match (
+        content_type.c_type.to_ascii_lowercase().as_str(),
+        content_type
+            .c_subtype
+            .as_ref()?
+            .to_ascii_lowercase()
+            .as_str(),
+    ) {
+        ("multipart", "signed")
+            if matches!(
+                content_type
+                    .get_attribute("protocol")
+                    .map(|p| p.to_ascii_lowercase())
+                    .as_deref(),
+                Some("application/pkcs7-signature" | "application/x-pkcs7-signature")
+            ) =>
+        {
+            detached_envelope(message, root)
+        }
+        ("application", "pkcs7-mime" | "x-pkcs7-mime") => {
+            match content_type
+                .get_attribute("smime-type")
+                .map(|t| t.to_ascii_lowercase())
+                .as_deref()
+            {
+                Some("signed-data") => Envelope::Opaque {
+                    data: part_contents(root)?,
+                }
+                .into(),
+                Some("enveloped-data" | "authenveloped-data") | None => Envelope::Encrypted.into(),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn pgp_envelope<'x>(message: &'x Message) -> Option<Envelope<'x>> {
+    let root = message.parts.first()?;
+    let content_type = content_type(root)?;
+    let protocol = content_type.get_attribute("protocol")?.to_ascii_lowercase();
+    match (
+        content_type.c_type.to_ascii_lowercase().as_str(),
+        content_type
+            .c_subtype
+            .as_ref()?
+            .to_ascii_lowercase()
+            .as_str(),
+        protocol.as_str(),
+    ) {
+        ("multipart", "signed", "application/pgp-signature") => detached_envelope(message, root),
+        ("multipart", "encrypted", "application/pgp-encrypted") => Envelope::Encrypted.into(),
+        _ => None,
+    }
+}
+
+fn detached_envelope<'x>(message: &'x Message, root: &MessagePart) -> Option<Envelope<'x>> {
+    if let PartType::Multipart(subparts) = &root.body {
+        let content = message.parts.get(*subparts.first()?)?;
+        let signature = message.parts.get(*subparts.get(1)?)?;
+        Envelope::Detached {
+            content: canonicalize(
+                message
+                    .raw_message
+                    .get(content.offset_header..content.offset_end)?,
+            ),
+            signature: part_contents(signature)?,
+        }
+        .into()
+    } else {
+        None
+    }
+}
+
+fn content_type<'x, 'y>(part: &'y MessagePart<'x>) -> Option<&'y ContentType<'x>> {
+    part.headers
+        .iter()
+        .find_map(|header| match (&header.name, &header.value) {
+            (HeaderName::Rfc(RfcHeader::ContentType), HeaderValue::ContentType(content_type)) => {
+                Some(content_type)
+            }
+            _ => None,
+        })
+}
+
+fn part_contents<'x>(part: &'x MessagePart) -> Option<&'x [u8]> {
+    match &part.body {
+        PartType::Binary(bytes) | PartType::InlineBinary(bytes) => Some(bytes.as_ref()),
+        PartType::Text(text) | PartType::Html(text) => Some(text.as_bytes()),
+        _ => None,
+    }
+}
+
+fn from_addresses(message: &Message) -> Vec<String> {
+    let mut addresses = Vec::new();
+    match message.get_from() {
+        HeaderValue::Address(addr) => addresses.extend(addr.address.as_deref()),
+        HeaderValue::AddressList(list) => {
+            addresses.extend(list.iter().filter_map(|addr| addr.address.as_deref()))
+        }
+        HeaderValue::Group(group) => addresses.extend(
+            group
+                .addresses
+                .iter()
+                .filter_map(|addr| addr.address.as_deref()),
+        ),
+        HeaderValue::GroupList(groups) => addresses.extend(
+            groups
+                .iter()
+                .flat_map(|group| group.addresses.iter())
+                .filter_map(|addr| addr.address.as_deref()),
+        ),
+        _ => (),
+    }
+    addresses
+        .into_iter()
+        .map(|addr| addr.trim().to_lowercase())
+        .collect()
+}
+
+// Signed content is verified with CRLF line endings
+fn canonicalize(content: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(content.len() + 64);
+    let mut last_ch = 0;
+    for &ch in content {
+        if ch == b'\n' && last_ch != b'\r' {
+            result.push(b'\r');
+        }
+        result.push(ch);
+        last_ch = ch;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonicalize, pgp_envelope, smime_envelope, verify_pkcs7, Envelope};
+    use mail_parser::Message;
+    use openssl::{
+        asn1::Asn1Time,
+        hash::MessageDigest,
+        pkcs7::{Pkcs7, Pkcs7Flags},
+        pkey::PKey,
+        rsa::Rsa,
+        stack::Stack,
+        x509::{extension::SubjectAlternativeName, store::X509StoreBuilder, X509NameBuilder, X509},
+    };
+
+    const SIGNED: &str = concat!(
+        "From: jdoe@example.org\r\n",
+        "Content-Type: multipart/signed; protocol=\"{}\";\r\n",
+        " micalg=sha-256; boundary=\"b1\"\r\n",
+        "\r\n",
+        "--b1\r\n",
+        "Content-Type: text/plain\r\n",
+        "\r\n",
+        "Hello\r\n",
+        "--b1\r\n",
+        "Content-Type: {}\r\n",
+        "\r\n",
+        "SIGNATURE\r\n",
+        "--b1--\r\n"
+    );
+
+    #[test]
+    fn canonical_line_endings() {
+        assert_eq!(canonicalize(b"a\nb\r\nc\n"), b"a\r\nb\r\nc\r\n".to_vec());
+    }
+
+    #[test]
+    fn detect_envelopes() {
+        for (protocol, is_smime) in [
+            ("application/pkcs7-signature", true),
+            ("application/pgp-signature", false),
+        ] {
+            let raw = SIGNED.replace("{}", protocol);
+            let message = Message::parse(raw.as_bytes()).unwrap();
+            let envelope = if is_smime {
+                assert!(pgp_envelope(&message).is_none());
+                smime_envelope(&message)
+            } else {
+                assert!(smime_envelope(&message).is_none());
+                pgp_envelope(&message)
+            };
+            match envelope {
+                Some(Envelope::Detached { content, signature }) => {
+                    assert_eq!(content, b"Content-Type: text/plain\r\n\r\nHello".to_vec());
+                    assert_eq!(signature, b"SIGNATURE");
+                }
+                _ => panic!("Expected a detached signature for {}.", protocol),
+            }
+        }
+
+        let message = Message::parse(
+            b"Content-Type: application/pkcs7-mime; smime-type=enveloped-data\r\n\r\nAAAA\r\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            smime_envelope(&message),
+            Some(Envelope::Encrypted)
+        ));
+    }
+
+    #[test]
+    fn smime_signer_matches_from() {
+        let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "John Doe").unwrap();
+        let name = name.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_version(2).unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        let alt_names = SubjectAlternativeName::new()
+            .email("jdoe@example.org")
+            .build(&cert.x509v3_context(None, None))
+            .unwrap();
+        cert.append_extension(alt_names).unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+        let cert = cert.build();
+
+        let content = b"Content-Type: text/plain\r\n\r\nHello".to_vec();
+        let signature = Pkcs7::sign(
+            &cert,
+            &key,
+            &Stack::<X509>::new().unwrap(),
+            &content,
+            Pkcs7Flags::DETACHED | Pkcs7Flags::BINARY,
+        )
+        .unwrap()
+        .to_der()
+        .unwrap();
+        let mut store = X509StoreBuilder::new().unwrap();
+        store.add_cert(cert).unwrap();
+        let store = store.build();
+        let envelope = Envelope::Detached {
+            content,
+            signature: &signature,
+        };
+
+        for (from, is_verified) in [
+            (vec!["jdoe@example.org".to_string()], true),
+            (vec!["jane@example.org".to_string()], false),
+            (vec![], false),
+        ] {
+            assert_eq!(
+                verify_pkcs7(&store, &envelope, &from).is_ok(),
+                is_verified,
+                "{:?}",
+                from
+            );
+        }
+    }
+}
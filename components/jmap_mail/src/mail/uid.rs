@@ -0,0 +1,281 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::{
+    bincode,
+    core::{collection::Collection, document::Document, tag::Tag, vec_map::VecMap},
+    serialize::{StoreDeserialize, StoreSerialize},
+    write::{
+        batch::{WriteAction, WriteBatch},
+        options::{IndexOptions, Options},
+    },
+    AccountId, DocumentId, FieldId, JMAPStore, Store,
+};
+
+use crate::mailbox::MailboxField;
+
+use super::MessageField;
+
+// IMAP UIDs assigned to a message, by mailbox. Messages stored before UIDs
+// were tracked have no entry, their UID in every mailbox is their document
+// id plus one.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct MessageUids {
+    pub uids: VecMap<DocumentId, u32>,
+}
+
+impl StoreSerialize for MessageUids {
+    fn serialize(&self) -> Option<Vec<u8>> {
+        bincode::serialize(self).ok()
+    }
+}
+
+impl StoreDeserialize for MessageUids {
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize_from(bytes).ok()
+    }
+}
+
+pub trait JMAPMailUids<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn mail_assign_uids(
+        &self,
+        batch: &mut WriteBatch,
+        document: &mut Document,
+    ) -> store::Result<()>;
+
+    fn mail_clear_uids(&self, document: &mut Document);
+
+    fn mail_mailbox_uids(
+        &self,
+        account_id: AccountId,
+        mailbox_id: DocumentId,
+    ) -> store::Result<Vec<(u32, DocumentId)>>;
+
+    fn mail_uid(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        mailbox_id: DocumentId,
+    ) -> store::Result<Option<u32>>;
+
+    fn mail_uid_next(&self, account_id: AccountId, mailbox_id: DocumentId) -> store::Result<u32>;
+}
+
+impl<T> JMAPMailUids<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Gives the message a new UID in every mailbox it is being added to and
+    // drops the UIDs of the mailboxes it is being removed from. Must be called
+    // with the Mail collection locked, after the message's mailbox tags have
+    // been added to the document.
+    fn mail_assign_uids(
+        &self,
+        batch: &mut WriteBatch,
+        document: &mut Document,
+    ) -> store::Result<()> {
+        let mut added = Vec::new();
+        let mut removed = Vec::new();
+        for field in &document.tag_fields {
+            if field.field == MessageField::Mailbox as FieldId {
+                if let Tag::Id(mailbox_id) = field.value {
+                    if field.is_clear() {
+                        removed.push(mailbox_id);
+                    } else {
+                        added.push(mailbox_id);
+                    }
+                }
+            }
+        }
+        if added.is_empty() && removed.is_empty() {
+            return Ok(());
+        }
+
+        let mut message_uids = self
+            .get_document_value::<MessageUids>(
+                batch.account_id,
+                Collection::Mail,
+                document.document_id,
+                MessageField::ImapUid.into(),
+            )?
+            .unwrap_or_default();
+        for mailbox_id in removed {
+            message_uids.uids.remove(&mailbox_id);
+        }
+        for mailbox_id in added {
+            // UIDs are never reused, a message that is moved or copied back
+            // into a mailbox is always given a new one.
+            let uid = assign_uid(self, batch, mailbox_id)?;
+            message_uids.uids.set(mailbox_id, uid);
+        }
+
+        document.binary(
+            MessageField::ImapUid,
+            message_uids.serialize().unwrap(),
+            IndexOptions::new(),
+        );
+        Ok(())
+    }
+
+    fn mail_clear_uids(&self, document: &mut Document) {
+        document.binary(
+            MessageField::ImapUid,
+            Vec::with_capacity(0),
+            IndexOptions::new().clear(),
+        );
+    }
+
+    // Returns the UIDs of the messages in a mailbox paired with their
+    // document ids, in ascending UID order.
+    fn mail_mailbox_uids(
+        &self,
+        account_id: AccountId,
+        mailbox_id: DocumentId,
+    ) -> store::Result<Vec<(u32, DocumentId)>> {
+        let document_ids = if let Some(document_ids) = self.get_tag(
+            account_id,
+            Collection::Mail,
+            MessageField::Mailbox.into(),
+            Tag::Id(mailbox_id),
+        )? {
+            document_ids
+        } else {
+            return Ok(Vec::new());
+        };
+
+        let mut uids = document_ids
+            .iter()
+            .zip(self.get_multi_document_value::<MessageUids>(
+                account_id,
+                Collection::Mail,
+                document_ids.iter(),
+                MessageField::ImapUid.into(),
+            )?)
+            .map(|(document_id, message_uids)| {
+                (
+                    message_uids
+                        .and_then(|message_uids| message_uids.uids.get(&mailbox_id).copied())
+                        .unwrap_or(document_id + 1),
+                    document_id,
+                )
+            })
+            .collect::<Vec<_>>();
+        uids.sort_unstable();
+        Ok(uids)
+    }
+
+    // Returns the UID of a message in a mailbox, if it belongs to it.
+    fn mail_uid(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+        mailbox_id: DocumentId,
+    ) -> store::Result<Option<u32>> {
+        if !self
+            .get_tag(
+                account_id,
+                Collection::Mail,
+                MessageField::Mailbox.into(),
+                Tag::Id(mailbox_id),
+            )?
+            .map_or(false, |document_ids| document_ids.contains(document_id))
+        {
+            return Ok(None);
+        }
+        Ok(Some(
+            self.get_document_value::<MessageUids>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::ImapUid.into(),
+            )?
+            .and_then(|message_uids| message_uids.uids.get(&mailbox_id).copied())
+            .unwrap_or(document_id + 1),
+        ))
+    }
+
+    // Returns the UID the next message added to a mailbox will be given.
+    fn mail_uid_next(&self, account_id: AccountId, mailbox_id: DocumentId) -> store::Result<u32> {
+        if let Some(uid_next) = self.get_document_value::<u32>(
+            account_id,
+            Collection::Mailbox,
+            mailbox_id,
+            MailboxField::UidNext.into(),
+        )? {
+            Ok(uid_next)
+        } else {
+            // Mailboxes created before UIDs were tracked continue after
+            // the highest UID derived from a document id.
+            Ok(self
+                .get_tag(
+                    account_id,
+                    Collection::Mail,
+                    MessageField::Mailbox.into(),
+                    Tag::Id(mailbox_id),
+                )?
+                .and_then(|document_ids| document_ids.max())
+                .map_or(1, |document_id| document_id + 2))
+        }
+    }
+}
+
+fn assign_uid<T>(
+    store: &JMAPStore<T>,
+    batch: &mut WriteBatch,
+    mailbox_id: DocumentId,
+) -> store::Result<u32>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Continue from any UID assigned earlier in the same batch
+    let account_id = batch.account_id;
+    let pending = batch.documents.iter_mut().find_map(|action| match action {
+        WriteAction::Update(document)
+            if document.collection == Collection::Mailbox && document.document_id == mailbox_id =>
+        {
+            document
+                .binary_fields
+                .iter_mut()
+                .find(|field| field.field == MailboxField::UidNext as FieldId)
+        }
+        _ => None,
+    });
+    let uid = if let Some(field) = &pending {
+        u32::deserialize(&field.value).unwrap_or(1)
+    } else {
+        store.mail_uid_next(account_id, mailbox_id)?
+    };
+
+    let uid_next = (uid + 1).serialize().unwrap();
+    if let Some(field) = pending {
+        field.value = uid_next;
+    } else {
+        let mut document = Document::new(Collection::Mailbox, mailbox_id);
+        document.binary(MailboxField::UidNext, uid_next, IndexOptions::new());
+        batch.update_document(document);
+    }
+    Ok(uid)
+}
@@ -113,6 +113,7 @@ where
                     | Property::Role
                     | Property::SortOrder
                     | Property::ACL
+                    | Property::Retention
             )
         });
         let account_id = helper.account_id;
@@ -141,7 +142,7 @@ where
             for property in properties {
                 let value = match property {
                     Property::Id => Value::Id { value: id },
-                    Property::Name | Property::Role => fields
+                    Property::Name | Property::Role | Property::Retention => fields
                         .as_mut()
                         .unwrap()
                         .remove(property)
@@ -25,16 +25,20 @@ pub mod changes;
 pub mod get;
 pub mod query;
 pub mod raft;
+pub mod retention;
 pub mod schema;
 pub mod serialize;
 pub mod set;
 
+use std::time::SystemTime;
+
 use jmap::types::jmap::JMAPId;
 use jmap::{jmap_store::Object, orm::TinyORM};
 
 use store::core::collection::Collection;
 use store::core::tag::Tag;
 use store::write::options::Options;
+use store::FieldId;
 
 use self::schema::{Mailbox, Property, Value};
 
@@ -83,6 +87,18 @@ impl Object for Mailbox {
     }
 }
 
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+pub enum MailboxField {
+    UidNext = 127,
+}
+
+impl From<MailboxField> for FieldId {
+    fn from(field: MailboxField) -> Self {
+        field as FieldId
+    }
+}
+
 pub trait CreateMailbox: Sized {
     fn new_mailbox(name: &str, role: &str) -> Self;
 }
@@ -104,6 +120,24 @@ impl CreateMailbox for TinyORM<Mailbox> {
         );
         mailbox.tag(Property::Role, Tag::Default);
         mailbox.set(Property::ParentId, Value::Id { value: 0u64.into() });
+        mailbox.set(
+            Property::UidValidity,
+            Value::Number {
+                value: new_uid_validity(),
+            },
+        );
         mailbox
     }
 }
+
+// IMAP UIDVALIDITY of a new mailbox. Mailbox ids are reused after a
+// deletion, so the creation time is used to tell both mailboxes apart.
+pub fn new_uid_validity() -> u32 {
+    std::cmp::max(
+        SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0) as u32,
+        1,
+    )
+}
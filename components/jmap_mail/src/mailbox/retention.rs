@@ -0,0 +1,317 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use jmap::{
+    orm::{serialize::JMAPOrm, TinyORM},
+    principal,
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use store::{
+    ahash::AHashMap,
+    core::{collection::Collection, document::Document, error::StoreError, tag::Tag, JMAPIdPrefix},
+    read::{
+        comparator::Comparator,
+        filter::{Filter, Query},
+        FilterMapper,
+    },
+    roaring::RoaringBitmap,
+    tracing::debug,
+    write::{batch::WriteBatch, update::Changes},
+    AccountId, DocumentId, JMAPStore, LongInteger, Store,
+};
+
+use crate::mail::{self, schema::Email, set::JMAPSetMail, uid::JMAPMailUids, MessageField};
+
+use super::schema::{Mailbox, Property, RetentionAction, Value};
+
+const BATCH_SIZE: usize = 100;
+
+pub trait JMAPMailboxRetention {
+    fn mailbox_retention_purge(&self) -> store::Result<Vec<(AccountId, Changes)>>;
+    fn mailbox_retention_apply(&self, account_id: AccountId) -> store::Result<Option<Changes>>;
+}
+
+struct ExpiredMessages {
+    mailbox_id: DocumentId,
+    move_to: Option<DocumentId>,
+    message_ids: RoaringBitmap,
+}
+
+impl<T> JMAPMailboxRetention for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Applies the retention rules to all accounts, returning the changes
+    // written for each account.
+    fn mailbox_retention_purge(&self) -> store::Result<Vec<(AccountId, Changes)>> {
+        let mut results = Vec::new();
+        if let Some(account_ids) = self.get_document_ids(SUPERUSER_ID, Collection::Principal)? {
+            for account_id in account_ids {
+                if let Some(changes) = self.mailbox_retention_apply(account_id)? {
+                    results.push((account_id, changes));
+                }
+            }
+        }
+        Ok(results)
+    }
+
+    fn mailbox_retention_apply(&self, account_id: AccountId) -> store::Result<Option<Changes>> {
+        // Accounts under legal hold are never expired
+        if let Some(principal::schema::Value::Bool { value: true }) = self
+            .get_orm::<principal::schema::Principal>(SUPERUSER_ID, account_id)?
+            .and_then(|mut fields| fields.remove(&principal::schema::Property::LegalHold))
+        {
+            debug!(
+                "Skipping retention for account {} under legal hold.",
+                account_id
+            );
+            return Ok(None);
+        }
+
+        // Lock collection while the expired messages are looked up and removed,
+        // so that messages delivered or moved meanwhile are not affected.
+        let _lock = self.lock_collection(account_id, Collection::Mail);
+
+        let mailbox_ids =
+            if let Some(mailbox_ids) = self.get_document_ids(account_id, Collection::Mailbox)? {
+                mailbox_ids
+            } else {
+                return Ok(None);
+            };
+
+        // Obtain the role and retention policy of each mailbox
+        let mut roles = AHashMap::new();
+        let mut mailboxes = Vec::new();
+        for mailbox_id in &mailbox_ids {
+            if let Some(mut fields) = self.get_orm::<Mailbox>(account_id, mailbox_id)? {
+                let role = fields
+                    .remove(&Property::Role)
+                    .and_then(|role| role.unwrap_text());
+                if let Some(role) = &role {
+                    roles.insert(role.clone(), mailbox_id);
+                }
+                mailboxes.push((mailbox_id, role, fields.remove(&Property::Retention)));
+            }
+        }
+
+        // Find the expired messages in each mailbox, giving precedence to
+        // policies set on the mailbox over the configured role rules.
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut expired = Vec::new();
+        for (mailbox_id, role, retention) in mailboxes {
+            let (max_age, max_emails, move_to) = match retention {
+                Some(Value::Retention { value }) => (
+                    value.max_age,
+                    value.max_emails,
+                    match (value.action, value.move_to) {
+                        (RetentionAction::Move, Some(move_to)) => Some(move_to.get_document_id()),
+                        _ => None,
+                    },
+                ),
+                _ => {
+                    if let Some(rule) = role.as_ref().and_then(|role| {
+                        self.config
+                            .mailbox_retention
+                            .iter()
+                            .find(|rule| &rule.role == role)
+                    }) {
+                        let move_to = if let Some(move_to) = &rule.move_to {
+                            if let Some(move_to) = roles.get(move_to) {
+                                Some(*move_to)
+                            } else {
+                                debug!(
+                                    "Account {} has no mailbox with role '{}', skipping retention rule for '{}'.",
+                                    account_id, move_to, rule.role
+                                );
+                                continue;
+                            }
+                        } else {
+                            None
+                        };
+                        (rule.max_age, rule.max_emails, move_to)
+                    } else {
+                        continue;
+                    }
+                }
+            };
+
+            if matches!(move_to, Some(move_to) if move_to == mailbox_id || !mailbox_ids.contains(move_to))
+            {
+                debug!(
+                    "Invalid retention destination for mailbox {}:{}.",
+                    account_id, mailbox_id
+                );
+                continue;
+            }
+
+            let message_ids = if let Some(message_ids) = self.get_tag(
+                account_id,
+                Collection::Mail,
+                MessageField::Mailbox.into(),
+                Tag::Id(mailbox_id),
+            )? {
+                message_ids
+            } else {
+                continue;
+            };
+
+            let mut expired_ids = RoaringBitmap::new();
+            if let Some(max_age) = max_age {
+                for id in self.query_store::<FilterMapper>(
+                    account_id,
+                    Collection::Mail,
+                    Filter::and(vec![
+                        Filter::eq(
+                            MessageField::Mailbox.into(),
+                            Query::Tag(Tag::Id(mailbox_id)),
+                        ),
+                        Filter::lt(
+                            MessageField::ReceivedAt.into(),
+                            Query::LongInteger(now.saturating_sub(max_age) as LongInteger),
+                        ),
+                    ]),
+                    Comparator::None,
+                )? {
+                    expired_ids.insert(id.get_document_id());
+                }
+            }
+            if let Some(max_emails) = max_emails {
+                if message_ids.len() > max_emails as u64 {
+                    for id in self
+                        .query_store::<FilterMapper>(
+                            account_id,
+                            Collection::Mail,
+                            Filter::eq(
+                                MessageField::Mailbox.into(),
+                                Query::Tag(Tag::Id(mailbox_id)),
+                            ),
+                            Comparator::descending(MessageField::ReceivedAt.into()),
+                        )?
+                        .into_iter()
+                        .skip(max_emails as usize)
+                    {
+                        expired_ids.insert(id.get_document_id());
+                    }
+                }
+            }
+
+            if !expired_ids.is_empty() {
+                expired.push(ExpiredMessages {
+                    mailbox_id,
+                    move_to,
+                    message_ids: expired_ids,
+                });
+            }
+        }
+
+        if expired.is_empty() {
+            return Ok(None);
+        }
+
+        let mut result: Option<Changes> = None;
+        for ExpiredMessages {
+            mailbox_id,
+            move_to,
+            message_ids,
+        } in expired
+        {
+            let message_ids = message_ids.into_iter().collect::<Vec<_>>();
+            for message_ids in message_ids.chunks(BATCH_SIZE) {
+                let mut batch = WriteBatch::new(account_id);
+
+                for &message_id in message_ids {
+                    let mut document = Document::new(Collection::Mail, message_id);
+                    let current_fields = if let Some(current_fields) =
+                        self.get_orm::<Email>(account_id, message_id)?
+                    {
+                        current_fields
+                    } else {
+                        continue;
+                    };
+
+                    // Destroy the message unless it is being moved or also
+                    // belongs to other mailboxes.
+                    match current_fields.get_tags(&mail::schema::Property::MailboxIds) {
+                        Some(tags) if !tags.contains(&Tag::Id(mailbox_id)) => (),
+                        Some(tags) if move_to.is_some() || tags.len() > 1 => {
+                            let thread_id = self
+                                .get_document_value::<DocumentId>(
+                                    account_id,
+                                    Collection::Mail,
+                                    message_id,
+                                    MessageField::ThreadId.into(),
+                                )?
+                                .ok_or_else(|| {
+                                    StoreError::DataCorruption(format!(
+                                        "Failed to fetch threadId for {}:{}.",
+                                        account_id, message_id
+                                    ))
+                                })?;
+                            let mut fields = TinyORM::track_changes(&current_fields);
+                            fields.untag(&mail::schema::Property::MailboxIds, &Tag::Id(mailbox_id));
+                            batch.log_child_update(Collection::Mailbox, mailbox_id);
+                            if let Some(move_to) = move_to {
+                                fields.tag(mail::schema::Property::MailboxIds, Tag::Id(move_to));
+                                batch.log_child_update(Collection::Mailbox, move_to);
+                            }
+                            current_fields.merge(&mut document, fields)?;
+                            self.mail_assign_uids(&mut batch, &mut document)?;
+                            batch.update_document(document);
+                            batch.log_update(
+                                Collection::Mail,
+                                JMAPId::from_parts(thread_id, message_id),
+                            );
+                        }
+                        _ => {
+                            if let Some(id) =
+                                self.mail_delete(account_id, Some(&mut batch), &mut document)?
+                            {
+                                batch.delete_document(document);
+                                batch.log_delete(Collection::Mail, id);
+                            }
+                        }
+                    }
+                }
+
+                if !batch.is_empty() {
+                    if let Some(changes) = self.write(batch)? {
+                        if let Some(result) = &mut result {
+                            result.collections.union(&changes.collections);
+                            result.change_id = std::cmp::max(result.change_id, changes.change_id);
+                        } else {
+                            result = changes.into();
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(result)
+    }
+}
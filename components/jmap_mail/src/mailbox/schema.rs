@@ -52,6 +52,7 @@ pub enum Value {
     ACLSet(Vec<ACLUpdate>),
     ACLGet(VecMap<String, Vec<ACL>>),
     Null,
+    Retention { value: RetentionPolicy },
 }
 
 impl Default for Value {
@@ -100,6 +101,14 @@ impl orm::Value for Value {
                 acc + k.len() + v.len() * std::mem::size_of::<ACL>()
             }),
             Value::Null => 0,
+            Value::Retention { .. } => std::mem::size_of::<RetentionPolicy>(),
+        }
+    }
+
+    fn as_text(&self) -> Option<&str> {
+        match self {
+            Value::Text { value } => Some(value),
+            _ => None,
         }
     }
 }
@@ -178,6 +187,28 @@ pub struct MailboxRights {
     may_submit: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    #[serde(rename = "maxAge")]
+    pub max_age: Option<u64>,
+
+    #[serde(rename = "maxEmails")]
+    pub max_emails: Option<u32>,
+
+    pub action: RetentionAction,
+
+    #[serde(rename = "moveTo")]
+    pub move_to: Option<JMAPId>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum RetentionAction {
+    #[serde(rename = "destroy")]
+    Destroy,
+    #[serde(rename = "move")]
+    Move,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Copy)]
 #[repr(u8)]
 pub enum Property {
@@ -193,7 +224,9 @@ pub enum Property {
     MyRights = 9,
     IsSubscribed = 10,
     ACL = 11,
-    Invalid = 12,
+    UidValidity = 12,
+    Retention = 13,
+    Invalid = 14,
 }
 
 impl Display for Property {
@@ -211,6 +244,8 @@ impl Display for Property {
             Property::MyRights => write!(f, "myRights"),
             Property::IsSubscribed => write!(f, "isSubscribed"),
             Property::ACL => write!(f, "acl"),
+            Property::UidValidity => write!(f, "uidValidity"),
+            Property::Retention => write!(f, "retention"),
             Property::Invalid => Ok(()),
         }
     }
@@ -231,6 +266,8 @@ impl Property {
             "unreadThreads" => Property::UnreadThreads,
             "myRights" => Property::MyRights,
             "acl" => Property::ACL,
+            "uidValidity" => Property::UidValidity,
+            "retention" => Property::Retention,
             _ => Property::Invalid,
         }
     }
@@ -278,6 +315,8 @@ impl From<FieldId> for Property {
             9 => Property::MyRights,
             10 => Property::IsSubscribed,
             11 => Property::ACL,
+            12 => Property::UidValidity,
+            13 => Property::Retention,
             _ => Property::Invalid,
         }
     }
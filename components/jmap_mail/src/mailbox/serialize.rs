@@ -32,7 +32,7 @@ use serde::{de::IgnoredAny, ser::SerializeMap, Deserialize, Serialize};
 use store::core::{acl::ACL, vec_map::VecMap};
 
 use super::{
-    schema::{Filter, Mailbox, Property, Value},
+    schema::{Filter, Mailbox, Property, RetentionPolicy, Value},
     set::SetArguments,
 };
 
@@ -92,6 +92,7 @@ impl Serialize for Mailbox {
                     map.serialize_entry(name, &format!("#{}", value))?
                 }
                 Value::ACLGet(value) => map.serialize_entry(name, value)?,
+                Value::Retention { value } => map.serialize_entry(name, value)?,
                 Value::Subscriptions { .. } | Value::ACLSet(_) => (),
             }
         }
@@ -161,6 +162,16 @@ impl<'de> serde::de::Visitor<'de> for MailboxVisitor {
                         },
                     );
                 }
+                "retention" => {
+                    properties.append(
+                        Property::Retention,
+                        if let Some(value) = map.next_value::<Option<RetentionPolicy>>()? {
+                            Value::Retention { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
                 "isSubscribed" => {
                     properties.append(
                         Property::IsSubscribed,
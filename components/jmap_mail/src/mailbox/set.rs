@@ -23,10 +23,13 @@
 
 use std::time::Duration;
 
-use super::schema::{Mailbox, Property, Value};
+use super::new_uid_validity;
+use super::schema::{Mailbox, Property, RetentionAction, Value};
+use super::MailboxField;
 use crate::mail::schema::Email;
 use crate::mail::set::JMAPSetMail;
 use crate::mail::sharing::JMAPShareMail;
+use crate::mail::uid::JMAPMailUids;
 use crate::mail::{self, MessageField};
 use crate::{INBOX_ID, TRASH_ID};
 use jmap::error::set::{SetError, SetErrorType};
@@ -49,6 +52,7 @@ use store::read::comparator::Comparator;
 use store::read::filter::{ComparisonOperator, Filter, Query};
 use store::read::FilterMapper;
 use store::tracing::debug;
+use store::write::options::{IndexOptions, Options};
 use store::{AccountId, DocumentId, JMAPStore, LongInteger, SharedResource};
 use store::{SharedBitmap, Store};
 
@@ -142,6 +146,12 @@ where
             if !mailbox.has_property(&Property::ParentId) {
                 mailbox.set(Property::ParentId, Value::Id { value: 0u64.into() });
             }
+            mailbox.set(
+                Property::UidValidity,
+                Value::Number {
+                    value: new_uid_validity(),
+                },
+            );
             mailbox.insert_validate(document)?;
 
             Ok(Mailbox::new(document.document_id.into()))
@@ -324,6 +334,7 @@ where
                                     &Tag::Id(document_id),
                                 );
                                 current_fields.merge(&mut document, fields)?;
+                                self.mail_assign_uids(&mut helper.changes, &mut document)?;
                                 helper.changes.update_document(document);
                                 helper.changes.log_update(
                                     Collection::Mail,
@@ -374,6 +385,11 @@ where
                 ))
             })?
             .delete(document);
+        document.binary(
+            MailboxField::UidNext,
+            Vec::with_capacity(0),
+            IndexOptions::new().clear(),
+        );
 
         Ok(())
     }
@@ -506,6 +522,45 @@ where
                     Value::Null
                 }
                 (Property::SortOrder, value @ Value::Number { .. }) => value,
+                (Property::Retention, Value::Retention { value }) => {
+                    if value.max_age.is_none() && value.max_emails.is_none() {
+                        return Err(SetError::invalid_property(
+                            property,
+                            "Either maxAge or maxEmails must be set.".to_string(),
+                        ));
+                    }
+                    match (value.action, &value.move_to) {
+                        (RetentionAction::Destroy, None) => (),
+                        (RetentionAction::Move, Some(move_to)) => {
+                            let move_to = move_to.get_document_id();
+                            if mailbox_id == Some(move_to) {
+                                return Err(SetError::invalid_property(
+                                    property,
+                                    "Mailbox cannot move messages to itself.".to_string(),
+                                ));
+                            } else if !helper.document_ids.contains(move_to) {
+                                return Err(SetError::invalid_property(
+                                    property,
+                                    "Destination mailbox does not exist.".to_string(),
+                                ));
+                            }
+                        }
+                        (RetentionAction::Destroy, Some(_)) => {
+                            return Err(SetError::invalid_property(
+                                property,
+                                "moveTo is only valid with the move action.".to_string(),
+                            ));
+                        }
+                        (RetentionAction::Move, None) => {
+                            return Err(SetError::invalid_property(
+                                property,
+                                "The move action requires moveTo.".to_string(),
+                            ));
+                        }
+                    }
+                    Value::Retention { value }
+                }
+                (Property::Retention, Value::Null) => Value::Null,
                 (Property::ACL, Value::ACLSet(value)) => {
                     for acl_update in &value {
                         match acl_update {
@@ -688,6 +743,9 @@ where
             }
         }
 
+        // Notify the accounts whose rights were granted, changed or revoked
+        helper.notify_acl_changes(&self, current_fields, &[Property::Name]);
+
         Ok(self)
     }
 }
@@ -0,0 +1,153 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::jmap_store::get::{default_mapper, GetHelper, GetObject, SharedDocsFnc};
+use jmap::orm::serialize::JMAPOrm;
+use jmap::request::get::{GetRequest, GetResponse};
+use jmap::request::query::{Comparator, Filter};
+use jmap::types::jmap::JMAPId;
+
+use store::core::error::StoreError;
+use store::core::vec_map::VecMap;
+use store::JMAPStore;
+use store::{AccountId, DocumentId, Store};
+
+use crate::mail::schema::{Comparator as EmailComparator, Filter as EmailFilter};
+
+use super::schema::{Property, SavedSearch, Value};
+
+pub type SavedQuery = (
+    Filter<EmailFilter>,
+    Option<Vec<Comparator<EmailComparator>>>,
+);
+
+impl GetObject for SavedSearch {
+    type GetArguments = ();
+
+    fn default_properties() -> Vec<Self::Property> {
+        vec![
+            Property::Id,
+            Property::Name,
+            Property::Filter,
+            Property::Sort,
+        ]
+    }
+
+    fn get_as_id(&self, _property: &Self::Property) -> Option<Vec<JMAPId>> {
+        None
+    }
+}
+
+pub trait JMAPGetSavedSearch<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn saved_search_get(
+        &self,
+        request: GetRequest<SavedSearch>,
+    ) -> jmap::Result<GetResponse<SavedSearch>>;
+
+    fn saved_search_query(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<Option<SavedQuery>>;
+}
+
+impl<T> JMAPGetSavedSearch<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn saved_search_get(
+        &self,
+        request: GetRequest<SavedSearch>,
+    ) -> jmap::Result<GetResponse<SavedSearch>> {
+        let mut helper =
+            GetHelper::new(self, request, default_mapper.into(), None::<SharedDocsFnc>)?;
+        let account_id = helper.account_id;
+
+        // Add Id Property
+        if !helper.properties.contains(&Property::Id) {
+            helper.properties.push(Property::Id);
+        }
+
+        helper.get(|id, properties| {
+            let document_id = id.get_document_id();
+            let mut fields = self
+                .get_orm::<SavedSearch>(account_id, document_id)?
+                .ok_or_else(|| StoreError::NotFound("SavedSearch data not found".to_string()))?;
+            let mut saved_search = VecMap::with_capacity(properties.len());
+
+            for property in properties {
+                saved_search.append(
+                    *property,
+                    match property {
+                        Property::Id => Value::Id { value: id },
+                        _ => fields.remove(property).unwrap_or_default(),
+                    },
+                );
+            }
+            Ok(Some(SavedSearch {
+                properties: saved_search,
+            }))
+        })
+    }
+
+    fn saved_search_query(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<Option<SavedQuery>> {
+        let mut fields =
+            if let Some(fields) = self.get_orm::<SavedSearch>(account_id, document_id)? {
+                fields
+            } else {
+                return Ok(None);
+            };
+        let filter = match fields.remove(&Property::Filter) {
+            Some(Value::Json { value }) => parse_filter(&value),
+            _ => None,
+        };
+        let sort = match fields.remove(&Property::Sort) {
+            Some(Value::Json { value }) => parse_sort(&value).map(Some),
+            _ => Some(None),
+        };
+
+        if let (Some(filter), Some(sort)) = (filter, sort) {
+            Ok(Some((filter, sort)))
+        } else {
+            Err(StoreError::DataCorruption(format!(
+                "Failed to parse SavedSearch {}/{}",
+                account_id, document_id
+            )))
+        }
+    }
+}
+
+pub fn parse_filter(value: &str) -> Option<Filter<EmailFilter>> {
+    serde_json::from_str(value).ok()
+}
+
+pub fn parse_sort(value: &str) -> Option<Vec<Comparator<EmailComparator>>> {
+    serde_json::from_str(value).ok()
+}
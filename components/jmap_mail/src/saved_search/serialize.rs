@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, fmt};
+
+use serde::{de::IgnoredAny, ser::Error, ser::SerializeMap, Deserialize, Serialize};
+use store::core::vec_map::VecMap;
+
+use super::schema::{Property, SavedSearch, Value};
+
+// Property de/serialization
+impl Serialize for Property {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+struct PropertyVisitor;
+
+impl<'de> serde::de::Visitor<'de> for PropertyVisitor {
+    type Value = Property;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a valid JMAP SavedSearch property")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        Ok(Property::parse(v))
+    }
+}
+
+impl<'de> Deserialize<'de> for Property {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str(PropertyVisitor)
+    }
+}
+
+// SavedSearch de/serialization
+impl Serialize for SavedSearch {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        let mut map = serializer.serialize_map(self.properties.len().into())?;
+
+        for (name, value) in &self.properties {
+            match value {
+                Value::Id { value } => map.serialize_entry(name, value)?,
+                Value::Text { value } => map.serialize_entry(name, value)?,
+                Value::Json { value } => map.serialize_entry(
+                    name,
+                    &serde_json::from_str::<serde_json::Value>(value).map_err(S::Error::custom)?,
+                )?,
+                Value::Null => map.serialize_entry(name, &())?,
+            }
+        }
+
+        map.end()
+    }
+}
+
+struct SavedSearchVisitor;
+
+impl<'de> serde::de::Visitor<'de> for SavedSearchVisitor {
+    type Value = SavedSearch;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a valid JMAP SavedSearch object")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+    where
+        A: serde::de::MapAccess<'de>,
+    {
+        let mut properties: VecMap<Property, Value> = VecMap::new();
+
+        while let Some(key) = map.next_key::<Cow<str>>()? {
+            match key.as_ref() {
+                "name" => {
+                    properties.append(
+                        Property::Name,
+                        if let Some(value) = map.next_value::<Option<String>>()? {
+                            Value::Text { value }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                "filter" => {
+                    properties.append(
+                        Property::Filter,
+                        if let Some(value) = map.next_value::<Option<serde_json::Value>>()? {
+                            Value::Json {
+                                value: value.to_string(),
+                            }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                "sort" => {
+                    properties.append(
+                        Property::Sort,
+                        if let Some(value) = map.next_value::<Option<serde_json::Value>>()? {
+                            Value::Json {
+                                value: value.to_string(),
+                            }
+                        } else {
+                            Value::Null
+                        },
+                    );
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        Ok(SavedSearch { properties })
+    }
+}
+
+impl<'de> Deserialize<'de> for SavedSearch {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_map(SavedSearchVisitor)
+    }
+}
@@ -0,0 +1,179 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::error::set::{SetError, SetErrorType};
+use jmap::jmap_store::set::SetHelper;
+use jmap::jmap_store::Object;
+use jmap::orm::{serialize::JMAPOrm, TinyORM};
+use jmap::request::query::Filter;
+use jmap::request::set::SetResponse;
+use jmap::request::ResultReference;
+use jmap::types::jmap::JMAPId;
+use jmap::{jmap_store::set::SetObject, request::set::SetRequest};
+use store::core::document::Document;
+use store::core::error::StoreError;
+use store::{AccountId, JMAPStore, Store};
+
+use crate::mail::schema::Filter as EmailFilter;
+
+use super::get::{parse_filter, parse_sort};
+use super::schema::{Property, SavedSearch, Value};
+
+impl SetObject for SavedSearch {
+    type SetArguments = ();
+
+    type NextCall = ();
+
+    fn eval_id_references(&mut self, _fnc: impl FnMut(&str) -> Option<JMAPId>) {}
+    fn eval_result_references(&mut self, _fnc: impl FnMut(&ResultReference) -> Option<Vec<u64>>) {}
+}
+
+pub trait JMAPSetSavedSearch<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn saved_search_set(
+        &self,
+        request: SetRequest<SavedSearch>,
+    ) -> jmap::Result<SetResponse<SavedSearch>>;
+
+    fn saved_search_delete(
+        &self,
+        account_id: AccountId,
+        document: &mut Document,
+    ) -> store::Result<()>;
+}
+
+impl<T> JMAPSetSavedSearch<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn saved_search_set(
+        &self,
+        request: SetRequest<SavedSearch>,
+    ) -> jmap::Result<SetResponse<SavedSearch>> {
+        let mut helper = SetHelper::new(self, request)?;
+
+        helper.create(|_create_id, item, _helper, document| {
+            let mut fields = TinyORM::<SavedSearch>::new();
+
+            for (property, value) in item.properties {
+                fields.set(property, validate_property(property, value)?);
+            }
+
+            // Validate fields
+            fields.insert_validate(document)?;
+
+            Ok(SavedSearch::new(document.document_id.into()))
+        })?;
+
+        helper.update(|id, item, helper, document| {
+            let current_fields = self
+                .get_orm::<SavedSearch>(helper.account_id, id.get_document_id())?
+                .ok_or_else(|| SetError::new_err(SetErrorType::NotFound))?;
+            let mut fields = TinyORM::track_changes(&current_fields);
+
+            for (property, value) in item.properties {
+                fields.set(property, validate_property(property, value)?);
+            }
+
+            // Merge changes
+            current_fields.merge_validate(document, fields)?;
+            Ok(None)
+        })?;
+
+        helper.destroy(|_id, helper, document| {
+            if let Some(orm) =
+                self.get_orm::<SavedSearch>(helper.account_id, document.document_id)?
+            {
+                orm.delete(document);
+            }
+            Ok(())
+        })?;
+
+        helper.into_response()
+    }
+
+    fn saved_search_delete(
+        &self,
+        account_id: AccountId,
+        document: &mut Document,
+    ) -> store::Result<()> {
+        // Delete ORM
+        self.get_orm::<SavedSearch>(account_id, document.document_id)?
+            .ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Failed to fetch SavedSearch ORM for {}:{}.",
+                    account_id, document.document_id
+                ))
+            })?
+            .delete(document);
+
+        Ok(())
+    }
+}
+
+fn validate_property(property: Property, value: Value) -> Result<Value, SetError<Property>> {
+    match (property, value) {
+        (Property::Name, value @ Value::Text { .. }) => Ok(value),
+        (Property::Filter, Value::Json { value }) => match parse_filter(&value) {
+            Some(filter) => {
+                if let Some(condition) = find_unsupported(&filter) {
+                    Err(SetError::invalid_property(
+                        Property::Filter,
+                        format!("Unsupported filter condition '{}'.", condition),
+                    ))
+                } else {
+                    Ok(Value::Json { value })
+                }
+            }
+            None => Err(SetError::invalid_property(
+                Property::Filter,
+                "Invalid Email/query filter.",
+            )),
+        },
+        (Property::Sort, Value::Json { value }) => {
+            if parse_sort(&value).is_some() {
+                Ok(Value::Json { value })
+            } else {
+                Err(SetError::invalid_property(
+                    Property::Sort,
+                    "Invalid Email/query sort.",
+                ))
+            }
+        }
+        (Property::Sort, Value::Null) => Ok(Value::Null),
+        (property, _) => Err(SetError::invalid_property(
+            property,
+            "Field could not be set.",
+        )),
+    }
+}
+
+fn find_unsupported(filter: &Filter<EmailFilter>) -> Option<&str> {
+    match filter {
+        Filter::FilterOperator(operator) => operator.conditions.iter().find_map(find_unsupported),
+        Filter::FilterCondition(EmailFilter::Unsupported { value }) => Some(value),
+        Filter::FilterCondition(_) | Filter::Empty => None,
+    }
+}
@@ -21,18 +21,21 @@
  * for more details.
 */
 
-use super::schema::{Property, Thread};
-use crate::mail::{sharing::JMAPShareMail, MessageField};
+use super::{
+    metadata::{JMAPThreadMetadata, ThreadMetadata},
+    schema::{Property, Thread},
+};
+use crate::mail::{schema::Keyword, sharing::JMAPShareMail, MessageField};
 use jmap::{
     jmap_store::get::{GetHelper, GetObject, IdMapper, SharedDocsFnc},
     request::{
         get::{GetRequest, GetResponse},
         ACLEnforce,
     },
-    types::jmap::JMAPId,
+    types::{date::JMAPDate, jmap::JMAPId},
 };
 use store::{
-    core::{acl::ACL, collection::Collection, tag::Tag, JMAPIdPrefix},
+    core::{acl::ACL, collection::Collection, tag::Tag, vec_map::VecMap, JMAPIdPrefix},
     read::{
         comparator::{Comparator, FieldComparator},
         filter::Filter,
@@ -82,7 +85,7 @@ where
             helper.properties.push(Property::Id);
         }
 
-        let response = helper.get(|id, _properties| {
+        let response = helper.get(|id, properties| {
             let thread_id = id.get_document_id();
             if let Some(mut doc_ids) = self.get_tag(
                 account_id,
@@ -99,22 +102,74 @@ where
                     }
                 }
 
-                Ok(Some(Thread {
+                let mut thread = Thread {
                     id,
-                    email_ids: self
-                        .query_store::<FilterMapper>(
-                            account_id,
-                            Collection::Mail,
-                            Filter::DocumentSet(doc_ids),
-                            Comparator::Field(FieldComparator {
-                                field: MessageField::ReceivedAt.into(),
-                                ascending: true,
-                            }),
-                        )?
-                        .into_iter()
-                        .map(|doc_id| JMAPId::from_parts(thread_id, doc_id.get_document_id()))
-                        .collect(),
-                }))
+                    email_ids: Vec::new(),
+                    participants: None,
+                    received_at: None,
+                    unread_count: None,
+                    keywords: None,
+                };
+
+                if properties.iter().any(|property| {
+                    matches!(
+                        property,
+                        Property::Participants
+                            | Property::ReceivedAt
+                            | Property::UnreadCount
+                            | Property::Keywords
+                    )
+                }) {
+                    // Shared threads only disclose the messages visible to the member
+                    let metadata = if shared_messages.is_some() {
+                        let mut metadata = ThreadMetadata::default();
+                        for document_id in &doc_ids {
+                            metadata.merge(self.thread_metadata_message(account_id, document_id)?);
+                        }
+                        metadata
+                    } else {
+                        self.thread_metadata(account_id, thread_id)?
+                    };
+
+                    for property in properties {
+                        match property {
+                            Property::Participants => {
+                                thread.participants = metadata.participants.clone().into();
+                            }
+                            Property::ReceivedAt => {
+                                thread.received_at =
+                                    JMAPDate::from_timestamp(metadata.received_at).into();
+                            }
+                            Property::UnreadCount => {
+                                thread.unread_count = metadata.unread_count().into();
+                            }
+                            Property::Keywords => {
+                                let mut keywords = VecMap::new();
+                                for (keyword, _) in &metadata.keywords {
+                                    keywords.set(Keyword::from(keyword), true);
+                                }
+                                thread.keywords = keywords.into();
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+
+                thread.email_ids = self
+                    .query_store::<FilterMapper>(
+                        account_id,
+                        Collection::Mail,
+                        Filter::DocumentSet(doc_ids),
+                        Comparator::Field(FieldComparator {
+                            field: MessageField::ReceivedAt.into(),
+                            ascending: true,
+                        }),
+                    )?
+                    .into_iter()
+                    .map(|doc_id| JMAPId::from_parts(thread_id, doc_id.get_document_id()))
+                    .collect();
+
+                Ok(Some(thread))
             } else {
                 Ok(None)
             }
@@ -0,0 +1,443 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::orm::serialize::JMAPOrm;
+use mail_parser::RfcHeader;
+use store::{
+    bincode,
+    blob::BlobId,
+    core::{
+        collection::Collection, document::Document, error::StoreError, number::Number, tag::Tag,
+    },
+    serialize::{StoreDeserialize, StoreSerialize},
+    write::{
+        batch::{WriteAction, WriteBatch},
+        options::{IndexOptions, Options},
+    },
+    AccountId, DocumentId, FieldId, JMAPStore, Store, ThreadId,
+};
+
+use crate::mail::{
+    schema::{Email, Keyword, Property},
+    MessageData, MessageField,
+};
+
+const MAX_PARTICIPANTS: usize = 50;
+
+#[derive(Debug, Clone, Copy)]
+#[repr(u8)]
+pub enum ThreadField {
+    Metadata = 0,
+}
+
+impl From<ThreadField> for FieldId {
+    fn from(field: ThreadField) -> Self {
+        field as FieldId
+    }
+}
+
+// Aggregated thread properties, updated as messages are added to a thread or
+// their keywords change, and rebuilt when messages are removed from it.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ThreadMetadata {
+    pub received_at: i64,
+    pub participants: Vec<String>,
+    pub messages: u64,
+    pub keywords: Vec<(Tag, u64)>,
+}
+
+impl StoreSerialize for ThreadMetadata {
+    fn serialize(&self) -> Option<Vec<u8>> {
+        bincode::serialize(self).ok()
+    }
+}
+
+impl StoreDeserialize for ThreadMetadata {
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        bincode::deserialize_from(bytes).ok()
+    }
+}
+
+impl ThreadMetadata {
+    // Obtains the receivedAt date and senders from a message's index.
+    pub fn from_document(document: &Document) -> Self {
+        let mut metadata = ThreadMetadata {
+            received_at: document
+                .number_fields
+                .iter()
+                .find(|field| field.field == MessageField::ReceivedAt as FieldId)
+                .map(|field| match field.value {
+                    Number::LongInteger(value) => value as i64,
+                    Number::Integer(value) => value as i64,
+                    Number::Float(value) => value as i64,
+                })
+                .unwrap_or(0),
+            participants: Vec::new(),
+            messages: 1,
+            keywords: Vec::new(),
+        };
+        metadata.update_keywords(document);
+        for field in &document.text_fields {
+            if field.field == RfcHeader::From as FieldId
+                && field.options.get_text_options() == <u64 as Options>::F_TOKENIZE
+                && field.value.text.contains('@')
+            {
+                metadata.add_participant(field.value.text.to_lowercase());
+            }
+        }
+        metadata
+    }
+
+    pub fn merge(&mut self, other: ThreadMetadata) {
+        self.received_at = std::cmp::max(self.received_at, other.received_at);
+        for participant in other.participants {
+            self.add_participant(participant);
+        }
+        self.messages += other.messages;
+        for (keyword, count) in other.keywords {
+            self.add_keyword(keyword, count);
+        }
+    }
+
+    // Applies the keywords set or cleared in a message's document.
+    pub fn update_keywords(&mut self, document: &Document) {
+        for field in &document.tag_fields {
+            if field.field == MessageField::Keyword as FieldId {
+                if !field.is_clear() {
+                    self.add_keyword(field.value.clone(), 1);
+                } else if let Some(pos) = self
+                    .keywords
+                    .iter()
+                    .position(|(keyword, _)| keyword == &field.value)
+                {
+                    if self.keywords[pos].1 > 1 {
+                        self.keywords[pos].1 -= 1;
+                    } else {
+                        self.keywords.swap_remove(pos);
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn unread_count(&self) -> u64 {
+        self.messages.saturating_sub(
+            self.keywords
+                .iter()
+                .find(|(keyword, _)| keyword == &Tag::Static(Keyword::SEEN))
+                .map_or(0, |(_, count)| *count),
+        )
+    }
+
+    fn add_keyword(&mut self, keyword: Tag, count: u64) {
+        if let Some((_, current)) = self.keywords.iter_mut().find(|(k, _)| k == &keyword) {
+            *current += count;
+        } else {
+            self.keywords.push((keyword, count));
+        }
+    }
+
+    fn add_participant(&mut self, participant: String) {
+        if self.participants.len() < MAX_PARTICIPANTS && !self.participants.contains(&participant) {
+            self.participants.push(participant);
+        }
+    }
+}
+
+pub trait JMAPThreadMetadata<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn thread_metadata(
+        &self,
+        account_id: AccountId,
+        thread_id: ThreadId,
+    ) -> store::Result<ThreadMetadata>;
+
+    fn thread_metadata_update(
+        &self,
+        batch: &mut WriteBatch,
+        thread_id: ThreadId,
+        metadata: ThreadMetadata,
+    ) -> store::Result<()>;
+
+    fn thread_metadata_keywords(
+        &self,
+        batch: &mut WriteBatch,
+        thread_id: ThreadId,
+        document: &Document,
+    ) -> store::Result<()>;
+
+    fn thread_metadata_rebuild(
+        &self,
+        batch: &mut WriteBatch,
+        thread_id: ThreadId,
+        document_id: DocumentId,
+    ) -> store::Result<()>;
+
+    fn thread_metadata_modify(
+        &self,
+        batch: &mut WriteBatch,
+        thread_id: ThreadId,
+        fnc: impl FnOnce(&mut ThreadMetadata),
+    ) -> store::Result<()>;
+
+    fn thread_metadata_delete(&self, batch: &mut WriteBatch, thread_id: ThreadId);
+
+    fn thread_metadata_message(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<ThreadMetadata>;
+}
+
+impl<T> JMAPThreadMetadata<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn thread_metadata(
+        &self,
+        account_id: AccountId,
+        thread_id: ThreadId,
+    ) -> store::Result<ThreadMetadata> {
+        if let Some(metadata) = self.get_document_value::<ThreadMetadata>(
+            account_id,
+            Collection::Thread,
+            thread_id,
+            ThreadField::Metadata.into(),
+        )? {
+            return Ok(metadata);
+        }
+
+        // Threads created before metadata was tracked are built from their messages
+        let mut metadata = ThreadMetadata::default();
+        for document_id in self
+            .get_tag(
+                account_id,
+                Collection::Mail,
+                MessageField::ThreadId.into(),
+                Tag::Id(thread_id),
+            )?
+            .unwrap_or_default()
+        {
+            metadata.merge(self.thread_metadata_message(account_id, document_id)?);
+        }
+        Ok(metadata)
+    }
+
+    fn thread_metadata_update(
+        &self,
+        batch: &mut WriteBatch,
+        thread_id: ThreadId,
+        metadata: ThreadMetadata,
+    ) -> store::Result<()> {
+        self.thread_metadata_modify(batch, thread_id, |current| current.merge(metadata))
+    }
+
+    fn thread_metadata_keywords(
+        &self,
+        batch: &mut WriteBatch,
+        thread_id: ThreadId,
+        document: &Document,
+    ) -> store::Result<()> {
+        if document
+            .tag_fields
+            .iter()
+            .any(|field| field.field == MessageField::Keyword as FieldId)
+        {
+            self.thread_metadata_modify(batch, thread_id, |current| {
+                current.update_keywords(document)
+            })
+        } else {
+            Ok(())
+        }
+    }
+
+    // Builds the metadata again from the messages that remain in the thread after
+    // removing a message, as the most recent date and senders cannot be subtracted.
+    fn thread_metadata_rebuild(
+        &self,
+        batch: &mut WriteBatch,
+        thread_id: ThreadId,
+        document_id: DocumentId,
+    ) -> store::Result<()> {
+        let mut document_ids = self
+            .get_tag(
+                batch.account_id,
+                Collection::Mail,
+                MessageField::ThreadId.into(),
+                Tag::Id(thread_id),
+            )?
+            .unwrap_or_default();
+        document_ids.remove(document_id);
+        for action in &batch.documents {
+            if let WriteAction::Delete(document) = action {
+                if document.collection == Collection::Mail {
+                    document_ids.remove(document.document_id);
+                }
+            }
+        }
+        if document_ids.is_empty() {
+            self.thread_metadata_delete(batch, thread_id);
+            return Ok(());
+        }
+
+        let mut metadata = ThreadMetadata::default();
+        for document_id in document_ids {
+            let mut message = self.thread_metadata_message(batch.account_id, document_id)?;
+
+            // Include keyword changes not yet written to the store
+            for action in &batch.documents {
+                if let WriteAction::Update(document) = action {
+                    if document.collection == Collection::Mail
+                        && document.document_id == document_id
+                    {
+                        message.update_keywords(document);
+                    }
+                }
+            }
+            metadata.merge(message);
+        }
+
+        write_metadata(batch, thread_id, metadata)
+    }
+
+    fn thread_metadata_modify(
+        &self,
+        batch: &mut WriteBatch,
+        thread_id: ThreadId,
+        fnc: impl FnOnce(&mut ThreadMetadata),
+    ) -> store::Result<()> {
+        // Apply the changes to any pending update for this thread in the same batch
+        let pending = batch.documents.iter().find_map(|action| match action {
+            WriteAction::Update(document)
+                if document.collection == Collection::Thread
+                    && document.document_id == thread_id =>
+            {
+                Some(
+                    document
+                        .binary_fields
+                        .iter()
+                        .find(|field| !field.is_clear())
+                        .and_then(|field| ThreadMetadata::deserialize(&field.value)),
+                )
+            }
+            _ => None,
+        });
+        let mut metadata = if let Some(pending) = pending {
+            pending.unwrap_or_default()
+        } else if self
+            .get_tag(
+                batch.account_id,
+                Collection::Mail,
+                MessageField::ThreadId.into(),
+                Tag::Id(thread_id),
+            )?
+            .map_or(false, |document_ids| !document_ids.is_empty())
+        {
+            self.thread_metadata(batch.account_id, thread_id)?
+        } else {
+            // Empty threads start from scratch, discarding any stale metadata
+            ThreadMetadata::default()
+        };
+        fnc(&mut metadata);
+        write_metadata(batch, thread_id, metadata)
+    }
+
+    fn thread_metadata_delete(&self, batch: &mut WriteBatch, thread_id: ThreadId) {
+        batch.documents.retain(|action| {
+            !matches!(action, WriteAction::Update(document)
+                if document.collection == Collection::Thread && document.document_id == thread_id)
+        });
+        let mut document = Document::new(Collection::Thread, thread_id);
+        document.binary(
+            ThreadField::Metadata,
+            Vec::with_capacity(0),
+            IndexOptions::new().clear(),
+        );
+        batch.update_document(document);
+    }
+
+    // Builds the metadata of a stored message from its parsed headers and keywords.
+    fn thread_metadata_message(
+        &self,
+        account_id: AccountId,
+        document_id: DocumentId,
+    ) -> store::Result<ThreadMetadata> {
+        let message_data = self
+            .get_document_value::<BlobId>(
+                account_id,
+                Collection::Mail,
+                document_id,
+                MessageField::Metadata.into(),
+            )?
+            .map(|blob_id| self.blob_get(&blob_id))
+            .transpose()?
+            .flatten()
+            .and_then(|bytes| MessageData::deserialize(&bytes));
+
+        if let Some(message_data) = message_data {
+            let mut document = Document::new(Collection::Mail, document_id);
+            message_data.build_index(&mut document, true)?;
+            let mut metadata = ThreadMetadata::from_document(&document);
+            if let Some(keywords) = self
+                .get_orm::<Email>(account_id, document_id)?
+                .as_ref()
+                .and_then(|fields| fields.get_tags(&Property::Keywords))
+            {
+                for keyword in keywords {
+                    metadata.add_keyword(keyword.clone(), 1);
+                }
+            }
+            Ok(metadata)
+        } else {
+            Ok(ThreadMetadata::default())
+        }
+    }
+}
+
+fn write_metadata(
+    batch: &mut WriteBatch,
+    thread_id: ThreadId,
+    metadata: ThreadMetadata,
+) -> store::Result<()> {
+    let mut document = Document::new(Collection::Thread, thread_id);
+    document.binary(
+        ThreadField::Metadata,
+        metadata.serialize().ok_or_else(|| {
+            StoreError::SerializeError("Failed to serialize thread metadata".into())
+        })?,
+        IndexOptions::new().store(),
+    );
+
+    // Replace any pending update for this thread in the same batch
+    if let Some(WriteAction::Update(pending)) = batch.documents.iter_mut().find(|action| {
+        matches!(action, WriteAction::Update(document)
+            if document.collection == Collection::Thread && document.document_id == thread_id)
+    }) {
+        *pending = document;
+    } else {
+        batch.update_document(document);
+    }
+    Ok(())
+}
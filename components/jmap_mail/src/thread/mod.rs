@@ -28,6 +28,8 @@ use self::schema::{Property, Thread};
 
 pub mod changes;
 pub mod get;
+pub mod metadata;
+pub mod query;
 pub mod schema;
 
 impl Object for Thread {
@@ -39,6 +41,10 @@ impl Object for Thread {
         Thread {
             id,
             email_ids: Vec::new(),
+            participants: None,
+            received_at: None,
+            unread_count: None,
+            keywords: None,
         }
     }
 
@@ -0,0 +1,153 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use super::{
+    metadata::JMAPThreadMetadata,
+    schema::{Comparator, Thread},
+};
+use crate::mail::{
+    query::{JMAPMailQuery, QueryArguments},
+    schema::{Comparator as EmailComparator, Filter},
+};
+use jmap::{
+    jmap_store::query::QueryObject,
+    request::query::{Comparator as QueryComparator, QueryRequest, QueryResponse},
+    types::jmap::JMAPId,
+};
+use store::{core::collection::Collection, JMAPStore, Store};
+
+impl QueryObject for Thread {
+    type QueryArguments = ();
+
+    type Filter = Filter;
+
+    type Comparator = Comparator;
+}
+
+pub trait JMAPThreadQuery<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn thread_query(&self, request: QueryRequest<Thread>) -> jmap::Result<QueryResponse>;
+}
+
+impl<T> JMAPThreadQuery<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn thread_query(&self, request: QueryRequest<Thread>) -> jmap::Result<QueryResponse> {
+        let account_id = request.account_id.get_document_id();
+
+        // Threads are sorted by their most recent message, newest first by default
+        let is_ascending =
+            request
+                .sort
+                .as_ref()
+                .and_then(|sort| sort.first())
+                .map_or(false, |comparator| match comparator.property {
+                    Comparator::ReceivedAt => comparator.is_ascending,
+                });
+
+        // Obtain the threads of all matching messages, Email/query returns at most
+        // query-max-results ids per call so all pages are requested.
+        let mut threads = Vec::new();
+        let mut position = 0;
+        loop {
+            let email_ids = self
+                .mail_query(QueryRequest {
+                    acl: request.acl.clone(),
+                    account_id: request.account_id,
+                    filter: request.filter.clone(),
+                    sort: vec![QueryComparator {
+                        is_ascending: false,
+                        collation: None,
+                        property: EmailComparator::ReceivedAt,
+                    }]
+                    .into(),
+                    position: (position as i32).into(),
+                    anchor: None,
+                    anchor_offset: None,
+                    limit: None,
+                    calculate_total: None,
+                    arguments: QueryArguments {
+                        collapse_threads: true.into(),
+                    },
+                })?
+                .ids;
+            let num_ids = email_ids.len();
+
+            for id in email_ids {
+                let thread_id = id.get_prefix_id();
+                threads.push((
+                    self.thread_metadata(account_id, thread_id)?.received_at,
+                    JMAPId::from(thread_id),
+                ));
+            }
+
+            if num_ids == 0 || num_ids < self.config.query_max_results {
+                break;
+            }
+            position += num_ids;
+        }
+        if is_ascending {
+            threads.sort_by_key(|(received_at, _)| *received_at);
+        } else {
+            threads.sort_by_key(|(received_at, _)| std::cmp::Reverse(*received_at));
+        }
+
+        let total = threads.len();
+        let mut response = QueryResponse {
+            account_id: request.account_id,
+            query_state: self.get_state(account_id, Collection::Thread)?,
+            can_calculate_changes: false,
+            position: 0,
+            ids: Vec::with_capacity(0),
+            total: None,
+            limit: None,
+            is_immutable: false,
+        };
+
+        let limit = match request.limit {
+            Some(limit) if limit > 0 => std::cmp::min(limit, self.config.query_max_results),
+            Some(_) => 0,
+            None => self.config.query_max_results,
+        };
+        if limit > 0 {
+            response.paginate(
+                threads.into_iter().map(|(_, id)| id),
+                limit,
+                request.position.unwrap_or(0),
+                request.anchor,
+                request.anchor_offset.unwrap_or(0),
+            )?;
+            if limit < total {
+                response.limit = limit.into();
+            }
+        }
+        if request.calculate_total.unwrap_or(false) {
+            response.total = total.into();
+        }
+
+        Ok(response)
+    }
+}
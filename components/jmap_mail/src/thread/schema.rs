@@ -21,15 +21,27 @@
  * for more details.
 */
 
-use jmap::types::jmap::JMAPId;
+use jmap::types::{date::JMAPDate, jmap::JMAPId};
 use serde::{Deserialize, Serialize};
-use store::FieldId;
+use store::{core::vec_map::VecMap, FieldId};
+
+use crate::mail::schema::Keyword;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Thread {
     pub id: JMAPId,
     #[serde(rename = "emailIds")]
     pub email_ids: Vec<JMAPId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub participants: Option<Vec<String>>,
+    #[serde(rename = "receivedAt")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub received_at: Option<JMAPDate>,
+    #[serde(rename = "unreadCount")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unread_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keywords: Option<VecMap<Keyword, bool>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, Copy)]
@@ -39,6 +51,14 @@ pub enum Property {
     Id = 0,
     #[serde(rename = "emailIds")]
     EmailIds = 1,
+    #[serde(rename = "participants")]
+    Participants = 2,
+    #[serde(rename = "receivedAt")]
+    ReceivedAt = 3,
+    #[serde(rename = "unreadCount")]
+    UnreadCount = 4,
+    #[serde(rename = "keywords")]
+    Keywords = 5,
 }
 
 impl Property {
@@ -46,6 +66,10 @@ impl Property {
         match value {
             "id" => Property::Id,
             "emailIds" => Property::EmailIds,
+            "participants" => Property::Participants,
+            "receivedAt" => Property::ReceivedAt,
+            "unreadCount" => Property::UnreadCount,
+            "keywords" => Property::Keywords,
             _ => Property::Id,
         }
     }
@@ -60,6 +84,10 @@ impl From<FieldId> for Property {
     fn from(field: FieldId) -> Self {
         match field {
             0 => Property::Id,
+            2 => Property::Participants,
+            3 => Property::ReceivedAt,
+            4 => Property::UnreadCount,
+            5 => Property::Keywords,
             _ => Property::EmailIds,
         }
     }
@@ -72,3 +100,10 @@ impl TryFrom<&str> for Property {
         Ok(Property::parse(value))
     }
 }
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "property")]
+pub enum Comparator {
+    #[serde(rename = "receivedAt")]
+    ReceivedAt,
+}
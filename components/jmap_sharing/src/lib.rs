@@ -22,4 +22,5 @@
 */
 
 pub mod principal;
+pub mod share_notification;
 pub use argon2;
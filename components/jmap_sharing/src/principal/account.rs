@@ -25,7 +25,7 @@ use std::sync::Arc;
 
 use jmap::{
     orm::serialize::JMAPOrm,
-    principal::schema::{Principal, Property, Type, Value},
+    principal::schema::{Principal, Property, SpamFilter, Type, Value},
     types::jmap::JMAPId,
     SUPERUSER_ID,
 };
@@ -49,6 +49,7 @@ pub trait JMAPAccountStore {
         account_id: AccountId,
     ) -> store::Result<Option<(String, String, Type)>>;
     fn get_account_secret_hash(&self, account_id: AccountId) -> store::Result<Option<String>>;
+    fn get_account_spam_filter(&self, account_id: AccountId) -> store::Result<Option<SpamFilter>>;
     fn expand_rcpt(&self, email: String) -> store::Result<Arc<RecipientType>>;
 }
 
@@ -142,8 +143,11 @@ where
     }
 
     fn get_acl_token(&self, primary_id: AccountId) -> store::Result<Arc<ACLToken>> {
+        self.cache_stats.acl_tokens.request();
         self.acl_tokens
             .try_get_with::<_, StoreError>(primary_id, || {
+                self.cache_stats.acl_tokens.miss();
+
                 // Find all groups this account is a member of
                 let mut member_of = vec![primary_id];
                 let mut iter_stack = Vec::new();
@@ -242,6 +246,15 @@ where
         }
     }
 
+    fn get_account_spam_filter(&self, account_id: AccountId) -> store::Result<Option<SpamFilter>> {
+        Ok(self
+            .get_orm::<Principal>(SUPERUSER_ID, account_id)?
+            .and_then(|mut fields| match fields.remove(&Property::SpamFilter) {
+                Some(Value::SpamFilter { value }) => Some(value),
+                _ => None,
+            }))
+    }
+
     // Used as nonce for token encryption
     fn get_account_secret_hash(&self, account_id: AccountId) -> store::Result<Option<String>> {
         if let Some(mut fields) = self.get_orm::<Principal>(SUPERUSER_ID, account_id)? {
@@ -263,8 +276,10 @@ where
     }
 
     fn expand_rcpt(&self, email: String) -> store::Result<Arc<RecipientType>> {
+        self.cache_stats.recipients.request();
         self.recipients
             .try_get_with::<_, StoreError>(email.clone(), || {
+                self.cache_stats.recipients.miss();
                 Ok(Arc::new(
                     if let Some(account_id) = self
                         .query_store::<FilterMapper>(
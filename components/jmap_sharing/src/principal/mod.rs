@@ -28,6 +28,7 @@ use jmap::{
 use store::rand::{self, Rng};
 
 pub mod account;
+pub mod changes;
 pub mod get;
 pub mod query;
 pub mod set;
@@ -32,6 +32,7 @@ use jmap::request::set::SetRequest;
 use jmap::request::set::SetResponse;
 use jmap::types::jmap::JMAPId;
 use jmap::{sanitize_domain, sanitize_email, SUPERUSER_ID};
+use jmap_mail::mail::encryption::EncryptionParams;
 use jmap_mail::mail_send::dkim::DKIM;
 use jmap_mail::mailbox::schema::Mailbox;
 use jmap_mail::mailbox::CreateMailbox;
@@ -542,6 +543,44 @@ where
 
                 (Property::Quota, value @ (Value::Number { .. } | Value::Null)) => value,
 
+                (Property::DuplicateWindow, value @ (Value::Number { .. } | Value::Null))
+                    if ptype == Type::Individual =>
+                {
+                    value
+                }
+
+                (Property::LegalHold, value @ (Value::Bool { .. } | Value::Null))
+                    if ptype == Type::Individual =>
+                {
+                    value
+                }
+
+                (Property::SpamFilter, Value::SpamFilter { value })
+                    if ptype == Type::Individual =>
+                {
+                    if let (Some(junk_score), Some(reject_score)) =
+                        (value.junk_score, value.reject_score)
+                    {
+                        if junk_score >= reject_score {
+                            return Err(SetError::invalid_property(
+                                property,
+                                "Junk score must be lower than the reject score.".to_string(),
+                            ));
+                        }
+                    }
+                    Value::SpamFilter { value }
+                }
+
+                (Property::EncryptionKey, Value::Text { value }) if ptype == Type::Individual => {
+                    if let Err(err) = EncryptionParams::parse(&value) {
+                        return Err(SetError::invalid_property(
+                            property,
+                            format!("Invalid encryption key: {}", err),
+                        ));
+                    }
+                    Value::Text { value }
+                }
+
                 (Property::Picture, value @ (Value::Blob { .. } | Value::Null)) => value,
 
                 (Property::Members, Value::Members { value }) if ptype == Type::Group => {
@@ -700,6 +739,8 @@ where
                     Property::Email
                     | Property::Secret
                     | Property::DKIM
+                    | Property::SpamFilter
+                    | Property::EncryptionKey
                     | Property::Aliases
                     | Property::Members,
                     Value::Null,
@@ -836,6 +877,9 @@ where
             helper.changes.add_linked_batch(batch);
         }
 
+        // Notify the accounts whose rights were granted, changed or revoked
+        helper.notify_acl_changes(&self, current_fields, &[Property::Name]);
+
         Ok(self)
     }
 }
@@ -0,0 +1,100 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::error::set::SetError;
+use jmap::jmap_store::set::SetHelper;
+use jmap::orm::serialize::JMAPOrm;
+use jmap::request::set::{SetRequest, SetResponse};
+use jmap::share_notification::schema::ShareNotification;
+use store::core::document::Document;
+use store::core::error::StoreError;
+use store::{AccountId, JMAPStore, Store};
+
+pub trait JMAPSetShareNotification<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn share_notification_set(
+        &self,
+        request: SetRequest<ShareNotification>,
+    ) -> jmap::Result<SetResponse<ShareNotification>>;
+
+    fn share_notification_delete(
+        &self,
+        account_id: AccountId,
+        document: &mut Document,
+    ) -> store::Result<()>;
+}
+
+impl<T> JMAPSetShareNotification<T> for JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    fn share_notification_set(
+        &self,
+        request: SetRequest<ShareNotification>,
+    ) -> jmap::Result<SetResponse<ShareNotification>> {
+        let mut helper = SetHelper::new(self, request)?;
+
+        helper.create(|_create_id, _item, _helper, _document| {
+            Err(SetError::forbidden(
+                "Share notifications can only be created by the server.",
+            ))
+        })?;
+
+        helper.update(|_id, _item, _helper, _document| {
+            Err(SetError::forbidden(
+                "Share notifications cannot be modified.",
+            ))
+        })?;
+
+        helper.destroy(|_id, helper, document| {
+            if let Some(orm) =
+                self.get_orm::<ShareNotification>(helper.account_id, document.document_id)?
+            {
+                orm.delete(document);
+            }
+            Ok(())
+        })?;
+
+        helper.into_response()
+    }
+
+    fn share_notification_delete(
+        &self,
+        account_id: AccountId,
+        document: &mut Document,
+    ) -> store::Result<()> {
+        // Delete ORM
+        self.get_orm::<ShareNotification>(account_id, document.document_id)?
+            .ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Failed to fetch ShareNotification ORM for {}:{}.",
+                    account_id, document.document_id
+                ))
+            })?
+            .delete(document);
+
+        Ok(())
+    }
+}
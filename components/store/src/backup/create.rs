@@ -0,0 +1,148 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::fs;
+use std::path::Path;
+
+use crate::log::raft::{LogIndex, RaftId};
+use crate::{JMAPStore, Store, StoreError};
+
+use super::{
+    copy_dir, list_backups, unix_timestamp, Backup, BackupManifest, BACKUP_BLOBS, BACKUP_DB,
+};
+
+impl<T> JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    /// Creates a consistent snapshot of the database and the blob store under
+    /// the configured backup path. Incremental backups are based on the most
+    /// recent backup and are skipped when the Raft log has not advanced since.
+    pub fn backup(&self, incremental: bool) -> crate::Result<Option<Backup>> {
+        let backup_path = self.config.backup_path.as_ref().ok_or_else(|| {
+            StoreError::InvalidArguments("Parameter 'backup-path' is not configured.".to_string())
+        })?;
+
+        // Blob purges are not allowed while a backup is in progress.
+        let _maintenance_lock = self.maintenance_lock.lock();
+
+        let raft_id = self
+            .get_prev_raft_id(RaftId::new(LogIndex::MAX, LogIndex::MAX))?
+            .unwrap_or_else(RaftId::none);
+        let base = if incremental {
+            list_backups(backup_path)?.pop()
+        } else {
+            None
+        };
+        if base
+            .as_ref()
+            .map_or(false, |base| base.manifest.raft_id == raft_id)
+        {
+            return Ok(None);
+        }
+
+        // Backups taken within the same second are told apart by a sequence number.
+        let created_at = unix_timestamp();
+        let base_name = if !raft_id.is_none() {
+            format!("{}-{}-{}", created_at, raft_id.term, raft_id.index)
+        } else {
+            format!("{}-0-0", created_at)
+        };
+        let mut name = base_name.clone();
+        let mut tmp_path = backup_path.join(format!(".{}", name));
+        let mut path = backup_path.join(&name);
+        for seq in 1.. {
+            if !path.exists() && !tmp_path.exists() {
+                break;
+            }
+            name = format!("{}-{}", base_name, seq);
+            tmp_path = backup_path.join(format!(".{}", name));
+            path = backup_path.join(&name);
+        }
+        fs::create_dir_all(&tmp_path)?;
+
+        // Take a database checkpoint and link any files unchanged since the base backup.
+        let db_path = tmp_path.join(BACKUP_DB);
+        self.db.backup(&db_path)?;
+        let mut files_linked = 0;
+        if let Some(base) = &base {
+            files_linked += relink_unchanged(&db_path, &base.path.join(BACKUP_DB))?;
+        }
+
+        // Copy blobs, these are content-addressed so existing files are never modified.
+        let (files_copied, blobs_linked) = copy_dir(
+            &self.blob_store.base_path,
+            &tmp_path.join(BACKUP_BLOBS),
+            base.as_ref()
+                .map(|base| base.path.join(BACKUP_BLOBS))
+                .as_deref(),
+        )?;
+        files_linked += blobs_linked;
+
+        let manifest = BackupManifest {
+            raft_id,
+            created_at,
+            base: base.map(|base| base.name),
+            files_copied,
+            files_linked,
+        };
+        manifest.write(&tmp_path)?;
+        fs::rename(&tmp_path, &path)?;
+
+        Ok(Some(Backup {
+            name,
+            path,
+            manifest,
+        }))
+    }
+}
+
+fn relink_unchanged(path: &Path, base_path: &Path) -> crate::Result<u64> {
+    let mut files_linked = 0;
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+
+        // Only table and blob files are immutable.
+        if !file_name.to_str().map_or(false, |name| {
+            name.ends_with(".sst") || name.ends_with(".blob")
+        }) {
+            continue;
+        }
+
+        let base_file = base_path.join(&file_name);
+        if fs::metadata(&base_file).map_or(false, |base_metadata| {
+            entry
+                .metadata()
+                .map_or(false, |metadata| metadata.len() == base_metadata.len())
+        }) {
+            let file = entry.path();
+            fs::remove_file(&file)?;
+            fs::hard_link(&base_file, &file)?;
+            files_linked += 1;
+        }
+    }
+
+    Ok(files_linked)
+}
@@ -0,0 +1,202 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod create;
+pub mod restore;
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::{log::raft::RaftId, StoreError};
+
+pub const BACKUP_MANIFEST: &str = "manifest";
+pub const BACKUP_DB: &str = "db";
+pub const BACKUP_BLOBS: &str = "blobs";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct BackupManifest {
+    pub raft_id: RaftId,
+    pub created_at: u64,
+    pub base: Option<String>,
+    pub files_copied: u64,
+    pub files_linked: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub name: String,
+    pub path: PathBuf,
+    pub manifest: BackupManifest,
+}
+
+impl BackupManifest {
+    pub fn read(path: &Path) -> crate::Result<Self> {
+        bincode::deserialize(&fs::read(path.join(BACKUP_MANIFEST))?).map_err(|err| {
+            StoreError::DeserializeError(format!(
+                "Failed to deserialize backup manifest {}: {}",
+                path.display(),
+                err
+            ))
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> crate::Result<()> {
+        fs::write(
+            path.join(BACKUP_MANIFEST),
+            bincode::serialize(self).map_err(|err| {
+                StoreError::SerializeError(format!("Failed to serialize backup manifest: {}", err))
+            })?,
+        )?;
+        Ok(())
+    }
+}
+
+impl Backup {
+    pub fn open(path: &Path) -> crate::Result<Self> {
+        Ok(Backup {
+            name: path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or_default()
+                .to_string(),
+            path: path.to_path_buf(),
+            manifest: BackupManifest::read(path)?,
+        })
+    }
+
+    /// Sequence number of backups created within the same second,
+    /// encoded as the last component of `{secs}-{term}-{index}-{seq}`.
+    pub fn sequence(&self) -> u64 {
+        self.name
+            .splitn(4, '-')
+            .nth(3)
+            .and_then(|seq| seq.parse().ok())
+            .unwrap_or(0)
+    }
+}
+
+/// Returns all completed backups found under `base_path`, oldest first.
+pub fn list_backups(base_path: &Path) -> crate::Result<Vec<Backup>> {
+    let mut backups = Vec::new();
+    if !base_path.exists() {
+        return Ok(backups);
+    }
+
+    for entry in fs::read_dir(base_path)? {
+        let path = entry?.path();
+        // Backups in progress are written to a hidden directory.
+        if path.is_dir()
+            && path.join(BACKUP_MANIFEST).exists()
+            && !path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map_or(true, |name| name.starts_with('.'))
+        {
+            backups.push(Backup::open(&path)?);
+        }
+    }
+    backups.sort_unstable_by_key(|backup| {
+        let raft_id = backup.manifest.raft_id;
+        (
+            backup.manifest.created_at,
+            !raft_id.is_none(),
+            raft_id.term,
+            raft_id.index,
+            backup.sequence(),
+        )
+    });
+
+    Ok(backups)
+}
+
+/// Renames an existing file or directory so it is not overwritten by a restore.
+pub fn move_aside(path: &Path) -> crate::Result<()> {
+    if path.exists() {
+        let timestamp = unix_timestamp();
+        for num in 0.. {
+            let mut new_path = path.as_os_str().to_owned();
+            if num == 0 {
+                new_path.push(format!(".{}.old", timestamp));
+            } else {
+                new_path.push(format!(".{}-{}.old", timestamp, num));
+            }
+            let new_path = PathBuf::from(new_path);
+            if !new_path.exists() {
+                fs::rename(path, &new_path)?;
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Recursively copies `src` into `dest`. Files that also exist in `base` with
+/// the same size are hard linked from there instead of being copied, which is
+/// safe for content-addressed blobs and immutable database files.
+pub fn copy_dir(src: &Path, dest: &Path, base: Option<&Path>) -> crate::Result<(u64, u64)> {
+    let mut files_copied = 0;
+    let mut files_linked = 0;
+
+    fs::create_dir_all(dest)?;
+    if !src.exists() {
+        return Ok((files_copied, files_linked));
+    }
+
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+        let src_path = entry.path();
+        let dest_path = dest.join(&file_name);
+        let base_path = base.map(|base| base.join(&file_name));
+
+        if entry.file_type()?.is_dir() {
+            let (copied, linked) = copy_dir(&src_path, &dest_path, base_path.as_deref())?;
+            files_copied += copied;
+            files_linked += linked;
+        } else if base_path.as_ref().map_or(false, |base_path| {
+            fs::metadata(base_path).map_or(false, |base_metadata| {
+                entry
+                    .metadata()
+                    .map_or(false, |metadata| metadata.len() == base_metadata.len())
+            })
+        }) {
+            fs::hard_link(base_path.unwrap(), &dest_path)?;
+            files_linked += 1;
+        } else {
+            fs::copy(&src_path, &dest_path)?;
+            files_copied += 1;
+        }
+    }
+
+    Ok((files_copied, files_linked))
+}
+
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,71 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::path::Path;
+
+use crate::blob::{local::LocalBlobStore, BlobStore};
+use crate::config::env_settings::EnvSettings;
+use crate::log::raft::LogIndex;
+use crate::{JMAPStore, Store, StoreError};
+
+use super::{copy_dir, list_backups, move_aside, Backup, BACKUP_BLOBS, BACKUP_DB, BACKUP_MANIFEST};
+
+impl<T> JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    /// Rebuilds the local database and blob store from a backup. `path` is
+    /// either a backup directory or the backup root, in which case the most
+    /// recent backup at or before `raft_index` is restored. Existing data is
+    /// renamed rather than deleted.
+    pub fn restore(
+        settings: &EnvSettings,
+        path: &Path,
+        raft_index: Option<LogIndex>,
+    ) -> crate::Result<Backup> {
+        let backup = if path.join(BACKUP_MANIFEST).exists() {
+            Backup::open(path)?
+        } else {
+            list_backups(path)?
+                .into_iter()
+                .filter(|backup| {
+                    raft_index.map_or(true, |raft_index| {
+                        !backup.manifest.raft_id.is_none()
+                            && backup.manifest.raft_id.index <= raft_index
+                    })
+                })
+                .last()
+                .ok_or_else(|| {
+                    StoreError::NotFound(format!("No suitable backup found at {}", path.display()))
+                })?
+        };
+
+        T::restore(settings, &backup.path.join(BACKUP_DB))?;
+
+        let blob_path = LocalBlobStore::new(settings)?.base_path;
+        move_aside(&blob_path)?;
+        copy_dir(&backup.path.join(BACKUP_BLOBS), &blob_path, None)?;
+
+        Ok(backup)
+    }
+}
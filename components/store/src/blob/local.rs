@@ -22,20 +22,31 @@
 */
 
 use std::{
+    borrow::Cow,
     fs::{self, File},
     io::{Read, Seek, SeekFrom, Write},
     ops::Range,
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
-use crate::{config::env_settings::EnvSettings, write::mutex_map::MutexMap};
+use crate::{
+    config::env_settings::EnvSettings,
+    core::{
+        cipher::{StoreCipher, STATE_ENCRYPTED, STATE_MIGRATING},
+        error::StoreError,
+    },
+    write::mutex_map::MutexMap,
+};
 
 use super::{BlobId, BlobStore};
 
+const ENCRYPTION_STATE_FILE: &str = "encryption-state";
+
 pub struct LocalBlobStore {
     pub lock: MutexMap<()>,
     pub base_path: PathBuf,
     pub hash_levels: usize,
+    pub cipher: Option<StoreCipher>,
 }
 
 impl BlobStore for LocalBlobStore {
@@ -46,11 +57,14 @@ impl BlobStore for LocalBlobStore {
                 .unwrap_or_else(|| "/usr/local/stalwart-jmap/data".to_string()),
         );
         base_path.push("blobs");
-        Ok(LocalBlobStore {
+        let blob_store = LocalBlobStore {
             lock: MutexMap::with_capacity(1024),
             base_path,
             hash_levels: std::cmp::min(settings.parse("blob-nested-levels").unwrap_or(2), 5),
-        })
+            cipher: StoreCipher::new(settings)?,
+        };
+        blob_store.init_encryption()?;
+        Ok(blob_store)
     }
 
     fn put(&self, blob_id: &BlobId, blob: &[u8]) -> crate::Result<bool> {
@@ -58,25 +72,73 @@ impl BlobStore for LocalBlobStore {
 
         if blob_path.exists() {
             let metadata = fs::metadata(&blob_path)?;
-            if metadata.len() as usize == blob.len() {
+            if metadata.len() as usize == blob.len()
+                || (self.cipher.is_some()
+                    && metadata.len() as usize == blob.len() + StoreCipher::OVERHEAD)
+            {
                 return Ok(false);
             }
         }
 
+        let blob = if let Some(cipher) = &self.cipher {
+            Cow::Owned(cipher.encrypt(blob_id.to_string().as_bytes(), blob)?)
+        } else {
+            Cow::Borrowed(blob)
+        };
+
         fs::create_dir_all(blob_path.parent().unwrap())?;
         let mut blob_file = File::create(&blob_path)?;
-        blob_file.write_all(blob)?;
+        blob_file.write_all(&blob)?;
         blob_file.flush()?;
 
         Ok(true)
     }
 
+    fn put_file(&self, blob_id: &BlobId, path: &Path) -> crate::Result<bool> {
+        // Encrypted blobs are sealed as a whole
+        if self.cipher.is_some() {
+            return self.put(blob_id, &fs::read(path)?);
+        }
+
+        let blob_path = self.get_path(blob_id)?;
+        if blob_path.exists() && fs::metadata(&blob_path)?.len() == fs::metadata(path)?.len() {
+            return Ok(false);
+        }
+
+        fs::create_dir_all(blob_path.parent().unwrap())?;
+        fs::copy(path, &blob_path)?;
+
+        Ok(true)
+    }
+
     fn get_range(&self, blob_id: &BlobId, range: Range<u32>) -> crate::Result<Option<Vec<u8>>> {
         let blob_path = self.get_path(blob_id)?;
         if !blob_path.exists() {
             return Ok(None);
         }
 
+        if let Some(cipher) = &self.cipher {
+            // Encrypted blobs have to be authenticated as a whole
+            let bytes = fs::read(&blob_path)?;
+            return Ok(
+                if let Some(bytes) = cipher.decrypt(blob_id.to_string().as_bytes(), &bytes)? {
+                    let bytes = bytes.into_owned();
+                    Some(if range.start != 0 || range.end != u32::MAX {
+                        let from_offset = if range.start < bytes.len() as u32 {
+                            range.start as usize
+                        } else {
+                            0
+                        };
+                        bytes[from_offset..std::cmp::min(range.end as usize, bytes.len())].to_vec()
+                    } else {
+                        bytes
+                    })
+                } else {
+                    None
+                },
+            );
+        }
+
         let blob_size = fs::metadata(&blob_path)?.len();
         let mut blob = File::open(&blob_path)?;
         Ok(Some(if range.start != 0 || range.end != u32::MAX {
@@ -112,6 +174,113 @@ impl BlobStore for LocalBlobStore {
 }
 
 impl LocalBlobStore {
+    /// Rewrites all blob files that are not encrypted with the current key,
+    /// returning the number of files re-encrypted.
+    pub fn reencrypt(&self) -> crate::Result<u64> {
+        if let Some(cipher) = &self.cipher {
+            let total = if self.base_path.exists() {
+                Self::reencrypt_dir(cipher, &self.base_path)?
+            } else {
+                0
+            };
+
+            // From now on unencrypted blob files are rejected
+            if cipher.is_migrating() {
+                self.write_encryption_state(cipher, STATE_ENCRYPTED)?;
+                cipher.set_migrating(false);
+            }
+
+            return Ok(total);
+        }
+        Ok(0)
+    }
+
+    /// Records the encryption state of the blob files. Blob stores that
+    /// already contain files when encryption is enabled accept unencrypted
+    /// files until they have been re-encrypted.
+    fn init_encryption(&self) -> crate::Result<()> {
+        let state_path = self.base_path.join(ENCRYPTION_STATE_FILE);
+        match (&self.cipher, state_path.exists()) {
+            (Some(cipher), true) => {
+                if cipher
+                    .decrypt(ENCRYPTION_STATE_FILE.as_bytes(), &fs::read(&state_path)?)?
+                    .map_or(false, |state| state.as_ref() == [STATE_MIGRATING])
+                {
+                    cipher.set_migrating(true);
+                }
+                Ok(())
+            }
+            (Some(cipher), false) => {
+                let state =
+                    if self.base_path.exists() && fs::read_dir(&self.base_path)?.next().is_some() {
+                        cipher.set_migrating(true);
+                        STATE_MIGRATING
+                    } else {
+                        STATE_ENCRYPTED
+                    };
+                self.write_encryption_state(cipher, state)
+            }
+            (None, true) => Err(StoreError::InvalidArguments(
+                "The blob store is encrypted but no store encryption key is configured."
+                    .to_string(),
+            )),
+            (None, false) => Ok(()),
+        }
+    }
+
+    fn write_encryption_state(&self, cipher: &StoreCipher, state: u8) -> crate::Result<()> {
+        fs::create_dir_all(&self.base_path)?;
+        let state_path = self.base_path.join(ENCRYPTION_STATE_FILE);
+        let tmp_path = state_path.with_file_name(format!("{}.tmp", ENCRYPTION_STATE_FILE));
+        let mut state_file = File::create(&tmp_path)?;
+        state_file.write_all(&cipher.encrypt(ENCRYPTION_STATE_FILE.as_bytes(), &[state])?)?;
+        state_file.sync_all()?;
+        fs::rename(&tmp_path, &state_path)?;
+        Ok(())
+    }
+
+    fn reencrypt_dir(cipher: &StoreCipher, path: &Path) -> crate::Result<u64> {
+        let mut total = 0;
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let path = entry.path();
+            if entry.file_type()?.is_dir() {
+                total += Self::reencrypt_dir(cipher, &path)?;
+                continue;
+            }
+
+            let file_name = entry.file_name();
+            let file_name = if let Some(file_name) = file_name.to_str() {
+                file_name
+            } else {
+                continue;
+            };
+            if file_name.ends_with(".tmp") {
+                continue;
+            }
+
+            let bytes = fs::read(&path)?;
+            if cipher.is_current(&bytes) {
+                continue;
+            }
+            let bytes = cipher
+                .decrypt(file_name.as_bytes(), &bytes)?
+                .ok_or_else(|| {
+                    StoreError::DataCorruption(format!("Invalid blob file {}.", path.display()))
+                })?;
+
+            // Replace the file atomically, concurrent writers of the same blob
+            // can only store an identical copy.
+            let tmp_path = path.with_file_name(format!("{}.tmp", file_name));
+            let mut blob_file = File::create(&tmp_path)?;
+            blob_file.write_all(&cipher.encrypt(file_name.as_bytes(), &bytes)?)?;
+            blob_file.sync_all()?;
+            fs::rename(&tmp_path, &path)?;
+            total += 1;
+        }
+        Ok(total)
+    }
+
     fn get_path(&self, blob_id: &BlobId) -> crate::Result<PathBuf> {
         let mut path = self.base_path.clone();
         let hash = blob_id.hash();
@@ -21,7 +21,14 @@
  * for more details.
 */
 
-use std::{convert::TryInto, fmt::Display, io::Write, ops::Range};
+use std::{
+    convert::TryInto,
+    fmt::Display,
+    fs::File,
+    io::{self, Write},
+    ops::Range,
+    path::Path,
+};
 
 use sha2::{Digest, Sha256};
 
@@ -65,6 +72,16 @@ impl BlobId {
         }
     }
 
+    pub fn new_external_file(path: &Path) -> io::Result<Self> {
+        // Create blob key without reading the whole file in memory
+        let mut hasher = Sha256::new();
+        io::copy(&mut File::open(path)?, &mut hasher)?;
+
+        Ok(BlobId::External {
+            hash: hasher.finalize().into(),
+        })
+    }
+
     pub fn is_local(&self) -> bool {
         matches!(self, BlobId::Local { .. })
     }
@@ -124,5 +141,8 @@ pub trait BlobStore: Sized {
         self.get_range(blob_id, 0..u32::MAX)
     }
     fn put(&self, blob_id: &BlobId, blob: &[u8]) -> crate::Result<bool>;
+    fn put_file(&self, blob_id: &BlobId, path: &Path) -> crate::Result<bool> {
+        self.put(blob_id, &std::fs::read(path)?)
+    }
     fn delete(&self, blob_id: &BlobId) -> crate::Result<bool>;
 }
@@ -37,6 +37,7 @@ where
     T: for<'x> Store<'x> + 'static,
 {
     pub fn purge_blobs(&self) -> crate::Result<()> {
+        let _maintenance_lock = self.maintenance_lock.lock();
         let mut batch = Vec::with_capacity(16);
         let now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
@@ -47,10 +48,11 @@ where
         let mut blob_link_count = u32::MAX;
         let mut _blob_lock = None;
 
-        for (key, value) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Blobs, &[], Direction::Forward)?
         {
+            let (key, value) = item?;
             if key.len() < BLOB_HASH_LEN + 1 {
                 continue;
             }
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{ops::Range, time::SystemTime};
+use std::{ops::Range, path::Path, time::SystemTime};
 
 use roaring::RoaringBitmap;
 use tracing::error;
@@ -59,6 +59,30 @@ where
             bytes
         };
 
+        self.blob_commit(blob_id, key, value)
+    }
+
+    // Stores a file as an external blob without loading it in memory,
+    // returning the id of the new blob.
+    pub fn blob_store_file(&self, path: &Path) -> crate::Result<BlobId> {
+        let blob_id = BlobId::new_external_file(path)?;
+        let key = BlobKey::serialize(&blob_id);
+
+        // Lock blob hash
+        let _lock = self.blob_store.lock.lock_hash(&blob_id);
+
+        // Blob already exists, return.
+        if self.db.exists(ColumnFamily::Blobs, &key)? {
+            return Ok(blob_id);
+        }
+
+        self.blob_store.put_file(&blob_id, path)?;
+        self.blob_commit(&blob_id, key, Vec::new())?;
+
+        Ok(blob_id)
+    }
+
+    fn blob_commit(&self, blob_id: &BlobId, key: Vec<u8>, value: Vec<u8>) -> crate::Result<()> {
         // Write blob or blob reference to database
         let mut batch = Vec::with_capacity(2);
         batch.push(WriteOperation::Set {
@@ -149,10 +173,11 @@ where
     ) -> crate::Result<bool> {
         let prefix = BlobKey::serialize_prefix(blob_id, AccountId::MAX);
 
-        for (key, _) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Blobs, &prefix, Direction::Forward)?
         {
+            let (key, _) = item?;
             if key.starts_with(&prefix) {
                 if key.len() > prefix.len() {
                     if let Some((account_id, _)) = (&key[prefix.len()..]).read_leb128() {
@@ -180,10 +205,11 @@ where
     ) -> crate::Result<bool> {
         let prefix = BlobKey::serialize_collection(blob_id, account_id, collection);
 
-        for (key, _) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Blobs, &prefix, Direction::Forward)?
         {
+            let (key, _) = item?;
             if key.starts_with(&prefix) && key.len() > prefix.len() {
                 if let Some((document_id, _)) = (&key[prefix.len()..]).read_leb128() {
                     if documents.contains(document_id) {
@@ -212,6 +238,7 @@ where
             .db
             .iterator(ColumnFamily::Blobs, &prefix, Direction::Forward)?
             .next()
+            .transpose()?
         {
             if key.starts_with(&prefix) && key.len() > prefix.len() {
                 if let Some((document_id, _)) = (&key[prefix.len()..]).read_leb128() {
@@ -30,7 +30,9 @@ use std::{
 
 use ahash::AHashMap;
 
-#[derive(Debug)]
+use super::schema;
+
+#[derive(Debug, Clone)]
 pub struct EnvSettings {
     pub args: AHashMap<String, String>,
 }
@@ -43,42 +45,58 @@ impl Default for EnvSettings {
 
 impl EnvSettings {
     pub fn new() -> Self {
+        Self::try_new().unwrap_or_else(|err| soft_panic(&err))
+    }
+
+    pub fn try_new() -> Result<Self, String> {
+        Self::from_args(env::args().skip(1))
+    }
+
+    pub fn from_args(cmd_args: impl IntoIterator<Item = String>) -> Result<Self, String> {
         let mut args = AHashMap::default();
         let mut current_key: Option<String> = None;
 
-        for arg in env::args().into_iter().skip(1) {
+        for arg in cmd_args {
             if let Some((key, value)) = arg.split_once('=') {
                 if let Some(key) = key.strip_prefix("--") {
                     args.insert(key.to_lowercase(), value.to_string());
                 } else {
-                    soft_panic(&format!("Invalid command line argument: {}", key));
+                    return Err(format!("Invalid command line argument: {}", key));
+                }
+            } else if let Some(key) = arg.strip_prefix("--") {
+                // A previous argument without a value is a flag
+                if let Some(flag) = current_key.replace(key.to_lowercase()) {
+                    args.insert(flag, "true".to_string());
                 }
             } else if let Some(key) = std::mem::take(&mut current_key) {
                 args.insert(key, arg);
-            } else if let Some(key) = arg.strip_prefix("--") {
-                current_key = Some(key.to_lowercase());
             } else {
-                soft_panic(&format!("Invalid command line argument: {}", arg));
+                return Err(format!("Invalid command line argument: {}", arg));
             }
         }
+        if let Some(flag) = current_key {
+            args.insert(flag, "true".to_string());
+        }
 
         // Read config file if it was provided
         if let Some(config_path) = args.remove("config") {
-            std::fs::read(&config_path)
-                .unwrap_or_else(|err| {
-                    soft_panic(&format!(
-                        "Failed to read config file {}: {}",
-                        config_path, err
-                    ));
-                })
-                .lines()
-                .for_each(|line| {
-                    let line = line.unwrap_or_else(|err| {
-                        soft_panic(&format!(
-                            "Failed to read config file {}: {}",
-                            config_path, err
-                        ));
-                    });
+            if config_path.ends_with(".toml") {
+                let contents = std::fs::read_to_string(&config_path).map_err(|err| {
+                    format!("Failed to read config file {}: {}", config_path, err)
+                })?;
+                for (key, value) in schema::parse_toml(&contents)
+                    .map_err(|err| format!("Error in config file {}: {}", config_path, err))?
+                {
+                    args.entry(key).or_insert(value);
+                }
+            } else {
+                for line in std::fs::read(&config_path)
+                    .map_err(|err| format!("Failed to read config file {}: {}", config_path, err))?
+                    .lines()
+                {
+                    let line = line.map_err(|err| {
+                        format!("Failed to read config file {}: {}", config_path, err)
+                    })?;
                     let line = line.trim();
                     if !line.is_empty() && !line.starts_with('#') {
                         if let Some((key, value)) = line.split_once(':') {
@@ -96,13 +114,16 @@ impl EnvSettings {
                                 }
                             }
                         } else {
-                            soft_panic(&format!("Invalid config file line: {}", line));
+                            return Err(format!("Invalid config file line: {}", line));
                         }
                     }
-                });
+                }
+            }
         }
 
-        EnvSettings { args }
+        schema::validate(&args)?;
+
+        Ok(EnvSettings { args })
     }
 
     pub fn get(&self, name: &str) -> Option<String> {
@@ -134,6 +155,20 @@ impl EnvSettings {
         }
     }
 
+    pub fn try_parse<T>(&self, name: &str) -> Result<Option<T>, String>
+    where
+        T: FromStr,
+    {
+        if let Some(value) = self.get(name) {
+            value
+                .parse::<T>()
+                .map(Some)
+                .map_err(|_| format!("Failed to parse argument: {}", name))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub fn parse_list(&self, name: &str) -> Option<Vec<String>> {
         if let Some(value) = self.get(name) {
             value
@@ -21,25 +21,31 @@
  * for more details.
 */
 
+use std::path::PathBuf;
+
 use crate::nlp::Language;
 
-use super::env_settings::EnvSettings;
+use super::{
+    env_settings::{soft_panic, EnvSettings},
+    retention::RetentionRule,
+    Reloadable,
+};
 
 pub struct JMAPConfig {
     pub blob_temp_ttl: u64,
     pub default_language: Language,
 
-    pub max_size_upload: usize,
-    pub max_concurrent_uploads: usize,
-    pub max_size_request: usize,
-    pub max_concurrent_requests: usize,
+    pub max_size_upload: Reloadable<usize>,
+    pub max_concurrent_uploads: Reloadable<usize>,
+    pub max_size_request: Reloadable<usize>,
+    pub max_concurrent_requests: Reloadable<usize>,
     pub max_calls_in_request: usize,
     pub max_objects_in_get: usize,
     pub max_objects_in_set: usize,
 
-    pub rate_limit_authenticated: (u64, u64),
-    pub rate_limit_anonymous: (u64, u64),
-    pub rate_limit_auth: (u64, u64),
+    pub rate_limit_authenticated: Reloadable<(u64, u64)>,
+    pub rate_limit_anonymous: Reloadable<(u64, u64)>,
+    pub rate_limit_auth: Reloadable<(u64, u64)>,
     pub use_forwarded_header: bool,
 
     pub query_max_results: usize,
@@ -47,10 +53,13 @@ pub struct JMAPConfig {
     pub mailbox_name_max_len: usize,
     pub mailbox_max_total: usize,
     pub mailbox_max_depth: usize,
-    pub mail_max_size: usize,
-    pub mail_attachments_max_size: usize,
+    pub mail_max_size: Reloadable<usize>,
+    pub mail_attachments_max_size: Reloadable<usize>,
     pub mail_import_max_items: usize,
     pub mail_parse_max_items: usize,
+    pub mail_duplicate_window: u64,
+    pub submission_sent_to_delegate: bool,
+    pub mailbox_retention: Vec<RetentionRule>,
 
     pub push_max_total: usize,
     pub ws_heartbeat_interval: u64,
@@ -59,15 +68,24 @@ pub struct JMAPConfig {
     pub event_source_throttle: u64,
 
     pub raft_commit_timeout: u64,
+
+    pub backup_path: Option<PathBuf>,
+    pub smime_trust_store: Option<PathBuf>,
+    pub pgp_keyring: Option<PathBuf>,
 }
 
 impl From<&EnvSettings> for JMAPConfig {
     fn from(settings: &EnvSettings) -> Self {
-        JMAPConfig {
-            max_size_upload: settings.parse("max-size-upload").unwrap_or(50000000),
-            max_concurrent_uploads: settings.parse("max-concurrent-uploads").unwrap_or(4),
-            max_concurrent_requests: settings.parse("max-concurrent-requests").unwrap_or(4),
-            max_size_request: settings.parse("max-size-request").unwrap_or(10000000),
+        let config = JMAPConfig {
+            max_size_upload: Reloadable::default(),
+            max_concurrent_uploads: Reloadable::default(),
+            max_size_request: Reloadable::default(),
+            max_concurrent_requests: Reloadable::default(),
+            mail_max_size: Reloadable::default(),
+            mail_attachments_max_size: Reloadable::default(),
+            rate_limit_authenticated: Reloadable::default(),
+            rate_limit_anonymous: Reloadable::default(),
+            rate_limit_auth: Reloadable::default(),
             max_calls_in_request: settings.parse("max-calls-in-request").unwrap_or(16),
             max_objects_in_get: settings.parse("max-objects-in-get").unwrap_or(500),
             max_objects_in_set: settings.parse("max-objects-in-set").unwrap_or(500),
@@ -77,12 +95,25 @@ impl From<&EnvSettings> for JMAPConfig {
             mailbox_name_max_len: settings.parse("mailbox-name-max-len").unwrap_or(255),
             mailbox_max_total: settings.parse("mailbox-max-total").unwrap_or(1000),
             mailbox_max_depth: settings.parse("mailbox-max-depth").unwrap_or(10),
-            mail_attachments_max_size: settings
-                .parse("mail-attachments-max-size")
-                .unwrap_or(50000000),
-            mail_max_size: settings.parse("mail-max-size").unwrap_or(104857600),
             mail_import_max_items: settings.parse("mail-import-max-items").unwrap_or(5),
             mail_parse_max_items: settings.parse("mail-parse-max-items").unwrap_or(5),
+            mail_duplicate_window: settings.parse("mail-duplicate-window").unwrap_or(0),
+            submission_sent_to_delegate: match settings.get("submission-sent-copy").as_deref() {
+                Some("delegate") => true,
+                Some("owner") | None => false,
+                Some(value) => soft_panic(&format!(
+                    "Invalid value '{}' for 'submission-sent-copy', expected 'owner' or 'delegate'.",
+                    value
+                )),
+            },
+            mailbox_retention: settings
+                .parse_list("mailbox-retention")
+                .unwrap_or_default()
+                .iter()
+                .filter(|rule| !rule.trim().is_empty())
+                .map(|rule| RetentionRule::parse(rule))
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap_or_else(|err| soft_panic(&err)),
             push_max_total: settings.parse("push-max-total").unwrap_or(100),
             ws_client_timeout: settings.parse("ws-client-timeout").unwrap_or(10 * 1000),
             ws_heartbeat_interval: settings.parse("ws-heartbeat-interval").unwrap_or(5 * 1000),
@@ -95,37 +126,64 @@ impl From<&EnvSettings> for JMAPConfig {
                     .unwrap_or_else(|| "en".to_string()),
             )
             .unwrap_or(Language::English),
-            rate_limit_authenticated: settings
-                .get("rate-limit-authenticated")
-                .unwrap_or_else(|| "1000/60".to_string())
-                .split_once('/')
-                .and_then(|(a, b)| {
-                    a.parse::<u64>()
-                        .ok()
-                        .map(|a| (a, b.parse::<u64>().unwrap_or(60)))
-                })
-                .unwrap_or((1000, 60)),
-            rate_limit_anonymous: settings
-                .get("rate-limit-anonymous")
-                .unwrap_or_else(|| "100/60".to_string())
-                .split_once('/')
-                .and_then(|(a, b)| {
-                    a.parse::<u64>()
-                        .ok()
-                        .map(|a| (a, b.parse::<u64>().unwrap_or(60)))
-                })
-                .unwrap_or((100, 60)),
-            rate_limit_auth: settings
-                .get("rate-limit-auth")
-                .unwrap_or_else(|| "10/60".to_string())
-                .split_once('/')
-                .and_then(|(a, b)| {
-                    a.parse::<u64>()
-                        .ok()
-                        .map(|a| (a, b.parse::<u64>().unwrap_or(60)))
-                })
-                .unwrap_or((100, 60)),
             use_forwarded_header: settings.parse("use-forwarded-header").unwrap_or(false),
-        }
+            backup_path: settings.get("backup-path").map(PathBuf::from),
+            smime_trust_store: settings.get("smime-trust-store").map(PathBuf::from),
+            pgp_keyring: settings.get("pgp-keyring").map(PathBuf::from),
+        };
+        config
+            .reload(settings)
+            .unwrap_or_else(|err| soft_panic(&err));
+        config
     }
 }
+
+impl JMAPConfig {
+    /// Applies the limits that can be changed at runtime. Nothing is
+    /// modified if any of the values fails to parse.
+    pub fn reload(&self, settings: &EnvSettings) -> Result<(), String> {
+        let max_size_upload = settings.try_parse("max-size-upload")?.unwrap_or(50000000);
+        let max_concurrent_uploads = settings.try_parse("max-concurrent-uploads")?.unwrap_or(4);
+        let max_size_request = settings.try_parse("max-size-request")?.unwrap_or(10000000);
+        let max_concurrent_requests = settings.try_parse("max-concurrent-requests")?.unwrap_or(4);
+        let mail_max_size = settings.try_parse("mail-max-size")?.unwrap_or(104857600);
+        let mail_attachments_max_size = settings
+            .try_parse("mail-attachments-max-size")?
+            .unwrap_or(50000000);
+        let rate_limit_authenticated =
+            parse_rate_limit(settings, "rate-limit-authenticated", "1000/60")?;
+        let rate_limit_anonymous = parse_rate_limit(settings, "rate-limit-anonymous", "100/60")?;
+        let rate_limit_auth = parse_rate_limit(settings, "rate-limit-auth", "10/60")?;
+
+        self.max_size_upload.set(max_size_upload);
+        self.max_concurrent_uploads.set(max_concurrent_uploads);
+        self.max_size_request.set(max_size_request);
+        self.max_concurrent_requests.set(max_concurrent_requests);
+        self.mail_max_size.set(mail_max_size);
+        self.mail_attachments_max_size
+            .set(mail_attachments_max_size);
+        self.rate_limit_authenticated.set(rate_limit_authenticated);
+        self.rate_limit_anonymous.set(rate_limit_anonymous);
+        self.rate_limit_auth.set(rate_limit_auth);
+
+        Ok(())
+    }
+}
+
+fn parse_rate_limit(
+    settings: &EnvSettings,
+    name: &str,
+    default: &str,
+) -> Result<(u64, u64), String> {
+    settings
+        .get(name)
+        .unwrap_or_else(|| default.to_string())
+        .split_once('/')
+        .and_then(|(a, b)| {
+            a.trim()
+                .parse::<u64>()
+                .ok()
+                .map(|a| (a, b.trim().parse::<u64>().unwrap_or(60)))
+        })
+        .ok_or_else(|| format!("Failed to parse argument: {}", name))
+}
@@ -23,3 +23,32 @@
 
 pub mod env_settings;
 pub mod jmap;
+pub mod retention;
+pub mod schema;
+
+use parking_lot::RwLock;
+
+/// A configuration value that can be replaced while the server is running.
+#[derive(Debug, Default)]
+pub struct Reloadable<T: Copy>(RwLock<T>);
+
+impl<T: Copy> Reloadable<T> {
+    pub fn new(value: T) -> Self {
+        Reloadable(RwLock::new(value))
+    }
+
+    #[inline(always)]
+    pub fn get(&self) -> T {
+        *self.0.read()
+    }
+
+    pub fn set(&self, value: T) {
+        *self.0.write() = value;
+    }
+}
+
+impl<T: Copy> From<T> for Reloadable<T> {
+    fn from(value: T) -> Self {
+        Reloadable::new(value)
+    }
+}
@@ -0,0 +1,164 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+/// Retention rule applied to every mailbox with the given role, unless the
+/// mailbox defines a policy of its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetentionRule {
+    pub role: String,
+    pub max_age: Option<u64>,
+    pub max_emails: Option<u32>,
+    /// Role of the mailbox expired messages are moved to, or `None` if
+    /// they are destroyed.
+    pub move_to: Option<String>,
+}
+
+const ROLES: &[&str] = &[
+    "inbox", "trash", "spam", "junk", "drafts", "archive", "sent",
+];
+
+impl RetentionRule {
+    /// Parses a rule in the format
+    /// `<role> [max-age=<duration>] [max-emails=<count>] <destroy | move=<role>>`,
+    /// where durations are in seconds or suffixed with `m`, `h`, `d` or `w`.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let mut parts = value.split_whitespace();
+        let role = parts
+            .next()
+            .map(|role| role.to_lowercase())
+            .filter(|role| ROLES.contains(&role.as_str()))
+            .ok_or_else(|| format!("Invalid mailbox role in retention rule '{}'.", value))?;
+        let mut rule = RetentionRule {
+            role,
+            max_age: None,
+            max_emails: None,
+            move_to: None,
+        };
+        let mut has_action = false;
+
+        for part in parts {
+            match part.split_once('=') {
+                Some(("max-age", max_age)) => {
+                    rule.max_age = parse_duration(max_age)
+                        .filter(|max_age| *max_age > 0)
+                        .ok_or_else(|| {
+                            format!("Invalid maximum age in retention rule '{}'.", value)
+                        })?
+                        .into();
+                }
+                Some(("max-emails", max_emails)) => {
+                    rule.max_emails = max_emails
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|max_emails| *max_emails > 0)
+                        .ok_or_else(|| {
+                            format!("Invalid maximum emails in retention rule '{}'.", value)
+                        })?
+                        .into();
+                }
+                Some(("move", move_to))
+                    if !has_action && ROLES.contains(&move_to) && move_to != rule.role.as_str() =>
+                {
+                    rule.move_to = move_to.to_string().into();
+                    has_action = true;
+                }
+                None if part == "destroy" && !has_action => {
+                    has_action = true;
+                }
+                _ => {
+                    return Err(format!(
+                        "Invalid parameter '{}' in retention rule '{}'.",
+                        part, value
+                    ));
+                }
+            }
+        }
+
+        if rule.max_age.is_none() && rule.max_emails.is_none() {
+            Err(format!(
+                "Retention rule '{}' requires a maximum age or a maximum number of emails.",
+                value
+            ))
+        } else if !has_action {
+            Err(format!(
+                "Retention rule '{}' requires either 'destroy' or 'move=<role>'.",
+                value
+            ))
+        } else {
+            Ok(rule)
+        }
+    }
+}
+
+fn parse_duration(value: &str) -> Option<u64> {
+    let (amount, multiplier) = match value.as_bytes().last()? {
+        b's' => (&value[..value.len() - 1], 1),
+        b'm' => (&value[..value.len() - 1], 60),
+        b'h' => (&value[..value.len() - 1], 3600),
+        b'd' => (&value[..value.len() - 1], 86400),
+        b'w' => (&value[..value.len() - 1], 7 * 86400),
+        _ => (value, 1),
+    };
+    amount.parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetentionRule;
+
+    #[test]
+    fn parse_retention_rules() {
+        assert_eq!(
+            RetentionRule::parse("trash max-age=30d destroy").unwrap(),
+            RetentionRule {
+                role: "trash".to_string(),
+                max_age: Some(30 * 86400),
+                max_emails: None,
+                move_to: None,
+            }
+        );
+        assert_eq!(
+            RetentionRule::parse("Inbox max-age=3600 max-emails=1000 move=archive").unwrap(),
+            RetentionRule {
+                role: "inbox".to_string(),
+                max_age: Some(3600),
+                max_emails: Some(1000),
+                move_to: Some("archive".to_string()),
+            }
+        );
+
+        for invalid in [
+            "",
+            "folder max-age=1d destroy",
+            "trash destroy",
+            "trash max-age=1d",
+            "trash max-age=0 destroy",
+            "trash max-age=1y destroy",
+            "trash max-emails=-1 destroy",
+            "trash max-age=1d move=trash",
+            "trash max-age=1d destroy move=archive",
+        ] {
+            assert!(RetentionRule::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
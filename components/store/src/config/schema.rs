@@ -0,0 +1,577 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{borrow::Cow, fmt::Write, net::IpAddr, str::FromStr};
+
+use ahash::AHashMap;
+
+use crate::nlp::Language;
+
+use super::env_settings::EnvSettings;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Type {
+    Boolean,
+    Integer { min: u64, max: u64 },
+    Float,
+    String,
+    Secret,
+    Url,
+    IpAddr,
+    IpAddrList,
+    List,
+    RateLimit,
+    Cron,
+    LogLevel,
+    Language,
+    Enum(&'static [&'static str]),
+}
+
+#[derive(Debug)]
+pub struct Setting {
+    pub section: Option<&'static str>,
+    pub name: &'static str,
+    pub key: &'static str,
+    pub stype: Type,
+    pub default: Option<&'static str>,
+}
+
+const fn setting(
+    section: Option<&'static str>,
+    name: &'static str,
+    key: &'static str,
+    stype: Type,
+    default: Option<&'static str>,
+) -> Setting {
+    Setting {
+        section,
+        name,
+        key,
+        stype,
+        default,
+    }
+}
+
+const fn int(min: u64, max: u64) -> Type {
+    Type::Integer { min, max }
+}
+
+const PORT: Type = int(1, u16::MAX as u64);
+const SIZE: Type = int(1, u64::MAX);
+const COUNT: Type = int(1, u32::MAX as u64);
+const MILLIS: Type = int(1, 30 * 86400 * 1000);
+const SECS: Type = int(1, 365 * 86400);
+
+const JMAP: Option<&str> = Some("jmap");
+const LMTP: Option<&str> = Some("lmtp");
+const IMAP: Option<&str> = Some("imap");
+const CLUSTER: Option<&str> = Some("cluster");
+const OAUTH: Option<&str> = Some("oauth");
+const PUSH: Option<&str> = Some("push");
+const SMTP: Option<&str> = Some("smtp");
+const STORE: Option<&str> = Some("store");
+const SCHEDULE: Option<&str> = Some("schedule");
+const SPAM: Option<&str> = Some("spam-filter");
+
+/// All settings accepted in configuration files, grouped by section in the
+/// order they are printed by `--check-config`.
+#[rustfmt::skip]
+pub static SETTINGS: &[Setting] = &[
+    setting(None, "db-path", "db-path", Type::String, Some("/usr/local/stalwart-jmap/data")),
+    setting(None, "log-level", "log-level", Type::LogLevel, Some("info")),
+    setting(None, "worker-pool-size", "worker-pool-size", int(0, 1024), None),
+    setting(None, "encryption-key", "encryption-key", Type::Secret, None),
+    setting(None, "backup-path", "backup-path", Type::String, None),
+    // JMAP
+    setting(JMAP, "url", "jmap-url", Type::Url, None),
+    setting(JMAP, "bind-addr", "jmap-bind-addr", Type::IpAddr, Some("0.0.0.0")),
+    setting(JMAP, "port", "jmap-port", PORT, Some("8080")),
+    setting(JMAP, "cert-path", "jmap-cert-path", Type::String, None),
+    setting(JMAP, "key-path", "jmap-key-path", Type::String, None),
+    setting(JMAP, "strict-cors", "strict-cors", Type::Boolean, Some("false")),
+    setting(JMAP, "use-forwarded-header", "use-forwarded-header", Type::Boolean, Some("false")),
+    setting(JMAP, "default-language", "default-language", Type::Language, Some("en")),
+    setting(JMAP, "max-size-upload", "max-size-upload", SIZE, Some("50000000")),
+    setting(JMAP, "max-size-request", "max-size-request", SIZE, Some("10000000")),
+    setting(JMAP, "max-concurrent-uploads", "max-concurrent-uploads", COUNT, Some("4")),
+    setting(JMAP, "max-concurrent-requests", "max-concurrent-requests", COUNT, Some("4")),
+    setting(JMAP, "max-calls-in-request", "max-calls-in-request", COUNT, Some("16")),
+    setting(JMAP, "max-objects-in-get", "max-objects-in-get", COUNT, Some("500")),
+    setting(JMAP, "max-objects-in-set", "max-objects-in-set", COUNT, Some("500")),
+    setting(JMAP, "changes-max-results", "changes-max-results", COUNT, Some("5000")),
+    setting(JMAP, "query-max-results", "query-max-results", COUNT, Some("5000")),
+    setting(JMAP, "mailbox-name-max-len", "mailbox-name-max-len", COUNT, Some("255")),
+    setting(JMAP, "mailbox-max-total", "mailbox-max-total", COUNT, Some("1000")),
+    setting(JMAP, "mailbox-max-depth", "mailbox-max-depth", COUNT, Some("10")),
+    setting(JMAP, "mail-max-size", "mail-max-size", SIZE, Some("104857600")),
+    setting(JMAP, "mail-attachments-max-size", "mail-attachments-max-size", SIZE, Some("50000000")),
+    setting(JMAP, "mail-import-max-items", "mail-import-max-items", COUNT, Some("5")),
+    setting(JMAP, "mail-parse-max-items", "mail-parse-max-items", COUNT, Some("5")),
+    setting(JMAP, "mail-duplicate-window", "mail-duplicate-window", int(0, 365 * 86400), Some("0")),
+    setting(JMAP, "submission-sent-copy", "submission-sent-copy", Type::Enum(&["owner", "delegate"]), Some("owner")),
+    setting(JMAP, "mailbox-retention", "mailbox-retention", Type::List, None),
+    setting(JMAP, "smime-trust-store", "smime-trust-store", Type::String, None),
+    setting(JMAP, "pgp-keyring", "pgp-keyring", Type::String, None),
+    setting(JMAP, "blob-temp-ttl", "blob-temp-ttl", SECS, Some("3600")),
+    setting(JMAP, "ws-client-timeout", "ws-client-timeout", MILLIS, Some("10000")),
+    setting(JMAP, "ws-heartbeat-interval", "ws-heartbeat-interval", MILLIS, Some("5000")),
+    setting(JMAP, "ws-throttle", "ws-throttle", MILLIS, Some("1000")),
+    setting(JMAP, "event-source-throttle", "event-source-throttle", MILLIS, Some("1000")),
+    setting(JMAP, "rate-limit-authenticated", "rate-limit-authenticated", Type::RateLimit, Some("1000/60")),
+    setting(JMAP, "rate-limit-anonymous", "rate-limit-anonymous", Type::RateLimit, Some("100/60")),
+    setting(JMAP, "rate-limit-auth", "rate-limit-auth", Type::RateLimit, Some("10/60")),
+    // LMTP
+    setting(LMTP, "bind-addr", "lmtp-bind-addr", Type::IpAddr, Some("127.0.0.1")),
+    setting(LMTP, "port", "lmtp-port", PORT, Some("11200")),
+    setting(LMTP, "cert-path", "lmtp-cert-path", Type::String, None),
+    setting(LMTP, "key-path", "lmtp-key-path", Type::String, None),
+    setting(LMTP, "tls-only", "lmtp-tls-only", Type::Boolean, Some("false")),
+    setting(LMTP, "trusted-ips", "lmtp-trusted-ips", Type::IpAddrList, None),
+    setting(LMTP, "unix-socket", "lmtp-unix-socket", Type::String, None),
+    setting(LMTP, "unix-socket-mode", "lmtp-unix-socket-mode", Type::String, None),
+    setting(LMTP, "auth-verify", "lmtp-auth-verify", Type::Boolean, Some("false")),
+    setting(LMTP, "auth-server-id", "lmtp-auth-server-id", Type::String, None),
+    // IMAP
+    setting(IMAP, "bind-addr", "imap-bind-addr", Type::IpAddr, Some("0.0.0.0")),
+    setting(IMAP, "port", "imap-port", PORT, None),
+    setting(IMAP, "tls-port", "imap-tls-port", PORT, None),
+    setting(IMAP, "cert-path", "imap-cert-path", Type::String, None),
+    setting(IMAP, "key-path", "imap-key-path", Type::String, None),
+    setting(IMAP, "plaintext-auth", "imap-plaintext-auth", Type::Boolean, Some("false")),
+    // Spam filter
+    setting(SPAM, "type", "spam-filter-type", Type::String, None),
+    setting(SPAM, "url", "spam-filter-url", Type::Url, None),
+    setting(SPAM, "milter-addr", "spam-filter-milter-addr", Type::String, None),
+    setting(SPAM, "timeout", "spam-filter-timeout", MILLIS, Some("30000")),
+    setting(SPAM, "junk-score", "spam-filter-junk-score", Type::Float, None),
+    setting(SPAM, "reject-score", "spam-filter-reject-score", Type::Float, None),
+    // Cluster
+    setting(CLUSTER, "seed-nodes", "seed-nodes", Type::List, None),
+    setting(CLUSTER, "advertise-addr", "rpc-advertise-addr", Type::IpAddr, None),
+    setting(CLUSTER, "bind-addr", "rpc-bind-addr", Type::IpAddr, None),
+    setting(CLUSTER, "port", "rpc-port", PORT, Some("7911")),
+    setting(CLUSTER, "cert-path", "rpc-cert-path", Type::String, None),
+    setting(CLUSTER, "key-path", "rpc-key-path", Type::String, None),
+    setting(CLUSTER, "tls-domain", "rpc-tls-domain", Type::String, None),
+    setting(CLUSTER, "shard-id", "shard-id", int(0, u32::MAX as u64), Some("0")),
+    setting(CLUSTER, "peer-ping-interval", "peer-ping-interval", MILLIS, Some("500")),
+    setting(CLUSTER, "raft-batch-max", "raft-batch-max", SIZE, Some("10485760")),
+    setting(CLUSTER, "raft-election-timeout", "raft-election-timeout", MILLIS, Some("1000")),
+    setting(CLUSTER, "raft-commit-timeout", "raft-commit-timeout", MILLIS, Some("1000")),
+    setting(CLUSTER, "inactivity-timeout", "rpc-inactivity-timeout", MILLIS, Some("300000")),
+    setting(CLUSTER, "timeout", "rpc-timeout", MILLIS, Some("1000")),
+    setting(CLUSTER, "retries-max", "rpc-retries-max", COUNT, Some("5")),
+    setting(CLUSTER, "backoff-max", "rpc-backoff-max", MILLIS, Some("180000")),
+    // OAuth
+    setting(OAUTH, "user-code-expiry", "oauth-user-code-expiry", SECS, Some("1800")),
+    setting(OAUTH, "auth-code-expiry", "oauth-auth-code-expiry", SECS, Some("600")),
+    setting(OAUTH, "token-expiry", "oauth-token-expiry", SECS, Some("3600")),
+    setting(OAUTH, "refresh-token-expiry", "oauth-refresh-token-expiry", SECS, Some("2592000")),
+    setting(OAUTH, "refresh-token-renew", "oauth-refresh-token-renew", SECS, Some("345600")),
+    setting(OAUTH, "max-attempts", "oauth-max-attempts", COUNT, Some("3")),
+    // Push
+    setting(PUSH, "max-total", "push-max-total", COUNT, Some("100")),
+    setting(PUSH, "attempt-interval", "push-attempt-interval", MILLIS, Some("60000")),
+    setting(PUSH, "attempts-max", "push-attempts-max", COUNT, Some("3")),
+    setting(PUSH, "retry-interval", "push-retry-interval", MILLIS, Some("1000")),
+    setting(PUSH, "timeout", "push-timeout", MILLIS, Some("10000")),
+    setting(PUSH, "verify-timeout", "push-verify-timeout", MILLIS, Some("60000")),
+    setting(PUSH, "throttle", "push-throttle", MILLIS, Some("1000")),
+    // SMTP relay
+    setting(SMTP, "relay-host", "smtp-relay-host", Type::String, None),
+    setting(SMTP, "relay-port", "smtp-relay-port", PORT, None),
+    setting(SMTP, "relay-auth", "smtp-relay-auth", Type::String, None),
+    setting(SMTP, "relay-secret", "smtp-relay-secret", Type::Secret, None),
+    setting(SMTP, "relay-tls", "smtp-relay-tls", Type::Boolean, Some("false")),
+    setting(SMTP, "relay-timeout", "smtp-relay-timeout", MILLIS, Some("60000")),
+    // SMTP submission
+    setting(SMTP, "submission-bind-addr", "smtp-submission-bind-addr", Type::IpAddr, Some("0.0.0.0")),
+    setting(SMTP, "submission-port", "smtp-submission-port", PORT, None),
+    setting(SMTP, "submission-tls-port", "smtp-submission-tls-port", PORT, None),
+    setting(SMTP, "submission-cert-path", "smtp-submission-cert-path", Type::String, None),
+    setting(SMTP, "submission-key-path", "smtp-submission-key-path", Type::String, None),
+    setting(SMTP, "submission-plaintext-auth", "smtp-submission-plaintext-auth", Type::Boolean, Some("false")),
+    setting(SMTP, "submission-max-rcpt", "smtp-submission-max-rcpt", COUNT, Some("100")),
+    // Store
+    setting(STORE, "blob-min-size", "blob-min-size", SIZE, Some("16384")),
+    setting(STORE, "blob-nested-levels", "blob-nested-levels", int(0, 5), Some("2")),
+    setting(STORE, "max-changelog-entries", "max-changelog-entries", COUNT, Some("10000")),
+    setting(STORE, "cache-size-ids", "cache-size-ids", SIZE, Some("33554432")),
+    setting(STORE, "cache-tti-ids", "cache-tti-ids", SECS, Some("3600")),
+    setting(STORE, "cache-tti-sharings", "cache-tti-sharings", SECS, Some("300")),
+    setting(STORE, "cache-tti-acl", "cache-tti-acl", SECS, Some("3600")),
+    setting(STORE, "cache-tti-recipients", "cache-tti-recipients", SECS, Some("86400")),
+    setting(STORE, "encryption-key", "store-encryption-key", Type::Secret, None),
+    setting(STORE, "encryption-key-file", "store-encryption-key-file", Type::String, None),
+    setting(STORE, "encryption-previous-keys", "store-encryption-previous-keys", Type::List, None),
+    setting(STORE, "encryption-required", "store-encryption-required", Type::Boolean, Some("false")),
+    // Housekeeper
+    setting(SCHEDULE, "purge-accounts", "schedule-purge-accounts", Type::Cron, Some("0 3 *")),
+    setting(SCHEDULE, "purge-blobs", "schedule-purge-blobs", Type::Cron, Some("30 3 *")),
+    setting(SCHEDULE, "snapshot-log", "schedule-snapshot-log", Type::Cron, Some("45 3 *")),
+    setting(SCHEDULE, "compact-db", "schedule-compact-db", Type::Cron, Some("0 4 *")),
+    setting(SCHEDULE, "backup", "schedule-backup", Type::Cron, None),
+    setting(SCHEDULE, "retention", "schedule-retention", Type::Cron, Some("15 4 *")),
+];
+
+/// Arguments that are only accepted on the command line.
+#[rustfmt::skip]
+pub static COMMANDS: &[Setting] = &[
+    setting(None, "check-config", "check-config", Type::Boolean, None),
+    setting(None, "set-admin-password", "set-admin-password", Type::Secret, None),
+    setting(None, "restore", "restore", Type::String, None),
+    setting(None, "restore-index", "restore-index", int(0, u64::MAX), None),
+];
+
+impl Setting {
+    pub fn find(key: &str) -> Option<&'static Setting> {
+        SETTINGS
+            .iter()
+            .chain(COMMANDS.iter())
+            .find(|setting| setting.key == key)
+    }
+
+    pub fn validate(&self, value: &str) -> Result<(), String> {
+        let is_valid = match self.stype {
+            Type::Boolean => value.parse::<bool>().is_ok(),
+            Type::Float => value
+                .parse::<f64>()
+                .map_or(false, |value| value.is_finite()),
+            Type::Integer { min, max } => {
+                let value = value.parse::<u64>().map_err(|_| {
+                    format!(
+                        "Invalid value '{}' for '{}', expected an integer.",
+                        value,
+                        self.path()
+                    )
+                })?;
+                if !(min..=max).contains(&value) {
+                    return Err(format!(
+                        "Value {} for '{}' is out of range, expected {} to {}.",
+                        value,
+                        self.path(),
+                        min,
+                        max
+                    ));
+                }
+                true
+            }
+            Type::String | Type::Secret => !value.is_empty(),
+            Type::Url => value.starts_with("http://") || value.starts_with("https://"),
+            Type::IpAddr => value.parse::<IpAddr>().is_ok(),
+            Type::IpAddrList => split_list(value).all(|ip| ip.parse::<IpAddr>().is_ok()),
+            Type::List => split_list(value).all(|item| !item.is_empty()),
+            Type::RateLimit => value
+                .split_once('/')
+                .and_then(|(requests, period)| {
+                    Some(
+                        requests.trim().parse::<u64>().ok()? > 0
+                            && period.trim().parse::<u64>().ok()? > 0,
+                    )
+                })
+                .unwrap_or(false),
+            Type::Cron => is_valid_cron(value),
+            Type::LogLevel => tracing::Level::from_str(value).is_ok(),
+            Type::Language => Language::from_iso_639(value).is_some(),
+            Type::Enum(values) => values.contains(&value),
+        };
+
+        if is_valid {
+            Ok(())
+        } else {
+            Err(format!(
+                "Invalid value '{}' for '{}', expected {}.",
+                value,
+                self.path(),
+                self.stype.description()
+            ))
+        }
+    }
+
+    /// Returns the dotted TOML path of the setting.
+    pub fn path(&self) -> String {
+        if let Some(section) = self.section {
+            format!("{}.{}", section, self.name)
+        } else {
+            self.name.to_string()
+        }
+    }
+}
+
+impl Type {
+    fn description(&self) -> Cow<'static, str> {
+        let description = match self {
+            Type::Boolean => "'true' or 'false'",
+            Type::Integer { .. } => "an integer",
+            Type::Float => "a number",
+            Type::String | Type::Secret => "a non-empty string",
+            Type::Url => "an http or https URL",
+            Type::IpAddr => "an IP address",
+            Type::IpAddrList => "a list of IP addresses",
+            Type::List => "a list of non-empty values",
+            Type::RateLimit => "'<requests>/<seconds>'",
+            Type::Cron => "'<minute> <hour> <weekday or *>'",
+            Type::LogLevel => "one of 'trace', 'debug', 'info', 'warn' or 'error'",
+            Type::Language => "an ISO 639-1 language code",
+            Type::Enum(values) => {
+                return format!(
+                    "one of {}",
+                    values
+                        .iter()
+                        .map(|value| format!("'{}'", value))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+                .into();
+            }
+        };
+        description.into()
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(if value.contains(';') { ';' } else { ',' })
+        .map(|item| item.trim())
+}
+
+fn is_valid_cron(value: &str) -> bool {
+    let mut parts = value.split(' ');
+    matches!(
+        (parts.next(), parts.next(), parts.next(), parts.next()),
+        (Some(minute), Some(hour), Some(day), None)
+            if minute.parse::<u32>().map_or(false, |m| m < 60)
+                && hour.parse::<u32>().map_or(false, |h| h < 24)
+                && (day == "*" || day.parse::<u32>().map_or(false, |d| (1..=7).contains(&d)))
+    )
+}
+
+/// Makes sure that all settings are known and have valid values.
+pub fn validate(args: &AHashMap<String, String>) -> Result<(), String> {
+    for (key, value) in args {
+        Setting::find(key)
+            .ok_or_else(|| format!("Unknown setting '{}'.", key))?
+            .validate(value)?;
+    }
+    Ok(())
+}
+
+/// Parses a TOML configuration file into flat settings.
+pub fn parse_toml(contents: &str) -> Result<AHashMap<String, String>, String> {
+    let root = contents
+        .parse::<toml::Value>()
+        .map_err(|err| format!("Failed to parse TOML configuration: {}", err))?;
+    let mut args = AHashMap::default();
+
+    for (name, value) in root
+        .as_table()
+        .ok_or_else(|| "Invalid TOML configuration.".to_string())?
+    {
+        if let toml::Value::Table(table) = value {
+            for (sub_name, value) in table {
+                insert_toml_value(&mut args, Some(name), sub_name, value)?;
+            }
+        } else {
+            insert_toml_value(&mut args, None, name, value)?;
+        }
+    }
+
+    Ok(args)
+}
+
+fn insert_toml_value(
+    args: &mut AHashMap<String, String>,
+    section: Option<&str>,
+    name: &str,
+    value: &toml::Value,
+) -> Result<(), String> {
+    let path = section.map_or_else(|| name.to_string(), |s| format!("{}.{}", s, name));
+    let setting = SETTINGS
+        .iter()
+        .find(|setting| setting.section == section && setting.name == name)
+        .ok_or_else(|| format!("Unknown setting '{}'.", path))?;
+
+    let value = match (value, setting.stype) {
+        (toml::Value::String(value), _) => value.to_string(),
+        (toml::Value::Integer(value), Type::Integer { .. } | Type::String) if *value >= 0 => {
+            value.to_string()
+        }
+        (toml::Value::Integer(value), Type::Integer { .. }) => {
+            return Err(format!(
+                "Value {} for '{}' is out of range, expected a positive integer.",
+                value, path
+            ));
+        }
+        (toml::Value::Boolean(value), Type::Boolean) => value.to_string(),
+        (toml::Value::Float(value), Type::Float) => value.to_string(),
+        (toml::Value::Integer(value), Type::Float) => value.to_string(),
+        (toml::Value::Array(items), Type::List | Type::IpAddrList) => items
+            .iter()
+            .map(|item| {
+                item.as_str().map(|item| item.to_string()).ok_or_else(|| {
+                    format!("Invalid value for '{}', expected a list of strings.", path)
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?
+            .join(";"),
+        _ => {
+            return Err(format!(
+                "Invalid value for '{}', expected {}.",
+                path,
+                setting.stype.description()
+            ));
+        }
+    };
+
+    setting.validate(&value)?;
+    args.insert(setting.key.to_string(), value);
+    Ok(())
+}
+
+/// Renders the effective configuration as TOML, including defaults.
+pub fn print_effective(settings: &EnvSettings) -> String {
+    let mut result = String::new();
+    let mut current_section = None;
+
+    for setting in SETTINGS {
+        let (value, is_default) = if let Some(value) = settings.get(setting.key) {
+            (value, false)
+        } else if let Some(value) = setting.default {
+            (value.to_string(), true)
+        } else {
+            continue;
+        };
+
+        if setting.section != current_section {
+            current_section = setting.section;
+            let _ = write!(result, "\n[{}]\n", setting.section.unwrap_or_default());
+        }
+
+        let value = match setting.stype {
+            Type::Secret => toml::Value::String("********".to_string()),
+            Type::Boolean => value
+                .parse::<bool>()
+                .map(toml::Value::Boolean)
+                .unwrap_or(toml::Value::String(value)),
+            Type::Integer { .. } => value
+                .parse::<i64>()
+                .map(toml::Value::Integer)
+                .unwrap_or(toml::Value::String(value)),
+            Type::Float => value
+                .parse::<f64>()
+                .map(toml::Value::Float)
+                .unwrap_or(toml::Value::String(value)),
+            Type::List | Type::IpAddrList => toml::Value::Array(
+                split_list(&value)
+                    .map(|item| toml::Value::String(item.to_string()))
+                    .collect(),
+            ),
+            _ => toml::Value::String(value),
+        };
+
+        let _ = writeln!(
+            result,
+            "{} = {}{}",
+            setting.name,
+            value,
+            if is_default { " # default" } else { "" }
+        );
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_toml, validate, Setting, SETTINGS};
+
+    #[test]
+    fn parse_toml_config() {
+        let args = parse_toml(concat!(
+            "db-path = \"/tmp/db\"\n",
+            "[jmap]\n",
+            "port = 8081\n",
+            "strict-cors = true\n",
+            "rate-limit-auth = \"5/60\"\n",
+            "[lmtp]\n",
+            "trusted-ips = [\"127.0.0.1\", \"::1\"]\n",
+            "[push]\n",
+            "attempts-max = 5\n",
+            "[smtp]\n",
+            "submission-port = 587\n",
+            "[spam-filter]\n",
+            "junk-score = 6.5\n",
+            "reject-score = 15\n",
+        ))
+        .unwrap();
+
+        assert_eq!(args.get("db-path").unwrap(), "/tmp/db");
+        assert_eq!(args.get("jmap-port").unwrap(), "8081");
+        assert_eq!(args.get("strict-cors").unwrap(), "true");
+        assert_eq!(args.get("rate-limit-auth").unwrap(), "5/60");
+        assert_eq!(args.get("lmtp-trusted-ips").unwrap(), "127.0.0.1;::1");
+        assert_eq!(args.get("push-attempts-max").unwrap(), "5");
+        assert_eq!(args.get("smtp-submission-port").unwrap(), "587");
+        assert_eq!(args.get("spam-filter-junk-score").unwrap(), "6.5");
+        assert_eq!(args.get("spam-filter-reject-score").unwrap(), "15");
+        validate(&args).unwrap();
+
+        for invalid in [
+            "[jmap]\nprot = 8080\n",
+            "[jmap]\nport = 0\n",
+            "[jmap]\nport = 70000\n",
+            "[jmap]\nport = \"http\"\n",
+            "[jmap]\nstrict-cors = 1\n",
+            "[jmap]\nrate-limit-auth = \"10\"\n",
+            "[lmtp]\ntrusted-ips = [\"localhost\"]\n",
+            "[schedule]\nbackup = \"0 25 *\"\n",
+            "[spam-filter]\njunk-score = \"high\"\n",
+            "[unknown]\nport = 8080\n",
+            "port = 8080\n",
+            "log-level = \"verbose\"\n",
+            "[jmap]\nsubmission-sent-copy = \"delegates\"\n",
+        ] {
+            assert!(parse_toml(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parse_default_config() {
+        validate(&parse_toml(include_str!("../../../../resources/config/config.toml")).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn schema_defaults() {
+        for setting in SETTINGS {
+            if let Some(default) = setting.default {
+                setting.validate(default).unwrap();
+            }
+            assert!(
+                std::ptr::eq(Setting::find(setting.key).unwrap(), setting),
+                "Duplicate key {}",
+                setting.key
+            );
+        }
+    }
+}
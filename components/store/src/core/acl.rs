@@ -43,7 +43,8 @@ pub enum ACL {
     CreateChild = 7,
     Administer = 8,
     Submit = 9,
-    None_ = 10,
+    SendOnBehalf = 10,
+    None_ = 11,
 }
 
 #[derive(
@@ -73,6 +74,7 @@ impl ACL {
             "createChild" => ACL::CreateChild,
             "administer" => ACL::Administer,
             "submit" => ACL::Submit,
+            "sendOnBehalf" => ACL::SendOnBehalf,
             _ => ACL::None_,
         }
     }
@@ -107,6 +109,7 @@ impl From<u64> for ACL {
             7 => ACL::CreateChild,
             8 => ACL::Administer,
             9 => ACL::Submit,
+            10 => ACL::SendOnBehalf,
             _ => {
                 debug_assert!(false, "Invalid ACL value: {}", value);
                 ACL::None_
@@ -128,6 +131,7 @@ impl Display for ACL {
             ACL::CreateChild => write!(f, "createChild"),
             ACL::Administer => write!(f, "administer"),
             ACL::Submit => write!(f, "submit"),
+            ACL::SendOnBehalf => write!(f, "sendOnBehalf"),
             ACL::None_ => Ok(()),
         }
     }
@@ -0,0 +1,285 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    borrow::Cow,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use aes_gcm_siv::{
+    aead::{generic_array::GenericArray, Aead, Payload},
+    Aes256GcmSiv, KeyInit, Nonce,
+};
+use rand::RngCore;
+
+use crate::config::env_settings::EnvSettings;
+
+use super::error::StoreError;
+
+const CONTEXT: &str = "Stalwart JMAP store encryption key";
+
+const MARKER: u8 = 0xe5;
+const KEY_ID_LEN: usize = 4;
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = 1 + KEY_ID_LEN + NONCE_LEN;
+
+/// Placeholder written as a merge operand to request that an existing value
+/// is re-encrypted with the current key. It is never returned to callers.
+pub const TOUCH: [u8; 1 + KEY_ID_LEN] = [MARKER, 0, 0, 0, 0];
+
+/// Encryption state of a store whose values are all encrypted.
+pub const STATE_ENCRYPTED: u8 = 0;
+/// Encryption state of a store that may still contain values written before
+/// encryption was enabled.
+pub const STATE_MIGRATING: u8 = 1;
+
+/// Authenticated encryption of values at rest using a master key.
+///
+/// Encrypted values are laid out as `marker | key id | nonce | ciphertext`,
+/// and the location of the value (the database key or the blob file name)
+/// is bound as associated data so values cannot be swapped around on disk.
+/// Only values are encrypted, database keys (which include index terms,
+/// keywords and sort prefixes) and blob file names are stored in the clear.
+///
+/// While a store is being migrated, values without a recognized header are
+/// assumed to have been written before encryption was enabled and are
+/// returned as-is. Once migrated, such values are reported as corrupted.
+pub struct StoreCipher {
+    keys: Vec<([u8; KEY_ID_LEN], Aes256GcmSiv)>,
+    is_migrating: AtomicBool,
+}
+
+impl StoreCipher {
+    pub const OVERHEAD: usize = HEADER_LEN + TAG_LEN;
+
+    pub fn new(settings: &EnvSettings) -> crate::Result<Option<Self>> {
+        let key = if let Some(key) = settings.get("store-encryption-key") {
+            key
+        } else if let Some(path) = settings.get("store-encryption-key-file") {
+            std::fs::read_to_string(&path)
+                .map_err(|err| {
+                    StoreError::InvalidArguments(format!(
+                        "Failed to read store encryption key from {}: {}",
+                        path, err
+                    ))
+                })?
+                .trim()
+                .to_string()
+        } else if settings.parse("store-encryption-required").unwrap_or(false) {
+            return Err(StoreError::InvalidArguments(
+                "Store encryption is required but no store encryption key is configured."
+                    .to_string(),
+            ));
+        } else {
+            return Ok(None);
+        };
+
+        let mut keys = vec![key];
+        if let Some(previous_keys) = settings.parse_list("store-encryption-previous-keys") {
+            keys.extend(
+                previous_keys
+                    .into_iter()
+                    .map(|key| key.trim().to_string())
+                    .filter(|key| !key.is_empty()),
+            );
+        }
+
+        Self::from_keys(&keys).map(Some)
+    }
+
+    pub fn from_keys(keys: &[impl AsRef<[u8]>]) -> crate::Result<Self> {
+        let mut cipher = StoreCipher {
+            keys: Vec::with_capacity(keys.len()),
+            is_migrating: AtomicBool::new(false),
+        };
+
+        for key in keys {
+            let key = key.as_ref();
+            if key.len() < 16 {
+                return Err(StoreError::InvalidArguments(
+                    "Store encryption keys must be at least 16 bytes long.".to_string(),
+                ));
+            }
+            let key = blake3::derive_key(CONTEXT, key);
+            let mut key_id = [0u8; KEY_ID_LEN];
+            key_id.copy_from_slice(&blake3::hash(&key).as_bytes()[..KEY_ID_LEN]);
+            if key_id == TOUCH[1..] {
+                key_id[0] = 1;
+            }
+            if cipher.keys.iter().all(|(id, _)| id != &key_id) {
+                cipher.keys.push((
+                    key_id,
+                    Aes256GcmSiv::new(&GenericArray::clone_from_slice(&key[..])),
+                ));
+            }
+        }
+
+        Ok(cipher)
+    }
+
+    pub fn encrypt(&self, aad: &[u8], value: &[u8]) -> crate::Result<Vec<u8>> {
+        let (key_id, aes) = &self.keys[0];
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+
+        let mut bytes = Vec::with_capacity(value.len() + Self::OVERHEAD);
+        bytes.push(MARKER);
+        bytes.extend_from_slice(key_id);
+        bytes.extend_from_slice(&nonce);
+        bytes.extend_from_slice(
+            &aes.encrypt(Nonce::from_slice(&nonce), Payload { msg: value, aad })
+                .map_err(|err| {
+                    StoreError::InternalError(format!("Failed to encrypt value: {}", err))
+                })?,
+        );
+        Ok(bytes)
+    }
+
+    /// Decrypts a value, returning `None` for re-encryption placeholders.
+    /// Unencrypted values are only accepted while the store is being migrated.
+    pub fn decrypt<'x>(&self, aad: &[u8], value: &'x [u8]) -> crate::Result<Option<Cow<'x, [u8]>>> {
+        if value == TOUCH {
+            return Ok(None);
+        }
+        match self.find_key(value) {
+            Some(aes) => aes
+                .decrypt(
+                    Nonce::from_slice(&value[1 + KEY_ID_LEN..HEADER_LEN]),
+                    Payload {
+                        msg: &value[HEADER_LEN..],
+                        aad,
+                    },
+                )
+                .map(|bytes| Some(Cow::Owned(bytes)))
+                .map_err(|_| {
+                    StoreError::DataCorruption(
+                        "Failed to authenticate encrypted value.".to_string(),
+                    )
+                }),
+            None if self.is_migrating() => Ok(Some(Cow::Borrowed(value))),
+            None if value.first() == Some(&MARKER) => Err(StoreError::DataCorruption(
+                "Encrypted value uses an unknown key or is truncated.".to_string(),
+            )),
+            None => Err(StoreError::DataCorruption(
+                "Value is not encrypted.".to_string(),
+            )),
+        }
+    }
+
+    /// Returns `true` if the value is encrypted with the current key.
+    pub fn is_current(&self, value: &[u8]) -> bool {
+        value.len() >= HEADER_LEN + TAG_LEN
+            && value[0] == MARKER
+            && value[1..1 + KEY_ID_LEN] == self.keys[0].0
+    }
+
+    pub fn is_touch(value: &[u8]) -> bool {
+        value == TOUCH
+    }
+
+    pub fn has_previous_keys(&self) -> bool {
+        self.keys.len() > 1
+    }
+
+    pub fn is_migrating(&self) -> bool {
+        self.is_migrating.load(Ordering::Relaxed)
+    }
+
+    pub fn set_migrating(&self, is_migrating: bool) {
+        self.is_migrating.store(is_migrating, Ordering::Relaxed);
+    }
+
+    fn find_key(&self, value: &[u8]) -> Option<&Aes256GcmSiv> {
+        if value.len() >= HEADER_LEN + TAG_LEN && value[0] == MARKER {
+            self.keys
+                .iter()
+                .find(|(key_id, _)| value[1..1 + KEY_ID_LEN] == *key_id)
+                .map(|(_, aes)| aes)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StoreCipher, MARKER, TOUCH};
+
+    #[test]
+    fn encrypt_decrypt() {
+        let cipher = StoreCipher::from_keys(&["0123456789abcdef0123"]).unwrap();
+        let value = cipher.encrypt(b"key", b"hello world").unwrap();
+        assert_eq!(value.len(), b"hello world".len() + StoreCipher::OVERHEAD);
+        assert!(cipher.is_current(&value));
+        assert_eq!(
+            cipher.decrypt(b"key", &value).unwrap().unwrap().as_ref(),
+            b"hello world"
+        );
+
+        // Values are bound to their location
+        assert!(cipher.decrypt(b"other key", &value).is_err());
+
+        // Unencrypted values are only passed through while migrating,
+        // including those that happen to start with the marker
+        let marked = [MARKER; 40];
+        assert!(cipher.decrypt(b"key", b"plain").is_err());
+        assert!(cipher.decrypt(b"key", &marked).is_err());
+        cipher.set_migrating(true);
+        assert_eq!(
+            cipher.decrypt(b"key", b"plain").unwrap().unwrap().as_ref(),
+            b"plain"
+        );
+        assert_eq!(
+            cipher.decrypt(b"key", &marked).unwrap().unwrap().as_ref(),
+            &marked[..]
+        );
+        assert!(cipher.decrypt(b"other key", &value).is_err());
+        assert!(!cipher.is_current(b"plain"));
+
+        // Placeholders are never returned
+        assert!(cipher.decrypt(b"key", &TOUCH).unwrap().is_none());
+    }
+
+    #[test]
+    fn rotate_keys() {
+        let old = StoreCipher::from_keys(&["old key 0123456789"]).unwrap();
+        let new = StoreCipher::from_keys(&["new key 0123456789", "old key 0123456789"]).unwrap();
+        assert!(new.has_previous_keys());
+
+        let value = old.encrypt(b"key", b"rotate me").unwrap();
+        assert!(!new.is_current(&value));
+        assert_eq!(
+            new.decrypt(b"key", &value).unwrap().unwrap().as_ref(),
+            b"rotate me"
+        );
+
+        let value = new.encrypt(b"key", b"rotate me").unwrap();
+        assert!(new.is_current(&value));
+
+        // Values encrypted with a key that is no longer configured are not
+        // mistaken for plaintext
+        assert!(old.decrypt(b"key", &value).is_err());
+        assert!(StoreCipher::from_keys(&["short"]).is_err());
+    }
+}
@@ -34,7 +34,13 @@ pub enum Collection {
     Identity = 5,
     EmailSubmission = 6,
     VacationResponse = 7,
-    None = 8,
+    SavedSearch = 8,
+    AddressBook = 9,
+    ContactCard = 10,
+    Calendar = 11,
+    CalendarEvent = 12,
+    ShareNotification = 13,
+    None = 14,
 }
 
 impl Default for Collection {
@@ -54,6 +60,12 @@ impl From<u8> for Collection {
             5 => Collection::Identity,
             6 => Collection::EmailSubmission,
             7 => Collection::VacationResponse,
+            8 => Collection::SavedSearch,
+            9 => Collection::AddressBook,
+            10 => Collection::ContactCard,
+            11 => Collection::Calendar,
+            12 => Collection::CalendarEvent,
+            13 => Collection::ShareNotification,
             _ => {
                 debug_assert!(false, "Invalid collection value: {}", value);
                 Collection::None
@@ -85,6 +97,12 @@ impl From<u64> for Collection {
             5 => Collection::Identity,
             6 => Collection::EmailSubmission,
             7 => Collection::VacationResponse,
+            8 => Collection::SavedSearch,
+            9 => Collection::AddressBook,
+            10 => Collection::ContactCard,
+            11 => Collection::Calendar,
+            12 => Collection::CalendarEvent,
+            13 => Collection::ShareNotification,
             _ => {
                 debug_assert!(false, "Invalid collection value: {}", value);
                 Collection::None
@@ -27,6 +27,8 @@ use crate::{DocumentId, JMAPId};
 
 pub mod acl;
 pub mod bitmap;
+pub mod cache;
+pub mod cipher;
 pub mod collection;
 pub mod document;
 pub mod error;
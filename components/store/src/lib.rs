@@ -21,6 +21,7 @@
  * for more details.
 */
 
+pub mod backup;
 pub mod blob;
 pub mod config;
 pub mod core;
@@ -31,6 +32,7 @@ pub mod serialize;
 pub mod write;
 
 use crate::core::acl::ACL;
+use crate::core::cache::CacheMetrics;
 use crate::core::{acl::ACLToken, collection::Collection, error::StoreError};
 use crate::nlp::Language;
 use blob::local::LocalBlobStore;
@@ -41,6 +43,7 @@ use moka::sync::Cache;
 use parking_lot::{Mutex, MutexGuard};
 use roaring::RoaringBitmap;
 use serialize::StoreDeserialize;
+use std::path::Path;
 use std::sync::atomic::AtomicBool;
 use std::{
     sync::{atomic::AtomicU64, Arc},
@@ -85,6 +88,18 @@ pub enum ColumnFamily {
     Logs,
 }
 
+impl ColumnFamily {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ColumnFamily::Bitmaps => "bitmaps",
+            ColumnFamily::Values => "values",
+            ColumnFamily::Indexes => "indexes",
+            ColumnFamily::Blobs => "blobs",
+            ColumnFamily::Logs => "logs",
+        }
+    }
+}
+
 pub enum Direction {
     Forward,
     Backward,
@@ -94,7 +109,7 @@ pub trait Store<'x>
 where
     Self: Sized + Send + Sync,
 {
-    type Iterator: Iterator<Item = (Box<[u8]>, Box<[u8]>)> + 'x;
+    type Iterator: Iterator<Item = Result<(Box<[u8]>, Box<[u8]>)>> + 'x;
 
     fn open(settings: &EnvSettings) -> Result<Self>;
     fn delete(&self, cf: ColumnFamily, key: &[u8]) -> Result<()>;
@@ -117,6 +132,11 @@ where
         direction: Direction,
     ) -> Result<Self::Iterator>;
     fn compact(&self, cf: ColumnFamily) -> Result<()>;
+    fn stats(&self) -> Result<Vec<(ColumnFamily, &'static str, u64)>>;
+    fn reencrypt(&self) -> Result<u64>;
+    fn has_unencrypted_values(&self) -> bool;
+    fn backup(&self, path: &Path) -> Result<()>;
+    fn restore(settings: &EnvSettings, path: &Path) -> Result<()>;
     fn close(&self) -> Result<()>;
 }
 
@@ -141,11 +161,13 @@ pub struct JMAPStore<T> {
     pub config: JMAPConfig,
 
     pub account_lock: MutexMap<()>,
+    pub maintenance_lock: Mutex<()>,
 
     pub id_assigner: Cache<IdCacheKey, Arc<Mutex<IdAssigner>>>,
     pub shared_documents: Cache<SharedResource, Arc<Option<RoaringBitmap>>>,
     pub acl_tokens: Cache<AccountId, Arc<ACLToken>>,
     pub recipients: Cache<String, Arc<RecipientType>>,
+    pub cache_stats: CacheMetrics,
 
     pub raft_term: AtomicU64,
     pub raft_index: AtomicU64,
@@ -185,7 +207,9 @@ where
                     settings.parse("cache-tti-recipients").unwrap_or(86400),
                 ))
                 .build(),
+            cache_stats: CacheMetrics::default(),
             account_lock: MutexMap::with_capacity(1024),
+            maintenance_lock: Mutex::new(()),
             raft_index: 0.into(),
             raft_term: 0.into(),
             tombstone_deletions: false.into(),
@@ -228,6 +252,24 @@ where
         self.account_lock
             .try_lock_hash((account, collection), timeout)
     }
+
+    /// Re-encrypts database values and blob files that are not encrypted with
+    /// the current store encryption key, returning the number of values and
+    /// blob files rewritten.
+    pub fn reencrypt(&self) -> Result<(u64, u64)> {
+        // Blob purges and backups are not allowed while rewriting blob files.
+        let _maintenance_lock = self.maintenance_lock.lock();
+        Ok((self.db.reencrypt()?, self.blob_store.reencrypt()?))
+    }
+
+    /// Returns `true` if values or blob files are pending re-encryption, either
+    /// because encryption was enabled on an existing store or the key changed.
+    pub fn needs_reencryption(&self) -> bool {
+        self.db.has_unencrypted_values()
+            || self.blob_store.cipher.as_ref().map_or(false, |cipher| {
+                cipher.has_previous_keys() || cipher.is_migrating()
+            })
+    }
 }
 
 impl SharedResource {
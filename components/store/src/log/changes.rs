@@ -167,6 +167,7 @@ where
             .iterator(ColumnFamily::Logs, &match_key, Direction::Backward)?
             .into_iter()
             .next()
+            .transpose()?
         {
             if key.starts_with(&match_key[0..LogKey::CHANGE_ID_POS]) {
                 return Ok(Some(LogKey::deserialize_change_id(&key).ok_or_else(
@@ -201,10 +202,11 @@ where
         let prefix = &key[0..LogKey::CHANGE_ID_POS];
         let mut is_first = true;
 
-        for (key, value) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Logs, &key, Direction::Forward)?
         {
+            let (key, value) = item?;
             if !key.starts_with(prefix) {
                 break;
             }
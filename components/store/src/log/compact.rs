@@ -75,11 +75,12 @@ where
         let mut write_batch = Vec::new();
         let mut has_changes = false;
 
-        for (key, value) in self.db.iterator(
+        for item in self.db.iterator(
             ColumnFamily::Logs,
             &[LogKey::CHANGE_KEY_PREFIX],
             Direction::Forward,
         )? {
+            let (key, value) = item?;
             if !key.starts_with(&[LogKey::CHANGE_KEY_PREFIX]) {
                 break;
             }
@@ -153,11 +154,12 @@ where
         let mut last_term = TermId::MAX;
         let mut changed_accounts = AHashMap::default();
 
-        for (key, value) in self.db.iterator(
+        for item in self.db.iterator(
             ColumnFamily::Logs,
             &[LogKey::RAFT_KEY_PREFIX],
             Direction::Forward,
         )? {
+            let (key, value) = item?;
             if !key.starts_with(&[LogKey::RAFT_KEY_PREFIX]) {
                 break;
             }
@@ -243,10 +245,11 @@ where
 
     /*pub fn compact_bitmaps(&self) -> crate::Result<()> {
         // Not currently used.
-        for (key, value) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Bitmaps, &[], Direction::Forward)?
         {
+            let (key, value) = item?;
             match RoaringBitmap::deserialize(&value) {
                 Some(bm) if bm.is_empty() => {
                     self.db.delete(ColumnFamily::Bitmaps, &key)?;
@@ -92,6 +92,7 @@ where
             .db
             .iterator(ColumnFamily::Logs, &key, Direction::Backward)?
             .next()
+            .transpose()?
         {
             if key.starts_with(&[LogKey::RAFT_KEY_PREFIX]) {
                 return Ok(Some(LogKey::deserialize_raft(&key).ok_or_else(|| {
@@ -109,6 +110,7 @@ where
             .db
             .iterator(ColumnFamily::Logs, &key, Direction::Forward)?
             .next()
+            .transpose()?
         {
             if key.starts_with(&[LogKey::RAFT_KEY_PREFIX]) {
                 return Ok(Some(LogKey::deserialize_raft(&key).ok_or_else(|| {
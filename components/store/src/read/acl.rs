@@ -44,10 +44,11 @@ where
         for account_id in member_of {
             let prefix =
                 ValueKey::serialize_acl_prefix(*account_id, AccountId::MAX, Collection::None);
-            for (key, value) in
-                self.db
-                    .iterator(ColumnFamily::Values, &prefix, Direction::Forward)?
+            for item in self
+                .db
+                .iterator(ColumnFamily::Values, &prefix, Direction::Forward)?
             {
+                let (key, value) = item?;
                 if key.starts_with(&prefix)
                     && key.len() > prefix.len() + 2
                     && key[prefix.len()] != u8::MAX
@@ -67,8 +68,22 @@ where
                         if acl.contains(ACL::Read) {
                             collections.insert(to_collection);
                         }
-                        if (acl.contains(ACL::ReadItems)) && to_collection == Collection::Mailbox {
-                            collections.insert(Collection::Mail);
+                        if acl.contains(ACL::ReadItems) {
+                            match to_collection {
+                                Collection::Mailbox => collections.insert(Collection::Mail),
+                                Collection::AddressBook => {
+                                    collections.insert(Collection::ContactCard)
+                                }
+                                Collection::Calendar => {
+                                    collections.insert(Collection::CalendarEvent)
+                                }
+                                _ => (),
+                            }
+                        }
+                        if to_collection == Collection::Identity
+                            && (acl.contains(ACL::Submit) || acl.contains(ACL::SendOnBehalf))
+                        {
+                            collections.insert(Collection::EmailSubmission);
                         }
 
                         if !collections.is_empty() {
@@ -100,10 +115,11 @@ where
         let mut shared_documents = RoaringBitmap::new();
         for account_id in member_of {
             let prefix = ValueKey::serialize_acl_prefix(*account_id, to_account_id, to_collection);
-            for (key, value) in
-                self.db
-                    .iterator(ColumnFamily::Values, &prefix, Direction::Forward)?
+            for item in self
+                .db
+                .iterator(ColumnFamily::Values, &prefix, Direction::Forward)?
             {
+                let (key, value) = item?;
                 if key.starts_with(&prefix) && key.len() > prefix.len() {
                     let (document_id, _) =
                         (&key[prefix.len()..]).read_leb128().ok_or_else(|| {
@@ -94,7 +94,7 @@ where
         let mut bm = RoaringBitmap::new();
         let match_prefix = &match_key[0..FIELD_PREFIX_LEN];
         let match_value = &match_key[FIELD_PREFIX_LEN..];
-        for (key, _) in self.db.iterator(
+        for item in self.db.iterator(
             ColumnFamily::Indexes,
             match_key,
             match op {
@@ -104,6 +104,7 @@ where
                 _ => Direction::Backward,
             },
         )? {
+            let (key, _) = item?;
             if !key.starts_with(match_prefix) {
                 break;
             }
@@ -24,6 +24,7 @@
 use std::ops::{BitAndAssign, BitXorAssign};
 
 use roaring::RoaringBitmap;
+use tracing::error;
 
 use crate::{
     core::collection::Collection, serialize::key::IndexKey, AccountId, ColumnFamily, Direction,
@@ -272,7 +273,15 @@ where
 
                         let mut is_eof = false;
                         loop {
-                            if let Some((key, _)) = it.next() {
+                            let item = match it.next() {
+                                Some(Ok(item)) => Some(item),
+                                Some(Err(err)) => {
+                                    error!("Failed to read index: {}", err);
+                                    return None;
+                                }
+                                None => None,
+                            };
+                            if let Some((key, _)) = item {
                                 if !key.starts_with(&index.prefix) {
                                     index.prev_key = None;
                                     is_eof = true;
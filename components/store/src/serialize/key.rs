@@ -62,6 +62,7 @@ pub const INTERNAL_KEY_PREFIX: u8 = 0;
 
 pub const FOLLOWER_COMMIT_INDEX_KEY: &[u8; 2] = &[INTERNAL_KEY_PREFIX, 1];
 pub const LEADER_COMMIT_INDEX_KEY: &[u8; 2] = &[INTERNAL_KEY_PREFIX, 2];
+pub const ENCRYPTION_STATE_KEY: &[u8; 2] = &[INTERNAL_KEY_PREFIX, 3];
 
 pub struct ValueKey {}
 pub struct BitmapKey {}
@@ -41,10 +41,11 @@ where
         let mut batch = Vec::with_capacity(64);
 
         // Delete values
-        for (key, _) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Values, &[], Direction::Forward)?
         {
+            let (key, _) = item?;
             let mut bytes = key.iter();
             if let Some(account_id) = bytes.next_leb128() {
                 let do_delete = if account_ids.contains(account_id) {
@@ -70,10 +71,11 @@ where
         }
 
         // Delete indexes
-        for (key, _) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Indexes, &[], Direction::Forward)?
         {
+            let (key, _) = item?;
             if let Some(account_id) = (&key[..]).deserialize_be_u32(0) {
                 if account_ids.contains(account_id) {
                     batch.push(WriteOperation::Delete {
@@ -89,10 +91,11 @@ where
         }
 
         // Delete linked blobs
-        for (key, _) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Blobs, &[], Direction::Forward)?
         {
+            let (key, _) = item?;
             if let Some((account_id, _)) =
                 key.get(BLOB_HASH_LEN + 1..).and_then(|b| b.read_leb128())
             {
@@ -110,10 +113,11 @@ where
         }
 
         // Delete bitmaps
-        for (key, _) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Bitmaps, &[], Direction::Forward)?
         {
+            let (key, _) = item?;
             if matches!(BitmapKey::deserialize_account_id(&key), Some(account_id) if account_ids.contains(account_id))
             {
                 batch.push(WriteOperation::Delete {
@@ -96,8 +96,10 @@ where
         account_id: AccountId,
         collection: Collection,
     ) -> crate::Result<Arc<Mutex<IdAssigner>>> {
+        self.cache_stats.id_assigner.request();
         self.id_assigner
             .try_get_with::<_, StoreError>(IdCacheKey::new(account_id, collection), || {
+                self.cache_stats.id_assigner.miss();
                 Ok(Arc::new(Mutex::new(IdAssigner::new(
                     self.get_document_ids(account_id, collection)?,
                 ))))
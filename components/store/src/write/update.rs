@@ -55,6 +55,7 @@ use super::{
 pub struct Changes {
     pub collections: Bitmap<Collection>,
     pub change_id: ChangeId,
+    pub linked: Vec<(AccountId, Changes)>,
 }
 
 impl<T> JMAPStore<T>
@@ -68,12 +69,19 @@ where
             .load(std::sync::atomic::Ordering::Relaxed);
 
         // Prepare linked batch
+        let mut linked = Vec::new();
         for sub_batch in batch.linked_batch.drain(..) {
-            self.prepare_batch(&mut ops, sub_batch, tombstone_deletions)?;
+            let account_id = sub_batch.account_id;
+            if let Some(changes) = self.prepare_batch(&mut ops, sub_batch, tombstone_deletions)? {
+                linked.push((account_id, changes));
+            }
         }
 
         // Prepare main batch
-        let changes = self.prepare_batch(&mut ops, batch, tombstone_deletions)?;
+        let mut changes = self.prepare_batch(&mut ops, batch, tombstone_deletions)?;
+        if let Some(changes) = &mut changes {
+            changes.linked = linked;
+        }
 
         // Submit write batch
         self.db.write(ops)?;
@@ -528,6 +536,7 @@ where
             Ok(Changes {
                 collections,
                 change_id: raft_id.index,
+                linked: Vec::new(),
             }
             .into())
         } else {
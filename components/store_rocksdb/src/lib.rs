@@ -21,34 +21,70 @@
  * for more details.
 */
 
-use std::{convert::TryInto, path::PathBuf, sync::Arc};
+use std::{
+    borrow::Cow,
+    convert::TryInto,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 use rocksdb::{
-    BoundColumnFamily, ColumnFamilyDescriptor, DBIteratorWithThreadMode, DBWithThreadMode,
-    MergeOperands, MultiThreaded, Options,
+    checkpoint::Checkpoint, BoundColumnFamily, ColumnFamilyDescriptor, DBIteratorWithThreadMode,
+    DBWithThreadMode, MergeOperands, MultiThreaded, Options,
 };
 use store::{
-    config::env_settings::EnvSettings, core::error::StoreError, roaring::RoaringBitmap,
-    serialize::StoreDeserialize, write::operation::WriteOperation, Result, Store,
+    backup::move_aside,
+    config::env_settings::EnvSettings,
+    core::{
+        cipher::{StoreCipher, STATE_ENCRYPTED, STATE_MIGRATING, TOUCH},
+        error::StoreError,
+    },
+    roaring::RoaringBitmap,
+    serialize::{key::ENCRYPTION_STATE_KEY, StoreDeserialize},
+    tracing::error,
+    write::operation::WriteOperation,
+    Result, Store,
 };
 
+const REENCRYPT_BATCH_SIZE: usize = 1000;
+
 pub struct RocksDB {
     db: DBWithThreadMode<MultiThreaded>,
+    cipher: Option<Arc<StoreCipher>>,
 }
 
 pub struct RocksDBIterator<'x> {
     it: DBIteratorWithThreadMode<'x, DBWithThreadMode<MultiThreaded>>,
+    cipher: Option<&'x StoreCipher>,
 }
 
 impl Iterator for RocksDBIterator<'_> {
-    type Item = (Box<[u8]>, Box<[u8]>);
+    type Item = Result<(Box<[u8]>, Box<[u8]>)>;
 
     #[allow(clippy::while_let_on_iterator)]
     #[inline(always)]
     fn next(&mut self) -> Option<Self::Item> {
         while let Some(result) = self.it.next() {
-            if let Ok(item) = result {
-                return Some(item);
+            let (key, value) = match result {
+                Ok(item) => item,
+                Err(err) => {
+                    return Some(Err(StoreError::InternalError(format!(
+                        "iterator failed: {}",
+                        err
+                    ))))
+                }
+            };
+            if let Some(cipher) = self.cipher {
+                // Placeholders left by re-encryption are skipped
+                match cipher.decrypt(&key, &value) {
+                    Ok(Some(value)) => {
+                        return Some(Ok((key, value.into_owned().into_boxed_slice())));
+                    }
+                    Ok(None) => (),
+                    Err(err) => return Some(Err(err)),
+                }
+            } else {
+                return Some(Ok((key, value)));
             }
         }
         None
@@ -68,7 +104,7 @@ impl<'x> Store<'x> for RocksDB {
     #[inline(always)]
     fn set(&self, cf: store::ColumnFamily, key: &[u8], value: &[u8]) -> Result<()> {
         self.db
-            .put_cf(&self.cf_handle(cf)?, key, value)
+            .put_cf(&self.cf_handle(cf)?, key, self.encrypt(key, value)?)
             .map_err(|err| StoreError::InternalError(format!("put_cf failed: {}", err)))
     }
 
@@ -82,9 +118,13 @@ impl<'x> Store<'x> for RocksDB {
             .get_pinned_cf(&self.cf_handle(cf)?, &key)
             .map_err(|err| StoreError::InternalError(format!("get_cf failed: {}", err)))?
         {
-            Ok(Some(U::deserialize(&bytes).ok_or_else(|| {
-                StoreError::DeserializeError(format!("Failed to deserialize key: {:?}", key))
-            })?))
+            if let Some(bytes) = self.decrypt(key, &bytes)? {
+                Ok(Some(U::deserialize(&bytes).ok_or_else(|| {
+                    StoreError::DeserializeError(format!("Failed to deserialize key: {:?}", key))
+                })?))
+            } else {
+                Ok(None)
+            }
         } else {
             Ok(None)
         }
@@ -93,7 +133,7 @@ impl<'x> Store<'x> for RocksDB {
     #[inline(always)]
     fn merge(&self, cf: store::ColumnFamily, key: &[u8], value: &[u8]) -> Result<()> {
         self.db
-            .merge_cf(&self.cf_handle(cf)?, key, value)
+            .merge_cf(&self.cf_handle(cf)?, key, self.encrypt(key, value)?)
             .map_err(|err| StoreError::InternalError(format!("merge_cf failed: {}", err)))
     }
 
@@ -109,6 +149,7 @@ impl<'x> Store<'x> for RocksDB {
         for op in batch {
             match op {
                 WriteOperation::Set { cf, key, value } => {
+                    let value = self.encrypt(&key, &value)?;
                     rocks_batch.put_cf(
                         match cf {
                             store::ColumnFamily::Bitmaps => &cf_bitmaps,
@@ -134,6 +175,7 @@ impl<'x> Store<'x> for RocksDB {
                     );
                 }
                 WriteOperation::Merge { cf, key, value } => {
+                    let value = self.encrypt(&key, &value)?;
                     rocks_batch.merge_cf(
                         match cf {
                             store::ColumnFamily::Bitmaps => &cf_bitmaps,
@@ -159,7 +201,7 @@ impl<'x> Store<'x> for RocksDB {
             .db
             .get_pinned_cf(&self.cf_handle(cf)?, &key)
             .map_err(|err| StoreError::InternalError(format!("get_cf failed: {}", err)))?
-            .is_some())
+            .map_or(false, |bytes| !StoreCipher::is_touch(&bytes)))
     }
 
     #[inline(always)]
@@ -170,19 +212,25 @@ impl<'x> Store<'x> for RocksDB {
     {
         let cf_handle = self.cf_handle(cf)?;
         let mut results = Vec::with_capacity(keys.len());
-        for value in self
-            .db
-            .multi_get_cf(keys.iter().map(|key| (&cf_handle, key)).collect::<Vec<_>>())
-        {
+        for (key, value) in keys.iter().zip(
+            self.db
+                .multi_get_cf(keys.iter().map(|key| (&cf_handle, key)).collect::<Vec<_>>()),
+        ) {
             results.push(
                 if let Some(bytes) = value.map_err(|err| {
                     StoreError::InternalError(format!("multi_get_cf failed: {}", err))
                 })? {
-                    T::deserialize(&bytes)
-                        .ok_or_else(|| {
-                            StoreError::DeserializeError("Failed to deserialize keys.".to_string())
-                        })?
-                        .into()
+                    if let Some(bytes) = self.decrypt(key.as_ref(), &bytes)? {
+                        T::deserialize(&bytes)
+                            .ok_or_else(|| {
+                                StoreError::DeserializeError(
+                                    "Failed to deserialize keys.".to_string(),
+                                )
+                            })?
+                            .into()
+                    } else {
+                        None
+                    }
                 } else {
                     None
                 },
@@ -210,6 +258,7 @@ impl<'x> Store<'x> for RocksDB {
                     },
                 ),
             ),
+            cipher: self.cipher.as_deref(),
         })
     }
 
@@ -219,15 +268,134 @@ impl<'x> Store<'x> for RocksDB {
         Ok(())
     }
 
+    fn stats(&self) -> Result<Vec<(store::ColumnFamily, &'static str, u64)>> {
+        let mut stats = Vec::new();
+        for cf in [
+            store::ColumnFamily::Bitmaps,
+            store::ColumnFamily::Values,
+            store::ColumnFamily::Indexes,
+            store::ColumnFamily::Blobs,
+            store::ColumnFamily::Logs,
+        ] {
+            let cf_handle = self.cf_handle(cf)?;
+            for (name, property) in [
+                ("estimated_keys", "rocksdb.estimate-num-keys"),
+                ("sst_files_bytes", "rocksdb.total-sst-files-size"),
+                ("blob_files_bytes", "rocksdb.total-blob-file-size"),
+                ("memtable_bytes", "rocksdb.cur-size-all-mem-tables"),
+                (
+                    "pending_compaction_bytes",
+                    "rocksdb.estimate-pending-compaction-bytes",
+                ),
+                ("running_compactions", "rocksdb.num-running-compactions"),
+            ] {
+                if let Some(value) = self
+                    .db
+                    .property_int_value_cf(&cf_handle, property)
+                    .map_err(|err| {
+                        StoreError::InternalError(format!("property_int_value_cf failed: {}", err))
+                    })?
+                {
+                    stats.push((cf, name, value));
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    fn reencrypt(&self) -> Result<u64> {
+        let cipher = if let Some(cipher) = &self.cipher {
+            cipher
+        } else {
+            return Ok(0);
+        };
+
+        // Values are re-encrypted by the merge operators, which avoids
+        // overwriting any changes made while the column families are scanned.
+        // A full compaction then removes the values encrypted with old keys.
+        let mut total = 0;
+        for cf in [
+            store::ColumnFamily::Bitmaps,
+            store::ColumnFamily::Values,
+            store::ColumnFamily::Indexes,
+            store::ColumnFamily::Blobs,
+            store::ColumnFamily::Logs,
+        ] {
+            let cf_handle = self.cf_handle(cf)?;
+            let mut batch = rocksdb::WriteBatch::default();
+            let mut cf_total = 0;
+            for result in self
+                .db
+                .iterator_cf(&cf_handle, rocksdb::IteratorMode::Start)
+            {
+                let (key, value) = result.map_err(|err| {
+                    StoreError::InternalError(format!("iterator failed: {}", err))
+                })?;
+                if !cipher.is_current(&value) && !StoreCipher::is_touch(&value) {
+                    batch.merge_cf(&cf_handle, key, TOUCH);
+                    cf_total += 1;
+                    if batch.len() == REENCRYPT_BATCH_SIZE {
+                        self.db.write(std::mem::take(&mut batch)).map_err(|err| {
+                            StoreError::InternalError(format!("batch write failed: {}", err))
+                        })?;
+                    }
+                }
+            }
+            if !batch.is_empty() {
+                self.db.write(batch).map_err(|err| {
+                    StoreError::InternalError(format!("batch write failed: {}", err))
+                })?;
+            }
+            if cf_total > 0 {
+                self.db
+                    .compact_range_cf(&cf_handle, None::<&[u8]>, None::<&[u8]>);
+                total += cf_total;
+            }
+        }
+
+        // All values written before encryption was enabled have been merged
+        // and compacted, from now on unencrypted values are rejected.
+        if cipher.is_migrating() {
+            self.set(
+                store::ColumnFamily::Values,
+                ENCRYPTION_STATE_KEY,
+                &[STATE_ENCRYPTED],
+            )?;
+            cipher.set_migrating(false);
+        }
+
+        Ok(total)
+    }
+
+    fn has_unencrypted_values(&self) -> bool {
+        self.cipher
+            .as_ref()
+            .map_or(false, |cipher| cipher.is_migrating())
+    }
+
+    fn backup(&self, path: &Path) -> Result<()> {
+        Checkpoint::new(&self.db)
+            .and_then(|checkpoint| checkpoint.create_checkpoint(path))
+            .map_err(|err| StoreError::InternalError(format!("checkpoint failed: {}", err)))
+    }
+
+    fn restore(settings: &EnvSettings, path: &Path) -> Result<()> {
+        let idx_path = RocksDB::get_path(settings);
+        move_aside(&idx_path)?;
+        std::fs::create_dir_all(&idx_path)?;
+
+        // Checkpoints are flat directories
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            std::fs::copy(entry.path(), idx_path.join(entry.file_name()))?;
+        }
+
+        Ok(())
+    }
+
     fn open(settings: &EnvSettings) -> Result<Self> {
         // Create the database directory if it doesn't exist
-        let path = PathBuf::from(
-            &settings
-                .get("db-path")
-                .unwrap_or_else(|| "/usr/local/stalwart-jmap/data".to_string()),
-        );
-        let mut idx_path = path;
-        idx_path.push("idx");
+        let idx_path = RocksDB::get_path(settings);
         std::fs::create_dir_all(&idx_path).map_err(|err| {
             StoreError::InternalError(format!(
                 "Failed to create index directory {}: {:?}",
@@ -236,25 +404,69 @@ impl<'x> Store<'x> for RocksDB {
             ))
         })?;
 
+        // Encryption at rest
+        let cipher = StoreCipher::new(settings)?.map(Arc::new);
+
         // Bitmaps
         let cf_bitmaps = {
             let mut cf_opts = Options::default();
             //cf_opts.set_max_write_buffer_number(16);
-            cf_opts.set_merge_operator("merge", bitmap_merge, bitmap_partial_merge);
-            cf_opts.set_compaction_filter("compact", bitmap_compact);
+            if let Some(cipher) = &cipher {
+                let merge_cipher = cipher.clone();
+                let compact_cipher = cipher.clone();
+                cf_opts.set_merge_operator(
+                    "merge",
+                    move |key: &[u8], existing_val: Option<&[u8]>, operands: &MergeOperands| {
+                        encrypted_merge(&merge_cipher, key, existing_val, operands, |val, ops| {
+                            store::serialize::bitmap::bitmap_merge(
+                                val,
+                                ops.len(),
+                                ops.iter().map(|op| op.as_ref()),
+                            )
+                        })
+                    },
+                    bitmap_partial_merge,
+                );
+                cf_opts.set_compaction_filter("compact", move |level, key: &[u8], value: &[u8]| {
+                    match compact_cipher.decrypt(key, value) {
+                        Ok(Some(value)) => bitmap_compact(level, key, &value),
+                        Ok(None) => rocksdb::compaction_filter::Decision::Remove,
+                        Err(_) => rocksdb::compaction_filter::Decision::Keep,
+                    }
+                });
+            } else {
+                cf_opts.set_merge_operator("merge", bitmap_merge, bitmap_partial_merge);
+                cf_opts.set_compaction_filter("compact", bitmap_compact);
+            }
             ColumnFamilyDescriptor::new("bitmaps", cf_opts)
         };
 
         // Stored values
         let cf_values = {
             let mut cf_opts = Options::default();
-            cf_opts.set_merge_operator_associative("merge", numeric_value_merge);
+            if let Some(cipher) = &cipher {
+                let merge_cipher = cipher.clone();
+                cf_opts.set_merge_operator_associative(
+                    "merge",
+                    move |key: &[u8], existing_val: Option<&[u8]>, operands: &MergeOperands| {
+                        encrypted_merge(&merge_cipher, key, existing_val, operands, |val, ops| {
+                            numeric_merge(val, ops.iter().map(|op| op.as_ref()))
+                        })
+                    },
+                );
+                cf_opts.set_compaction_filter("compact", touch_compact);
+            } else {
+                cf_opts.set_merge_operator_associative("merge", numeric_value_merge);
+            }
             ColumnFamilyDescriptor::new("values", cf_opts)
         };
 
         // Secondary indexes
         let cf_indexes = {
-            let cf_opts = Options::default();
+            let mut cf_opts = Options::default();
+            if let Some(cipher) = &cipher {
+                set_touch_operator(&mut cf_opts, cipher);
+            }
             ColumnFamilyDescriptor::new("indexes", cf_opts)
         };
 
@@ -263,12 +475,18 @@ impl<'x> Store<'x> for RocksDB {
             let mut cf_opts = Options::default();
             cf_opts.set_enable_blob_files(true);
             cf_opts.set_min_blob_size(settings.parse("blob-min-size").unwrap_or(16384));
+            if let Some(cipher) = &cipher {
+                set_touch_operator(&mut cf_opts, cipher);
+            }
             ColumnFamilyDescriptor::new("blobs", cf_opts)
         };
 
         // Raft log and change log
         let cf_log = {
-            let cf_opts = Options::default();
+            let mut cf_opts = Options::default();
+            if let Some(cipher) = &cipher {
+                set_touch_operator(&mut cf_opts, cipher);
+            }
             ColumnFamilyDescriptor::new("logs", cf_opts)
         };
 
@@ -276,14 +494,17 @@ impl<'x> Store<'x> for RocksDB {
         db_opts.create_missing_column_families(true);
         db_opts.create_if_missing(true);
 
-        Ok(RocksDB {
+        let db = RocksDB {
             db: DBWithThreadMode::open_cf_descriptors(
                 &db_opts,
                 idx_path,
                 vec![cf_bitmaps, cf_values, cf_indexes, cf_blobs, cf_log],
             )
             .map_err(|e| StoreError::InternalError(e.into_string()))?,
-        })
+            cipher,
+        };
+        db.init_encryption()?;
+        Ok(db)
     }
 
     fn close(&self) -> Result<()> {
@@ -296,22 +517,93 @@ impl<'x> Store<'x> for RocksDB {
 }
 
 impl RocksDB {
+    fn get_path(settings: &EnvSettings) -> PathBuf {
+        let mut path = PathBuf::from(
+            &settings
+                .get("db-path")
+                .unwrap_or_else(|| "/usr/local/stalwart-jmap/data".to_string()),
+        );
+        path.push("idx");
+        path
+    }
+
+    /// Records the encryption state of the database. Databases that already
+    /// contain values when encryption is enabled accept unencrypted values
+    /// until they have been re-encrypted.
+    fn init_encryption(&self) -> Result<()> {
+        let state = self
+            .db
+            .get_pinned_cf(
+                &self.cf_handle(store::ColumnFamily::Values)?,
+                ENCRYPTION_STATE_KEY,
+            )
+            .map_err(|err| StoreError::InternalError(format!("get_cf failed: {}", err)))?;
+
+        match (&self.cipher, state) {
+            (Some(cipher), Some(state)) => {
+                if cipher
+                    .decrypt(ENCRYPTION_STATE_KEY, &state)?
+                    .map_or(false, |state| state.as_ref() == [STATE_MIGRATING])
+                {
+                    cipher.set_migrating(true);
+                }
+                Ok(())
+            }
+            (Some(cipher), None) => {
+                let mut state = STATE_ENCRYPTED;
+                for cf in [
+                    store::ColumnFamily::Bitmaps,
+                    store::ColumnFamily::Values,
+                    store::ColumnFamily::Indexes,
+                    store::ColumnFamily::Blobs,
+                    store::ColumnFamily::Logs,
+                ] {
+                    if self
+                        .db
+                        .iterator_cf(&self.cf_handle(cf)?, rocksdb::IteratorMode::Start)
+                        .next()
+                        .is_some()
+                    {
+                        cipher.set_migrating(true);
+                        state = STATE_MIGRATING;
+                        break;
+                    }
+                }
+                self.set(store::ColumnFamily::Values, ENCRYPTION_STATE_KEY, &[state])
+            }
+            (None, Some(_)) => Err(StoreError::InvalidArguments(
+                "The database is encrypted but no store encryption key is configured.".to_string(),
+            )),
+            (None, None) => Ok(()),
+        }
+    }
+
+    #[inline(always)]
+    fn encrypt<'y>(&self, key: &[u8], value: &'y [u8]) -> Result<Cow<'y, [u8]>> {
+        if let Some(cipher) = &self.cipher {
+            cipher.encrypt(key, value).map(Cow::Owned)
+        } else {
+            Ok(Cow::Borrowed(value))
+        }
+    }
+
+    #[inline(always)]
+    fn decrypt<'y>(&self, key: &[u8], value: &'y [u8]) -> Result<Option<Cow<'y, [u8]>>> {
+        if let Some(cipher) = &self.cipher {
+            cipher.decrypt(key, value)
+        } else {
+            Ok(Some(Cow::Borrowed(value)))
+        }
+    }
+
     #[inline(always)]
     fn cf_handle(&self, cf: store::ColumnFamily) -> Result<Arc<BoundColumnFamily>> {
-        self.db
-            .cf_handle(match cf {
-                store::ColumnFamily::Bitmaps => "bitmaps",
-                store::ColumnFamily::Values => "values",
-                store::ColumnFamily::Indexes => "indexes",
-                store::ColumnFamily::Blobs => "blobs",
-                store::ColumnFamily::Logs => "logs",
-            })
-            .ok_or_else(|| {
-                StoreError::InternalError(format!(
-                    "Failed to get handle for '{:?}' column family.",
-                    cf
-                ))
-            })
+        self.db.cf_handle(cf.as_str()).ok_or_else(|| {
+            StoreError::InternalError(format!(
+                "Failed to get handle for '{:?}' column family.",
+                cf
+            ))
+        })
     }
 }
 
@@ -319,6 +611,13 @@ pub fn numeric_value_merge(
     _key: &[u8],
     value: Option<&[u8]>,
     operands: &MergeOperands,
+) -> Option<Vec<u8>> {
+    numeric_merge(value, operands.iter())
+}
+
+fn numeric_merge<'x>(
+    value: Option<&[u8]>,
+    operands: impl IntoIterator<Item = &'x [u8]>,
 ) -> Option<Vec<u8>> {
     let mut value = if let Some(value) = value {
         i64::from_le_bytes(value.try_into().ok()?)
@@ -326,7 +625,7 @@ pub fn numeric_value_merge(
         0
     };
 
-    for op in operands.iter() {
+    for op in operands {
         value += i64::from_le_bytes(op.try_into().ok()?);
     }
 
@@ -362,3 +661,72 @@ pub fn bitmap_compact(
         _ => rocksdb::compaction_filter::Decision::Keep,
     }
 }
+
+/// Decrypts the existing value and operands, merges them and encrypts the
+/// result with the current key. Merges consisting only of re-encryption
+/// requests leave the existing value untouched.
+fn encrypted_merge(
+    cipher: &StoreCipher,
+    key: &[u8],
+    existing_val: Option<&[u8]>,
+    operands: &MergeOperands,
+    merge_fn: impl FnOnce(Option<&[u8]>, &[Cow<[u8]>]) -> Option<Vec<u8>>,
+) -> Option<Vec<u8>> {
+    let existing_val = match existing_val {
+        Some(existing_val) => decrypt_operand(cipher, key, existing_val)?,
+        None => None,
+    };
+    let mut values = Vec::with_capacity(operands.len());
+    for op in operands.iter() {
+        if let Some(op) = decrypt_operand(cipher, key, op)? {
+            values.push(op);
+        }
+    }
+
+    let value = if !values.is_empty() {
+        merge_fn(existing_val.as_deref(), &values)?
+    } else if let Some(existing_val) = existing_val {
+        existing_val.into_owned()
+    } else {
+        return Some(TOUCH.to_vec());
+    };
+
+    cipher.encrypt(key, &value).ok()
+}
+
+fn decrypt_operand<'x>(
+    cipher: &StoreCipher,
+    key: &[u8],
+    value: &'x [u8],
+) -> Option<Option<Cow<'x, [u8]>>> {
+    match cipher.decrypt(key, value) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            error!("Failed to decrypt merge operand for key {:?}: {}", key, err);
+            None
+        }
+    }
+}
+
+fn set_touch_operator(cf_opts: &mut Options, cipher: &Arc<StoreCipher>) {
+    let cipher = cipher.clone();
+    cf_opts.set_merge_operator_associative(
+        "merge",
+        move |key: &[u8], existing_val: Option<&[u8]>, operands: &MergeOperands| {
+            encrypted_merge(&cipher, key, existing_val, operands, |_, _| None)
+        },
+    );
+    cf_opts.set_compaction_filter("compact", touch_compact);
+}
+
+pub fn touch_compact(
+    _level: u32,
+    _key: &[u8],
+    value: &[u8],
+) -> rocksdb::compaction_filter::Decision {
+    if StoreCipher::is_touch(value) {
+        rocksdb::compaction_filter::Decision::Remove
+    } else {
+        rocksdb::compaction_filter::Decision::Keep
+    }
+}
@@ -129,7 +129,7 @@ where
         core.rate_limiters
             .get(&RemoteAddress::AccountId(session.account_id()))
             .unwrap()
-            .is_upload_allowed(core.store.config.max_concurrent_uploads)
+            .is_upload_allowed(core.store.config.max_concurrent_uploads.get())
             .ok_or_else(|| RequestError::limit(RequestLimitError::Concurrent))?
             .into()
     } else {
@@ -144,7 +144,7 @@ where
         }
     }
 
-    if bytes.len() > core.store.config.max_size_upload {
+    if bytes.len() > core.store.config.max_size_upload.get() {
         return Err(RequestError::limit(RequestLimitError::Size));
     }
 
@@ -0,0 +1,276 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::Path,
+};
+
+use jmap_mail::mail::schema::Keyword;
+use store::{
+    ahash::AHashSet,
+    chrono::{TimeZone, Utc},
+    core::tag::Tag,
+};
+
+use crate::imap::utf7_encode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveType {
+    Zip,
+    Tar,
+}
+
+// Archives are written to a file as they are built, so exports of large
+// accounts are not held in memory.
+#[allow(clippy::large_enum_variant)]
+pub enum ArchiveWriter {
+    Zip(zip::ZipWriter<File>),
+    Tar(tar::Builder<File>),
+}
+
+impl ArchiveWriter {
+    pub fn new(archive_type: ArchiveType, file: File) -> Self {
+        match archive_type {
+            ArchiveType::Zip => ArchiveWriter::Zip(zip::ZipWriter::new(file)),
+            ArchiveType::Tar => ArchiveWriter::Tar(tar::Builder::new(file)),
+        }
+    }
+
+    pub fn append(&mut self, path: &str, bytes: &[u8], modified: i64) -> io::Result<()> {
+        match self {
+            ArchiveWriter::Zip(zip) => {
+                zip.start_file(
+                    path,
+                    zip::write::FileOptions::default()
+                        .compression_method(zip::CompressionMethod::Deflated),
+                )
+                .map_err(zip_error)?;
+                zip.write_all(bytes)
+            }
+            ArchiveWriter::Tar(tar) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(bytes.len() as u64);
+                header.set_mode(0o600);
+                header.set_mtime(modified.max(0) as u64);
+                header.set_cksum();
+                tar.append_data(&mut header, path, bytes)
+            }
+        }
+    }
+
+    // Appends the contents of a file without reading it in memory.
+    pub fn append_file(&mut self, path: &str, file: &Path, modified: i64) -> io::Result<()> {
+        let mut file = File::open(file)?;
+        match self {
+            ArchiveWriter::Zip(zip) => {
+                zip.start_file(
+                    path,
+                    zip::write::FileOptions::default()
+                        .compression_method(zip::CompressionMethod::Deflated),
+                )
+                .map_err(zip_error)?;
+                io::copy(&mut file, zip).map(|_| ())
+            }
+            ArchiveWriter::Tar(tar) => {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(file.metadata()?.len());
+                header.set_mode(0o600);
+                header.set_mtime(modified.max(0) as u64);
+                header.set_cksum();
+                tar.append_data(&mut header, path, file)
+            }
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            ArchiveWriter::Zip(mut zip) => zip.finish().map_err(zip_error)?,
+            ArchiveWriter::Tar(tar) => tar.into_inner()?,
+        };
+        file.flush()
+    }
+}
+
+fn zip_error(err: zip::result::ZipError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+// Builds the path of a message inside a Maildir++ tree, the inverse of
+// the mapping used when importing.
+pub fn maildir_path(
+    folder: &str,
+    document_id: u32,
+    received_at: i64,
+    keywords: Option<&AHashSet<Tag>>,
+) -> String {
+    let mut path = "Maildir/".to_string();
+    if folder != "INBOX" {
+        path.push('.');
+        path.push_str(&utf7_encode(folder).replace('/', "."));
+        path.push('/');
+    }
+    path.push_str(&format!(
+        "cur/{}.{}.export:2,{}",
+        received_at,
+        document_id,
+        &maildir_flags(keywords)
+    ));
+    path
+}
+
+fn maildir_flags(keywords: Option<&AHashSet<Tag>>) -> String {
+    let mut flags = keywords
+        .into_iter()
+        .flatten()
+        .filter_map(|tag| match tag {
+            Tag::Static(Keyword::DRAFT) => 'D'.into(),
+            Tag::Static(Keyword::FLAGGED) => 'F'.into(),
+            Tag::Static(Keyword::FORWARDED) => 'P'.into(),
+            Tag::Static(Keyword::ANSWERED) => 'R'.into(),
+            Tag::Static(Keyword::SEEN) => 'S'.into(),
+            Tag::Static(Keyword::DELETED) => 'T'.into(),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    flags.sort_unstable();
+    flags.into_iter().collect()
+}
+
+// Appends a message to an mbox file, keywords are written as Status and
+// X-Status headers and lines starting with "From " are quoted (mboxrd).
+pub fn mbox_append(
+    mbox: &mut impl Write,
+    raw_message: &[u8],
+    received_at: i64,
+    keywords: Option<&AHashSet<Tag>>,
+) -> io::Result<()> {
+    let has =
+        |keyword: u8| keywords.map_or(false, |keywords| keywords.contains(&Tag::Static(keyword)));
+
+    mbox.write_all(b"From MAILER-DAEMON ")?;
+    mbox.write_all(
+        Utc.timestamp_opt(received_at, 0)
+            .single()
+            .unwrap_or_else(Utc::now)
+            .format("%a %b %e %H:%M:%S %Y\n")
+            .to_string()
+            .as_bytes(),
+    )?;
+    mbox.write_all(if has(Keyword::SEEN) {
+        b"Status: RO\n"
+    } else {
+        b"Status: O\n"
+    })?;
+    let x_status = [
+        (Keyword::ANSWERED, 'A'),
+        (Keyword::FLAGGED, 'F'),
+        (Keyword::DRAFT, 'T'),
+        (Keyword::DELETED, 'D'),
+    ]
+    .into_iter()
+    .filter_map(|(keyword, flag)| if has(keyword) { Some(flag) } else { None })
+    .collect::<String>();
+    if !x_status.is_empty() {
+        mbox.write_all(format!("X-Status: {}\n", x_status).as_bytes())?;
+    }
+
+    for line in raw_message.split_inclusive(|&ch| ch == b'\n') {
+        let quotes = line.iter().take_while(|&&ch| ch == b'>').count();
+        if line[quotes..].starts_with(b"From ") {
+            mbox.write_all(b">")?;
+        }
+        mbox.write_all(line)?;
+    }
+    if !raw_message.ends_with(b"\n") {
+        mbox.write_all(b"\n")?;
+    }
+    mbox.write_all(b"\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use jmap_mail::mail::schema::Keyword;
+    use store::{ahash::AHashSet, core::tag::Tag};
+
+    use crate::api::import::archive::{maildir_keywords, mbox_keywords, MboxReader};
+
+    use super::{maildir_path, mbox_append};
+
+    #[test]
+    fn export_roundtrip() {
+        let keywords = [Keyword::SEEN, Keyword::FLAGGED, Keyword::ANSWERED]
+            .into_iter()
+            .map(Tag::Static)
+            .collect::<AHashSet<_>>();
+
+        let path = maildir_path("Lists/Entwürfe", 7, 1204680122, Some(&keywords));
+        assert_eq!(
+            path,
+            "Maildir/.Lists.Entw&APw-rfe/cur/1204680122.7.export:2,FRS"
+        );
+        assert_eq!(
+            maildir_keywords(&path)
+                .into_iter()
+                .map(|k| k.tag)
+                .collect::<AHashSet<_>>(),
+            keywords
+        );
+        assert_eq!(
+            maildir_path("INBOX", 1, 1204680122, None),
+            "Maildir/cur/1204680122.1.export:2,"
+        );
+
+        let messages = [
+            b"Subject: first\r\n\r\nFrom here\r\n>From there\r\n".to_vec(),
+            b"Subject: second\n\nNo trailing newline".to_vec(),
+        ];
+        let mut mbox = Vec::new();
+        mbox_append(&mut mbox, &messages[0], 1310126914, Some(&keywords)).unwrap();
+        mbox_append(&mut mbox, &messages[1], 1310198400, None).unwrap();
+
+        let parsed = MboxReader::new(&mbox[..])
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(
+            parsed[0].0,
+            b"Status: RO\nX-Status: AF\nSubject: first\r\n\r\nFrom here\r\n>From there\r\n"
+        );
+        assert_eq!(parsed[0].1, Some(1310126914));
+        assert_eq!(
+            mbox_keywords(&parsed[0].0)
+                .into_iter()
+                .map(|k| k.tag)
+                .collect::<AHashSet<_>>(),
+            keywords
+        );
+        assert_eq!(
+            parsed[1].0,
+            b"Status: O\nSubject: second\n\nNo trailing newline\n"
+        );
+        assert_eq!(parsed[1].1, Some(1310198400));
+    }
+}
@@ -0,0 +1,540 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod archive;
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    web, HttpResponse,
+};
+use jmap::{
+    orm::serialize::JMAPOrm,
+    types::{blob::JMAPBlob, jmap::JMAPId},
+    SUPERUSER_ID,
+};
+use jmap_mail::mail::schema::{Email, Property};
+use jmap_sharing::principal::account::JMAPAccountStore;
+use store::{
+    ahash::{AHashMap, AHashSet},
+    blob::BlobId,
+    chrono::Utc,
+    core::{collection::Collection, tag::Tag},
+    parking_lot::Mutex,
+    rand::{distributions::Alphanumeric, thread_rng, Rng},
+    serialize::{StoreDeserialize, StoreSerialize},
+    tracing::{debug, error, info},
+    AccountId, JMAPStore, Store,
+};
+
+use crate::{
+    api::{redirect_to_leader, RequestError},
+    authorization::Session,
+    imap::{fetch::get_message_data, mailbox::get_mailboxes},
+    JMAPServer,
+};
+
+use self::archive::{maildir_path, mbox_append, ArchiveType, ArchiveWriter};
+
+use super::import::archive::Format;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ExportRequest {
+    format: Format,
+    archive: ArchiveType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ExportProgress {
+    pub status: ExportStatus,
+    pub exported: u64,
+    pub failed: u64,
+    #[serde(rename = "blobId")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blob_id: Option<JMAPBlob>,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Jobs in progress, the state of every job is also kept in the store so it
+// can be queried once the job has finished or after a restart.
+pub struct ExportJob {
+    pub id: String,
+    pub account_id: AccountId,
+    pub format: Format,
+    pub archive: ArchiveType,
+    pub progress: Mutex<ExportProgress>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ExportJobState {
+    id: String,
+    #[serde(rename = "accountId")]
+    account_id: JMAPId,
+    format: Format,
+    archive: ArchiveType,
+    #[serde(flatten)]
+    progress: ExportProgress,
+}
+
+impl StoreSerialize for ExportJobState {
+    fn serialize(&self) -> Option<Vec<u8>> {
+        serde_json::to_vec(self).ok()
+    }
+}
+
+impl StoreDeserialize for ExportJobState {
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        serde_json::from_slice(bytes).ok()
+    }
+}
+
+impl ExportJobState {
+    fn response(&self, status: StatusCode) -> HttpResponse {
+        HttpResponse::build(status)
+            .insert_header(ContentType::json())
+            .json(self)
+    }
+}
+
+impl ExportJob {
+    fn state(&self) -> ExportJobState {
+        ExportJobState {
+            id: self.id.clone(),
+            account_id: JMAPId::from(self.account_id),
+            format: self.format,
+            archive: self.archive,
+            progress: self.progress.lock().clone(),
+        }
+    }
+
+    fn file_name(&self) -> &'static str {
+        match self.archive {
+            ArchiveType::Zip => "export.zip",
+            ArchiveType::Tar => "export.tar",
+        }
+    }
+}
+
+pub async fn handle_export_create<T>(
+    path: web::Path<(JMAPId,)>,
+    request: web::Bytes,
+    core: web::Data<JMAPServer<T>>,
+    session: Session,
+) -> Result<HttpResponse, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let (id,) = path.into_inner();
+    let account_id = id.get_document_id();
+    if let Some(redirect) = redirect_to_leader(&core, &format!("/jmap/export/{}", id))? {
+        return Ok(redirect);
+    }
+    let request = serde_json::from_slice::<ExportRequest>(&request).map_err(|err| {
+        debug!("Failed to parse export request: {}", err);
+        RequestError::not_request()
+    })?;
+    check_access(&core, &session, account_id).await?;
+
+    let job = Arc::new(ExportJob {
+        id: thread_rng()
+            .sample_iter(Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect::<String>(),
+        account_id,
+        format: request.format,
+        archive: request.archive,
+        progress: Mutex::new(ExportProgress {
+            status: ExportStatus::Running,
+            exported: 0,
+            failed: 0,
+            blob_id: None,
+            error: None,
+        }),
+    });
+    core.export_jobs.insert(job.id.clone(), job.clone()).await;
+    save_job(&core, &job).await;
+    info!(
+        "Starting {:?} export job {} for account {}.",
+        job.format, job.id, account_id
+    );
+    tokio::spawn(run_export(core.clone(), job.clone()));
+
+    Ok(job.state().response(StatusCode::ACCEPTED))
+}
+
+pub async fn handle_export_get<T>(
+    path: web::Path<(JMAPId, String)>,
+    core: web::Data<JMAPServer<T>>,
+    session: Session,
+) -> Result<HttpResponse, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let (id, job_id) = path.into_inner();
+    let account_id = id.get_document_id();
+    if let Some(redirect) = redirect_to_leader(&core, &format!("/jmap/export/{}/{}", id, job_id))? {
+        return Ok(redirect);
+    }
+    check_access(&core, &session, account_id).await?;
+
+    let state = if let Some(job) = core.export_jobs.get(&job_id) {
+        job.state()
+    } else {
+        match core.get_key::<ExportJobState>(job_key(&job_id)).await {
+            Ok(Some(mut state)) => {
+                // Jobs are not resumed, a job left running was interrupted by a restart
+                if state.progress.status == ExportStatus::Running {
+                    state.progress.status = ExportStatus::Failed;
+                    state.progress.error =
+                        "Export interrupted by a server restart.".to_string().into();
+                }
+                state
+            }
+            Ok(None) => return Err(RequestError::not_found()),
+            Err(err) => {
+                error!("Failed to obtain export job {}: {:?}", job_id, err);
+                return Err(RequestError::internal_server_error());
+            }
+        }
+    };
+    if state.account_id == id {
+        Ok(state.response(StatusCode::OK))
+    } else {
+        Err(RequestError::not_found())
+    }
+}
+
+fn job_key(job_id: &str) -> String {
+    format!("export/{}", job_id)
+}
+
+async fn save_job<T>(core: &web::Data<JMAPServer<T>>, job: &ExportJob)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    if let Err(err) = core.set_key(job_key(&job.id), job.state()).await {
+        error!("Failed to save state of export job {}: {}", job.id, err);
+    }
+}
+
+async fn check_access<T>(
+    core: &web::Data<JMAPServer<T>>,
+    session: &Session,
+    account_id: AccountId,
+) -> Result<(), RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let store = core.store.clone();
+    let session_account_id = session.account_id();
+    match core
+        .spawn_worker(move || store.get_acl_token(session_account_id))
+        .await
+    {
+        Ok(acl) if acl.is_member(account_id) || acl.is_member(SUPERUSER_ID) => Ok(()),
+        Ok(_) => Err(RequestError::forbidden()),
+        Err(err) => {
+            error!("Failed to obtain ACL token: {:?}", err);
+            Err(RequestError::internal_server_error())
+        }
+    }
+}
+
+async fn run_export<T>(core: web::Data<JMAPServer<T>>, job: Arc<ExportJob>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let store = core.store.clone();
+    let job_ = job.clone();
+    let result = match tokio::task::spawn_blocking(move || write_archive(&store, &job_)).await {
+        Ok(result) => result,
+        Err(err) => {
+            error!("Export job {} writer failed: {}", job.id, err);
+            Err("Internal server error.".to_string())
+        }
+    };
+
+    {
+        let mut progress = job.progress.lock();
+        match &result {
+            Ok(blob_id) => {
+                progress.status = ExportStatus::Completed;
+                progress.blob_id = JMAPBlob::new(blob_id.clone()).into();
+            }
+            Err(err) => {
+                progress.status = ExportStatus::Failed;
+                progress.error = err.clone().into();
+            }
+        }
+        info!(
+            "Export job {} for account {} finished with {} messages exported and {} failed.",
+            job.id, job.account_id, progress.exported, progress.failed
+        );
+    }
+
+    save_job(&core, &job).await;
+    core.export_jobs.invalidate(&job.id).await;
+
+    if let Err(err) = notify_account(&core, &job).await {
+        error!("Export job {} failed to send notification: {}", job.id, err);
+    }
+}
+
+// Writes all messages in the account to an archive and stores it as a
+// temporary blob linked to the account. The archive and the mbox files it
+// contains are built in a temporary directory, which is removed afterwards.
+fn write_archive<T>(store: &JMAPStore<T>, job: &ExportJob) -> Result<BlobId, String>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let account_id = job.account_id;
+    let mailboxes = get_mailboxes(store, account_id)
+        .map_err(|err| format!("Failed to obtain mailboxes: {}", err))?
+        .into_iter()
+        .map(|mailbox| (mailbox.id, mailbox.name))
+        .collect::<AHashMap<_, _>>();
+    let document_ids = store
+        .get_document_ids(account_id, Collection::Mail)
+        .map_err(|err| format!("Failed to obtain messages: {}", err))?
+        .unwrap_or_default();
+
+    let io_error = |err: io::Error| format!("Failed to write archive: {}", err);
+    let temp_dir = TempDir::new(&job.id).map_err(io_error)?;
+    let archive_path = temp_dir.path.join(job.file_name());
+    let mut archive =
+        ArchiveWriter::new(job.archive, File::create(&archive_path).map_err(io_error)?);
+    let mut mbox_files: AHashMap<&str, PathBuf> = AHashMap::new();
+
+    for document_id in document_ids {
+        let (fields, raw_message, received_at) =
+            match get_message_data(store, account_id, document_id).and_then(|data| {
+                if let (Some(fields), Some(data)) =
+                    (store.get_orm::<Email>(account_id, document_id)?, data)
+                {
+                    Ok(store
+                        .blob_get(&data.raw_message)?
+                        .map(|raw_message| (fields, raw_message, data.received_at)))
+                } else {
+                    Ok(None)
+                }
+            }) {
+                Ok(Some(message)) => message,
+                Ok(None) => {
+                    job.progress.lock().failed += 1;
+                    continue;
+                }
+                Err(err) => {
+                    debug!(
+                        "Export job {} failed to read message {}: {}",
+                        job.id, document_id, err
+                    );
+                    job.progress.lock().failed += 1;
+                    continue;
+                }
+            };
+
+        // Messages are written once for every mailbox they belong to
+        let keywords = fields.get_tags(&Property::Keywords);
+        let mut folders = fields
+            .get_tags(&Property::MailboxIds)
+            .into_iter()
+            .flatten()
+            .filter_map(|tag| match tag {
+                Tag::Id(mailbox_id) => mailboxes.get(mailbox_id).map(|name| name.as_str()),
+                _ => None,
+            })
+            .collect::<AHashSet<_>>();
+        if folders.is_empty() {
+            folders.insert("INBOX");
+        }
+
+        for folder in folders {
+            match job.format {
+                Format::Maildir => archive
+                    .append(
+                        &maildir_path(folder, document_id, received_at, keywords),
+                        &raw_message,
+                        received_at,
+                    )
+                    .map_err(io_error)?,
+                Format::Mbox => {
+                    let num_files = mbox_files.len();
+                    let path = mbox_files
+                        .entry(folder)
+                        .or_insert_with(|| temp_dir.path.join(format!("{}.mbox", num_files)));
+                    let mut mbox = BufWriter::new(
+                        OpenOptions::new()
+                            .create(true)
+                            .append(true)
+                            .open(path)
+                            .map_err(io_error)?,
+                    );
+                    mbox_append(&mut mbox, &raw_message, received_at, keywords)
+                        .and_then(|_| mbox.flush())
+                        .map_err(io_error)?;
+                }
+            }
+        }
+        job.progress.lock().exported += 1;
+    }
+
+    let now = Utc::now().timestamp();
+    for (folder, path) in mbox_files {
+        archive
+            .append_file(&format!("{}.mbox", folder), &path, now)
+            .map_err(io_error)?;
+    }
+    archive.finish().map_err(io_error)?;
+
+    let blob_id = store
+        .blob_store_file(&archive_path)
+        .and_then(|blob_id| {
+            store
+                .blob_link_ephemeral(&blob_id, account_id)
+                .map(|_| blob_id)
+        })
+        .map_err(|err| format!("Failed to store archive: {}", err))?;
+    Ok(blob_id)
+}
+
+struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    fn new(job_id: &str) -> io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("stalwart-export-{}", job_id));
+        fs::create_dir_all(&path)?;
+        Ok(TempDir { path })
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        if let Err(err) = fs::remove_dir_all(&self.path) {
+            debug!(
+                "Failed to remove temporary directory {}: {}",
+                self.path.display(),
+                err
+            );
+        }
+    }
+}
+
+// Delivers a message to the account's Inbox with the outcome of the export.
+async fn notify_account<T>(core: &web::Data<JMAPServer<T>>, job: &ExportJob) -> Result<(), String>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let store = core.store.clone();
+    let account_id = job.account_id;
+    let email = core
+        .spawn_worker(move || store.get_account_details(account_id))
+        .await
+        .map_err(|err| err.to_string())?
+        .map(|(email, _, _)| email)
+        .filter(|email| !email.is_empty())
+        .ok_or_else(|| "Account has no e-mail address.".to_string())?;
+
+    let hostname = gethostname::gethostname()
+        .to_str()
+        .unwrap_or("localhost")
+        .to_string();
+    let progress = job.progress.lock().clone();
+    let (subject, body) = match progress.blob_id {
+        Some(blob_id) => (
+            "Your mailbox export is ready",
+            format!(
+                concat!(
+                    "Your mailbox export has completed, {} messages were exported",
+                    " ({} could not be read).\r\n\r\n",
+                    "The archive can be downloaded from:\r\n\r\n{}/jmap/download/{}/{}/{}\r\n\r\n",
+                    "The download link will expire in {} minutes.\r\n"
+                ),
+                progress.exported,
+                progress.failed,
+                core.base_session.base_url(),
+                JMAPId::from(account_id),
+                blob_id,
+                job.file_name(),
+                core.store.config.blob_temp_ttl / 60,
+            ),
+        ),
+        None => (
+            "Your mailbox export has failed",
+            format!(
+                "Your mailbox export could not be completed: {}\r\n",
+                progress.error.as_deref().unwrap_or("Unknown error.")
+            ),
+        ),
+    };
+    let raw_message = format!(
+        concat!(
+            "From: Mail Delivery System <mailer-daemon@{}>\r\n",
+            "To: <{}>\r\n",
+            "Subject: {}\r\n",
+            "Date: {}\r\n",
+            "Message-ID: <export.{}@{}>\r\n",
+            "Auto-Submitted: auto-generated\r\n",
+            "MIME-Version: 1.0\r\n",
+            "Content-Type: text/plain; charset=utf-8\r\n",
+            "Content-Transfer-Encoding: 8bit\r\n\r\n{}"
+        ),
+        hostname,
+        email,
+        subject,
+        Utc::now().to_rfc2822(),
+        job.id,
+        hostname,
+        body
+    )
+    .into_bytes();
+
+    core.mail_ingest(
+        format!("mailer-daemon@{}", hostname),
+        [account_id].into_iter().collect(),
+        raw_message,
+        None,
+        None,
+    )
+    .await
+    .map(|_| ())
+}
@@ -0,0 +1,551 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    fs,
+    io::{self, BufRead, BufReader, Read},
+    path::{Component, Path, PathBuf},
+    time::SystemTime,
+};
+
+use jmap_mail::mail::schema::Keyword;
+use store::{chrono::NaiveDateTime, core::tag::Tag};
+
+use crate::imap::utf7_decode;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Mbox,
+    Maildir,
+}
+
+pub enum Source {
+    Blob(Vec<u8>),
+    Path(PathBuf),
+}
+
+#[derive(Debug)]
+pub struct ImportItem {
+    pub folder: String,
+    pub raw_message: Vec<u8>,
+    pub keywords: Vec<Keyword>,
+    pub received_at: Option<i64>,
+}
+
+// Reads all messages from an archive, stops early when the callback returns false.
+// Uploaded Maildir trees are expected to be tar archives.
+pub fn read_source(
+    format: Format,
+    source: Source,
+    folder: &str,
+    mut f: impl FnMut(ImportItem) -> bool,
+) -> io::Result<()> {
+    match (format, source) {
+        (Format::Mbox, Source::Blob(bytes)) => read_mbox(&bytes[..], folder, &mut f).map(|_| ()),
+        (Format::Mbox, Source::Path(path)) => {
+            if path.is_dir() {
+                read_mbox_dir(&path, &mut Vec::new(), &mut f).map(|_| ())
+            } else {
+                read_mbox(BufReader::new(fs::File::open(path)?), folder, &mut f).map(|_| ())
+            }
+        }
+        (Format::Maildir, Source::Blob(bytes)) => read_maildir_tar(&bytes, &mut f),
+        (Format::Maildir, Source::Path(path)) => read_maildir_dir(&path, &mut f),
+    }
+}
+
+fn read_mbox(
+    reader: impl BufRead,
+    folder: &str,
+    f: &mut impl FnMut(ImportItem) -> bool,
+) -> io::Result<bool> {
+    for message in MboxReader::new(reader) {
+        let (raw_message, received_at) = message?;
+        if !f(ImportItem {
+            folder: folder.to_string(),
+            keywords: mbox_keywords(&raw_message),
+            raw_message,
+            received_at,
+        }) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+// Each file is imported into a folder named after its path, Thunderbird's
+// ".sbd" directories hold the children of the mbox file with the same name.
+fn read_mbox_dir(
+    path: &Path,
+    parents: &mut Vec<String>,
+    f: &mut impl FnMut(ImportItem) -> bool,
+) -> io::Result<bool> {
+    let mut entries = fs::read_dir(path)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_unstable_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with('.') || name.ends_with(".msf") {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            parents.push(name.strip_suffix(".sbd").unwrap_or(&name).to_string());
+            let result = read_mbox_dir(&entry.path(), parents, f)?;
+            parents.pop();
+            if !result {
+                return Ok(false);
+            }
+        } else {
+            parents.push(name.strip_suffix(".mbox").unwrap_or(&name).to_string());
+            let folder = parents.join("/");
+            parents.pop();
+            if !read_mbox(BufReader::new(fs::File::open(entry.path())?), &folder, f)? {
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+// Maildir++ layout, the root is the Inbox and each ".Folder.Child"
+// directory a subfolder.
+fn read_maildir_dir(path: &Path, f: &mut impl FnMut(ImportItem) -> bool) -> io::Result<()> {
+    let mut folders = vec![(path.to_path_buf(), maildir_folder(None))];
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.len() > 1 && name != ".." && name.starts_with('.') && entry.file_type()?.is_dir() {
+            folders.push((entry.path(), maildir_folder(Some(&name))));
+        }
+    }
+
+    for (path, folder) in folders {
+        for subdir in ["cur", "new"] {
+            let path = path.join(subdir);
+            if !path.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(path)? {
+                let entry = entry?;
+                if !entry.file_type()?.is_file() {
+                    continue;
+                }
+                let file_name = entry.file_name().to_string_lossy().into_owned();
+                if !f(ImportItem {
+                    folder: folder.clone(),
+                    raw_message: fs::read(entry.path())?,
+                    keywords: if subdir == "cur" {
+                        maildir_keywords(&file_name)
+                    } else {
+                        Vec::new()
+                    },
+                    received_at: maildir_received_at(&file_name).or_else(|| {
+                        entry
+                            .metadata()
+                            .ok()?
+                            .modified()
+                            .ok()?
+                            .duration_since(SystemTime::UNIX_EPOCH)
+                            .ok()
+                            .map(|d| d.as_secs() as i64)
+                    }),
+                }) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_maildir_tar(bytes: &[u8], f: &mut impl FnMut(ImportItem) -> bool) -> io::Result<()> {
+    let mut archive = tar::Archive::new(bytes);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry
+            .path()?
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => name.to_str().map(|name| name.to_string()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let (dir_name, subdir, file_name) = match path.as_slice() {
+            [.., dir_name, subdir, file_name] => (Some(dir_name.as_str()), subdir, file_name),
+            [subdir, file_name] => (None, subdir, file_name),
+            _ => continue,
+        };
+        if subdir != "cur" && subdir != "new" {
+            continue;
+        }
+
+        let mut raw_message = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut raw_message)?;
+        if !f(ImportItem {
+            folder: maildir_folder(dir_name.filter(|name| name.starts_with('.'))),
+            raw_message,
+            keywords: if subdir == "cur" {
+                maildir_keywords(file_name)
+            } else {
+                Vec::new()
+            },
+            received_at: maildir_received_at(file_name)
+                .or_else(|| entry.header().mtime().ok().map(|t| t as i64)),
+        }) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+// Maps a Maildir++ directory name to a folder name, names are encoded in
+// modified UTF-7 and use '.' as the hierarchy delimiter.
+pub fn maildir_folder(dir_name: Option<&str>) -> String {
+    match dir_name.and_then(|name| name.strip_prefix('.')) {
+        Some(name) if !name.is_empty() => {
+            let name = name.replace('.', "/");
+            utf7_decode(&name).unwrap_or(name)
+        }
+        _ => "INBOX".to_string(),
+    }
+}
+
+// Parses the flags following the ":2," suffix (or "!2," on Windows).
+pub fn maildir_keywords(file_name: &str) -> Vec<Keyword> {
+    let flags = match file_name
+        .rsplit_once(":2,")
+        .or_else(|| file_name.rsplit_once("!2,"))
+    {
+        Some((_, flags)) => flags,
+        None => return Vec::new(),
+    };
+    flags
+        .chars()
+        .filter_map(|flag| {
+            Keyword::new(Tag::Static(match flag {
+                'D' => Keyword::DRAFT,
+                'F' => Keyword::FLAGGED,
+                'P' => Keyword::FORWARDED,
+                'R' => Keyword::ANSWERED,
+                'S' => Keyword::SEEN,
+                'T' => Keyword::DELETED,
+                _ => return None,
+            }))
+            .into()
+        })
+        .collect()
+}
+
+// Unique Maildir file names start with the delivery timestamp.
+pub fn maildir_received_at(file_name: &str) -> Option<i64> {
+    file_name.split('.').next()?.parse().ok()
+}
+
+// Maps the Status and X-Status headers written by mbox based clients.
+pub fn mbox_keywords(raw_message: &[u8]) -> Vec<Keyword> {
+    let mut keywords = Vec::new();
+    for line in raw_message.split(|&ch| ch == b'\n') {
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = match line.iter().position(|&ch| ch == b':') {
+            Some(pos) => (&line[..pos], &line[pos + 1..]),
+            None => continue,
+        };
+        let flags: &[(u8, u8)] = if name.eq_ignore_ascii_case(b"Status") {
+            &[(b'R', Keyword::SEEN)]
+        } else if name.eq_ignore_ascii_case(b"X-Status") {
+            &[
+                (b'A', Keyword::ANSWERED),
+                (b'F', Keyword::FLAGGED),
+                (b'T', Keyword::DRAFT),
+                (b'D', Keyword::DELETED),
+            ]
+        } else {
+            continue;
+        };
+        for (flag, keyword) in flags {
+            let keyword = Keyword::new(Tag::Static(*keyword));
+            if value.contains(flag) && !keywords.contains(&keyword) {
+                keywords.push(keyword);
+            }
+        }
+    }
+    keywords
+}
+
+// Maps top level folders with well-known names to a mailbox role.
+pub fn folder_role(name: &str) -> Option<&'static str> {
+    match name.to_lowercase().as_str() {
+        "sent" | "sent items" | "sent messages" | "sent mail" => "sent",
+        "drafts" => "drafts",
+        "trash" | "deleted items" | "deleted messages" => "trash",
+        "junk" | "spam" | "junk e-mail" | "junk email" => "junk",
+        "archive" | "archives" => "archive",
+        _ => return None,
+    }
+    .into()
+}
+
+// Splits an mbox file into messages, returning each message along with
+// the date found in its "From " separator line. Lines quoted as per mboxrd
+// (i.e. ">From ") are unquoted.
+pub struct MboxReader<R: BufRead> {
+    reader: R,
+    from_line: Option<Vec<u8>>,
+    is_eof: bool,
+}
+
+impl<R: BufRead> MboxReader<R> {
+    pub fn new(reader: R) -> Self {
+        MboxReader {
+            reader,
+            from_line: None,
+            is_eof: false,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for MboxReader<R> {
+    type Item = io::Result<(Vec<u8>, Option<i64>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = Vec::new();
+
+        // Skip anything before the first separator
+        while self.from_line.is_none() {
+            if self.is_eof {
+                return None;
+            }
+            line.clear();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) => self.is_eof = true,
+                Ok(_) if line.starts_with(b"From ") => self.from_line = line.clone().into(),
+                Ok(_) => (),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        let from_line = self.from_line.take().unwrap();
+        let mut message = Vec::new();
+        let mut prev_is_blank = true;
+        while !self.is_eof {
+            line.clear();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) => self.is_eof = true,
+                Ok(_) if prev_is_blank && line.starts_with(b"From ") => {
+                    self.from_line = line.clone().into();
+                    break;
+                }
+                Ok(_) => {
+                    let quotes = line.iter().take_while(|&&ch| ch == b'>').count();
+                    if quotes > 0 && line[quotes..].starts_with(b"From ") {
+                        message.extend_from_slice(&line[1..]);
+                    } else {
+                        message.extend_from_slice(&line);
+                    }
+                    prev_is_blank = line == b"\n" || line == b"\r\n";
+                }
+                Err(err) => return Some(Err(err)),
+            }
+        }
+
+        // Remove the blank line preceding the next separator
+        if message.ends_with(b"\r\n\r\n") {
+            message.truncate(message.len() - 2);
+        } else if message.ends_with(b"\n\n") {
+            message.truncate(message.len() - 1);
+        }
+
+        Some(Ok((message, parse_from_line(&from_line))))
+    }
+}
+
+// Parses the asctime date of a separator line, i.e. "From user@domain Fri Jul  8 12:08:34 2011"
+fn parse_from_line(line: &[u8]) -> Option<i64> {
+    let date = std::str::from_utf8(line)
+        .ok()?
+        .split_whitespace()
+        .skip(2)
+        .collect::<Vec<_>>();
+    // Some writers add a timezone after the year, which is ignored
+    [&date[..], date.get(..5).unwrap_or_default()]
+        .into_iter()
+        .find_map(|date| {
+            NaiveDateTime::parse_from_str(&date.join(" "), "%a %b %d %H:%M:%S %Y").ok()
+        })
+        .map(|date| date.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use jmap_mail::mail::schema::Keyword;
+    use store::core::tag::Tag;
+
+    use super::{
+        folder_role, maildir_folder, maildir_keywords, maildir_received_at, mbox_keywords,
+        read_source, Format, MboxReader, Source,
+    };
+
+    #[test]
+    fn import_mbox_reader() {
+        let mbox = concat!(
+            "From alice@example.org Fri Jul  8 12:08:34 2011\n",
+            "Subject: first\n",
+            "Status: RO\n",
+            "X-Status: AF\n",
+            "\n",
+            "Hello\n",
+            ">From the archive\n",
+            "From here on\n",
+            "\n",
+            "From bob@example.org Sat Jul  9 08:00:00 2011\n",
+            "Subject: second\n",
+            "\n",
+            "Bye\n",
+        );
+        let messages = MboxReader::new(mbox.as_bytes())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(
+            messages,
+            vec![
+                (
+                    concat!(
+                        "Subject: first\n",
+                        "Status: RO\n",
+                        "X-Status: AF\n",
+                        "\n",
+                        "Hello\n",
+                        "From the archive\n",
+                        "From here on\n",
+                    )
+                    .as_bytes()
+                    .to_vec(),
+                    Some(1310126914)
+                ),
+                (b"Subject: second\n\nBye\n".to_vec(), Some(1310198400)),
+            ]
+        );
+        assert_eq!(
+            mbox_keywords(&messages[0].0),
+            vec![
+                Keyword::new(Tag::Static(Keyword::SEEN)),
+                Keyword::new(Tag::Static(Keyword::ANSWERED)),
+                Keyword::new(Tag::Static(Keyword::FLAGGED)),
+            ]
+        );
+        assert_eq!(mbox_keywords(&messages[1].0), vec![]);
+    }
+
+    #[test]
+    fn import_maildir_names() {
+        assert_eq!(
+            maildir_keywords("1204680122.M2P3.host:2,FRS"),
+            vec![
+                Keyword::new(Tag::Static(Keyword::FLAGGED)),
+                Keyword::new(Tag::Static(Keyword::ANSWERED)),
+                Keyword::new(Tag::Static(Keyword::SEEN)),
+            ]
+        );
+        assert_eq!(maildir_keywords("1204680122.M2P3.host"), vec![]);
+        assert_eq!(
+            maildir_received_at("1204680122.M2P3.host:2,S"),
+            Some(1204680122)
+        );
+        assert_eq!(maildir_received_at("msg.host"), None);
+        assert_eq!(maildir_folder(None), "INBOX");
+        assert_eq!(maildir_folder(Some(".Lists.Rust")), "Lists/Rust");
+        assert_eq!(maildir_folder(Some(".Entw&APw-rfe")), "Entwürfe");
+        assert_eq!(folder_role("Sent Items"), Some("sent"));
+        assert_eq!(folder_role("Projects"), None);
+    }
+
+    #[test]
+    fn import_maildir_tar() {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, contents) in [
+            ("Maildir/cur/1204680122.M1.host:2,S", "Subject: seen\n\nA\n"),
+            ("Maildir/new/1204680123.M2.host", "Subject: new\n\nB\n"),
+            ("Maildir/tmp/1204680124.M3.host", "Subject: tmp\n\nC\n"),
+            (
+                "Maildir/.Lists.Rust/cur/1204680125.M4.host:2,RS",
+                "Subject: list\n\nD\n",
+            ),
+        ] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+
+        let mut items = Vec::new();
+        read_source(
+            Format::Maildir,
+            Source::Blob(builder.into_inner().unwrap()),
+            "INBOX",
+            |item| {
+                items.push((
+                    item.folder,
+                    String::from_utf8(item.raw_message).unwrap(),
+                    item.keywords.len(),
+                    item.received_at,
+                ));
+                true
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            items,
+            vec![
+                (
+                    "INBOX".to_string(),
+                    "Subject: seen\n\nA\n".to_string(),
+                    1,
+                    Some(1204680122)
+                ),
+                (
+                    "INBOX".to_string(),
+                    "Subject: new\n\nB\n".to_string(),
+                    0,
+                    Some(1204680123)
+                ),
+                (
+                    "Lists/Rust".to_string(),
+                    "Subject: list\n\nD\n".to_string(),
+                    2,
+                    Some(1204680125)
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,617 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod archive;
+
+use std::{io, sync::Arc};
+
+use actix_web::{
+    http::{header::ContentType, StatusCode},
+    web, HttpResponse,
+};
+use jmap::{
+    request::{set::SetRequest, MaybeIdReference, MaybeResultReference},
+    types::{blob::JMAPBlob, date::JMAPDate, jmap::JMAPId},
+    SUPERUSER_ID,
+};
+use jmap_mail::{
+    mail::{
+        get::{BlobResult, JMAPGetMail},
+        import::{EmailImport, EmailImportRequest},
+    },
+    mailbox::{
+        schema::{Mailbox, Property, Value},
+        set::SetArguments,
+    },
+};
+use jmap_sharing::principal::account::JMAPAccountStore;
+use store::{
+    ahash::AHashMap,
+    blob::BlobId,
+    core::vec_map::VecMap,
+    parking_lot::Mutex,
+    rand::{distributions::Alphanumeric, thread_rng, Rng},
+    serialize::{StoreDeserialize, StoreSerialize},
+    tracing::{debug, error, info},
+    AccountId, DocumentId, Store,
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+
+use crate::{
+    api::{
+        invocation::handle_method_calls, method, redirect_to_leader,
+        request::Request as JMAPRequest, RequestError,
+    },
+    authorization::Session,
+    imap::mailbox::{find_mailbox, get_mailboxes, normalize_name},
+    JMAPServer,
+};
+
+use self::archive::{folder_role, read_source, Format, ImportItem, Source};
+
+const IMPORT_BATCH_SIZE: usize = 50;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ImportRequest {
+    format: Format,
+    #[serde(rename = "blobId")]
+    blob_id: Option<JMAPBlob>,
+    path: Option<String>,
+    mailbox: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ImportProgress {
+    pub status: ImportStatus,
+    pub imported: u64,
+    pub failed: u64,
+    #[serde(rename = "mailboxesCreated")]
+    pub mailboxes_created: u64,
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+// Jobs in progress, the state of every job is also kept in the store so it
+// can be queried once the job has finished or after a restart.
+pub struct ImportJob {
+    pub id: String,
+    pub account_id: AccountId,
+    pub format: Format,
+    pub progress: Mutex<ImportProgress>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct ImportJobState {
+    id: String,
+    #[serde(rename = "accountId")]
+    account_id: JMAPId,
+    format: Format,
+    #[serde(flatten)]
+    progress: ImportProgress,
+}
+
+impl StoreSerialize for ImportJobState {
+    fn serialize(&self) -> Option<Vec<u8>> {
+        serde_json::to_vec(self).ok()
+    }
+}
+
+impl StoreDeserialize for ImportJobState {
+    fn deserialize(bytes: &[u8]) -> Option<Self> {
+        serde_json::from_slice(bytes).ok()
+    }
+}
+
+impl ImportJobState {
+    fn response(&self, status: StatusCode) -> HttpResponse {
+        HttpResponse::build(status)
+            .insert_header(ContentType::json())
+            .json(self)
+    }
+}
+
+impl ImportJob {
+    fn state(&self) -> ImportJobState {
+        ImportJobState {
+            id: self.id.clone(),
+            account_id: JMAPId::from(self.account_id),
+            format: self.format,
+            progress: self.progress.lock().clone(),
+        }
+    }
+}
+
+fn job_key(job_id: &str) -> String {
+    format!("import/{}", job_id)
+}
+
+async fn save_job<T>(core: &web::Data<JMAPServer<T>>, job: &ImportJob)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    if let Err(err) = core.set_key(job_key(&job.id), job.state()).await {
+        error!("Failed to save state of import job {}: {}", job.id, err);
+    }
+}
+
+pub async fn handle_import_create<T>(
+    path: web::Path<(JMAPId,)>,
+    request: web::Bytes,
+    core: web::Data<JMAPServer<T>>,
+    session: Session,
+) -> Result<HttpResponse, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let (id,) = path.into_inner();
+    let account_id = id.get_document_id();
+    if let Some(redirect) = redirect_to_leader(&core, &format!("/jmap/import/{}", id))? {
+        return Ok(redirect);
+    }
+    let request = serde_json::from_slice::<ImportRequest>(&request).map_err(|err| {
+        debug!("Failed to parse import request: {}", err);
+        RequestError::not_request()
+    })?;
+
+    // Obtain the messages source, local paths are only available to administrators
+    let store = core.store.clone();
+    let session_account_id = session.account_id();
+    let source = match core
+        .spawn_worker(move || {
+            let acl = store.get_acl_token(session_account_id)?;
+            if !acl.is_member(account_id) && !acl.is_member(SUPERUSER_ID) {
+                return Ok(Err(RequestError::forbidden()));
+            }
+            Ok(match (request.blob_id, request.path) {
+                (Some(blob_id), None) => match store.mail_blob_get(account_id, &acl, &blob_id)? {
+                    BlobResult::Blob(bytes) => Ok(Source::Blob(bytes)),
+                    BlobResult::NotFound => Err(RequestError::not_found()),
+                    BlobResult::Unauthorized => Err(RequestError::forbidden()),
+                },
+                (None, Some(path)) if acl.is_member(SUPERUSER_ID) => Ok(Source::Path(path.into())),
+                (None, Some(_)) => Err(RequestError::forbidden()),
+                _ => Err(RequestError::blank(
+                    400,
+                    "Invalid Parameters",
+                    "Either a blobId or a path has to be provided.",
+                )),
+            })
+        })
+        .await
+    {
+        Ok(Ok(source)) => source,
+        Ok(Err(err)) => return Err(err),
+        Err(err) => {
+            error!("Failed to obtain import source: {:?}", err);
+            return Err(RequestError::internal_server_error());
+        }
+    };
+
+    let job = Arc::new(ImportJob {
+        id: thread_rng()
+            .sample_iter(Alphanumeric)
+            .take(16)
+            .map(char::from)
+            .collect::<String>(),
+        account_id,
+        format: request.format,
+        progress: Mutex::new(ImportProgress {
+            status: ImportStatus::Running,
+            imported: 0,
+            failed: 0,
+            mailboxes_created: 0,
+            error: None,
+        }),
+    });
+    core.import_jobs.insert(job.id.clone(), job.clone()).await;
+    save_job(&core, &job).await;
+    info!(
+        "Starting {:?} import job {} for account {}.",
+        job.format, job.id, account_id
+    );
+
+    // Read the archive from a blocking thread while messages are imported
+    let (tx, rx) = mpsc::channel(IMPORT_BATCH_SIZE);
+    let format = request.format;
+    let folder = normalize_name(request.mailbox.as_deref().unwrap_or("INBOX"));
+    let reader = tokio::task::spawn_blocking(move || {
+        read_source(format, source, &folder, |item| {
+            tx.blocking_send(item).is_ok()
+        })
+    });
+    tokio::spawn(run_import(core.clone(), session, job.clone(), rx, reader));
+
+    Ok(job.state().response(StatusCode::ACCEPTED))
+}
+
+pub async fn handle_import_get<T>(
+    path: web::Path<(JMAPId, String)>,
+    core: web::Data<JMAPServer<T>>,
+    session: Session,
+) -> Result<HttpResponse, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let (id, job_id) = path.into_inner();
+    let account_id = id.get_document_id();
+    if let Some(redirect) = redirect_to_leader(&core, &format!("/jmap/import/{}/{}", id, job_id))? {
+        return Ok(redirect);
+    }
+
+    let store = core.store.clone();
+    match core
+        .spawn_worker(move || store.get_acl_token(session.account_id()))
+        .await
+    {
+        Ok(acl) if acl.is_member(account_id) || acl.is_member(SUPERUSER_ID) => (),
+        Ok(_) => return Err(RequestError::forbidden()),
+        Err(err) => {
+            error!("Failed to obtain ACL token: {:?}", err);
+            return Err(RequestError::internal_server_error());
+        }
+    }
+
+    let state = if let Some(job) = core.import_jobs.get(&job_id) {
+        job.state()
+    } else {
+        match core.get_key::<ImportJobState>(job_key(&job_id)).await {
+            Ok(Some(mut state)) => {
+                // Jobs are not resumed, a job left running was interrupted by a restart
+                if state.progress.status == ImportStatus::Running {
+                    state.progress.status = ImportStatus::Failed;
+                    state.progress.error =
+                        "Import interrupted by a server restart.".to_string().into();
+                }
+                state
+            }
+            Ok(None) => return Err(RequestError::not_found()),
+            Err(err) => {
+                error!("Failed to obtain import job {}: {:?}", job_id, err);
+                return Err(RequestError::internal_server_error());
+            }
+        }
+    };
+    if state.account_id == id {
+        Ok(state.response(StatusCode::OK))
+    } else {
+        Err(RequestError::not_found())
+    }
+}
+
+async fn run_import<T>(
+    core: web::Data<JMAPServer<T>>,
+    session: Session,
+    job: Arc<ImportJob>,
+    mut rx: mpsc::Receiver<ImportItem>,
+    reader: JoinHandle<io::Result<()>>,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut mailboxes: AHashMap<String, Option<DocumentId>> = AHashMap::new();
+    let batch_size = std::cmp::max(core.store.config.mail_import_max_items, 1);
+    let mut batch = Vec::with_capacity(batch_size);
+
+    while let Some(item) = rx.recv().await {
+        let mailbox_id = if let Some(mailbox_id) = mailboxes.get(&item.folder) {
+            *mailbox_id
+        } else {
+            let mailbox_id = match get_or_create_mailbox(&core, &session, &job, &item.folder).await
+            {
+                Ok(mailbox_id) => mailbox_id.into(),
+                Err(err) => {
+                    debug!(
+                        "Import job {} failed to create mailbox {:?}: {}",
+                        job.id, item.folder, err
+                    );
+                    None
+                }
+            };
+            mailboxes.insert(item.folder.clone(), mailbox_id);
+            mailbox_id
+        };
+
+        if let Some(mailbox_id) = mailbox_id {
+            batch.push((item, mailbox_id));
+            if batch.len() == batch_size {
+                import_batch(&core, &session, &job, std::mem::take(&mut batch)).await;
+                save_job(&core, &job).await;
+            }
+        } else {
+            job.progress.lock().failed += 1;
+        }
+    }
+    if !batch.is_empty() {
+        import_batch(&core, &session, &job, batch).await;
+    }
+
+    let result = reader.await;
+    {
+        let mut progress = job.progress.lock();
+        match result {
+            Ok(Ok(())) => {
+                progress.status = ImportStatus::Completed;
+            }
+            Ok(Err(err)) => {
+                progress.status = ImportStatus::Failed;
+                progress.error = format!("Failed to read archive: {}", err).into();
+            }
+            Err(err) => {
+                error!("Import job {} reader failed: {}", job.id, err);
+                progress.status = ImportStatus::Failed;
+                progress.error = "Internal server error.".to_string().into();
+            }
+        }
+        info!(
+            "Import job {} for account {} finished with {} messages imported and {} failed.",
+            job.id, job.account_id, progress.imported, progress.failed
+        );
+    }
+    save_job(&core, &job).await;
+    core.import_jobs.invalidate(&job.id).await;
+}
+
+async fn import_batch<T>(
+    core: &web::Data<JMAPServer<T>>,
+    session: &Session,
+    job: &ImportJob,
+    batch: Vec<(ImportItem, DocumentId)>,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    let account_id = job.account_id;
+    let count = batch.len() as u64;
+    let mut raw_messages = Vec::with_capacity(batch.len());
+    let mut items = Vec::with_capacity(batch.len());
+    for (item, mailbox_id) in batch {
+        raw_messages.push(item.raw_message);
+        items.push((mailbox_id, item.keywords, item.received_at));
+    }
+
+    // Store the messages as blobs, then import them
+    let store = core.store.clone();
+    let blob_ids = match core
+        .spawn_worker(move || {
+            let mut blob_ids = Vec::with_capacity(raw_messages.len());
+            for raw_message in raw_messages {
+                let blob_id = BlobId::new_external(&raw_message);
+                store.blob_store(&blob_id, raw_message)?;
+                store.blob_link_ephemeral(&blob_id, account_id)?;
+                blob_ids.push(blob_id);
+            }
+            Ok(blob_ids)
+        })
+        .await
+    {
+        Ok(blob_ids) => blob_ids,
+        Err(err) => {
+            error!("Import job {} failed to store messages: {}", job.id, err);
+            job.progress.lock().failed += count;
+            return;
+        }
+    };
+
+    let mut emails = VecMap::with_capacity(items.len());
+    for (pos, (blob_id, (mailbox_id, keywords, received_at))) in
+        blob_ids.into_iter().zip(items).enumerate()
+    {
+        let mut mailbox_ids = VecMap::new();
+        mailbox_ids.append(MaybeIdReference::Value(mailbox_id.into()), true);
+        emails.append(
+            format!("m{}", pos),
+            EmailImport {
+                blob_id: JMAPBlob::new(blob_id),
+                mailbox_ids: MaybeResultReference::Value(mailbox_ids).into(),
+                keywords: if !keywords.is_empty() {
+                    let mut keywords_ = VecMap::with_capacity(keywords.len());
+                    for keyword in keywords {
+                        keywords_.append(keyword, true);
+                    }
+                    keywords_.into()
+                } else {
+                    None
+                },
+                received_at: received_at.map(JMAPDate::from_timestamp),
+            },
+        );
+    }
+
+    match call_method(
+        core,
+        session,
+        method::Request::ImportEmail(EmailImportRequest {
+            acl: None,
+            account_id: JMAPId::from(account_id),
+            if_in_state: None,
+            emails,
+        }),
+    )
+    .await
+    {
+        Some(method::Response::ImportEmail(response)) => {
+            let mut progress = job.progress.lock();
+            progress.imported += response.created.map_or(0, |created| created.len() as u64);
+            if let Some(not_created) = response.not_created {
+                for (_, err) in not_created.iter() {
+                    debug!("Import job {} failed to import message: {:?}", job.id, err);
+                }
+                progress.failed += not_created.len() as u64;
+            }
+        }
+        Some(method::Response::Error(err)) => {
+            error!("Import job {} failed to import messages: {}", job.id, err);
+            job.progress.lock().failed += count;
+        }
+        _ => {
+            job.progress.lock().failed += count;
+        }
+    }
+}
+
+// Returns the mailbox for a folder, creating it along with any missing parents.
+async fn get_or_create_mailbox<T>(
+    core: &web::Data<JMAPServer<T>>,
+    session: &Session,
+    job: &ImportJob,
+    folder: &str,
+) -> Result<DocumentId, String>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let account_id = job.account_id;
+    let store = core.store.clone();
+    let name = normalize_name(folder);
+    let (parent_id, missing) = match core
+        .spawn_worker(move || {
+            let mailboxes = get_mailboxes(&store, account_id)?;
+            if let Some(mailbox) = find_mailbox(&mailboxes, &name).or_else(|| {
+                let role = folder_role(&name)?;
+                mailboxes
+                    .iter()
+                    .find(|mailbox| mailbox.role.as_deref() == Some(role))
+            }) {
+                return Ok(Err(mailbox.id));
+            }
+
+            let parts = name.split('/').collect::<Vec<_>>();
+            let mut parent_id = None;
+            for pos in 0..parts.len() {
+                if let Some(mailbox) = find_mailbox(&mailboxes, &parts[..=pos].join("/")) {
+                    parent_id = mailbox.id.into();
+                } else {
+                    return Ok(Ok((
+                        parent_id,
+                        parts[pos..]
+                            .iter()
+                            .map(|part| part.to_string())
+                            .collect::<Vec<_>>(),
+                    )));
+                }
+            }
+            Ok(Ok((parent_id, Vec::new())))
+        })
+        .await
+        .map_err(|err| err.to_string())?
+    {
+        Ok((parent_id, missing)) if !missing.is_empty() => (parent_id, missing),
+        Ok(_) => return Err("Invalid mailbox name.".to_string()),
+        Err(mailbox_id) => return Ok(mailbox_id),
+    };
+    if missing.iter().any(|part| part.is_empty()) {
+        return Err("Invalid mailbox name.".to_string());
+    }
+
+    let mut create = VecMap::with_capacity(missing.len());
+    let last_id = format!("m{}", missing.len() - 1);
+    for (pos, name) in missing.into_iter().enumerate() {
+        let mut mailbox = Mailbox::default();
+        mailbox
+            .properties
+            .append(Property::Name, Value::Text { value: name });
+        if pos > 0 {
+            mailbox.properties.append(
+                Property::ParentId,
+                Value::IdReference {
+                    value: format!("m{}", pos - 1),
+                },
+            );
+        } else if let Some(parent_id) = parent_id {
+            mailbox.properties.append(
+                Property::ParentId,
+                Value::Id {
+                    value: parent_id.into(),
+                },
+            );
+        }
+        create.append(format!("m{}", pos), mailbox);
+    }
+    let count = create.len() as u64;
+
+    match call_method(
+        core,
+        session,
+        method::Request::SetMailbox(SetRequest {
+            acl: None,
+            account_id: JMAPId::from(account_id),
+            if_in_state: None,
+            create: create.into(),
+            update: None,
+            destroy: None,
+            arguments: SetArguments::default(),
+        }),
+    )
+    .await
+    {
+        Some(method::Response::SetMailbox(response)) => {
+            if let Some((_, err)) = response.not_created.into_iter().next() {
+                return Err(err.type_.as_str().to_string());
+            }
+            job.progress.lock().mailboxes_created += count;
+            match response
+                .created
+                .get(&last_id)
+                .and_then(|mailbox| mailbox.properties.get(&Property::Id))
+            {
+                Some(Value::Id { value }) => Ok(value.get_document_id()),
+                _ => Err("Mailbox id not found.".to_string()),
+            }
+        }
+        Some(method::Response::Error(err)) => Err(err.to_string()),
+        _ => Err("Unexpected response.".to_string()),
+    }
+}
+
+async fn call_method<T>(
+    core: &web::Data<JMAPServer<T>>,
+    session: &Session,
+    request: method::Request,
+) -> Option<method::Response>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    handle_method_calls(
+        JMAPRequest {
+            using: Vec::new(),
+            method_calls: vec![method::Call {
+                id: "c".to_string(),
+                method: request,
+            }],
+            created_ids: None,
+        },
+        core.clone(),
+        session.clone(),
+    )
+    .await
+    .method_responses
+    .into_iter()
+    .next()
+    .map(|call| call.method)
+}
@@ -30,6 +30,22 @@ use jmap::{
     request::ACLEnforce,
     SUPERUSER_ID,
 };
+use jmap_calendars::{
+    calendar::{changes::JMAPCalendarChanges, get::JMAPGetCalendar, set::JMAPSetCalendar},
+    calendar_event::{
+        changes::JMAPCalendarEventChanges, get::JMAPGetCalendarEvent,
+        query::JMAPCalendarEventQuery, set::JMAPSetCalendarEvent,
+    },
+};
+use jmap_contacts::{
+    address_book::{
+        changes::JMAPAddressBookChanges, get::JMAPGetAddressBook, set::JMAPSetAddressBook,
+    },
+    contact_card::{
+        changes::JMAPContactCardChanges, copy::JMAPCopyContactCard, get::JMAPGetContactCard,
+        query::JMAPContactCardQuery, set::JMAPSetContactCard,
+    },
+};
 use jmap_mail::{
     email_submission::{
         changes::JMAPEmailSubmissionChanges, get::JMAPGetEmailSubmission,
@@ -45,13 +61,23 @@ use jmap_mail::{
         changes::JMAPMailboxChanges, get::JMAPGetMailbox, query::JMAPMailboxQuery,
         set::JMAPSetMailbox,
     },
-    thread::{changes::JMAPThreadChanges, get::JMAPGetThread},
+    saved_search::{
+        changes::JMAPSavedSearchChanges, get::JMAPGetSavedSearch, set::JMAPSetSavedSearch,
+    },
+    thread::{changes::JMAPThreadChanges, get::JMAPGetThread, query::JMAPThreadQuery},
     vacation_response::{get::JMAPGetVacationResponse, set::JMAPSetVacationResponse},
 };
-use jmap_sharing::principal::{
-    account::JMAPAccountStore, get::JMAPGetPrincipal, query::JMAPPrincipalQuery,
-    set::JMAPSetPrincipal,
+use jmap_sharing::{
+    principal::{
+        account::JMAPAccountStore, changes::JMAPPrincipalChanges, get::JMAPGetPrincipal,
+        query::JMAPPrincipalQuery, set::JMAPSetPrincipal,
+    },
+    share_notification::{
+        changes::JMAPShareNotificationChanges, get::JMAPGetShareNotification,
+        query::JMAPShareNotificationQuery, set::JMAPSetShareNotification,
+    },
 };
+use std::time::Instant;
 use store::{core::collection::Collection, tracing::error, AccountId, Store};
 
 pub async fn handle_method_calls<T>(
@@ -87,7 +113,13 @@ where
             }
 
             // Execute request
-            match handle_method_call(call_method, &core, session.account_id()).await {
+            let method_name = call_method.name();
+            let time_start = Instant::now();
+            let result = handle_method_call(call_method, &core, session.account_id()).await;
+            core.metrics
+                .record_method(method_name, time_start.elapsed(), result.is_err());
+
+            match result {
                 Ok(mut method_response) => {
                     let next_call_method = match method_response.changes() {
                         method::Changes::Item {
@@ -111,8 +143,20 @@ where
                                 }
                             }
 
+                            // Broadcast changes made to other accounts, such as share notifications
+                            for state_change in method_response.linked_state_changes() {
+                                if let Err(err) =
+                                    core.update_shared_accounts(state_change.account_id).await
+                                {
+                                    error!("Failed to update shared accounts: {}", err);
+                                }
+                                if let Err(err) = core.publish_state_change(state_change).await {
+                                    error!("Failed to publish state change: {}", err);
+                                }
+                            }
+
                             // Notify E-mail delivery service of changes
-                            match &method_response {
+                            match &mut method_response {
                                 method::Response::SetEmailSubmission(submission_response) => {
                                     if let Err(err) = core
                                         .notify_email_delivery(
@@ -144,6 +188,22 @@ where
                                         .await
                                         .ok();
                                 }
+                                method::Response::SetCalendarEvent(event_response) => {
+                                    for message in event_response.next_call().unwrap_or_default() {
+                                        if let Err(err) = core
+                                            .notify_email_delivery(
+                                                email_delivery::Event::scheduling_message(
+                                                    message.from,
+                                                    message.to,
+                                                    message.message,
+                                                ),
+                                            )
+                                            .await
+                                        {
+                                            error!("Failed to send scheduling message: {}", err);
+                                        }
+                                    }
+                                }
                                 _ => {}
                             }
 
@@ -279,6 +339,13 @@ where
                     .into();
                 method::Response::ChangesThread(store.thread_changes(request)?)
             }
+            method::Request::QueryThread(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(request.account_id.get_document_id(), Collection::Mail)?
+                    .into();
+                method::Response::QueryThread(store.thread_query(request)?)
+            }
             method::Request::GetEmail(mut request) => {
                 request.acl = store
                     .get_acl_token(account_id)?
@@ -346,7 +413,7 @@ where
             method::Request::GetIdentity(mut request) => {
                 request.acl = store
                     .get_acl_token(account_id)?
-                    .assert_is_member(request.account_id.get_document_id())?
+                    .assert_has_access(request.account_id.get_document_id(), Collection::Identity)?
                     .into();
                 method::Response::GetIdentity(store.identity_get(request)?)
             }
@@ -397,7 +464,10 @@ where
             method::Request::SetEmailSubmission(mut request) => {
                 request.acl = store
                     .get_acl_token(account_id)?
-                    .assert_is_member(request.account_id.get_document_id())?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::EmailSubmission,
+                    )?
                     .into();
                 method::Response::SetEmailSubmission(store.email_submission_set(request)?)
             }
@@ -415,6 +485,196 @@ where
                     .into();
                 method::Response::SetVacationResponse(store.vacation_response_set(request)?)
             }
+            method::Request::GetSavedSearch(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_is_member(request.account_id.get_document_id())?
+                    .into();
+                method::Response::GetSavedSearch(store.saved_search_get(request)?)
+            }
+            method::Request::ChangesSavedSearch(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_is_member(request.account_id.get_document_id())?
+                    .into();
+                method::Response::ChangesSavedSearch(store.saved_search_changes(request)?)
+            }
+            method::Request::SetSavedSearch(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_is_member(request.account_id.get_document_id())?
+                    .into();
+                method::Response::SetSavedSearch(store.saved_search_set(request)?)
+            }
+            method::Request::GetAddressBook(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::AddressBook,
+                    )?
+                    .into();
+                method::Response::GetAddressBook(store.address_book_get(request)?)
+            }
+            method::Request::ChangesAddressBook(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::AddressBook,
+                    )?
+                    .into();
+                method::Response::ChangesAddressBook(store.address_book_changes(request)?)
+            }
+            method::Request::SetAddressBook(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::AddressBook,
+                    )?
+                    .into();
+                method::Response::SetAddressBook(store.address_book_set(request)?)
+            }
+            method::Request::GetContactCard(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::ContactCard,
+                    )?
+                    .into();
+                method::Response::GetContactCard(store.contact_card_get(request)?)
+            }
+            method::Request::ChangesContactCard(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::ContactCard,
+                    )?
+                    .into();
+                method::Response::ChangesContactCard(store.contact_card_changes(request)?)
+            }
+            method::Request::QueryContactCard(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::ContactCard,
+                    )?
+                    .into();
+                method::Response::QueryContactCard(store.contact_card_query(request)?)
+            }
+            method::Request::QueryChangesContactCard(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::ContactCard,
+                    )?
+                    .into();
+                method::Response::QueryChangesContactCard(
+                    store.contact_card_query_changes(request)?,
+                )
+            }
+            method::Request::SetContactCard(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::ContactCard,
+                    )?
+                    .into();
+                method::Response::SetContactCard(store.contact_card_set(request)?)
+            }
+            method::Request::CopyContactCard(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::AddressBook,
+                    )?
+                    .assert_has_access(
+                        request.from_account_id.get_document_id(),
+                        Collection::ContactCard,
+                    )?
+                    .into();
+                method::Response::CopyContactCard(store.contact_card_copy(request)?)
+            }
+            method::Request::GetCalendar(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(request.account_id.get_document_id(), Collection::Calendar)?
+                    .into();
+                method::Response::GetCalendar(store.calendar_get(request)?)
+            }
+            method::Request::ChangesCalendar(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(request.account_id.get_document_id(), Collection::Calendar)?
+                    .into();
+                method::Response::ChangesCalendar(store.calendar_changes(request)?)
+            }
+            method::Request::SetCalendar(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(request.account_id.get_document_id(), Collection::Calendar)?
+                    .into();
+                method::Response::SetCalendar(store.calendar_set(request)?)
+            }
+            method::Request::GetCalendarEvent(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::CalendarEvent,
+                    )?
+                    .into();
+                method::Response::GetCalendarEvent(store.calendar_event_get(request)?)
+            }
+            method::Request::ChangesCalendarEvent(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::CalendarEvent,
+                    )?
+                    .into();
+                method::Response::ChangesCalendarEvent(store.calendar_event_changes(request)?)
+            }
+            method::Request::QueryCalendarEvent(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::CalendarEvent,
+                    )?
+                    .into();
+                method::Response::QueryCalendarEvent(store.calendar_event_query(request)?)
+            }
+            method::Request::QueryChangesCalendarEvent(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::CalendarEvent,
+                    )?
+                    .into();
+                method::Response::QueryChangesCalendarEvent(
+                    store.calendar_event_query_changes(request)?,
+                )
+            }
+            method::Request::SetCalendarEvent(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_has_access(
+                        request.account_id.get_document_id(),
+                        Collection::CalendarEvent,
+                    )?
+                    .into();
+                method::Response::SetCalendarEvent(store.calendar_event_set(request)?)
+            }
             method::Request::GetPrincipal(mut request) => {
                 request.acl = store
                     .get_acl_token(account_id)?
@@ -429,6 +689,20 @@ where
                     .into();
                 method::Response::QueryPrincipal(store.principal_query(request)?)
             }
+            method::Request::ChangesPrincipal(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_is_member(SUPERUSER_ID)?
+                    .into();
+                method::Response::ChangesPrincipal(store.principal_changes(request)?)
+            }
+            method::Request::QueryChangesPrincipal(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_is_member(SUPERUSER_ID)?
+                    .into();
+                method::Response::QueryChangesPrincipal(store.principal_query_changes(request)?)
+            }
             method::Request::SetPrincipal(mut request) => {
                 request.acl = store
                     .get_acl_token(account_id)?
@@ -436,6 +710,45 @@ where
                     .into();
                 method::Response::SetPrincipal(store.principal_set(request)?)
             }
+            method::Request::GetShareNotification(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_is_member(request.account_id.get_document_id())?
+                    .into();
+                method::Response::GetShareNotification(store.share_notification_get(request)?)
+            }
+            method::Request::ChangesShareNotification(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_is_member(request.account_id.get_document_id())?
+                    .into();
+                method::Response::ChangesShareNotification(
+                    store.share_notification_changes(request)?,
+                )
+            }
+            method::Request::QueryShareNotification(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_is_member(request.account_id.get_document_id())?
+                    .into();
+                method::Response::QueryShareNotification(store.share_notification_query(request)?)
+            }
+            method::Request::QueryChangesShareNotification(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_is_member(request.account_id.get_document_id())?
+                    .into();
+                method::Response::QueryChangesShareNotification(
+                    store.share_notification_query_changes(request)?,
+                )
+            }
+            method::Request::SetShareNotification(mut request) => {
+                request.acl = store
+                    .get_acl_token(account_id)?
+                    .assert_is_member(request.account_id.get_document_id())?
+                    .into();
+                method::Response::SetShareNotification(store.share_notification_set(request)?)
+            }
             method::Request::Echo(payload) => method::Response::Echo(payload),
             method::Request::Error(err) => return Err(err),
         })
@@ -37,10 +37,13 @@ use jmap::{
         set::{SetRequest, SetResponse},
         Method, ResultReference,
     },
+    share_notification::schema::ShareNotification,
     types::jmap::JMAPId,
     types::{json_pointer::JSONPointerEval, type_state::TypeState},
 };
 
+use jmap_calendars::{calendar::schema::Calendar, calendar_event::schema::CalendarEvent};
+use jmap_contacts::{address_book::schema::AddressBook, contact_card::schema::ContactCard};
 use jmap_mail::{
     email_submission::schema::EmailSubmission,
     identity::schema::Identity,
@@ -51,6 +54,7 @@ use jmap_mail::{
         search_snippet::{SearchSnippetGetRequest, SearchSnippetGetResponse},
     },
     mailbox::schema::Mailbox,
+    saved_search::schema::SavedSearch,
     thread::schema::Thread,
     vacation_response::schema::VacationResponse,
 };
@@ -100,6 +104,7 @@ pub enum Request {
     // Thread
     GetThread(GetRequest<Thread>),
     ChangesThread(ChangesRequest),
+    QueryThread(QueryRequest<Thread>),
 
     // Email
     GetEmail(GetRequest<Email>),
@@ -128,11 +133,50 @@ pub enum Request {
     GetVacationResponse(GetRequest<VacationResponse>),
     SetVacationResponse(SetRequest<VacationResponse>),
 
+    // Saved Search
+    GetSavedSearch(GetRequest<SavedSearch>),
+    ChangesSavedSearch(ChangesRequest),
+    SetSavedSearch(SetRequest<SavedSearch>),
+
+    // Address Book
+    GetAddressBook(GetRequest<AddressBook>),
+    ChangesAddressBook(ChangesRequest),
+    SetAddressBook(SetRequest<AddressBook>),
+
+    // Contact Card
+    GetContactCard(GetRequest<ContactCard>),
+    ChangesContactCard(ChangesRequest),
+    QueryContactCard(QueryRequest<ContactCard>),
+    QueryChangesContactCard(QueryChangesRequest<ContactCard>),
+    SetContactCard(SetRequest<ContactCard>),
+    CopyContactCard(CopyRequest<ContactCard>),
+
+    // Calendar
+    GetCalendar(GetRequest<Calendar>),
+    ChangesCalendar(ChangesRequest),
+    SetCalendar(SetRequest<Calendar>),
+
+    // Calendar Event
+    GetCalendarEvent(GetRequest<CalendarEvent>),
+    ChangesCalendarEvent(ChangesRequest),
+    QueryCalendarEvent(QueryRequest<CalendarEvent>),
+    QueryChangesCalendarEvent(QueryChangesRequest<CalendarEvent>),
+    SetCalendarEvent(SetRequest<CalendarEvent>),
+
     // Principal
     GetPrincipal(GetRequest<Principal>),
+    ChangesPrincipal(ChangesRequest),
     QueryPrincipal(QueryRequest<Principal>),
+    QueryChangesPrincipal(QueryChangesRequest<Principal>),
     SetPrincipal(SetRequest<Principal>),
 
+    // Share Notification
+    GetShareNotification(GetRequest<ShareNotification>),
+    ChangesShareNotification(ChangesRequest),
+    QueryShareNotification(QueryRequest<ShareNotification>),
+    QueryChangesShareNotification(QueryChangesRequest<ShareNotification>),
+    SetShareNotification(SetRequest<ShareNotification>),
+
     // Core methods
     CopyBlob(CopyBlobRequest),
     Echo(serde_json::Value),
@@ -155,6 +199,7 @@ pub enum Response {
     // Thread
     GetThread(GetResponse<Thread>),
     ChangesThread(ChangesResponse<Thread>),
+    QueryThread(QueryResponse),
 
     // Email
     GetEmail(GetResponse<Email>),
@@ -183,11 +228,50 @@ pub enum Response {
     GetVacationResponse(GetResponse<VacationResponse>),
     SetVacationResponse(SetResponse<VacationResponse>),
 
+    // Saved Search
+    GetSavedSearch(GetResponse<SavedSearch>),
+    ChangesSavedSearch(ChangesResponse<SavedSearch>),
+    SetSavedSearch(SetResponse<SavedSearch>),
+
+    // Address Book
+    GetAddressBook(GetResponse<AddressBook>),
+    ChangesAddressBook(ChangesResponse<AddressBook>),
+    SetAddressBook(SetResponse<AddressBook>),
+
+    // Contact Card
+    GetContactCard(GetResponse<ContactCard>),
+    ChangesContactCard(ChangesResponse<ContactCard>),
+    QueryContactCard(QueryResponse),
+    QueryChangesContactCard(QueryChangesResponse),
+    SetContactCard(SetResponse<ContactCard>),
+    CopyContactCard(CopyResponse<ContactCard>),
+
+    // Calendar
+    GetCalendar(GetResponse<Calendar>),
+    ChangesCalendar(ChangesResponse<Calendar>),
+    SetCalendar(SetResponse<Calendar>),
+
+    // Calendar Event
+    GetCalendarEvent(GetResponse<CalendarEvent>),
+    ChangesCalendarEvent(ChangesResponse<CalendarEvent>),
+    QueryCalendarEvent(QueryResponse),
+    QueryChangesCalendarEvent(QueryChangesResponse),
+    SetCalendarEvent(SetResponse<CalendarEvent>),
+
     // Principal
     GetPrincipal(GetResponse<Principal>),
+    ChangesPrincipal(ChangesResponse<Principal>),
     QueryPrincipal(QueryResponse),
+    QueryChangesPrincipal(QueryChangesResponse),
     SetPrincipal(SetResponse<Principal>),
 
+    // Share Notification
+    GetShareNotification(GetResponse<ShareNotification>),
+    ChangesShareNotification(ChangesResponse<ShareNotification>),
+    QueryShareNotification(QueryResponse),
+    QueryChangesShareNotification(QueryChangesResponse),
+    SetShareNotification(SetResponse<ShareNotification>),
+
     // Core methods
     CopyBlob(CopyBlobResponse),
     Echo(serde_json::Value),
@@ -204,6 +288,7 @@ impl Request {
             | Request::QueryChangesMailbox(_)
             | Request::GetThread(_)
             | Request::ChangesThread(_)
+            | Request::QueryThread(_)
             | Request::GetEmail(_)
             | Request::ChangesEmail(_)
             | Request::QueryEmail(_)
@@ -217,8 +302,28 @@ impl Request {
             | Request::QueryEmailSubmission(_)
             | Request::QueryChangesEmailSubmission(_)
             | Request::GetVacationResponse(_)
+            | Request::GetSavedSearch(_)
+            | Request::ChangesSavedSearch(_)
+            | Request::GetAddressBook(_)
+            | Request::ChangesAddressBook(_)
+            | Request::GetContactCard(_)
+            | Request::ChangesContactCard(_)
+            | Request::QueryContactCard(_)
+            | Request::QueryChangesContactCard(_)
+            | Request::GetCalendar(_)
+            | Request::ChangesCalendar(_)
+            | Request::GetCalendarEvent(_)
+            | Request::ChangesCalendarEvent(_)
+            | Request::QueryCalendarEvent(_)
+            | Request::QueryChangesCalendarEvent(_)
             | Request::GetPrincipal(_)
+            | Request::ChangesPrincipal(_)
             | Request::QueryPrincipal(_)
+            | Request::QueryChangesPrincipal(_)
+            | Request::GetShareNotification(_)
+            | Request::ChangesShareNotification(_)
+            | Request::QueryShareNotification(_)
+            | Request::QueryChangesShareNotification(_)
             | Request::Echo(_)
             | Request::Error(_) => true,
 
@@ -230,11 +335,85 @@ impl Request {
             | Request::SetIdentity(_)
             | Request::SetEmailSubmission(_)
             | Request::SetVacationResponse(_)
+            | Request::SetSavedSearch(_)
+            | Request::SetAddressBook(_)
+            | Request::SetContactCard(_)
+            | Request::CopyContactCard(_)
+            | Request::SetCalendar(_)
+            | Request::SetCalendarEvent(_)
             | Request::SetPrincipal(_)
+            | Request::SetShareNotification(_)
             | Request::CopyBlob(_) => false,
         }
     }
 
+    pub fn name(&self) -> &'static str {
+        match self {
+            Request::GetPushSubscription(_) => "PushSubscription/get",
+            Request::SetPushSubscription(_) => "PushSubscription/set",
+            Request::GetMailbox(_) => "Mailbox/get",
+            Request::ChangesMailbox(_) => "Mailbox/changes",
+            Request::QueryMailbox(_) => "Mailbox/query",
+            Request::QueryChangesMailbox(_) => "Mailbox/queryChanges",
+            Request::SetMailbox(_) => "Mailbox/set",
+            Request::GetThread(_) => "Thread/get",
+            Request::ChangesThread(_) => "Thread/changes",
+            Request::QueryThread(_) => "Thread/query",
+            Request::GetEmail(_) => "Email/get",
+            Request::ChangesEmail(_) => "Email/changes",
+            Request::QueryEmail(_) => "Email/query",
+            Request::QueryChangesEmail(_) => "Email/queryChanges",
+            Request::SetEmail(_) => "Email/set",
+            Request::CopyEmail(_) => "Email/copy",
+            Request::ImportEmail(_) => "Email/import",
+            Request::ParseEmail(_) => "Email/parse",
+            Request::GetSearchSnippet(_) => "SearchSnippet/get",
+            Request::GetIdentity(_) => "Identity/get",
+            Request::ChangesIdentity(_) => "Identity/changes",
+            Request::SetIdentity(_) => "Identity/set",
+            Request::GetEmailSubmission(_) => "EmailSubmission/get",
+            Request::ChangesEmailSubmission(_) => "EmailSubmission/changes",
+            Request::QueryEmailSubmission(_) => "EmailSubmission/query",
+            Request::QueryChangesEmailSubmission(_) => "EmailSubmission/queryChanges",
+            Request::SetEmailSubmission(_) => "EmailSubmission/set",
+            Request::GetVacationResponse(_) => "VacationResponse/get",
+            Request::SetVacationResponse(_) => "VacationResponse/set",
+            Request::GetSavedSearch(_) => "SavedSearch/get",
+            Request::ChangesSavedSearch(_) => "SavedSearch/changes",
+            Request::SetSavedSearch(_) => "SavedSearch/set",
+            Request::GetAddressBook(_) => "AddressBook/get",
+            Request::ChangesAddressBook(_) => "AddressBook/changes",
+            Request::SetAddressBook(_) => "AddressBook/set",
+            Request::GetContactCard(_) => "ContactCard/get",
+            Request::ChangesContactCard(_) => "ContactCard/changes",
+            Request::QueryContactCard(_) => "ContactCard/query",
+            Request::QueryChangesContactCard(_) => "ContactCard/queryChanges",
+            Request::SetContactCard(_) => "ContactCard/set",
+            Request::CopyContactCard(_) => "ContactCard/copy",
+            Request::GetCalendar(_) => "Calendar/get",
+            Request::ChangesCalendar(_) => "Calendar/changes",
+            Request::SetCalendar(_) => "Calendar/set",
+            Request::GetCalendarEvent(_) => "CalendarEvent/get",
+            Request::ChangesCalendarEvent(_) => "CalendarEvent/changes",
+            Request::QueryCalendarEvent(_) => "CalendarEvent/query",
+            Request::QueryChangesCalendarEvent(_) => "CalendarEvent/queryChanges",
+            Request::SetCalendarEvent(_) => "CalendarEvent/set",
+            Request::GetPrincipal(_) => "Principal/get",
+            Request::ChangesPrincipal(_) => "Principal/changes",
+            Request::QueryPrincipal(_) => "Principal/query",
+            Request::QueryChangesPrincipal(_) => "Principal/queryChanges",
+            Request::SetPrincipal(_) => "Principal/set",
+            Request::GetShareNotification(_) => "ShareNotification/get",
+            Request::ChangesShareNotification(_) => "ShareNotification/changes",
+            Request::QueryShareNotification(_) => "ShareNotification/query",
+            Request::QueryChangesShareNotification(_) => "ShareNotification/queryChanges",
+            Request::SetShareNotification(_) => "ShareNotification/set",
+            Request::CopyBlob(_) => "Blob/copy",
+            Request::Echo(_) => "Core/echo",
+            Request::Error(_) => "error",
+        }
+    }
+
     pub fn prepare_request(&mut self, response: &response::Response) -> jmap::Result<()> {
         // Create JSON Pointer evaluation function
         let mut eval_result_ref = |rr: &ResultReference| -> Option<Vec<u64>> {
@@ -259,6 +438,9 @@ impl Request {
                         (Method::ChangesThread, Response::ChangesThread(response)) => {
                             return response.eval_json_pointer(&rr.path);
                         }
+                        (Method::QueryThread, Response::QueryThread(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
                         (Method::GetEmail, Response::GetEmail(response)) => {
                             return response.eval_json_pointer(&rr.path);
                         }
@@ -298,12 +480,96 @@ impl Request {
                         ) => {
                             return response.eval_json_pointer(&rr.path);
                         }
+                        (Method::GetSavedSearch, Response::GetSavedSearch(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (Method::ChangesSavedSearch, Response::ChangesSavedSearch(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (Method::GetAddressBook, Response::GetAddressBook(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (Method::ChangesAddressBook, Response::ChangesAddressBook(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (Method::GetContactCard, Response::GetContactCard(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (Method::ChangesContactCard, Response::ChangesContactCard(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (Method::QueryContactCard, Response::QueryContactCard(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (
+                            Method::QueryChangesContactCard,
+                            Response::QueryChangesContactCard(response),
+                        ) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (Method::GetCalendar, Response::GetCalendar(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (Method::ChangesCalendar, Response::ChangesCalendar(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (Method::GetCalendarEvent, Response::GetCalendarEvent(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (
+                            Method::ChangesCalendarEvent,
+                            Response::ChangesCalendarEvent(response),
+                        ) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (Method::QueryCalendarEvent, Response::QueryCalendarEvent(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (
+                            Method::QueryChangesCalendarEvent,
+                            Response::QueryChangesCalendarEvent(response),
+                        ) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
                         (Method::GetPrincipal, Response::GetPrincipal(response)) => {
                             return response.eval_json_pointer(&rr.path);
                         }
+                        (Method::ChangesPrincipal, Response::ChangesPrincipal(response)) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
                         (Method::QueryPrincipal, Response::QueryPrincipal(response)) => {
                             return response.eval_json_pointer(&rr.path);
                         }
+                        (
+                            Method::QueryChangesPrincipal,
+                            Response::QueryChangesPrincipal(response),
+                        ) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (
+                            Method::GetShareNotification,
+                            Response::GetShareNotification(response),
+                        ) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (
+                            Method::ChangesShareNotification,
+                            Response::ChangesShareNotification(response),
+                        ) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (
+                            Method::QueryShareNotification,
+                            Response::QueryShareNotification(response),
+                        ) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
+                        (
+                            Method::QueryChangesShareNotification,
+                            Response::QueryChangesShareNotification(response),
+                        ) => {
+                            return response.eval_json_pointer(&rr.path);
+                        }
                         _ => {
                             break;
                         }
@@ -351,12 +617,51 @@ impl Request {
             Request::SetEmailSubmission(request) => {
                 request.eval_references(&mut eval_result_ref, &response.created_ids)?;
             }
+            Request::GetSavedSearch(request) => {
+                request.eval_result_references(&mut eval_result_ref)?;
+            }
+            Request::SetSavedSearch(request) => {
+                request.eval_references(&mut eval_result_ref, &response.created_ids)?;
+            }
+            Request::GetAddressBook(request) => {
+                request.eval_result_references(&mut eval_result_ref)?;
+            }
+            Request::SetAddressBook(request) => {
+                request.eval_references(&mut eval_result_ref, &response.created_ids)?;
+            }
+            Request::GetContactCard(request) => {
+                request.eval_result_references(&mut eval_result_ref)?;
+            }
+            Request::SetContactCard(request) => {
+                request.eval_references(&mut eval_result_ref, &response.created_ids)?;
+            }
+            Request::CopyContactCard(request) => {
+                request.eval_references(&mut eval_result_ref, &response.created_ids)?;
+            }
+            Request::GetCalendar(request) => {
+                request.eval_result_references(&mut eval_result_ref)?;
+            }
+            Request::SetCalendar(request) => {
+                request.eval_references(&mut eval_result_ref, &response.created_ids)?;
+            }
+            Request::GetCalendarEvent(request) => {
+                request.eval_result_references(&mut eval_result_ref)?;
+            }
+            Request::SetCalendarEvent(request) => {
+                request.eval_references(&mut eval_result_ref, &response.created_ids)?;
+            }
             Request::GetPrincipal(request) => {
                 request.eval_result_references(&mut eval_result_ref)?;
             }
             Request::SetPrincipal(request) => {
                 request.eval_references(&mut eval_result_ref, &response.created_ids)?;
             }
+            Request::GetShareNotification(request) => {
+                request.eval_result_references(&mut eval_result_ref)?;
+            }
+            Request::SetShareNotification(request) => {
+                request.eval_references(&mut eval_result_ref, &response.created_ids)?;
+            }
             _ => (),
         }
         Ok(())
@@ -442,6 +747,90 @@ impl Response {
                     Changes::None
                 }
             }
+            Response::SetSavedSearch(response) => {
+                if let Some(change_id) = response.has_changes() {
+                    Changes::Item {
+                        created_ids: response.created_ids(),
+                        change_id,
+                        state_change: response
+                            .state_changes()
+                            .map(|s| StateChange::new(response.account_id(), s)),
+                        next_call: None,
+                    }
+                } else {
+                    Changes::None
+                }
+            }
+            Response::SetAddressBook(response) => {
+                if let Some(change_id) = response.has_changes() {
+                    Changes::Item {
+                        created_ids: response.created_ids(),
+                        change_id,
+                        state_change: response
+                            .state_changes()
+                            .map(|s| StateChange::new(response.account_id(), s)),
+                        next_call: None,
+                    }
+                } else {
+                    Changes::None
+                }
+            }
+            Response::SetContactCard(response) => {
+                if let Some(change_id) = response.has_changes() {
+                    Changes::Item {
+                        created_ids: response.created_ids(),
+                        change_id,
+                        state_change: response
+                            .state_changes()
+                            .map(|s| StateChange::new(response.account_id(), s)),
+                        next_call: None,
+                    }
+                } else {
+                    Changes::None
+                }
+            }
+            Response::CopyContactCard(response) => {
+                if let Some(change_id) = response.has_changes() {
+                    Changes::Item {
+                        created_ids: response.created_ids(),
+                        change_id,
+                        state_change: response
+                            .state_changes()
+                            .map(|s| StateChange::new(response.account_id(), s)),
+                        next_call: response.next_call.take().map(Request::SetContactCard),
+                    }
+                } else {
+                    Changes::None
+                }
+            }
+            Response::SetCalendar(response) => {
+                if let Some(change_id) = response.has_changes() {
+                    Changes::Item {
+                        created_ids: response.created_ids(),
+                        change_id,
+                        state_change: response
+                            .state_changes()
+                            .map(|s| StateChange::new(response.account_id(), s)),
+                        next_call: None,
+                    }
+                } else {
+                    Changes::None
+                }
+            }
+            Response::SetCalendarEvent(response) => {
+                if let Some(change_id) = response.has_changes() {
+                    Changes::Item {
+                        created_ids: response.created_ids(),
+                        change_id,
+                        state_change: response
+                            .state_changes()
+                            .map(|s| StateChange::new(response.account_id(), s)),
+                        next_call: None,
+                    }
+                } else {
+                    Changes::None
+                }
+            }
             Response::SetEmailSubmission(response) => {
                 if let Some(change_id) = response.has_changes() {
                     Changes::Item {
@@ -491,7 +880,23 @@ impl Response {
                     Changes::Item {
                         created_ids: None,
                         change_id,
-                        state_change: None,
+                        state_change: response
+                            .state_changes()
+                            .map(|s| StateChange::new(response.account_id(), s)),
+                        next_call: None,
+                    }
+                } else {
+                    Changes::None
+                }
+            }
+            Response::SetShareNotification(response) => {
+                if let Some(change_id) = response.has_changes() {
+                    Changes::Item {
+                        created_ids: None,
+                        change_id,
+                        state_change: response
+                            .state_changes()
+                            .map(|s| StateChange::new(response.account_id(), s)),
                         next_call: None,
                     }
                 } else {
@@ -504,6 +909,7 @@ impl Response {
             | Response::QueryChangesMailbox(_)
             | Response::GetThread(_)
             | Response::ChangesThread(_)
+            | Response::QueryThread(_)
             | Response::GetEmail(_)
             | Response::ChangesEmail(_)
             | Response::QueryEmail(_)
@@ -517,13 +923,46 @@ impl Response {
             | Response::QueryEmailSubmission(_)
             | Response::QueryChangesEmailSubmission(_)
             | Response::GetVacationResponse(_)
+            | Response::GetSavedSearch(_)
+            | Response::ChangesSavedSearch(_)
+            | Response::GetAddressBook(_)
+            | Response::ChangesAddressBook(_)
+            | Response::GetContactCard(_)
+            | Response::ChangesContactCard(_)
+            | Response::QueryContactCard(_)
+            | Response::QueryChangesContactCard(_)
+            | Response::GetCalendar(_)
+            | Response::ChangesCalendar(_)
+            | Response::GetCalendarEvent(_)
+            | Response::ChangesCalendarEvent(_)
+            | Response::QueryCalendarEvent(_)
+            | Response::QueryChangesCalendarEvent(_)
             | Response::GetPrincipal(_)
+            | Response::ChangesPrincipal(_)
             | Response::QueryPrincipal(_)
+            | Response::QueryChangesPrincipal(_)
+            | Response::GetShareNotification(_)
+            | Response::ChangesShareNotification(_)
+            | Response::QueryShareNotification(_)
+            | Response::QueryChangesShareNotification(_)
             | Response::CopyBlob(_)
             | Response::Echo(_)
             | Response::Error(_) => Changes::None,
         }
     }
+
+    pub fn linked_state_changes(&mut self) -> Vec<StateChange> {
+        match self {
+            Response::SetMailbox(response) => response.linked_state_changes(),
+            Response::SetAddressBook(response) => response.linked_state_changes(),
+            Response::SetCalendar(response) => response.linked_state_changes(),
+            Response::SetPrincipal(response) => response.linked_state_changes(),
+            _ => Vec::new(),
+        }
+        .into_iter()
+        .map(|(account_id, types)| StateChange::new(account_id, types))
+        .collect()
+    }
 }
 
 impl<'de> Deserialize<'de> for Call<Request> {
@@ -658,6 +1097,11 @@ where
                 .map_err(|err| MatchError::Parse(err.to_string()))?
                 .ok_or(MatchError::Eof)?,
         ),
+        "Thread/query" => Request::QueryThread(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
         "SearchSnippet/get" => Request::GetSearchSnippet(
             seq.next_element()
                 .map_err(|err| MatchError::Parse(err.to_string()))?
@@ -713,6 +1157,106 @@ where
                 .map_err(|err| MatchError::Parse(err.to_string()))?
                 .ok_or(MatchError::Eof)?,
         ),
+        "SavedSearch/get" => Request::GetSavedSearch(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "SavedSearch/changes" => Request::ChangesSavedSearch(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "SavedSearch/set" => Request::SetSavedSearch(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "AddressBook/get" => Request::GetAddressBook(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "AddressBook/changes" => Request::ChangesAddressBook(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "AddressBook/set" => Request::SetAddressBook(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "ContactCard/get" => Request::GetContactCard(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "ContactCard/changes" => Request::ChangesContactCard(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "ContactCard/query" => Request::QueryContactCard(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "ContactCard/queryChanges" => Request::QueryChangesContactCard(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "ContactCard/set" => Request::SetContactCard(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "ContactCard/copy" => Request::CopyContactCard(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "Calendar/get" => Request::GetCalendar(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "Calendar/changes" => Request::ChangesCalendar(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "Calendar/set" => Request::SetCalendar(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "CalendarEvent/get" => Request::GetCalendarEvent(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "CalendarEvent/changes" => Request::ChangesCalendarEvent(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "CalendarEvent/query" => Request::QueryCalendarEvent(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "CalendarEvent/queryChanges" => Request::QueryChangesCalendarEvent(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "CalendarEvent/set" => Request::SetCalendarEvent(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
         "PushSubscription/get" => Request::GetPushSubscription(
             seq.next_element()
                 .map_err(|err| MatchError::Parse(err.to_string()))?
@@ -738,6 +1282,41 @@ where
                 .map_err(|err| MatchError::Parse(err.to_string()))?
                 .ok_or(MatchError::Eof)?,
         ),
+        "Principal/changes" => Request::ChangesPrincipal(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "Principal/queryChanges" => Request::QueryChangesPrincipal(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "ShareNotification/get" => Request::GetShareNotification(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "ShareNotification/changes" => Request::ChangesShareNotification(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "ShareNotification/query" => Request::QueryShareNotification(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "ShareNotification/queryChanges" => Request::QueryChangesShareNotification(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
+        "ShareNotification/set" => Request::SetShareNotification(
+            seq.next_element()
+                .map_err(|err| MatchError::Parse(err.to_string()))?
+                .ok_or(MatchError::Eof)?,
+        ),
         "Blob/copy" => Request::CopyBlob(
             seq.next_element()
                 .map_err(|err| MatchError::Parse(err.to_string()))?
@@ -801,6 +1380,10 @@ impl Serialize for Call<Response> {
                 seq.serialize_element("Thread/changes")?;
                 seq.serialize_element(response)?;
             }
+            Response::QueryThread(response) => {
+                seq.serialize_element("Thread/query")?;
+                seq.serialize_element(response)?;
+            }
             Response::GetEmail(response) => {
                 seq.serialize_element("Email/get")?;
                 seq.serialize_element(response)?;
@@ -877,6 +1460,86 @@ impl Serialize for Call<Response> {
                 seq.serialize_element("VacationResponse/set")?;
                 seq.serialize_element(response)?;
             }
+            Response::GetSavedSearch(response) => {
+                seq.serialize_element("SavedSearch/get")?;
+                seq.serialize_element(response)?;
+            }
+            Response::ChangesSavedSearch(response) => {
+                seq.serialize_element("SavedSearch/changes")?;
+                seq.serialize_element(response)?;
+            }
+            Response::SetSavedSearch(response) => {
+                seq.serialize_element("SavedSearch/set")?;
+                seq.serialize_element(response)?;
+            }
+            Response::GetAddressBook(response) => {
+                seq.serialize_element("AddressBook/get")?;
+                seq.serialize_element(response)?;
+            }
+            Response::ChangesAddressBook(response) => {
+                seq.serialize_element("AddressBook/changes")?;
+                seq.serialize_element(response)?;
+            }
+            Response::SetAddressBook(response) => {
+                seq.serialize_element("AddressBook/set")?;
+                seq.serialize_element(response)?;
+            }
+            Response::GetContactCard(response) => {
+                seq.serialize_element("ContactCard/get")?;
+                seq.serialize_element(response)?;
+            }
+            Response::ChangesContactCard(response) => {
+                seq.serialize_element("ContactCard/changes")?;
+                seq.serialize_element(response)?;
+            }
+            Response::QueryContactCard(response) => {
+                seq.serialize_element("ContactCard/query")?;
+                seq.serialize_element(response)?;
+            }
+            Response::QueryChangesContactCard(response) => {
+                seq.serialize_element("ContactCard/queryChanges")?;
+                seq.serialize_element(response)?;
+            }
+            Response::SetContactCard(response) => {
+                seq.serialize_element("ContactCard/set")?;
+                seq.serialize_element(response)?;
+            }
+            Response::CopyContactCard(response) => {
+                seq.serialize_element("ContactCard/copy")?;
+                seq.serialize_element(response)?;
+            }
+            Response::GetCalendar(response) => {
+                seq.serialize_element("Calendar/get")?;
+                seq.serialize_element(response)?;
+            }
+            Response::ChangesCalendar(response) => {
+                seq.serialize_element("Calendar/changes")?;
+                seq.serialize_element(response)?;
+            }
+            Response::SetCalendar(response) => {
+                seq.serialize_element("Calendar/set")?;
+                seq.serialize_element(response)?;
+            }
+            Response::GetCalendarEvent(response) => {
+                seq.serialize_element("CalendarEvent/get")?;
+                seq.serialize_element(response)?;
+            }
+            Response::ChangesCalendarEvent(response) => {
+                seq.serialize_element("CalendarEvent/changes")?;
+                seq.serialize_element(response)?;
+            }
+            Response::QueryCalendarEvent(response) => {
+                seq.serialize_element("CalendarEvent/query")?;
+                seq.serialize_element(response)?;
+            }
+            Response::QueryChangesCalendarEvent(response) => {
+                seq.serialize_element("CalendarEvent/queryChanges")?;
+                seq.serialize_element(response)?;
+            }
+            Response::SetCalendarEvent(response) => {
+                seq.serialize_element("CalendarEvent/set")?;
+                seq.serialize_element(response)?;
+            }
             Response::GetPrincipal(response) => {
                 seq.serialize_element("Principal/get")?;
                 seq.serialize_element(response)?;
@@ -889,6 +1552,34 @@ impl Serialize for Call<Response> {
                 seq.serialize_element("Principal/set")?;
                 seq.serialize_element(response)?;
             }
+            Response::ChangesPrincipal(response) => {
+                seq.serialize_element("Principal/changes")?;
+                seq.serialize_element(response)?;
+            }
+            Response::QueryChangesPrincipal(response) => {
+                seq.serialize_element("Principal/queryChanges")?;
+                seq.serialize_element(response)?;
+            }
+            Response::GetShareNotification(response) => {
+                seq.serialize_element("ShareNotification/get")?;
+                seq.serialize_element(response)?;
+            }
+            Response::ChangesShareNotification(response) => {
+                seq.serialize_element("ShareNotification/changes")?;
+                seq.serialize_element(response)?;
+            }
+            Response::QueryShareNotification(response) => {
+                seq.serialize_element("ShareNotification/query")?;
+                seq.serialize_element(response)?;
+            }
+            Response::QueryChangesShareNotification(response) => {
+                seq.serialize_element("ShareNotification/queryChanges")?;
+                seq.serialize_element(response)?;
+            }
+            Response::SetShareNotification(response) => {
+                seq.serialize_element("ShareNotification/set")?;
+                seq.serialize_element(response)?;
+            }
             Response::CopyBlob(response) => {
                 seq.serialize_element("Blob/copy")?;
                 seq.serialize_element(response)?;
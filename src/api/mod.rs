@@ -21,15 +21,20 @@
  * for more details.
 */
 
-use actix_web::error;
+use actix_web::error::{self, ResponseError};
 use actix_web::http::header;
 use actix_web::{http::StatusCode, HttpResponse};
 use jmap::types::{jmap::JMAPId, state::JMAPState, type_state::TypeState};
 use std::borrow::Cow;
 use std::fmt::Display;
 use store::core::vec_map::VecMap;
+use store::{tracing::debug, Store};
+
+use crate::JMAPServer;
 
 pub mod blob;
+pub mod export;
+pub mod import;
 pub mod invocation;
 pub mod method;
 pub mod request;
@@ -311,3 +316,28 @@ impl error::ResponseError for Redirect {
             .finish()
     }
 }
+
+// Import and export jobs run and are tracked on the leader, which is also
+// where DAV writes are sent to.
+pub fn redirect_to_leader<T>(
+    core: &JMAPServer<T>,
+    path: &str,
+) -> Result<Option<HttpResponse>, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    if core.is_leader() {
+        Ok(None)
+    } else if let Some(leader_hostname) = core
+        .cluster
+        .as_ref()
+        .and_then(|cluster| cluster.leader_hostname.lock().clone())
+    {
+        let redirect_uri = format!("{}{}", leader_hostname, path);
+        debug!("Redirecting request to '{}'", redirect_uri);
+        Ok(Some(Redirect::temporary(redirect_uri).error_response()))
+    } else {
+        debug!("Rejecting request, no leader has been elected.");
+        Err(RequestError::unavailable())
+    }
+}
@@ -55,7 +55,7 @@ pub async fn handle_jmap_request<T>(
 where
     T: for<'x> Store<'x> + 'static,
 {
-    if request.len() < core.store.config.max_size_request {
+    if request.len() < core.store.config.max_size_request.get() {
         match serde_json::from_slice::<Request>(&request) {
             Ok(request) => {
                 if request.method_calls.len() < core.store.config.max_calls_in_request {
@@ -87,6 +87,10 @@ enum Capabilities {
     Submission(SubmissionCapabilities),
     VacationResponse(VacationResponseCapabilities),
     WebSocket(WebSocketCapabilities),
+    Contacts(ContactsCapabilities),
+    Calendars(CalendarsCapabilities),
+    Principals(PrincipalsCapabilities),
+    SmimeVerify(SmimeVerifyCapabilities),
 }
 
 #[derive(Debug, Clone, serde::Serialize)]
@@ -144,6 +148,30 @@ struct SubmissionCapabilities {
 #[derive(Debug, Clone, serde::Serialize)]
 struct VacationResponseCapabilities {}
 
+#[derive(Debug, Clone, serde::Serialize)]
+struct ContactsCapabilities {
+    #[serde(rename(serialize = "maxAddressBooksPerCard"))]
+    max_address_books_per_card: Option<usize>,
+    #[serde(rename(serialize = "mayCreateAddressBook"))]
+    may_create_address_book: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct PrincipalsCapabilities {}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct SmimeVerifyCapabilities {}
+
+#[derive(Debug, Clone, serde::Serialize)]
+struct CalendarsCapabilities {
+    #[serde(rename(serialize = "maxCalendarsPerEvent"))]
+    max_calendars_per_event: Option<usize>,
+    #[serde(rename(serialize = "maxParticipantsPerEvent"))]
+    max_participants_per_event: Option<usize>,
+    #[serde(rename(serialize = "mayCreateCalendar"))]
+    may_create_calendar: bool,
+}
+
 impl Session {
     pub fn new(settings: &EnvSettings, config: &JMAPConfig) -> Session {
         let base_url = settings.get("jmap-url").unwrap();
@@ -152,6 +180,22 @@ impl Session {
             capabilities: VecMap::from_iter([
                 (URI::Core, Capabilities::Core(CoreCapabilities::new(config))),
                 (URI::Mail, Capabilities::Mail(MailCapabilities::new(config))),
+                (
+                    URI::Contacts,
+                    Capabilities::Contacts(ContactsCapabilities::new()),
+                ),
+                (
+                    URI::Calendars,
+                    Capabilities::Calendars(CalendarsCapabilities::new()),
+                ),
+                (
+                    URI::Principals,
+                    Capabilities::Principals(PrincipalsCapabilities {}),
+                ),
+                (
+                    URI::SmimeVerify,
+                    Capabilities::SmimeVerify(SmimeVerifyCapabilities {}),
+                ),
                 (
                     URI::WebSocket,
                     Capabilities::WebSocket(WebSocketCapabilities::new(&base_url)),
@@ -215,6 +259,13 @@ impl Session {
         );
     }
 
+    pub fn set_limits(&mut self, config: &JMAPConfig) {
+        self.capabilities
+            .set(URI::Core, Capabilities::Core(CoreCapabilities::new(config)));
+        self.capabilities
+            .set(URI::Mail, Capabilities::Mail(MailCapabilities::new(config)));
+    }
+
     pub fn set_state(&mut self, state: u32) {
         self.state = state;
     }
@@ -260,10 +311,10 @@ impl Account {
 impl CoreCapabilities {
     pub fn new(config: &JMAPConfig) -> Self {
         CoreCapabilities {
-            max_size_upload: config.max_size_upload,
-            max_concurrent_upload: config.max_concurrent_uploads,
-            max_size_request: config.max_size_request,
-            max_concurrent_requests: config.max_concurrent_requests,
+            max_size_upload: config.max_size_upload.get(),
+            max_concurrent_upload: config.max_concurrent_uploads.get(),
+            max_size_request: config.max_size_request.get(),
+            max_concurrent_requests: config.max_concurrent_requests.get(),
             max_calls_in_request: config.max_calls_in_request,
             max_objects_in_get: config.max_objects_in_get,
             max_objects_in_set: config.max_objects_in_set,
@@ -291,7 +342,7 @@ impl MailCapabilities {
             max_mailboxes_per_email: None,
             max_mailbox_depth: config.mailbox_max_depth,
             max_size_mailbox_name: config.mailbox_name_max_len,
-            max_size_attachments_per_email: config.mail_attachments_max_size,
+            max_size_attachments_per_email: config.mail_attachments_max_size.get(),
             email_query_sort_options: [
                 "receivedAt",
                 "size",
@@ -311,6 +362,25 @@ impl MailCapabilities {
     }
 }
 
+impl ContactsCapabilities {
+    pub fn new() -> Self {
+        ContactsCapabilities {
+            max_address_books_per_card: None,
+            may_create_address_book: true,
+        }
+    }
+}
+
+impl CalendarsCapabilities {
+    pub fn new() -> Self {
+        CalendarsCapabilities {
+            max_calendars_per_event: None,
+            max_participants_per_event: None,
+            may_create_calendar: true,
+        }
+    }
+}
+
 pub async fn handle_jmap_session<T>(
     core: web::Data<JMAPServer<T>>,
     session: authorization::Session,
@@ -324,6 +394,8 @@ where
         .spawn_worker(move || {
             let mut response = core.base_session.clone();
 
+            // Limits might have changed since the base session was built
+            response.set_limits(&store.config);
             response.set_state(session.state());
 
             // Obtain member and shared accounts
@@ -360,7 +432,14 @@ where
                         if !name.is_empty() { name } else { email },
                         matches!(ptype, Type::Individual),
                         is_readonly,
-                        Some(&[URI::Core, URI::Mail, URI::WebSocket]),
+                        Some(&[
+                            URI::Core,
+                            URI::Mail,
+                            URI::Contacts,
+                            URI::Calendars,
+                            URI::WebSocket,
+                            URI::SmimeVerify,
+                        ]),
                     );
                 }
             }
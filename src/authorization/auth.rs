@@ -91,7 +91,7 @@ where
                     .unwrap_or("");
 
                 // Check whether a redirect is needed
-                let do_redirect = !core.is_up_to_date()
+                let do_redirect = (!core.is_up_to_date() && !request_path.starts_with("/metrics"))
                     || request_path.starts_with("/jmap/upload")
                     || request_path.starts_with("/jmap/ws")
                     || request_path.starts_with("/jmap/eventsource")
@@ -193,16 +193,15 @@ where
             let limiter = self
                 .rate_limiters
                 .get_with(RemoteAddress::AccountId(account_id), async {
-                    Arc::new(Limiter::new_authenticated(
-                        self.store.config.rate_limit_authenticated.0,
-                        self.store.config.rate_limit_authenticated.1,
-                    ))
+                    let (max_requests, max_interval) =
+                        self.store.config.rate_limit_authenticated.get();
+                    Arc::new(Limiter::new_authenticated(max_requests, max_interval))
                 })
                 .await;
 
             if limiter.is_rate_allowed() {
                 if let Some(in_flight_request) =
-                    limiter.is_request_allowed(self.store.config.max_concurrent_requests)
+                    limiter.is_request_allowed(self.store.config.max_concurrent_requests.get())
                 {
                     Ok(in_flight_request)
                 } else {
@@ -222,11 +221,14 @@ where
         if self
             .rate_limiters
             .get_with(addr, async {
+                let (max_requests, max_interval) = self.store.config.rate_limit_anonymous.get();
+                let (max_auth_requests, max_auth_interval) =
+                    self.store.config.rate_limit_auth.get();
                 Arc::new(Limiter::new_anonymous(
-                    self.store.config.rate_limit_anonymous.0,
-                    self.store.config.rate_limit_anonymous.1,
-                    self.store.config.rate_limit_auth.0,
-                    self.store.config.rate_limit_auth.1,
+                    max_requests,
+                    max_interval,
+                    max_auth_requests,
+                    max_auth_interval,
                 ))
             })
             .await
@@ -242,11 +244,14 @@ where
         if self
             .rate_limiters
             .get_with(addr, async {
+                let (max_requests, max_interval) = self.store.config.rate_limit_anonymous.get();
+                let (max_auth_requests, max_auth_interval) =
+                    self.store.config.rate_limit_auth.get();
                 Arc::new(Limiter::new_anonymous(
-                    self.store.config.rate_limit_anonymous.0,
-                    self.store.config.rate_limit_anonymous.1,
-                    self.store.config.rate_limit_auth.0,
-                    self.store.config.rate_limit_auth.1,
+                    max_requests,
+                    max_interval,
+                    max_auth_requests,
+                    max_auth_interval,
                 ))
             })
             .await
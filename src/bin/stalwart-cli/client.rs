@@ -0,0 +1,233 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use jmap::{
+    principal::schema::Principal,
+    request::{get::GetRequest, query::QueryRequest, set::SetRequest},
+    SUPERUSER_ID,
+};
+use jmap_mail::mailbox::retention::JMAPMailboxRetention;
+use jmap_sharing::principal::{
+    account::JMAPAccountStore, get::JMAPGetPrincipal, query::JMAPPrincipalQuery,
+    set::JMAPSetPrincipal,
+};
+use serde_json::{json, Value};
+use stalwart_jmap::services::housekeeper::Event;
+use store::{
+    config::{env_settings::EnvSettings, jmap::JMAPConfig},
+    ColumnFamily, JMAPStore, Store,
+};
+use store_rocksdb::RocksDB;
+
+pub enum Client {
+    Online {
+        http: reqwest::Client,
+        url: String,
+        user: String,
+        password: String,
+    },
+    Offline {
+        store: Arc<JMAPStore<RocksDB>>,
+        max_log_entries: u64,
+    },
+}
+
+impl Client {
+    pub fn online(
+        url: &str,
+        user: String,
+        password: String,
+        insecure: bool,
+    ) -> Result<Self, String> {
+        Ok(Client::Online {
+            http: reqwest::Client::builder()
+                .danger_accept_invalid_certs(insecure)
+                .build()
+                .map_err(|err| format!("Failed to create HTTP client: {}", err))?,
+            url: url.trim_end_matches('/').to_string(),
+            user,
+            password,
+        })
+    }
+
+    pub fn offline(settings: &EnvSettings) -> Result<Self, String> {
+        Ok(Client::Offline {
+            store: JMAPStore::new(
+                RocksDB::open(settings).map_err(|err| {
+                    format!(
+                        "Failed to open database: {} (is the server running? use --url instead)",
+                        err
+                    )
+                })?,
+                JMAPConfig::from(settings),
+                settings,
+            )
+            .into(),
+            max_log_entries: settings.parse("max-changelog-entries").unwrap_or(10000),
+        })
+    }
+
+    pub async fn call(&self, method: &str, arguments: Value) -> Result<Value, String> {
+        match self {
+            Client::Online {
+                http,
+                url,
+                user,
+                password,
+            } => {
+                let response = http
+                    .post(format!("{}/jmap", url))
+                    .basic_auth(user, Some(password))
+                    .header("Content-Type", "application/json")
+                    .body(
+                        json!({
+                            "using": [
+                                "urn:ietf:params:jmap:core",
+                                "urn:ietf:params:jmap:principals"
+                            ],
+                            "methodCalls": [[method, arguments, "c0"]]
+                        })
+                        .to_string(),
+                    )
+                    .send()
+                    .await
+                    .map_err(|err| format!("Failed to connect to {}: {}", url, err))?;
+                let status = response.status();
+                let body = response
+                    .text()
+                    .await
+                    .map_err(|err| format!("Failed to read response: {}", err))?;
+                if !status.is_success() {
+                    return Err(format!("Request failed with status {}: {}", status, body));
+                }
+
+                let mut response = serde_json::from_str::<Value>(&body)
+                    .map_err(|err| format!("Invalid response from server: {}", err))?;
+                match response
+                    .get_mut("methodResponses")
+                    .and_then(|r| r.get_mut(0))
+                    .and_then(|r| r.as_array_mut())
+                {
+                    Some(call) if call.len() == 3 => {
+                        if call[0] != "error" {
+                            Ok(call[1].take())
+                        } else {
+                            Err(format!("{} failed: {}", method, call[1]))
+                        }
+                    }
+                    _ => Err(format!("Unexpected response from server: {}", body)),
+                }
+            }
+            Client::Offline { store, .. } => call_offline(store, method, arguments)
+                .map_err(|err| format!("{} failed: {}", method, err)),
+        }
+    }
+
+    pub async fn run_task(&self, name: &str) -> Result<(), String> {
+        let event = Event::parse(name).ok_or_else(|| format!("Unknown task '{}'.", name))?;
+
+        match self {
+            Client::Online {
+                http,
+                url,
+                user,
+                password,
+            } => {
+                let response = http
+                    .post(format!("{}/admin/task/{}", url, name))
+                    .basic_auth(user, Some(password))
+                    .send()
+                    .await
+                    .map_err(|err| format!("Failed to connect to {}: {}", url, err))?;
+                if response.status().is_success() {
+                    Ok(())
+                } else {
+                    Err(format!("Request failed with status {}", response.status()))
+                }
+            }
+            Client::Offline {
+                store,
+                max_log_entries,
+            } => match event {
+                Event::PurgeAccounts => store.principal_purge(),
+                Event::PurgeBlobs => store.purge_blobs(),
+                Event::SnapshotLog => store.compact_log(*max_log_entries),
+                Event::CompactDb => store.db.compact(ColumnFamily::Bitmaps),
+                Event::Backup { incremental } => store.backup(incremental).map(|_| ()),
+                Event::Retention => store.mailbox_retention_purge().map(|_| ()),
+                Event::Reencrypt => store.reencrypt().map(|_| ()),
+                Event::Exit => Ok(()),
+            }
+            .map_err(|err| format!("Task '{}' failed: {}", name, err)),
+        }
+    }
+}
+
+fn call_offline(
+    store: &JMAPStore<RocksDB>,
+    method: &str,
+    arguments: Value,
+) -> Result<Value, String> {
+    let acl = Some(
+        store
+            .get_acl_token(SUPERUSER_ID)
+            .map_err(|err| err.to_string())?,
+    );
+
+    match method {
+        "Principal/get" => {
+            let mut request: GetRequest<Principal> =
+                serde_json::from_value(arguments).map_err(|err| err.to_string())?;
+            request.acl = acl;
+            serde_json::to_value(
+                store
+                    .principal_get(request)
+                    .map_err(|err| err.to_string())?,
+            )
+        }
+        "Principal/query" => {
+            let mut request: QueryRequest<Principal> =
+                serde_json::from_value(arguments).map_err(|err| err.to_string())?;
+            request.acl = acl;
+            serde_json::to_value(
+                store
+                    .principal_query(request)
+                    .map_err(|err| err.to_string())?,
+            )
+        }
+        "Principal/set" => {
+            let mut request: SetRequest<Principal> =
+                serde_json::from_value(arguments).map_err(|err| err.to_string())?;
+            request.acl = acl;
+            serde_json::to_value(
+                store
+                    .principal_set(request)
+                    .map_err(|err| err.to_string())?,
+            )
+        }
+        _ => return Err(format!("Unsupported method {}", method)),
+    }
+    .map_err(|err| err.to_string())
+}
@@ -0,0 +1,172 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{collections::HashMap, io::Read};
+
+use serde_json::{json, Value};
+
+use crate::{
+    client::Client,
+    principal::{account_id, create, fetch, find, update},
+};
+
+// Principal types in dependency order: domains before the addresses that
+// use them and individuals before the groups and lists that contain them.
+const EXPORT_TYPES: [&str; 4] = ["domain", "individual", "group", "list"];
+const EXPORT_PROPERTIES: [&str; 11] = [
+    "id",
+    "type",
+    "name",
+    "description",
+    "email",
+    "timezone",
+    "capabilities",
+    "aliases",
+    "dkim",
+    "quota",
+    "members",
+];
+
+pub async fn export(client: &Client, path: &str) -> Result<(), String> {
+    let mut principals = Vec::new();
+    for ptype in EXPORT_TYPES {
+        principals.extend(fetch(client, json!({ "type": ptype }), &EXPORT_PROPERTIES).await?);
+    }
+
+    // Members are exported by e-mail address, ids are not portable
+    let superuser_id = account_id();
+    let emails = principals
+        .iter()
+        .filter_map(|p| Some((p.get("id")?.as_str()?, p.get("email")?.as_str()?)))
+        .map(|(id, email)| (id.to_string(), email.to_string()))
+        .collect::<HashMap<_, _>>();
+    let principals = principals
+        .into_iter()
+        .filter(|p| p.get("id").and_then(|id| id.as_str()) != Some(superuser_id.as_str()))
+        .filter_map(|p| match p {
+            Value::Object(mut p) => {
+                p.remove("id");
+                p.retain(|_, v| !v.is_null());
+                if let Some(Value::Array(members)) = p.get_mut("members") {
+                    for member in members {
+                        if let Some(email) = member.as_str().and_then(|id| emails.get(id)) {
+                            *member = email.as_str().into();
+                        }
+                    }
+                }
+                Some(Value::Object(p))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let total = principals.len();
+    let json = serde_json::to_string_pretty(&principals)
+        .map_err(|err| format!("Failed to serialize principals: {}", err))?;
+    if path != "-" {
+        std::fs::write(path, json).map_err(|err| format!("Failed to write {}: {}", path, err))?;
+        eprintln!("Exported {} principals to {}.", total, path);
+    } else {
+        println!("{}", json);
+    }
+
+    Ok(())
+}
+
+pub async fn import(client: &Client, path: &str) -> Result<(), String> {
+    let contents = if path != "-" {
+        std::fs::read_to_string(path).map_err(|err| format!("Failed to read {}: {}", path, err))?
+    } else {
+        let mut contents = String::new();
+        std::io::stdin()
+            .read_to_string(&mut contents)
+            .map_err(|err| format!("Failed to read from stdin: {}", err))?;
+        contents
+    };
+    let mut principals = match serde_json::from_str::<Value>(&contents)
+        .map_err(|err| format!("Failed to parse {}: {}", path, err))?
+    {
+        Value::Array(principals) => principals,
+        _ => return Err(format!("Expected an array of principals in {}.", path)),
+    };
+    principals.sort_by_key(|p| {
+        p.get("type")
+            .and_then(|t| t.as_str())
+            .and_then(|t| EXPORT_TYPES.iter().position(|et| *et == t))
+            .unwrap_or(EXPORT_TYPES.len())
+    });
+
+    // Create principals, members are added once all of them exist
+    let mut members = Vec::new();
+    let mut created = 0;
+    for mut principal in principals {
+        let ptype = principal
+            .get("type")
+            .and_then(|t| t.as_str())
+            .unwrap_or("individual")
+            .to_string();
+        let key = principal
+            .get(if ptype == "domain" { "name" } else { "email" })
+            .and_then(|k| k.as_str())
+            .ok_or_else(|| format!("Principal without name or email: {}", principal))?
+            .to_string();
+
+        if find(client, Some(&ptype), &key).await.is_ok() {
+            eprintln!("Skipping {} {}, already exists.", ptype, key);
+            continue;
+        }
+        let principal_members = principal
+            .as_object_mut()
+            .and_then(|p| p.remove("members"))
+            .filter(|m| m.as_array().map_or(false, |m| !m.is_empty()));
+
+        match create(client, principal).await {
+            Ok(id) => {
+                created += 1;
+                if let Some(principal_members) = principal_members {
+                    members.push((id, key, principal_members));
+                }
+            }
+            Err(err) => eprintln!("Failed to create {} {}: {}", ptype, key, err),
+        }
+    }
+
+    for (id, key, principal_members) in members {
+        let mut member_ids = Vec::new();
+        for member in principal_members.as_array().into_iter().flatten() {
+            if let Some(email) = member.as_str() {
+                match find(client, None, email).await {
+                    Ok(member_id) => member_ids.push(member_id),
+                    Err(err) => eprintln!("Skipping member {} of {}: {}", email, key, err),
+                }
+            }
+        }
+        if let Err(err) = update(client, &id, json!({ "members": member_ids })).await {
+            eprintln!("Failed to set members of {}: {}", key, err);
+        }
+    }
+
+    println!("Imported {} principals from {}.", created, path);
+
+    Ok(())
+}
@@ -0,0 +1,191 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::collections::VecDeque;
+
+use client::Client;
+use store::{ahash::AHashMap, config::env_settings::EnvSettings};
+
+mod client;
+mod export;
+mod principal;
+
+const USAGE: &str = "Stalwart JMAP administration tool
+
+Usage: stalwart-cli [OPTIONS] <COMMAND>
+
+Connection options:
+    --url=<URL>              Connect to a running server (e.g. https://localhost:8080)
+    --user=<LOGIN>           Administrator login (default: admin)
+    --insecure               Accept invalid TLS certificates
+    --config=<PATH>          Open the database offline using this configuration file
+    --db-path=<PATH>         Open the database offline at this path
+
+Commands:
+    account create <email> [--name= --description= --quota= --aliases= --timezone=]
+    account list
+    account delete <email>
+    account password <email>
+    account quota <email> <bytes|none>
+    domain create <domain> [--description=]
+    domain list
+    domain delete <domain>
+    domain dkim <domain> <pkcs1-pem-file> [--selector= --expiration=]
+    group create <email> [--name= --description= --quota= --aliases=]
+    group list
+    group delete <email>
+    group members <email> [member-email...]
+    list create <email> [--name= --description= --aliases=]
+    list list
+    list delete <email>
+    list members <email> [member-email...]
+    task <purge-accounts|purge-blobs|snapshot-log|compact-db|backup|backup-full|retention|reencrypt>
+    export <file|->
+    import <file|->
+
+Passwords are never passed on the command line: they are prompted for on the
+terminal or, when no terminal is available, read one per line from stdin.
+
+The offline mode requires the server to be stopped. Exported accounts do not
+include passwords or DKIM private keys.
+";
+
+pub struct Arguments {
+    positional: VecDeque<String>,
+    options: AHashMap<String, String>,
+}
+
+impl Arguments {
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Self {
+        let mut positional = VecDeque::new();
+        let mut options = AHashMap::default();
+
+        for arg in args {
+            if let Some(option) = arg.strip_prefix("--") {
+                if let Some((key, value)) = option.split_once('=') {
+                    options.insert(key.to_lowercase(), value.to_string());
+                } else {
+                    options.insert(option.to_lowercase(), "true".to_string());
+                }
+            } else {
+                positional.push_back(arg);
+            }
+        }
+
+        Arguments {
+            positional,
+            options,
+        }
+    }
+
+    pub fn next(&mut self, name: &str) -> Result<String, String> {
+        self.positional
+            .pop_front()
+            .ok_or_else(|| format!("Missing {}, run with --help for usage.", name))
+    }
+
+    pub fn remaining(&mut self) -> Vec<String> {
+        self.positional.drain(..).collect()
+    }
+
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(|v| v.as_str())
+    }
+}
+
+pub fn read_secret(prompt: &str) -> Result<String, String> {
+    match rpassword::prompt_password(prompt) {
+        Ok(secret) => Ok(secret),
+        Err(_) => {
+            // No terminal available, read the secret from stdin instead.
+            let mut line = String::new();
+            std::io::stdin()
+                .read_line(&mut line)
+                .map_err(|err| format!("Failed to read from stdin: {}", err))?;
+            Ok(line.trim_end_matches(['\r', '\n']).to_string())
+        }
+    }
+}
+
+fn connect(args: &Arguments) -> Result<Client, String> {
+    if let Some(url) = args.option("url") {
+        Client::online(
+            url,
+            args.option("user").unwrap_or("admin").to_string(),
+            read_secret("Administrator password: ")?,
+            args.option("insecure").is_some(),
+        )
+    } else if args.option("config").is_some() || args.option("db-path").is_some() {
+        Client::offline(&EnvSettings::from_args(
+            ["config", "db-path"]
+                .into_iter()
+                .filter_map(|key| Some(format!("--{}={}", key, args.option(key)?))),
+        )?)
+    } else {
+        Err("Missing --url or --config, run with --help for usage.".to_string())
+    }
+}
+
+async fn run(mut args: Arguments) -> Result<(), String> {
+    let command = args.next("command")?;
+    let client = connect(&args)?;
+
+    match command.as_str() {
+        "account" => principal::exec(&client, "individual", &mut args).await,
+        "domain" | "group" | "list" => principal::exec(&client, &command, &mut args).await,
+        "task" => {
+            let name = args.next("task name")?;
+            client.run_task(&name).await?;
+            println!(
+                "Task {} {}.",
+                name,
+                if matches!(client, Client::Online { .. }) {
+                    "scheduled"
+                } else {
+                    "completed"
+                }
+            );
+            Ok(())
+        }
+        "export" => export::export(&client, &args.next("file")?).await,
+        "import" => export::import(&client, &args.next("file")?).await,
+        _ => Err(format!(
+            "Unknown command '{}', run with --help for usage.",
+            command
+        )),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Arguments::parse(std::env::args().skip(1));
+    if args.positional.is_empty() || args.option("help").is_some() {
+        print!("{}", USAGE);
+        return;
+    }
+
+    if let Err(err) = run(args).await {
+        eprintln!("{}", err);
+        std::process::exit(1);
+    }
+}
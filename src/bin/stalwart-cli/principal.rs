@@ -0,0 +1,361 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use serde_json::{json, Map, Value};
+
+use crate::{client::Client, read_secret, Arguments};
+
+const MAX_OBJECTS_IN_GET: usize = 100;
+
+pub async fn exec(client: &Client, ptype: &str, args: &mut Arguments) -> Result<(), String> {
+    let command = args.next("command")?;
+    match (ptype, command.as_str()) {
+        (_, "create") => {
+            let key = args.next(if ptype == "domain" { "domain" } else { "email" })?;
+            let mut principal = Map::new();
+            principal.insert("type".to_string(), ptype.into());
+            if ptype == "domain" {
+                principal.insert("name".to_string(), key.as_str().into());
+            } else {
+                principal.insert(
+                    "name".to_string(),
+                    args.option("name").unwrap_or(&key).into(),
+                );
+                principal.insert("email".to_string(), key.as_str().into());
+            }
+            if let Some(description) = args.option("description") {
+                principal.insert("description".to_string(), description.into());
+            }
+            if ptype == "individual" {
+                let secret = read_secret("Password (empty for none): ")?;
+                if !secret.is_empty() {
+                    principal.insert("secret".to_string(), secret.into());
+                }
+                if let Some(timezone) = args.option("timezone") {
+                    principal.insert("timezone".to_string(), timezone.into());
+                }
+            }
+            if let Some(quota) = args.option("quota") {
+                principal.insert("quota".to_string(), parse_quota(quota)?);
+            }
+            if let Some(aliases) = args.option("aliases") {
+                principal.insert(
+                    "aliases".to_string(),
+                    aliases.split([',', ';']).map(|a| a.trim()).collect(),
+                );
+            }
+
+            let id = create(client, principal.into()).await?;
+            println!("Created {} {} with id {}.", ptype, key, id);
+        }
+        (_, "list") => {
+            for principal in fetch(
+                client,
+                json!({ "type": ptype }),
+                &["id", "name", "email", "description", "quota", "members"],
+            )
+            .await?
+            {
+                let field = |name: &str| {
+                    principal
+                        .get(name)
+                        .and_then(|v| v.as_str())
+                        .unwrap_or_default()
+                        .to_string()
+                };
+                match ptype {
+                    "domain" => println!("{:<40} {}", field("name"), field("description")),
+                    "individual" => println!(
+                        "{:<40} {:<30} {}",
+                        field("email"),
+                        field("name"),
+                        principal
+                            .get("quota")
+                            .and_then(|v| v.as_u64())
+                            .map_or_else(|| "-".to_string(), |quota| quota.to_string())
+                    ),
+                    _ => println!(
+                        "{:<40} {:<30} {} members",
+                        field("email"),
+                        field("name"),
+                        principal
+                            .get("members")
+                            .and_then(|v| v.as_array())
+                            .map_or(0, |m| m.len())
+                    ),
+                }
+            }
+        }
+        (_, "delete") => {
+            let key = args.next(if ptype == "domain" { "domain" } else { "email" })?;
+            destroy(client, &find(client, Some(ptype), &key).await?).await?;
+            println!("Deleted {} {}.", ptype, key);
+        }
+        ("individual", "password") => {
+            let email = args.next("email")?;
+            let secret = read_secret("New password: ")?;
+            update(
+                client,
+                &find(client, Some(ptype), &email).await?,
+                json!({ "secret": secret }),
+            )
+            .await?;
+            println!("Password for {} successfully changed.", email);
+        }
+        (_, "quota") if ptype != "domain" => {
+            let email = args.next("email")?;
+            let quota = parse_quota(&args.next("quota")?)?;
+            update(
+                client,
+                &find(client, Some(ptype), &email).await?,
+                json!({ "quota": quota }),
+            )
+            .await?;
+            println!("Quota for {} successfully updated.", email);
+        }
+        ("group" | "list", "members") => {
+            let email = args.next("email")?;
+            let mut members = Vec::new();
+            for member in args.remaining() {
+                members.push(Value::String(find(client, None, &member).await?));
+            }
+            update(
+                client,
+                &find(client, Some(ptype), &email).await?,
+                json!({ "members": members }),
+            )
+            .await?;
+            println!("Members of {} successfully updated.", email);
+        }
+        ("domain", "dkim") => {
+            let domain = args.next("domain")?;
+            let pem_path = args.next("pem file")?;
+            let pem = std::fs::read_to_string(&pem_path)
+                .map_err(|err| format!("Failed to read {}: {}", pem_path, err))?;
+            let expiration = args
+                .option("expiration")
+                .map(|e| {
+                    e.parse::<i64>()
+                        .map_err(|_| format!("Invalid expiration '{}'.", e))
+                })
+                .transpose()?;
+            update(
+                client,
+                &find(client, Some(ptype), &domain).await?,
+                json!({
+                    "secret": pem,
+                    "dkim": {
+                        "dkimSelector": args.option("selector"),
+                        "dkimExpiration": expiration,
+                    }
+                }),
+            )
+            .await?;
+            println!("DKIM key for {} successfully updated.", domain);
+        }
+        _ => {
+            return Err(format!("Unknown command '{}'.", command));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn account_id() -> String {
+    JMAPId::from(SUPERUSER_ID).to_string()
+}
+
+pub async fn create(client: &Client, principal: Value) -> Result<String, String> {
+    let response = client
+        .call(
+            "Principal/set",
+            json!({
+                "accountId": account_id(),
+                "create": { "c0": principal }
+            }),
+        )
+        .await?;
+
+    response
+        .pointer("/created/c0/id")
+        .and_then(|id| id.as_str())
+        .map(|id| id.to_string())
+        .ok_or_else(|| set_error(response.pointer("/notCreated/c0")))
+}
+
+pub async fn update(client: &Client, id: &str, patch: Value) -> Result<(), String> {
+    let response = client
+        .call(
+            "Principal/set",
+            json!({
+                "accountId": account_id(),
+                "update": { id: patch }
+            }),
+        )
+        .await?;
+
+    if response
+        .get("updated")
+        .and_then(|updated| updated.get(id))
+        .is_some()
+    {
+        Ok(())
+    } else {
+        Err(set_error(
+            response
+                .get("notUpdated")
+                .and_then(|not_updated| not_updated.get(id)),
+        ))
+    }
+}
+
+pub async fn destroy(client: &Client, id: &str) -> Result<(), String> {
+    let response = client
+        .call(
+            "Principal/set",
+            json!({
+                "accountId": account_id(),
+                "destroy": [id]
+            }),
+        )
+        .await?;
+
+    if response
+        .get("destroyed")
+        .and_then(|destroyed| destroyed.as_array())
+        .map_or(false, |destroyed| destroyed.iter().any(|d| d == id))
+    {
+        Ok(())
+    } else {
+        Err(set_error(
+            response
+                .get("notDestroyed")
+                .and_then(|not_destroyed| not_destroyed.get(id)),
+        ))
+    }
+}
+
+pub async fn fetch(
+    client: &Client,
+    filter: Value,
+    properties: &[&str],
+) -> Result<Vec<Value>, String> {
+    // Obtain all matching ids
+    let mut ids = Vec::new();
+    loop {
+        let response = client
+            .call(
+                "Principal/query",
+                json!({
+                    "accountId": account_id(),
+                    "filter": filter,
+                    "position": ids.len(),
+                }),
+            )
+            .await?;
+        match response.get("ids").and_then(|ids| ids.as_array()) {
+            Some(page) if !page.is_empty() => ids.extend(page.iter().cloned()),
+            _ => break,
+        }
+    }
+
+    // Fetch principals in chunks
+    let mut principals = Vec::with_capacity(ids.len());
+    for ids in ids.chunks(MAX_OBJECTS_IN_GET) {
+        let mut response = client
+            .call(
+                "Principal/get",
+                json!({
+                    "accountId": account_id(),
+                    "ids": ids,
+                    "properties": properties,
+                }),
+            )
+            .await?;
+        if let Some(Value::Array(list)) = response.get_mut("list").map(|list| list.take()) {
+            principals.extend(list);
+        }
+    }
+
+    Ok(principals)
+}
+
+pub async fn find(client: &Client, ptype: Option<&str>, key: &str) -> Result<String, String> {
+    let (property, filter) = if ptype == Some("domain") {
+        ("name", json!({ "name": key }))
+    } else {
+        ("email", json!({ "email": key }))
+    };
+    let filter = if let Some(ptype) = ptype {
+        json!({
+            "operator": "AND",
+            "conditions": [{ "type": ptype }, filter]
+        })
+    } else {
+        filter
+    };
+
+    fetch(client, filter, &["id", property])
+        .await?
+        .into_iter()
+        .find_map(|principal| {
+            if principal
+                .get(property)
+                .and_then(|v| v.as_str())
+                .map_or(false, |v| v.eq_ignore_ascii_case(key))
+            {
+                principal
+                    .get("id")
+                    .and_then(|id| id.as_str())
+                    .map(|id| id.to_string())
+            } else {
+                None
+            }
+        })
+        .ok_or_else(|| format!("Principal '{}' not found.", key))
+}
+
+fn parse_quota(quota: &str) -> Result<Value, String> {
+    if quota == "none" {
+        Ok(Value::Null)
+    } else {
+        quota
+            .parse::<u64>()
+            .map(Value::from)
+            .map_err(|_| format!("Invalid quota '{}'.", quota))
+    }
+}
+
+fn set_error(error: Option<&Value>) -> String {
+    if let Some(error) = error {
+        error
+            .get("description")
+            .or_else(|| error.get("type"))
+            .and_then(|v| v.as_str())
+            .unwrap_or("Unknown error")
+            .to_string()
+    } else {
+        "Unexpected response from server.".to_string()
+    }
+}
@@ -84,11 +84,12 @@ where
             );
 
             let mut log_batch = Vec::new();
-            for (key, value) in store.db.iterator(
+            for item in store.db.iterator(
                 ColumnFamily::Logs,
                 &[LogKey::PENDING_UPDATES_KEY_PREFIX],
                 Direction::Forward,
             )? {
+                let (key, value) = item?;
                 if !key.starts_with(&[LogKey::PENDING_UPDATES_KEY_PREFIX]) {
                     break;
                 }
@@ -189,6 +190,7 @@ where
                         Direction::Forward,
                     )?
                     .next()
+                    .transpose()?
                 {
                     if key.starts_with(&[LogKey::RAFT_KEY_PREFIX]) {
                         let raft_id = LogKey::deserialize_raft(&key).ok_or_else(|| {
@@ -213,11 +215,11 @@ where
                     key: FOLLOWER_COMMIT_INDEX_KEY.to_vec(),
                 });
 
-                for (key, value) in
-                    store
-                        .db
-                        .iterator(ColumnFamily::Logs, &key, Direction::Forward)?
+                for item in store
+                    .db
+                    .iterator(ColumnFamily::Logs, &key, Direction::Forward)?
                 {
+                    let (key, value) = item?;
                     if !key.starts_with(&[LogKey::RAFT_KEY_PREFIX]) {
                         break;
                     }
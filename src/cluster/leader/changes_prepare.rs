@@ -27,10 +27,16 @@ use crate::cluster::log::{DocumentUpdate, Update};
 use crate::JMAPServer;
 use jmap::principal::schema::Principal;
 use jmap::push_subscription::schema::PushSubscription;
+use jmap::share_notification::schema::ShareNotification;
+use jmap_calendars::calendar::schema::Calendar;
+use jmap_calendars::calendar_event::schema::CalendarEvent;
+use jmap_contacts::address_book::schema::AddressBook;
+use jmap_contacts::contact_card::schema::ContactCard;
 use jmap_mail::email_submission::schema::EmailSubmission;
 use jmap_mail::identity::schema::Identity;
 use jmap_mail::mail::schema::Email;
 use jmap_mail::mailbox::schema::Mailbox;
+use jmap_mail::saved_search::schema::SavedSearch;
 use jmap_mail::vacation_response::schema::VacationResponse;
 use store::core::collection::Collection;
 use store::core::error::StoreError;
@@ -93,6 +99,29 @@ where
                         document_id,
                         is_insert,
                     ),
+                    Collection::SavedSearch => {
+                        store.raft_prepare_update::<SavedSearch>(account_id, document_id, is_insert)
+                    }
+                    Collection::AddressBook => {
+                        store.raft_prepare_update::<AddressBook>(account_id, document_id, is_insert)
+                    }
+                    Collection::ContactCard => {
+                        store.raft_prepare_update::<ContactCard>(account_id, document_id, is_insert)
+                    }
+                    Collection::Calendar => {
+                        store.raft_prepare_update::<Calendar>(account_id, document_id, is_insert)
+                    }
+                    Collection::CalendarEvent => store.raft_prepare_update::<CalendarEvent>(
+                        account_id,
+                        document_id,
+                        is_insert,
+                    ),
+                    Collection::ShareNotification => store
+                        .raft_prepare_update::<ShareNotification>(
+                            account_id,
+                            document_id,
+                            is_insert,
+                        ),
                     Collection::Thread | Collection::None => Err(StoreError::InternalError(
                         "Unsupported collection for changes".into(),
                     )),
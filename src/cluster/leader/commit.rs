@@ -74,11 +74,12 @@ where
             );
 
             let mut log_batch = Vec::new();
-            for (key, value) in store.db.iterator(
+            for item in store.db.iterator(
                 ColumnFamily::Logs,
                 &[LogKey::TOMBSTONE_KEY_PREFIX],
                 Direction::Forward,
             )? {
+                let (key, value) = item?;
                 if !key.starts_with(&[LogKey::TOMBSTONE_KEY_PREFIX]) {
                     break;
                 }
@@ -68,10 +68,11 @@ where
         );
         let prefix = &key[0..LogKey::CHANGE_ID_POS];
 
-        for (key, value) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Logs, &key, Direction::Forward)?
         {
+            let (key, value) = item?;
             if !key.starts_with(prefix) {
                 break;
             }
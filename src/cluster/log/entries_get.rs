@@ -73,10 +73,11 @@ where
         let mut entries_size = 0;
 
         if pending_changes.is_empty() && start_index != to_index {
-            for (key, value) in self
+            for item in self
                 .db
                 .iterator(ColumnFamily::Logs, &key, Direction::Forward)?
             {
+                let (key, value) = item?;
                 if !key.starts_with(prefix) {
                     break;
                 }
@@ -48,10 +48,11 @@ where
         let prefix = &[LogKey::RAFT_KEY_PREFIX];
         let mut last_term_id = TermId::MAX;
 
-        for (key, _) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Logs, prefix, Direction::Forward)?
         {
+            let (key, _) = item?;
             if key.starts_with(prefix) {
                 let raft_id = LogKey::deserialize_raft(&key).ok_or_else(|| {
                     StoreError::InternalError(format!("Corrupted raft entry for [{:?}]", key))
@@ -76,10 +77,11 @@ where
         let prefix = &from_key[..LogKey::RAFT_TERM_POS];
         let mut term_id = TermId::MAX;
 
-        for (key, _) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Logs, prefix, Direction::Forward)?
         {
+            let (key, _) = item?;
             if key.starts_with(prefix) {
                 let raft_id = LogKey::deserialize_raft(&key).ok_or_else(|| {
                     StoreError::InternalError(format!("Corrupted raft entry for [{:?}]", key))
@@ -52,6 +52,7 @@ where
                     Direction::Forward,
                 )?
                 .next()
+                .transpose()?
             {
                 if key.starts_with(&[LogKey::ROLLBACK_KEY_PREFIX]) {
                     Some((
@@ -95,6 +96,7 @@ where
                 Direction::Forward,
             )?
             .next()
+            .transpose()?
             .is_some()
         {
             debug!("This node has pending a rollback and won't start a new election.");
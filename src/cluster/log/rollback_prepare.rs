@@ -53,11 +53,12 @@ where
         let mut changes = MergedChanges::new();
         let mut write_batch = Vec::new();
 
-        for (key, value) in self.db.iterator(
+        for item in self.db.iterator(
             ColumnFamily::Logs,
             &[LogKey::CHANGE_KEY_PREFIX],
             Direction::Forward,
         )? {
+            let (key, value) = item?;
             if !key.starts_with(&[LogKey::CHANGE_KEY_PREFIX]) {
                 break;
             }
@@ -146,11 +147,12 @@ where
             write_batch = Vec::new();
         }
 
-        for (key, _) in self.db.iterator(
+        for item in self.db.iterator(
             ColumnFamily::Logs,
             &[LogKey::RAFT_KEY_PREFIX],
             Direction::Forward,
         )? {
+            let (key, _) = item?;
             if key.starts_with(&[LogKey::RAFT_KEY_PREFIX]) {
                 if after_index == LogIndex::MAX
                     || LogKey::deserialize_raft(&key)
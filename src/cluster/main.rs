@@ -24,11 +24,13 @@
 use crate::JMAPServer;
 
 use super::{
+    raft,
     rpc::{self},
-    Cluster, Event,
+    Cluster, ClusterStatus, Event, PeerStatus,
 };
 use store::tracing::error;
 use store::{log::raft::LogIndex, Store};
+use tokio::sync::oneshot;
 
 impl<T> Cluster<T>
 where
@@ -142,6 +144,9 @@ where
             } => {
                 self.send_command(command, response_tx).await;
             }
+            Event::Status { response_tx } => response_tx
+                .send(self.status())
+                .unwrap_or_else(|_| error!("Oneshot response channel closed.")),
             Event::Shutdown => return Ok(false),
 
             #[cfg(test)]
@@ -167,6 +172,31 @@ where
         }
         (total, healthy)
     }
+
+    pub fn status(&self) -> ClusterStatus {
+        ClusterStatus {
+            role: match &self.state {
+                raft::State::Leader { .. } => "leader",
+                raft::State::Follower { .. } => "follower",
+                raft::State::Candidate { .. } => "candidate",
+                raft::State::Wait { .. } | raft::State::VotedFor { .. } => "wait",
+            },
+            term: self.term,
+            last_log: self.last_log,
+            uncommitted_index: self.uncommitted_index,
+            commit_index: *self.commit_index_tx.borrow(),
+            peers: self
+                .peers
+                .iter()
+                .filter(|peer| peer.is_in_shard(self.shard_id))
+                .map(|peer| PeerStatus {
+                    hostname: peer.hostname.clone(),
+                    is_healthy: peer.is_healthy(),
+                    commit_index: peer.commit_index,
+                })
+                .collect(),
+        }
+    }
 }
 
 impl<T> JMAPServer<T>
@@ -176,4 +206,15 @@ where
     pub fn is_in_cluster(&self) -> bool {
         self.cluster.is_some()
     }
+
+    pub async fn cluster_status(&self) -> Option<ClusterStatus> {
+        let (response_tx, response_rx) = oneshot::channel();
+        self.cluster
+            .as_ref()?
+            .tx
+            .send(Event::Status { response_tx })
+            .await
+            .ok()?;
+        response_rx.await.ok()
+    }
 }
@@ -140,6 +140,9 @@ pub enum Event {
         peer_id: PeerId,
         commit_index: LogIndex,
     },
+    Status {
+        response_tx: oneshot::Sender<ClusterStatus>,
+    },
     Shutdown,
 
     #[cfg(test)]
@@ -180,6 +183,23 @@ pub struct Peer {
     pub vote_granted: bool,
 }
 
+#[derive(Debug)]
+pub struct ClusterStatus {
+    pub role: &'static str,
+    pub term: TermId,
+    pub last_log: RaftId,
+    pub uncommitted_index: LogIndex,
+    pub commit_index: LogIndex,
+    pub peers: Vec<PeerStatus>,
+}
+
+#[derive(Debug)]
+pub struct PeerStatus {
+    pub hostname: String,
+    pub is_healthy: bool,
+    pub commit_index: LogIndex,
+}
+
 pub struct ClusterIpc {
     pub tx: mpsc::Sender<Event>,
     pub state: AtomicU8,
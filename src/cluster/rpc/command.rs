@@ -32,7 +32,7 @@ use tokio::sync::oneshot;
 
 use crate::{
     cluster::{self, Cluster},
-    lmtp::ingest::DeliveryStatus,
+    lmtp::{ingest::DeliveryStatus, mail_auth::AuthOutput, spam_filter::SpamScore},
     JMAPServer,
 };
 
@@ -47,6 +47,8 @@ pub enum Command {
         mail_from: String,
         rcpt_to: AHashSet<AccountId>,
         raw_message: Vec<u8>,
+        auth_result: Option<AuthOutput>,
+        spam: Option<SpamScore>,
     },
 }
 
@@ -126,8 +128,12 @@ where
                         mail_from,
                         rcpt_to,
                         raw_message,
+                        auth_result,
+                        spam,
                     } => CommandResponse::IngestMessage {
-                        result: core.mail_ingest(mail_from, rcpt_to, raw_message).await,
+                        result: core
+                            .mail_ingest(mail_from, rcpt_to, raw_message, auth_result, spam)
+                            .await,
                     },
                 };
 
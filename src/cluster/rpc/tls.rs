@@ -24,11 +24,13 @@
 use std::{fs::File, io::BufReader, sync::Arc};
 
 use rustls::{
-    client::WebPkiVerifier, Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore,
-    ServerConfig,
+    client::WebPkiVerifier,
+    server::{ClientHello, ResolvesServerCert},
+    sign::{any_supported_type, CertifiedKey},
+    Certificate, ClientConfig, OwnedTrustAnchor, PrivateKey, RootCertStore, ServerConfig,
 };
 use rustls_pemfile::{certs, pkcs8_private_keys};
-use store::tracing::error;
+use store::{parking_lot::RwLock, tracing::error};
 
 use crate::server::UnwrapFailure;
 
@@ -87,6 +89,67 @@ pub fn load_tls_server_config(cert_path: &str, key_path: &str) -> ServerConfig {
     config.with_single_cert(cert_chain, keys.remove(0)).unwrap()
 }
 
+/// Serves a certificate that can be replaced without restarting the listener.
+pub struct CertResolver {
+    key: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertResolver {
+    pub fn new(cert_path: &str, key_path: &str) -> Result<Self, String> {
+        Ok(CertResolver {
+            key: RwLock::new(Arc::new(load_certified_key(cert_path, key_path)?)),
+        })
+    }
+
+    pub fn reload(&self, cert_path: &str, key_path: &str) -> Result<(), String> {
+        let key = load_certified_key(cert_path, key_path)?;
+        *self.key.write() = Arc::new(key);
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.key.read().clone())
+    }
+}
+
+pub fn load_tls_server_config_with_resolver(resolver: Arc<CertResolver>) -> ServerConfig {
+    ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver)
+}
+
+fn load_certified_key(cert_path: &str, key_path: &str) -> Result<CertifiedKey, String> {
+    let cert_chain = certs(&mut BufReader::new(File::open(cert_path).map_err(
+        |err| format!("Failed to open certificate file {}: {}", cert_path, err),
+    )?))
+    .map_err(|err| format!("Failed to parse certificate file {}: {}", cert_path, err))?
+    .into_iter()
+    .map(Certificate)
+    .collect::<Vec<_>>();
+    if cert_chain.is_empty() {
+        return Err(format!("No certificates found in {}.", cert_path));
+    }
+
+    let key = pkcs8_private_keys(&mut BufReader::new(
+        File::open(key_path)
+            .map_err(|err| format!("Failed to open key file {}: {}", key_path, err))?,
+    ))
+    .map_err(|err| format!("Failed to parse key file {}: {}", key_path, err))?
+    .into_iter()
+    .next()
+    .map(PrivateKey)
+    .ok_or_else(|| format!("Could not locate PKCS 8 private keys in {}.", key_path))?;
+
+    Ok(CertifiedKey::new(
+        cert_chain,
+        any_supported_type(&key)
+            .map_err(|_| format!("Unsupported private key type in {}.", key_path))?,
+    ))
+}
+
 struct DummyVerifier;
 
 impl rustls::client::ServerCertVerifier for DummyVerifier {
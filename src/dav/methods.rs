@@ -0,0 +1,310 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::web;
+use serde_json::{json, Map, Value as JsonValue};
+use store::{tracing::debug, Store};
+
+use crate::{
+    api::{invocation::handle_method_calls, request::Request, RequestError},
+    authorization::Session,
+    JMAPServer,
+};
+
+use super::DavType;
+
+// Runs DAV operations as regular JMAP method calls, so access control,
+// validation and change tracking are the same as for JMAP clients.
+pub struct DavSession<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub core: web::Data<JMAPServer<T>>,
+    pub session: Session,
+}
+
+#[derive(Debug, Default)]
+pub struct DavChanges {
+    pub changed: Vec<String>,
+    pub destroyed: Vec<String>,
+    pub new_state: String,
+}
+
+pub enum DavSet {
+    Create(Map<String, JsonValue>),
+    Update(String, Map<String, JsonValue>),
+    Destroy(String),
+}
+
+pub enum DavWrite {
+    Done,
+    StateMismatch,
+}
+
+impl<T> DavSession<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub async fn call(
+        &self,
+        method: &str,
+        arguments: JsonValue,
+    ) -> Result<JsonValue, RequestError> {
+        self.call_raw(method, arguments)
+            .await?
+            .map_err(|error| method_error(&error))
+    }
+
+    // Returns the method error, if any, as sent to JMAP clients.
+    async fn call_raw(
+        &self,
+        method: &str,
+        arguments: JsonValue,
+    ) -> Result<Result<JsonValue, JsonValue>, RequestError> {
+        let request = serde_json::to_vec(&json!({
+            "using": [],
+            "methodCalls": [[method, arguments, "0"]]
+        }))
+        .ok()
+        .and_then(|request| serde_json::from_slice::<Request>(&request).ok())
+        .ok_or_else(RequestError::internal_server_error)?;
+        let response = serde_json::to_value(
+            handle_method_calls(request, self.core.clone(), self.session.clone()).await,
+        )
+        .map_err(|_| RequestError::internal_server_error())?;
+
+        match response["methodResponses"][0]
+            .as_array()
+            .map(|v| v.as_slice())
+        {
+            Some([name, arguments, _]) if name == "error" => Ok(Err(arguments.clone())),
+            Some([_, arguments, _]) => Ok(Ok(arguments.clone())),
+            _ => Err(RequestError::internal_server_error()),
+        }
+    }
+
+    pub async fn get_collections(
+        &self,
+        dav_type: DavType,
+        account_id: &str,
+        ids: Option<&[String]>,
+    ) -> Result<Vec<JsonValue>, RequestError> {
+        let mut response = self
+            .call(
+                &format!("{}/get", dav_type.collection_object()),
+                json!({"accountId": account_id, "ids": ids}),
+            )
+            .await?;
+        Ok(take_list(&mut response["list"]))
+    }
+
+    pub async fn get_state(
+        &self,
+        dav_type: DavType,
+        account_id: &str,
+    ) -> Result<String, RequestError> {
+        let response = self
+            .call(
+                &format!("{}/get", dav_type.item_object()),
+                json!({"accountId": account_id, "ids": [], "properties": ["id"]}),
+            )
+            .await?;
+        Ok(response["state"].as_str().unwrap_or_default().to_string())
+    }
+
+    pub async fn query(
+        &self,
+        dav_type: DavType,
+        account_id: &str,
+        filter: JsonValue,
+    ) -> Result<Vec<String>, RequestError> {
+        let mut ids = Vec::new();
+        loop {
+            let response = self
+                .call(
+                    &format!("{}/query", dav_type.item_object()),
+                    json!({
+                        "accountId": account_id,
+                        "filter": filter,
+                        "position": ids.len(),
+                        "calculateTotal": true
+                    }),
+                )
+                .await?;
+            let page = response["ids"]
+                .as_array()
+                .map(|v| v.as_slice())
+                .unwrap_or_default();
+            ids.extend(page.iter().filter_map(|id| id.as_str()).map(String::from));
+            if page.is_empty() || ids.len() as u64 >= response["total"].as_u64().unwrap_or(0) {
+                return Ok(ids);
+            }
+        }
+    }
+
+    pub async fn get_items(
+        &self,
+        dav_type: DavType,
+        account_id: &str,
+        ids: &[String],
+    ) -> Result<Vec<Map<String, JsonValue>>, RequestError> {
+        let mut items = Vec::with_capacity(ids.len());
+        for ids in ids.chunks(self.core.store.config.max_objects_in_get) {
+            let mut response = self
+                .call(
+                    &format!("{}/get", dav_type.item_object()),
+                    json!({"accountId": account_id, "ids": ids}),
+                )
+                .await?;
+            items.extend(take_list(&mut response["list"]).into_iter().filter_map(
+                |item| match item {
+                    JsonValue::Object(item) => Some(item),
+                    _ => None,
+                },
+            ));
+        }
+        Ok(items)
+    }
+
+    pub async fn changes(
+        &self,
+        dav_type: DavType,
+        account_id: &str,
+        since_state: &str,
+    ) -> Result<DavChanges, RequestError> {
+        let mut changes = DavChanges {
+            new_state: since_state.to_string(),
+            ..Default::default()
+        };
+        loop {
+            let response = self
+                .call(
+                    &format!("{}/changes", dav_type.item_object()),
+                    json!({"accountId": account_id, "sinceState": changes.new_state}),
+                )
+                .await?;
+            for property in ["created", "updated", "destroyed"] {
+                let list = if property != "destroyed" {
+                    &mut changes.changed
+                } else {
+                    &mut changes.destroyed
+                };
+                for id in response[property].as_array().into_iter().flatten() {
+                    if let Some(id) = id.as_str() {
+                        if !list.iter().any(|item| item == id) {
+                            list.push(id.to_string());
+                        }
+                    }
+                }
+            }
+            changes.new_state = response["newState"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            if !response["hasMoreChanges"].as_bool().unwrap_or(false) {
+                let destroyed = &changes.destroyed;
+                changes.changed.retain(|id| !destroyed.contains(id));
+                return Ok(changes);
+            }
+        }
+    }
+
+    // Writes the object only if the collection is still in the given state,
+    // which the store checks while holding the collection lock.
+    pub async fn set(
+        &self,
+        dav_type: DavType,
+        account_id: &str,
+        if_in_state: &str,
+        operation: DavSet,
+    ) -> Result<DavWrite, RequestError> {
+        let (arguments, error) = match operation {
+            DavSet::Create(item) => (
+                json!({"accountId": account_id, "ifInState": if_in_state, "create": {"c0": item}}),
+                "notCreated",
+            ),
+            DavSet::Update(id, item) => (
+                json!({"accountId": account_id, "ifInState": if_in_state, "update": {id: item}}),
+                "notUpdated",
+            ),
+            DavSet::Destroy(id) => (
+                json!({"accountId": account_id, "ifInState": if_in_state, "destroy": [id]}),
+                "notDestroyed",
+            ),
+        };
+        let response = match self
+            .call_raw(&format!("{}/set", dav_type.item_object()), arguments)
+            .await?
+        {
+            Ok(response) => response,
+            Err(error) if error["type"] == "stateMismatch" => return Ok(DavWrite::StateMismatch),
+            Err(error) => return Err(method_error(&error)),
+        };
+
+        if let Some((_, error)) = response[error].as_object().and_then(|v| v.iter().next()) {
+            Err(set_error(error))
+        } else {
+            Ok(DavWrite::Done)
+        }
+    }
+}
+
+fn take_list(value: &mut JsonValue) -> Vec<JsonValue> {
+    match value.take() {
+        JsonValue::Array(list) => list,
+        _ => Vec::new(),
+    }
+}
+
+fn method_error(error: &JsonValue) -> RequestError {
+    let description = error["description"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    debug!("DAV request failed: {}", error);
+    match error["type"].as_str().unwrap_or_default() {
+        "forbidden" | "accountReadOnly" => RequestError::forbidden(),
+        "accountNotFound" | "accountNotSupportedByMethod" => RequestError::not_found(),
+        "serverUnavailable" => RequestError::unavailable(),
+        "invalidArguments" | "unsupportedFilter" | "requestTooLarge" => {
+            RequestError::blank(400, "Bad Request", description)
+        }
+        _ => RequestError::internal_server_error(),
+    }
+}
+
+fn set_error(error: &JsonValue) -> RequestError {
+    let description = error["description"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    debug!("DAV update failed: {}", error);
+    match error["type"].as_str().unwrap_or_default() {
+        "forbidden" => RequestError::forbidden(),
+        "notFound" => RequestError::not_found(),
+        "overQuota" => RequestError::blank(507, "Insufficient Storage", description),
+        "tooLarge" => RequestError::blank(413, "Payload Too Large", description),
+        "stateMismatch" => RequestError::blank(412, "Precondition Failed", description),
+        _ => RequestError::blank(400, "Bad Request", description),
+    }
+}
@@ -0,0 +1,547 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod methods;
+pub mod propfind;
+pub mod xml;
+
+use actix_web::{
+    http::{header, StatusCode},
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use jmap::types::jmap::JMAPId;
+use jmap_calendars::calendar_event::ical::{build_event, ICalendar, ICALENDAR_PROPERTIES};
+use jmap_contacts::contact_card::vcard::{build_vcard, parse_vcard, VCARD_PROPERTIES};
+use serde_json::{json, Map, Value as JsonValue};
+use store::{blake3, Store};
+
+use crate::{
+    api::{redirect_to_leader, Redirect, RequestError},
+    authorization::Session,
+    JMAPServer,
+};
+
+use self::{
+    methods::{DavSession, DavSet, DavWrite},
+    propfind::{handle_propfind, handle_report},
+};
+
+const DAV_CAPABILITIES: &str = "1, 3, addressbook, calendar-access";
+const DAV_METHODS: &str = "OPTIONS, GET, HEAD, PUT, DELETE, PROPFIND, REPORT";
+const MAX_WRITE_ATTEMPTS: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DavType {
+    Card,
+    Cal,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DavPath {
+    Root,
+    Principal {
+        account_id: JMAPId,
+    },
+    Home {
+        dav_type: DavType,
+        account_id: JMAPId,
+    },
+    Collection {
+        dav_type: DavType,
+        account_id: JMAPId,
+        collection_id: JMAPId,
+    },
+    Resource {
+        dav_type: DavType,
+        account_id: JMAPId,
+        collection_id: JMAPId,
+        name: String,
+    },
+}
+
+// A contact or event together with its DAV representation. Resources keep
+// the name they were created with over DAV, objects created by JMAP clients
+// are named after their uid.
+#[derive(Debug)]
+pub struct DavItem {
+    pub id: String,
+    pub uid: String,
+    pub name: String,
+    pub data: String,
+    pub etag: String,
+}
+
+impl DavType {
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "card" => Some(DavType::Card),
+            "cal" => Some(DavType::Cal),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DavType::Card => "card",
+            DavType::Cal => "cal",
+        }
+    }
+
+    pub fn collection_object(&self) -> &'static str {
+        match self {
+            DavType::Card => "AddressBook",
+            DavType::Cal => "Calendar",
+        }
+    }
+
+    pub fn item_object(&self) -> &'static str {
+        match self {
+            DavType::Card => "ContactCard",
+            DavType::Cal => "CalendarEvent",
+        }
+    }
+
+    fn membership(&self) -> &'static str {
+        match self {
+            DavType::Card => "addressBookIds",
+            DavType::Cal => "calendarIds",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            DavType::Card => ".vcf",
+            DavType::Cal => ".ics",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            DavType::Card => "text/vcard; charset=utf-8",
+            DavType::Cal => "text/calendar; charset=utf-8",
+        }
+    }
+
+    pub fn in_collection(&self, collection_id: &str) -> JsonValue {
+        match self {
+            DavType::Card => json!({ "inAddressBook": collection_id }),
+            DavType::Cal => json!({ "inCalendars": [collection_id] }),
+        }
+    }
+
+    fn serialize(&self, item: &Map<String, JsonValue>) -> Option<String> {
+        match self {
+            DavType::Card => build_vcard(item),
+            DavType::Cal => build_event(item),
+        }
+    }
+
+    fn deserialize(&self, data: &str) -> Option<Map<String, JsonValue>> {
+        match self {
+            DavType::Card => parse_vcard(data),
+            DavType::Cal => {
+                let mut events = ICalendar::parse(data)?.events;
+                if events.len() == 1 {
+                    events.pop()
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn removable_properties(&self) -> &'static [&'static str] {
+        match self {
+            DavType::Card => &VCARD_PROPERTIES,
+            DavType::Cal => &ICALENDAR_PROPERTIES,
+        }
+    }
+}
+
+impl DavPath {
+    pub fn parse(path: &str) -> Option<Self> {
+        let mut parts = path
+            .strip_prefix("/dav")?
+            .split('/')
+            .filter(|part| !part.is_empty());
+        let path = match (parts.next(), parts.next()) {
+            (None, _) => DavPath::Root,
+            (Some("principals"), Some(account_id)) => DavPath::Principal {
+                account_id: JMAPId::parse(account_id)?,
+            },
+            (Some(dav_type), Some(account_id)) => {
+                let dav_type = DavType::parse(dav_type)?;
+                let account_id = JMAPId::parse(account_id)?;
+                match (parts.next(), parts.next()) {
+                    (None, _) => DavPath::Home {
+                        dav_type,
+                        account_id,
+                    },
+                    (Some(collection_id), None) => DavPath::Collection {
+                        dav_type,
+                        account_id,
+                        collection_id: JMAPId::parse(collection_id)?,
+                    },
+                    (Some(collection_id), Some(name)) => DavPath::Resource {
+                        dav_type,
+                        account_id,
+                        collection_id: JMAPId::parse(collection_id)?,
+                        name: name.to_string(),
+                    },
+                }
+            }
+            _ => return None,
+        };
+        if parts.next().is_none() {
+            Some(path)
+        } else {
+            None
+        }
+    }
+}
+
+impl DavItem {
+    fn new(dav_type: DavType, item: &Map<String, JsonValue>) -> Option<Self> {
+        let data = dav_type.serialize(item)?;
+        let uid = item.get("uid")?.as_str()?.to_string();
+        Some(DavItem {
+            id: item.get("id")?.as_str()?.to_string(),
+            name: item
+                .get("davName")
+                .and_then(|name| name.as_str())
+                .map(String::from)
+                .unwrap_or_else(|| format!("{}{}", encode_name(&uid), dav_type.extension())),
+            etag: format!(
+                "\"{}\"",
+                &blake3::hash(data.as_bytes()).to_hex().as_str()[..32]
+            ),
+            uid,
+            data,
+        })
+    }
+}
+
+pub fn principal_href(account_id: &str) -> String {
+    format!("/dav/principals/{}/", account_id)
+}
+
+pub fn home_href(dav_type: DavType, account_id: &str) -> String {
+    format!("/dav/{}/{}/", dav_type.as_str(), account_id)
+}
+
+pub fn collection_href(dav_type: DavType, account_id: &str, collection_id: &str) -> String {
+    format!(
+        "/dav/{}/{}/{}/",
+        dav_type.as_str(),
+        account_id,
+        collection_id
+    )
+}
+
+pub async fn handle_dav_request<T>(
+    request: HttpRequest,
+    body: web::Bytes,
+    core: web::Data<JMAPServer<T>>,
+    session: Session,
+) -> Result<HttpResponse, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let path = DavPath::parse(request.path()).ok_or_else(RequestError::not_found)?;
+    let dav = DavSession { core, session };
+
+    match request.method().as_str() {
+        "OPTIONS" => Ok(HttpResponse::Ok()
+            .insert_header(("DAV", DAV_CAPABILITIES))
+            .insert_header((header::ALLOW, DAV_METHODS))
+            .finish()),
+        "PROPFIND" => {
+            let depth = match request.headers().get("Depth") {
+                Some(depth) if depth.as_bytes() == b"0" => 0,
+                _ => 1,
+            };
+            handle_propfind(&dav, &path, depth, &body).await
+        }
+        "REPORT" => handle_report(&dav, &path, &body).await,
+        method @ ("GET" | "HEAD" | "PUT" | "DELETE") => {
+            let (dav_type, account_id, collection_id, name) = match &path {
+                DavPath::Resource {
+                    dav_type,
+                    account_id,
+                    collection_id,
+                    name,
+                } => (
+                    *dav_type,
+                    account_id.to_string(),
+                    collection_id.to_string(),
+                    name,
+                ),
+                _ => return Ok(method_not_allowed()),
+            };
+            if method == "GET" || method == "HEAD" {
+                let item = resolve_items(
+                    &dav,
+                    dav_type,
+                    &account_id,
+                    &collection_id,
+                    std::slice::from_ref(name),
+                )
+                .await?
+                .pop()
+                .ok_or_else(RequestError::not_found)?;
+                return Ok(HttpResponse::Ok()
+                    .insert_header((header::CONTENT_TYPE, dav_type.content_type()))
+                    .insert_header((header::ETAG, item.etag))
+                    .body(item.data));
+            }
+            if let Some(redirect) = redirect_to_leader(&dav.core, request.path())? {
+                return Ok(redirect);
+            }
+            let object = if method == "PUT" {
+                Some(
+                    std::str::from_utf8(&body)
+                        .ok()
+                        .and_then(|data| dav_type.deserialize(data))
+                        .ok_or_else(|| {
+                            RequestError::blank(
+                                415,
+                                "Unsupported Media Type",
+                                "The request does not contain a valid vCard or iCalendar object.",
+                            )
+                        })?,
+                )
+            } else {
+                None
+            };
+
+            // The preconditions are checked against the collection state the
+            // item was read in, and the write is rejected if anything changed
+            // in between. Concurrent writes make the request start over.
+            for _ in 0..MAX_WRITE_ATTEMPTS {
+                let state = dav.get_state(dav_type, &account_id).await?;
+                let item = resolve_items(
+                    &dav,
+                    dav_type,
+                    &account_id,
+                    &collection_id,
+                    std::slice::from_ref(name),
+                )
+                .await?
+                .pop();
+                check_preconditions(&request, item.as_ref())?;
+
+                let (operation, mut response) = match (object.clone(), item) {
+                    (Some(mut object), Some(item)) => {
+                        object.remove("uid");
+                        for property in dav_type.removable_properties() {
+                            if !object.contains_key(*property) {
+                                object.insert(property.to_string(), JsonValue::Null);
+                            }
+                        }
+                        (DavSet::Update(item.id, object), HttpResponse::NoContent())
+                    }
+                    (Some(mut object), None) => {
+                        if let Some(uid) = object.get("uid").and_then(|v| v.as_str()) {
+                            if !dav
+                                .query(
+                                    dav_type,
+                                    &account_id,
+                                    json!({
+                                        "operator": "AND",
+                                        "conditions": [
+                                            dav_type.in_collection(&collection_id),
+                                            { "uid": uid }
+                                        ]
+                                    }),
+                                )
+                                .await?
+                                .is_empty()
+                            {
+                                return Err(RequestError::blank(
+                                    409,
+                                    "Conflict",
+                                    "An object with the same UID is stored under a different name.",
+                                ));
+                            }
+                        }
+                        object.insert(
+                            dav_type.membership().to_string(),
+                            json!({ collection_id: true }),
+                        );
+                        object.insert("davName".to_string(), name.as_str().into());
+                        (DavSet::Create(object), HttpResponse::Created())
+                    }
+                    (None, Some(item)) => (DavSet::Destroy(item.id), HttpResponse::NoContent()),
+                    (None, None) => return Err(RequestError::not_found()),
+                };
+                if let DavWrite::Done = dav.set(dav_type, &account_id, &state, operation).await? {
+                    return Ok(response.finish());
+                }
+            }
+            Err(RequestError::unavailable())
+        }
+        _ => Ok(method_not_allowed()),
+    }
+}
+
+// Service discovery (RFC 6764) points clients to the DAV root.
+pub async fn handle_dav_discovery() -> HttpResponse {
+    Redirect::permanent("/dav/").error_response()
+}
+
+// Returns the objects of a collection stored under the requested resource
+// names. Objects without a stored name are looked up by uid.
+pub async fn resolve_items<T>(
+    dav: &DavSession<T>,
+    dav_type: DavType,
+    account_id: &str,
+    collection_id: &str,
+    names: &[String],
+) -> Result<Vec<DavItem>, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut items = Vec::with_capacity(names.len());
+    for names in names.chunks(dav.core.store.config.max_objects_in_get) {
+        let filter = json!({
+            "operator": "AND",
+            "conditions": [
+                dav_type.in_collection(collection_id),
+                {
+                    "operator": "OR",
+                    "conditions": names
+                        .iter()
+                        .flat_map(|name| {
+                            [
+                                json!({ "davName": name }),
+                                json!({ "uid": decode_name(name, dav_type) }),
+                            ]
+                        })
+                        .collect::<Vec<_>>()
+                }
+            ]
+        });
+        let ids = dav.query(dav_type, account_id, filter).await?;
+        items.extend(
+            list_items(dav, dav_type, account_id, collection_id, &ids)
+                .await?
+                .into_iter()
+                .filter(|item| names.contains(&item.name)),
+        );
+    }
+    Ok(items)
+}
+
+// Fetches objects by id, skipping any that are not in the collection.
+pub async fn list_items<T>(
+    dav: &DavSession<T>,
+    dav_type: DavType,
+    account_id: &str,
+    collection_id: &str,
+    ids: &[String],
+) -> Result<Vec<DavItem>, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    Ok(dav
+        .get_items(dav_type, account_id, ids)
+        .await?
+        .iter()
+        .filter(|item| is_member(dav_type, item, collection_id))
+        .filter_map(|item| DavItem::new(dav_type, item))
+        .collect())
+}
+
+fn is_member(dav_type: DavType, item: &Map<String, JsonValue>, collection_id: &str) -> bool {
+    item.get(dav_type.membership())
+        .and_then(|collections| collections.get(collection_id))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+fn check_preconditions(request: &HttpRequest, item: Option<&DavItem>) -> Result<(), RequestError> {
+    let matches = |header: &str| {
+        request
+            .headers()
+            .get(header)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| {
+                item.map_or(false, |item| {
+                    value
+                        .split(',')
+                        .any(|etag| etag.trim() == "*" || etag.trim() == item.etag)
+                })
+            })
+    };
+    if matches("If-Match") == Some(false) || matches("If-None-Match") == Some(true) {
+        Err(RequestError::blank(
+            412,
+            "Precondition Failed",
+            "The resource has been modified or already exists.",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn method_not_allowed() -> HttpResponse {
+    HttpResponse::build(StatusCode::METHOD_NOT_ALLOWED)
+        .insert_header((header::ALLOW, DAV_METHODS))
+        .finish()
+}
+
+// Resource names are percent-encoded uids.
+fn encode_name(uid: &str) -> String {
+    let mut name = String::with_capacity(uid.len());
+    for byte in uid.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~@".contains(&byte) {
+            name.push(byte as char);
+        } else {
+            name.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    name
+}
+
+fn decode_name(name: &str, dav_type: DavType) -> String {
+    let name = name.strip_suffix(dav_type.extension()).unwrap_or(name);
+    let bytes = name.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut pos = 0;
+    while pos < bytes.len() {
+        if bytes[pos] == b'%' {
+            if let Some(byte) = bytes
+                .get(pos + 1..pos + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                result.push(byte);
+                pos += 3;
+                continue;
+            }
+        }
+        result.push(bytes[pos]);
+        pos += 1;
+    }
+    String::from_utf8_lossy(&result).into_owned()
+}
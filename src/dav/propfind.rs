@@ -0,0 +1,517 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::{
+    http::{header, StatusCode},
+    HttpResponse,
+};
+use jmap::types::jmap::JMAPId;
+use serde_json::Value as JsonValue;
+use store::Store;
+
+use crate::api::RequestError;
+
+use super::{
+    collection_href, home_href, is_member, list_items,
+    methods::DavSession,
+    principal_href, resolve_items,
+    xml::{
+        escape, href, parse_propfind, parse_report, precondition_error, DavProperty, MultiStatus,
+        PropFind, Report, NS_DAV,
+    },
+    DavItem, DavPath, DavType,
+};
+
+const SYNC_TOKEN_PREFIX: &str = "urn:x-stalwart:sync:";
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";
+
+const PRIVILEGES: &str = concat!(
+    "<D:privilege><D:read/></D:privilege>",
+    "<D:privilege><D:write/></D:privilege>",
+    "<D:privilege><D:write-content/></D:privilege>",
+    "<D:privilege><D:bind/></D:privilege>",
+    "<D:privilege><D:unbind/></D:privilege>",
+);
+
+enum DavResource<'x> {
+    Root,
+    Principal {
+        account_id: &'x str,
+    },
+    Home {
+        account_id: &'x str,
+    },
+    Collection {
+        dav_type: DavType,
+        account_id: &'x str,
+        collection: &'x JsonValue,
+        state: &'x str,
+    },
+    Item {
+        dav_type: DavType,
+        item: &'x DavItem,
+    },
+}
+
+struct PropFindResponse<'x> {
+    properties: &'x [DavProperty],
+    all_prop: bool,
+    current_principal: String,
+    multistatus: MultiStatus,
+}
+
+pub async fn handle_propfind<T>(
+    dav: &DavSession<T>,
+    path: &DavPath,
+    depth: u32,
+    body: &[u8],
+) -> Result<HttpResponse, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let (properties, all_prop) = match parse_propfind(body) {
+        Some(PropFind::Prop(properties)) => (properties, false),
+        Some(PropFind::AllProp) => (DavProperty::ALL_PROP.to_vec(), true),
+        None => return Err(RequestError::invalid_parameters()),
+    };
+    let mut response = PropFindResponse::new(dav, &properties, all_prop);
+
+    match path {
+        DavPath::Root => {
+            response.add("/dav/", &DavResource::Root);
+        }
+        DavPath::Principal { account_id } => {
+            let account_id = account_id.to_string();
+            response.add(
+                &principal_href(&account_id),
+                &DavResource::Principal {
+                    account_id: &account_id,
+                },
+            );
+        }
+        DavPath::Home {
+            dav_type,
+            account_id,
+        } => {
+            let account_id = account_id.to_string();
+            response.add(
+                &home_href(*dav_type, &account_id),
+                &DavResource::Home {
+                    account_id: &account_id,
+                },
+            );
+            if depth > 0 {
+                let state = dav.get_state(*dav_type, &account_id).await?;
+                for collection in dav.get_collections(*dav_type, &account_id, None).await? {
+                    response.add_collection(*dav_type, &account_id, &collection, &state);
+                }
+            }
+        }
+        DavPath::Collection {
+            dav_type,
+            account_id,
+            collection_id,
+        } => {
+            let account_id = account_id.to_string();
+            let collection_id = collection_id.to_string();
+            let state = dav.get_state(*dav_type, &account_id).await?;
+            let collection = dav
+                .get_collections(
+                    *dav_type,
+                    &account_id,
+                    Some(std::slice::from_ref(&collection_id)),
+                )
+                .await?
+                .pop()
+                .ok_or_else(RequestError::not_found)?;
+            response.add_collection(*dav_type, &account_id, &collection, &state);
+
+            if depth > 0 {
+                let ids = dav
+                    .query(
+                        *dav_type,
+                        &account_id,
+                        dav_type.in_collection(&collection_id),
+                    )
+                    .await?;
+                let href = collection_href(*dav_type, &account_id, &collection_id);
+                for item in list_items(dav, *dav_type, &account_id, &collection_id, &ids).await? {
+                    response.add_item(*dav_type, &href, &item);
+                }
+            }
+        }
+        DavPath::Resource {
+            dav_type,
+            account_id,
+            collection_id,
+            name,
+        } => {
+            let account_id = account_id.to_string();
+            let collection_id = collection_id.to_string();
+            let item = resolve_items(
+                dav,
+                *dav_type,
+                &account_id,
+                &collection_id,
+                std::slice::from_ref(name),
+            )
+            .await?
+            .pop()
+            .ok_or_else(RequestError::not_found)?;
+            response.add_item(
+                *dav_type,
+                &collection_href(*dav_type, &account_id, &collection_id),
+                &item,
+            );
+        }
+    }
+
+    Ok(multistatus(response.multistatus.finish(None)))
+}
+
+pub async fn handle_report<T>(
+    dav: &DavSession<T>,
+    path: &DavPath,
+    body: &[u8],
+) -> Result<HttpResponse, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let (dav_type, account_id, collection_id) = match path {
+        DavPath::Collection {
+            dav_type,
+            account_id,
+            collection_id,
+        } => (*dav_type, account_id.to_string(), collection_id.to_string()),
+        _ => return Err(RequestError::forbidden()),
+    };
+    let href = collection_href(dav_type, &account_id, &collection_id);
+
+    match parse_report(body).ok_or_else(RequestError::invalid_parameters)? {
+        Report::Multiget { properties, hrefs } => {
+            let mut response = PropFindResponse::new(dav, &properties, false);
+            let names = hrefs
+                .iter()
+                .filter_map(|item_href| {
+                    let name = &item_href[item_href.find(&href)? + href.len()..];
+                    if !name.is_empty() && !name.contains('/') {
+                        Some(name.to_string())
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>();
+            let items = resolve_items(dav, dav_type, &account_id, &collection_id, &names).await?;
+
+            for item_href in &hrefs {
+                let name = item_href.rsplit('/').next();
+                if let Some(item) = items.iter().find(|item| Some(item.name.as_str()) == name) {
+                    response.add(item_href, &DavResource::Item { dav_type, item });
+                } else {
+                    response.multistatus.add_status(item_href, "404 Not Found");
+                }
+            }
+
+            Ok(multistatus(response.multistatus.finish(None)))
+        }
+        Report::Query {
+            properties,
+            time_range,
+        } => {
+            let mut filter = dav_type.in_collection(&collection_id);
+            if let Some(time_range) = time_range {
+                if let Some(start) = time_range.start.as_deref().and_then(parse_utc) {
+                    filter["after"] = start.into();
+                }
+                if let Some(end) = time_range.end.as_deref().and_then(parse_utc) {
+                    filter["before"] = end.into();
+                }
+            }
+            let ids = dav.query(dav_type, &account_id, filter).await?;
+            let mut response = PropFindResponse::new(dav, &properties, false);
+            for item in list_items(dav, dav_type, &account_id, &collection_id, &ids).await? {
+                response.add_item(dav_type, &href, &item);
+            }
+
+            Ok(multistatus(response.multistatus.finish(None)))
+        }
+        Report::SyncCollection {
+            properties,
+            sync_token,
+        } => {
+            let mut response = PropFindResponse::new(dav, &properties, false);
+            let state = if let Some(sync_token) = sync_token {
+                // Deleted objects can no longer be mapped to their resource
+                // names, so clients are asked to do a full sync instead.
+                let changes = match sync_token.strip_prefix(SYNC_TOKEN_PREFIX) {
+                    Some(state) => dav.changes(dav_type, &account_id, state).await.ok(),
+                    None => None,
+                };
+                let changes = match changes {
+                    Some(changes) if changes.destroyed.is_empty() => changes,
+                    _ => {
+                        return Ok(HttpResponse::build(StatusCode::FORBIDDEN)
+                            .insert_header((header::CONTENT_TYPE, XML_CONTENT_TYPE))
+                            .body(precondition_error(NS_DAV, "valid-sync-token")))
+                    }
+                };
+
+                let items = dav
+                    .get_items(dav_type, &account_id, &changes.changed)
+                    .await?;
+                for item in &items {
+                    let is_member = is_member(dav_type, item, &collection_id);
+                    match DavItem::new(dav_type, item) {
+                        Some(item) if is_member => response.add_item(dav_type, &href, &item),
+                        Some(item) => response
+                            .multistatus
+                            .add_status(&format!("{}{}", href, item.name), "404 Not Found"),
+                        None => (),
+                    }
+                }
+                changes.new_state
+            } else {
+                let state = dav.get_state(dav_type, &account_id).await?;
+                let ids = dav
+                    .query(
+                        dav_type,
+                        &account_id,
+                        dav_type.in_collection(&collection_id),
+                    )
+                    .await?;
+                for item in list_items(dav, dav_type, &account_id, &collection_id, &ids).await? {
+                    response.add_item(dav_type, &href, &item);
+                }
+                state
+            };
+
+            Ok(multistatus(
+                response
+                    .multistatus
+                    .finish(Some(&format!("{}{}", SYNC_TOKEN_PREFIX, state))),
+            ))
+        }
+    }
+}
+
+impl<'x> PropFindResponse<'x> {
+    fn new<T>(dav: &DavSession<T>, properties: &'x [DavProperty], all_prop: bool) -> Self
+    where
+        T: for<'y> Store<'y> + 'static,
+    {
+        PropFindResponse {
+            properties,
+            all_prop,
+            current_principal: principal_href(&JMAPId::from(dav.session.account_id()).to_string()),
+            multistatus: MultiStatus::new(),
+        }
+    }
+
+    fn add(&mut self, href: &str, resource: &DavResource) {
+        let mut found = Vec::with_capacity(self.properties.len());
+        let mut not_found = Vec::new();
+        for property in self.properties {
+            if let Some(value) = self.value(resource, property) {
+                found.push((property, value));
+            } else if !self.all_prop {
+                not_found.push(property);
+            }
+        }
+        self.multistatus.add_response(href, &found, &not_found);
+    }
+
+    fn add_collection(
+        &mut self,
+        dav_type: DavType,
+        account_id: &str,
+        collection: &JsonValue,
+        state: &str,
+    ) {
+        if let Some(collection_id) = collection["id"].as_str() {
+            self.add(
+                &collection_href(dav_type, account_id, collection_id),
+                &DavResource::Collection {
+                    dav_type,
+                    account_id,
+                    collection,
+                    state,
+                },
+            );
+        }
+    }
+
+    fn add_item(&mut self, dav_type: DavType, collection_href: &str, item: &DavItem) {
+        self.add(
+            &format!("{}{}", collection_href, item.name),
+            &DavResource::Item { dav_type, item },
+        );
+    }
+
+    fn value(&self, resource: &DavResource, property: &DavProperty) -> Option<String> {
+        let text = |value: &JsonValue| value.as_str().map(escape);
+        match (resource, property) {
+            (_, DavProperty::CurrentUserPrincipal) => Some(href(&self.current_principal)),
+            (DavResource::Root | DavResource::Home { .. }, DavProperty::ResourceType) => {
+                Some("<D:collection/>".to_string())
+            }
+            (DavResource::Principal { .. }, DavProperty::ResourceType) => {
+                Some("<D:principal/>".to_string())
+            }
+            (DavResource::Principal { account_id }, DavProperty::PrincipalUrl) => {
+                Some(href(&principal_href(account_id)))
+            }
+            (DavResource::Principal { account_id }, DavProperty::AddressBookHomeSet) => {
+                Some(href(&home_href(DavType::Card, account_id)))
+            }
+            (DavResource::Principal { account_id }, DavProperty::CalendarHomeSet) => {
+                Some(href(&home_href(DavType::Cal, account_id)))
+            }
+            (
+                DavResource::Home { account_id, .. } | DavResource::Collection { account_id, .. },
+                DavProperty::Owner,
+            ) => Some(href(&principal_href(account_id))),
+            (
+                DavResource::Home { .. }
+                | DavResource::Collection { .. }
+                | DavResource::Item { .. },
+                DavProperty::CurrentUserPrivilegeSet,
+            ) => Some(PRIVILEGES.to_string()),
+            (DavResource::Collection { dav_type, .. }, DavProperty::ResourceType) => {
+                Some(match dav_type {
+                    DavType::Card => "<D:collection/><CARD:addressbook/>".to_string(),
+                    DavType::Cal => "<D:collection/><CAL:calendar/>".to_string(),
+                })
+            }
+            (DavResource::Collection { collection, .. }, DavProperty::DisplayName) => {
+                text(&collection["name"])
+            }
+            (DavResource::Collection { state, .. }, DavProperty::GetCTag) => Some(escape(state)),
+            (DavResource::Collection { state, .. }, DavProperty::SyncToken) => {
+                Some(escape(&format!("{}{}", SYNC_TOKEN_PREFIX, state)))
+            }
+            (DavResource::Collection { dav_type, .. }, DavProperty::SupportedReportSet) => {
+                let reports = match dav_type {
+                    DavType::Card => ["<CARD:addressbook-multiget/>", "<CARD:addressbook-query/>"],
+                    DavType::Cal => ["<CAL:calendar-multiget/>", "<CAL:calendar-query/>"],
+                };
+                Some(
+                    reports
+                        .iter()
+                        .chain(["<D:sync-collection/>"].iter())
+                        .map(|report| {
+                            format!(
+                                "<D:supported-report><D:report>{}</D:report></D:supported-report>",
+                                report
+                            )
+                        })
+                        .collect(),
+                )
+            }
+            (
+                DavResource::Collection {
+                    dav_type: DavType::Card,
+                    collection,
+                    ..
+                },
+                DavProperty::AddressBookDescription,
+            )
+            | (
+                DavResource::Collection {
+                    dav_type: DavType::Cal,
+                    collection,
+                    ..
+                },
+                DavProperty::CalendarDescription,
+            ) => text(&collection["description"]),
+            (
+                DavResource::Collection {
+                    dav_type: DavType::Card,
+                    ..
+                },
+                DavProperty::SupportedAddressData,
+            ) => Some(
+                "<CARD:address-data-type content-type=\"text/vcard\" version=\"3.0\"/>".to_string(),
+            ),
+            (
+                DavResource::Collection {
+                    dav_type: DavType::Cal,
+                    ..
+                },
+                DavProperty::SupportedCalendarComponentSet,
+            ) => Some("<CAL:comp name=\"VEVENT\"/>".to_string()),
+            (
+                DavResource::Collection {
+                    dav_type: DavType::Cal,
+                    collection,
+                    ..
+                },
+                DavProperty::CalendarColor,
+            ) => text(&collection["color"]),
+            (DavResource::Item { .. }, DavProperty::ResourceType) => Some(String::new()),
+            (DavResource::Item { item, .. }, DavProperty::GetETag) => Some(escape(&item.etag)),
+            (DavResource::Item { dav_type, .. }, DavProperty::GetContentType) => {
+                Some(dav_type.content_type().to_string())
+            }
+            (
+                DavResource::Item {
+                    dav_type: DavType::Card,
+                    item,
+                },
+                DavProperty::AddressData,
+            )
+            | (
+                DavResource::Item {
+                    dav_type: DavType::Cal,
+                    item,
+                },
+                DavProperty::CalendarData,
+            ) => Some(escape(&item.data)),
+            _ => None,
+        }
+    }
+}
+
+fn multistatus(body: String) -> HttpResponse {
+    HttpResponse::build(StatusCode::MULTI_STATUS)
+        .insert_header((header::CONTENT_TYPE, XML_CONTENT_TYPE))
+        .body(body)
+}
+
+// Converts a CalDAV UTC date-time such as 20221018T000000Z to the format
+// expected by CalendarEvent/query filters.
+fn parse_utc(value: &str) -> Option<String> {
+    let value = value.trim().strip_suffix('Z')?;
+    if value.len() == 15 && value.is_ascii() && &value[8..9] == "T" {
+        Some(format!(
+            "{}-{}-{}T{}:{}:{}Z",
+            &value[0..4],
+            &value[4..6],
+            &value[6..8],
+            &value[9..11],
+            &value[11..13],
+            &value[13..15]
+        ))
+    } else {
+        None
+    }
+}
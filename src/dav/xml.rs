@@ -0,0 +1,486 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::fmt::Write;
+
+use quick_xml::{
+    events::Event,
+    name::{Namespace, ResolveResult},
+    NsReader,
+};
+
+pub const NS_DAV: &str = "DAV:";
+pub const NS_CARDDAV: &str = "urn:ietf:params:xml:ns:carddav";
+pub const NS_CALDAV: &str = "urn:ietf:params:xml:ns:caldav";
+pub const NS_CALENDARSERVER: &str = "http://calendarserver.org/ns/";
+pub const NS_APPLE_ICAL: &str = "http://apple.com/ns/ical/";
+
+#[derive(Debug, Default)]
+pub struct Element {
+    pub namespace: String,
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub text: String,
+    pub children: Vec<Element>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DavProperty {
+    ResourceType,
+    DisplayName,
+    GetETag,
+    GetContentType,
+    GetCTag,
+    SyncToken,
+    Owner,
+    CurrentUserPrincipal,
+    CurrentUserPrivilegeSet,
+    PrincipalUrl,
+    SupportedReportSet,
+    AddressBookHomeSet,
+    AddressBookDescription,
+    SupportedAddressData,
+    AddressData,
+    CalendarHomeSet,
+    CalendarDescription,
+    SupportedCalendarComponentSet,
+    CalendarData,
+    CalendarColor,
+    Unknown { namespace: String, name: String },
+}
+
+#[derive(Debug)]
+pub enum PropFind {
+    AllProp,
+    Prop(Vec<DavProperty>),
+}
+
+#[derive(Debug)]
+pub enum Report {
+    Multiget {
+        properties: Vec<DavProperty>,
+        hrefs: Vec<String>,
+    },
+    Query {
+        properties: Vec<DavProperty>,
+        time_range: Option<TimeRange>,
+    },
+    SyncCollection {
+        properties: Vec<DavProperty>,
+        sync_token: Option<String>,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct TimeRange {
+    pub start: Option<String>,
+    pub end: Option<String>,
+}
+
+pub struct MultiStatus {
+    xml: String,
+}
+
+impl Element {
+    pub fn parse(bytes: &[u8]) -> Option<Element> {
+        let mut reader = NsReader::from_reader(bytes);
+        reader.trim_text(true).expand_empty_elements(true);
+        let mut buf = Vec::new();
+        let mut stack: Vec<Element> = Vec::new();
+
+        loop {
+            match reader.read_resolved_event_into(&mut buf).ok()? {
+                (namespace, Event::Start(element)) => {
+                    let mut attributes = Vec::new();
+                    for attribute in element.attributes() {
+                        let attribute = attribute.ok()?;
+                        attributes.push((
+                            String::from_utf8_lossy(attribute.key.local_name().as_ref())
+                                .into_owned(),
+                            attribute.unescape_value().ok()?.into_owned(),
+                        ));
+                    }
+                    stack.push(Element {
+                        namespace: match namespace {
+                            ResolveResult::Bound(Namespace(namespace)) => {
+                                String::from_utf8_lossy(namespace).into_owned()
+                            }
+                            _ => String::new(),
+                        },
+                        name: String::from_utf8_lossy(element.local_name().as_ref()).into_owned(),
+                        attributes,
+                        ..Default::default()
+                    });
+                }
+                (_, Event::End(_)) => {
+                    let element = stack.pop()?;
+                    if let Some(parent) = stack.last_mut() {
+                        parent.children.push(element);
+                    } else {
+                        return Some(element);
+                    }
+                }
+                (_, Event::Text(text)) => {
+                    stack.last_mut()?.text.push_str(&text.unescape().ok()?);
+                }
+                (_, Event::CData(text)) => {
+                    stack
+                        .last_mut()?
+                        .text
+                        .push_str(&String::from_utf8_lossy(&text));
+                }
+                (_, Event::Eof) => return None,
+                _ => (),
+            }
+            buf.clear();
+        }
+    }
+
+    pub fn is(&self, namespace: &str, name: &str) -> bool {
+        self.namespace == namespace && self.name == name
+    }
+
+    pub fn child(&self, namespace: &str, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.is(namespace, name))
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Depth-first search, used to locate elements nested in filters.
+    pub fn find(&self, namespace: &str, name: &str) -> Option<&Element> {
+        self.children.iter().find_map(|child| {
+            if child.is(namespace, name) {
+                Some(child)
+            } else {
+                child.find(namespace, name)
+            }
+        })
+    }
+
+    fn properties(&self) -> Vec<DavProperty> {
+        self.child(NS_DAV, "prop")
+            .map(|prop| prop.children.iter().map(DavProperty::from).collect())
+            .unwrap_or_default()
+    }
+}
+
+impl From<&Element> for DavProperty {
+    fn from(element: &Element) -> Self {
+        match (element.namespace.as_str(), element.name.as_str()) {
+            (NS_DAV, "resourcetype") => DavProperty::ResourceType,
+            (NS_DAV, "displayname") => DavProperty::DisplayName,
+            (NS_DAV, "getetag") => DavProperty::GetETag,
+            (NS_DAV, "getcontenttype") => DavProperty::GetContentType,
+            (NS_DAV, "sync-token") => DavProperty::SyncToken,
+            (NS_DAV, "owner") => DavProperty::Owner,
+            (NS_DAV, "current-user-principal") => DavProperty::CurrentUserPrincipal,
+            (NS_DAV, "current-user-privilege-set") => DavProperty::CurrentUserPrivilegeSet,
+            (NS_DAV, "principal-URL") => DavProperty::PrincipalUrl,
+            (NS_DAV, "supported-report-set") => DavProperty::SupportedReportSet,
+            (NS_CALENDARSERVER, "getctag") => DavProperty::GetCTag,
+            (NS_CARDDAV, "addressbook-home-set") => DavProperty::AddressBookHomeSet,
+            (NS_CARDDAV, "addressbook-description") => DavProperty::AddressBookDescription,
+            (NS_CARDDAV, "supported-address-data") => DavProperty::SupportedAddressData,
+            (NS_CARDDAV, "address-data") => DavProperty::AddressData,
+            (NS_CALDAV, "calendar-home-set") => DavProperty::CalendarHomeSet,
+            (NS_CALDAV, "calendar-description") => DavProperty::CalendarDescription,
+            (NS_CALDAV, "supported-calendar-component-set") => {
+                DavProperty::SupportedCalendarComponentSet
+            }
+            (NS_CALDAV, "calendar-data") => DavProperty::CalendarData,
+            (NS_APPLE_ICAL, "calendar-color") => DavProperty::CalendarColor,
+            (namespace, name) => DavProperty::Unknown {
+                namespace: namespace.to_string(),
+                name: name.to_string(),
+            },
+        }
+    }
+}
+
+impl DavProperty {
+    // Properties returned by an "allprop" PROPFIND.
+    pub const ALL_PROP: [DavProperty; 4] = [
+        DavProperty::ResourceType,
+        DavProperty::DisplayName,
+        DavProperty::GetETag,
+        DavProperty::GetContentType,
+    ];
+
+    fn tag(&self) -> (&str, &str) {
+        match self {
+            DavProperty::ResourceType => ("D", "resourcetype"),
+            DavProperty::DisplayName => ("D", "displayname"),
+            DavProperty::GetETag => ("D", "getetag"),
+            DavProperty::GetContentType => ("D", "getcontenttype"),
+            DavProperty::GetCTag => ("CS", "getctag"),
+            DavProperty::SyncToken => ("D", "sync-token"),
+            DavProperty::Owner => ("D", "owner"),
+            DavProperty::CurrentUserPrincipal => ("D", "current-user-principal"),
+            DavProperty::CurrentUserPrivilegeSet => ("D", "current-user-privilege-set"),
+            DavProperty::PrincipalUrl => ("D", "principal-URL"),
+            DavProperty::SupportedReportSet => ("D", "supported-report-set"),
+            DavProperty::AddressBookHomeSet => ("CARD", "addressbook-home-set"),
+            DavProperty::AddressBookDescription => ("CARD", "addressbook-description"),
+            DavProperty::SupportedAddressData => ("CARD", "supported-address-data"),
+            DavProperty::AddressData => ("CARD", "address-data"),
+            DavProperty::CalendarHomeSet => ("CAL", "calendar-home-set"),
+            DavProperty::CalendarDescription => ("CAL", "calendar-description"),
+            DavProperty::SupportedCalendarComponentSet => {
+                ("CAL", "supported-calendar-component-set")
+            }
+            DavProperty::CalendarData => ("CAL", "calendar-data"),
+            DavProperty::CalendarColor => ("ICAL", "calendar-color"),
+            DavProperty::Unknown { name, .. } => ("", name),
+        }
+    }
+
+    fn write(&self, xml: &mut String, value: Option<&str>) {
+        let (prefix, name) = self.tag();
+        let name = if let DavProperty::Unknown { namespace, .. } = self {
+            let _ = write!(xml, "<{} xmlns=\"{}\"", name, escape(namespace));
+            name.to_string()
+        } else {
+            let _ = write!(xml, "<{}:{}", prefix, name);
+            format!("{}:{}", prefix, name)
+        };
+        match value {
+            Some(value) if !value.is_empty() => {
+                let _ = write!(xml, ">{}</{}>", value, name);
+            }
+            _ => xml.push_str("/>"),
+        }
+    }
+}
+
+// A missing or empty body is an "allprop" request.
+pub fn parse_propfind(body: &[u8]) -> Option<PropFind> {
+    if body.iter().all(|ch| ch.is_ascii_whitespace()) {
+        return Some(PropFind::AllProp);
+    }
+    let root = Element::parse(body)?;
+    if !root.is(NS_DAV, "propfind") {
+        None
+    } else if root.child(NS_DAV, "prop").is_some() {
+        Some(PropFind::Prop(root.properties()))
+    } else {
+        Some(PropFind::AllProp)
+    }
+}
+
+pub fn parse_report(body: &[u8]) -> Option<Report> {
+    let root = Element::parse(body)?;
+    match (root.namespace.as_str(), root.name.as_str()) {
+        (NS_CARDDAV, "addressbook-multiget") | (NS_CALDAV, "calendar-multiget") => {
+            Report::Multiget {
+                properties: root.properties(),
+                hrefs: root
+                    .children
+                    .iter()
+                    .filter(|child| child.is(NS_DAV, "href"))
+                    .map(|href| href.text.trim().to_string())
+                    .collect(),
+            }
+        }
+        (NS_CARDDAV, "addressbook-query") => Report::Query {
+            properties: root.properties(),
+            time_range: None,
+        },
+        (NS_CALDAV, "calendar-query") => Report::Query {
+            properties: root.properties(),
+            time_range: root
+                .child(NS_CALDAV, "filter")
+                .and_then(|filter| filter.find(NS_CALDAV, "time-range"))
+                .map(|time_range| TimeRange {
+                    start: time_range.attribute("start").map(|v| v.to_string()),
+                    end: time_range.attribute("end").map(|v| v.to_string()),
+                }),
+        },
+        (NS_DAV, "sync-collection") => Report::SyncCollection {
+            properties: root.properties(),
+            sync_token: root
+                .child(NS_DAV, "sync-token")
+                .map(|token| token.text.trim().to_string())
+                .filter(|token| !token.is_empty()),
+        },
+        _ => return None,
+    }
+    .into()
+}
+
+impl MultiStatus {
+    pub fn new() -> Self {
+        let mut xml = String::with_capacity(1024);
+        let _ = write!(
+            xml,
+            concat!(
+                "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+                "<D:multistatus xmlns:D=\"{}\" xmlns:CARD=\"{}\" xmlns:CAL=\"{}\" ",
+                "xmlns:CS=\"{}\" xmlns:ICAL=\"{}\">"
+            ),
+            NS_DAV, NS_CARDDAV, NS_CALDAV, NS_CALENDARSERVER, NS_APPLE_ICAL
+        );
+        MultiStatus { xml }
+    }
+
+    // Values are expected to be already formatted as XML.
+    pub fn add_response(
+        &mut self,
+        href: &str,
+        found: &[(&DavProperty, String)],
+        not_found: &[&DavProperty],
+    ) {
+        let _ = write!(self.xml, "<D:response><D:href>{}</D:href>", escape(href));
+        if !found.is_empty() {
+            self.xml.push_str("<D:propstat><D:prop>");
+            for (property, value) in found {
+                property.write(&mut self.xml, Some(value));
+            }
+            self.xml
+                .push_str("</D:prop><D:status>HTTP/1.1 200 OK</D:status></D:propstat>");
+        }
+        if !not_found.is_empty() {
+            self.xml.push_str("<D:propstat><D:prop>");
+            for property in not_found {
+                property.write(&mut self.xml, None);
+            }
+            self.xml
+                .push_str("</D:prop><D:status>HTTP/1.1 404 Not Found</D:status></D:propstat>");
+        }
+        self.xml.push_str("</D:response>");
+    }
+
+    pub fn add_status(&mut self, href: &str, status: &str) {
+        let _ = write!(
+            self.xml,
+            "<D:response><D:href>{}</D:href><D:status>HTTP/1.1 {}</D:status></D:response>",
+            escape(href),
+            status
+        );
+    }
+
+    pub fn finish(mut self, sync_token: Option<&str>) -> String {
+        if let Some(sync_token) = sync_token {
+            let _ = write!(
+                self.xml,
+                "<D:sync-token>{}</D:sync-token>",
+                escape(sync_token)
+            );
+        }
+        self.xml.push_str("</D:multistatus>");
+        self.xml
+    }
+}
+
+impl Default for MultiStatus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn href(path: &str) -> String {
+    format!("<D:href>{}</D:href>", escape(path))
+}
+
+// Body of a failed precondition (RFC 4918, section 16).
+pub fn precondition_error(namespace: &str, name: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<error xmlns=\"{}\"><{}/></error>",
+        namespace, name
+    )
+}
+
+pub fn escape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '&' => result.push_str("&amp;"),
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '"' => result.push_str("&quot;"),
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_propfind, parse_report, DavProperty, PropFind, Report};
+
+    #[test]
+    fn parse_requests() {
+        assert!(matches!(parse_propfind(b""), Some(PropFind::AllProp)));
+        assert!(matches!(
+            parse_propfind(
+                br#"<propfind xmlns="DAV:" xmlns:CS="http://calendarserver.org/ns/">
+                    <prop><resourcetype/><CS:getctag/><x:color xmlns:x="urn:x"/></prop>
+                </propfind>"#
+            ),
+            Some(PropFind::Prop(properties)) if properties == [
+                DavProperty::ResourceType,
+                DavProperty::GetCTag,
+                DavProperty::Unknown {
+                    namespace: "urn:x".to_string(),
+                    name: "color".to_string()
+                }
+            ]
+        ));
+        assert!(parse_propfind(b"<prop xmlns=\"DAV:\"/>").is_none());
+
+        match parse_report(
+            br#"<C:calendar-query xmlns:D="DAV:" xmlns:C="urn:ietf:params:xml:ns:caldav">
+                <D:prop><D:getetag/></D:prop>
+                <C:filter><C:comp-filter name="VCALENDAR"><C:comp-filter name="VEVENT">
+                    <C:time-range start="20221018T000000Z"/>
+                </C:comp-filter></C:comp-filter></C:filter>
+            </C:calendar-query>"#,
+        ) {
+            Some(Report::Query {
+                properties,
+                time_range: Some(time_range),
+            }) => {
+                assert_eq!(properties, [DavProperty::GetETag]);
+                assert_eq!(time_range.start.as_deref(), Some("20221018T000000Z"));
+                assert_eq!(time_range.end, None);
+            }
+            report => panic!("Unexpected report {:?}", report),
+        }
+
+        match parse_report(
+            br#"<D:sync-collection xmlns:D="DAV:">
+                <D:sync-token>urn:x-stalwart:sync:a&amp;b</D:sync-token>
+                <D:prop><D:getetag/></D:prop>
+            </D:sync-collection>"#,
+        ) {
+            Some(Report::SyncCollection { sync_token, .. }) => {
+                assert_eq!(sync_token.as_deref(), Some("urn:x-stalwart:sync:a&b"));
+            }
+            report => panic!("Unexpected report {:?}", report),
+        }
+    }
+}
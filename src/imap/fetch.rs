@@ -0,0 +1,1028 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{sync::Arc, vec::IntoIter};
+
+use jmap::orm::serialize::JMAPOrm;
+use jmap_mail::{
+    mail::{
+        conv::HeaderValueInto,
+        schema::{Email, EmailAddress, Keyword, Property, Value},
+        HeaderValue, MessageData, MessageField, MimePart, MimePartType,
+    },
+    mail_parser::{Encoding, HeaderName, Message, PartType, RfcHeader},
+};
+use store::{
+    blob::BlobId,
+    chrono::{TimeZone, Utc},
+    core::{collection::Collection, error::StoreError, tag::Tag, vec_map::VecMap},
+    serialize::StoreDeserialize,
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use super::{
+    message::format_flags,
+    request::{parse_sequence_set, Token},
+    session::{Session, NOT_LEADER},
+    write_literal, write_nstring, write_string,
+};
+
+const FETCH_BATCH_SIZE: usize = 50;
+const MAX_NESTING_LEVEL: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Section {
+    Full,
+    Header,
+    HeaderFields { names: Vec<String>, not: bool },
+    Text,
+    Mime,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    Uid,
+    Flags,
+    InternalDate,
+    Rfc822Size,
+    Envelope,
+    Body,
+    BodyStructure,
+    Rfc822,
+    Rfc822Header,
+    Rfc822Text,
+    BodySection {
+        peek: bool,
+        label: String,
+        path: Vec<u32>,
+        section: Section,
+        partial: Option<(usize, usize)>,
+    },
+    Binary {
+        peek: bool,
+        label: String,
+        path: Vec<u32>,
+        partial: Option<(usize, usize)>,
+    },
+    BinarySize {
+        label: String,
+        path: Vec<u32>,
+    },
+}
+
+impl Item {
+    fn sets_seen(&self) -> bool {
+        matches!(
+            self,
+            Item::Rfc822
+                | Item::Rfc822Text
+                | Item::BodySection { peek: false, .. }
+                | Item::Binary { peek: false, .. }
+        )
+    }
+
+    fn needs_raw_message(&self) -> bool {
+        !matches!(
+            self,
+            Item::Uid | Item::Flags | Item::InternalDate | Item::Rfc822Size | Item::Envelope
+        )
+    }
+}
+
+// Parses the data items of a FETCH command, expanding the ALL, FAST
+// and FULL macros.
+pub fn parse_items(args: impl Iterator<Token>) -> Option<Vec<Item>> {
+    let mut items = Vec::new();
+    for token in args {
+        let atom = match token {
+            Token::Atom(atom) => atom,
+            Token::ListStart | Token::ListEnd => continue,
+            Token::String(_) => return None,
+        };
+        match atom.to_ascii_uppercase().as_str() {
+            "ALL" | "FAST" | "FULL" => {
+                items.extend([Item::Flags, Item::InternalDate, Item::Rfc822Size]);
+                if !atom.eq_ignore_ascii_case("FAST") {
+                    items.push(Item::Envelope);
+                }
+                if atom.eq_ignore_ascii_case("FULL") {
+                    items.push(Item::Body);
+                }
+            }
+            _ => items.push(parse_item(&atom)?),
+        }
+    }
+    Some(items)
+}
+
+fn parse_item(atom: &str) -> Option<Item> {
+    let upper = atom.to_ascii_uppercase();
+    let section_start = match upper.find('[') {
+        Some(section_start) => section_start,
+        None => {
+            return match upper.as_str() {
+                "UID" => Item::Uid,
+                "FLAGS" => Item::Flags,
+                "INTERNALDATE" => Item::InternalDate,
+                "RFC822.SIZE" => Item::Rfc822Size,
+                "ENVELOPE" => Item::Envelope,
+                "BODY" => Item::Body,
+                "BODYSTRUCTURE" => Item::BodyStructure,
+                "RFC822" => Item::Rfc822,
+                "RFC822.HEADER" => Item::Rfc822Header,
+                "RFC822.TEXT" => Item::Rfc822Text,
+                _ => return None,
+            }
+            .into();
+        }
+    };
+    let section_end = upper.rfind(']').filter(|&pos| pos > section_start)?;
+    let section_text = &atom[section_start + 1..section_end];
+    let partial = match &upper[section_end + 1..] {
+        "" => None,
+        partial => {
+            let (start, length) = partial
+                .strip_prefix('<')?
+                .strip_suffix('>')?
+                .split_once('.')?;
+            (start.parse().ok()?, length.parse().ok()?).into()
+        }
+    };
+    let (path, section) = parse_section(section_text)?;
+    let label = if let Some((start, _)) = partial {
+        format!("[{}]<{}>", section_text, start)
+    } else {
+        format!("[{}]", section_text)
+    };
+
+    match &upper[..section_start] {
+        name @ ("BODY" | "BODY.PEEK") => Item::BodySection {
+            peek: name == "BODY.PEEK",
+            label: format!("BODY{}", label),
+            path,
+            section,
+            partial,
+        },
+        name @ ("BINARY" | "BINARY.PEEK") if section == Section::Full => Item::Binary {
+            peek: name == "BINARY.PEEK",
+            label: format!("BINARY{}", label),
+            path,
+            partial,
+        },
+        "BINARY.SIZE" if section == Section::Full && partial.is_none() => Item::BinarySize {
+            label: format!("BINARY.SIZE{}", label),
+            path,
+        },
+        _ => return None,
+    }
+    .into()
+}
+
+fn parse_section(text: &str) -> Option<(Vec<u32>, Section)> {
+    let mut path = Vec::new();
+    let mut rest = text.trim();
+    while rest.starts_with(|ch: char| ch.is_ascii_digit()) {
+        let part_end = rest.find('.').unwrap_or(rest.len());
+        path.push(rest[..part_end].parse().ok().filter(|&part| part > 0)?);
+        rest = rest.get(part_end + 1..).unwrap_or_default();
+    }
+
+    let upper = rest.to_ascii_uppercase();
+    let section = match upper.as_str() {
+        "" => Section::Full,
+        "HEADER" => Section::Header,
+        "TEXT" => Section::Text,
+        "MIME" if !path.is_empty() => Section::Mime,
+        _ => {
+            let fields = upper.strip_prefix("HEADER.FIELDS")?;
+            let (not, fields) = match fields.strip_prefix(".NOT") {
+                Some(fields) => (true, fields),
+                None => (false, fields),
+            };
+            let names = fields
+                .trim()
+                .strip_prefix('(')?
+                .strip_suffix(')')?
+                .split_whitespace()
+                .map(|name| name.trim_matches('"').to_string())
+                .collect::<Vec<_>>();
+            if names.is_empty() {
+                return None;
+            }
+            Section::HeaderFields { names, not }
+        }
+    };
+
+    Some((path, section))
+}
+
+pub fn get_message_data<T>(
+    store: &JMAPStore<T>,
+    account_id: AccountId,
+    document_id: DocumentId,
+) -> store::Result<Option<MessageData>>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    if let Some(blob_id) = store.get_document_value::<BlobId>(
+        account_id,
+        Collection::Mail,
+        document_id,
+        MessageField::Metadata.into(),
+    )? {
+        if let Some(bytes) = store.blob_get(&blob_id)? {
+            return MessageData::deserialize(&bytes)
+                .ok_or_else(|| {
+                    StoreError::DataCorruption(format!(
+                        "Failed to deserialize email metadata for {}/{}",
+                        account_id, document_id
+                    ))
+                })
+                .map(Some);
+        }
+    }
+    Ok(None)
+}
+
+// A message or an attached message/rfc822 part along with its raw contents.
+#[derive(Debug, Default)]
+pub struct ParsedMessage {
+    pub raw: Vec<u8>,
+    pub headers: VecMap<RfcHeader, Vec<HeaderValue>>,
+    pub mime_parts: Vec<MimePart>,
+    pub body_offset: usize,
+}
+
+enum PartRef<'x> {
+    Local(usize),
+    Nested(ParsedMessage, &'x [u32]),
+}
+
+impl ParsedMessage {
+    pub fn parse(raw: Vec<u8>) -> Option<Self> {
+        let (headers, mime_parts, body_offset) = {
+            let message = Message::parse(&raw)?;
+            let body_offset = message.get_root_part().offset_body;
+            let mut headers = VecMap::new();
+            let mut mime_parts = Vec::with_capacity(message.parts.len());
+
+            for (part_id, mut part) in message.parts.into_iter().enumerate() {
+                if part_id == 0 {
+                    for header in part.headers.iter_mut() {
+                        let header_name = if let HeaderName::Rfc(header_name) = &header.name {
+                            *header_name
+                        } else {
+                            continue;
+                        };
+                        let value = std::mem::take(&mut header.value);
+                        if let Some(value) = match header_name {
+                            RfcHeader::MessageId | RfcHeader::InReplyTo => value.into_keyword(),
+                            RfcHeader::From
+                            | RfcHeader::Sender
+                            | RfcHeader::ReplyTo
+                            | RfcHeader::To
+                            | RfcHeader::Cc
+                            | RfcHeader::Bcc => value.into_address(),
+                            RfcHeader::Date => value.into_date(),
+                            RfcHeader::Subject => value.into_text(),
+                            _ => {
+                                // Keep MIME headers for the root part
+                                header.value = value;
+                                continue;
+                            }
+                        } {
+                            headers.get_mut_or_insert(header_name).push(value);
+                        }
+                    }
+                }
+
+                let message_part = jmap_mail::mail::MessagePart {
+                    offset_start: part.offset_body,
+                    offset_end: part.offset_end,
+                    encoding: part.encoding,
+                };
+                let mime_type = match part.body {
+                    PartType::Text(_) => MimePartType::Text { part: message_part },
+                    PartType::Html(_) => MimePartType::Html { part: message_part },
+                    PartType::Multipart(subparts) => MimePartType::MultiPart { subparts },
+                    _ => MimePartType::Other { part: message_part },
+                };
+                mime_parts.push(MimePart::from_headers(
+                    part.headers,
+                    mime_type,
+                    part.is_encoding_problem,
+                    0,
+                ));
+            }
+            (headers, mime_parts, body_offset)
+        };
+
+        Some(ParsedMessage {
+            raw,
+            headers,
+            mime_parts,
+            body_offset,
+        })
+    }
+
+    fn nested(&self, part_id: usize) -> Option<ParsedMessage> {
+        let part = self.mime_parts.get(part_id)?;
+        if is_message(part) {
+            ParsedMessage::parse(part.mime_type.part()?.decode(&self.raw)?)
+        } else {
+            None
+        }
+    }
+
+    fn find_part<'x>(&self, path: &'x [u32]) -> Option<PartRef<'x>> {
+        let mut part_id = 0;
+        for (pos, &number) in path.iter().enumerate() {
+            match &self.mime_parts.get(part_id)?.mime_type {
+                MimePartType::MultiPart { subparts } => {
+                    part_id = *subparts.get(number as usize - 1)?;
+                }
+                _ if pos == 0 && number == 1 => (),
+                _ => return PartRef::Nested(self.nested(part_id)?, &path[pos..]).into(),
+            }
+        }
+        PartRef::Local(part_id).into()
+    }
+
+    pub fn get_section(&self, path: &[u32], section: &Section) -> Option<Vec<u8>> {
+        if path.is_empty() {
+            return match section {
+                Section::Full => self.raw.clone().into(),
+                Section::Header => self.raw.get(..self.body_offset)?.to_vec().into(),
+                Section::Text => self.raw.get(self.body_offset..)?.to_vec().into(),
+                Section::HeaderFields { names, not } => {
+                    filter_headers(self.raw.get(..self.body_offset)?, names, *not).into()
+                }
+                Section::Mime => None,
+            };
+        }
+
+        match self.find_part(path)? {
+            PartRef::Local(part_id) => match section {
+                Section::Full => {
+                    let part = self.mime_parts[part_id].mime_type.part()?;
+                    self.raw
+                        .get(part.offset_start..part.offset_end)?
+                        .to_vec()
+                        .into()
+                }
+                Section::Mime => self.part_header(part_id),
+                _ => self.nested(part_id)?.get_section(&[], section),
+            },
+            PartRef::Nested(message, path) => message.get_section(path, section),
+        }
+    }
+
+    pub fn get_binary(&self, path: &[u32]) -> Option<Vec<u8>> {
+        if path.is_empty() {
+            return self.raw.clone().into();
+        }
+        match self.find_part(path)? {
+            PartRef::Local(part_id) => self.mime_parts[part_id].mime_type.part()?.decode(&self.raw),
+            PartRef::Nested(message, path) => message.get_binary(path),
+        }
+    }
+
+    // Returns the header block of a body part, including the trailing
+    // empty line.
+    fn part_header(&self, part_id: usize) -> Option<Vec<u8>> {
+        let part = self.mime_parts.get(part_id)?;
+        let mut header = if let (Some(start), Some(end)) = (
+            part.raw_headers.iter().map(|(_, start, _)| *start).min(),
+            part.raw_headers.iter().map(|(_, _, end)| *end).max(),
+        ) {
+            let start = self.raw[..start]
+                .iter()
+                .rposition(|&ch| ch == b'\n')
+                .map_or(0, |pos| pos + 1);
+            let end = self.raw[end.saturating_sub(1)..]
+                .iter()
+                .position(|&ch| ch == b'\n')
+                .map_or(self.raw.len(), |pos| end + pos);
+            self.raw.get(start..end)?.to_vec()
+        } else {
+            Vec::new()
+        };
+        header.extend_from_slice(b"\r\n");
+        Some(header)
+    }
+
+    pub fn write_envelope(&self, buf: &mut Vec<u8>) {
+        let header = |name: RfcHeader| self.headers.get(&name).and_then(|values| values.last());
+        let date = match header(RfcHeader::Date) {
+            Some(HeaderValue::Timestamp(timestamp)) => Utc
+                .timestamp_opt(*timestamp, 0)
+                .single()
+                .map(|date| date.to_rfc2822()),
+            _ => None,
+        };
+        let subject = match header(RfcHeader::Subject) {
+            Some(HeaderValue::Text(subject)) => Some(subject.as_str()),
+            _ => None,
+        };
+        let from = header(RfcHeader::From);
+
+        buf.push(b'(');
+        write_nstring(buf, date.as_deref());
+        buf.push(b' ');
+        write_nstring(buf, subject);
+        for value in [
+            from,
+            header(RfcHeader::Sender).or(from),
+            header(RfcHeader::ReplyTo).or(from),
+            header(RfcHeader::To),
+            header(RfcHeader::Cc),
+            header(RfcHeader::Bcc),
+        ] {
+            buf.push(b' ');
+            write_addresses(buf, value);
+        }
+        for name in [RfcHeader::InReplyTo, RfcHeader::MessageId] {
+            buf.push(b' ');
+            let ids = match header(name) {
+                Some(HeaderValue::TextList(ids)) if !ids.is_empty() => Some(
+                    ids.iter()
+                        .map(|id| format!("<{}>", id))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ),
+                Some(HeaderValue::Text(id)) => Some(format!("<{}>", id)),
+                _ => None,
+            };
+            write_nstring(buf, ids.as_deref());
+        }
+        buf.push(b')');
+    }
+
+    pub fn write_body_structure(
+        &self,
+        buf: &mut Vec<u8>,
+        part_id: usize,
+        is_extended: bool,
+        level: usize,
+    ) {
+        let part = if let Some(part) = self.mime_parts.get(part_id) {
+            part
+        } else {
+            buf.extend_from_slice(b"NIL");
+            return;
+        };
+        let (type_, subtype) = part
+            .type_
+            .as_deref()
+            .and_then(|type_| type_.split_once('/'))
+            .unwrap_or(match &part.mime_type {
+                MimePartType::MultiPart { .. } => ("multipart", "mixed"),
+                MimePartType::Html { .. } => ("text", "html"),
+                _ => ("text", "plain"),
+            });
+
+        buf.push(b'(');
+        let message_part = match &part.mime_type {
+            MimePartType::MultiPart { subparts } => {
+                for &subpart_id in subparts {
+                    self.write_body_structure(buf, subpart_id, is_extended, level);
+                }
+                buf.push(b' ');
+                write_string(buf, subtype.to_ascii_uppercase().as_bytes());
+                if is_extended {
+                    buf.extend_from_slice(b" NIL");
+                    write_body_extension(buf, part);
+                }
+                buf.push(b')');
+                return;
+            }
+            MimePartType::Text { part }
+            | MimePartType::Html { part }
+            | MimePartType::Other { part } => part,
+        };
+        let contents = self
+            .raw
+            .get(message_part.offset_start..message_part.offset_end)
+            .unwrap_or_default();
+        let is_text = type_.eq_ignore_ascii_case("text");
+
+        write_string(buf, type_.to_ascii_uppercase().as_bytes());
+        buf.push(b' ');
+        write_string(buf, subtype.to_ascii_uppercase().as_bytes());
+        buf.push(b' ');
+
+        // Body parameters
+        let mut params = Vec::new();
+        if is_text {
+            params.push(("CHARSET", part.charset.as_deref().unwrap_or("us-ascii")));
+        }
+        if let Some(name) = &part.name {
+            params.push(("NAME", name.as_str()));
+        }
+        write_params(buf, &params);
+
+        buf.push(b' ');
+        write_nstring(
+            buf,
+            part.cid.as_ref().map(|cid| format!("<{}>", cid)).as_deref(),
+        );
+        buf.extend_from_slice(
+            format!(
+                " NIL \"{}\" {}",
+                match message_part.encoding {
+                    Encoding::Base64 => "BASE64",
+                    Encoding::QuotedPrintable => "QUOTED-PRINTABLE",
+                    Encoding::None if contents.iter().any(|&ch| ch > 0x7f) => "8BIT",
+                    Encoding::None => "7BIT",
+                },
+                contents.len()
+            )
+            .as_bytes(),
+        );
+
+        if is_message(part) {
+            if let Some(message) = self.nested(part_id).filter(|_| level < MAX_NESTING_LEVEL) {
+                buf.push(b' ');
+                message.write_envelope(buf);
+                buf.push(b' ');
+                message.write_body_structure(buf, 0, is_extended, level + 1);
+                buf.extend_from_slice(format!(" {}", count_lines(&message.raw)).as_bytes());
+            }
+        } else if is_text {
+            buf.extend_from_slice(format!(" {}", count_lines(contents)).as_bytes());
+        }
+
+        if is_extended {
+            buf.extend_from_slice(b" NIL");
+            write_body_extension(buf, part);
+        }
+        buf.push(b')');
+    }
+}
+
+fn is_message(part: &MimePart) -> bool {
+    part.type_.as_ref().map_or(false, |type_| {
+        type_.eq_ignore_ascii_case("message/rfc822") || type_.eq_ignore_ascii_case("message/global")
+    })
+}
+
+fn count_lines(bytes: &[u8]) -> usize {
+    bytes.iter().filter(|&&ch| ch == b'\n').count()
+}
+
+// Returns the header lines matching (or not matching) the requested names,
+// followed by an empty line.
+pub fn filter_headers(header: &[u8], names: &[String], not: bool) -> Vec<u8> {
+    let mut result = Vec::new();
+    let mut include = false;
+    for line in header.split_inclusive(|&ch| ch == b'\n') {
+        match line.first() {
+            Some(b' ' | b'\t') => (),
+            Some(b'\r' | b'\n') => break,
+            _ => {
+                let name = line.split(|&ch| ch == b':').next().unwrap_or_default();
+                let name = std::str::from_utf8(name).unwrap_or_default().trim();
+                include = names.iter().any(|n| n.eq_ignore_ascii_case(name)) != not;
+            }
+        }
+        if include {
+            result.extend_from_slice(line);
+        }
+    }
+    result.extend_from_slice(b"\r\n");
+    result
+}
+
+fn write_params(buf: &mut Vec<u8>, params: &[(&str, &str)]) {
+    if params.is_empty() {
+        buf.extend_from_slice(b"NIL");
+        return;
+    }
+    buf.push(b'(');
+    for (pos, (name, value)) in params.iter().enumerate() {
+        if pos > 0 {
+            buf.push(b' ');
+        }
+        write_string(buf, name.as_bytes());
+        buf.push(b' ');
+        write_string(buf, value.as_bytes());
+    }
+    buf.push(b')');
+}
+
+// Writes the disposition, language and location of a body part.
+fn write_body_extension(buf: &mut Vec<u8>, part: &MimePart) {
+    buf.push(b' ');
+    if let Some(disposition) = &part.disposition {
+        buf.push(b'(');
+        write_string(buf, disposition.to_ascii_uppercase().as_bytes());
+        buf.push(b' ');
+        if let Some(name) = &part.name {
+            write_params(buf, &[("FILENAME", name.as_str())]);
+        } else {
+            buf.extend_from_slice(b"NIL");
+        }
+        buf.push(b')');
+    } else {
+        buf.extend_from_slice(b"NIL");
+    }
+    buf.push(b' ');
+    match &part.language {
+        Some(languages) if !languages.is_empty() => {
+            buf.push(b'(');
+            for (pos, language) in languages.iter().enumerate() {
+                if pos > 0 {
+                    buf.push(b' ');
+                }
+                write_string(buf, language.as_bytes());
+            }
+            buf.push(b')');
+        }
+        _ => buf.extend_from_slice(b"NIL"),
+    }
+    buf.push(b' ');
+    write_nstring(buf, part.location.as_deref());
+}
+
+fn write_addresses(buf: &mut Vec<u8>, value: Option<&HeaderValue>) {
+    match value {
+        Some(HeaderValue::Addresses(addresses)) if !addresses.is_empty() => {
+            buf.push(b'(');
+            for address in addresses {
+                write_address(buf, address);
+            }
+            buf.push(b')');
+        }
+        Some(HeaderValue::GroupedAddresses(groups)) if !groups.is_empty() => {
+            buf.push(b'(');
+            for group in groups {
+                if let Some(name) = &group.name {
+                    buf.extend_from_slice(b"(NIL NIL ");
+                    write_string(buf, name.as_bytes());
+                    buf.extend_from_slice(b" NIL)");
+                    for address in &group.addresses {
+                        write_address(buf, address);
+                    }
+                    buf.extend_from_slice(b"(NIL NIL NIL NIL)");
+                } else {
+                    for address in &group.addresses {
+                        write_address(buf, address);
+                    }
+                }
+            }
+            buf.push(b')');
+        }
+        _ => buf.extend_from_slice(b"NIL"),
+    }
+}
+
+fn write_address(buf: &mut Vec<u8>, address: &EmailAddress) {
+    let (mailbox, host) = address
+        .email
+        .rsplit_once('@')
+        .unwrap_or((address.email.as_str(), ""));
+    buf.push(b'(');
+    write_nstring(buf, address.name.as_deref());
+    buf.extend_from_slice(b" NIL ");
+    write_string(buf, mailbox.as_bytes());
+    buf.push(b' ');
+    write_string(buf, host.as_bytes());
+    buf.push(b')');
+}
+
+fn write_partial(buf: &mut Vec<u8>, bytes: Option<Vec<u8>>, partial: Option<(usize, usize)>) {
+    match (bytes, partial) {
+        (Some(bytes), Some((start, length))) => write_literal(
+            buf,
+            bytes
+                .get(start..std::cmp::min(start.saturating_add(length), bytes.len()))
+                .unwrap_or_default(),
+        ),
+        (Some(bytes), None) => write_literal(buf, &bytes),
+        (None, _) => buf.extend_from_slice(b"NIL"),
+    }
+}
+
+fn fetch_message<T>(
+    store: &JMAPStore<T>,
+    account_id: AccountId,
+    (seq, uid, document_id): (usize, u32, DocumentId),
+    items: &[Item],
+    buf: &mut Vec<u8>,
+) -> store::Result<()>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let fields = if let Some(fields) = store.get_orm::<Email>(account_id, document_id)? {
+        fields
+    } else {
+        return Ok(());
+    };
+    let (message, received_at, size) = if items
+        .iter()
+        .any(|item| !matches!(item, Item::Uid | Item::Flags))
+    {
+        let message_data =
+            if let Some(message_data) = get_message_data(store, account_id, document_id)? {
+                message_data
+            } else {
+                return Ok(());
+            };
+        let raw = if items.iter().any(|item| item.needs_raw_message()) {
+            store.blob_get(&message_data.raw_message)?.ok_or_else(|| {
+                StoreError::NotFound(format!(
+                    "Raw message blob linked to {}/{} does not exist.",
+                    account_id, document_id
+                ))
+            })?
+        } else {
+            Vec::new()
+        };
+        (
+            ParsedMessage {
+                raw,
+                headers: message_data.headers,
+                mime_parts: message_data.mime_parts,
+                body_offset: message_data.body_offset,
+            },
+            message_data.received_at,
+            message_data.size,
+        )
+    } else {
+        (ParsedMessage::default(), 0, 0)
+    };
+
+    buf.extend_from_slice(format!("* {} FETCH (", seq).as_bytes());
+    for (pos, item) in items.iter().enumerate() {
+        if pos > 0 {
+            buf.push(b' ');
+        }
+        match item {
+            Item::Uid => buf.extend_from_slice(format!("UID {}", uid).as_bytes()),
+            Item::Flags => buf.extend_from_slice(
+                format!(
+                    "FLAGS ({})",
+                    format_flags(fields.get_tags(&Property::Keywords))
+                )
+                .as_bytes(),
+            ),
+            Item::InternalDate => buf.extend_from_slice(
+                format!(
+                    "INTERNALDATE \"{}\"",
+                    Utc.timestamp_opt(received_at, 0)
+                        .single()
+                        .unwrap_or_else(Utc::now)
+                        .format("%d-%b-%Y %H:%M:%S %z")
+                )
+                .as_bytes(),
+            ),
+            Item::Rfc822Size => buf.extend_from_slice(format!("RFC822.SIZE {}", size).as_bytes()),
+            Item::Envelope => {
+                buf.extend_from_slice(b"ENVELOPE ");
+                message.write_envelope(buf);
+            }
+            Item::Body | Item::BodyStructure => {
+                let is_extended = item == &Item::BodyStructure;
+                buf.extend_from_slice(if is_extended {
+                    b"BODYSTRUCTURE "
+                } else {
+                    b"BODY "
+                });
+                message.write_body_structure(buf, 0, is_extended, 0);
+            }
+            Item::Rfc822 => {
+                buf.extend_from_slice(b"RFC822 ");
+                write_literal(buf, &message.raw);
+            }
+            Item::Rfc822Header => {
+                buf.extend_from_slice(b"RFC822.HEADER ");
+                write_partial(buf, message.get_section(&[], &Section::Header), None);
+            }
+            Item::Rfc822Text => {
+                buf.extend_from_slice(b"RFC822.TEXT ");
+                write_partial(buf, message.get_section(&[], &Section::Text), None);
+            }
+            Item::BodySection {
+                label,
+                path,
+                section,
+                partial,
+                ..
+            } => {
+                buf.extend_from_slice(label.as_bytes());
+                buf.push(b' ');
+                write_partial(buf, message.get_section(path, section), *partial);
+            }
+            Item::Binary {
+                label,
+                path,
+                partial,
+                ..
+            } => {
+                buf.extend_from_slice(label.as_bytes());
+                buf.push(b' ');
+                write_partial(buf, message.get_binary(path), *partial);
+            }
+            Item::BinarySize { label, path } => buf.extend_from_slice(
+                format!(
+                    "{} {}",
+                    label,
+                    message.get_binary(path).map_or(0, |bytes| bytes.len())
+                )
+                .as_bytes(),
+            ),
+        }
+    }
+    buf.extend_from_slice(b")\r\n");
+
+    Ok(())
+}
+
+impl<T> Session<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub async fn handle_fetch(
+        &mut self,
+        tag: String,
+        mut args: IntoIter<Token>,
+        is_uid: bool,
+    ) -> Result<(), ()> {
+        let sequence = match args
+            .next()
+            .and_then(|t| t.unwrap_atom())
+            .and_then(|s| parse_sequence_set(&s))
+        {
+            Some(sequence) => sequence,
+            None => return self.write_bad(&tag, "Invalid sequence set.").await,
+        };
+        let mut items = match parse_items(args) {
+            Some(items) if !items.is_empty() => items,
+            _ => return self.write_bad(&tag, "Invalid fetch items.").await,
+        };
+        if is_uid && !items.contains(&Item::Uid) {
+            items.insert(0, Item::Uid);
+        }
+
+        let selected = self.selected.as_ref().unwrap();
+        let messages = selected.resolve(&sequence, is_uid);
+        let account_id = self.account_id();
+
+        // Fetching a message body without PEEK sets the \Seen flag
+        if !selected.read_only && items.iter().any(|item| item.sets_seen()) {
+            if !self.core.is_leader() {
+                return self.write_no(&tag, NOT_LEADER).await;
+            }
+            if !items.contains(&Item::Flags) {
+                items.push(Item::Flags);
+            }
+
+            let store = self.core.store.clone();
+            let document_ids = messages
+                .iter()
+                .map(|(_, _, document_id)| *document_id)
+                .collect::<Vec<_>>();
+            let unseen_ids = match self
+                .core
+                .spawn_worker(move || {
+                    let seen_ids = store
+                        .get_tag(
+                            account_id,
+                            Collection::Mail,
+                            MessageField::Keyword.into(),
+                            Tag::Static(Keyword::SEEN),
+                        )?
+                        .unwrap_or_default();
+                    Ok(document_ids
+                        .into_iter()
+                        .filter(|document_id| !seen_ids.contains(*document_id))
+                        .collect::<Vec<_>>())
+                })
+                .await
+            {
+                Ok(unseen_ids) => unseen_ids,
+                Err(err) => return self.write_server_error(&tag, err).await,
+            };
+
+            let update = unseen_ids
+                .into_iter()
+                .map(|document_id| {
+                    let mut value = VecMap::with_capacity(1);
+                    value.append(Keyword::new(Tag::Static(Keyword::SEEN)), true);
+                    let mut email = Email::default();
+                    email.insert(Property::Keywords, Value::Keywords { value, set: false });
+                    (document_id, email)
+                })
+                .collect::<Vec<_>>();
+            if let Err(message) = self.set_emails(update, Vec::new()).await {
+                return self.write_no(&tag, &message).await;
+            }
+        }
+
+        let items = Arc::new(items);
+        for batch in messages.chunks(FETCH_BATCH_SIZE) {
+            let batch = batch.to_vec();
+            let items = items.clone();
+            let store = self.core.store.clone();
+            match self
+                .core
+                .spawn_worker(move || {
+                    let mut buf = Vec::with_capacity(batch.len() * 128);
+                    for message in batch {
+                        fetch_message(&store, account_id, message, &items, &mut buf)?;
+                    }
+                    Ok(buf)
+                })
+                .await
+            {
+                Ok(buf) => self.write_bytes(&buf).await?,
+                Err(err) => return self.write_server_error(&tag, err).await,
+            }
+        }
+
+        self.write_ok(&tag, "FETCH completed.").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{filter_headers, parse_item, Item, Section};
+
+    #[test]
+    fn imap_fetch_items() {
+        assert_eq!(parse_item("uid"), Some(Item::Uid));
+        assert_eq!(parse_item("X-UNKNOWN"), None);
+        assert_eq!(
+            parse_item("BODY.PEEK[HEADER.FIELDS (From To)]<0.100>"),
+            Some(Item::BodySection {
+                peek: true,
+                label: "BODY[HEADER.FIELDS (From To)]<0>".to_string(),
+                path: vec![],
+                section: Section::HeaderFields {
+                    names: vec!["FROM".to_string(), "TO".to_string()],
+                    not: false
+                },
+                partial: Some((0, 100)),
+            })
+        );
+        assert_eq!(
+            parse_item("body[1.2.MIME]"),
+            Some(Item::BodySection {
+                peek: false,
+                label: "BODY[1.2.MIME]".to_string(),
+                path: vec![1, 2],
+                section: Section::Mime,
+                partial: None,
+            })
+        );
+        assert_eq!(
+            parse_item("BINARY.SIZE[2]"),
+            Some(Item::BinarySize {
+                label: "BINARY.SIZE[2]".to_string(),
+                path: vec![2],
+            })
+        );
+        assert_eq!(parse_item("BODY[MIME]"), None);
+        assert_eq!(parse_item("BINARY[1.TEXT]"), None);
+    }
+
+    #[test]
+    fn imap_filter_headers() {
+        let header = concat!(
+            "From: jdoe@example.com\r\n",
+            "Subject: Hello\r\n",
+            "  world\r\n",
+            "To: alice@example.com\r\n",
+            "\r\n"
+        )
+        .as_bytes();
+        assert_eq!(
+            String::from_utf8(filter_headers(header, &["SUBJECT".to_string()], false)).unwrap(),
+            "Subject: Hello\r\n  world\r\n\r\n"
+        );
+        assert_eq!(
+            String::from_utf8(filter_headers(header, &["subject".to_string()], true)).unwrap(),
+            "From: jdoe@example.com\r\nTo: alice@example.com\r\n\r\n"
+        );
+    }
+}
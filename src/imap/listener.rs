@@ -0,0 +1,206 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use actix_web::web;
+use store::{
+    config::env_settings::EnvSettings,
+    tracing::{debug, error, info},
+    Store,
+};
+use tokio::{net::TcpListener, sync::watch};
+use tokio_rustls::TlsAcceptor;
+
+use crate::{
+    cluster::rpc::tls::load_tls_server_config_with_resolver, lmtp::session::Stream,
+    server::failed_to, JMAPServer,
+};
+
+use super::session::Session;
+
+const TIMEOUT: Duration = Duration::from_secs(30 * 60); // 30 minutes
+
+pub fn spawn_imap<T>(
+    core: web::Data<JMAPServer<T>>,
+    settings: &EnvSettings,
+    shutdown_rx: watch::Receiver<bool>,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    // The IMAP service is only started when a port is configured
+    let bind_ip = settings.parse_ipaddr("imap-bind-addr", "0.0.0.0");
+    let mut listeners = Vec::new();
+    if let Some(port) = settings.parse::<u16>("imap-port") {
+        listeners.push((SocketAddr::from((bind_ip, port)), false));
+    }
+    if let Some(port) = settings.parse::<u16>("imap-tls-port") {
+        listeners.push((SocketAddr::from((bind_ip, port)), true));
+    }
+    if listeners.is_empty() {
+        return;
+    }
+
+    // Build TLS acceptor
+    let tls_acceptor = core.reload.imap_tls.clone().map(|resolver| {
+        Arc::new(TlsAcceptor::from(Arc::new(
+            load_tls_server_config_with_resolver(resolver),
+        )))
+    });
+    if tls_acceptor.is_none() && listeners.iter().any(|(_, implicit_tls)| *implicit_tls) {
+        failed_to("parse 'imap-tls-port', no TLS certificate was provided.");
+    }
+    let allow_plain_auth = settings.parse("imap-plaintext-auth").unwrap_or(false);
+
+    for (bind_addr, implicit_tls) in listeners {
+        info!("Starting IMAP service at {}...", bind_addr);
+
+        let core = core.clone();
+        let tls_acceptor = tls_acceptor.clone();
+        let mut shutdown_rx = shutdown_rx.clone();
+
+        tokio::spawn(async move {
+            // Start listening for IMAP connections.
+            let listener = match TcpListener::bind(bind_addr).await {
+                Ok(listener) => listener,
+                Err(err) => {
+                    error!("Failed to bind IMAP service to {}: {}", bind_addr, err);
+                    return;
+                }
+            };
+
+            loop {
+                tokio::select! {
+                    stream = listener.accept() => {
+                        match stream {
+                            Ok((stream, peer_addr)) => {
+                                let shutdown_rx = shutdown_rx.clone();
+                                let core = core.clone();
+                                let tls_acceptor = tls_acceptor.clone();
+
+                                tokio::spawn(async move {
+                                    let (stream, tls_acceptor) = if implicit_tls {
+                                        match tls_acceptor.as_ref().unwrap().accept(stream).await {
+                                            Ok(stream) => (Stream::from(stream), None),
+                                            Err(e) => {
+                                                debug!("Failed to accept TLS connection: {}", e);
+                                                return;
+                                            }
+                                        }
+                                    } else {
+                                        (Stream::from(stream), tls_acceptor)
+                                    };
+
+                                    // Send greeting
+                                    let mut session = Session::new(core, peer_addr, stream, tls_acceptor, allow_plain_auth);
+                                    let greeting = format!(
+                                        concat!(
+                                            "* OK [CAPABILITY {}] Stalwart IMAP4rev2 v",
+                                            env!("CARGO_PKG_VERSION"),
+                                            " ready.\r\n"
+                                        ),
+                                        session.capabilities()
+                                    );
+                                    if session.write_bytes(greeting.as_bytes()).await.is_err() {
+                                        debug!("Failed to send greeting to {}.", peer_addr);
+                                        return;
+                                    }
+
+                                    handle_conn(session, shutdown_rx).await;
+                                });
+                            }
+                            Err(err) => {
+                                error!("Failed to accept TCP connection: {}", err);
+                            }
+                        }
+                    },
+                    _ = shutdown_rx.changed() => {
+                        debug!("IMAP listener shutting down.");
+                        break;
+                    }
+                };
+            }
+        });
+    }
+}
+
+pub async fn handle_conn<T>(mut session: Session<T>, mut shutdown_rx: watch::Receiver<bool>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut buf = vec![0; 4096];
+
+    loop {
+        // State changes are only received while the client is idling
+        let mut state_rx = session.state_rx.take();
+
+        tokio::select! {
+            result = tokio::time::timeout(
+                TIMEOUT,
+                session.read_bytes(&mut buf)) => {
+                session.state_rx = state_rx;
+                match result {
+                    Ok(Ok(bytes_read)) => {
+                        if bytes_read > 0 {
+                            if session.ingest(&buf[..bytes_read]).await.is_err() {
+                                debug!("Disconnecting client.");
+                                return;
+                            }
+                        } else {
+                            debug!("IMAP connection closed by {}", session.peer_addr);
+                            break;
+                        }
+                    },
+                    Ok(Err(_)) => {
+                        break;
+                    },
+                    Err(_) => {
+                        session.write_bytes(b"* BYE Disconnecting inactive client.\r\n").await.ok();
+                        debug!("IMAP connection timed out with {}.", session.peer_addr);
+                        break;
+                    }
+                }
+            },
+            state_change = async {
+                if let Some(state_rx) = state_rx.as_mut() {
+                    state_rx.recv().await
+                } else {
+                    std::future::pending().await
+                }
+            } => {
+                if state_change.is_some() {
+                    session.state_rx = state_rx;
+                    if session.handle_state_change().await.is_err() {
+                        debug!("Disconnecting client.");
+                        return;
+                    }
+                }
+            },
+            _ = shutdown_rx.changed() => {
+                session.write_bytes(b"* BYE Server shutting down.\r\n").await.ok();
+                debug!("IMAP connection with peer {} shutting down.", session.peer_addr);
+                return;
+            }
+        };
+    }
+}
@@ -0,0 +1,877 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::vec::IntoIter;
+
+use jmap::{
+    orm::serialize::JMAPOrm,
+    request::{set::SetRequest, MaybeResultReference},
+    types::jmap::JMAPId,
+};
+use jmap_mail::{
+    mail::{schema::Keyword, uid::JMAPMailUids, MessageField},
+    mailbox::{
+        schema::{Mailbox, Property, Value},
+        set::SetArguments,
+    },
+};
+use store::{
+    ahash::AHashMap,
+    core::{collection::Collection, tag::Tag, vec_map::VecMap},
+    roaring::RoaringBitmap,
+    AccountId, DocumentId, JMAPStore, Store,
+};
+
+use crate::api::method;
+
+use super::{
+    fetch::get_message_data,
+    request::Token,
+    session::{set_error_code, SelectedMailbox, Session, NOT_LEADER},
+    utf7_decode, utf7_encode, write_string,
+};
+
+#[derive(Debug, Clone)]
+pub struct MailboxInfo {
+    pub id: DocumentId,
+    pub name: String,
+    pub role: Option<String>,
+    pub is_subscribed: bool,
+    pub has_children: bool,
+    pub uid_validity: u32,
+}
+
+// Lists the account's mailboxes using their full hierarchical name.
+pub fn get_mailboxes<T>(
+    store: &JMAPStore<T>,
+    account_id: AccountId,
+) -> store::Result<Vec<MailboxInfo>>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut entries = Vec::new();
+    for document_id in store
+        .get_document_ids(account_id, Collection::Mailbox)?
+        .unwrap_or_default()
+    {
+        if let Some(fields) = store.get_orm::<Mailbox>(account_id, document_id)? {
+            let name = match fields.get(&Property::Name) {
+                Some(Value::Text { value }) => value.to_string(),
+                _ => continue,
+            };
+            let parent_id = match fields.get(&Property::ParentId) {
+                Some(Value::Id { value }) => value.get_document_id(),
+                _ => 0,
+            };
+            let role = match fields.get(&Property::Role) {
+                Some(Value::Text { value }) => Some(value.to_string()),
+                _ => None,
+            };
+            let is_subscribed = matches!(
+                fields.get(&Property::IsSubscribed),
+                Some(Value::Subscriptions { value }) if value.contains(&account_id)
+            );
+            let uid_validity = match fields.get(&Property::UidValidity) {
+                Some(Value::Number { value }) => *value,
+                _ => 1,
+            };
+            entries.push((
+                document_id,
+                name,
+                parent_id,
+                role,
+                is_subscribed,
+                uid_validity,
+            ));
+        }
+    }
+
+    let positions = entries
+        .iter()
+        .enumerate()
+        .map(|(pos, entry)| (entry.0, pos))
+        .collect::<AHashMap<_, _>>();
+    let mut mailboxes = Vec::with_capacity(entries.len());
+    for (document_id, _, _, role, is_subscribed, uid_validity) in &entries {
+        // Walk up the tree, parent ids are stored incremented by one.
+        let mut path = Vec::new();
+        let mut current_id = *document_id;
+        while let Some(&pos) = positions.get(&current_id) {
+            let (_, name, parent_id, role, _, _) = &entries[pos];
+            path.push(if role.as_deref() == Some("inbox") {
+                "INBOX"
+            } else {
+                name.as_str()
+            });
+            if *parent_id == 0 || path.len() > entries.len() {
+                break;
+            }
+            current_id = parent_id - 1;
+        }
+        path.reverse();
+
+        mailboxes.push(MailboxInfo {
+            id: *document_id,
+            name: path.join("/"),
+            role: role.clone(),
+            is_subscribed: *is_subscribed,
+            has_children: entries
+                .iter()
+                .any(|(_, _, parent_id, _, _, _)| *parent_id == document_id + 1),
+            uid_validity: *uid_validity,
+        });
+    }
+    mailboxes.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(mailboxes)
+}
+
+pub fn find_mailbox<'x>(mailboxes: &'x [MailboxInfo], name: &str) -> Option<&'x MailboxInfo> {
+    let name = normalize_name(name);
+    mailboxes.iter().find(|mailbox| mailbox.name == name)
+}
+
+// INBOX is case-insensitive, trailing hierarchy delimiters are ignored.
+pub fn normalize_name(name: &str) -> String {
+    let name = name.trim_end_matches('/');
+    match name.get(..5) {
+        Some(prefix)
+            if prefix.eq_ignore_ascii_case("INBOX")
+                && (name.len() == 5 || name.as_bytes()[5] == b'/') =>
+        {
+            format!("INBOX{}", &name[5..])
+        }
+        _ => name.to_string(),
+    }
+}
+
+// Matches a mailbox name against a LIST pattern, '*' matches any
+// character while '%' does not match the hierarchy delimiter.
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    fn matches(pattern: &[char], name: &[char]) -> bool {
+        match pattern.first() {
+            None => name.is_empty(),
+            Some('*') => (0..=name.len()).any(|pos| matches(&pattern[1..], &name[pos..])),
+            Some('%') => (0..=name.len())
+                .take_while(|&pos| pos == 0 || name[pos - 1] != '/')
+                .any(|pos| matches(&pattern[1..], &name[pos..])),
+            Some(ch) => name.first() == Some(ch) && matches(&pattern[1..], &name[1..]),
+        }
+    }
+
+    matches(
+        &normalize_name(pattern).chars().collect::<Vec<_>>(),
+        &name.chars().collect::<Vec<_>>(),
+    )
+}
+
+pub fn special_use(role: &str) -> Option<&'static str> {
+    match role {
+        "archive" => "\\Archive".into(),
+        "drafts" => "\\Drafts".into(),
+        "junk" => "\\Junk".into(),
+        "sent" => "\\Sent".into(),
+        "trash" => "\\Trash".into(),
+        "all" => "\\All".into(),
+        "flagged" => "\\Flagged".into(),
+        _ => None,
+    }
+}
+
+impl<T> Session<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Mailbox names are exchanged in modified UTF-7 until IMAP4rev2 is enabled.
+    pub fn decode_mailbox_name(&self, name: String) -> String {
+        if !self.is_rev2 {
+            utf7_decode(&name).unwrap_or(name)
+        } else {
+            name
+        }
+    }
+
+    pub fn encode_mailbox_name(&self, name: &str) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(name.len() + 2);
+        if !self.is_rev2 {
+            write_string(&mut buf, utf7_encode(name).as_bytes());
+        } else {
+            write_string(&mut buf, name.as_bytes());
+        }
+        buf
+    }
+
+    pub async fn handle_select(
+        &mut self,
+        tag: String,
+        mut args: IntoIter<Token>,
+        read_only: bool,
+    ) -> Result<(), ()> {
+        let name = match args.next().and_then(|t| t.unwrap_string()) {
+            Some(name) => self.decode_mailbox_name(name),
+            None => return self.write_bad(&tag, "Expected mailbox name.").await,
+        };
+
+        // A failed SELECT also closes the current mailbox
+        self.selected = None;
+
+        let account_id = self.account_id();
+        let store = self.core.store.clone();
+        match self
+            .core
+            .spawn_worker(move || {
+                let mailboxes = get_mailboxes(&store, account_id)?;
+                if let Some(mailbox) = find_mailbox(&mailboxes, &name) {
+                    let change_id = store.get_last_change_id(account_id, Collection::Mail)?;
+                    Ok(Some((
+                        mailbox.id,
+                        mailbox.uid_validity,
+                        store.mail_mailbox_uids(account_id, mailbox.id)?,
+                        store.mail_uid_next(account_id, mailbox.id)?,
+                        change_id,
+                    )))
+                } else {
+                    Ok(None)
+                }
+            })
+            .await
+        {
+            Ok(Some((mailbox_id, uid_validity, messages, uid_next, change_id))) => {
+                let mut response = format!(
+                    concat!(
+                        "* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft)\r\n",
+                        "* OK [PERMANENTFLAGS ({})] Flags permitted.\r\n",
+                        "* {} EXISTS\r\n"
+                    ),
+                    if !read_only {
+                        "\\Answered \\Flagged \\Deleted \\Seen \\Draft \\*"
+                    } else {
+                        ""
+                    },
+                    messages.len()
+                );
+                if !self.is_rev2 {
+                    response.push_str("* 0 RECENT\r\n");
+                }
+                response.push_str(&format!(
+                    concat!(
+                        "* OK [UIDVALIDITY {}] UIDs valid.\r\n",
+                        "* OK [UIDNEXT {}] Next UID.\r\n",
+                        "{} OK [{}] {} completed.\r\n"
+                    ),
+                    uid_validity,
+                    uid_next,
+                    tag,
+                    if read_only { "READ-ONLY" } else { "READ-WRITE" },
+                    if read_only { "EXAMINE" } else { "SELECT" }
+                ));
+
+                let (uids, document_ids) = messages.into_iter().unzip();
+                self.selected = SelectedMailbox {
+                    id: mailbox_id,
+                    read_only,
+                    uid_validity,
+                    uids,
+                    document_ids,
+                    change_id,
+                }
+                .into();
+                self.write_bytes(response.as_bytes()).await
+            }
+            Ok(None) => {
+                self.write_no(&tag, "[NONEXISTENT] Mailbox does not exist.")
+                    .await
+            }
+            Err(err) => self.write_server_error(&tag, err).await,
+        }
+    }
+
+    pub async fn handle_close(&mut self, tag: String, expunge: bool) -> Result<(), ()> {
+        if expunge && !self.selected.as_ref().unwrap().read_only {
+            // Messages are removed silently
+            if let Err(message) = self.expunge_deleted(None).await {
+                return self.write_no(&tag, &message).await;
+            }
+        }
+        self.selected = None;
+        self.write_ok(
+            &tag,
+            if expunge {
+                "CLOSE completed."
+            } else {
+                "UNSELECT completed."
+            },
+        )
+        .await
+    }
+
+    pub async fn handle_create(
+        &mut self,
+        tag: String,
+        mut args: IntoIter<Token>,
+    ) -> Result<(), ()> {
+        let name = match args.next().and_then(|t| t.unwrap_string()) {
+            Some(name) => normalize_name(&self.decode_mailbox_name(name)),
+            None => return self.write_bad(&tag, "Expected mailbox name.").await,
+        };
+        if name.is_empty() || name.split('/').any(|part| part.is_empty()) {
+            return self.write_no(&tag, "[CANNOT] Invalid mailbox name.").await;
+        } else if !self.core.is_leader() {
+            return self.write_no(&tag, NOT_LEADER).await;
+        }
+
+        // Locate the deepest existing parent, missing parents are created
+        let account_id = self.account_id();
+        let store = self.core.store.clone();
+        let (parent_id, missing) = match self
+            .core
+            .spawn_worker(move || {
+                let mailboxes = get_mailboxes(&store, account_id)?;
+                let parts = name.split('/').collect::<Vec<_>>();
+                let mut parent_id = None;
+                for pos in 0..parts.len() {
+                    if let Some(mailbox) = find_mailbox(&mailboxes, &parts[..=pos].join("/")) {
+                        parent_id = mailbox.id.into();
+                    } else {
+                        return Ok((
+                            parent_id,
+                            parts[pos..]
+                                .iter()
+                                .map(|part| part.to_string())
+                                .collect::<Vec<_>>(),
+                        ));
+                    }
+                }
+                Ok((parent_id, Vec::new()))
+            })
+            .await
+        {
+            Ok((_, missing)) if missing.is_empty() => {
+                return self
+                    .write_no(&tag, "[ALREADYEXISTS] Mailbox already exists.")
+                    .await;
+            }
+            Ok(result) => result,
+            Err(err) => return self.write_server_error(&tag, err).await,
+        };
+
+        let mut create = VecMap::with_capacity(missing.len());
+        for (pos, name) in missing.into_iter().enumerate() {
+            let mut mailbox = Mailbox::default();
+            mailbox
+                .properties
+                .append(Property::Name, Value::Text { value: name });
+            if pos > 0 {
+                mailbox.properties.append(
+                    Property::ParentId,
+                    Value::IdReference {
+                        value: format!("m{}", pos - 1),
+                    },
+                );
+            } else if let Some(parent_id) = parent_id {
+                mailbox.properties.append(
+                    Property::ParentId,
+                    Value::Id {
+                        value: parent_id.into(),
+                    },
+                );
+            }
+            create.append(format!("m{}", pos), mailbox);
+        }
+
+        self.set_mailbox(&tag, "CREATE", create.into(), None, None)
+            .await
+    }
+
+    pub async fn handle_delete(
+        &mut self,
+        tag: String,
+        mut args: IntoIter<Token>,
+    ) -> Result<(), ()> {
+        let mailbox = match self.mailbox_from_args(&tag, args.next(), false).await? {
+            Some(mailbox) => mailbox,
+            None => return Ok(()),
+        };
+        if mailbox.name == "INBOX" {
+            return self
+                .write_no(&tag, "[CANNOT] INBOX cannot be deleted.")
+                .await;
+        } else if !self.core.is_leader() {
+            return self.write_no(&tag, NOT_LEADER).await;
+        }
+
+        self.set_mailbox(&tag, "DELETE", None, None, vec![mailbox.id].into())
+            .await
+    }
+
+    pub async fn handle_rename(
+        &mut self,
+        tag: String,
+        mut args: IntoIter<Token>,
+    ) -> Result<(), ()> {
+        let mailbox = match self.mailbox_from_args(&tag, args.next(), false).await? {
+            Some(mailbox) => mailbox,
+            None => return Ok(()),
+        };
+        let new_name = match args.next().and_then(|t| t.unwrap_string()) {
+            Some(name) => normalize_name(&self.decode_mailbox_name(name)),
+            None => return self.write_bad(&tag, "Expected new mailbox name.").await,
+        };
+        if mailbox.name == "INBOX" {
+            return self
+                .write_no(&tag, "[CANNOT] Renaming INBOX is not supported.")
+                .await;
+        } else if new_name.is_empty() || new_name.split('/').any(|part| part.is_empty()) {
+            return self.write_no(&tag, "[CANNOT] Invalid mailbox name.").await;
+        } else if !self.core.is_leader() {
+            return self.write_no(&tag, NOT_LEADER).await;
+        }
+
+        let (parent_name, name) = new_name
+            .rsplit_once('/')
+            .map_or(("", new_name.as_str()), |(parent, name)| (parent, name));
+        let account_id = self.account_id();
+        let store = self.core.store.clone();
+        let parent_name_ = parent_name.to_string();
+        let new_name_ = new_name.clone();
+        let parent_id = match self
+            .core
+            .spawn_worker(move || {
+                let mailboxes = get_mailboxes(&store, account_id)?;
+                Ok(if find_mailbox(&mailboxes, &new_name_).is_some() {
+                    Err("[ALREADYEXISTS] Mailbox already exists.")
+                } else if parent_name_.is_empty() {
+                    Ok(None)
+                } else if let Some(parent) = find_mailbox(&mailboxes, &parent_name_) {
+                    Ok(Some(parent.id))
+                } else {
+                    Err("[NONEXISTENT] Parent mailbox does not exist.")
+                })
+            })
+            .await
+        {
+            Ok(Ok(parent_id)) => parent_id,
+            Ok(Err(message)) => return self.write_no(&tag, message).await,
+            Err(err) => return self.write_server_error(&tag, err).await,
+        };
+
+        let mut changes = Mailbox::default();
+        changes.properties.append(
+            Property::Name,
+            Value::Text {
+                value: name.to_string(),
+            },
+        );
+        changes.properties.append(
+            Property::ParentId,
+            parent_id.map_or(Value::Null, |parent_id| Value::Id {
+                value: parent_id.into(),
+            }),
+        );
+        let mut update = VecMap::with_capacity(1);
+        update.append(JMAPId::from(mailbox.id), changes);
+
+        self.set_mailbox(&tag, "RENAME", None, update.into(), None)
+            .await
+    }
+
+    pub async fn handle_subscribe(
+        &mut self,
+        tag: String,
+        mut args: IntoIter<Token>,
+        subscribe: bool,
+    ) -> Result<(), ()> {
+        let mailbox = match self.mailbox_from_args(&tag, args.next(), false).await? {
+            Some(mailbox) => mailbox,
+            None => return Ok(()),
+        };
+        let command = if subscribe {
+            "SUBSCRIBE"
+        } else {
+            "UNSUBSCRIBE"
+        };
+        if mailbox.is_subscribed == subscribe {
+            return self
+                .write_ok(&tag, &format!("{} completed.", command))
+                .await;
+        } else if !self.core.is_leader() {
+            return self.write_no(&tag, NOT_LEADER).await;
+        }
+
+        let mut changes = Mailbox::default();
+        changes
+            .properties
+            .append(Property::IsSubscribed, Value::Bool { value: subscribe });
+        let mut update = VecMap::with_capacity(1);
+        update.append(JMAPId::from(mailbox.id), changes);
+
+        self.set_mailbox(&tag, command, None, update.into(), None)
+            .await
+    }
+
+    pub async fn handle_list(
+        &mut self,
+        tag: String,
+        args: IntoIter<Token>,
+        is_lsub: bool,
+    ) -> Result<(), ()> {
+        let mut args = args.peekable();
+        let mut subscribed_only = is_lsub;
+        let command = if is_lsub { "LSUB" } else { "LIST" };
+
+        // Selection options (RFC 5258)
+        if !is_lsub && matches!(args.peek(), Some(Token::ListStart)) {
+            args.next();
+            for token in args.by_ref() {
+                match token {
+                    Token::ListEnd => break,
+                    Token::Atom(option) if option.eq_ignore_ascii_case("SUBSCRIBED") => {
+                        subscribed_only = true;
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let reference = match args.next().and_then(|t| t.unwrap_string()) {
+            Some(reference) => self.decode_mailbox_name(reference),
+            None => return self.write_bad(&tag, "Expected reference name.").await,
+        };
+        let mut patterns = Vec::new();
+        match args.next() {
+            Some(Token::ListStart) => {
+                for token in args.by_ref() {
+                    if let Some(pattern) = token.unwrap_string() {
+                        patterns.push(self.decode_mailbox_name(pattern));
+                    } else {
+                        break;
+                    }
+                }
+            }
+            Some(token) => {
+                if let Some(pattern) = token.unwrap_string() {
+                    patterns.push(self.decode_mailbox_name(pattern));
+                }
+            }
+            None => (),
+        }
+        if patterns.is_empty() {
+            return self.write_bad(&tag, "Expected mailbox pattern.").await;
+        } else if patterns.len() == 1 && patterns[0].is_empty() {
+            // Request for the hierarchy delimiter
+            return self
+                .write_bytes(
+                    format!(
+                        "* {} (\\Noselect) \"/\" \"\"\r\n{} OK {} completed.\r\n",
+                        command, tag, command
+                    )
+                    .as_bytes(),
+                )
+                .await;
+        }
+
+        let account_id = self.account_id();
+        let store = self.core.store.clone();
+        let mailboxes = match self
+            .core
+            .spawn_worker(move || get_mailboxes(&store, account_id))
+            .await
+        {
+            Ok(mailboxes) => mailboxes,
+            Err(err) => return self.write_server_error(&tag, err).await,
+        };
+
+        let mut response = Vec::with_capacity(mailboxes.len() * 32);
+        for mailbox in mailboxes {
+            if (subscribed_only && !mailbox.is_subscribed)
+                || !patterns.iter().any(|pattern| {
+                    matches_pattern(&format!("{}{}", reference, pattern), &mailbox.name)
+                })
+            {
+                continue;
+            }
+
+            let mut attributes = vec![if mailbox.has_children {
+                "\\HasChildren"
+            } else {
+                "\\HasNoChildren"
+            }];
+            if mailbox.is_subscribed && !is_lsub {
+                attributes.push("\\Subscribed");
+            }
+            if let Some(special_use) = mailbox.role.as_deref().and_then(special_use) {
+                attributes.push(special_use);
+            }
+            response.extend_from_slice(
+                format!("* {} ({}) \"/\" ", command, attributes.join(" ")).as_bytes(),
+            );
+            response.extend_from_slice(&self.encode_mailbox_name(&mailbox.name));
+            response.extend_from_slice(b"\r\n");
+        }
+        response.extend_from_slice(format!("{} OK {} completed.\r\n", tag, command).as_bytes());
+
+        self.write_bytes(&response).await
+    }
+
+    pub async fn handle_status(
+        &mut self,
+        tag: String,
+        mut args: IntoIter<Token>,
+    ) -> Result<(), ()> {
+        let name = match args.next().and_then(|t| t.unwrap_string()) {
+            Some(name) => self.decode_mailbox_name(name),
+            None => return self.write_bad(&tag, "Expected mailbox name.").await,
+        };
+        let mut items = Vec::new();
+        for token in args {
+            if let Token::Atom(item) = token {
+                let item = item.to_ascii_uppercase();
+                if ![
+                    "MESSAGES",
+                    "UIDNEXT",
+                    "UIDVALIDITY",
+                    "UNSEEN",
+                    "RECENT",
+                    "DELETED",
+                    "SIZE",
+                ]
+                .contains(&item.as_str())
+                {
+                    return self
+                        .write_bad(&tag, &format!("Unsupported status item '{}'.", item))
+                        .await;
+                }
+                items.push(item);
+            }
+        }
+
+        let account_id = self.account_id();
+        let store = self.core.store.clone();
+        let name_ = name.clone();
+        match self
+            .core
+            .spawn_worker(move || {
+                let mailboxes = get_mailboxes(&store, account_id)?;
+                let mailbox = if let Some(mailbox) = find_mailbox(&mailboxes, &name_) {
+                    mailbox
+                } else {
+                    return Ok(None);
+                };
+                let message_ids = store
+                    .get_tag(
+                        account_id,
+                        Collection::Mail,
+                        MessageField::Mailbox.into(),
+                        Tag::Id(mailbox.id),
+                    )?
+                    .unwrap_or_default();
+                let keyword_ids = |keyword| -> store::Result<RoaringBitmap> {
+                    Ok(store
+                        .get_tag(
+                            account_id,
+                            Collection::Mail,
+                            MessageField::Keyword.into(),
+                            Tag::Static(keyword),
+                        )?
+                        .unwrap_or_default())
+                };
+
+                let mut values = Vec::with_capacity(items.len());
+                for item in items {
+                    let value = match item.as_str() {
+                        "MESSAGES" => message_ids.len(),
+                        "UIDNEXT" => store.mail_uid_next(account_id, mailbox.id)? as u64,
+                        "UIDVALIDITY" => mailbox.uid_validity as u64,
+                        "UNSEEN" => (&message_ids - &keyword_ids(Keyword::SEEN)?).len(),
+                        "DELETED" => (&message_ids & &keyword_ids(Keyword::DELETED)?).len(),
+                        "SIZE" => {
+                            let mut size = 0;
+                            for document_id in &message_ids {
+                                if let Some(message_data) =
+                                    get_message_data(&store, account_id, document_id)?
+                                {
+                                    size += message_data.size as u64;
+                                }
+                            }
+                            size
+                        }
+                        _ => 0,
+                    };
+                    values.push(format!("{} {}", item, value));
+                }
+                Ok(Some(values))
+            })
+            .await
+        {
+            Ok(Some(values)) => {
+                let mut response = b"* STATUS ".to_vec();
+                response.extend_from_slice(&self.encode_mailbox_name(&normalize_name(&name)));
+                response.extend_from_slice(
+                    format!(
+                        " ({})\r\n{} OK STATUS completed.\r\n",
+                        values.join(" "),
+                        tag
+                    )
+                    .as_bytes(),
+                );
+                self.write_bytes(&response).await
+            }
+            Ok(None) => {
+                self.write_no(&tag, "[NONEXISTENT] Mailbox does not exist.")
+                    .await
+            }
+            Err(err) => self.write_server_error(&tag, err).await,
+        }
+    }
+
+    // Looks up a mailbox by name, a missing APPEND, COPY or MOVE target
+    // is reported with TRYCREATE.
+    pub async fn mailbox_from_args(
+        &mut self,
+        tag: &str,
+        token: Option<Token>,
+        is_target: bool,
+    ) -> Result<Option<MailboxInfo>, ()> {
+        let name = match token.and_then(|t| t.unwrap_string()) {
+            Some(name) => self.decode_mailbox_name(name),
+            None => {
+                self.write_bad(tag, "Expected mailbox name.").await?;
+                return Ok(None);
+            }
+        };
+
+        let account_id = self.account_id();
+        let store = self.core.store.clone();
+        match self
+            .core
+            .spawn_worker(move || {
+                Ok(find_mailbox(&get_mailboxes(&store, account_id)?, &name).cloned())
+            })
+            .await
+        {
+            Ok(Some(mailbox)) => Ok(Some(mailbox)),
+            Ok(None) => {
+                self.write_no(
+                    tag,
+                    if is_target {
+                        "[TRYCREATE] Mailbox does not exist."
+                    } else {
+                        "[NONEXISTENT] Mailbox does not exist."
+                    },
+                )
+                .await?;
+                Ok(None)
+            }
+            Err(err) => {
+                self.write_server_error(tag, err).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    async fn set_mailbox(
+        &mut self,
+        tag: &str,
+        command: &str,
+        create: Option<VecMap<String, Mailbox>>,
+        update: Option<VecMap<JMAPId, Mailbox>>,
+        destroy: Option<Vec<DocumentId>>,
+    ) -> Result<(), ()> {
+        match self
+            .call_method(method::Request::SetMailbox(SetRequest {
+                acl: None,
+                account_id: JMAPId::from(self.account_id()),
+                if_in_state: None,
+                create,
+                update,
+                destroy: destroy.map(|ids| {
+                    MaybeResultReference::Value(ids.into_iter().map(JMAPId::from).collect())
+                }),
+                arguments: SetArguments {
+                    on_destroy_remove_emails: true.into(),
+                },
+            }))
+            .await
+        {
+            Some(method::Response::SetMailbox(response)) => {
+                if let Some(err) = response
+                    .not_created
+                    .into_iter()
+                    .map(|(_, err)| err)
+                    .chain(response.not_updated.into_iter().map(|(_, err)| err))
+                    .chain(response.not_destroyed.into_iter().map(|(_, err)| err))
+                    .next()
+                {
+                    self.write_no(
+                        tag,
+                        &format!(
+                            "{} {} failed: {}.",
+                            set_error_code(&err.type_),
+                            command,
+                            err.type_.as_str()
+                        ),
+                    )
+                    .await
+                } else {
+                    self.write_ok(tag, &format!("{} completed.", command)).await
+                }
+            }
+            Some(method::Response::Error(err)) => self.write_server_error(tag, err).await,
+            _ => self.write_server_error(tag, "unexpected response").await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{matches_pattern, normalize_name};
+
+    #[test]
+    fn imap_list_pattern() {
+        for (pattern, name, expected) in [
+            ("*", "INBOX", true),
+            ("*", "Archive/2022/May", true),
+            ("%", "Archive", true),
+            ("%", "Archive/2022", false),
+            ("Archive/%", "Archive/2022", true),
+            ("Archive/%", "Archive/2022/May", false),
+            ("Archive/*", "Archive/2022/May", true),
+            ("inbox", "INBOX", true),
+            ("Inbox/%", "INBOX/Orders", true),
+            ("A*e", "Archive", true),
+            ("A%e", "Archive/Inside", false),
+            ("Sent", "Sent Items", false),
+        ] {
+            assert_eq!(
+                matches_pattern(pattern, name),
+                expected,
+                "{} {}",
+                pattern,
+                name
+            );
+        }
+
+        assert_eq!(normalize_name("inbox/"), "INBOX");
+        assert_eq!(normalize_name("Inboxes"), "Inboxes");
+        assert_eq!(normalize_name("iNbOx/Child"), "INBOX/Child");
+    }
+}
@@ -0,0 +1,703 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::vec::IntoIter;
+
+use jmap::{
+    orm::serialize::JMAPOrm,
+    request::{set::SetRequest, MaybeIdReference, MaybeResultReference},
+    types::{blob::JMAPBlob, date::JMAPDate, jmap::JMAPId},
+};
+use jmap_mail::mail::{
+    import::{EmailImport, EmailImportRequest},
+    schema::{Email, Keyword, Property, Value},
+    uid::JMAPMailUids,
+    MessageField,
+};
+use store::{
+    ahash::AHashSet,
+    blob::BlobId,
+    core::{collection::Collection, tag::Tag, vec_map::VecMap},
+    tracing::error,
+    DocumentId, Store,
+};
+
+use crate::api::method;
+
+use super::{
+    request::{parse_datetime, parse_sequence_set, Sequence, Token},
+    session::{set_error_code, SelectedMailbox, Session, NOT_LEADER},
+};
+
+// Formats a message's keywords as IMAP flags, system flags are mapped
+// to their JMAP counterparts while other keywords are passed as is.
+pub fn format_flags(tags: Option<&AHashSet<Tag>>) -> String {
+    let mut flags = String::new();
+    for tag in tags.into_iter().flatten() {
+        let flag = match tag {
+            Tag::Static(Keyword::SEEN) => "\\Seen".to_string(),
+            Tag::Static(Keyword::ANSWERED) => "\\Answered".to_string(),
+            Tag::Static(Keyword::FLAGGED) => "\\Flagged".to_string(),
+            Tag::Static(Keyword::DELETED) => "\\Deleted".to_string(),
+            Tag::Static(Keyword::DRAFT) => "\\Draft".to_string(),
+            Tag::Static(Keyword::RECENT) => continue,
+            _ => Keyword::new(tag.clone()).to_string(),
+        };
+        if !flags.is_empty() {
+            flags.push(' ');
+        }
+        flags.push_str(&flag);
+    }
+    flags
+}
+
+// Parses an IMAP flag, \Recent and unknown system flags are ignored.
+pub fn parse_flag(flag: &str) -> Option<Keyword> {
+    if let Some(system_flag) = flag.strip_prefix('\\') {
+        Keyword::new(Tag::Static(
+            match system_flag.to_ascii_lowercase().as_str() {
+                "seen" => Keyword::SEEN,
+                "answered" => Keyword::ANSWERED,
+                "flagged" => Keyword::FLAGGED,
+                "deleted" => Keyword::DELETED,
+                "draft" => Keyword::DRAFT,
+                _ => return None,
+            },
+        ))
+        .into()
+    } else if !flag.is_empty() {
+        Keyword::parse(flag).into()
+    } else {
+        None
+    }
+}
+
+// Formats a list of UIDs as a sequence set, i.e. 1:3,5
+pub fn format_uids(uids: &[u32]) -> String {
+    let mut result = String::new();
+    let mut pos = 0;
+    while pos < uids.len() {
+        let start = uids[pos];
+        let mut end = start;
+        while pos + 1 < uids.len() && uids[pos + 1] == end + 1 {
+            end += 1;
+            pos += 1;
+        }
+        if !result.is_empty() {
+            result.push(',');
+        }
+        if start == end {
+            result.push_str(&start.to_string());
+        } else {
+            result.push_str(&format!("{}:{}", start, end));
+        }
+        pos += 1;
+    }
+    result
+}
+
+impl SelectedMailbox {
+    // Returns the sequence number, UID and document id of the messages in a
+    // sequence set, skipping those that have been expunged meanwhile.
+    pub fn resolve(&self, sequence: &[Sequence], is_uid: bool) -> Vec<(usize, u32, DocumentId)> {
+        let max = if is_uid {
+            self.uids.last().copied().unwrap_or(0)
+        } else {
+            self.uids.len() as u32
+        };
+        self.uids
+            .iter()
+            .zip(self.document_ids.iter())
+            .enumerate()
+            .filter(|(pos, (uid, document_id))| {
+                let value = if is_uid { **uid } else { (*pos + 1) as u32 };
+                **document_id != DocumentId::MAX && sequence.iter().any(|s| s.contains(value, max))
+            })
+            .map(|(pos, (uid, document_id))| (pos + 1, *uid, *document_id))
+            .collect()
+    }
+}
+
+impl<T> Session<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub async fn handle_append(
+        &mut self,
+        tag: String,
+        mut args: IntoIter<Token>,
+    ) -> Result<(), ()> {
+        let mailbox = match self.mailbox_from_args(&tag, args.next(), true).await? {
+            Some(mailbox) => mailbox,
+            None => return Ok(()),
+        };
+        let mut args = args.collect::<Vec<_>>();
+        let raw_message = match args.pop() {
+            Some(Token::String(raw_message)) if !raw_message.is_empty() => raw_message,
+            _ => return self.write_bad(&tag, "Expected message literal.").await,
+        };
+        let mut keywords = VecMap::new();
+        let mut received_at = None;
+        for token in args {
+            match token {
+                Token::Atom(flag) => {
+                    if let Some(keyword) = parse_flag(&flag) {
+                        keywords.append(keyword, true);
+                    }
+                }
+                Token::String(date) => {
+                    if let Some(date) = std::str::from_utf8(&date).ok().and_then(parse_datetime) {
+                        received_at = JMAPDate::from_timestamp(date).into();
+                    } else {
+                        return self.write_bad(&tag, "Invalid date-time.").await;
+                    }
+                }
+                Token::ListStart | Token::ListEnd => (),
+            }
+        }
+        if !self.core.is_leader() {
+            return self.write_no(&tag, NOT_LEADER).await;
+        }
+
+        // Store the message as a blob, then import it
+        let account_id = self.account_id();
+        let store = self.core.store.clone();
+        let blob_id = match self
+            .core
+            .spawn_worker(move || {
+                let blob_id = BlobId::new_external(&raw_message);
+                store.blob_store(&blob_id, raw_message)?;
+                store.blob_link_ephemeral(&blob_id, account_id)?;
+                Ok(blob_id)
+            })
+            .await
+        {
+            Ok(blob_id) => blob_id,
+            Err(err) => return self.write_server_error(&tag, err).await,
+        };
+
+        let mut mailbox_ids = VecMap::new();
+        mailbox_ids.append(MaybeIdReference::Value(mailbox.id.into()), true);
+        let mut emails = VecMap::new();
+        emails.append(
+            "m".to_string(),
+            EmailImport {
+                blob_id: JMAPBlob::new(blob_id),
+                mailbox_ids: MaybeResultReference::Value(mailbox_ids).into(),
+                keywords: keywords.into(),
+                received_at,
+            },
+        );
+
+        match self
+            .call_method(method::Request::ImportEmail(EmailImportRequest {
+                acl: None,
+                account_id: JMAPId::from(account_id),
+                if_in_state: None,
+                emails,
+            }))
+            .await
+        {
+            Some(method::Response::ImportEmail(response)) => {
+                if let Some((_, err)) = response.not_created.and_then(|e| e.into_iter().next()) {
+                    return self
+                        .write_no(
+                            &tag,
+                            &format!(
+                                "{} Failed to append message: {}.",
+                                set_error_code(&err.type_),
+                                err.type_.as_str()
+                            ),
+                        )
+                        .await;
+                }
+                let document_id = response
+                    .created
+                    .and_then(|e| e.into_iter().next())
+                    .and_then(|(_, email)| match email.properties.get(&Property::Id) {
+                        Some(Value::Id { value }) => Some(value.get_document_id()),
+                        _ => None,
+                    });
+                let uid = if let Some(document_id) = document_id {
+                    let account_id = self.account_id();
+                    let store = self.core.store.clone();
+                    match self
+                        .core
+                        .spawn_worker(move || store.mail_uid(account_id, document_id, mailbox.id))
+                        .await
+                    {
+                        Ok(uid) => uid.unwrap_or(0),
+                        Err(err) => return self.write_server_error(&tag, err).await,
+                    }
+                } else {
+                    0
+                };
+
+                if self.selected.is_some() {
+                    self.synchronize(false).await?;
+                }
+                self.write_ok(
+                    &tag,
+                    &format!(
+                        "[APPENDUID {} {}] APPEND completed.",
+                        mailbox.uid_validity, uid
+                    ),
+                )
+                .await
+            }
+            Some(method::Response::Error(err)) => self.write_server_error(&tag, err).await,
+            _ => self.write_server_error(&tag, "unexpected response").await,
+        }
+    }
+
+    pub async fn handle_store(
+        &mut self,
+        tag: String,
+        mut args: IntoIter<Token>,
+        is_uid: bool,
+    ) -> Result<(), ()> {
+        let sequence = match args
+            .next()
+            .and_then(|t| t.unwrap_atom())
+            .and_then(|s| parse_sequence_set(&s))
+        {
+            Some(sequence) => sequence,
+            None => return self.write_bad(&tag, "Invalid sequence set.").await,
+        };
+        let operation = args
+            .next()
+            .and_then(|t| t.unwrap_atom())
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+        let (operation, is_silent) = match operation.strip_suffix(".SILENT") {
+            Some(operation) => (operation.to_string(), true),
+            None => (operation, false),
+        };
+        let (is_replace, is_add) = match operation.as_str() {
+            "FLAGS" => (true, true),
+            "+FLAGS" => (false, true),
+            "-FLAGS" => (false, false),
+            _ => {
+                return self
+                    .write_bad(&tag, "Expected FLAGS, +FLAGS or -FLAGS.")
+                    .await
+            }
+        };
+        let keywords = args
+            .filter_map(|t| t.unwrap_atom().and_then(|flag| parse_flag(&flag)))
+            .collect::<Vec<_>>();
+
+        let selected = self.selected.as_ref().unwrap();
+        if selected.read_only {
+            return self
+                .write_no(&tag, "[READ-ONLY] Mailbox is read-only.")
+                .await;
+        } else if !self.core.is_leader() {
+            return self.write_no(&tag, NOT_LEADER).await;
+        }
+        let messages = selected.resolve(&sequence, is_uid);
+
+        let update = messages
+            .iter()
+            .map(|(_, _, document_id)| {
+                let mut value = VecMap::with_capacity(keywords.len());
+                for keyword in &keywords {
+                    value.append(keyword.clone(), is_add);
+                }
+                let mut email = Email::default();
+                email.insert(
+                    Property::Keywords,
+                    Value::Keywords {
+                        value,
+                        set: is_replace,
+                    },
+                );
+                (*document_id, email)
+            })
+            .collect::<Vec<_>>();
+        if let Err(message) = self.set_emails(update, Vec::new()).await {
+            return self.write_no(&tag, &message).await;
+        }
+
+        if !is_silent {
+            let account_id = self.account_id();
+            let store = self.core.store.clone();
+            let messages_ = messages.clone();
+            let flags = match self
+                .core
+                .spawn_worker(move || {
+                    let mut flags = Vec::with_capacity(messages_.len());
+                    for (_, _, document_id) in messages_ {
+                        flags.push(
+                            store
+                                .get_orm::<Email>(account_id, document_id)?
+                                .map(|fields| format_flags(fields.get_tags(&Property::Keywords))),
+                        );
+                    }
+                    Ok(flags)
+                })
+                .await
+            {
+                Ok(flags) => flags,
+                Err(err) => return self.write_server_error(&tag, err).await,
+            };
+
+            let mut response = String::with_capacity(messages.len() * 32);
+            for ((seq, uid, _), flags) in messages.into_iter().zip(flags) {
+                if let Some(flags) = flags {
+                    if is_uid {
+                        response.push_str(&format!(
+                            "* {} FETCH (FLAGS ({}) UID {})\r\n",
+                            seq, flags, uid
+                        ));
+                    } else {
+                        response.push_str(&format!("* {} FETCH (FLAGS ({}))\r\n", seq, flags));
+                    }
+                }
+            }
+            self.write_bytes(response.as_bytes()).await?;
+        }
+
+        self.write_ok(&tag, "STORE completed.").await
+    }
+
+    pub async fn handle_copy(
+        &mut self,
+        tag: String,
+        mut args: IntoIter<Token>,
+        is_uid: bool,
+        is_move: bool,
+    ) -> Result<(), ()> {
+        let command = if is_move { "MOVE" } else { "COPY" };
+        let sequence = match args
+            .next()
+            .and_then(|t| t.unwrap_atom())
+            .and_then(|s| parse_sequence_set(&s))
+        {
+            Some(sequence) => sequence,
+            None => return self.write_bad(&tag, "Invalid sequence set.").await,
+        };
+        let mailbox = match self.mailbox_from_args(&tag, args.next(), true).await? {
+            Some(mailbox) => mailbox,
+            None => return Ok(()),
+        };
+
+        let selected = self.selected.as_ref().unwrap();
+        if is_move && selected.read_only {
+            return self
+                .write_no(&tag, "[READ-ONLY] Mailbox is read-only.")
+                .await;
+        } else if !self.core.is_leader() {
+            return self.write_no(&tag, NOT_LEADER).await;
+        }
+        let source_id = selected.id;
+        let messages = selected.resolve(&sequence, is_uid);
+        if messages.is_empty() {
+            return self
+                .write_ok(&tag, &format!("{} completed, no messages.", command))
+                .await;
+        }
+
+        // Messages are linked to the target mailbox rather than duplicated,
+        // which gives them a new UID there.
+        let update = messages
+            .iter()
+            .map(|(_, _, document_id)| {
+                let mut value = VecMap::with_capacity(2);
+                value.append(MaybeIdReference::Value(mailbox.id.into()), true);
+                if is_move && mailbox.id != source_id {
+                    value.append(MaybeIdReference::Value(source_id.into()), false);
+                }
+                let mut email = Email::default();
+                email.insert(
+                    Property::MailboxIds,
+                    Value::MailboxIds { value, set: false },
+                );
+                (*document_id, email)
+            })
+            .collect::<Vec<_>>();
+        let document_ids = match self.set_emails(update, Vec::new()).await {
+            Ok(document_ids) => document_ids,
+            Err(message) => return self.write_no(&tag, &message).await,
+        };
+
+        // Map the source UIDs to the ones assigned in the target mailbox
+        let account_id = self.account_id();
+        let store = self.core.store.clone();
+        let target_id = mailbox.id;
+        let uids = match self
+            .core
+            .spawn_worker(move || {
+                let mut uids = Vec::with_capacity(messages.len());
+                for (_, uid, document_id) in messages {
+                    if document_ids.contains(&document_id) {
+                        if let Some(target_uid) =
+                            store.mail_uid(account_id, document_id, target_id)?
+                        {
+                            uids.push((uid, target_uid));
+                        }
+                    }
+                }
+                Ok(uids)
+            })
+            .await
+        {
+            Ok(uids) => uids,
+            Err(err) => return self.write_server_error(&tag, err).await,
+        };
+        let copy_uid = format!(
+            "COPYUID {} {} {}",
+            mailbox.uid_validity,
+            format_uids(&uids.iter().map(|(uid, _)| *uid).collect::<Vec<_>>()),
+            format_uids(&uids.iter().map(|(_, uid)| *uid).collect::<Vec<_>>())
+        );
+
+        if is_move {
+            self.write_bytes(format!("* OK [{}] Moved.\r\n", copy_uid).as_bytes())
+                .await?;
+            self.synchronize(true).await?;
+            self.write_ok(&tag, "MOVE completed.").await
+        } else {
+            self.synchronize(false).await?;
+            self.write_ok(&tag, &format!("[{}] COPY completed.", copy_uid))
+                .await
+        }
+    }
+
+    pub async fn handle_expunge(&mut self, tag: String, uids: Option<String>) -> Result<(), ()> {
+        let uids = match uids {
+            Some(uids) => match parse_sequence_set(&uids) {
+                Some(sequence) => Some(sequence),
+                None => return self.write_bad(&tag, "Invalid UID set.").await,
+            },
+            None => None,
+        };
+        if self.selected.as_ref().unwrap().read_only {
+            return self
+                .write_no(&tag, "[READ-ONLY] Mailbox is read-only.")
+                .await;
+        }
+
+        if let Err(message) = self.expunge_deleted(uids).await {
+            self.write_no(&tag, &message).await
+        } else {
+            self.synchronize(true).await?;
+            self.write_ok(&tag, "EXPUNGE completed.").await
+        }
+    }
+
+    // Removes the messages flagged as \Deleted from the selected mailbox,
+    // messages that also belong to other mailboxes are only unlinked.
+    pub async fn expunge_deleted(&mut self, uids: Option<Vec<Sequence>>) -> Result<(), String> {
+        if !self.core.is_leader() {
+            return Err(NOT_LEADER.to_string());
+        }
+        let selected = self.selected.as_ref().unwrap();
+        let mailbox_id = selected.id;
+        let allowed_ids = uids.map(|sequence| {
+            selected
+                .resolve(&sequence, true)
+                .into_iter()
+                .map(|(_, _, document_id)| document_id)
+                .collect::<AHashSet<_>>()
+        });
+
+        let account_id = self.account_id();
+        let store = self.core.store.clone();
+        let (update, destroy) = self
+            .core
+            .spawn_worker(move || {
+                let mut update = Vec::new();
+                let mut destroy = Vec::new();
+                let mut deleted_ids = if let Some(deleted_ids) = store.get_tag(
+                    account_id,
+                    Collection::Mail,
+                    MessageField::Keyword.into(),
+                    Tag::Static(Keyword::DELETED),
+                )? {
+                    deleted_ids
+                } else {
+                    return Ok((update, destroy));
+                };
+                deleted_ids &= store
+                    .get_tag(
+                        account_id,
+                        Collection::Mail,
+                        MessageField::Mailbox.into(),
+                        Tag::Id(mailbox_id),
+                    )?
+                    .unwrap_or_default();
+
+                for document_id in deleted_ids {
+                    if allowed_ids
+                        .as_ref()
+                        .map_or(false, |ids| !ids.contains(&document_id))
+                    {
+                        continue;
+                    }
+                    if let Some(fields) = store.get_orm::<Email>(account_id, document_id)? {
+                        if fields
+                            .get_tags(&Property::MailboxIds)
+                            .map_or(0, |tags| tags.len())
+                            > 1
+                        {
+                            let mut value = VecMap::with_capacity(1);
+                            value.append(MaybeIdReference::Value(mailbox_id.into()), false);
+                            let mut email = Email::default();
+                            email.insert(
+                                Property::MailboxIds,
+                                Value::MailboxIds { value, set: false },
+                            );
+                            update.push((document_id, email));
+                        } else {
+                            destroy.push(document_id);
+                        }
+                    }
+                }
+
+                Ok((update, destroy))
+            })
+            .await
+            .map_err(|err| {
+                error!("Failed to obtain deleted messages: {}", err);
+                "[UNAVAILABLE] Temporary server failure.".to_string()
+            })?;
+
+        self.set_emails(update, destroy).await.map(|_| ())
+    }
+
+    // Updates or destroys messages in batches, returns the ids of the
+    // messages that were modified.
+    pub async fn set_emails(
+        &mut self,
+        mut update: Vec<(DocumentId, Email)>,
+        mut destroy: Vec<DocumentId>,
+    ) -> Result<Vec<DocumentId>, String> {
+        let max_objects = std::cmp::max(self.core.store.config.max_objects_in_set, 1);
+        let account_id = self.account_id();
+        let mut changed_ids = Vec::with_capacity(update.len() + destroy.len());
+
+        while !update.is_empty() || !destroy.is_empty() {
+            let mut update_chunk = VecMap::new();
+            for (document_id, email) in update.drain(..std::cmp::min(max_objects, update.len())) {
+                update_chunk.append(JMAPId::from(document_id), email);
+            }
+            let destroy_chunk = destroy
+                .drain(..std::cmp::min(max_objects - update_chunk.len(), destroy.len()))
+                .map(JMAPId::from)
+                .collect::<Vec<_>>();
+
+            match self
+                .call_method(method::Request::SetEmail(SetRequest {
+                    acl: None,
+                    account_id: JMAPId::from(account_id),
+                    if_in_state: None,
+                    create: None,
+                    update: if !update_chunk.is_empty() {
+                        update_chunk.into()
+                    } else {
+                        None
+                    },
+                    destroy: if !destroy_chunk.is_empty() {
+                        MaybeResultReference::Value(destroy_chunk).into()
+                    } else {
+                        None
+                    },
+                    arguments: Default::default(),
+                }))
+                .await
+            {
+                Some(method::Response::SetEmail(response)) => {
+                    if let Some(err) = response
+                        .not_updated
+                        .into_iter()
+                        .map(|(_, err)| err)
+                        .chain(response.not_destroyed.into_iter().map(|(_, err)| err))
+                        .next()
+                    {
+                        return Err(format!(
+                            "{} Failed to update messages: {}.",
+                            set_error_code(&err.type_),
+                            err.type_.as_str()
+                        ));
+                    }
+                    changed_ids.extend(
+                        response
+                            .updated
+                            .into_iter()
+                            .map(|(id, _)| id.get_document_id())
+                            .chain(
+                                response
+                                    .destroyed
+                                    .into_iter()
+                                    .map(|id| id.get_document_id()),
+                            ),
+                    );
+                }
+                Some(method::Response::Error(err)) => {
+                    error!("Failed to update messages: {}", err);
+                    return Err("[UNAVAILABLE] Temporary server failure.".to_string());
+                }
+                _ => {
+                    return Err("[UNAVAILABLE] Temporary server failure.".to_string());
+                }
+            }
+        }
+
+        Ok(changed_ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jmap_mail::mail::schema::Keyword;
+    use store::{ahash::AHashSet, core::tag::Tag};
+
+    use super::{format_flags, format_uids, parse_flag};
+
+    #[test]
+    fn imap_flags() {
+        assert_eq!(
+            parse_flag("\\SEEN"),
+            Some(Keyword::new(Tag::Static(Keyword::SEEN)))
+        );
+        assert_eq!(parse_flag("\\Recent"), None);
+        assert_eq!(
+            parse_flag("$Forwarded"),
+            Some(Keyword::new(Tag::Static(Keyword::FORWARDED)))
+        );
+        assert_eq!(
+            parse_flag("Work"),
+            Some(Keyword::new(Tag::Text("work".to_string())))
+        );
+
+        let mut tags = AHashSet::default();
+        tags.insert(Tag::Static(Keyword::DELETED));
+        tags.insert(Tag::Static(Keyword::RECENT));
+        assert_eq!(format_flags(Some(&tags)), "\\Deleted");
+        assert_eq!(format_flags(None), "");
+
+        assert_eq!(format_uids(&[1, 2, 3, 5, 7, 8]), "1:3,5,7:8");
+        assert_eq!(format_uids(&[4]), "4");
+    }
+}
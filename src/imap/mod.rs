@@ -0,0 +1,170 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod fetch;
+pub mod listener;
+pub mod mailbox;
+pub mod message;
+pub mod request;
+pub mod search;
+pub mod session;
+
+use std::fmt::Write;
+
+// Writes a quoted string, or a literal if the value contains line breaks.
+pub fn write_string(buf: &mut Vec<u8>, value: &[u8]) {
+    if value
+        .iter()
+        .any(|&ch| ch == b'\r' || ch == b'\n' || ch == 0)
+    {
+        buf.extend_from_slice(format!("{{{}}}\r\n", value.len()).as_bytes());
+        buf.extend_from_slice(value);
+    } else {
+        buf.push(b'"');
+        for &ch in value {
+            if ch == b'"' || ch == b'\\' {
+                buf.push(b'\\');
+            }
+            buf.push(ch);
+        }
+        buf.push(b'"');
+    }
+}
+
+pub fn write_nstring(buf: &mut Vec<u8>, value: Option<&str>) {
+    if let Some(value) = value {
+        write_string(buf, value.as_bytes());
+    } else {
+        buf.extend_from_slice(b"NIL");
+    }
+}
+
+// Writes a literal, used for message contents.
+pub fn write_literal(buf: &mut Vec<u8>, value: &[u8]) {
+    buf.extend_from_slice(format!("{{{}}}\r\n", value.len()).as_bytes());
+    buf.extend_from_slice(value);
+}
+
+// Encodes a mailbox name using modified UTF-7 (RFC 3501, section 5.1.3).
+pub fn utf7_encode(name: &str) -> String {
+    let mut result = String::with_capacity(name.len());
+    let mut pending: Vec<u16> = Vec::new();
+
+    fn flush(result: &mut String, pending: &mut Vec<u16>) {
+        if !pending.is_empty() {
+            let bytes = pending
+                .drain(..)
+                .flat_map(|ch| ch.to_be_bytes())
+                .collect::<Vec<_>>();
+            let _ = write!(
+                result,
+                "&{}-",
+                jmap::base64::encode_config(&bytes, jmap::base64::STANDARD_NO_PAD)
+                    .replace('/', ",")
+            );
+        }
+    }
+
+    for ch in name.chars() {
+        if (' '..='~').contains(&ch) {
+            flush(&mut result, &mut pending);
+            if ch == '&' {
+                result.push_str("&-");
+            } else {
+                result.push(ch);
+            }
+        } else {
+            let mut utf16 = [0; 2];
+            pending.extend_from_slice(ch.encode_utf16(&mut utf16));
+        }
+    }
+    flush(&mut result, &mut pending);
+
+    result
+}
+
+// Decodes a modified UTF-7 mailbox name, returns None if the encoding is invalid.
+pub fn utf7_decode(name: &str) -> Option<String> {
+    let mut result = String::with_capacity(name.len());
+    let mut parts = name.split('&');
+    result.push_str(parts.next()?);
+
+    for part in parts {
+        let (encoded, text) = part.split_once('-')?;
+        if encoded.is_empty() {
+            result.push('&');
+        } else {
+            let bytes = jmap::base64::decode_config(
+                encoded.replace(',', "/"),
+                jmap::base64::STANDARD_NO_PAD,
+            )
+            .ok()?;
+            if bytes.len() % 2 != 0 {
+                return None;
+            }
+            result.push_str(
+                &char::decode_utf16(
+                    bytes
+                        .chunks_exact(2)
+                        .map(|ch| u16::from_be_bytes([ch[0], ch[1]])),
+                )
+                .collect::<Result<String, _>>()
+                .ok()?,
+            );
+        }
+        result.push_str(text);
+    }
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{utf7_decode, utf7_encode, write_string};
+
+    #[test]
+    fn imap_utf7() {
+        for (decoded, encoded) in [
+            ("INBOX", "INBOX"),
+            ("Tom & Jerry", "Tom &- Jerry"),
+            ("~peter/mail/台北/日本語", "~peter/mail/&U,BTFw-/&ZeVnLIqe-"),
+            ("Entwürfe", "Entw&APw-rfe"),
+        ] {
+            assert_eq!(utf7_encode(decoded), encoded);
+            assert_eq!(utf7_decode(encoded).unwrap(), decoded);
+        }
+        assert_eq!(utf7_decode("Entw&APw"), None);
+    }
+
+    #[test]
+    fn imap_string() {
+        let mut buf = Vec::new();
+        write_string(&mut buf, b"Say \"hello\"");
+        buf.push(b' ');
+        write_string(&mut buf, b"two\r\nlines");
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "\"Say \\\"hello\\\"\" {10}\r\ntwo\r\nlines"
+        );
+    }
+}
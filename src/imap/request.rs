@@ -0,0 +1,645 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::borrow::Cow;
+
+use store::chrono::{DateTime, NaiveDate};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Atom(String),
+    String(Vec<u8>),
+    ListStart,
+    ListEnd,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    Command {
+        tag: String,
+        name: String,
+        args: Vec<Token>,
+    },
+    Line {
+        data: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    NeedsMoreBytes,
+    Continuation,
+    Error {
+        tag: Option<String>,
+        message: Cow<'static, str>,
+    },
+    Disconnect {
+        message: Cow<'static, str>,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum State {
+    Start,
+    Arguments {
+        depth: u32,
+    },
+    Quoted {
+        escaped: bool,
+    },
+    LiteralSize {
+        size: usize,
+        non_sync: bool,
+        closed: bool,
+    },
+    Literal {
+        remaining: usize,
+    },
+    Line,
+    Discard,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Sequence {
+    Number {
+        value: u32,
+    },
+    Range {
+        start: Option<u32>,
+        end: Option<u32>,
+    },
+}
+
+pub struct RequestParser {
+    buf: Vec<u8>,
+    tokens: Vec<Token>,
+    pub state: State,
+    command_size: usize,
+    max_command_size: usize,
+    max_literal_size: usize,
+}
+
+impl RequestParser {
+    pub fn new(max_command_size: usize, max_literal_size: usize) -> Self {
+        RequestParser {
+            buf: Vec::with_capacity(10),
+            tokens: Vec::with_capacity(5),
+            state: State::Start,
+            command_size: 0,
+            max_command_size,
+            max_literal_size,
+        }
+    }
+
+    fn error_reset(&mut self, message: impl Into<Cow<'static, str>>, eol: bool) -> Event {
+        let tag = match self.tokens.first() {
+            Some(Token::Atom(tag)) => Some(tag.to_string()),
+            _ => None,
+        };
+        self.buf = Vec::with_capacity(10);
+        self.tokens.clear();
+        self.command_size = 0;
+        self.state = if eol { State::Start } else { State::Discard };
+        Event::Error {
+            tag,
+            message: message.into(),
+        }
+    }
+
+    fn push_buf(&mut self) -> Result<(), Event> {
+        if !self.buf.is_empty() {
+            let atom = String::from_utf8(std::mem::take(&mut self.buf))
+                .map_err(|_| self.error_reset("Invalid UTF-8.", false))?;
+            self.tokens.push(Token::Atom(atom));
+        }
+        Ok(())
+    }
+
+    fn push_byte(&mut self, ch: u8) -> Result<(), Event> {
+        self.command_size += 1;
+        if self.command_size > self.max_command_size {
+            return Err(self.error_reset("Command is too long.", false));
+        }
+        self.buf.push(ch);
+        Ok(())
+    }
+
+    pub fn parse(&mut self, bytes: &mut std::slice::Iter<'_, u8>) -> Result<Request, Event> {
+        #[allow(clippy::while_let_on_iterator)]
+        while let Some(&ch) = bytes.next() {
+            match self.state {
+                State::Start => {
+                    if !ch.is_ascii_whitespace() {
+                        self.state = State::Arguments { depth: 0 };
+                        self.command_size = 0;
+                        self.parse_argument(ch, 0)?;
+                    }
+                }
+                State::Arguments { depth } => {
+                    if ch == b'\n' {
+                        self.push_buf()?;
+                        return self.finish_command();
+                    } else {
+                        self.parse_argument(ch, depth)?;
+                    }
+                }
+                State::Quoted { escaped } => match ch {
+                    b'\\' if !escaped => {
+                        self.state = State::Quoted { escaped: true };
+                    }
+                    b'"' if !escaped => {
+                        self.tokens
+                            .push(Token::String(std::mem::take(&mut self.buf)));
+                        self.state = State::Arguments { depth: 0 };
+                    }
+                    b'\n' => {
+                        return Err(self.error_reset("Unterminated quoted string.", true));
+                    }
+                    _ => {
+                        self.push_byte(ch)?;
+                        self.state = State::Quoted { escaped: false };
+                    }
+                },
+                State::LiteralSize {
+                    size,
+                    non_sync,
+                    closed,
+                } => match ch {
+                    b'0'..=b'9' if !closed && !non_sync => {
+                        self.state = State::LiteralSize {
+                            size: size
+                                .checked_mul(10)
+                                .and_then(|size| size.checked_add((ch - b'0') as usize))
+                                .ok_or_else(|| self.error_reset("Invalid literal size.", false))?,
+                            non_sync,
+                            closed,
+                        };
+                    }
+                    b'+' if !closed && !non_sync => {
+                        self.state = State::LiteralSize {
+                            size,
+                            non_sync: true,
+                            closed,
+                        };
+                    }
+                    b'}' if !closed => {
+                        self.state = State::LiteralSize {
+                            size,
+                            non_sync,
+                            closed: true,
+                        };
+                    }
+                    b'\r' if closed => (),
+                    b'\n' if closed => {
+                        if size > self.max_literal_size {
+                            return if non_sync {
+                                Err(Event::Disconnect {
+                                    message: "Literal exceeds the maximum allowed size.".into(),
+                                })
+                            } else {
+                                Err(self.error_reset(
+                                    "[TOOBIG] Literal exceeds the maximum allowed size.",
+                                    true,
+                                ))
+                            };
+                        } else if size == 0 {
+                            self.tokens.push(Token::String(Vec::new()));
+                            self.state = State::Arguments { depth: 0 };
+                        } else {
+                            self.buf = Vec::with_capacity(size);
+                            self.state = State::Literal { remaining: size };
+                            if !non_sync {
+                                return Err(Event::Continuation);
+                            }
+                        }
+                    }
+                    _ => {
+                        return Err(self.error_reset("Invalid literal.", ch == b'\n'));
+                    }
+                },
+                State::Literal { remaining } => {
+                    self.buf.push(ch);
+                    if remaining > 1 {
+                        self.state = State::Literal {
+                            remaining: remaining - 1,
+                        };
+                    } else {
+                        self.tokens
+                            .push(Token::String(std::mem::take(&mut self.buf)));
+                        self.state = State::Arguments { depth: 0 };
+                    }
+                }
+                State::Line => match ch {
+                    b'\r' => (),
+                    b'\n' => {
+                        let data = String::from_utf8(std::mem::take(&mut self.buf))
+                            .map_err(|_| self.error_reset("Invalid UTF-8.", true))?;
+                        self.command_size = 0;
+                        self.state = State::Start;
+                        return Ok(Request::Line {
+                            data: data.trim().to_string(),
+                        });
+                    }
+                    _ => {
+                        self.push_byte(ch)?;
+                    }
+                },
+                State::Discard => {
+                    if ch == b'\n' {
+                        self.state = State::Start;
+                    }
+                }
+            }
+        }
+
+        Err(Event::NeedsMoreBytes)
+    }
+
+    fn parse_argument(&mut self, ch: u8, depth: u32) -> Result<(), Event> {
+        // Spaces and parentheses are part of the atom inside a section,
+        // i.e. BODY[HEADER.FIELDS (From To)]
+        if depth > 0 {
+            match ch {
+                b'\r' => (),
+                b'[' => {
+                    self.push_byte(ch)?;
+                    self.state = State::Arguments { depth: depth + 1 };
+                }
+                b']' => {
+                    self.push_byte(ch)?;
+                    self.state = State::Arguments { depth: depth - 1 };
+                }
+                _ => {
+                    self.push_byte(ch)?;
+                }
+            }
+            return Ok(());
+        }
+
+        match ch {
+            b' ' | b'\t' | b'\r' => {
+                self.push_buf()?;
+            }
+            b'(' => {
+                self.push_buf()?;
+                self.tokens.push(Token::ListStart);
+            }
+            b')' => {
+                self.push_buf()?;
+                self.tokens.push(Token::ListEnd);
+            }
+            b'"' if self.buf.is_empty() => {
+                self.state = State::Quoted { escaped: false };
+            }
+            b'{' if self.buf.is_empty() => {
+                self.state = State::LiteralSize {
+                    size: 0,
+                    non_sync: false,
+                    closed: false,
+                };
+            }
+            b'[' => {
+                self.push_byte(ch)?;
+                self.state = State::Arguments { depth: 1 };
+            }
+            _ => {
+                self.push_byte(ch)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish_command(&mut self) -> Result<Request, Event> {
+        let mut tokens = std::mem::take(&mut self.tokens).into_iter();
+        self.tokens = Vec::with_capacity(5);
+        self.state = State::Start;
+        self.command_size = 0;
+
+        let tag = match tokens.next() {
+            Some(Token::Atom(tag)) if !tag.contains('+') => tag,
+            _ => {
+                return Err(Event::Error {
+                    tag: None,
+                    message: "Missing or invalid tag.".into(),
+                })
+            }
+        };
+        let mut name = match tokens.next() {
+            Some(Token::Atom(name)) => name.to_ascii_uppercase(),
+            _ => {
+                return Err(Event::Error {
+                    tag: tag.into(),
+                    message: "Missing command.".into(),
+                })
+            }
+        };
+        if name == "UID" {
+            match tokens.next() {
+                Some(Token::Atom(command)) => {
+                    name = format!("UID {}", command.to_ascii_uppercase());
+                }
+                _ => {
+                    return Err(Event::Error {
+                        tag: tag.into(),
+                        message: "Missing command after UID.".into(),
+                    })
+                }
+            }
+        }
+
+        Ok(Request::Command {
+            tag,
+            name,
+            args: tokens.collect(),
+        })
+    }
+}
+
+impl Token {
+    // Atoms, quoted strings and literals (astring) as text.
+    pub fn unwrap_string(self) -> Option<String> {
+        match self {
+            Token::Atom(value) => Some(value),
+            Token::String(value) => String::from_utf8(value).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn unwrap_bytes(self) -> Option<Vec<u8>> {
+        match self {
+            Token::Atom(value) => Some(value.into_bytes()),
+            Token::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn unwrap_atom(self) -> Option<String> {
+        match self {
+            Token::Atom(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl Sequence {
+    // '*' stands for the largest number in use.
+    pub fn contains(&self, value: u32, max: u32) -> bool {
+        match self {
+            Sequence::Number { value: number } => *number == value,
+            Sequence::Range { start, end } => {
+                let start = start.unwrap_or(max);
+                let end = end.unwrap_or(max);
+                if start <= end {
+                    (start..=end).contains(&value)
+                } else {
+                    (end..=start).contains(&value)
+                }
+            }
+        }
+    }
+}
+
+pub fn parse_sequence_set(value: &str) -> Option<Vec<Sequence>> {
+    fn parse_number(value: &str) -> Option<Option<u32>> {
+        if value == "*" {
+            Some(None)
+        } else {
+            match value.parse::<u32>() {
+                Ok(number) if number > 0 => Some(Some(number)),
+                _ => None,
+            }
+        }
+    }
+
+    let mut sequence = Vec::new();
+    for item in value.split(',') {
+        if let Some((start, end)) = item.split_once(':') {
+            sequence.push(Sequence::Range {
+                start: parse_number(start)?,
+                end: parse_number(end)?,
+            });
+        } else {
+            sequence.push(match parse_number(item)? {
+                Some(value) => Sequence::Number { value },
+                None => Sequence::Range {
+                    start: None,
+                    end: None,
+                },
+            });
+        }
+    }
+
+    if !sequence.is_empty() {
+        Some(sequence)
+    } else {
+        None
+    }
+}
+
+// Parses an IMAP date (i.e. 1-Feb-1994) into a UTC timestamp.
+pub fn parse_date(value: &str) -> Option<i64> {
+    NaiveDate::parse_from_str(value.trim(), "%d-%b-%Y")
+        .ok()
+        .map(|date| date.and_hms(0, 0, 0).timestamp())
+}
+
+// Parses an IMAP date-time (i.e. 17-Jul-1996 02:44:25 -0700) into a timestamp.
+pub fn parse_datetime(value: &str) -> Option<i64> {
+    DateTime::parse_from_str(value.trim(), "%d-%b-%Y %H:%M:%S %z")
+        .ok()
+        .map(|date| date.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_date, parse_datetime, parse_sequence_set, Event, Request, RequestParser, Sequence,
+        State, Token,
+    };
+
+    #[test]
+    fn imap_parser() {
+        let mut parser = RequestParser::new(1024, 1024);
+        for (chunks, expected_requests) in [
+            (
+                vec!["a1 CAPABILITY\r\n", "a2 login ", "john \"pass word\"\r\n"],
+                vec![
+                    Request::Command {
+                        tag: "a1".to_string(),
+                        name: "CAPABILITY".to_string(),
+                        args: vec![],
+                    },
+                    Request::Command {
+                        tag: "a2".to_string(),
+                        name: "LOGIN".to_string(),
+                        args: vec![
+                            Token::Atom("john".to_string()),
+                            Token::String(b"pass word".to_vec()),
+                        ],
+                    },
+                ],
+            ),
+            (
+                vec![
+                    "a3 UID fetch 1:* (FLAGS BODY.PEEK[HEADER.FIELDS (From To)]<0.100>)\r\n",
+                    "a4 select \"[Gmail]/Sent \\\"Mail\\\"\"\r\n",
+                    "a5 list \"\" [Gmail]/%\r\n",
+                ],
+                vec![
+                    Request::Command {
+                        tag: "a3".to_string(),
+                        name: "UID FETCH".to_string(),
+                        args: vec![
+                            Token::Atom("1:*".to_string()),
+                            Token::ListStart,
+                            Token::Atom("FLAGS".to_string()),
+                            Token::Atom("BODY.PEEK[HEADER.FIELDS (From To)]<0.100>".to_string()),
+                            Token::ListEnd,
+                        ],
+                    },
+                    Request::Command {
+                        tag: "a4".to_string(),
+                        name: "SELECT".to_string(),
+                        args: vec![Token::String(b"[Gmail]/Sent \"Mail\"".to_vec())],
+                    },
+                    Request::Command {
+                        tag: "a5".to_string(),
+                        name: "LIST".to_string(),
+                        args: vec![
+                            Token::String(Vec::new()),
+                            Token::Atom("[Gmail]/%".to_string()),
+                        ],
+                    },
+                ],
+            ),
+            (
+                vec!["a6 APPEND INBOX (\\Seen) {5+}\r\nhel", "lo {0}\r\n\r\n"],
+                vec![Request::Command {
+                    tag: "a6".to_string(),
+                    name: "APPEND".to_string(),
+                    args: vec![
+                        Token::Atom("INBOX".to_string()),
+                        Token::ListStart,
+                        Token::Atom("\\Seen".to_string()),
+                        Token::ListEnd,
+                        Token::String(b"hello".to_vec()),
+                        Token::String(Vec::new()),
+                    ],
+                }],
+            ),
+        ] {
+            let mut requests = Vec::new();
+            for chunk in &chunks {
+                let mut bytes = chunk.as_bytes().iter();
+                loop {
+                    match parser.parse(&mut bytes) {
+                        Ok(request) => requests.push(request),
+                        Err(Event::NeedsMoreBytes) => break,
+                        Err(err) => panic!("{:?} for chunks {:#?}", err, chunks),
+                    }
+                }
+            }
+            assert_eq!(requests, expected_requests, "{:#?}", requests);
+        }
+
+        // Synchronizing literals wait for a continuation request
+        let mut bytes = b"a7 login {4}\r\njohn secret\r\n".iter();
+        assert_eq!(parser.parse(&mut bytes), Err(Event::Continuation));
+        assert_eq!(
+            parser.parse(&mut bytes),
+            Ok(Request::Command {
+                tag: "a7".to_string(),
+                name: "LOGIN".to_string(),
+                args: vec![
+                    Token::String(b"john".to_vec()),
+                    Token::Atom("secret".to_string()),
+                ],
+            })
+        );
+
+        // Oversized literals are rejected
+        let mut bytes = b"a8 append INBOX {2048}\r\na9 NOOP\r\n".iter();
+        assert!(matches!(
+            parser.parse(&mut bytes),
+            Err(Event::Error { tag: Some(tag), .. }) if tag == "a8"
+        ));
+        assert_eq!(
+            parser.parse(&mut bytes),
+            Ok(Request::Command {
+                tag: "a9".to_string(),
+                name: "NOOP".to_string(),
+                args: vec![],
+            })
+        );
+
+        // Lines are read verbatim when requested
+        parser.state = State::Line;
+        let mut bytes = b"DONE\r\n".iter();
+        assert_eq!(
+            parser.parse(&mut bytes),
+            Ok(Request::Line {
+                data: "DONE".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn imap_sequence_and_dates() {
+        assert_eq!(
+            parse_sequence_set("1,3:5,7:*,*").unwrap(),
+            vec![
+                Sequence::Number { value: 1 },
+                Sequence::Range {
+                    start: 3.into(),
+                    end: 5.into()
+                },
+                Sequence::Range {
+                    start: 7.into(),
+                    end: None
+                },
+                Sequence::Range {
+                    start: None,
+                    end: None
+                },
+            ]
+        );
+        assert_eq!(parse_sequence_set("0:3"), None);
+        assert_eq!(parse_sequence_set("1,,2"), None);
+
+        let range = Sequence::Range {
+            start: 10.into(),
+            end: None,
+        };
+        assert!(range.contains(8, 8));
+        assert!(range.contains(9, 8));
+        assert!(!range.contains(11, 8));
+
+        assert_eq!(parse_date("1-Feb-1994"), Some(760060800));
+        assert_eq!(parse_date("31-Foo-1994"), None);
+        assert_eq!(
+            parse_datetime(" 7-Jul-1996 02:44:25 -0700"),
+            Some(836732665)
+        );
+    }
+}
@@ -0,0 +1,475 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{iter::Peekable, vec::IntoIter};
+
+use jmap_mail::{
+    mail::{schema::Keyword, MessageField},
+    mail_parser::{
+        parsers::header::{parse_header_name, HeaderParserResult},
+        RfcHeader,
+    },
+};
+use store::{
+    ahash::AHashMap,
+    core::{collection::Collection, tag::Tag, JMAPIdPrefix},
+    nlp::Language,
+    read::{
+        comparator::Comparator,
+        filter::{Filter, Query},
+        FilterMapper,
+    },
+    roaring::RoaringBitmap,
+    DocumentId, FieldId, Integer, LongInteger, Store,
+};
+
+use super::{
+    message::{format_uids, parse_flag},
+    request::{parse_date, parse_sequence_set, Sequence, Token},
+    session::{SelectedMailbox, Session},
+};
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ReturnOptions {
+    pub min: bool,
+    pub max: bool,
+    pub count: bool,
+    pub all: bool,
+}
+
+// Converts IMAP search keys into a store filter, sequence sets are
+// resolved against the messages known to the client.
+pub fn parse_search_keys(
+    tokens: &mut Peekable<IntoIter<Token>>,
+    selected: &SelectedMailbox,
+) -> Result<Vec<Filter>, String> {
+    let mut filters = Vec::new();
+    while tokens
+        .peek()
+        .map_or(false, |token| !matches!(token, Token::ListEnd))
+    {
+        filters.push(parse_search_key(tokens, selected)?);
+    }
+    Ok(filters)
+}
+
+fn parse_search_key(
+    tokens: &mut Peekable<IntoIter<Token>>,
+    selected: &SelectedMailbox,
+) -> Result<Filter, String> {
+    let key = match tokens.next() {
+        Some(Token::Atom(key)) => key,
+        Some(Token::ListStart) => {
+            let filters = parse_search_keys(tokens, selected)?;
+            return if matches!(tokens.next(), Some(Token::ListEnd)) {
+                Ok(Filter::and(filters))
+            } else {
+                Err("Unterminated search key list.".to_string())
+            };
+        }
+        _ => return Err("Invalid search key.".to_string()),
+    };
+
+    Ok(match key.to_ascii_uppercase().as_str() {
+        "ALL" | "OLD" => all_messages(selected),
+        "NEW" | "RECENT" => Filter::DocumentSet(RoaringBitmap::new()),
+        "UNSEEN" => not_keyword(Keyword::SEEN),
+        "ANSWERED" => keyword(Keyword::ANSWERED),
+        "DELETED" => keyword(Keyword::DELETED),
+        "DRAFT" => keyword(Keyword::DRAFT),
+        "FLAGGED" => keyword(Keyword::FLAGGED),
+        "SEEN" => keyword(Keyword::SEEN),
+        "UNANSWERED" => not_keyword(Keyword::ANSWERED),
+        "UNDELETED" => not_keyword(Keyword::DELETED),
+        "UNDRAFT" => not_keyword(Keyword::DRAFT),
+        "UNFLAGGED" => not_keyword(Keyword::FLAGGED),
+        "KEYWORD" | "UNKEYWORD" => {
+            let filter = match parse_flag(&next_string(tokens)?) {
+                Some(keyword) => Filter::eq(MessageField::Keyword.into(), Query::Tag(keyword.tag)),
+                None => Filter::DocumentSet(RoaringBitmap::new()),
+            };
+            if key.eq_ignore_ascii_case("UNKEYWORD") {
+                Filter::not(vec![filter])
+            } else {
+                filter
+            }
+        }
+        "FROM" => Filter::eq(
+            RfcHeader::From.into(),
+            Query::Tokenize(next_string(tokens)?),
+        ),
+        "TO" => Filter::eq(RfcHeader::To.into(), Query::Tokenize(next_string(tokens)?)),
+        "CC" => Filter::eq(RfcHeader::Cc.into(), Query::Tokenize(next_string(tokens)?)),
+        "BCC" => Filter::eq(RfcHeader::Bcc.into(), Query::Tokenize(next_string(tokens)?)),
+        "SUBJECT" => Filter::eq(
+            RfcHeader::Subject.into(),
+            Query::match_text(next_string(tokens)?, Language::Unknown),
+        ),
+        "BODY" => Filter::eq(
+            MessageField::Body.into(),
+            Query::match_text(next_string(tokens)?, Language::Unknown),
+        ),
+        "TEXT" => {
+            let value = next_string(tokens)?;
+            Filter::or(vec![
+                Filter::eq(RfcHeader::From.into(), Query::Tokenize(value.clone())),
+                Filter::eq(RfcHeader::To.into(), Query::Tokenize(value.clone())),
+                Filter::eq(RfcHeader::Cc.into(), Query::Tokenize(value.clone())),
+                Filter::eq(RfcHeader::Bcc.into(), Query::Tokenize(value.clone())),
+                Filter::eq(
+                    RfcHeader::Subject.into(),
+                    Query::match_text(value.clone(), Language::Unknown),
+                ),
+                Filter::eq(
+                    MessageField::Body.into(),
+                    Query::match_text(value.clone(), Language::Unknown),
+                ),
+                Filter::eq(
+                    MessageField::Attachment.into(),
+                    Query::match_text(value, Language::Unknown),
+                ),
+            ])
+        }
+        "HEADER" => {
+            let name = next_string(tokens)?;
+            let value = next_string(tokens)?;
+            match parse_header_name(name.as_bytes()) {
+                (_, HeaderParserResult::Rfc(header)) => header_filter(header, value),
+                _ => Filter::DocumentSet(RoaringBitmap::new()),
+            }
+        }
+        "BEFORE" => Filter::lt(MessageField::ReceivedAt.into(), next_date(tokens)?),
+        "SINCE" => Filter::ge(MessageField::ReceivedAt.into(), next_date(tokens)?),
+        "ON" => date_range(MessageField::ReceivedAt.into(), tokens)?,
+        "SENTBEFORE" => Filter::lt(RfcHeader::Date.into(), next_date(tokens)?),
+        "SENTSINCE" => Filter::ge(RfcHeader::Date.into(), next_date(tokens)?),
+        "SENTON" => date_range(RfcHeader::Date.into(), tokens)?,
+        "LARGER" => Filter::gt(MessageField::Size.into(), next_number(tokens)?),
+        "SMALLER" => Filter::lt(MessageField::Size.into(), next_number(tokens)?),
+        "NOT" => Filter::not(vec![parse_search_key(tokens, selected)?]),
+        "OR" => Filter::or(vec![
+            parse_search_key(tokens, selected)?,
+            parse_search_key(tokens, selected)?,
+        ]),
+        "UID" => {
+            let sequence = parse_sequence_set(&next_string(tokens)?)
+                .ok_or_else(|| "Invalid UID set.".to_string())?;
+            message_set(selected, &sequence, true)
+        }
+        _ => {
+            let sequence = parse_sequence_set(&key)
+                .ok_or_else(|| format!("Unsupported search key '{}'.", key))?;
+            message_set(selected, &sequence, false)
+        }
+    })
+}
+
+fn keyword(keyword: u8) -> Filter {
+    Filter::eq(
+        MessageField::Keyword.into(),
+        Query::Tag(Tag::Static(keyword)),
+    )
+}
+
+fn not_keyword(keyword_: u8) -> Filter {
+    Filter::not(vec![keyword(keyword_)])
+}
+
+fn all_messages(selected: &SelectedMailbox) -> Filter {
+    Filter::DocumentSet(
+        selected
+            .document_ids
+            .iter()
+            .filter(|document_id| **document_id != DocumentId::MAX)
+            .copied()
+            .collect(),
+    )
+}
+
+fn message_set(selected: &SelectedMailbox, sequence: &[Sequence], is_uid: bool) -> Filter {
+    Filter::DocumentSet(
+        selected
+            .resolve(sequence, is_uid)
+            .into_iter()
+            .map(|(_, _, document_id)| document_id)
+            .collect(),
+    )
+}
+
+// Header values can only be matched on indexed headers, for other headers
+// the message is matched if the header is present.
+fn header_filter(header: RfcHeader, value: String) -> Filter {
+    if value.is_empty() {
+        return Filter::eq(
+            MessageField::HasHeader.into(),
+            Query::Tag(Tag::Static(header.into())),
+        );
+    }
+    match header {
+        RfcHeader::From | RfcHeader::To | RfcHeader::Cc | RfcHeader::Bcc => {
+            Filter::eq(header.into(), Query::Tokenize(value))
+        }
+        RfcHeader::Subject => {
+            Filter::eq(header.into(), Query::match_text(value, Language::Unknown))
+        }
+        RfcHeader::MessageId => Filter::eq(
+            header as FieldId,
+            Query::Keyword(value.trim_matches(|ch| ch == '<' || ch == '>').to_string()),
+        ),
+        RfcHeader::InReplyTo | RfcHeader::References => Filter::eq(
+            MessageField::MessageIdRef as FieldId,
+            Query::Keyword(value.trim_matches(|ch| ch == '<' || ch == '>').to_string()),
+        ),
+        _ => Filter::eq(
+            MessageField::HasHeader.into(),
+            Query::Tag(Tag::Static(header.into())),
+        ),
+    }
+}
+
+fn next_string(tokens: &mut Peekable<IntoIter<Token>>) -> Result<String, String> {
+    tokens
+        .next()
+        .and_then(|token| token.unwrap_string())
+        .ok_or_else(|| "Missing search argument.".to_string())
+}
+
+fn next_date(tokens: &mut Peekable<IntoIter<Token>>) -> Result<Query, String> {
+    parse_date(&next_string(tokens)?)
+        .map(|date| Query::LongInteger(date as LongInteger))
+        .ok_or_else(|| "Invalid date.".to_string())
+}
+
+fn next_number(tokens: &mut Peekable<IntoIter<Token>>) -> Result<Query, String> {
+    next_string(tokens)?
+        .parse::<Integer>()
+        .map(Query::Integer)
+        .map_err(|_| "Invalid number.".to_string())
+}
+
+fn date_range(field: FieldId, tokens: &mut Peekable<IntoIter<Token>>) -> Result<Filter, String> {
+    let date = parse_date(&next_string(tokens)?).ok_or_else(|| "Invalid date.".to_string())?;
+    Ok(Filter::and(vec![
+        Filter::ge(field, Query::LongInteger(date as LongInteger)),
+        Filter::lt(field, Query::LongInteger((date + 86400) as LongInteger)),
+    ]))
+}
+
+// Parses the RETURN options of an extended SEARCH command, an empty list
+// is equivalent to ALL.
+pub fn parse_return_options(
+    tokens: &mut Peekable<IntoIter<Token>>,
+) -> Result<Option<ReturnOptions>, String> {
+    if !matches!(tokens.peek(), Some(Token::Atom(atom)) if atom.eq_ignore_ascii_case("RETURN")) {
+        return Ok(None);
+    }
+    tokens.next();
+    if !matches!(tokens.next(), Some(Token::ListStart)) {
+        return Err("Expected RETURN options list.".to_string());
+    }
+    let mut options = ReturnOptions::default();
+    loop {
+        match tokens.next() {
+            Some(Token::Atom(option)) => match option.to_ascii_uppercase().as_str() {
+                "MIN" => options.min = true,
+                "MAX" => options.max = true,
+                "COUNT" => options.count = true,
+                "ALL" => options.all = true,
+                _ => return Err(format!("Unsupported RETURN option '{}'.", option)),
+            },
+            Some(Token::ListEnd) => break,
+            _ => return Err("Invalid RETURN options.".to_string()),
+        }
+    }
+    if options == ReturnOptions::default() {
+        options.all = true;
+    }
+    Ok(Some(options))
+}
+
+impl<T> Session<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub async fn handle_search(
+        &mut self,
+        tag: String,
+        args: IntoIter<Token>,
+        is_uid: bool,
+    ) -> Result<(), ()> {
+        let mut tokens = args.peekable();
+        let return_options = match parse_return_options(&mut tokens) {
+            Ok(return_options) => return_options,
+            Err(message) => return self.write_bad(&tag, &message).await,
+        };
+        if matches!(tokens.peek(), Some(Token::Atom(atom)) if atom.eq_ignore_ascii_case("CHARSET"))
+        {
+            tokens.next();
+            match tokens.next().and_then(|token| token.unwrap_string()) {
+                Some(charset)
+                    if charset.eq_ignore_ascii_case("UTF-8")
+                        || charset.eq_ignore_ascii_case("US-ASCII") => {}
+                _ => {
+                    return self
+                        .write_no(&tag, "[BADCHARSET (UTF-8 US-ASCII)] Unsupported charset.")
+                        .await
+                }
+            }
+        }
+
+        let selected = self.selected.as_ref().unwrap();
+        let mut filters = match parse_search_keys(&mut tokens, selected) {
+            Ok(filters) if !filters.is_empty() && tokens.next().is_none() => filters,
+            Ok(_) => return self.write_bad(&tag, "Invalid search criteria.").await,
+            Err(message) => return self.write_bad(&tag, &message).await,
+        };
+        filters.insert(0, all_messages(selected));
+
+        let account_id = self.account_id();
+        let store = self.core.store.clone();
+        let document_ids = match self
+            .core
+            .spawn_worker(move || {
+                Ok(store
+                    .query_store::<FilterMapper>(
+                        account_id,
+                        Collection::Mail,
+                        Filter::and(filters),
+                        Comparator::None,
+                    )?
+                    .into_iter()
+                    .map(|id| id.get_document_id())
+                    .collect::<Vec<_>>())
+            })
+            .await
+        {
+            Ok(document_ids) => document_ids,
+            Err(err) => return self.write_server_error(&tag, err).await,
+        };
+
+        // Map the results to UIDs or sequence numbers
+        let selected = self.selected.as_ref().unwrap();
+        let positions = selected
+            .document_ids
+            .iter()
+            .enumerate()
+            .map(|(pos, document_id)| (*document_id, pos))
+            .collect::<AHashMap<_, _>>();
+        let mut results = document_ids
+            .into_iter()
+            .filter_map(|document_id| {
+                let pos = *positions.get(&document_id)?;
+                Some(if is_uid {
+                    selected.uids[pos]
+                } else {
+                    (pos + 1) as u32
+                })
+            })
+            .collect::<Vec<_>>();
+        results.sort_unstable();
+
+        let response = match return_options {
+            None if !self.is_rev2 => {
+                let mut response = "* SEARCH".to_string();
+                for result in &results {
+                    response.push_str(&format!(" {}", result));
+                }
+                response
+            }
+            return_options => {
+                let options = return_options.unwrap_or(ReturnOptions {
+                    all: true,
+                    ..Default::default()
+                });
+                let mut response = format!("* ESEARCH (TAG \"{}\")", tag);
+                if is_uid {
+                    response.push_str(" UID");
+                }
+                if let (true, Some(min)) = (options.min, results.first()) {
+                    response.push_str(&format!(" MIN {}", min));
+                }
+                if let (true, Some(max)) = (options.max, results.last()) {
+                    response.push_str(&format!(" MAX {}", max));
+                }
+                if options.count {
+                    response.push_str(&format!(" COUNT {}", results.len()));
+                }
+                if options.all && !results.is_empty() {
+                    response.push_str(&format!(" ALL {}", format_uids(&results)));
+                }
+                response
+            }
+        };
+
+        self.write_bytes(format!("{}\r\n", response).as_bytes())
+            .await?;
+        self.write_ok(&tag, "SEARCH completed.").await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_return_options, ReturnOptions};
+    use crate::imap::request::Token;
+
+    #[test]
+    fn imap_search_return_options() {
+        let mut tokens = vec![
+            Token::Atom("RETURN".to_string()),
+            Token::ListStart,
+            Token::Atom("min".to_string()),
+            Token::Atom("COUNT".to_string()),
+            Token::ListEnd,
+            Token::Atom("ALL".to_string()),
+        ]
+        .into_iter()
+        .peekable();
+        assert_eq!(
+            parse_return_options(&mut tokens),
+            Ok(Some(ReturnOptions {
+                min: true,
+                count: true,
+                ..Default::default()
+            }))
+        );
+        assert_eq!(tokens.next(), Some(Token::Atom("ALL".to_string())));
+
+        let mut tokens = vec![
+            Token::Atom("RETURN".to_string()),
+            Token::ListStart,
+            Token::ListEnd,
+        ]
+        .into_iter()
+        .peekable();
+        assert_eq!(
+            parse_return_options(&mut tokens),
+            Ok(Some(ReturnOptions {
+                all: true,
+                ..Default::default()
+            }))
+        );
+
+        let mut tokens = vec![Token::Atom("SEEN".to_string())].into_iter().peekable();
+        assert_eq!(parse_return_options(&mut tokens), Ok(None));
+    }
+}
@@ -0,0 +1,692 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    vec::IntoIter,
+};
+
+use actix_web::web;
+use jmap::{
+    base64, error::set::SetErrorType, orm::serialize::JMAPOrm, types::type_state::TypeState,
+};
+use jmap_mail::mail::{
+    schema::{Email, Property as EmailProperty},
+    uid::JMAPMailUids,
+};
+use store::{
+    ahash::AHashMap,
+    core::{bitmap::Bitmap, collection::Collection, JMAPIdPrefix},
+    log::changes::{self, Change, ChangeId},
+    tracing::{debug, error},
+    AccountId, DocumentId, Store,
+};
+use tokio::sync::mpsc;
+use tokio_rustls::TlsAcceptor;
+
+use crate::{
+    api::{invocation::handle_method_calls, method, request::Request as JMAPRequest},
+    authorization::{self, auth::RemoteAddress},
+    lmtp::session::Stream,
+    services::state_change::StateChange,
+    smtp::auth::{decode_plain, decode_xoauth2, Credentials},
+    JMAPServer,
+};
+
+use super::{
+    message::format_flags,
+    request::{Event, Request, RequestParser, State, Token},
+};
+
+const MAX_COMMAND_LENGTH: usize = 8192;
+pub const NOT_LEADER: &str = "[UNAVAILABLE] Temporary cluster failure, try again later.";
+
+// State manager subscriber ids are shared with push and EventSource
+// clients, which use the account id, so IDLE starts from the upper half.
+static IDLE_ID: AtomicU32 = AtomicU32::new(u32::MAX >> 1);
+
+pub struct Session<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub core: web::Data<JMAPServer<T>>,
+    pub tls_acceptor: Option<Arc<TlsAcceptor>>,
+    pub parser: RequestParser,
+    pub peer_addr: SocketAddr,
+    pub stream: Stream,
+    pub allow_plain_auth: bool,
+
+    // State
+    pub account: Option<authorization::Session>,
+    pub sasl: Option<(String, Mechanism)>,
+    pub selected: Option<SelectedMailbox>,
+    pub is_rev2: bool,
+    pub idle_tag: Option<String>,
+    pub state_rx: Option<mpsc::Receiver<StateChange>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mechanism {
+    Plain,
+    XOAuth2,
+}
+
+pub struct SelectedMailbox {
+    pub id: DocumentId,
+    pub read_only: bool,
+    pub uid_validity: u32,
+    // Sorted UIDs as known by the client, the position is the sequence number.
+    pub uids: Vec<u32>,
+    // Document ids of the messages by position, set to DocumentId::MAX for
+    // messages expunged while EXPUNGE responses could not be sent.
+    pub document_ids: Vec<DocumentId>,
+    pub change_id: Option<ChangeId>,
+}
+
+impl<T> Session<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub fn new(
+        core: web::Data<JMAPServer<T>>,
+        peer_addr: SocketAddr,
+        stream: Stream,
+        tls_acceptor: Option<Arc<TlsAcceptor>>,
+        allow_plain_auth: bool,
+    ) -> Self {
+        Self {
+            parser: RequestParser::new(MAX_COMMAND_LENGTH, core.store.config.mail_max_size.get()),
+            tls_acceptor,
+            peer_addr,
+            stream,
+            core,
+            allow_plain_auth,
+            account: None,
+            sasl: None,
+            selected: None,
+            is_rev2: false,
+            idle_tag: None,
+            state_rx: None,
+        }
+    }
+
+    pub async fn ingest(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        let mut bytes = bytes.iter();
+
+        loop {
+            match self.parser.parse(&mut bytes) {
+                Ok(Request::Command { tag, name, args }) => {
+                    if name == "STARTTLS" {
+                        // Discard any pipelined commands sent before the TLS handshake
+                        return self.handle_starttls(tag).await;
+                    }
+                    self.handle_command(tag, name, args).await?;
+                }
+                Ok(Request::Line { data }) => {
+                    self.handle_line(data).await?;
+                }
+                Err(Event::NeedsMoreBytes) => {
+                    break;
+                }
+                Err(Event::Continuation) => {
+                    self.write_bytes(b"+ Ready for literal data\r\n").await?;
+                }
+                Err(Event::Error { tag, message }) => {
+                    self.write_bytes(
+                        format!("{} BAD {}\r\n", tag.as_deref().unwrap_or("*"), message).as_bytes(),
+                    )
+                    .await?;
+                }
+                Err(Event::Disconnect { message }) => {
+                    self.write_bytes(format!("* BYE {}\r\n", message).as_bytes())
+                        .await?;
+                    return Err(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_command(
+        &mut self,
+        tag: String,
+        name: String,
+        args: Vec<Token>,
+    ) -> Result<(), ()> {
+        let mut args = args.into_iter();
+
+        match name.as_str() {
+            "CAPABILITY" => {
+                self.write_bytes(
+                    format!(
+                        "* CAPABILITY {}\r\n{} OK CAPABILITY completed.\r\n",
+                        self.capabilities(),
+                        tag
+                    )
+                    .as_bytes(),
+                )
+                .await
+            }
+            "NOOP" | "CHECK" => {
+                if self.selected.is_some() {
+                    self.synchronize(true).await?;
+                }
+                self.write_ok(&tag, &format!("{} completed.", name)).await
+            }
+            "LOGOUT" => {
+                self.write_bytes(
+                    format!(
+                        "* BYE Stalwart IMAP bids you farewell.\r\n{} OK LOGOUT completed.\r\n",
+                        tag
+                    )
+                    .as_bytes(),
+                )
+                .await?;
+                Err(())
+            }
+            "LOGIN" | "AUTHENTICATE" if self.account.is_some() => {
+                self.write_bad(&tag, "Already authenticated.").await
+            }
+            "LOGIN" => match (
+                args.next().and_then(|t| t.unwrap_string()),
+                args.next().and_then(|t| t.unwrap_string()),
+            ) {
+                (Some(username), Some(secret)) => {
+                    if self.stream.is_tls() || self.allow_plain_auth {
+                        self.authenticate(
+                            tag,
+                            Credentials::Plain {
+                                username: username.trim().to_lowercase(),
+                                secret,
+                            },
+                        )
+                        .await
+                    } else {
+                        self.write_no(&tag, "[PRIVACYREQUIRED] LOGIN is disabled over cleartext.")
+                            .await
+                    }
+                }
+                _ => {
+                    self.write_bad(&tag, "Expected username and password.")
+                        .await
+                }
+            },
+            "AUTHENTICATE" => self.handle_authenticate(tag, args).await,
+            _ if self.account.is_none() => self.write_bad(&tag, "Please authenticate first.").await,
+            "ENABLE" => {
+                let mut enabled = String::new();
+                for capability in args.filter_map(|t| t.unwrap_atom()) {
+                    if capability.eq_ignore_ascii_case("IMAP4rev2") && !self.is_rev2 {
+                        self.is_rev2 = true;
+                        enabled.push_str(" IMAP4rev2");
+                    }
+                }
+                self.write_bytes(
+                    format!("* ENABLED{}\r\n{} OK ENABLE completed.\r\n", enabled, tag).as_bytes(),
+                )
+                .await
+            }
+            "SELECT" => self.handle_select(tag, args, false).await,
+            "EXAMINE" => self.handle_select(tag, args, true).await,
+            "CREATE" => self.handle_create(tag, args).await,
+            "DELETE" => self.handle_delete(tag, args).await,
+            "RENAME" => self.handle_rename(tag, args).await,
+            "SUBSCRIBE" => self.handle_subscribe(tag, args, true).await,
+            "UNSUBSCRIBE" => self.handle_subscribe(tag, args, false).await,
+            "LIST" => self.handle_list(tag, args, false).await,
+            "LSUB" => self.handle_list(tag, args, true).await,
+            "NAMESPACE" => {
+                self.write_bytes(
+                    format!(
+                        "* NAMESPACE ((\"\" \"/\")) NIL NIL\r\n{} OK NAMESPACE completed.\r\n",
+                        tag
+                    )
+                    .as_bytes(),
+                )
+                .await
+            }
+            "STATUS" => self.handle_status(tag, args).await,
+            "APPEND" => self.handle_append(tag, args).await,
+            "IDLE" => self.handle_idle(tag).await,
+            _ if self.selected.is_none() => self.write_bad(&tag, "No mailbox is selected.").await,
+            "CLOSE" => self.handle_close(tag, true).await,
+            "UNSELECT" => self.handle_close(tag, false).await,
+            "EXPUNGE" => self.handle_expunge(tag, None).await,
+            "UID EXPUNGE" => match args.next().and_then(|t| t.unwrap_atom()) {
+                Some(sequence) => self.handle_expunge(tag, sequence.into()).await,
+                None => self.write_bad(&tag, "Expected UID set.").await,
+            },
+            "SEARCH" => self.handle_search(tag, args, false).await,
+            "UID SEARCH" => self.handle_search(tag, args, true).await,
+            "FETCH" => self.handle_fetch(tag, args, false).await,
+            "UID FETCH" => self.handle_fetch(tag, args, true).await,
+            "STORE" => self.handle_store(tag, args, false).await,
+            "UID STORE" => self.handle_store(tag, args, true).await,
+            "COPY" => self.handle_copy(tag, args, false, false).await,
+            "UID COPY" => self.handle_copy(tag, args, true, false).await,
+            "MOVE" => self.handle_copy(tag, args, false, true).await,
+            "UID MOVE" => self.handle_copy(tag, args, true, true).await,
+            _ => self.write_bad(&tag, "Command not recognized.").await,
+        }
+    }
+
+    async fn handle_line(&mut self, data: String) -> Result<(), ()> {
+        if let Some(tag) = self.idle_tag.take() {
+            self.state_rx = None;
+            if data.eq_ignore_ascii_case("DONE") {
+                self.write_ok(&tag, "IDLE terminated.").await
+            } else {
+                self.write_bad(&tag, "Expected DONE.").await
+            }
+        } else if let Some((tag, mechanism)) = self.sasl.take() {
+            if data != "*" {
+                self.handle_sasl_data(tag, mechanism, data).await
+            } else {
+                self.write_bad(&tag, "Authentication cancelled.").await
+            }
+        } else {
+            self.write_bytes(b"* BAD Unexpected continuation data.\r\n")
+                .await
+        }
+    }
+
+    pub fn capabilities(&self) -> String {
+        let mut capabilities = String::from(concat!(
+            "IMAP4rev2 IMAP4rev1 LITERAL+ ENABLE IDLE NAMESPACE UNSELECT ",
+            "UIDPLUS MOVE SPECIAL-USE LIST-EXTENDED"
+        ));
+        if self.account.is_none() {
+            if !self.stream.is_tls() && self.tls_acceptor.is_some() {
+                capabilities.push_str(" STARTTLS");
+            }
+            if self.stream.is_tls() || self.allow_plain_auth {
+                capabilities.push_str(" AUTH=PLAIN AUTH=XOAUTH2");
+            } else {
+                capabilities.push_str(" LOGINDISABLED");
+            }
+        }
+        capabilities
+    }
+
+    async fn handle_starttls(&mut self, tag: String) -> Result<(), ()> {
+        match (&self.stream, &self.tls_acceptor) {
+            (Stream::Clear(_), Some(_)) => {
+                self.write_ok(&tag, "Begin TLS negotiation now.").await?;
+                match self
+                    .tls_acceptor
+                    .as_ref()
+                    .unwrap()
+                    .accept(std::mem::take(&mut self.stream).unwrap_clear())
+                    .await
+                {
+                    Ok(stream) => {
+                        self.stream = stream.into();
+                        Ok(())
+                    }
+                    Err(e) => {
+                        debug!("Failed to accept TLS connection: {}", e);
+                        Err(())
+                    }
+                }
+            }
+            (Stream::Clear(_), None) => {
+                self.write_no(&tag, "TLS not configured on this server.")
+                    .await
+            }
+            (Stream::Tls(_), _) => self.write_bad(&tag, "Already in TLS mode.").await,
+            (_, _) => {
+                unreachable!()
+            }
+        }
+    }
+
+    async fn handle_authenticate(
+        &mut self,
+        tag: String,
+        mut args: IntoIter<Token>,
+    ) -> Result<(), ()> {
+        if !self.stream.is_tls() && !self.allow_plain_auth {
+            return self
+                .write_no(&tag, "[PRIVACYREQUIRED] Authentication requires TLS.")
+                .await;
+        }
+
+        let mechanism = match args.next().and_then(|t| t.unwrap_atom()) {
+            Some(mechanism) if mechanism.eq_ignore_ascii_case("PLAIN") => Mechanism::Plain,
+            Some(mechanism) if mechanism.eq_ignore_ascii_case("XOAUTH2") => Mechanism::XOAuth2,
+            _ => {
+                return self
+                    .write_no(&tag, "[CANNOT] Mechanism not supported.")
+                    .await;
+            }
+        };
+
+        if let Some(initial_response) = args.next().and_then(|t| t.unwrap_string()) {
+            self.handle_sasl_data(tag, mechanism, initial_response)
+                .await
+        } else {
+            self.write_bytes(b"+ \r\n").await?;
+            self.sasl = (tag, mechanism).into();
+            self.parser.state = State::Line;
+            Ok(())
+        }
+    }
+
+    async fn handle_sasl_data(
+        &mut self,
+        tag: String,
+        mechanism: Mechanism,
+        data: String,
+    ) -> Result<(), ()> {
+        // A single '=' stands for an empty response
+        let data = if data == "=" {
+            Vec::new()
+        } else if let Ok(data) = base64::decode(data.as_bytes()) {
+            data
+        } else {
+            return self.write_bad(&tag, "Invalid base64 data.").await;
+        };
+
+        if let Some(credentials) = match mechanism {
+            Mechanism::Plain => decode_plain(&data),
+            Mechanism::XOAuth2 => decode_xoauth2(&data),
+        } {
+            self.authenticate(tag, credentials).await
+        } else {
+            self.write_bad(&tag, "Invalid authentication data.").await
+        }
+    }
+
+    async fn authenticate(&mut self, tag: String, credentials: Credentials) -> Result<(), ()> {
+        // Enforce rate limit for authentication requests
+        if self
+            .core
+            .is_auth_allowed(RemoteAddress::IpAddress(self.peer_addr.ip()))
+            .await
+            .is_err()
+        {
+            return self
+                .write_no(
+                    &tag,
+                    "[UNAVAILABLE] Too many authentication attempts, try again later.",
+                )
+                .await;
+        }
+
+        match self.core.authenticate_credentials(credentials).await {
+            Ok(Some(account)) => {
+                self.account = account.into();
+                self.write_ok(
+                    &tag,
+                    &format!(
+                        "[CAPABILITY {}] Authentication successful.",
+                        self.capabilities()
+                    ),
+                )
+                .await
+            }
+            Ok(None) => {
+                self.write_no(&tag, "[AUTHENTICATIONFAILED] Authentication failed.")
+                    .await
+            }
+            Err(err) => {
+                error!("Store error during authentication: {}", err);
+                self.write_no(&tag, "[UNAVAILABLE] Temporary authentication failure.")
+                    .await
+            }
+        }
+    }
+
+    async fn handle_idle(&mut self, tag: String) -> Result<(), ()> {
+        let mut types = Bitmap::default();
+        types.insert(TypeState::Email);
+        types.insert(TypeState::Mailbox);
+
+        if let Some(state_rx) = self
+            .core
+            .subscribe_state_manager(
+                IDLE_ID.fetch_add(1, Ordering::Relaxed),
+                self.account_id(),
+                types,
+            )
+            .await
+        {
+            self.write_bytes(b"+ Idling, waiting for DONE.\r\n").await?;
+            self.state_rx = state_rx.into();
+            self.idle_tag = tag.into();
+            self.parser.state = State::Line;
+            Ok(())
+        } else {
+            self.write_no(&tag, "[UNAVAILABLE] IDLE is temporarily unavailable.")
+                .await
+        }
+    }
+
+    pub async fn handle_state_change(&mut self) -> Result<(), ()> {
+        if self.selected.is_some() {
+            self.synchronize(true).await
+        } else {
+            Ok(())
+        }
+    }
+
+    // Reports expunged, new and updated messages in the selected mailbox.
+    // EXPUNGE responses are only sent when allowed, otherwise removed
+    // messages keep their sequence number until the next synchronization.
+    pub async fn synchronize(&mut self, allow_expunge: bool) -> Result<(), ()> {
+        let (mailbox_id, change_id) = match &self.selected {
+            Some(selected) => (selected.id, selected.change_id),
+            None => return Ok(()),
+        };
+        let account_id = self.account_id();
+        let store = self.core.store.clone();
+
+        let (messages, updated, change_id) = match self
+            .core
+            .spawn_worker(move || {
+                let messages = store.mail_mailbox_uids(account_id, mailbox_id)?;
+
+                let mut updated = Vec::new();
+                let changes = store.get_changes(
+                    account_id,
+                    Collection::Mail,
+                    change_id.map_or(changes::Query::All, changes::Query::Since),
+                )?;
+                let change_id = if let Some(changes) = changes {
+                    let uids = messages
+                        .iter()
+                        .map(|(uid, document_id)| (*document_id, *uid))
+                        .collect::<AHashMap<_, _>>();
+                    for change in changes.changes {
+                        if let Change::Update(id) = change {
+                            let document_id = id.get_document_id();
+                            if let Some(uid) = uids.get(&document_id) {
+                                if let Some(fields) = store
+                                    .get_orm::<jmap_mail::mail::schema::Email>(
+                                        account_id,
+                                        document_id,
+                                    )?
+                                {
+                                    updated.push((
+                                        *uid,
+                                        format_flags(fields.get_tags(&EmailProperty::Keywords)),
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                    changes.to_change_id.into()
+                } else {
+                    change_id
+                };
+
+                Ok((messages, updated, change_id))
+            })
+            .await
+        {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Failed to synchronize mailbox: {}", err);
+                return Ok(());
+            }
+        };
+
+        let selected = self.selected.as_mut().unwrap();
+        let mut response = String::new();
+        let prev_uids = std::mem::take(&mut selected.uids);
+        let prev_total = prev_uids.len();
+
+        if allow_expunge {
+            for (pos, uid) in prev_uids.iter().enumerate().rev() {
+                if messages.binary_search_by_key(uid, |(uid, _)| *uid).is_err() {
+                    response.push_str(&format!("* {} EXPUNGE\r\n", pos + 1));
+                }
+            }
+            let has_new = messages
+                .iter()
+                .any(|(uid, _)| prev_uids.binary_search(uid).is_err());
+            if has_new {
+                response.push_str(&format!("* {} EXISTS\r\n", messages.len()));
+            }
+            let (uids, document_ids) = messages.into_iter().unzip();
+            selected.uids = uids;
+            selected.document_ids = document_ids;
+        } else {
+            // Removed messages keep their sequence number, and as UIDs are
+            // never reused new messages are always appended at the end.
+            let mut uids = prev_uids;
+            for (pos, uid) in uids.iter().enumerate() {
+                if messages.binary_search_by_key(uid, |(uid, _)| *uid).is_err() {
+                    selected.document_ids[pos] = DocumentId::MAX;
+                }
+            }
+            let last_uid = uids.last().copied().unwrap_or(0);
+            for (uid, document_id) in messages {
+                if uid > last_uid {
+                    uids.push(uid);
+                    selected.document_ids.push(document_id);
+                }
+            }
+            if uids.len() != prev_total {
+                response.push_str(&format!("* {} EXISTS\r\n", uids.len()));
+            }
+            selected.uids = uids;
+        }
+
+        for (uid, flags) in updated {
+            if let Ok(pos) = selected.uids.binary_search(&uid) {
+                response.push_str(&format!(
+                    "* {} FETCH (UID {} FLAGS ({}))\r\n",
+                    pos + 1,
+                    uid,
+                    flags
+                ));
+            }
+        }
+        selected.change_id = change_id;
+
+        if !response.is_empty() {
+            self.write_bytes(response.as_bytes()).await
+        } else {
+            Ok(())
+        }
+    }
+
+    // Executes a single JMAP method, which takes care of committing the
+    // changes to the cluster and notifying state changes.
+    pub async fn call_method(&self, request: method::Request) -> Option<method::Response> {
+        handle_method_calls(
+            JMAPRequest {
+                using: Vec::new(),
+                method_calls: vec![method::Call {
+                    id: "c".to_string(),
+                    method: request,
+                }],
+                created_ids: None,
+            },
+            self.core.clone(),
+            self.account.clone().unwrap(),
+        )
+        .await
+        .method_responses
+        .into_iter()
+        .next()
+        .map(|call| call.method)
+    }
+
+    pub fn account_id(&self) -> AccountId {
+        self.account.as_ref().map(|a| a.account_id()).unwrap_or(0)
+    }
+
+    pub async fn write_ok(&mut self, tag: &str, message: &str) -> Result<(), ()> {
+        self.write_bytes(format!("{} OK {}\r\n", tag, message).as_bytes())
+            .await
+    }
+
+    pub async fn write_no(&mut self, tag: &str, message: &str) -> Result<(), ()> {
+        self.write_bytes(format!("{} NO {}\r\n", tag, message).as_bytes())
+            .await
+    }
+
+    pub async fn write_bad(&mut self, tag: &str, message: &str) -> Result<(), ()> {
+        self.write_bytes(format!("{} BAD {}\r\n", tag, message).as_bytes())
+            .await
+    }
+
+    pub async fn write_server_error(
+        &mut self,
+        tag: &str,
+        err: impl std::fmt::Display,
+    ) -> Result<(), ()> {
+        error!("IMAP request failed: {}", err);
+        self.write_no(tag, "[UNAVAILABLE] Temporary server failure.")
+            .await
+    }
+
+    pub async fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        self.stream.write_bytes(bytes).await
+    }
+
+    pub async fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<usize, ()> {
+        self.stream.read_bytes(bytes).await
+    }
+}
+
+// Maps JMAP set errors to IMAP response codes.
+pub fn set_error_code(err: &SetErrorType) -> &'static str {
+    match err {
+        SetErrorType::OverQuota
+        | SetErrorType::TooManyMailboxes
+        | SetErrorType::TooManyKeywords => "[OVERQUOTA]",
+        SetErrorType::Forbidden => "[NOPERM]",
+        SetErrorType::NotFound => "[NONEXISTENT]",
+        SetErrorType::MailboxHasChild | SetErrorType::MailboxHasEmail => "[INUSE]",
+        SetErrorType::TooLarge => "[LIMIT]",
+        _ => "[CANNOT]",
+    }
+}
@@ -31,9 +31,12 @@ use tokio::sync::{mpsc, watch};
 pub mod api;
 pub mod authorization;
 pub mod cluster;
+pub mod dav;
+pub mod imap;
 pub mod lmtp;
 pub mod server;
 pub mod services;
+pub mod smtp;
 
 #[cfg(test)]
 pub mod tests;
@@ -54,10 +57,15 @@ pub struct JMAPServer<T> {
 
     pub oauth: Box<authorization::oauth::OAuth>,
     pub oauth_codes: Cache<String, Arc<authorization::oauth::OAuthCode>>,
+    pub import_jobs: Cache<String, Arc<api::import::ImportJob>>,
+    pub export_jobs: Cache<String, Arc<api::export::ExportJob>>,
 
     pub sessions: Cache<String, authorization::Session>,
     pub rate_limiters: Cache<RemoteAddress, Arc<Limiter>>,
 
+    pub metrics: Arc<server::metrics::Metrics>,
+    pub reload: server::reload::ReloadState,
+
     #[cfg(test)]
     pub is_offline: std::sync::atomic::AtomicBool,
 }
@@ -28,12 +28,18 @@ use jmap::{
     sanitize_email,
     types::{jmap::JMAPId, type_state::TypeState},
 };
+use jmap_calendars::calendar_event::{ical::ICalendar, itip::JMAPCalendarITip};
 use jmap_mail::{
     mail::{
+        duplicate::{index_delivered_at, JMAPMailDuplicate},
+        encryption::JMAPMailEncryption,
         import::JMAPMailImport,
-        schema::{Email, Property},
+        schema::{AuthResult, Email, Keyword, Property, SignatureStatus},
+        signature::{is_encrypted, SignatureVerifier},
+        uid::JMAPMailUids,
     },
-    mail_parser::Message,
+    mail_parser::{HeaderName, HeaderValue, Message, PartType, RfcHeader},
+    mailbox::schema::Property as MailboxProperty,
     vacation_response::get::{JMAPGetVacationResponse, VacationMessage},
     INBOX_ID,
 };
@@ -42,9 +48,14 @@ use serde::{Deserialize, Serialize};
 use store::{
     ahash::{AHashMap, AHashSet},
     blob::BlobId,
-    core::{collection::Collection, document::Document, tag::Tag},
+    core::{collection::Collection, document::Document, tag::Tag, JMAPIdPrefix},
     log::changes::ChangeId,
-    tracing::{debug, error},
+    read::{
+        comparator::Comparator,
+        filter::{Filter, Query},
+        FilterMapper,
+    },
+    tracing::{debug, error, warn},
     write::{batch::WriteBatch, update::Changes},
     AccountId, DocumentId, JMAPStore, RecipientType, Store,
 };
@@ -55,7 +66,11 @@ use crate::{
     JMAPServer,
 };
 
-use super::session::{RcptType, Session};
+use super::{
+    mail_auth::AuthOutput,
+    session::{RcptType, Session},
+    spam_filter::{Action, Envelope, SpamScore},
+};
 
 impl<T> Session<T>
 where
@@ -80,9 +95,67 @@ where
         let rcpt_to_ids = std::mem::take(&mut self.rcpt_to_ids);
         let message = std::mem::take(&mut self.message);
 
+        // Verify DKIM, SPF and DMARC
+        let (message, auth_result) = if let Some(mail_auth) = &self.mail_auth {
+            let client = self
+                .client_addr
+                .map(|ip| (ip, self.client_helo.as_deref().unwrap_or_default()));
+            let (message, auth_result) = mail_auth.verify(message, &mail_from, client).await;
+            (message, Some(auth_result))
+        } else {
+            (message, None)
+        };
+
+        // Scan for spam
+        let (message, spam) = if let Some(spam_filter) = &self.spam_filter {
+            let rcpt_names = rcpt_to
+                .iter()
+                .map(|rcpt| match rcpt {
+                    RcptType::Mailbox { name, .. } | RcptType::List { name, .. } => name.clone(),
+                })
+                .collect::<Vec<_>>();
+            match spam_filter
+                .scan(
+                    &message,
+                    &Envelope {
+                        mail_from: &mail_from,
+                        rcpt_to: &rcpt_names,
+                        client_addr: self.client_addr,
+                        client_helo: self.client_helo.as_deref(),
+                        hostname: &self.hostname,
+                    },
+                )
+                .await
+            {
+                Ok(result) => {
+                    let headers = result.build_headers();
+                    let mut message_ = Vec::with_capacity(headers.len() + message.len());
+                    message_.extend_from_slice(headers.as_bytes());
+                    message_.extend_from_slice(&message);
+                    (message_, Some(result.score))
+                }
+                Err(err) => {
+                    warn!("Spam filter failed, delivering message unscanned: {}", err);
+                    (message, None)
+                }
+            }
+        } else {
+            (message, None)
+        };
+
+        debug!(
+            "Accepted message from <{}> for {} recipient(s), client {} [{}].",
+            mail_from,
+            rcpt_to_ids.len(),
+            self.client_name.as_deref().unwrap_or("unknown"),
+            self.client_addr.unwrap_or_else(|| self.peer_addr.ip())
+        );
+
         // Ingest
         let result = if self.core.is_leader() {
-            self.core.mail_ingest(mail_from, rcpt_to_ids, message).await
+            self.core
+                .mail_ingest(mail_from, rcpt_to_ids, message, auth_result, spam)
+                .await
         } else {
             // Send request to leader
             match self
@@ -91,15 +164,19 @@ where
                     mail_from,
                     rcpt_to: rcpt_to_ids,
                     raw_message: message,
+                    auth_result,
+                    spam,
                 })
                 .await
             {
                 Some(CommandResponse::IngestMessage { result }) => result,
                 Some(CommandResponse::Error { message }) => {
                     debug!("RPC failed: {}", message);
+                    self.core.metrics.record_lmtp_delivery(450);
                     return self.write_bytes(b"450 4.3.2 Temporary Failure.\r\n").await;
                 }
                 _ => {
+                    self.core.metrics.record_lmtp_delivery(450);
                     return self.write_bytes(b"450 4.3.2 Temporary Failure.\r\n").await;
                 }
             }
@@ -108,6 +185,9 @@ where
         let delivery_status = match result {
             Ok(delivery_status) => delivery_status,
             Err(err) => {
+                self.core.metrics.record_lmtp_delivery(
+                    err.get(..3).and_then(|code| code.parse().ok()).unwrap_or(0),
+                );
                 return self.write_bytes(err.as_bytes()).await;
             }
         };
@@ -152,6 +232,11 @@ where
                 }
             };
 
+            self.core.metrics.record_lmtp_delivery(match code[0] {
+                b'2' => 250,
+                b'4' => 451,
+                _ => 550,
+            });
             buf.extend_from_slice(code);
             buf.extend_from_slice(b" <");
             buf.extend_from_slice(mailbox);
@@ -213,11 +298,15 @@ where
         mail_from: String,
         rcpt_to: AHashSet<AccountId>,
         raw_message: Vec<u8>,
+        auth_result: Option<AuthOutput>,
+        spam: Option<SpamScore>,
     ) -> Result<AHashMap<AccountId, DeliveryStatus>, String> {
         // Ingest message
         let store = self.store.clone();
         let (change_id, status) = match self
-            .spawn_worker(move || Ok(store.mail_ingest(mail_from, rcpt_to, raw_message)))
+            .spawn_worker(move || {
+                Ok(store.mail_ingest(mail_from, rcpt_to, raw_message, auth_result, spam))
+            })
             .await
             .unwrap()
         {
@@ -290,6 +379,9 @@ where
 
                     delivery_status.insert(account_id, DeliveryStatus::Success);
                 }
+                Status::Duplicate { account_id } => {
+                    delivery_status.insert(account_id, DeliveryStatus::Success);
+                }
                 Status::TemporaryFailure { account_id, reason } => {
                     delivery_status.insert(account_id, DeliveryStatus::TemporaryFailure { reason });
                 }
@@ -309,12 +401,18 @@ pub trait JMAPMailIngest {
         mail_from: String,
         rcpt_to: AHashSet<AccountId>,
         raw_message: Vec<u8>,
+        auth_result: Option<AuthOutput>,
+        spam: Option<SpamScore>,
     ) -> Result<Vec<Status>, Status>;
     fn mail_deliver_rcpt(
         &self,
         account_id: AccountId,
         document: &Document,
         return_address: Option<&str>,
+        auth_result: Option<AuthResult>,
+        signatures: &[(Property, SignatureStatus)],
+        spam: Option<&SpamScore>,
+        itip: Option<(&str, &[ICalendar])>,
     ) -> Status;
 }
 
@@ -327,6 +425,8 @@ where
         mail_from: String,
         rcpt_to: AHashSet<AccountId>,
         raw_message: Vec<u8>,
+        auth_result: Option<AuthOutput>,
+        spam: Option<SpamScore>,
     ) -> Result<Vec<Status>, Status> {
         // Parse message
         let message = if let Some(message) = Message::parse(&raw_message) {
@@ -356,24 +456,149 @@ where
             }
         });
 
+        // Extract iMIP scheduling messages
+        let sender = match message.get_from() {
+            HeaderValue::Address(addr) => addr.address.as_deref().and_then(sanitize_email),
+            _ => None,
+        };
+        let calendars = message
+            .parts
+            .iter()
+            .filter(|part| {
+                part.headers
+                    .iter()
+                    .any(|header| match (&header.name, &header.value) {
+                        (HeaderName::Rfc(RfcHeader::ContentType), HeaderValue::ContentType(ct)) => {
+                            ct.c_type.eq_ignore_ascii_case("text")
+                                && ct
+                                    .c_subtype
+                                    .as_ref()
+                                    .map_or(false, |st| st.eq_ignore_ascii_case("calendar"))
+                        }
+                        _ => false,
+                    })
+            })
+            .filter_map(|part| match &part.body {
+                PartType::Text(text) => ICalendar::parse(text),
+                PartType::Binary(bytes) | PartType::InlineBinary(bytes) => {
+                    ICalendar::parse(std::str::from_utf8(bytes).ok()?)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        // Scheduling messages can create, update or cancel events on behalf of
+        // the sender, they are only applied when the From domain was authenticated
+        // by a DMARC pass or an aligned SPF or DKIM pass. Otherwise the message
+        // is delivered without touching the recipient's calendars.
+        let itip = match sender.as_deref() {
+            Some(sender) if !calendars.is_empty() => {
+                if auth_result.map_or(false, |auth| auth.is_from_authenticated()) {
+                    Some((sender, calendars.as_slice()))
+                } else {
+                    debug!(
+                        "Ignoring scheduling message from unauthenticated sender <{}>.",
+                        sender
+                    );
+                    None
+                }
+            }
+            _ => None,
+        };
+        let auth_result = auth_result.map(|auth| auth.result);
+
+        // Verify S/MIME and OpenPGP signatures at delivery time
+        let mut verifier = SignatureVerifier::new(&self.config);
+        let signatures = [
+            (
+                Property::SmimeStatusAtDelivery,
+                verifier.verify_smime(&message),
+            ),
+            (Property::PgpStatusAtDelivery, verifier.verify_pgp(&message)),
+        ]
+        .into_iter()
+        .filter_map(|(property, result)| (property, result?.status).into())
+        .collect::<Vec<_>>();
+
+        // Recipients with an encryption key receive their own encrypted copy
+        let mut result = Vec::with_capacity(rcpt_to.len());
+        let mut plain_rcpts = Vec::with_capacity(rcpt_to.len());
+        let mut encrypted_rcpts = Vec::new();
+        let already_encrypted = is_encrypted(&message);
+        for account_id in rcpt_to {
+            match self.mail_encryption_params(account_id) {
+                Ok(Some(params)) if !already_encrypted => {
+                    encrypted_rcpts.push((account_id, params))
+                }
+                Ok(_) => plain_rcpts.push(account_id),
+                Err(err) => {
+                    error!("Failed to obtain encryption key: {}", err);
+                    result.push(Status::internal_error(account_id));
+                }
+            }
+        }
+
         // Build message document
-        let mut document = Document::new(Collection::Mail, DocumentId::MAX);
         let blob_id = BlobId::new_external(&raw_message);
-        if let Err(err) = self.mail_parse_item(&mut document, blob_id.clone(), message, None) {
-            error!("Failed to parse message during ingestion: {}", err);
-            return Err(Status::internal_error(AccountId::MAX));
-        }
+        let document = if !plain_rcpts.is_empty() {
+            let mut document = Document::new(Collection::Mail, DocumentId::MAX);
+            if let Err(err) = self.mail_parse_item(&mut document, blob_id.clone(), message, None) {
+                error!("Failed to parse message during ingestion: {}", err);
+                return Err(Status::internal_error(AccountId::MAX));
+            }
+            Some(document)
+        } else {
+            drop(message);
+            None
+        };
 
-        // Store raw message as a blob
-        if let Err(err) = self.blob_store(&blob_id, raw_message) {
-            error!("Failed to store blob during message ingestion: {}", err);
-            return Err(Status::internal_error(AccountId::MAX));
+        // Deliver encrypted copies
+        for (account_id, params) in encrypted_rcpts {
+            let mut document = Document::new(Collection::Mail, DocumentId::MAX);
+            result.push(
+                match self.mail_parse_encrypted_item(&mut document, &params, &raw_message, None) {
+                    Ok(_) => self.mail_deliver_rcpt(
+                        account_id,
+                        &document,
+                        return_address.as_deref(),
+                        auth_result,
+                        &signatures,
+                        spam.as_ref(),
+                        itip,
+                    ),
+                    Err(err) => {
+                        error!("Failed to encrypt message during ingestion: {}", err);
+                        Status::internal_error(account_id)
+                    }
+                },
+            );
         }
 
-        // Deliver message to recipients
-        let mut result = Vec::with_capacity(rcpt_to.len());
-        for account_id in rcpt_to {
-            result.push(self.mail_deliver_rcpt(account_id, &document, return_address.as_deref()));
+        if let Some(document) = document {
+            // Store raw message as a blob
+            let is_stored = match self.blob_store(&blob_id, raw_message) {
+                Ok(_) => true,
+                Err(err) => {
+                    error!("Failed to store blob during message ingestion: {}", err);
+                    false
+                }
+            };
+
+            // Deliver message to recipients
+            for account_id in plain_rcpts {
+                result.push(if is_stored {
+                    self.mail_deliver_rcpt(
+                        account_id,
+                        &document,
+                        return_address.as_deref(),
+                        auth_result,
+                        &signatures,
+                        spam.as_ref(),
+                        itip,
+                    )
+                } else {
+                    Status::internal_error(account_id)
+                });
+            }
         }
 
         Ok(result)
@@ -384,6 +609,10 @@ where
         account_id: AccountId,
         document: &Document,
         return_address: Option<&str>,
+        auth_result: Option<AuthResult>,
+        signatures: &[(Property, SignatureStatus)],
+        spam: Option<&SpamScore>,
+        itip: Option<(&str, &[ICalendar])>,
     ) -> Status {
         // Prepare batch
         let mut batch = WriteBatch::new(account_id);
@@ -398,10 +627,79 @@ where
             }
         }
 
-        // Add mailbox tags
+        // Lock account while duplicates are looked up and threads are merged,
+        // the lock is held until the message is written.
+        let _lock = self.lock_collection(account_id, Collection::Mail);
+
+        // Discard copies of messages delivered within the account's duplicate window
+        match self.mail_find_duplicate(account_id, &document) {
+            Ok(Some((document_id, _))) => {
+                debug!(
+                    "Discarding duplicate of message {}/{}.",
+                    account_id, document_id
+                );
+                return Status::Duplicate { account_id };
+            }
+            Ok(None) => index_delivered_at(&mut document),
+            Err(err) => {
+                error!("Failed to look up duplicate messages: {}", err);
+                return Status::internal_error(account_id);
+            }
+        }
+
+        // Apply spam filter verdict using the account's thresholds
         let mut orm = TinyORM::<Email>::new();
-        batch.log_child_update(Collection::Mailbox, JMAPId::new(INBOX_ID.into()));
-        orm.tag(Property::MailboxIds, Tag::Id(INBOX_ID));
+        let mut mailbox_id = INBOX_ID;
+        let is_junk = if let Some(spam) = spam {
+            let thresholds = match self.get_account_spam_filter(account_id) {
+                Ok(thresholds) => thresholds,
+                Err(err) => {
+                    error!("Failed to obtain spam filter settings: {}", err);
+                    return Status::internal_error(account_id);
+                }
+            };
+            match spam.action(thresholds.as_ref()) {
+                Action::Accept => false,
+                Action::Junk => {
+                    match self.query_store::<FilterMapper>(
+                        account_id,
+                        Collection::Mailbox,
+                        Filter::eq(
+                            MailboxProperty::Role.into(),
+                            Query::Keyword("junk".to_string()),
+                        ),
+                        Comparator::None,
+                    ) {
+                        Ok(ids) => {
+                            if let Some(id) = ids.into_iter().next() {
+                                mailbox_id = id.get_document_id();
+                            }
+                        }
+                        Err(err) => {
+                            error!("Failed to query junk mailbox: {}", err);
+                            return Status::internal_error(account_id);
+                        }
+                    }
+                    orm.tag(Property::Keywords, Tag::Static(Keyword::JUNK));
+                    true
+                }
+                Action::Reject => {
+                    return Status::perm_fail(account_id, "Message rejected as spam.");
+                }
+            }
+        } else {
+            false
+        };
+
+        // Add mailbox tags
+        batch.log_child_update(Collection::Mailbox, JMAPId::new(mailbox_id.into()));
+        orm.tag(Property::MailboxIds, Tag::Id(mailbox_id));
+        if let Some(auth_result) = auth_result {
+            orm.tag(Property::AuthResult, auth_result.into());
+        }
+        for (property, status) in signatures {
+            orm.tag(property.clone(), (*status).into());
+        }
 
         // Serialize ORM
         if let Err(err) = orm.insert(&mut document) {
@@ -419,8 +717,14 @@ where
         };
         document.document_id = document_id;
 
-        // Build vacation response
-        let vacation_response = if let Some(return_address) = &return_address {
+        // Assign IMAP UIDs
+        if let Err(err) = self.mail_assign_uids(&mut batch, &mut document) {
+            error!("Failed to assign UIDs during ingestion: {}", err);
+            return Status::internal_error(account_id);
+        }
+
+        // Build vacation response, unless the message is junk
+        let vacation_response = if let Some(return_address) = return_address.filter(|_| !is_junk) {
             match self.get_account_details(account_id) {
                 Ok(Some((email, from_name, _))) => {
                     match self.build_vacation_response(
@@ -452,9 +756,6 @@ where
             None
         };
 
-        // Lock account while threads are merged
-        let _lock = self.lock_collection(account_id, Collection::Mail);
-
         // Obtain thread Id
         match self.mail_set_thread(&mut batch, &mut document) {
             Ok(thread_id) => {
@@ -462,11 +763,28 @@ where
                 batch.log_insert(Collection::Mail, JMAPId::from_parts(thread_id, document_id));
                 batch.insert_document(document);
                 match self.write(batch) {
-                    Ok(Some(changes)) => Status::Success {
-                        account_id,
-                        changes,
-                        vacation_response,
-                    },
+                    Ok(Some(mut changes)) => {
+                        // Apply scheduling messages to the recipient's calendars
+                        if let Some((sender, calendars)) = itip.filter(|_| !is_junk) {
+                            match self.calendar_itip_ingest(account_id, sender, calendars) {
+                                Ok(Some(itip_changes)) => {
+                                    changes.collections.union(&itip_changes.collections);
+                                    changes.change_id =
+                                        std::cmp::max(changes.change_id, itip_changes.change_id);
+                                }
+                                Ok(None) => (),
+                                Err(err) => {
+                                    error!("Failed to process scheduling message: {}", err);
+                                }
+                            }
+                        }
+
+                        Status::Success {
+                            account_id,
+                            changes,
+                            vacation_response,
+                        }
+                    }
                     Ok(None) => {
                         error!("Unexpected error during ingestion.");
                         Status::internal_error(account_id)
@@ -491,6 +809,9 @@ pub enum Status {
         changes: Changes,
         vacation_response: Option<VacationMessage>,
     },
+    Duplicate {
+        account_id: AccountId,
+    },
     TemporaryFailure {
         account_id: AccountId,
         reason: Cow<'static, str>,
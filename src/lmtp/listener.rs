@@ -37,7 +37,9 @@ use tokio::{io::AsyncWriteExt, net::TcpListener, sync::watch};
 use tokio_rustls::TlsAcceptor;
 
 use crate::{
-    cluster::rpc::tls::load_tls_server_config, lmtp::session::Session, server::failed_to,
+    cluster::rpc::tls::load_tls_server_config_with_resolver,
+    lmtp::{mail_auth::MailAuthenticator, session::Session, spam_filter::SpamFilter},
+    server::failed_to,
     JMAPServer,
 };
 
@@ -80,23 +82,74 @@ pub fn spawn_lmtp<T>(
     };
 
     // Build TLS acceptor
-    let tls_acceptor = if let (Some(cert_path), Some(key_path)) = (
-        settings.get("lmtp-cert-path"),
-        settings.get("lmtp-key-path"),
-    ) {
-        Arc::new(TlsAcceptor::from(Arc::new(load_tls_server_config(
-            &cert_path, &key_path,
-        ))))
-        .into()
-    } else {
-        None
-    };
+    let tls_acceptor = core.reload.lmtp_tls.clone().map(|resolver| {
+        Arc::new(TlsAcceptor::from(Arc::new(
+            load_tls_server_config_with_resolver(resolver),
+        )))
+    });
     let mut tls_only = settings.parse("lmtp-tls-only").unwrap_or(false);
     if tls_only && tls_acceptor.is_none() {
         warn!("LMTP server is configured to only accept TLS connections, but no TLS certificate was provided.");
         tls_only = false;
     }
 
+    // Build message authenticator and spam filter
+    let mail_auth = MailAuthenticator::new(settings).map(Arc::new);
+    let spam_filter = SpamFilter::new(settings).map(Arc::new);
+
+    let hostname = Arc::new(
+        gethostname::gethostname()
+            .to_str()
+            .unwrap_or("localhost")
+            .to_string(),
+    );
+    let greeting = Arc::new(
+        format!(
+            concat!(
+                "220 {} Stalwart LMTP v",
+                env!("CARGO_PKG_VERSION"),
+                " at your service.\r\n"
+            ),
+            &hostname
+        )
+        .into_bytes(),
+    );
+
+    // Listen on a local socket as well, if configured
+    if let Some(socket_path) = settings.get("lmtp-unix-socket") {
+        #[cfg(unix)]
+        {
+            let socket_mode = settings
+                .get("lmtp-unix-socket-mode")
+                .map(|mode| {
+                    u32::from_str_radix(&mode, 8).unwrap_or_else(|_| {
+                        failed_to(&format!(
+                            "parse 'lmtp-unix-socket-mode', invalid mode {}.",
+                            mode
+                        ));
+                    })
+                })
+                .unwrap_or(0o660);
+            spawn_lmtp_unix(
+                core.clone(),
+                socket_path,
+                socket_mode,
+                greeting.clone(),
+                hostname.clone(),
+                mail_auth.clone(),
+                spam_filter.clone(),
+                shutdown_rx.clone(),
+            );
+        }
+        #[cfg(not(unix))]
+        {
+            warn!(
+                "Ignoring 'lmtp-unix-socket' {}, Unix sockets are not supported on this platform.",
+                socket_path
+            );
+        }
+    }
+
     tokio::spawn(async move {
         // Start listening for LMTP connections.
         let listener = match TcpListener::bind(bind_addr).await {
@@ -107,24 +160,6 @@ pub fn spawn_lmtp<T>(
             }
         };
 
-        let hostname = Arc::new(
-            gethostname::gethostname()
-                .to_str()
-                .unwrap_or("localhost")
-                .to_string(),
-        );
-        let greeting = Arc::new(
-            format!(
-                concat!(
-                    "220 {} Stalwart LMTP v",
-                    env!("CARGO_PKG_VERSION"),
-                    " at your service.\r\n"
-                ),
-                &hostname
-            )
-            .into_bytes(),
-        );
-
         loop {
             tokio::select! {
                 stream = listener.accept() => {
@@ -142,6 +177,8 @@ pub fn spawn_lmtp<T>(
                             let greeting = greeting.clone();
                             let tls_acceptor = tls_acceptor.clone();
                             let hostname = hostname.clone();
+                            let mail_auth = mail_auth.clone();
+                            let spam_filter = spam_filter.clone();
 
                             tokio::spawn(async move {
                                 if tls_only {
@@ -160,7 +197,7 @@ pub fn spawn_lmtp<T>(
                                     }
 
                                     handle_conn(
-                                        Session::new(core, peer_addr, stream.into(), None, hostname),
+                                        Session::new(core, peer_addr, stream.into(), None, hostname, mail_auth, spam_filter),
                                         shutdown_rx
                                     ).await;
                                 } else {
@@ -171,7 +208,7 @@ pub fn spawn_lmtp<T>(
                                     }
 
                                     handle_conn(
-                                        Session::new(core, peer_addr, stream.into(), tls_acceptor, hostname),
+                                        Session::new(core, peer_addr, stream.into(), tls_acceptor, hostname, mail_auth, spam_filter),
                                         shutdown_rx
                                     ).await;
                                 }
@@ -191,6 +228,85 @@ pub fn spawn_lmtp<T>(
     });
 }
 
+#[cfg(unix)]
+#[allow(clippy::too_many_arguments)]
+fn spawn_lmtp_unix<T>(
+    core: web::Data<JMAPServer<T>>,
+    socket_path: String,
+    socket_mode: u32,
+    greeting: Arc<Vec<u8>>,
+    hostname: Arc<String>,
+    mail_auth: Option<Arc<MailAuthenticator>>,
+    spam_filter: Option<Arc<SpamFilter>>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    use std::fs;
+    use tokio::net::UnixListener;
+
+    info!("Starting LMTP service at {}...", socket_path);
+
+    // Local clients have no address, XCLIENT or XFORWARD is expected to
+    // provide the original one.
+    let peer_addr = SocketAddr::from((IpAddr::from([127, 0, 0, 1]), 0));
+
+    // Remove any socket left behind by a previous run. Access to the socket
+    // is restricted using its file permissions, which are set through the
+    // umask so that the socket is never reachable with a wider mode.
+    let _ = fs::remove_file(&socket_path);
+    let umask = unsafe { libc::umask((!socket_mode & 0o777) as libc::mode_t) };
+    let listener = UnixListener::bind(&socket_path);
+    unsafe { libc::umask(umask) };
+    let listener = match listener {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!("Failed to bind LMTP service to {}: {}", socket_path, err);
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                stream = listener.accept() => {
+                    match stream {
+                        Ok((mut stream, _)) => {
+                            let shutdown_rx = shutdown_rx.clone();
+                            let core = core.clone();
+                            let greeting = greeting.clone();
+                            let hostname = hostname.clone();
+                            let mail_auth = mail_auth.clone();
+                            let spam_filter = spam_filter.clone();
+
+                            tokio::spawn(async move {
+                                // Send greeting
+                                if let Err(err) = stream.write_all(&greeting).await {
+                                    debug!("Failed to send greeting to local client: {}", err);
+                                    return;
+                                }
+
+                                handle_conn(
+                                    Session::new(core, peer_addr, stream.into(), None, hostname, mail_auth, spam_filter),
+                                    shutdown_rx
+                                ).await;
+                            });
+                        }
+                        Err(err) => {
+                            error!("Failed to accept Unix socket connection: {}", err);
+                        }
+                    }
+                },
+                _ = shutdown_rx.changed() => {
+                    debug!("LMTP Unix socket listener shutting down.");
+                    let _ = fs::remove_file(&socket_path);
+                    break;
+                }
+            };
+        }
+    });
+}
+
 pub async fn handle_conn<T>(mut session: Session<T>, mut shutdown_rx: watch::Receiver<bool>)
 where
     T: for<'x> Store<'x> + 'static,
@@ -0,0 +1,544 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::SystemTime;
+
+use jmap::base64;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use rsa::{
+    pkcs1::DecodeRsaPublicKey, pkcs8::DecodePublicKey, PaddingScheme, PublicKey, RsaPublicKey,
+};
+use store::sha2::{Digest, Sha256};
+
+use super::{
+    dns::{DnsError, Resolver},
+    parse_tags, RawHeader,
+};
+
+// Maximum number of signatures verified per message
+const MAX_SIGNATURES: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DkimResult {
+    Pass,
+    Fail(String),
+    Neutral(String),
+    TempError(String),
+    PermError(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DkimOutput {
+    pub result: DkimResult,
+    pub domain: String,
+    pub selector: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Algorithm {
+    RsaSha256,
+    Ed25519Sha256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Canonicalization {
+    Simple,
+    Relaxed,
+}
+
+struct Signature<'x> {
+    algorithm: Algorithm,
+    header_canon: Canonicalization,
+    body_canon: Canonicalization,
+    signature: Vec<u8>,
+    body_hash: Vec<u8>,
+    domain: String,
+    selector: String,
+    headers: Vec<String>,
+    body_length: Option<usize>,
+    header: &'x RawHeader<'x>,
+}
+
+pub async fn verify(
+    resolver: &dyn Resolver,
+    headers: &[RawHeader<'_>],
+    body: &[u8],
+) -> Vec<DkimOutput> {
+    let mut results = Vec::new();
+
+    for header in headers
+        .iter()
+        .filter(|h| h.name.eq_ignore_ascii_case(b"DKIM-Signature"))
+        .take(MAX_SIGNATURES)
+    {
+        let signature = match Signature::parse(header) {
+            Ok(signature) => signature,
+            Err((domain, err)) => {
+                results.push(DkimOutput {
+                    result: DkimResult::PermError(err),
+                    domain,
+                    selector: String::new(),
+                });
+                continue;
+            }
+        };
+
+        results.push(DkimOutput {
+            result: signature.verify(resolver, headers, body).await,
+            domain: signature.domain,
+            selector: signature.selector,
+        });
+    }
+
+    results
+}
+
+impl<'x> Signature<'x> {
+    fn parse(header: &'x RawHeader<'x>) -> Result<Self, (String, String)> {
+        let value = String::from_utf8_lossy(header.value);
+        let tags = parse_tags(&value);
+        let tag = |name: &str| {
+            tags.iter()
+                .find_map(|(k, v)| if *k == name { Some(*v) } else { None })
+        };
+        let domain = tag("d").unwrap_or_default().to_ascii_lowercase();
+        let err = |message: &str| (domain.clone(), message.to_string());
+
+        if tag("v") != Some("1") {
+            return Err(err("unsupported version"));
+        }
+        let algorithm = match tag("a") {
+            Some(a) if a.eq_ignore_ascii_case("rsa-sha256") => Algorithm::RsaSha256,
+            Some(a) if a.eq_ignore_ascii_case("ed25519-sha256") => Algorithm::Ed25519Sha256,
+            Some(a) if a.eq_ignore_ascii_case("rsa-sha1") => {
+                return Err(err("rsa-sha1 signatures are not accepted"))
+            }
+            _ => return Err(err("unsupported algorithm")),
+        };
+        let (header_canon, body_canon) = match tag("c").unwrap_or("simple/simple").split_once('/') {
+            Some((h, b)) => (Canonicalization::parse(h), Canonicalization::parse(b)),
+            None => (
+                Canonicalization::parse(tag("c").unwrap_or("simple")),
+                Some(Canonicalization::Simple),
+            ),
+        };
+        let (header_canon, body_canon) = match (header_canon, body_canon) {
+            (Some(h), Some(b)) => (h, b),
+            _ => return Err(err("unsupported canonicalization")),
+        };
+        let headers = tag("h")
+            .ok_or_else(|| err("missing signed header list"))?
+            .split(':')
+            .map(|h| h.trim().to_ascii_lowercase())
+            .collect::<Vec<_>>();
+        if !headers.iter().any(|h| h == "from") {
+            return Err(err("from header is not signed"));
+        }
+        if domain.is_empty() {
+            return Err(err("missing signing domain"));
+        }
+        if let Some(expires) = tag("x").and_then(|x| x.parse::<u64>().ok()) {
+            if expires
+                < SystemTime::now()
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0)
+            {
+                return Err(err("signature expired"));
+            }
+        }
+
+        Ok(Signature {
+            algorithm,
+            header_canon,
+            body_canon,
+            signature: base64::decode(strip_whitespace(tag("b").unwrap_or_default()))
+                .map_err(|_| err("invalid signature encoding"))?,
+            body_hash: base64::decode(strip_whitespace(tag("bh").unwrap_or_default()))
+                .map_err(|_| err("invalid body hash encoding"))?,
+            selector: tag("s")
+                .filter(|s| !s.is_empty())
+                .ok_or_else(|| err("missing selector"))?
+                .to_ascii_lowercase(),
+            body_length: tag("l").and_then(|l| l.parse().ok()),
+            headers,
+            domain,
+            header,
+        })
+    }
+
+    async fn verify(
+        &self,
+        resolver: &dyn Resolver,
+        headers: &[RawHeader<'_>],
+        body: &[u8],
+    ) -> DkimResult {
+        // Verify body hash
+        let mut body = self.body_canon.canonicalize_body(body);
+        if let Some(body_length) = self.body_length {
+            if body_length > body.len() {
+                return DkimResult::PermError("body length exceeds message".to_string());
+            }
+            body.truncate(body_length);
+        }
+        if Sha256::digest(&body).as_slice() != self.body_hash.as_slice() {
+            return DkimResult::Fail("body hash mismatch".to_string());
+        }
+
+        // Fetch public key
+        let record = match resolver
+            .txt_lookup(&format!("{}._domainkey.{}", self.selector, self.domain))
+            .await
+        {
+            Ok(records) => records.join(""),
+            Err(DnsError::NotFound) => {
+                return DkimResult::PermError("no key for signature".to_string());
+            }
+            Err(DnsError::Temporary(err)) => return DkimResult::TempError(err),
+        };
+        let key_tags = parse_tags(&record);
+        let key_tag = |name: &str| {
+            key_tags
+                .iter()
+                .find_map(|(k, v)| if *k == name { Some(*v) } else { None })
+        };
+        let public_key = match key_tag("p").map(strip_whitespace) {
+            Some(p) if !p.is_empty() => match base64::decode(p) {
+                Ok(p) => p,
+                Err(_) => return DkimResult::PermError("invalid public key".to_string()),
+            },
+            _ => return DkimResult::PermError("key revoked".to_string()),
+        };
+
+        // Hash signed headers
+        let mut data = Vec::with_capacity(1024);
+        let mut used = vec![false; headers.len()];
+        for name in &self.headers {
+            if let Some(pos) = headers
+                .iter()
+                .enumerate()
+                .rev()
+                .position(|(pos, h)| !used[pos] && h.name.eq_ignore_ascii_case(name.as_bytes()))
+            {
+                let pos = headers.len() - 1 - pos;
+                used[pos] = true;
+                self.header_canon.canonicalize_header(
+                    headers[pos].raw_name,
+                    headers[pos].value,
+                    &mut data,
+                );
+            }
+        }
+        let unsigned = remove_signature(self.header.value);
+        self.header_canon
+            .canonicalize_header(self.header.raw_name, &unsigned, &mut data);
+        data.truncate(data.len() - 2);
+
+        let verified = match (self.algorithm, key_tag("k").unwrap_or("rsa")) {
+            (Algorithm::RsaSha256, k) if k.eq_ignore_ascii_case("rsa") => {
+                match RsaPublicKey::from_public_key_der(&public_key)
+                    .or_else(|_| RsaPublicKey::from_pkcs1_der(&public_key))
+                {
+                    Ok(key) => key
+                        .verify(
+                            PaddingScheme::new_pkcs1v15_sign(Some(rsa::Hash::SHA2_256)),
+                            Sha256::digest(&data).as_slice(),
+                            &self.signature,
+                        )
+                        .is_ok(),
+                    Err(_) => return DkimResult::PermError("invalid public key".to_string()),
+                }
+            }
+            (Algorithm::Ed25519Sha256, k) if k.eq_ignore_ascii_case("ed25519") => {
+                UnparsedPublicKey::new(&ED25519, &public_key)
+                    .verify(Sha256::digest(&data).as_slice(), &self.signature)
+                    .is_ok()
+            }
+            _ => return DkimResult::PermError("key type mismatch".to_string()),
+        };
+
+        if verified {
+            DkimResult::Pass
+        } else {
+            DkimResult::Fail("signature did not verify".to_string())
+        }
+    }
+}
+
+impl Canonicalization {
+    fn parse(value: &str) -> Option<Self> {
+        if value.eq_ignore_ascii_case("simple") {
+            Some(Canonicalization::Simple)
+        } else if value.eq_ignore_ascii_case("relaxed") {
+            Some(Canonicalization::Relaxed)
+        } else {
+            None
+        }
+    }
+
+    fn canonicalize_header(&self, name: &[u8], value: &[u8], out: &mut Vec<u8>) {
+        match self {
+            Canonicalization::Simple => {
+                out.extend_from_slice(name);
+                out.push(b':');
+                for (pos, &ch) in value.iter().enumerate() {
+                    if ch == b'\n' && (pos == 0 || value[pos - 1] != b'\r') {
+                        out.push(b'\r');
+                    }
+                    out.push(ch);
+                }
+                out.extend_from_slice(b"\r\n");
+            }
+            Canonicalization::Relaxed => {
+                out.extend(
+                    name.iter()
+                        .filter(|ch| !ch.is_ascii_whitespace())
+                        .map(|ch| ch.to_ascii_lowercase()),
+                );
+                out.push(b':');
+                let mut has_text = false;
+                let mut has_space = false;
+                for &ch in value {
+                    match ch {
+                        b'\r' | b'\n' => (),
+                        b' ' | b'\t' => {
+                            has_space = has_text;
+                        }
+                        _ => {
+                            if has_space {
+                                out.push(b' ');
+                                has_space = false;
+                            }
+                            out.push(ch);
+                            has_text = true;
+                        }
+                    }
+                }
+                out.extend_from_slice(b"\r\n");
+            }
+        }
+    }
+
+    fn canonicalize_body(&self, body: &[u8]) -> Vec<u8> {
+        let mut lines = body
+            .split(|&ch| ch == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+            .map(|line| match self {
+                Canonicalization::Simple => line.to_vec(),
+                Canonicalization::Relaxed => {
+                    let mut result = Vec::with_capacity(line.len());
+                    let mut has_space = false;
+                    for &ch in line {
+                        if ch == b' ' || ch == b'\t' {
+                            has_space = true;
+                        } else {
+                            if has_space {
+                                result.push(b' ');
+                                has_space = false;
+                            }
+                            result.push(ch);
+                        }
+                    }
+                    result
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Ignore empty lines at the end of the body
+        while lines.last().map_or(false, |line| line.is_empty()) {
+            lines.pop();
+        }
+
+        let mut result = Vec::with_capacity(body.len() + lines.len());
+        for line in lines {
+            result.extend_from_slice(&line);
+            result.extend_from_slice(b"\r\n");
+        }
+        if result.is_empty() && *self == Canonicalization::Simple {
+            result.extend_from_slice(b"\r\n");
+        }
+        result
+    }
+}
+
+// Returns the DKIM-Signature header value with the contents of its b= tag removed
+fn remove_signature(value: &[u8]) -> Vec<u8> {
+    let mut result = Vec::with_capacity(value.len());
+    for (pos, tag) in value.split(|&ch| ch == b';').enumerate() {
+        if pos > 0 {
+            result.push(b';');
+        }
+        let name_end = tag.iter().position(|&ch| ch == b'=').unwrap_or(tag.len());
+        if tag[..name_end]
+            .iter()
+            .filter(|ch| !ch.is_ascii_whitespace())
+            .eq(b"b".iter())
+        {
+            result.extend_from_slice(&tag[..std::cmp::min(name_end + 1, tag.len())]);
+        } else {
+            result.extend_from_slice(tag);
+        }
+    }
+    result
+}
+
+fn strip_whitespace(value: &str) -> String {
+    value.chars().filter(|ch| !ch.is_whitespace()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use jmap::base64;
+    use ring::signature::{Ed25519KeyPair, KeyPair};
+    use store::sha2::{Digest, Sha256};
+
+    use crate::lmtp::mail_auth::{split_headers, tests::StubResolver};
+
+    use super::{verify, Canonicalization, DkimResult};
+
+    #[test]
+    fn dkim_canonicalization() {
+        let mut relaxed = Vec::new();
+        let mut simple = Vec::new();
+        let (raw_headers, _) = split_headers(b"A: X\r\nB : Y\t\r\n\tZ  \r\n\r\n");
+        for header in &raw_headers {
+            Canonicalization::Relaxed.canonicalize_header(
+                header.raw_name,
+                header.value,
+                &mut relaxed,
+            );
+            Canonicalization::Simple.canonicalize_header(
+                header.raw_name,
+                header.value,
+                &mut simple,
+            );
+        }
+        assert_eq!(relaxed, b"a:X\r\nb:Y Z\r\n");
+        assert_eq!(simple, b"A: X\r\nB : Y\t\r\n\tZ  \r\n");
+
+        let body = b" C \r\nD \t E\r\n\r\n\r\n";
+        assert_eq!(
+            Canonicalization::Relaxed.canonicalize_body(body),
+            b" C\r\nD E\r\n"
+        );
+        assert_eq!(
+            Canonicalization::Simple.canonicalize_body(body),
+            b" C \r\nD \t E\r\n"
+        );
+        assert_eq!(Canonicalization::Simple.canonicalize_body(b""), b"\r\n");
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_ed25519() {
+        let key = Ed25519KeyPair::from_seed_unchecked(&[7u8; 32]).unwrap();
+        let resolver = StubResolver::default().txt(
+            "brisbane._domainkey.football.example.com",
+            &format!(
+                "v=DKIM1; k=ed25519; p={}",
+                base64::encode(key.public_key().as_ref())
+            ),
+        );
+
+        // Sign message
+        let body = b"Hi.\r\n\r\nWe lost the game.  Are you hungry yet?\r\n\r\nJoe.\r\n";
+        let headers = concat!(
+            "From: Joe SixPack <joe@football.example.com>\r\n",
+            "To: Suzie Q <suzie@shopping.example.net>\r\n",
+            "Subject: Is dinner ready?\r\n",
+        );
+        let signature = format!(
+            concat!(
+                "v=1; a=ed25519-sha256; c=relaxed/relaxed;\r\n",
+                "\td=football.example.com; s=brisbane; h=from:to:subject;\r\n",
+                "\tbh={}; b="
+            ),
+            base64::encode(Sha256::digest(
+                Canonicalization::Relaxed.canonicalize_body(body)
+            ))
+        );
+        let mut data = Vec::new();
+        let (raw_headers, _) = split_headers(headers.as_bytes());
+        for header in &raw_headers {
+            Canonicalization::Relaxed.canonicalize_header(header.name, header.value, &mut data);
+        }
+        Canonicalization::Relaxed.canonicalize_header(
+            b"DKIM-Signature",
+            signature.as_bytes(),
+            &mut data,
+        );
+        data.truncate(data.len() - 2);
+        let signature = format!(
+            "DKIM-Signature: {}{}\r\n",
+            signature,
+            base64::encode(key.sign(Sha256::digest(&data).as_slice()))
+        );
+
+        for (message, expected) in [
+            (
+                format!(
+                    "{}{}\r\n{}",
+                    signature,
+                    headers,
+                    String::from_utf8_lossy(body)
+                ),
+                DkimResult::Pass,
+            ),
+            (
+                format!(
+                    "{}{}\r\n{}\r\n\r\n",
+                    signature,
+                    headers.replace("To: Suzie", "To:   Suzie"),
+                    String::from_utf8_lossy(body)
+                ),
+                DkimResult::Pass,
+            ),
+            (
+                format!(
+                    "{}{}\r\n{}",
+                    signature,
+                    headers.replace("dinner", "lunch"),
+                    String::from_utf8_lossy(body)
+                ),
+                DkimResult::Fail("signature did not verify".to_string()),
+            ),
+            (
+                format!("{}{}\r\nWe won.\r\n", signature, headers),
+                DkimResult::Fail("body hash mismatch".to_string()),
+            ),
+            (
+                format!(
+                    "{}{}\r\n{}",
+                    signature.replace("s=brisbane", "s=sydney"),
+                    headers,
+                    String::from_utf8_lossy(body)
+                ),
+                DkimResult::PermError("no key for signature".to_string()),
+            ),
+        ] {
+            let (headers, body_offset) = split_headers(message.as_bytes());
+            let result = verify(&resolver, &headers, &message.as_bytes()[body_offset..]).await;
+            assert_eq!(result.len(), 1);
+            assert_eq!(result[0].result, expected, "{}", message);
+        }
+    }
+}
@@ -0,0 +1,249 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap_mail::mail::schema::AuthResult;
+
+use super::{
+    dkim::{DkimOutput, DkimResult},
+    dns::{DnsError, Resolver},
+    parse_tags,
+    spf::{SpfOutput, SpfResult},
+};
+
+// Second-level labels commonly used under country-code TLDs (co.uk, com.au, ...)
+static CC_SECOND_LEVEL: &[&str] = &[
+    "ac", "co", "com", "edu", "gov", "net", "org", "ne", "or", "gob", "mil",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    None,
+    Quarantine,
+    Reject,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DmarcOutput {
+    pub result: AuthResult,
+    pub domain: String,
+    pub policy: Option<Policy>,
+    // Whether a passing SPF or DKIM result is aligned with the author domain,
+    // evaluated with relaxed alignment when the domain has no DMARC record.
+    pub aligned: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Alignment {
+    Relaxed,
+    Strict,
+}
+
+struct Record {
+    policy: Policy,
+    subdomain_policy: Option<Policy>,
+    dkim_alignment: Alignment,
+    spf_alignment: Alignment,
+}
+
+pub async fn verify(
+    resolver: &dyn Resolver,
+    from_domains: &[String],
+    dkim: &[DkimOutput],
+    spf: &SpfOutput,
+) -> DmarcOutput {
+    // Messages with zero or multiple author domains cannot be evaluated
+    let from_domain = match from_domains {
+        [domain] if !domain.is_empty() => domain.to_ascii_lowercase(),
+        _ => {
+            return DmarcOutput {
+                result: AuthResult::PermError,
+                domain: from_domains.first().cloned().unwrap_or_default(),
+                policy: None,
+                aligned: false,
+            }
+        }
+    };
+    let org_domain = organizational_domain(&from_domain);
+
+    // Without a usable record, the alignment is still evaluated in relaxed mode
+    let record = match fetch_record(resolver, &from_domain).await {
+        Ok(Some(record)) => Ok((record, false)),
+        Ok(None) if org_domain != from_domain => match fetch_record(resolver, org_domain).await {
+            Ok(Some(record)) => Ok((record, true)),
+            Ok(None) => Err(AuthResult::None),
+            Err(result) => Err(result),
+        },
+        Ok(None) => Err(AuthResult::None),
+        Err(result) => Err(result),
+    };
+    let (record, is_subdomain) = match record {
+        Ok(record) => record,
+        Err(result) => {
+            let aligned = is_authenticated(
+                dkim,
+                spf,
+                &from_domain,
+                Alignment::Relaxed,
+                Alignment::Relaxed,
+            );
+            return DmarcOutput::new(result, from_domain, None, aligned);
+        }
+    };
+    let policy = if is_subdomain {
+        record.subdomain_policy.unwrap_or(record.policy)
+    } else {
+        record.policy
+    };
+
+    let aligned = is_authenticated(
+        dkim,
+        spf,
+        &from_domain,
+        record.dkim_alignment,
+        record.spf_alignment,
+    );
+
+    DmarcOutput::new(
+        if aligned {
+            AuthResult::Pass
+        } else if dkim
+            .iter()
+            .any(|output| matches!(output.result, DkimResult::TempError(_)))
+            || spf.result == SpfResult::TempError
+        {
+            AuthResult::TempError
+        } else {
+            AuthResult::Fail
+        },
+        from_domain,
+        Some(policy),
+        aligned,
+    )
+}
+
+impl DmarcOutput {
+    fn new(result: AuthResult, domain: String, policy: Option<Policy>, aligned: bool) -> Self {
+        DmarcOutput {
+            result,
+            domain,
+            policy,
+            aligned,
+        }
+    }
+}
+
+async fn fetch_record(resolver: &dyn Resolver, domain: &str) -> Result<Option<Record>, AuthResult> {
+    let records = match resolver.txt_lookup(&format!("_dmarc.{}", domain)).await {
+        Ok(records) => records,
+        Err(DnsError::NotFound) => return Ok(None),
+        Err(DnsError::Temporary(_)) => return Err(AuthResult::TempError),
+    };
+    let mut records = records
+        .iter()
+        .filter(|record| {
+            record
+                .get(..8)
+                .map_or(false, |v| v.eq_ignore_ascii_case("v=DMARC1"))
+        })
+        .map(|record| parse_tags(record));
+    let tags = match (records.next(), records.next()) {
+        (Some(tags), None) => tags,
+        (Some(_), Some(_)) => return Err(AuthResult::PermError),
+        (None, _) => return Ok(None),
+    };
+    let tag = |name: &str| {
+        tags.iter()
+            .find_map(|(k, v)| if *k == name { Some(*v) } else { None })
+    };
+
+    Ok(Some(Record {
+        policy: tag("p")
+            .and_then(Policy::parse)
+            .ok_or(AuthResult::PermError)?,
+        subdomain_policy: tag("sp").and_then(Policy::parse),
+        dkim_alignment: Alignment::parse(tag("adkim")),
+        spf_alignment: Alignment::parse(tag("aspf")),
+    }))
+}
+
+impl Policy {
+    fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Some(Policy::None),
+            "quarantine" => Some(Policy::Quarantine),
+            "reject" => Some(Policy::Reject),
+            _ => None,
+        }
+    }
+}
+
+impl Alignment {
+    fn parse(value: Option<&str>) -> Self {
+        match value {
+            Some(value) if value.eq_ignore_ascii_case("s") => Alignment::Strict,
+            _ => Alignment::Relaxed,
+        }
+    }
+}
+
+fn is_authenticated(
+    dkim: &[DkimOutput],
+    spf: &SpfOutput,
+    from_domain: &str,
+    dkim_alignment: Alignment,
+    spf_alignment: Alignment,
+) -> bool {
+    dkim.iter().any(|output| {
+        output.result == DkimResult::Pass && is_aligned(&output.domain, from_domain, dkim_alignment)
+    }) || (spf.result == SpfResult::Pass && is_aligned(&spf.domain, from_domain, spf_alignment))
+}
+
+fn is_aligned(domain: &str, from_domain: &str, alignment: Alignment) -> bool {
+    let domain = domain.to_ascii_lowercase();
+    match alignment {
+        Alignment::Strict => domain == from_domain,
+        Alignment::Relaxed => organizational_domain(&domain) == organizational_domain(from_domain),
+    }
+}
+
+// Approximates the organizational domain without the Public Suffix List:
+// the last two labels, or three when the second-level label is one of the
+// generic registries used under country-code TLDs.
+pub fn organizational_domain(domain: &str) -> &str {
+    let labels = domain.rsplitn(4, '.').collect::<Vec<_>>();
+    let keep = if labels.len() >= 3 && labels[0].len() == 2 && CC_SECOND_LEVEL.contains(&labels[1])
+    {
+        3
+    } else {
+        2
+    };
+    if labels.len() <= keep {
+        domain
+    } else {
+        let prefix_len = labels[keep..]
+            .iter()
+            .map(|label| label.len() + 1)
+            .sum::<usize>();
+        &domain[prefix_len..]
+    }
+}
@@ -0,0 +1,132 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{future::Future, net::IpAddr, pin::Pin};
+
+use trust_dns_resolver::{error::ResolveErrorKind, TokioAsyncResolver};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DnsError {
+    NotFound,
+    Temporary(String),
+}
+
+pub type Result<T> = std::result::Result<T, DnsError>;
+pub type LookupFuture<'x, T> = Pin<Box<dyn Future<Output = Result<T>> + Send + 'x>>;
+
+// DNS lookups used by the message authentication checks. Implemented by
+// the system resolver and by the stubs used in tests.
+pub trait Resolver: Send + Sync {
+    fn txt_lookup<'x>(&'x self, name: &'x str) -> LookupFuture<'x, Vec<String>>;
+    fn mx_lookup<'x>(&'x self, name: &'x str) -> LookupFuture<'x, Vec<String>>;
+    fn ip_lookup<'x>(&'x self, name: &'x str) -> LookupFuture<'x, Vec<IpAddr>>;
+    fn ptr_lookup(&self, addr: IpAddr) -> LookupFuture<'_, Vec<String>>;
+}
+
+pub struct SystemResolver {
+    resolver: TokioAsyncResolver,
+}
+
+impl SystemResolver {
+    pub fn new() -> std::result::Result<Self, String> {
+        Ok(SystemResolver {
+            resolver: TokioAsyncResolver::tokio_from_system_conf()
+                .map_err(|err| format!("Invalid system DNS configuration: {}", err))?,
+        })
+    }
+}
+
+impl Resolver for SystemResolver {
+    fn txt_lookup<'x>(&'x self, name: &'x str) -> LookupFuture<'x, Vec<String>> {
+        Box::pin(async move {
+            Ok(self
+                .resolver
+                .txt_lookup(name)
+                .await
+                .map_err(map_error)?
+                .iter()
+                .map(|txt| {
+                    txt.txt_data()
+                        .iter()
+                        .map(|chunk| String::from_utf8_lossy(chunk))
+                        .collect::<String>()
+                })
+                .collect())
+        })
+    }
+
+    fn mx_lookup<'x>(&'x self, name: &'x str) -> LookupFuture<'x, Vec<String>> {
+        Box::pin(async move {
+            let mut mx = self
+                .resolver
+                .mx_lookup(name)
+                .await
+                .map_err(map_error)?
+                .iter()
+                .map(|mx| (mx.preference(), to_hostname(mx.exchange().to_utf8())))
+                .collect::<Vec<_>>();
+            mx.sort_unstable_by_key(|(preference, _)| *preference);
+            Ok(mx.into_iter().map(|(_, exchange)| exchange).collect())
+        })
+    }
+
+    fn ip_lookup<'x>(&'x self, name: &'x str) -> LookupFuture<'x, Vec<IpAddr>> {
+        Box::pin(async move {
+            Ok(self
+                .resolver
+                .lookup_ip(name)
+                .await
+                .map_err(map_error)?
+                .iter()
+                .collect())
+        })
+    }
+
+    fn ptr_lookup(&self, addr: IpAddr) -> LookupFuture<'_, Vec<String>> {
+        Box::pin(async move {
+            Ok(self
+                .resolver
+                .reverse_lookup(addr)
+                .await
+                .map_err(map_error)?
+                .iter()
+                .map(|name| to_hostname(name.to_utf8()))
+                .collect())
+        })
+    }
+}
+
+fn map_error(err: trust_dns_resolver::error::ResolveError) -> DnsError {
+    match err.kind() {
+        ResolveErrorKind::NoRecordsFound { .. } => DnsError::NotFound,
+        _ => DnsError::Temporary(err.to_string()),
+    }
+}
+
+fn to_hostname(mut name: String) -> String {
+    if name.ends_with('.') {
+        name.pop();
+    }
+    name.make_ascii_lowercase();
+    name
+}
@@ -0,0 +1,624 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod dkim;
+pub mod dmarc;
+pub mod dns;
+pub mod spf;
+
+use std::{fmt::Write, net::IpAddr, sync::Arc};
+
+use jmap_mail::mail::schema::AuthResult;
+use serde::{Deserialize, Serialize};
+use store::{config::env_settings::EnvSettings, tracing::debug};
+
+use crate::server::failed_to;
+
+use self::{
+    dkim::{DkimOutput, DkimResult},
+    dmarc::DmarcOutput,
+    dns::{Resolver, SystemResolver},
+    spf::{SpfOutput, SpfResult},
+};
+
+pub struct MailAuthenticator {
+    resolver: Arc<dyn Resolver>,
+    authserv_id: String,
+}
+
+// Outcome of authenticating a message. The DMARC result is stored with the
+// message, the alignment decides whether its From address can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthOutput {
+    pub result: AuthResult,
+    pub from_aligned: bool,
+}
+
+pub struct RawHeader<'x> {
+    // Header name with trailing whitespace removed, used for lookups
+    pub name: &'x [u8],
+    // Header name as it appears in the message, used by "simple" canonicalization
+    pub raw_name: &'x [u8],
+    pub value: &'x [u8],
+    pub raw: &'x [u8],
+}
+
+impl AuthOutput {
+    // The author domain is authenticated by a DMARC pass or, for domains
+    // without a DMARC record, by an aligned SPF or DKIM pass.
+    pub fn is_from_authenticated(&self) -> bool {
+        self.result == AuthResult::Pass || self.from_aligned
+    }
+}
+
+impl MailAuthenticator {
+    pub fn new(settings: &EnvSettings) -> Option<Self> {
+        if !settings.parse("lmtp-auth-verify").unwrap_or(false) {
+            return None;
+        }
+
+        Some(MailAuthenticator {
+            resolver: Arc::new(
+                SystemResolver::new()
+                    .unwrap_or_else(|err| failed_to(&format!("create DNS resolver: {}", err))),
+            ),
+            authserv_id: settings.get("lmtp-auth-server-id").unwrap_or_else(|| {
+                gethostname::gethostname()
+                    .to_str()
+                    .unwrap_or("localhost")
+                    .to_string()
+            }),
+        })
+    }
+
+    pub fn with_resolver(resolver: Arc<dyn Resolver>, authserv_id: impl Into<String>) -> Self {
+        MailAuthenticator {
+            resolver,
+            authserv_id: authserv_id.into(),
+        }
+    }
+
+    // Verifies the DKIM signatures, SPF policy and DMARC alignment of a message.
+    // Returns the message with an Authentication-Results header prepended and
+    // any headers claiming to come from this server removed.
+    pub async fn verify(
+        &self,
+        message: Vec<u8>,
+        mail_from: &str,
+        client: Option<(IpAddr, &str)>,
+    ) -> (Vec<u8>, AuthOutput) {
+        let (headers, body_offset) = split_headers(&message);
+        let body = &message[body_offset..];
+
+        // DKIM
+        let dkim = dkim::verify(self.resolver.as_ref(), &headers, body).await;
+
+        // SPF, using the client address reported by the MTA. When not
+        // available, it is obtained from the topmost Received header
+        // added before ours.
+        let received = headers
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case(b"Received"))
+            .skip(1)
+            .find_map(|h| parse_received(h.value));
+        let spf = match client.map(|(ip, helo)| (ip, helo.to_string())).or(received) {
+            Some((ip, helo)) => spf::verify(self.resolver.as_ref(), ip, &helo, mail_from).await,
+            None => SpfOutput {
+                result: SpfResult::None,
+                domain: mail_from
+                    .rsplit_once('@')
+                    .map(|(_, domain)| domain.to_ascii_lowercase())
+                    .unwrap_or_default(),
+            },
+        };
+
+        // DMARC
+        let from_domains = headers
+            .iter()
+            .filter(|h| h.name.eq_ignore_ascii_case(b"From"))
+            .map(|h| from_domain(h.value))
+            .collect::<Vec<_>>();
+        let dmarc = dmarc::verify(self.resolver.as_ref(), &from_domains, &dkim, &spf).await;
+
+        debug!(
+            "Message authentication: dkim={:?}, spf={:?}, dmarc={:?}",
+            dkim, spf, dmarc
+        );
+
+        // Build message
+        let header = self.build_header(&dkim, &spf, &dmarc);
+        let mut result = Vec::with_capacity(message.len() + header.len());
+        result.extend_from_slice(header.as_bytes());
+        let mut pos = 0;
+        for h in &headers {
+            if h.name.eq_ignore_ascii_case(b"Authentication-Results")
+                && authserv_id(h.value).eq_ignore_ascii_case(&self.authserv_id)
+            {
+                let start = h.raw.as_ptr() as usize - message.as_ptr() as usize;
+                result.extend_from_slice(&message[pos..start]);
+                pos = start + h.raw.len();
+            }
+        }
+        result.extend_from_slice(&message[pos..]);
+
+        (
+            result,
+            AuthOutput {
+                result: dmarc.result,
+                from_aligned: dmarc.aligned,
+            },
+        )
+    }
+
+    fn build_header(&self, dkim: &[DkimOutput], spf: &SpfOutput, dmarc: &DmarcOutput) -> String {
+        let mut header = format!("Authentication-Results: {};", self.authserv_id);
+
+        if dkim.is_empty() {
+            header.push_str("\r\n\tdkim=none;");
+        }
+        for output in dkim {
+            let (result, reason) = match &output.result {
+                DkimResult::Pass => ("pass", None),
+                DkimResult::Fail(reason) => ("fail", Some(reason)),
+                DkimResult::Neutral(reason) => ("neutral", Some(reason)),
+                DkimResult::TempError(reason) => ("temperror", Some(reason)),
+                DkimResult::PermError(reason) => ("permerror", Some(reason)),
+            };
+            let _ = write!(header, "\r\n\tdkim={}", result);
+            if let Some(reason) = reason {
+                let _ = write!(header, " reason=\"{}\"", reason);
+            }
+            let _ = write!(header, " header.d={}", output.domain);
+            if !output.selector.is_empty() {
+                let _ = write!(header, " header.s={}", output.selector);
+            }
+            header.push(';');
+        }
+
+        let _ = write!(
+            header,
+            "\r\n\tspf={} smtp.mailfrom={};",
+            match spf.result {
+                SpfResult::Pass => "pass",
+                SpfResult::Fail => "fail",
+                SpfResult::SoftFail => "softfail",
+                SpfResult::Neutral => "neutral",
+                SpfResult::None => "none",
+                SpfResult::TempError => "temperror",
+                SpfResult::PermError => "permerror",
+            },
+            spf.domain
+        );
+        let _ = write!(
+            header,
+            "\r\n\tdmarc={} header.from={}\r\n",
+            dmarc.result, dmarc.domain
+        );
+
+        header
+    }
+}
+
+// Splits the header section of a message, returning the headers and the
+// offset where the body starts.
+pub fn split_headers(message: &[u8]) -> (Vec<RawHeader<'_>>, usize) {
+    let mut headers = Vec::new();
+    let mut pos = 0;
+
+    while pos < message.len() {
+        // Find end of header, including folded lines
+        let mut end = pos;
+        loop {
+            match message[end..].iter().position(|&ch| ch == b'\n') {
+                Some(lf) => {
+                    end += lf + 1;
+                    if !matches!(message.get(end), Some(b' ' | b'\t')) {
+                        break;
+                    }
+                }
+                None => {
+                    end = message.len();
+                    break;
+                }
+            }
+        }
+
+        let raw = &message[pos..end];
+        if raw == b"\r\n" || raw == b"\n" {
+            return (headers, end);
+        }
+        if let Some(colon) = raw.iter().position(|&ch| ch == b':') {
+            let value = &raw[colon + 1..];
+            let value = value
+                .strip_suffix(b"\r\n")
+                .or_else(|| value.strip_suffix(b"\n"))
+                .unwrap_or(value);
+            headers.push(RawHeader {
+                name: trim_end(&raw[..colon]),
+                raw_name: &raw[..colon],
+                value,
+                raw,
+            });
+        }
+        pos = end;
+    }
+
+    (headers, message.len())
+}
+
+// Parses a tag-value list as used by DKIM and DMARC records
+pub fn parse_tags(value: &str) -> Vec<(&str, &str)> {
+    value
+        .split(';')
+        .filter_map(|tag| {
+            let (name, value) = tag.split_once('=')?;
+            Some((name.trim(), value.trim()))
+        })
+        .collect()
+}
+
+// Obtains the client IP and HELO name from a Received header such as
+// "from helo.example.org (host.example.org [192.0.2.1]) by ..."
+fn parse_received(value: &[u8]) -> Option<(IpAddr, String)> {
+    let value = String::from_utf8_lossy(value);
+    let mut words = value.split_ascii_whitespace();
+    if !words.next()?.eq_ignore_ascii_case("from") {
+        return None;
+    }
+    let helo = words.next()?.to_ascii_lowercase();
+
+    let mut remaining = value.as_ref();
+    while let Some(start) = remaining.find('[') {
+        let end = remaining[start..].find(']')? + start;
+        let addr = &remaining[start + 1..end];
+        if let Ok(ip) = addr
+            .strip_prefix("IPv6:")
+            .or_else(|| addr.strip_prefix("ipv6:"))
+            .unwrap_or(addr)
+            .parse()
+        {
+            return Some((ip, helo));
+        }
+        remaining = &remaining[end + 1..];
+    }
+    None
+}
+
+// Obtains the domain of the first address in a From header
+fn from_domain(value: &[u8]) -> String {
+    let value = String::from_utf8_lossy(value);
+    let addr = match (value.rfind('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => &value[start + 1..end],
+        _ => value.split(',').next().unwrap_or_default(),
+    };
+    addr.trim()
+        .rsplit_once('@')
+        .map(|(_, domain)| domain.trim().to_ascii_lowercase())
+        .unwrap_or_default()
+}
+
+fn authserv_id(value: &[u8]) -> String {
+    let value = String::from_utf8_lossy(value);
+    value
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .split_ascii_whitespace()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn trim_end(value: &[u8]) -> &[u8] {
+    let end = value
+        .iter()
+        .rposition(|ch| !ch.is_ascii_whitespace())
+        .map_or(0, |pos| pos + 1);
+    &value[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::IpAddr, sync::Arc};
+
+    use jmap_mail::mail::schema::AuthResult;
+    use store::ahash::AHashMap;
+
+    use super::{
+        dmarc::organizational_domain,
+        dns::{DnsError, LookupFuture, Resolver},
+        spf::{self, SpfResult},
+        split_headers, MailAuthenticator,
+    };
+
+    #[derive(Default)]
+    pub struct StubResolver {
+        pub txt: AHashMap<String, Vec<String>>,
+        pub mx: AHashMap<String, Vec<String>>,
+        pub ip: AHashMap<String, Vec<IpAddr>>,
+    }
+
+    impl StubResolver {
+        pub fn txt(mut self, name: &str, value: &str) -> Self {
+            self.txt
+                .entry(name.to_string())
+                .or_insert_with(Vec::new)
+                .push(value.to_string());
+            self
+        }
+
+        pub fn mx(mut self, name: &str, value: &str) -> Self {
+            self.mx
+                .entry(name.to_string())
+                .or_insert_with(Vec::new)
+                .push(value.to_string());
+            self
+        }
+
+        pub fn ip(mut self, name: &str, value: &str) -> Self {
+            self.ip
+                .entry(name.to_string())
+                .or_insert_with(Vec::new)
+                .push(value.parse().unwrap());
+            self
+        }
+    }
+
+    fn lookup<T: Clone>(map: &AHashMap<String, Vec<T>>, name: &str) -> super::dns::Result<Vec<T>> {
+        map.get(name).cloned().ok_or(DnsError::NotFound)
+    }
+
+    impl Resolver for StubResolver {
+        fn txt_lookup<'x>(&'x self, name: &'x str) -> LookupFuture<'x, Vec<String>> {
+            Box::pin(async move { lookup(&self.txt, name) })
+        }
+
+        fn mx_lookup<'x>(&'x self, name: &'x str) -> LookupFuture<'x, Vec<String>> {
+            Box::pin(async move { lookup(&self.mx, name) })
+        }
+
+        fn ip_lookup<'x>(&'x self, name: &'x str) -> LookupFuture<'x, Vec<IpAddr>> {
+            Box::pin(async move { lookup(&self.ip, name) })
+        }
+
+        fn ptr_lookup(&self, _addr: IpAddr) -> LookupFuture<'_, Vec<String>> {
+            Box::pin(async move { Err(DnsError::NotFound) })
+        }
+    }
+
+    #[tokio::test]
+    async fn spf_check_host() {
+        let resolver = StubResolver::default()
+            .txt(
+                "example.org",
+                "v=spf1 ip4:192.0.2.0/24 include:_spf.example.org mx -all",
+            )
+            .txt("_spf.example.org", "v=spf1 ip6:2001:db8::/32 ~all")
+            .mx("example.org", "mx.example.org")
+            .ip("mx.example.org", "198.51.100.7")
+            .txt("softfail.org", "v=spf1 a:%{d1r}.example.org ~all")
+            .txt("loop.org", "v=spf1 include:loop.org -all")
+            .txt("redirect.org", "v=spf1 redirect=example.org")
+            .txt("macro.org", "v=spf1 exists:%{ir}.%{l1r+-}._spf.%{d} -all")
+            .ip("1.2.0.192.strong._spf.macro.org", "127.0.0.2");
+
+        for (ip, mail_from, expected) in [
+            ("192.0.2.10", "jdoe@example.org", SpfResult::Pass),
+            ("2001:db8::1", "jdoe@example.org", SpfResult::Pass),
+            ("198.51.100.7", "jdoe@example.org", SpfResult::Pass),
+            ("203.0.113.1", "jdoe@example.org", SpfResult::Fail),
+            ("203.0.113.1", "jdoe@softfail.org", SpfResult::SoftFail),
+            ("203.0.113.1", "jdoe@loop.org", SpfResult::PermError),
+            ("192.0.2.10", "jdoe@redirect.org", SpfResult::Pass),
+            ("203.0.113.1", "jdoe@redirect.org", SpfResult::Fail),
+            ("192.0.2.1", "strong-bad@macro.org", SpfResult::Pass),
+            ("192.0.2.2", "strong-bad@macro.org", SpfResult::Fail),
+            ("192.0.2.10", "jdoe@unknown.org", SpfResult::None),
+            ("192.0.2.10", "", SpfResult::Pass),
+        ] {
+            assert_eq!(
+                spf::verify(&resolver, ip.parse().unwrap(), "example.org", mail_from)
+                    .await
+                    .result,
+                expected,
+                "{} {}",
+                ip,
+                mail_from
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn authentication_results() {
+        let authenticator = MailAuthenticator::with_resolver(
+            Arc::new(
+                StubResolver::default()
+                    .txt("example.org", "v=spf1 ip4:192.0.2.0/24 -all")
+                    .txt("_dmarc.example.org", "v=DMARC1; p=reject; aspf=r")
+                    .txt("_dmarc.strict.org", "v=DMARC1; p=quarantine; aspf=s")
+                    .txt("nodmarc.org", "v=spf1 ip4:198.51.100.0/24 -all"),
+            ),
+            "mx.local",
+        );
+
+        for (message, mail_from, expected_result, expected_header) in [
+            (
+                concat!(
+                    "Received: from lmtp.local ([mx.local]) by 127.0.0.1\r\n",
+                    "Received: from mail.example.org (mail.example.org [192.0.2.5])\r\n",
+                    "\tby mta.local with ESMTP\r\n",
+                    "Authentication-Results: mx.local; dmarc=pass\r\n",
+                    "Authentication-Results: other.host; dmarc=pass\r\n",
+                    "From: John Doe <jdoe@mail.example.org>\r\n",
+                    "Subject: test\r\n\r\n",
+                    "body\r\n"
+                ),
+                "jdoe@example.org",
+                AuthResult::Pass,
+                concat!(
+                    "Authentication-Results: mx.local;\r\n",
+                    "\tdkim=none;\r\n",
+                    "\tspf=pass smtp.mailfrom=example.org;\r\n",
+                    "\tdmarc=pass header.from=mail.example.org\r\n",
+                ),
+            ),
+            (
+                concat!(
+                    "Received: from lmtp.local ([mx.local]) by 127.0.0.1\r\n",
+                    "Received: from mail.example.org ([IPv6:2001:db8::1])\r\n",
+                    "From: jdoe@example.org\r\n\r\n",
+                    "body\r\n"
+                ),
+                "jdoe@example.org",
+                AuthResult::Fail,
+                concat!(
+                    "Authentication-Results: mx.local;\r\n",
+                    "\tdkim=none;\r\n",
+                    "\tspf=fail smtp.mailfrom=example.org;\r\n",
+                    "\tdmarc=fail header.from=example.org\r\n",
+                ),
+            ),
+            (
+                concat!(
+                    "From: jdoe@example.org\r\n",
+                    "From: jane@example.org\r\n\r\n",
+                    "body\r\n"
+                ),
+                "",
+                AuthResult::PermError,
+                concat!(
+                    "Authentication-Results: mx.local;\r\n",
+                    "\tdkim=none;\r\n",
+                    "\tspf=none smtp.mailfrom=;\r\n",
+                    "\tdmarc=permerror header.from=example.org\r\n",
+                ),
+            ),
+            (
+                concat!("From: jdoe@nodmarc.org\r\n\r\n", "body\r\n"),
+                "jdoe@nodmarc.org",
+                AuthResult::None,
+                concat!(
+                    "Authentication-Results: mx.local;\r\n",
+                    "\tdkim=none;\r\n",
+                    "\tspf=none smtp.mailfrom=nodmarc.org;\r\n",
+                    "\tdmarc=none header.from=nodmarc.org\r\n",
+                ),
+            ),
+        ] {
+            let (result, auth_result) = authenticator
+                .verify(message.as_bytes().to_vec(), mail_from, None)
+                .await;
+            let result = String::from_utf8(result).unwrap();
+            assert_eq!(auth_result.result, expected_result, "{}", message);
+            assert_eq!(
+                auth_result.is_from_authenticated(),
+                expected_result == AuthResult::Pass,
+                "{}",
+                message
+            );
+            assert!(result.starts_with(expected_header), "{}", result);
+            assert!(!result[expected_header.len()..].contains("mx.local; dmarc"));
+            assert_eq!(
+                result.contains("other.host"),
+                message.contains("other.host")
+            );
+        }
+
+        // Client address reported by the MTA takes precedence
+        let (_, auth_result) = authenticator
+            .verify(
+                b"From: jdoe@example.org\r\n\r\nbody\r\n".to_vec(),
+                "jdoe@example.org",
+                Some(("192.0.2.1".parse().unwrap(), "mail.example.org")),
+            )
+            .await;
+        assert_eq!(auth_result.result, AuthResult::Pass);
+
+        // Domains without a DMARC record are authenticated by an aligned SPF pass,
+        // a pass for a different domain does not authenticate the author.
+        for (from, mail_from, expected_result, expected_aligned) in [
+            (
+                "jdoe@nodmarc.org",
+                "jdoe@nodmarc.org",
+                AuthResult::None,
+                true,
+            ),
+            (
+                "jdoe@mail.nodmarc.org",
+                "bounces@nodmarc.org",
+                AuthResult::None,
+                true,
+            ),
+            (
+                "ceo@example.org",
+                "jdoe@nodmarc.org",
+                AuthResult::Fail,
+                false,
+            ),
+            ("ceo@other.org", "jdoe@nodmarc.org", AuthResult::None, false),
+        ] {
+            let (_, auth_result) = authenticator
+                .verify(
+                    format!("From: {}\r\n\r\nbody\r\n", from).into_bytes(),
+                    mail_from,
+                    Some(("198.51.100.1".parse().unwrap(), "mail.nodmarc.org")),
+                )
+                .await;
+            assert_eq!(auth_result.result, expected_result, "{}", from);
+            assert_eq!(auth_result.from_aligned, expected_aligned, "{}", from);
+            assert_eq!(
+                auth_result.is_from_authenticated(),
+                expected_aligned,
+                "{}",
+                from
+            );
+        }
+    }
+
+    #[test]
+    fn split_message_headers() {
+        let message = b"Subject: hello\r\n  world\r\nFrom : a@b.org\r\n\r\nbody";
+        let (headers, body_offset) = split_headers(message);
+        assert_eq!(headers.len(), 2);
+        assert_eq!(headers[0].name, b"Subject");
+        assert_eq!(headers[0].value, b" hello\r\n  world");
+        assert_eq!(headers[1].name, b"From");
+        assert_eq!(headers[1].raw_name, b"From ");
+        assert_eq!(headers[1].value, b" a@b.org");
+        assert_eq!(&message[body_offset..], b"body");
+    }
+
+    #[test]
+    fn org_domain() {
+        for (domain, expected) in [
+            ("example.org", "example.org"),
+            ("mail.example.org", "example.org"),
+            ("a.b.mail.example.org", "example.org"),
+            ("mail.example.co.uk", "example.co.uk"),
+            ("example.co.uk", "example.co.uk"),
+            ("localhost", "localhost"),
+        ] {
+            assert_eq!(organizational_domain(domain), expected);
+        }
+    }
+}
@@ -0,0 +1,494 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{future::Future, net::IpAddr, pin::Pin};
+
+use super::dns::{DnsError, Resolver};
+
+// RFC 7208 processing limits
+const MAX_DNS_LOOKUPS: usize = 10;
+const MAX_VOID_LOOKUPS: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpfResult {
+    Pass,
+    Fail,
+    SoftFail,
+    Neutral,
+    None,
+    TempError,
+    PermError,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpfOutput {
+    pub result: SpfResult,
+    pub domain: String,
+}
+
+struct Context<'x> {
+    resolver: &'x dyn Resolver,
+    ip: IpAddr,
+    sender: String,
+    helo: &'x str,
+    lookups: usize,
+    void_lookups: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Qualifier {
+    Pass,
+    Fail,
+    SoftFail,
+    Neutral,
+}
+
+pub async fn verify(resolver: &dyn Resolver, ip: IpAddr, helo: &str, mail_from: &str) -> SpfOutput {
+    let sender = if !mail_from.is_empty() {
+        mail_from.to_string()
+    } else {
+        format!("postmaster@{}", helo)
+    };
+    let domain = sender
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .unwrap_or(helo)
+        .to_ascii_lowercase();
+
+    if domain.is_empty() || !domain.contains('.') {
+        return SpfOutput {
+            result: SpfResult::None,
+            domain,
+        };
+    }
+
+    let mut context = Context {
+        resolver,
+        ip,
+        sender,
+        helo,
+        lookups: 0,
+        void_lookups: 0,
+    };
+    SpfOutput {
+        result: check_host(&mut context, domain.clone()).await,
+        domain,
+    }
+}
+
+// Boxed so that include and redirect can recurse
+fn check_host<'x, 'y>(
+    context: &'y mut Context<'x>,
+    domain: String,
+) -> Pin<Box<dyn Future<Output = SpfResult> + Send + 'y>>
+where
+    'x: 'y,
+{
+    Box::pin(check_host_(context, domain))
+}
+
+async fn check_host_(context: &mut Context<'_>, domain: String) -> SpfResult {
+    let record = match context.resolver.txt_lookup(&domain).await {
+        Ok(records) => {
+            let mut records = records
+                .into_iter()
+                .filter(|r| r == "v=spf1" || r.starts_with("v=spf1 "));
+            match (records.next(), records.next()) {
+                (Some(record), None) => record,
+                (Some(_), Some(_)) => return SpfResult::PermError,
+                (None, _) => return SpfResult::None,
+            }
+        }
+        Err(DnsError::NotFound) => return SpfResult::None,
+        Err(DnsError::Temporary(_)) => return SpfResult::TempError,
+    };
+
+    let mut redirect = None;
+    for term in record.split_ascii_whitespace().skip(1) {
+        let lower_term = term.to_ascii_lowercase();
+        if let Some(target) = lower_term.strip_prefix("redirect=") {
+            redirect = Some(target.to_string());
+            continue;
+        } else if lower_term.starts_with("exp=") || is_unknown_modifier(&lower_term) {
+            continue;
+        }
+
+        let (qualifier, mechanism) = match term.as_bytes()[0] {
+            b'+' => (Qualifier::Pass, &term[1..]),
+            b'-' => (Qualifier::Fail, &term[1..]),
+            b'~' => (Qualifier::SoftFail, &term[1..]),
+            b'?' => (Qualifier::Neutral, &term[1..]),
+            _ => (Qualifier::Pass, term),
+        };
+        let (name, argument) = match mechanism.find(|ch| ch == ':' || ch == '/') {
+            Some(pos) => (&mechanism[..pos], &mechanism[pos..]),
+            None => (mechanism, ""),
+        };
+
+        let matched = match evaluate(context, &domain, &name.to_ascii_lowercase(), argument).await {
+            Ok(matched) => matched,
+            Err(result) => return result,
+        };
+        if matched {
+            return match qualifier {
+                Qualifier::Pass => SpfResult::Pass,
+                Qualifier::Fail => SpfResult::Fail,
+                Qualifier::SoftFail => SpfResult::SoftFail,
+                Qualifier::Neutral => SpfResult::Neutral,
+            };
+        }
+    }
+
+    if let Some(redirect) = redirect {
+        if !context.count_lookup() {
+            return SpfResult::PermError;
+        }
+        let target = match expand_macro(context, &redirect, &domain) {
+            Some(target) => target,
+            None => return SpfResult::PermError,
+        };
+        match check_host(context, target).await {
+            SpfResult::None => SpfResult::PermError,
+            result => result,
+        }
+    } else {
+        SpfResult::Neutral
+    }
+}
+
+async fn evaluate(
+    context: &mut Context<'_>,
+    domain: &str,
+    name: &str,
+    argument: &str,
+) -> Result<bool, SpfResult> {
+    let resolver = context.resolver;
+
+    // Split the optional ":domain-spec" and "/cidr" parts
+    let (target, cidr) = match argument.strip_prefix(':') {
+        Some(argument) => match argument.find('/') {
+            Some(pos) => (Some(&argument[..pos]), &argument[pos..]),
+            None => (Some(argument), ""),
+        },
+        None => (None, argument),
+    };
+    let target = match target {
+        Some(target) if name != "ip4" && name != "ip6" => {
+            expand_macro(context, target, domain).ok_or(SpfResult::PermError)?
+        }
+        _ => domain.to_string(),
+    };
+
+    match name {
+        "all" => Ok(true),
+        "include" => {
+            if argument.is_empty() {
+                return Err(SpfResult::PermError);
+            }
+            context.lookup()?;
+            match check_host(context, target).await {
+                SpfResult::Pass => Ok(true),
+                SpfResult::Fail | SpfResult::SoftFail | SpfResult::Neutral => Ok(false),
+                SpfResult::TempError => Err(SpfResult::TempError),
+                SpfResult::PermError | SpfResult::None => Err(SpfResult::PermError),
+            }
+        }
+        "a" | "mx" => {
+            let (cidr4, cidr6) = parse_dual_cidr(cidr).ok_or(SpfResult::PermError)?;
+            context.lookup()?;
+            let hosts = if name == "mx" {
+                let hosts = context.void_check(resolver.mx_lookup(&target).await)?;
+                if hosts.len() > MAX_DNS_LOOKUPS {
+                    return Err(SpfResult::PermError);
+                }
+                hosts
+            } else {
+                vec![target]
+            };
+            for host in hosts {
+                let addrs = context.void_check(resolver.ip_lookup(&host).await)?;
+                if addrs
+                    .iter()
+                    .any(|addr| ip_matches(context.ip, *addr, cidr4, cidr6))
+                {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        "ptr" => {
+            context.lookup()?;
+            let names = match resolver.ptr_lookup(context.ip).await {
+                Ok(names) => names,
+                Err(_) => return Ok(false),
+            };
+            for host in names.iter().take(MAX_DNS_LOOKUPS) {
+                if (host == &target || host.ends_with(&format!(".{}", target)))
+                    && resolver
+                        .ip_lookup(host)
+                        .await
+                        .map_or(false, |addrs| addrs.contains(&context.ip))
+                {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        "ip4" | "ip6" => {
+            let argument = argument.strip_prefix(':').ok_or(SpfResult::PermError)?;
+            let (addr, prefix) = match argument.split_once('/') {
+                Some((addr, prefix)) => (addr, Some(prefix)),
+                None => (argument, None),
+            };
+            let addr = addr.parse::<IpAddr>().map_err(|_| SpfResult::PermError)?;
+            let (max_prefix, valid) = match addr {
+                IpAddr::V4(_) => (32, name == "ip4"),
+                IpAddr::V6(_) => (128, name == "ip6"),
+            };
+            let prefix = match prefix {
+                Some(prefix) => prefix
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|prefix| *prefix <= max_prefix)
+                    .ok_or(SpfResult::PermError)?,
+                None => max_prefix,
+            };
+            if !valid {
+                return Err(SpfResult::PermError);
+            }
+            Ok(cidr_matches(context.ip, addr, prefix))
+        }
+        "exists" => {
+            if argument.is_empty() {
+                return Err(SpfResult::PermError);
+            }
+            context.lookup()?;
+            Ok(!context
+                .void_check(resolver.ip_lookup(&target).await)?
+                .is_empty())
+        }
+        _ => Err(SpfResult::PermError),
+    }
+}
+
+impl Context<'_> {
+    fn count_lookup(&mut self) -> bool {
+        self.lookups += 1;
+        self.lookups <= MAX_DNS_LOOKUPS
+    }
+
+    fn lookup(&mut self) -> Result<(), SpfResult> {
+        if self.count_lookup() {
+            Ok(())
+        } else {
+            Err(SpfResult::PermError)
+        }
+    }
+
+    fn void_check<T>(&mut self, result: super::dns::Result<Vec<T>>) -> Result<Vec<T>, SpfResult> {
+        match result {
+            Ok(result) if !result.is_empty() => Ok(result),
+            Ok(_) | Err(DnsError::NotFound) => {
+                self.void_lookups += 1;
+                if self.void_lookups > MAX_VOID_LOOKUPS {
+                    Err(SpfResult::PermError)
+                } else {
+                    Ok(Vec::new())
+                }
+            }
+            Err(DnsError::Temporary(_)) => Err(SpfResult::TempError),
+        }
+    }
+}
+
+fn is_unknown_modifier(term: &str) -> bool {
+    term.split_once('=').map_or(false, |(name, _)| {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '-' | '_' | '.'))
+    })
+}
+
+fn parse_dual_cidr(cidr: &str) -> Option<(u8, u8)> {
+    if cidr.is_empty() {
+        return Some((32, 128));
+    }
+    let cidr = cidr.strip_prefix('/')?;
+    let (cidr4, cidr6) = match cidr.split_once("//") {
+        Some((cidr4, cidr6)) => (cidr4, Some(cidr6)),
+        None => match cidr.strip_prefix('/') {
+            Some(cidr6) => ("", Some(cidr6)),
+            None => (cidr, None),
+        },
+    };
+    let cidr4 = if !cidr4.is_empty() {
+        cidr4.parse().ok().filter(|cidr| *cidr <= 32)?
+    } else {
+        32
+    };
+    let cidr6 = match cidr6 {
+        Some(cidr6) => cidr6.parse().ok().filter(|cidr| *cidr <= 128)?,
+        None => 128,
+    };
+    Some((cidr4, cidr6))
+}
+
+fn ip_matches(ip: IpAddr, addr: IpAddr, cidr4: u8, cidr6: u8) -> bool {
+    match addr {
+        IpAddr::V4(_) => cidr_matches(ip, addr, cidr4),
+        IpAddr::V6(_) => cidr_matches(ip, addr, cidr6),
+    }
+}
+
+fn cidr_matches(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    let (ip, network, bits) = match (to_canonical(ip), network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => {
+            (u32::from(ip) as u128, u32::from(network) as u128, 32)
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) => (u128::from(ip), u128::from(network), 128),
+        _ => return false,
+    };
+    if prefix == 0 {
+        return true;
+    }
+    let mask = (u128::MAX >> (128 - bits)) & !((1u128 << (bits - prefix as u32)) - 1);
+    (ip & mask) == (network & mask)
+}
+
+fn to_canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ip) => ip
+            .to_ipv4()
+            .filter(|_| ip.segments()[..5] == [0; 5])
+            .map_or(IpAddr::V6(ip), IpAddr::V4),
+        ip => ip,
+    }
+}
+
+// Expands RFC 7208 macros, returning None on syntax errors
+fn expand_macro(context: &Context<'_>, spec: &str, domain: &str) -> Option<String> {
+    let mut result = String::with_capacity(spec.len());
+    let mut chars = spec.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch != '%' {
+            result.push(ch);
+            continue;
+        }
+        match chars.next()? {
+            '%' => result.push('%'),
+            '_' => result.push(' '),
+            '-' => result.push_str("%20"),
+            '{' => {
+                let mut expr = String::new();
+                for ch in chars.by_ref() {
+                    if ch == '}' {
+                        break;
+                    }
+                    expr.push(ch);
+                }
+                let mut expr_chars = expr.chars();
+                let letter = expr_chars.next()?.to_ascii_lowercase();
+                let rest = expr_chars.as_str();
+                let digits_end = rest
+                    .find(|ch: char| !ch.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let keep = if digits_end > 0 {
+                    Some(
+                        rest[..digits_end]
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|n| *n > 0)?,
+                    )
+                } else {
+                    None
+                };
+                let rest = &rest[digits_end..];
+                let (reverse, delimiters) = match rest.strip_prefix(|ch| ch == 'r' || ch == 'R') {
+                    Some(delimiters) => (true, delimiters),
+                    None => (false, rest),
+                };
+                if !delimiters
+                    .chars()
+                    .all(|ch| matches!(ch, '.' | '-' | '+' | ',' | '/' | '_' | '='))
+                {
+                    return None;
+                }
+
+                let value = match letter {
+                    's' => context.sender.clone(),
+                    'l' => context
+                        .sender
+                        .rsplit_once('@')
+                        .map_or("postmaster", |(local, _)| local)
+                        .to_string(),
+                    'o' => context
+                        .sender
+                        .rsplit_once('@')
+                        .map_or(domain, |(_, domain)| domain)
+                        .to_string(),
+                    'd' => domain.to_string(),
+                    'i' => match to_canonical(context.ip) {
+                        IpAddr::V4(ip) => ip.to_string(),
+                        IpAddr::V6(ip) => ip
+                            .octets()
+                            .iter()
+                            .flat_map(|byte| [byte >> 4, byte & 0x0f])
+                            .map(|nibble| format!("{:x}", nibble))
+                            .collect::<Vec<_>>()
+                            .join("."),
+                    },
+                    'p' => "unknown".to_string(),
+                    'v' => match to_canonical(context.ip) {
+                        IpAddr::V4(_) => "in-addr".to_string(),
+                        IpAddr::V6(_) => "ip6".to_string(),
+                    },
+                    'h' => context.helo.to_string(),
+                    _ => return None,
+                };
+
+                let delimiters = if delimiters.is_empty() {
+                    "."
+                } else {
+                    delimiters
+                };
+                let mut parts = value
+                    .split(|ch| delimiters.contains(ch))
+                    .collect::<Vec<_>>();
+                if reverse {
+                    parts.reverse();
+                }
+                if let Some(keep) = keep {
+                    if parts.len() > keep {
+                        parts.drain(..parts.len() - keep);
+                    }
+                }
+                result.push_str(&parts.join("."));
+            }
+            _ => return None,
+        }
+    }
+
+    Some(result)
+}
@@ -23,6 +23,8 @@
 
 pub mod ingest;
 pub mod listener;
+pub mod mail_auth;
 pub mod request;
 pub mod response;
 pub mod session;
+pub mod spam_filter;
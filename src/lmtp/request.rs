@@ -39,6 +39,19 @@ pub enum Request {
     Lhlo {
         domain: String,
     },
+    Ehlo {
+        domain: String,
+    },
+    Helo {
+        domain: String,
+    },
+    Auth {
+        mechanism: String,
+        initial_response: Option<String>,
+    },
+    SaslResponse {
+        data: String,
+    },
     Mail {
         sender: String,
         params: Vec<Param>,
@@ -67,6 +80,12 @@ pub enum Request {
     Noop,
     Quit,
     StartTls,
+    Xclient {
+        attributes: Vec<(String, String)>,
+    },
+    Xforward {
+        attributes: Vec<(String, String)>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -82,6 +101,8 @@ pub enum State {
     Request { in_addr: bool },
     Bdat { chunk_size: usize, is_last: bool },
     Data { state: StateData },
+    Auth,
+    SaslResponse,
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -205,6 +226,27 @@ impl RequestParser {
                                     },
                                 )?,
                             }),
+                            "ehlo" => Ok(Request::Ehlo {
+                                domain: tokens.next().and_then(|t| t.unwrap_text()).ok_or_else(
+                                    || {
+                                        Event::parse_error(
+                                            "EHLO requires a domain name as argument.",
+                                        )
+                                    },
+                                )?,
+                            }),
+                            "helo" => Ok(Request::Helo {
+                                domain: tokens.next().and_then(|t| t.unwrap_text()).ok_or_else(
+                                    || {
+                                        Event::parse_error(
+                                            "HELO requires a domain name as argument.",
+                                        )
+                                    },
+                                )?,
+                            }),
+                            "auth" => {
+                                Err(Event::parse_error("AUTH requires a mechanism as argument."))
+                            }
                             "mail" => {
                                 if matches!(tokens.next(), Some(Token::Text(from)) if from == "from")
                                     && matches!(tokens.next(), Some(Token::Colon))
@@ -319,6 +361,12 @@ impl RequestParser {
                             "noop" => Ok(Request::Noop),
                             "starttls" => Ok(Request::StartTls),
                             "quit" => Ok(Request::Quit),
+                            "xclient" => Ok(Request::Xclient {
+                                attributes: parse_attributes("XCLIENT", tokens.collect())?,
+                            }),
+                            "xforward" => Ok(Request::Xforward {
+                                attributes: parse_attributes("XFORWARD", tokens.collect())?,
+                            }),
                             cmd => Err(self
                                 .error_reset(format!("Unknown command '{}'.", cmd.to_uppercase()))),
                         };
@@ -333,9 +381,51 @@ impl RequestParser {
                                 .push(if in_addr { ch } else { ch.to_ascii_lowercase() });
                         } else {
                             self.push_buf()?;
+
+                            // SASL arguments are case sensitive, read them verbatim
+                            if matches!(self.tokens.as_slice(), [Token::Text(cmd)] if cmd == "auth")
+                            {
+                                self.tokens.clear();
+                                self.state = State::Auth;
+                            }
                         }
                     }
                 },
+                State::Auth | State::SaslResponse => match ch {
+                    b'\r' => (),
+                    b'\n' => {
+                        let line = String::from_utf8(std::mem::take(&mut self.buf))
+                            .map_err(|_| self.error_reset("Invalid UTF-8"))?;
+                        let is_auth = self.state == State::Auth;
+                        self.buf = Vec::with_capacity(10);
+                        self.command_size = 0;
+                        self.state = State::Start;
+
+                        return if is_auth {
+                            let mut args = line.split_ascii_whitespace();
+                            Ok(Request::Auth {
+                                mechanism: args
+                                    .next()
+                                    .ok_or_else(|| {
+                                        Event::parse_error("AUTH requires a mechanism as argument.")
+                                    })?
+                                    .to_ascii_lowercase(),
+                                initial_response: args.next().map(|arg| arg.to_string()),
+                            })
+                        } else {
+                            Ok(Request::SaslResponse {
+                                data: line.trim().to_string(),
+                            })
+                        };
+                    }
+                    _ => {
+                        self.command_size += 1;
+                        if self.command_size > self.max_command_size {
+                            return Err(self.error_reset("Request is too long."));
+                        }
+                        self.buf.push(ch);
+                    }
+                },
                 State::Data { state } => {
                     let state = match ch {
                         b'\r' => match state {
@@ -563,12 +653,52 @@ impl Event {
     }
 }
 
+// Parses XCLIENT and XFORWARD name=value attributes. Values are rebuilt from
+// their tokens as they may contain colons (i.e. IPv6 addresses).
+fn parse_attributes(command: &str, tokens: Vec<Token>) -> Result<Vec<(String, String)>, Event> {
+    let mut attributes = Vec::new();
+    let mut tokens = tokens.into_iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        let name = match (token, tokens.next()) {
+            (Token::Text(name), Some(Token::Eq)) => name,
+            _ => {
+                return Err(Event::parse_error(format!(
+                    "Invalid {} attribute.",
+                    command
+                )))
+            }
+        };
+        let mut value = String::new();
+        while let Some(token) = tokens.peek() {
+            match token {
+                Token::Colon => value.push(':'),
+                Token::Text(text) if value.is_empty() || value.ends_with(':') => {
+                    value.push_str(text)
+                }
+                _ => break,
+            }
+            tokens.next();
+        }
+        attributes.push((name, value));
+    }
+
+    if !attributes.is_empty() {
+        Ok(attributes)
+    } else {
+        Err(Event::parse_error(format!(
+            "{} requires at least one attribute.",
+            command
+        )))
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
     use crate::lmtp::request::Event;
 
-    use super::{Param, Request, RequestParser};
+    use super::{Param, Request, RequestParser, State};
 
     #[test]
     fn lmtp_parser() {
@@ -681,6 +811,63 @@ mod tests {
                     data: b"\r\na\rb\nc\r\nd\r\n.\re".to_vec(),
                 }],
             ),
+            (
+                vec![
+                    "XCLIENT ADDR=192.0.2.1 HELO=mail.example.org\r\n",
+                    "xclient addr=IPV6:2001:db8::1 name=[UNAVAILABLE]\r\n",
+                ],
+                vec![
+                    Request::Xclient {
+                        attributes: vec![
+                            ("addr".to_string(), "192.0.2.1".to_string()),
+                            ("helo".to_string(), "mail.example.org".to_string()),
+                        ],
+                    },
+                    Request::Xclient {
+                        attributes: vec![
+                            ("addr".to_string(), "ipv6:2001:db8::1".to_string()),
+                            ("name".to_string(), "[unavailable]".to_string()),
+                        ],
+                    },
+                ],
+            ),
+            (
+                vec![
+                    "XFORWARD NAME=mail.example.org ADDR=192.0.2.1 ",
+                    "HELO=[tempunavail]\r\n",
+                ],
+                vec![Request::Xforward {
+                    attributes: vec![
+                        ("name".to_string(), "mail.example.org".to_string()),
+                        ("addr".to_string(), "192.0.2.1".to_string()),
+                        ("helo".to_string(), "[tempunavail]".to_string()),
+                    ],
+                }],
+            ),
+            (
+                vec![
+                    "EHLO client.example.org\r\n",
+                    "AUTH PLAIN AGpvaG4AU2VjcmV0\r\n",
+                    "auth login\r\n",
+                    "HELO client.example.org\r\n",
+                ],
+                vec![
+                    Request::Ehlo {
+                        domain: "client.example.org".to_string(),
+                    },
+                    Request::Auth {
+                        mechanism: "plain".to_string(),
+                        initial_response: "AGpvaG4AU2VjcmV0".to_string().into(),
+                    },
+                    Request::Auth {
+                        mechanism: "login".to_string(),
+                        initial_response: None,
+                    },
+                    Request::Helo {
+                        domain: "client.example.org".to_string(),
+                    },
+                ],
+            ),
         ] {
             let mut commands = Vec::new();
             for chunk in &chunks {
@@ -695,5 +882,16 @@ mod tests {
             }
             assert_eq!(commands, expected_commands, "{:#?}", commands);
         }
+
+        // SASL responses are read verbatim once requested by the server
+        parser.state = State::SaslResponse;
+        let mut bytes = b"dXNlcg==\r\nNOOP\r\n".iter();
+        assert_eq!(
+            parser.parse(&mut bytes).unwrap(),
+            Request::SaslResponse {
+                data: "dXNlcg==".to_string()
+            }
+        );
+        assert_eq!(parser.parse(&mut bytes).unwrap(), Request::Noop);
     }
 }
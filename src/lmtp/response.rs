@@ -49,6 +49,9 @@ pub enum Extension {
     SmtpUtf8,
     StartTls,
     EnhancedStatusCodes,
+    Xclient,
+    Xforward,
+    Auth(&'static str),
 }
 
 impl Response<'_> {
@@ -98,6 +101,12 @@ impl Response<'_> {
                         Extension::EnhancedStatusCodes => {
                             buf.extend_from_slice(b"ENHANCEDSTATUSCODES")
                         }
+                        Extension::Xclient => buf.extend_from_slice(b"XCLIENT NAME ADDR HELO"),
+                        Extension::Xforward => buf.extend_from_slice(b"XFORWARD NAME ADDR HELO"),
+                        Extension::Auth(mechanisms) => {
+                            buf.extend_from_slice(b"AUTH ");
+                            buf.extend_from_slice(mechanisms.as_bytes())
+                        }
                     }
                     buf.extend_from_slice(b"\r\n");
                 }
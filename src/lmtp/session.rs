@@ -21,10 +21,15 @@
  * for more details.
 */
 
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 
 use actix_web::web;
 use store::{ahash::AHashSet, chrono::Local, tracing::debug, AccountId, RecipientType, Store};
+#[cfg(unix)]
+use tokio::net::UnixStream;
 use tokio::{
     io::{AsyncReadExt, AsyncWriteExt},
     net::TcpStream,
@@ -34,8 +39,10 @@ use tokio_rustls::{server::TlsStream, TlsAcceptor};
 use crate::JMAPServer;
 
 use super::{
+    mail_auth::MailAuthenticator,
     request::{Event, Param, Request, RequestParser},
     response::{Extension, Response},
+    spam_filter::SpamFilter,
 };
 
 const MAX_COMMAND_LENGTH: usize = 1024;
@@ -50,9 +57,15 @@ where
     pub parser: RequestParser,
     pub peer_addr: SocketAddr,
     pub stream: Stream,
+    pub mail_auth: Option<Arc<MailAuthenticator>>,
+    pub spam_filter: Option<Arc<SpamFilter>>,
 
     // State
     pub remote_hostname: Option<String>,
+    pub client_addr: Option<IpAddr>,
+    pub client_name: Option<String>,
+    pub client_helo: Option<String>,
+    pub client_forwarded: bool,
     pub mail_from: Option<String>,
     pub mail_size: Option<usize>,
     pub rcpt_to: Vec<RcptType>,
@@ -69,6 +82,8 @@ pub enum RcptType {
 pub enum Stream {
     Clear(TcpStream),
     Tls(TlsStream<TcpStream>),
+    #[cfg(unix)]
+    Unix(UnixStream),
     None,
 }
 
@@ -82,20 +97,28 @@ where
         stream: Stream,
         tls_acceptor: Option<Arc<TlsAcceptor>>,
         hostname: Arc<String>,
+        mail_auth: Option<Arc<MailAuthenticator>>,
+        spam_filter: Option<Arc<SpamFilter>>,
     ) -> Self {
         Self {
-            parser: RequestParser::new(MAX_COMMAND_LENGTH, core.store.config.mail_max_size),
+            parser: RequestParser::new(MAX_COMMAND_LENGTH, core.store.config.mail_max_size.get()),
             tls_acceptor,
             peer_addr,
             stream,
             core,
             remote_hostname: None,
+            client_addr: None,
+            client_name: None,
+            client_helo: None,
+            client_forwarded: false,
             mail_from: None,
             mail_size: None,
             rcpt_to: Vec::new(),
             rcpt_to_ids: AHashSet::new(),
             message: Vec::new(),
             hostname,
+            mail_auth,
+            spam_filter,
         }
     }
 
@@ -115,9 +138,11 @@ where
                             Extension::SmtpUtf8,
                             Extension::Vrfy,
                             Extension::Help,
-                            Extension::Size(self.core.store.config.mail_max_size as u32),
+                            Extension::Size(self.core.store.config.mail_max_size.get() as u32),
+                            Extension::Xclient,
+                            Extension::Xforward,
                         ];
-                        if !self.stream.is_tls() {
+                        if let Stream::Clear(_) = self.stream {
                             extensions.push(Extension::StartTls);
                         }
                         self.write_bytes(
@@ -190,9 +215,12 @@ where
                     Request::Data { data } => {
                         self.message = data;
                         self.ingest_message().await?;
+                        self.reset_forwarded();
                     }
                     Request::Bdat { data, is_last } => {
-                        if self.message.len() + data.len() < self.core.store.config.mail_max_size {
+                        if self.message.len() + data.len()
+                            < self.core.store.config.mail_max_size.get()
+                        {
                             if self.message.is_empty() {
                                 let rp = self.build_return_path();
                                 self.message = Vec::with_capacity(
@@ -205,6 +233,7 @@ where
                             self.message.extend_from_slice(&data);
                             if is_last {
                                 self.ingest_message().await?;
+                                self.reset_forwarded();
                             } else {
                                 self.write_bytes(b"250 2.1.0 Message chunk accepted.\r\n")
                                     .await?;
@@ -213,7 +242,7 @@ where
                             self.write_bytes(
                                 format!(
                                     "500 5.3.4 Message exceeds maximum size of {} bytes.\r\n",
-                                    self.core.store.config.mail_max_size
+                                    self.core.store.config.mail_max_size.get()
                                 )
                                 .as_bytes(),
                             )
@@ -302,11 +331,17 @@ where
                             self.write_bytes(b"501 5.7.0 Already in TLS mode.\r\n")
                                 .await?;
                         }
+                        #[cfg(unix)]
+                        (Stream::Unix(_), _) => {
+                            self.write_bytes(b"501 5.7.4 TLS not available on local sockets.\r\n")
+                                .await?;
+                        }
                         (_, _) => {
                             unreachable!()
                         }
                     },
                     Request::Rset => {
+                        self.reset_forwarded();
                         self.mail_from = None;
                         self.mail_size = None;
                         self.rcpt_to.clear();
@@ -314,6 +349,33 @@ where
                         self.message = Vec::new();
                         self.write_bytes(b"250 2.0.0 OK\r\n").await?;
                     }
+                    Request::Xclient { attributes } => {
+                        self.set_client_attributes(attributes);
+                        self.client_forwarded = false;
+                        self.mail_from = None;
+                        self.mail_size = None;
+                        self.rcpt_to.clear();
+                        self.rcpt_to_ids.clear();
+                        self.message = Vec::new();
+                        self.write_bytes(
+                            format!("220 {} Stalwart LMTP at your service.\r\n", self.hostname)
+                                .as_bytes(),
+                        )
+                        .await?;
+                    }
+                    Request::Xforward { attributes } => {
+                        // Forwarded attributes only apply to the next transaction
+                        self.set_client_attributes(attributes);
+                        self.client_forwarded = true;
+                        self.write_bytes(b"250 2.0.0 OK\r\n").await?;
+                    }
+                    Request::Ehlo { .. }
+                    | Request::Helo { .. }
+                    | Request::Auth { .. }
+                    | Request::SaslResponse { .. } => {
+                        self.write_bytes(b"502 5.5.1 Command not implemented.\r\n")
+                            .await?;
+                    }
                     Request::Noop => {
                         self.write_bytes(b"250 2.0.0 OK\r\n").await?;
                     }
@@ -349,39 +411,70 @@ where
     }
 
     pub async fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), ()> {
-        match &mut self.stream {
-            Stream::Clear(stream) => stream.write_all(bytes).await.map_err(|err| {
-                debug!("Failed to write to stream: {}", err);
-            }),
-            Stream::Tls(stream) => stream.write_all(bytes).await.map_err(|err| {
-                debug!("Failed to write to TLS stream: {}", err);
-            }),
-            _ => unreachable!(),
-        }
+        self.stream.write_bytes(bytes).await
     }
 
     pub async fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<usize, ()> {
-        match &mut self.stream {
-            Stream::Clear(stream) => stream.read(bytes).await.map_err(|err| {
-                debug!("Failed to read from stream: {}", err);
-            }),
-            Stream::Tls(stream) => stream.read(bytes).await.map_err(|err| {
-                debug!("Failed to read from TLS stream: {}", err);
-            }),
-            _ => unreachable!(),
+        self.stream.read_bytes(bytes).await
+    }
+
+    // Sets the original client details as sent by the MTA using XCLIENT
+    // or XFORWARD. Unavailable values (i.e. "[UNAVAILABLE]") are discarded.
+    fn set_client_attributes(&mut self, attributes: Vec<(String, String)>) {
+        for (name, value) in attributes {
+            match name.as_str() {
+                "addr" => {
+                    self.client_addr = value.strip_prefix("ipv6:").unwrap_or(&value).parse().ok();
+                }
+                "name" => {
+                    self.client_name = if !value.starts_with('[') {
+                        value.into()
+                    } else {
+                        None
+                    };
+                }
+                "helo" => {
+                    self.client_helo = if !value.starts_with('[') {
+                        value.into()
+                    } else {
+                        None
+                    };
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn reset_forwarded(&mut self) {
+        if self.client_forwarded {
+            self.client_addr = None;
+            self.client_name = None;
+            self.client_helo = None;
+            self.client_forwarded = false;
         }
     }
 
     fn build_return_path(&self) -> String {
+        let (helo, name, addr) = if let Some(client_addr) = self.client_addr {
+            (
+                self.client_helo.as_deref(),
+                self.client_name.as_deref(),
+                client_addr,
+            )
+        } else {
+            (self.remote_hostname.as_deref(), None, self.peer_addr.ip())
+        };
+
         format!(
             concat!(
-                "Received: from {} ([{}])",
+                "Received: from {} ({} [{}])",
                 "\tby {} (Stalwart JMAP) with LMTP;",
                 "\t{}\r\n"
             ),
-            self.remote_hostname.as_deref().unwrap_or("unknown"),
+            helo.unwrap_or("unknown"),
+            name.unwrap_or("unknown"),
+            addr,
             self.hostname.as_ref(),
-            self.peer_addr.ip(),
             Local::now().to_rfc2822()
         )
     }
@@ -399,6 +492,13 @@ impl From<TlsStream<TcpStream>> for Stream {
     }
 }
 
+#[cfg(unix)]
+impl From<UnixStream> for Stream {
+    fn from(stream: UnixStream) -> Self {
+        Stream::Unix(stream)
+    }
+}
+
 impl Stream {
     pub fn unwrap_clear(self) -> TcpStream {
         match self {
@@ -410,6 +510,38 @@ impl Stream {
     pub fn is_tls(&self) -> bool {
         matches!(self, Stream::Tls(_))
     }
+
+    pub async fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        match self {
+            Stream::Clear(stream) => stream.write_all(bytes).await.map_err(|err| {
+                debug!("Failed to write to stream: {}", err);
+            }),
+            Stream::Tls(stream) => stream.write_all(bytes).await.map_err(|err| {
+                debug!("Failed to write to TLS stream: {}", err);
+            }),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.write_all(bytes).await.map_err(|err| {
+                debug!("Failed to write to Unix stream: {}", err);
+            }),
+            _ => unreachable!(),
+        }
+    }
+
+    pub async fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<usize, ()> {
+        match self {
+            Stream::Clear(stream) => stream.read(bytes).await.map_err(|err| {
+                debug!("Failed to read from stream: {}", err);
+            }),
+            Stream::Tls(stream) => stream.read(bytes).await.map_err(|err| {
+                debug!("Failed to read from TLS stream: {}", err);
+            }),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.read(bytes).await.map_err(|err| {
+                debug!("Failed to read from Unix stream: {}", err);
+            }),
+            _ => unreachable!(),
+        }
+    }
 }
 
 impl Default for Stream {
@@ -0,0 +1,386 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+#[cfg(unix)]
+use tokio::net::UnixStream;
+
+use crate::lmtp::mail_auth::split_headers;
+
+use super::{Action, Envelope, ScanResult, SpamScore};
+
+// Milter protocol version 6
+const VERSION: u32 = 6;
+
+// Actions requested from the filter
+const SMFIF_ADDHDRS: u32 = 0x01;
+const SMFIF_QUARANTINE: u32 = 0x20;
+
+// Protocol steps the filter may ask to skip
+const SMFIP_NOCONNECT: u32 = 0x01;
+const SMFIP_NOHELO: u32 = 0x02;
+const SMFIP_NOMAIL: u32 = 0x04;
+const SMFIP_NORCPT: u32 = 0x08;
+const SMFIP_NOBODY: u32 = 0x10;
+const SMFIP_NOHDRS: u32 = 0x20;
+const SMFIP_NOEOH: u32 = 0x40;
+const SMFIP_NODATA: u32 = 0x200;
+const SMFIP_SKIP_MASK: u32 = SMFIP_NOCONNECT
+    | SMFIP_NOHELO
+    | SMFIP_NOMAIL
+    | SMFIP_NORCPT
+    | SMFIP_NOBODY
+    | SMFIP_NOHDRS
+    | SMFIP_NOEOH
+    | SMFIP_NODATA;
+
+const MAX_CHUNK_SIZE: usize = 65535;
+const MAX_PACKET_SIZE: usize = 1024 * 1024;
+
+pub struct Client {
+    addr: String,
+}
+
+enum Reply {
+    Continue,
+    Accept,
+    Reject,
+    TempFail,
+}
+
+struct Connection<T: AsyncRead + AsyncWrite + Unpin> {
+    stream: T,
+    protocol: u32,
+}
+
+impl Client {
+    // Accepts either "host:port" or "unix:/path/to/socket"
+    pub fn new(addr: String) -> Self {
+        Client { addr }
+    }
+
+    pub async fn scan(
+        &self,
+        message: &[u8],
+        envelope: &Envelope<'_>,
+    ) -> Result<ScanResult, String> {
+        let map_err = |err: std::io::Error| format!("Milter connection failed: {}", err);
+
+        if let Some(path) = self.addr.strip_prefix("unix:") {
+            #[cfg(unix)]
+            {
+                Connection::negotiate(UnixStream::connect(path).await.map_err(map_err)?)
+                    .await?
+                    .scan(message, envelope)
+                    .await
+            }
+            #[cfg(not(unix))]
+            {
+                let _ = path;
+                Err("Unix sockets are not supported on this platform.".to_string())
+            }
+        } else {
+            Connection::negotiate(TcpStream::connect(&self.addr).await.map_err(map_err)?)
+                .await?
+                .scan(message, envelope)
+                .await
+        }
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> Connection<T> {
+    async fn negotiate(stream: T) -> Result<Self, String> {
+        let mut conn = Connection {
+            stream,
+            protocol: 0,
+        };
+        let mut data = Vec::with_capacity(12);
+        data.extend_from_slice(&VERSION.to_be_bytes());
+        data.extend_from_slice(&(SMFIF_ADDHDRS | SMFIF_QUARANTINE).to_be_bytes());
+        data.extend_from_slice(&SMFIP_SKIP_MASK.to_be_bytes());
+        conn.write(b'O', &data).await?;
+
+        match conn.read().await? {
+            (b'O', data) if data.len() >= 12 => {
+                conn.protocol = u32::from_be_bytes(data[8..12].try_into().unwrap());
+                Ok(conn)
+            }
+            _ => Err("Milter option negotiation failed.".to_string()),
+        }
+    }
+
+    async fn scan(mut self, message: &[u8], envelope: &Envelope<'_>) -> Result<ScanResult, String> {
+        let (headers, body_offset) = split_headers(message);
+        let mut result = ScanResult {
+            score: SpamScore {
+                score: None,
+                action: Action::Accept,
+            },
+            headers: Vec::new(),
+        };
+
+        // Send envelope
+        let mut steps = Vec::with_capacity(4 + envelope.rcpt_to.len());
+        if self.protocol & SMFIP_NOCONNECT == 0 {
+            let mut data = Vec::with_capacity(64);
+            data.extend_from_slice(envelope.client_helo.unwrap_or("unknown").as_bytes());
+            data.push(0);
+            match envelope.client_addr {
+                Some(addr) => {
+                    data.push(if addr.is_ipv4() { b'4' } else { b'6' });
+                    data.extend_from_slice(&25u16.to_be_bytes());
+                    data.extend_from_slice(addr.to_string().as_bytes());
+                    data.push(0);
+                }
+                None => data.push(b'U'),
+            }
+            steps.push((b'C', data));
+        }
+        if self.protocol & SMFIP_NOHELO == 0 {
+            steps.push((b'H', c_string(envelope.client_helo.unwrap_or("unknown"))));
+        }
+        if self.protocol & SMFIP_NOMAIL == 0 {
+            steps.push((b'M', c_string(&format!("<{}>", envelope.mail_from))));
+        }
+        if self.protocol & SMFIP_NORCPT == 0 {
+            for rcpt in envelope.rcpt_to {
+                steps.push((b'R', c_string(&format!("<{}>", rcpt))));
+            }
+        }
+        if self.protocol & SMFIP_NODATA == 0 {
+            steps.push((b'T', Vec::new()));
+        }
+        if self.protocol & SMFIP_NOHDRS == 0 {
+            for header in &headers {
+                let mut data = header.name.to_vec();
+                data.push(0);
+                data.extend_from_slice(header.value.strip_prefix(b" ").unwrap_or(header.value));
+                data.push(0);
+                steps.push((b'L', data));
+            }
+        }
+        if self.protocol & SMFIP_NOEOH == 0 {
+            steps.push((b'N', Vec::new()));
+        }
+        if self.protocol & SMFIP_NOBODY == 0 {
+            for chunk in message[body_offset..].chunks(MAX_CHUNK_SIZE) {
+                steps.push((b'B', chunk.to_vec()));
+            }
+        }
+
+        for (command, data) in steps {
+            self.write(command, &data).await?;
+            match self.reply(&mut result).await? {
+                Reply::Continue => (),
+                Reply::Accept => return self.quit(result).await,
+                Reply::Reject => {
+                    result.score.action = Action::Reject;
+                    return self.quit(result).await;
+                }
+                Reply::TempFail => return Err("Milter returned a temporary failure.".to_string()),
+            }
+        }
+
+        // End of message, obtain modifications and the final verdict
+        self.write(b'E', &[]).await?;
+        match self.reply(&mut result).await? {
+            Reply::Continue | Reply::Accept => (),
+            Reply::Reject => {
+                result.score.action = Action::Reject;
+            }
+            Reply::TempFail => return Err("Milter returned a temporary failure.".to_string()),
+        }
+
+        // Obtain score from the added headers, if any
+        result.score.score = result.headers.iter().find_map(|(name, value)| {
+            if name.eq_ignore_ascii_case("X-Spam-Score") {
+                value.trim().parse().ok()
+            } else {
+                None
+            }
+        });
+
+        self.quit(result).await
+    }
+
+    // Reads replies until the filter returns a decision, collecting modifications
+    async fn reply(&mut self, result: &mut ScanResult) -> Result<Reply, String> {
+        loop {
+            let (command, data) = self.read().await?;
+            match command {
+                b'c' => return Ok(Reply::Continue),
+                b'a' => return Ok(Reply::Accept),
+                b'r' | b'd' => return Ok(Reply::Reject),
+                b't' => return Ok(Reply::TempFail),
+                b'y' => {
+                    return Ok(if data.first() == Some(&b'5') {
+                        Reply::Reject
+                    } else {
+                        Reply::TempFail
+                    });
+                }
+                b'h' | b'i' => {
+                    let data = if command == b'i' {
+                        data.get(4..).unwrap_or_default()
+                    } else {
+                        &data[..]
+                    };
+                    let mut parts = data.split(|&ch| ch == 0);
+                    if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                        result.headers.push((
+                            String::from_utf8_lossy(name).into_owned(),
+                            String::from_utf8_lossy(value).into_owned(),
+                        ));
+                    }
+                }
+                b'q' => {
+                    result.score.action = Action::Junk;
+                }
+                // Progress and unsupported modifications
+                _ => (),
+            }
+        }
+    }
+
+    async fn quit(mut self, result: ScanResult) -> Result<ScanResult, String> {
+        let _ = self.write(b'Q', &[]).await;
+        Ok(result)
+    }
+
+    async fn write(&mut self, command: u8, data: &[u8]) -> Result<(), String> {
+        let mut packet = Vec::with_capacity(data.len() + 5);
+        packet.extend_from_slice(&((data.len() + 1) as u32).to_be_bytes());
+        packet.push(command);
+        packet.extend_from_slice(data);
+        self.stream
+            .write_all(&packet)
+            .await
+            .map_err(|err| format!("Failed to write to milter: {}", err))
+    }
+
+    async fn read(&mut self) -> Result<(u8, Vec<u8>), String> {
+        let map_err = |err: std::io::Error| format!("Failed to read from milter: {}", err);
+        let mut len = [0u8; 4];
+        self.stream.read_exact(&mut len).await.map_err(map_err)?;
+        let len = u32::from_be_bytes(len) as usize;
+        if len == 0 || len > MAX_PACKET_SIZE {
+            return Err(format!("Invalid milter packet length {}.", len));
+        }
+        let mut data = vec![0u8; len];
+        self.stream.read_exact(&mut data).await.map_err(map_err)?;
+        let command = data.remove(0);
+        Ok((command, data))
+    }
+}
+
+fn c_string(value: &str) -> Vec<u8> {
+    let mut data = Vec::with_capacity(value.len() + 1);
+    data.extend_from_slice(value.as_bytes());
+    data.push(0);
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use crate::lmtp::spam_filter::{Action, Envelope};
+
+    use super::{Client, SMFIP_NOHELO};
+
+    #[tokio::test]
+    async fn milter_scan() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut commands = Vec::new();
+
+            loop {
+                let len = stream.read_u32().await.unwrap() as usize;
+                let mut data = vec![0u8; len];
+                stream.read_exact(&mut data).await.unwrap();
+                commands.push(data[0]);
+
+                let reply = |command: u8, data: &[u8]| {
+                    let mut packet = ((data.len() + 1) as u32).to_be_bytes().to_vec();
+                    packet.push(command);
+                    packet.extend_from_slice(data);
+                    packet
+                };
+                let response = match data[0] {
+                    b'O' => {
+                        let mut options = data[1..9].to_vec();
+                        options.extend_from_slice(&SMFIP_NOHELO.to_be_bytes());
+                        reply(b'O', &options)
+                    }
+                    b'L' => {
+                        assert_eq!(&data[1..], b"Subject\0test\0");
+                        reply(b'c', &[])
+                    }
+                    b'E' => {
+                        let mut response = reply(b'h', b"X-Spam-Score\07.5\0");
+                        response.extend(reply(b'q', b"spam\0"));
+                        response.extend(reply(b'a', &[]));
+                        response
+                    }
+                    b'Q' => break,
+                    _ => reply(b'c', &[]),
+                };
+                stream.write_all(&response).await.unwrap();
+            }
+
+            commands
+        });
+
+        let result = Client::new(addr)
+            .scan(
+                b"Subject: test\r\n\r\nbody",
+                &Envelope {
+                    mail_from: "jdoe@example.org",
+                    rcpt_to: &["jane@example.org".to_string()],
+                    client_addr: "192.0.2.1".parse().ok(),
+                    client_helo: Some("mail.example.org"),
+                    hostname: "mx.local",
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.score.score, Some(7.5));
+        assert_eq!(result.score.action, Action::Junk);
+        assert_eq!(
+            result.headers,
+            vec![("X-Spam-Score".to_string(), "7.5".to_string())]
+        );
+        assert_eq!(server.await.unwrap(), b"OCMRTLNBEQ");
+    }
+}
@@ -0,0 +1,287 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+pub mod milter;
+pub mod rspamd;
+
+use std::{net::IpAddr, time::Duration};
+
+use jmap::principal::schema::SpamFilter as SpamThresholds;
+use serde::{Deserialize, Serialize};
+use store::config::env_settings::EnvSettings;
+
+use crate::server::failed_to;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Action {
+    Accept,
+    Junk,
+    Reject,
+}
+
+// Verdict passed on to delivery, where per-account thresholds are applied
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpamScore {
+    pub score: Option<f64>,
+    pub action: Action,
+}
+
+pub struct ScanResult {
+    pub score: SpamScore,
+    pub headers: Vec<(String, String)>,
+}
+
+pub struct Envelope<'x> {
+    pub mail_from: &'x str,
+    pub rcpt_to: &'x [String],
+    pub client_addr: Option<IpAddr>,
+    pub client_helo: Option<&'x str>,
+    pub hostname: &'x str,
+}
+
+enum Scanner {
+    Rspamd(rspamd::Client),
+    Milter(milter::Client),
+}
+
+pub struct SpamFilter {
+    scanner: Scanner,
+    timeout: Duration,
+    thresholds: SpamThresholds,
+}
+
+impl SpamFilter {
+    pub fn new(settings: &EnvSettings) -> Option<Self> {
+        let timeout = Duration::from_millis(settings.parse("spam-filter-timeout").unwrap_or(30000));
+        let scanner = match settings.get("spam-filter-type")?.as_str() {
+            "rspamd" => Scanner::Rspamd(
+                rspamd::Client::new(
+                    settings
+                        .get("spam-filter-url")
+                        .unwrap_or_else(|| "http://127.0.0.1:11333/checkv2".to_string()),
+                    timeout,
+                )
+                .unwrap_or_else(|err| failed_to(&format!("create spam filter client: {}", err))),
+            ),
+            "milter" => Scanner::Milter(milter::Client::new(
+                settings
+                    .get("spam-filter-milter-addr")
+                    .unwrap_or_else(|| "127.0.0.1:11332".to_string()),
+            )),
+            other => failed_to(&format!(
+                "parse 'spam-filter-type', unknown value '{}'.",
+                other
+            )),
+        };
+
+        Some(SpamFilter {
+            scanner,
+            timeout,
+            thresholds: SpamThresholds {
+                junk_score: settings.parse("spam-filter-junk-score"),
+                reject_score: settings.parse("spam-filter-reject-score"),
+            },
+        })
+    }
+
+    // Scans a message and returns the headers to prepend to it along with
+    // the verdict after applying the server-wide thresholds.
+    pub async fn scan(
+        &self,
+        message: &[u8],
+        envelope: &Envelope<'_>,
+    ) -> Result<ScanResult, String> {
+        let mut result = tokio::time::timeout(self.timeout, async {
+            match &self.scanner {
+                Scanner::Rspamd(client) => client.scan(message, envelope).await,
+                Scanner::Milter(client) => client.scan(message, envelope).await,
+            }
+        })
+        .await
+        .map_err(|_| "Spam filter timed out.".to_string())??;
+        result.score.action = result.score.action(Some(&self.thresholds));
+        Ok(result)
+    }
+}
+
+impl ScanResult {
+    pub fn build_headers(&self) -> String {
+        let mut headers = format!(
+            "X-Spam-Status: {}",
+            if self.score.action != Action::Accept {
+                "Yes"
+            } else {
+                "No"
+            }
+        );
+        if let Some(score) = self.score.score {
+            headers.push_str(&format!(", score={:.2}", score));
+        }
+        headers.push_str("\r\n");
+        for (name, value) in &self.headers {
+            // Drop header names that could not be parsed back, and fold any
+            // line breaks in the values so scanners can't inject headers.
+            if !name.is_empty()
+                && name
+                    .bytes()
+                    .all(|ch| (33..=126).contains(&ch) && ch != b':')
+                && !name.eq_ignore_ascii_case("X-Spam-Status")
+            {
+                let value = value
+                    .split(['\r', '\n'])
+                    .map(|line| line.trim())
+                    .filter(|line| !line.is_empty())
+                    .collect::<Vec<_>>()
+                    .join("\r\n\t");
+                headers.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        headers
+    }
+}
+
+impl SpamScore {
+    // Thresholds that are not set fall back to the action suggested by the scanner
+    pub fn action(&self, thresholds: Option<&SpamThresholds>) -> Action {
+        let is_reject = match (self.score, thresholds.and_then(|t| t.reject_score)) {
+            (Some(score), Some(threshold)) => score >= threshold,
+            _ => self.action == Action::Reject,
+        };
+        let is_junk = match (self.score, thresholds.and_then(|t| t.junk_score)) {
+            (Some(score), Some(threshold)) => score >= threshold,
+            _ => self.action != Action::Accept,
+        };
+
+        if is_reject {
+            Action::Reject
+        } else if is_junk {
+            Action::Junk
+        } else {
+            Action::Accept
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jmap::principal::schema::SpamFilter as SpamThresholds;
+
+    use super::{Action, ScanResult, SpamScore};
+
+    #[test]
+    fn spam_thresholds() {
+        let thresholds = SpamThresholds {
+            junk_score: Some(5.0),
+            reject_score: Some(15.0),
+        };
+        let junk_only = SpamThresholds {
+            junk_score: Some(10.0),
+            reject_score: None,
+        };
+
+        for (score, action, expected, expected_thresholds, expected_junk_only) in [
+            (
+                Some(2.0),
+                Action::Accept,
+                Action::Accept,
+                Action::Accept,
+                Action::Accept,
+            ),
+            (
+                Some(6.0),
+                Action::Accept,
+                Action::Accept,
+                Action::Junk,
+                Action::Accept,
+            ),
+            (
+                Some(6.0),
+                Action::Junk,
+                Action::Junk,
+                Action::Junk,
+                Action::Accept,
+            ),
+            (
+                Some(12.0),
+                Action::Reject,
+                Action::Reject,
+                Action::Junk,
+                Action::Reject,
+            ),
+            (
+                Some(20.0),
+                Action::Junk,
+                Action::Junk,
+                Action::Reject,
+                Action::Junk,
+            ),
+            (None, Action::Junk, Action::Junk, Action::Junk, Action::Junk),
+            (
+                None,
+                Action::Reject,
+                Action::Reject,
+                Action::Reject,
+                Action::Reject,
+            ),
+        ] {
+            let spam = SpamScore { score, action };
+            assert_eq!(spam.action(None), expected);
+            assert_eq!(spam.action(Some(&thresholds)), expected_thresholds);
+            assert_eq!(spam.action(Some(&junk_only)), expected_junk_only);
+        }
+    }
+
+    #[test]
+    fn spam_headers() {
+        let result = ScanResult {
+            score: SpamScore {
+                score: Some(7.5),
+                action: Action::Junk,
+            },
+            headers: vec![
+                ("X-Spam-Status".to_string(), "No".to_string()),
+                (
+                    "X-Spamd-Result".to_string(),
+                    "default: False\r\n\tBAYES_HAM(-3.00)".to_string(),
+                ),
+                (
+                    "X-Rspamd-Server".to_string(),
+                    "scanner\r\nSubject: Injected\n".to_string(),
+                ),
+                (
+                    "Bcc: victim@example.org\r\nX-Bad".to_string(),
+                    "1".to_string(),
+                ),
+                ("".to_string(), "empty".to_string()),
+            ],
+        };
+        assert_eq!(
+            result.build_headers(),
+            concat!(
+                "X-Spam-Status: Yes, score=7.50\r\n",
+                "X-Spamd-Result: default: False\r\n\tBAYES_HAM(-3.00)\r\n",
+                "X-Rspamd-Server: scanner\r\n\tSubject: Injected\r\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,209 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use serde::Deserialize;
+use store::ahash::AHashMap;
+
+use super::{Action, Envelope, ScanResult, SpamScore};
+
+pub struct Client {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Response {
+    score: Option<f64>,
+    action: Option<String>,
+    milter: Option<MilterResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MilterResponse {
+    add_headers: Option<AHashMap<String, HeaderValue>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum HeaderValue {
+    Text(String),
+    Ordered { value: String },
+}
+
+impl Client {
+    pub fn new(url: String, timeout: Duration) -> Result<Self, String> {
+        Ok(Client {
+            client: reqwest::Client::builder()
+                .timeout(timeout)
+                .build()
+                .map_err(|err| err.to_string())?,
+            url,
+        })
+    }
+
+    pub async fn scan(
+        &self,
+        message: &[u8],
+        envelope: &Envelope<'_>,
+    ) -> Result<ScanResult, String> {
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header("From", envelope.mail_from)
+            .header("Hostname", envelope.hostname);
+        for rcpt in envelope.rcpt_to {
+            request = request.header("Rcpt", rcpt.as_str());
+        }
+        if let Some(addr) = &envelope.client_addr {
+            request = request.header("IP", addr.to_string());
+        }
+        if let Some(helo) = envelope.client_helo {
+            request = request.header("Helo", helo);
+        }
+
+        let response = request
+            .body(message.to_vec())
+            .send()
+            .await
+            .map_err(|err| format!("Request to spam filter failed: {}", err))?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Spam filter returned HTTP status {}",
+                response.status()
+            ));
+        }
+        let response = serde_json::from_slice::<Response>(
+            &response
+                .bytes()
+                .await
+                .map_err(|err| format!("Failed to read spam filter response: {}", err))?,
+        )
+        .map_err(|err| format!("Failed to parse spam filter response: {}", err))?;
+
+        let mut headers = response
+            .milter
+            .and_then(|milter| milter.add_headers)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(name, value)| match value {
+                HeaderValue::Text(value) | HeaderValue::Ordered { value } => (name, value),
+            })
+            .collect::<Vec<_>>();
+        headers.sort_unstable();
+
+        Ok(ScanResult {
+            score: SpamScore {
+                score: response.score,
+                action: match response.action.as_deref() {
+                    Some("reject") => Action::Reject,
+                    Some("add header" | "rewrite subject") => Action::Junk,
+                    _ => Action::Accept,
+                },
+            },
+            headers,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use crate::lmtp::spam_filter::{Action, Envelope};
+
+    use super::Client;
+
+    #[tokio::test]
+    async fn rspamd_scan() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/checkv2", listener.local_addr().unwrap());
+
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            while !request.ends_with(b"Subject: test\r\n\r\nbody") {
+                let len = stream.read(&mut buf).await.unwrap();
+                assert!(len > 0);
+                request.extend_from_slice(&buf[..len]);
+            }
+            let request = String::from_utf8(request).unwrap().to_lowercase();
+            assert!(request.starts_with("post /checkv2"), "{}", request);
+            assert!(request.contains("from: jdoe@example.org"), "{}", request);
+            assert!(request.contains("rcpt: jane@example.org"), "{}", request);
+            assert!(request.contains("ip: 192.0.2.1"), "{}", request);
+
+            let body = concat!(
+                "{\"score\": 7.5, \"required_score\": 15.0, \"action\": \"add header\", ",
+                "\"milter\": {\"add_headers\": {\"X-Spamd-Bar\": \"+++++++\", ",
+                "\"X-Spam\": {\"value\": \"Yes\", \"order\": 0}}}}"
+            );
+            stream
+                .write_all(
+                    format!(
+                        concat!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\n",
+                            "Content-Length: {}\r\nConnection: close\r\n\r\n{}"
+                        ),
+                        body.len(),
+                        body
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+        });
+
+        let result = Client::new(url, Duration::from_secs(5))
+            .unwrap()
+            .scan(
+                b"Subject: test\r\n\r\nbody",
+                &Envelope {
+                    mail_from: "jdoe@example.org",
+                    rcpt_to: &["jane@example.org".to_string()],
+                    client_addr: "192.0.2.1".parse().ok(),
+                    client_helo: Some("mail.example.org"),
+                    hostname: "mx.local",
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(result.score.score, Some(7.5));
+        assert_eq!(result.score.action, Action::Junk);
+        assert_eq!(
+            result.headers,
+            vec![
+                ("X-Spam".to_string(), "Yes".to_string()),
+                ("X-Spamd-Bar".to_string(), "+++++++".to_string())
+            ]
+        );
+    }
+}
@@ -36,7 +36,8 @@ static GLOBAL: Jemalloc = Jemalloc;*/
 use stalwart_jmap::{
     cluster::init::{init_cluster, start_cluster},
     server::{
-        http::{build_jmap_server, init_jmap_server},
+        http::{build_jmap_server, init_jmap_server, restore_jmap_server},
+        reload::set_default_settings,
         UnwrapFailure,
     },
 };
@@ -44,8 +45,8 @@ use stalwart_jmap::{
 use std::time::Duration;
 
 use store::{
-    config::env_settings::EnvSettings,
-    tracing::{self, debug, info, warn, Level},
+    config::{env_settings::EnvSettings, schema::print_effective},
+    tracing::{self, debug, info, level_filters::LevelFilter, Level},
     Store,
 };
 use store_rocksdb::RocksDB;
@@ -55,27 +56,33 @@ async fn main() -> std::io::Result<()> {
     // Read configuration parameters
     let mut settings = EnvSettings::new();
 
+    // Print the effective configuration
+    if settings.parse("check-config").unwrap_or(false) {
+        set_default_settings(&mut settings);
+        print!("{}", print_effective(&settings));
+        return Ok(());
+    }
+
     // Enable logging
-    tracing::subscriber::set_global_default(
-        tracing_subscriber::FmtSubscriber::builder()
-            .with_max_level(settings.parse("log-level").unwrap_or(Level::INFO))
-            .finish(),
-    )
-    .failed_to("set default subscriber");
+    let subscriber = tracing_subscriber::FmtSubscriber::builder()
+        .with_max_level(settings.parse("log-level").unwrap_or(Level::INFO))
+        .with_filter_reloading();
+    let log_handle = subscriber.reload_handle();
+    tracing::subscriber::set_global_default(subscriber.finish())
+        .failed_to("set default subscriber");
 
     // Set base URL if missing
-    if !settings.contains_key("jmap-url") {
-        let jmap_url = if settings.contains_key("jmap-cert-path") {
-            "https://localhost:8080"
-        } else {
-            "http://localhost:8080"
-        }
-        .to_string();
-        warn!(
-            "Warning: Hostname parameter 'jmap-url' was not specified, using '{}'.",
-            jmap_url
+    set_default_settings(&mut settings);
+
+    // Restore backup
+    if let Some(backup_path) = settings.get("restore") {
+        let (backup, raft_id) =
+            restore_jmap_server::<RocksDB>(&settings, &backup_path).failed_to("restore backup");
+        info!(
+            "Backup {} successfully restored (Raft term {}, index {}).",
+            backup.name, raft_id.term, raft_id.index
         );
-        settings.set_value("jmap-url".to_string(), jmap_url);
+        return Ok(());
     }
 
     // Init JMAP server
@@ -90,6 +97,11 @@ async fn main() -> std::io::Result<()> {
         .await
         .failed_to("start JMAP server");
     let server_handle = server.handle();
+    core.set_log_level_reloader(Box::new(move |level| {
+        log_handle
+            .reload(LevelFilter::from_level(level))
+            .map_err(|err| format!("Failed to change log level: {}", err))
+    }));
 
     // Start web server
     actix_web::rt::spawn(async move { server.await });
@@ -101,11 +113,38 @@ async fn main() -> std::io::Result<()> {
 
         let mut h_term = signal(SignalKind::terminate()).failed_to("start signal handler");
         let mut h_int = signal(SignalKind::interrupt()).failed_to("start signal handler");
-
-        tokio::select! {
-            _ = h_term.recv() => debug!("Received SIGTERM."),
-            _ = h_int.recv() => debug!("Received SIGINT."),
-        };
+        let mut h_hup = signal(SignalKind::hangup()).failed_to("start signal handler");
+
+        loop {
+            tokio::select! {
+                _ = h_term.recv() => {
+                    debug!("Received SIGTERM.");
+                    break;
+                }
+                _ = h_int.recv() => {
+                    debug!("Received SIGINT.");
+                    break;
+                }
+                _ = h_hup.recv() => {
+                    info!("Received SIGHUP, reloading configuration.");
+                    match EnvSettings::try_new() {
+                        Ok(settings) => core.reload_config(settings).await,
+                        Err(err) => Err(err),
+                    }
+                    .map(|report| {
+                        if !report.restart_required.is_empty() {
+                            tracing::warn!(
+                                "The following settings require a restart: {}",
+                                report.restart_required.join(", ")
+                            );
+                        }
+                    })
+                    .unwrap_or_else(|err| {
+                        tracing::error!("Failed to reload configuration: {}", err)
+                    });
+                }
+            };
+        }
     }
 
     #[cfg(target_env = "msvc")]
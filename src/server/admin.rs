@@ -0,0 +1,147 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::{http::StatusCode, web, HttpResponse};
+use jmap::SUPERUSER_ID;
+use store::{
+    config::env_settings::EnvSettings,
+    tracing::{error, warn},
+    Store,
+};
+
+use crate::{api::RequestError, authorization::Session, services::housekeeper, JMAPServer};
+
+#[derive(serde::Deserialize)]
+pub struct BackupParams {
+    incremental: Option<bool>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct BackupResponse {
+    name: Option<String>,
+    base: Option<String>,
+    term: u64,
+    index: Option<u64>,
+    #[serde(rename(serialize = "filesCopied"))]
+    files_copied: u64,
+    #[serde(rename(serialize = "filesLinked"))]
+    files_linked: u64,
+}
+
+pub async fn handle_admin_backup<T>(
+    params: web::Query<BackupParams>,
+    core: web::Data<JMAPServer<T>>,
+    session: Session,
+) -> Result<HttpResponse, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    if session.account_id() != SUPERUSER_ID {
+        return Err(RequestError::forbidden());
+    }
+
+    let incremental = params.into_inner().incremental.unwrap_or(true);
+    let store = core.store.clone();
+    match core.spawn_worker(move || store.backup(incremental)).await {
+        Ok(backup) => Ok(HttpResponse::build(StatusCode::OK)
+            .insert_header(("Content-Type", "application/json"))
+            .body(
+                serde_json::to_string(&if let Some(backup) = backup {
+                    BackupResponse {
+                        name: backup.name.into(),
+                        base: backup.manifest.base,
+                        term: backup.manifest.raft_id.term,
+                        index: if !backup.manifest.raft_id.is_none() {
+                            backup.manifest.raft_id.index.into()
+                        } else {
+                            None
+                        },
+                        files_copied: backup.manifest.files_copied,
+                        files_linked: backup.manifest.files_linked,
+                    }
+                } else {
+                    BackupResponse {
+                        name: None,
+                        base: None,
+                        term: 0,
+                        index: None,
+                        files_copied: 0,
+                        files_linked: 0,
+                    }
+                })
+                .unwrap_or_default(),
+            )),
+        Err(err) => {
+            error!("Backup failed: {:?}", err);
+            Err(RequestError::internal_server_error())
+        }
+    }
+}
+
+pub async fn handle_admin_reload<T>(
+    core: web::Data<JMAPServer<T>>,
+    session: Session,
+) -> Result<HttpResponse, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    if session.account_id() != SUPERUSER_ID {
+        return Err(RequestError::forbidden());
+    }
+
+    match EnvSettings::try_new() {
+        Ok(settings) => core.reload_config(settings).await,
+        Err(err) => Err(err),
+    }
+    .map(|report| {
+        HttpResponse::build(StatusCode::OK)
+            .insert_header(("Content-Type", "application/json"))
+            .body(serde_json::to_string(&report).unwrap_or_default())
+    })
+    .map_err(|err| {
+        warn!("Failed to reload configuration: {}", err);
+        RequestError::blank(400, "Invalid Configuration", err)
+    })
+}
+
+pub async fn handle_admin_task<T>(
+    path: web::Path<String>,
+    core: web::Data<JMAPServer<T>>,
+    session: Session,
+) -> Result<HttpResponse, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    if session.account_id() != SUPERUSER_ID {
+        return Err(RequestError::forbidden());
+    }
+
+    let event =
+        housekeeper::Event::parse(&path.into_inner()).ok_or_else(RequestError::not_found)?;
+    if let Err(err) = core.housekeeper.send(event).await {
+        error!("Channel failure while sending housekeeper task: {}", err);
+        return Err(RequestError::internal_server_error());
+    }
+
+    Ok(HttpResponse::build(StatusCode::ACCEPTED).finish())
+}
@@ -21,7 +21,7 @@
  * for more details.
 */
 
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
 
 use actix_cors::Cors;
 use actix_web::{
@@ -37,11 +37,13 @@ use jmap::{
 };
 use jmap_sharing::principal::CreateAccount;
 use store::{
+    backup::Backup,
     config::{env_settings::EnvSettings, jmap::JMAPConfig},
     core::{collection::Collection, document::Document},
+    log::raft::{LogIndex, RaftId},
     moka::future::Cache,
     rand::{distributions::Alphanumeric, thread_rng, Rng},
-    tracing::info,
+    tracing::{info, warn},
     write::batch::WriteBatch,
     JMAPStore, Store,
 };
@@ -49,6 +51,8 @@ use store::{
 use crate::{
     api::{
         blob::{handle_jmap_download, handle_jmap_upload},
+        export::{handle_export_create, handle_export_get},
+        import::{handle_import_create, handle_import_get},
         request::handle_jmap_request,
         session::{handle_jmap_session, Session},
     },
@@ -60,14 +64,23 @@ use crate::{
             OAuth, OAuthMetadata,
         },
     },
-    cluster::{rpc::tls::load_tls_server_config, ClusterIpc},
+    cluster::{rpc::tls::load_tls_server_config_with_resolver, ClusterIpc},
+    dav::{handle_dav_discovery, handle_dav_request},
+    imap::listener::spawn_imap,
     lmtp::listener::{init_lmtp, spawn_lmtp},
-    server::{event_source::handle_jmap_event_source, websocket::handle_ws},
+    server::{
+        admin::{handle_admin_backup, handle_admin_reload, handle_admin_task},
+        event_source::handle_jmap_event_source,
+        metrics::{handle_metrics, Metrics},
+        reload::ReloadState,
+        websocket::handle_ws,
+    },
     services::{
         email_delivery::{init_email_delivery, spawn_email_delivery},
         housekeeper::{init_housekeeper, spawn_housekeeper},
         state_change::{init_state_manager, spawn_state_manager},
     },
+    smtp::listener::spawn_smtp,
     JMAPServer, DEFAULT_HTTP_PORT,
 };
 
@@ -76,6 +89,35 @@ use super::{failed_to, UnwrapFailure};
 const ONE_HOUR_EXPIRY: Duration = Duration::from_secs(60 * 60);
 const HALF_HOUR_EXPIRY: Duration = Duration::from_secs(30 * 60);
 
+/// Restores the backup at `backup_path` into the configured database and
+/// blob store, returning the Raft id the store was restored to.
+pub fn restore_jmap_server<T>(
+    settings: &EnvSettings,
+    backup_path: &str,
+) -> store::Result<(Backup, RaftId)>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let backup = JMAPStore::<T>::restore(
+        settings,
+        &PathBuf::from(backup_path),
+        settings.parse("restore-index"),
+    )?;
+    let store = JMAPStore::new(T::open(settings)?, JMAPConfig::from(settings), settings);
+    let raft_id = store
+        .get_prev_raft_id(RaftId::new(LogIndex::MAX, LogIndex::MAX))?
+        .unwrap_or_else(RaftId::none);
+    if raft_id != backup.manifest.raft_id {
+        warn!(
+            "Backup manifest references Raft term {} index {}.",
+            backup.manifest.raft_id.term, backup.manifest.raft_id.index
+        );
+    }
+    store.db.close()?;
+
+    Ok((backup, raft_id))
+}
+
 pub fn init_jmap_server<T>(
     settings: &EnvSettings,
     cluster: Option<ClusterIpc>,
@@ -86,6 +128,7 @@ where
     // Build the JMAP server.
     let config = JMAPConfig::from(settings);
     let base_session = Session::new(settings, &config);
+
     let store: Arc<JMAPStore<T>> = JMAPStore::new(
         T::open(settings).failed_to("open database"),
         config,
@@ -142,6 +185,11 @@ where
         std::process::exit(0);
     }
 
+    let payload_limit = std::cmp::max(
+        store.config.max_size_upload.get(),
+        store.config.max_size_request.get(),
+    );
+
     let (email_tx, email_rx) = init_email_delivery();
     let (housekeeper_tx, housekeeper_rx) = init_housekeeper();
     let (change_tx, change_rx) = init_state_manager();
@@ -204,6 +252,10 @@ where
             .time_to_idle(ONE_HOUR_EXPIRY)
             .build(),
         oauth_codes: Cache::builder().time_to_live(ONE_HOUR_EXPIRY).build(),
+        import_jobs: Cache::builder().build(),
+        export_jobs: Cache::builder().build(),
+        metrics: Arc::new(Metrics::default()),
+        reload: ReloadState::new(settings, payload_limit),
         oauth,
         cluster,
         base_session,
@@ -211,7 +263,9 @@ where
         is_offline: false.into(),
     });
 
-    // Spawn LMTP service
+    // Spawn LMTP, SMTP submission and IMAP services, all stop on the same signal
+    spawn_imap(server.clone(), settings, lmtp_rx.clone());
+    spawn_smtp(server.clone(), settings, lmtp_rx.clone());
     spawn_lmtp(server.clone(), settings, lmtp_rx);
 
     // Spawn TypeState manager
@@ -239,18 +293,12 @@ where
         settings.parse("jmap-port").unwrap_or(DEFAULT_HTTP_PORT),
     ));
 
-    // Obtain TLS path
-    let tls_config = if let Some(cert_path) = settings.get("jmap-cert-path") {
-        load_tls_server_config(
-            &cert_path,
-            &settings
-                .get("jmap-key-path")
-                .failed_to("load TLS config, missing 'jmap-key-path' argument."),
-        )
-        .into()
-    } else {
-        None
-    };
+    // Obtain TLS config
+    let tls_config = jmap_server
+        .reload
+        .jmap_tls
+        .clone()
+        .map(load_tls_server_config_with_resolver);
 
     info!(
         "Starting Stalwart JMAP server v{} at {} ({})...",
@@ -276,10 +324,7 @@ where
             })
             .wrap(middleware::Logger::default())
             .wrap(middleware::NormalizePath::trim())
-            .app_data(PayloadConfig::new(std::cmp::max(
-                jmap_server.store.config.max_size_upload,
-                jmap_server.store.config.max_size_request,
-            )))
+            .app_data(PayloadConfig::new(jmap_server.reload.payload_limit))
             .app_data(jmap_server.clone())
             .route("/.well-known/jmap", web::get().to(handle_jmap_session::<T>))
             .route("/jmap", web::post().to(handle_jmap_request::<T>))
@@ -291,11 +336,31 @@ where
                 "/jmap/download/{accountId}/{blobId}/{name}",
                 web::get().to(handle_jmap_download::<T>),
             )
+            .route(
+                "/jmap/import/{accountId}",
+                web::post().to(handle_import_create::<T>),
+            )
+            .route(
+                "/jmap/import/{accountId}/{jobId}",
+                web::get().to(handle_import_get::<T>),
+            )
+            .route(
+                "/jmap/export/{accountId}",
+                web::post().to(handle_export_create::<T>),
+            )
+            .route(
+                "/jmap/export/{accountId}/{jobId}",
+                web::get().to(handle_export_get::<T>),
+            )
             .route(
                 "/jmap/eventsource",
                 web::get().to(handle_jmap_event_source::<T>),
             )
             .route("/jmap/ws", web::get().to(handle_ws::<T>))
+            .route("/admin/backup", web::post().to(handle_admin_backup::<T>))
+            .route("/admin/reload", web::post().to(handle_admin_reload::<T>))
+            .route("/admin/task/{name}", web::post().to(handle_admin_task::<T>))
+            .route("/metrics", web::get().to(handle_metrics::<T>))
             .route("/auth", web::get().to(handle_user_device_auth::<T>))
             .route("/auth", web::post().to(handle_user_device_auth_post::<T>))
             .route("/auth/code", web::get().to(handle_user_code_auth::<T>))
@@ -309,6 +374,13 @@ where
                 "/.well-known/oauth-authorization-server",
                 web::get().to(handle_oauth_metadata::<T>),
             )
+            .route(
+                "/.well-known/carddav",
+                web::route().to(handle_dav_discovery),
+            )
+            .route("/.well-known/caldav", web::route().to(handle_dav_discovery))
+            .route("/dav", web::route().to(handle_dav_request::<T>))
+            .route("/dav/{path:.*}", web::route().to(handle_dav_request::<T>))
     });
     if let Some(tls_config) = tls_config {
         server.bind_rustls(http_addr, tls_config)
@@ -0,0 +1,386 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use actix_web::{http::StatusCode, web, HttpResponse};
+use jmap::SUPERUSER_ID;
+use store::{
+    ahash::AHashMap, core::cache::CacheStats, log::raft::LogIndex, parking_lot::Mutex,
+    tracing::error, Store,
+};
+
+use crate::{api::RequestError, authorization::Session, JMAPServer};
+
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Debug, Default)]
+pub struct Metrics {
+    methods: Mutex<AHashMap<&'static str, MethodMetrics>>,
+    lmtp_deliveries: Mutex<AHashMap<u16, u64>>,
+    pub push_queue: AtomicU64,
+    pub delivery_queue: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct MethodMetrics {
+    requests: u64,
+    errors: u64,
+    latency_buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+}
+
+impl Metrics {
+    pub fn record_method(&self, method: &'static str, elapsed: Duration, is_error: bool) {
+        let elapsed = elapsed.as_secs_f64();
+        let mut methods = self.methods.lock();
+        let metrics = methods.entry(method).or_insert_with(MethodMetrics::default);
+        metrics.requests += 1;
+        if is_error {
+            metrics.errors += 1;
+        }
+        metrics.latency_sum += elapsed;
+        for (pos, bucket) in LATENCY_BUCKETS.iter().enumerate() {
+            if elapsed <= *bucket {
+                metrics.latency_buckets[pos] += 1;
+            }
+        }
+    }
+
+    pub fn record_lmtp_delivery(&self, code: u16) {
+        *self.lmtp_deliveries.lock().entry(code).or_insert(0) += 1;
+    }
+
+    pub fn set_push_queue(&self, size: usize) {
+        self.push_queue.store(size as u64, Ordering::Relaxed);
+    }
+
+    pub fn set_delivery_queue(&self, size: usize) {
+        self.delivery_queue.store(size as u64, Ordering::Relaxed);
+    }
+}
+
+pub async fn handle_metrics<T>(
+    core: web::Data<JMAPServer<T>>,
+    session: Session,
+) -> Result<HttpResponse, RequestError>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    if session.account_id() != SUPERUSER_ID {
+        return Err(RequestError::forbidden());
+    }
+
+    let store = core.store.clone();
+    let mut db_stats = match core.spawn_worker(move || store.db.stats()).await {
+        Ok(db_stats) => db_stats,
+        Err(err) => {
+            error!("Failed to obtain database statistics: {:?}", err);
+            return Err(RequestError::internal_server_error());
+        }
+    };
+    let cluster_status = core.cluster_status().await;
+
+    let mut buf = String::with_capacity(4096);
+    let metrics = &core.metrics;
+
+    // JMAP methods
+    {
+        let methods = metrics.methods.lock();
+        write_header(
+            &mut buf,
+            "jmap_method_requests_total",
+            "counter",
+            "JMAP method calls.",
+        );
+        for (method, stats) in methods.iter() {
+            writeln!(
+                buf,
+                "jmap_method_requests_total{{method=\"{}\"}} {}",
+                method, stats.requests
+            )
+            .ok();
+        }
+        write_header(
+            &mut buf,
+            "jmap_method_errors_total",
+            "counter",
+            "JMAP method calls that returned an error.",
+        );
+        for (method, stats) in methods.iter() {
+            writeln!(
+                buf,
+                "jmap_method_errors_total{{method=\"{}\"}} {}",
+                method, stats.errors
+            )
+            .ok();
+        }
+        write_header(
+            &mut buf,
+            "jmap_method_duration_seconds",
+            "histogram",
+            "JMAP method call latency.",
+        );
+        for (method, stats) in methods.iter() {
+            for (bucket, count) in LATENCY_BUCKETS.iter().zip(stats.latency_buckets.iter()) {
+                writeln!(
+                    buf,
+                    "jmap_method_duration_seconds_bucket{{method=\"{}\",le=\"{}\"}} {}",
+                    method, bucket, count
+                )
+                .ok();
+            }
+            writeln!(
+                buf,
+                "jmap_method_duration_seconds_bucket{{method=\"{}\",le=\"+Inf\"}} {}",
+                method, stats.requests
+            )
+            .ok();
+            writeln!(
+                buf,
+                "jmap_method_duration_seconds_sum{{method=\"{}\"}} {}",
+                method, stats.latency_sum
+            )
+            .ok();
+            writeln!(
+                buf,
+                "jmap_method_duration_seconds_count{{method=\"{}\"}} {}",
+                method, stats.requests
+            )
+            .ok();
+        }
+    }
+
+    // LMTP
+    write_header(
+        &mut buf,
+        "jmap_lmtp_deliveries_total",
+        "counter",
+        "LMTP delivery replies by status code.",
+    );
+    for (code, count) in metrics.lmtp_deliveries.lock().iter() {
+        writeln!(
+            buf,
+            "jmap_lmtp_deliveries_total{{code=\"{}\"}} {}",
+            code, count
+        )
+        .ok();
+    }
+
+    // Queues
+    write_header(
+        &mut buf,
+        "jmap_push_queue_size",
+        "gauge",
+        "Push subscriptions waiting to be delivered.",
+    );
+    writeln!(
+        buf,
+        "jmap_push_queue_size {}",
+        metrics.push_queue.load(Ordering::Relaxed)
+    )
+    .ok();
+    write_header(
+        &mut buf,
+        "jmap_delivery_queue_size",
+        "gauge",
+        "Messages waiting to be relayed.",
+    );
+    writeln!(
+        buf,
+        "jmap_delivery_queue_size {}",
+        metrics.delivery_queue.load(Ordering::Relaxed)
+    )
+    .ok();
+
+    // Caches
+    let cache_stats = &core.store.cache_stats;
+    let caches: [(&str, &CacheStats, u64); 4] = [
+        (
+            "ids",
+            &cache_stats.id_assigner,
+            core.store.id_assigner.entry_count(),
+        ),
+        (
+            "shared_documents",
+            &cache_stats.shared_documents,
+            core.store.shared_documents.entry_count(),
+        ),
+        (
+            "acl_tokens",
+            &cache_stats.acl_tokens,
+            core.store.acl_tokens.entry_count(),
+        ),
+        (
+            "recipients",
+            &cache_stats.recipients,
+            core.store.recipients.entry_count(),
+        ),
+    ];
+    write_header(
+        &mut buf,
+        "jmap_cache_requests_total",
+        "counter",
+        "Cache lookups.",
+    );
+    for (name, stats, _) in &caches {
+        writeln!(
+            buf,
+            "jmap_cache_requests_total{{cache=\"{}\"}} {}",
+            name,
+            stats.requests.load(Ordering::Relaxed)
+        )
+        .ok();
+    }
+    write_header(&mut buf, "jmap_cache_hits_total", "counter", "Cache hits.");
+    for (name, stats, _) in &caches {
+        writeln!(
+            buf,
+            "jmap_cache_hits_total{{cache=\"{}\"}} {}",
+            name,
+            stats.hits()
+        )
+        .ok();
+    }
+    write_header(
+        &mut buf,
+        "jmap_cache_entries",
+        "gauge",
+        "Entries held in cache.",
+    );
+    for (name, _, entries) in &caches {
+        writeln!(buf, "jmap_cache_entries{{cache=\"{}\"}} {}", name, entries).ok();
+    }
+    writeln!(
+        buf,
+        "jmap_cache_entries{{cache=\"sessions\"}} {}",
+        core.sessions.entry_count()
+    )
+    .ok();
+
+    // Database
+    db_stats.sort_by_key(|(_, name, _)| *name);
+    let mut last_name = "";
+    for (cf, name, value) in db_stats {
+        if name != last_name {
+            write_header(
+                &mut buf,
+                &format!("jmap_db_{}", name),
+                "gauge",
+                "RocksDB column family property.",
+            );
+            last_name = name;
+        }
+        writeln!(buf, "jmap_db_{}{{cf=\"{}\"}} {}", name, cf.as_str(), value).ok();
+    }
+
+    // Raft
+    if let Some(status) = cluster_status {
+        write_header(
+            &mut buf,
+            "jmap_raft_role",
+            "gauge",
+            "Raft role of this node.",
+        );
+        for role in ["leader", "follower", "candidate", "wait"] {
+            writeln!(
+                buf,
+                "jmap_raft_role{{role=\"{}\"}} {}",
+                role,
+                u32::from(role == status.role)
+            )
+            .ok();
+        }
+        write_header(&mut buf, "jmap_raft_term", "gauge", "Current Raft term.");
+        writeln!(buf, "jmap_raft_term {}", status.term).ok();
+        write_header(
+            &mut buf,
+            "jmap_raft_last_log_index",
+            "gauge",
+            "Last committed Raft log index.",
+        );
+        writeln!(
+            buf,
+            "jmap_raft_last_log_index {}",
+            log_index(status.last_log.index)
+        )
+        .ok();
+        write_header(
+            &mut buf,
+            "jmap_raft_commit_index",
+            "gauge",
+            "Raft commit index.",
+        );
+        writeln!(
+            buf,
+            "jmap_raft_commit_index {}",
+            log_index(status.commit_index)
+        )
+        .ok();
+
+        if status.role == "leader" {
+            write_header(
+                &mut buf,
+                "jmap_raft_follower_lag",
+                "gauge",
+                "Log entries not yet committed by each follower.",
+            );
+            let leader_index = log_index(status.uncommitted_index);
+            for peer in status.peers {
+                writeln!(
+                    buf,
+                    "jmap_raft_follower_lag{{peer=\"{}\",healthy=\"{}\"}} {}",
+                    peer.hostname,
+                    peer.is_healthy,
+                    leader_index.saturating_sub(log_index(peer.commit_index))
+                )
+                .ok();
+            }
+        }
+    }
+
+    Ok(HttpResponse::build(StatusCode::OK)
+        .insert_header(("Content-Type", "text/plain; version=0.0.4"))
+        .body(buf))
+}
+
+fn write_header(buf: &mut String, name: &str, metric_type: &str, help: &str) {
+    writeln!(buf, "# HELP {} {}", name, help).ok();
+    writeln!(buf, "# TYPE {} {}", name, metric_type).ok();
+}
+
+#[inline(always)]
+fn log_index(index: LogIndex) -> u64 {
+    // LogIndex::MAX is used to represent an empty log.
+    if index != LogIndex::MAX {
+        index + 1
+    } else {
+        0
+    }
+}
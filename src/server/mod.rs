@@ -21,8 +21,11 @@
  * for more details.
 */
 
+pub mod admin;
 pub mod event_source;
 pub mod http;
+pub mod metrics;
+pub mod reload;
 pub mod websocket;
 
 use crate::services::{email_delivery, housekeeper, state_change};
@@ -40,21 +43,29 @@ impl<T> JMAPServer<T>
 where
     T: for<'x> Store<'x> + 'static,
 {
-    pub async fn get_key<U>(&self, key: &'static str) -> store::Result<Option<U>>
+    pub async fn get_key<U>(
+        &self,
+        key: impl AsRef<str> + Send + 'static,
+    ) -> store::Result<Option<U>>
     where
         U: StoreDeserialize + Send + Sync + 'static,
     {
         let store = self.store.clone();
-        self.spawn_worker(move || store.db.get(ColumnFamily::Values, key.as_bytes()))
+        self.spawn_worker(move || store.db.get(ColumnFamily::Values, key.as_ref().as_bytes()))
             .await
     }
 
-    pub async fn set_key<U>(&self, key: &'static str, value: U) -> store::Result<()>
+    pub async fn set_key<U>(
+        &self,
+        key: impl AsRef<str> + Send + 'static,
+        value: U,
+    ) -> store::Result<()>
     where
         U: StoreSerialize + Send + Sync + 'static,
     {
         let store = self.store.clone();
         self.spawn_worker(move || {
+            let key = key.as_ref();
             store.db.set(
                 ColumnFamily::Values,
                 key.as_bytes(),
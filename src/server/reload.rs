@@ -0,0 +1,321 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::sync::Arc;
+
+use store::{
+    config::env_settings::EnvSettings,
+    parking_lot::Mutex,
+    tracing::{info, warn, Level},
+    Store,
+};
+
+use crate::{
+    cluster::rpc::tls::CertResolver,
+    services::{
+        email_delivery::{self, parse_smtp_settings},
+        push_subscription::PushSettings,
+        state_change,
+    },
+    JMAPServer,
+};
+
+use super::UnwrapFailure;
+
+const LIMIT_KEYS: &[&str] = &[
+    "max-size-upload",
+    "max-size-request",
+    "max-concurrent-uploads",
+    "max-concurrent-requests",
+    "mail-max-size",
+    "mail-attachments-max-size",
+];
+const RATE_LIMIT_KEYS: &[&str] = &[
+    "rate-limit-authenticated",
+    "rate-limit-anonymous",
+    "rate-limit-auth",
+];
+const SMTP_RELAY_KEYS: &[&str] = &[
+    "smtp-relay-host",
+    "smtp-relay-port",
+    "smtp-relay-auth",
+    "smtp-relay-secret",
+    "smtp-relay-tls",
+    "smtp-relay-timeout",
+];
+const PUSH_KEYS: &[&str] = &[
+    "push-attempt-interval",
+    "push-attempts-max",
+    "push-retry-interval",
+    "push-timeout",
+    "push-verify-timeout",
+    "push-throttle",
+];
+const JMAP_TLS_KEYS: &[&str] = &["jmap-cert-path", "jmap-key-path"];
+const LMTP_TLS_KEYS: &[&str] = &["lmtp-cert-path", "lmtp-key-path"];
+const SMTP_TLS_KEYS: &[&str] = &["smtp-submission-cert-path", "smtp-submission-key-path"];
+const IMAP_TLS_KEYS: &[&str] = &["imap-cert-path", "imap-key-path"];
+
+pub type LogLevelReloader = Box<dyn Fn(Level) -> Result<(), String> + Send + Sync>;
+
+pub struct ReloadState {
+    settings: Mutex<EnvSettings>,
+    pub payload_limit: usize,
+    pub jmap_tls: Option<Arc<CertResolver>>,
+    pub lmtp_tls: Option<Arc<CertResolver>>,
+    pub smtp_tls: Option<Arc<CertResolver>>,
+    pub imap_tls: Option<Arc<CertResolver>>,
+    log_level: Mutex<Option<LogLevelReloader>>,
+}
+
+#[derive(Debug, Default, serde::Serialize)]
+pub struct ReloadReport {
+    pub applied: Vec<String>,
+    #[serde(rename(serialize = "restartRequired"))]
+    pub restart_required: Vec<String>,
+}
+
+impl ReloadState {
+    pub fn new(settings: &EnvSettings, payload_limit: usize) -> Self {
+        ReloadState {
+            jmap_tls: settings.get("jmap-cert-path").map(|cert_path| {
+                Arc::new(
+                    CertResolver::new(
+                        &cert_path,
+                        &settings
+                            .get("jmap-key-path")
+                            .failed_to("load TLS config, missing 'jmap-key-path' argument."),
+                    )
+                    .failed_to("load JMAP TLS certificate"),
+                )
+            }),
+            lmtp_tls: if let (Some(cert_path), Some(key_path)) = (
+                settings.get("lmtp-cert-path"),
+                settings.get("lmtp-key-path"),
+            ) {
+                Arc::new(
+                    CertResolver::new(&cert_path, &key_path).failed_to("load LMTP TLS certificate"),
+                )
+                .into()
+            } else {
+                None
+            },
+            smtp_tls: if let (Some(cert_path), Some(key_path)) = (
+                settings.get("smtp-submission-cert-path"),
+                settings.get("smtp-submission-key-path"),
+            ) {
+                Arc::new(
+                    CertResolver::new(&cert_path, &key_path)
+                        .failed_to("load SMTP submission TLS certificate"),
+                )
+                .into()
+            } else {
+                None
+            },
+            imap_tls: if let (Some(cert_path), Some(key_path)) = (
+                settings.get("imap-cert-path"),
+                settings.get("imap-key-path"),
+            ) {
+                Arc::new(
+                    CertResolver::new(&cert_path, &key_path).failed_to("load IMAP TLS certificate"),
+                )
+                .into()
+            } else {
+                None
+            },
+            settings: Mutex::new(settings.clone()),
+            payload_limit,
+            log_level: Mutex::new(None),
+        }
+    }
+
+    pub fn current_settings(&self) -> EnvSettings {
+        self.settings.lock().clone()
+    }
+}
+
+/// Fills in the settings that have computed defaults, so that a reload
+/// compares like with like.
+pub fn set_default_settings(settings: &mut EnvSettings) {
+    if !settings.contains_key("jmap-url") {
+        let jmap_url = if settings.contains_key("jmap-cert-path") {
+            "https://localhost:8080"
+        } else {
+            "http://localhost:8080"
+        }
+        .to_string();
+        warn!(
+            "Warning: Hostname parameter 'jmap-url' was not specified, using '{}'.",
+            jmap_url
+        );
+        settings.set_value("jmap-url".to_string(), jmap_url);
+    }
+}
+
+impl<T> JMAPServer<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub fn set_log_level_reloader(&self, reloader: LogLevelReloader) {
+        *self.reload.log_level.lock() = Some(reloader);
+    }
+
+    /// Applies the settings that can be changed at runtime and reports which
+    /// of the modified settings require a restart. Nothing is applied if any
+    /// of the new values is invalid.
+    pub async fn reload_config(&self, mut settings: EnvSettings) -> Result<ReloadReport, String> {
+        set_default_settings(&mut settings);
+
+        let current = self.reload.settings.lock().clone();
+        let mut changed = current
+            .args
+            .keys()
+            .chain(settings.args.keys())
+            .filter(|key| current.args.get(*key) != settings.args.get(*key))
+            .cloned()
+            .collect::<Vec<_>>();
+        changed.sort_unstable();
+        changed.dedup();
+        let has_changed = |keys: &[&str]| changed.iter().any(|key| keys.contains(&key.as_str()));
+
+        // Validate the new settings before applying any of them
+        let log_level = settings
+            .try_parse::<Level>("log-level")?
+            .unwrap_or(Level::INFO);
+        let push_settings = PushSettings::parse(&settings)?;
+        settings.try_parse::<u16>("smtp-relay-port")?;
+        settings.try_parse::<bool>("smtp-relay-tls")?;
+        settings.try_parse::<u64>("smtp-relay-timeout")?;
+        let jmap_tls = load_tls_paths(&settings, &self.reload.jmap_tls, JMAP_TLS_KEYS)?;
+        let lmtp_tls = load_tls_paths(&settings, &self.reload.lmtp_tls, LMTP_TLS_KEYS)?;
+        let smtp_tls = load_tls_paths(&settings, &self.reload.smtp_tls, SMTP_TLS_KEYS)?;
+        let imap_tls = load_tls_paths(&settings, &self.reload.imap_tls, IMAP_TLS_KEYS)?;
+
+        let config = &self.store.config;
+        let (max_size_upload, max_size_request) =
+            (config.max_size_upload.get(), config.max_size_request.get());
+        config.reload(&settings)?;
+
+        let mut report = ReloadReport::default();
+        let mut applied_keys: Vec<&str> = Vec::new();
+
+        // Request payloads are capped when the HTTP server starts
+        if config.max_size_upload.get() > self.reload.payload_limit
+            || config.max_size_request.get() > self.reload.payload_limit
+        {
+            config.max_size_upload.set(max_size_upload);
+            config.max_size_request.set(max_size_request);
+            applied_keys.extend(
+                LIMIT_KEYS
+                    .iter()
+                    .filter(|key| !["max-size-upload", "max-size-request"].contains(*key)),
+            );
+        } else {
+            applied_keys.extend(LIMIT_KEYS);
+        }
+
+        if has_changed(RATE_LIMIT_KEYS) {
+            self.rate_limiters.invalidate_all();
+        }
+        applied_keys.extend(RATE_LIMIT_KEYS);
+
+        if has_changed(SMTP_RELAY_KEYS) && current.contains_key("smtp-relay-host") {
+            if let Some(smtp_relay) = parse_smtp_settings(&settings) {
+                self.email_delivery
+                    .send(email_delivery::Event::RelaySettings(smtp_relay))
+                    .await
+                    .map_err(|err| format!("Failed to reload SMTP relay: {}", err))?;
+                applied_keys.extend(SMTP_RELAY_KEYS);
+            }
+        }
+
+        if has_changed(PUSH_KEYS) {
+            self.state_change
+                .send(state_change::Event::ReloadPush {
+                    settings: push_settings,
+                })
+                .await
+                .map_err(|err| format!("Failed to reload push settings: {}", err))?;
+        }
+        applied_keys.extend(PUSH_KEYS);
+
+        // Certificates are renewed in place, so they are always reloaded
+        for (resolver, paths, keys) in [
+            (&self.reload.jmap_tls, jmap_tls, JMAP_TLS_KEYS),
+            (&self.reload.lmtp_tls, lmtp_tls, LMTP_TLS_KEYS),
+            (&self.reload.smtp_tls, smtp_tls, SMTP_TLS_KEYS),
+            (&self.reload.imap_tls, imap_tls, IMAP_TLS_KEYS),
+        ] {
+            if let (Some(resolver), Some((cert_path, key_path))) = (resolver, paths) {
+                resolver.reload(&cert_path, &key_path)?;
+                applied_keys.extend(keys);
+            }
+        }
+
+        if has_changed(&["log-level"]) {
+            if let Some(reloader) = self.reload.log_level.lock().as_ref() {
+                reloader(log_level)?;
+                applied_keys.push("log-level");
+            }
+        }
+
+        // Update the snapshot with the settings that were applied
+        let mut snapshot = self.reload.settings.lock();
+        for key in changed {
+            if applied_keys.contains(&key.as_str()) {
+                if let Some(value) = settings.args.get(&key) {
+                    snapshot.args.insert(key.clone(), value.clone());
+                } else {
+                    snapshot.args.remove(&key);
+                }
+                report.applied.push(key);
+            } else {
+                report.restart_required.push(key);
+            }
+        }
+
+        info!(
+            "Configuration reloaded: {} setting(s) applied, {} require a restart.",
+            report.applied.len(),
+            report.restart_required.len()
+        );
+
+        Ok(report)
+    }
+}
+
+fn load_tls_paths(
+    settings: &EnvSettings,
+    resolver: &Option<Arc<CertResolver>>,
+    keys: &[&str],
+) -> Result<Option<(String, String)>, String> {
+    if let (Some(_), Some(cert_path), Some(key_path)) =
+        (resolver, settings.get(keys[0]), settings.get(keys[1]))
+    {
+        // Make sure the certificate loads before anything else is applied
+        CertResolver::new(&cert_path, &key_path)?;
+        Ok(Some((cert_path, key_path)))
+    } else {
+        Ok(None)
+    }
+}
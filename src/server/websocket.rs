@@ -259,7 +259,7 @@ where
                 self.hb = Instant::now();
             }
             Ok(ws::Message::Text(request)) => {
-                let error = if request.len() < self.core.store.config.max_size_request {
+                let error = if request.len() < self.core.store.config.max_size_request.get() {
                     match serde_json::from_slice::<WebSocketMessage>(request.as_bytes()) {
                         Ok(message) => match message {
                             WebSocketMessage::Request(request) => {
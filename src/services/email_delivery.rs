@@ -61,7 +61,13 @@ pub enum Event {
         to: String,
         message: Vec<u8>,
     },
+    SchedulingMessage {
+        from: String,
+        to: String,
+        message: Vec<u8>,
+    },
     RelayReady,
+    RelaySettings(SMTPRelay),
     Reload,
     Start,
     Stop,
@@ -83,6 +89,10 @@ impl Event {
     pub fn vacation_response(from: String, to: String, message: Vec<u8>) -> Self {
         Event::VacationResponse { from, to, message }
     }
+
+    pub fn scheduling_message(from: String, to: String, message: Vec<u8>) -> Self {
+        Event::SchedulingMessage { from, to, message }
+    }
 }
 
 pub fn init_email_delivery() -> (mpsc::Sender<Event>, mpsc::Receiver<Event>) {
@@ -98,8 +108,9 @@ pub fn spawn_email_delivery<T>(
     T: for<'x> Store<'x> + 'static,
 {
     // Parse SMTP relay
-    let relay_tx = if let Some(smtp_relay) = parse_smtp_settings(settings) {
-        spawn_email_relay(core, smtp_relay, tx)
+    let metrics = core.metrics.clone();
+    let mut relay_tx = if let Some(smtp_relay) = parse_smtp_settings(settings) {
+        spawn_email_relay(core.clone(), smtp_relay, tx.clone())
     } else {
         return;
     };
@@ -125,6 +136,11 @@ pub fn spawn_email_delivery<T>(
                     }
                     queue.clear();
                 }
+                Event::RelaySettings(smtp_relay) => {
+                    // Replace the relay, the previous one exits once its
+                    // current delivery completes and the channel is dropped.
+                    relay_tx = spawn_email_relay(core.clone(), smtp_relay, tx.clone());
+                }
                 Event::Start => (),
                 event => {
                     if is_ready {
@@ -136,6 +152,8 @@ pub fn spawn_email_delivery<T>(
                     }
                 }
             }
+
+            metrics.set_delivery_queue(queue.len());
         }
     });
 }
@@ -523,7 +541,8 @@ where
                         }
                     }
                 }
-                Event::VacationResponse { from, to, message } => {
+                Event::VacationResponse { from, to, message }
+                | Event::SchedulingMessage { from, to, message } => {
                     match if is_tls {
                         client.clone().connect_tls().await
                     } else {
@@ -534,7 +553,7 @@ where
                                 .send(Message::empty().from(from).to(to).body(&message))
                                 .await
                             {
-                                debug!("Failed to send vacation response: {}", err);
+                                debug!("Failed to send message: {}", err);
                             }
                             client.quit().await.ok();
                         }
@@ -558,7 +577,7 @@ where
     tx
 }
 
-struct SMTPRelay {
+pub struct SMTPRelay {
     hostname: String,
     port: u16,
     credentials: Option<(String, String)>,
@@ -566,7 +585,7 @@ struct SMTPRelay {
     timeout: Duration,
 }
 
-fn parse_smtp_settings(settings: &EnvSettings) -> Option<SMTPRelay> {
+pub fn parse_smtp_settings(settings: &EnvSettings) -> Option<SMTPRelay> {
     Some(SMTPRelay {
         hostname: settings.get("smtp-relay-host")?,
         port: settings.parse("smtp-relay-port").unwrap_or(0),
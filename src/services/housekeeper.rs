@@ -24,6 +24,8 @@
 use std::time::{Duration, SystemTime};
 
 use actix_web::web;
+use jmap::types::type_state::TypeState;
+use jmap_mail::mailbox::retention::JMAPMailboxRetention;
 use jmap_sharing::principal::set::JMAPSetPrincipal;
 use store::{
     chrono::{self, Datelike, TimeZone},
@@ -39,14 +41,36 @@ use crate::{
     JMAPServer,
 };
 
+use super::{state_change::StateChange, LONG_SLUMBER_MS};
+
 pub enum Event {
     PurgeAccounts,
     PurgeBlobs,
     SnapshotLog,
     CompactDb,
+    Backup { incremental: bool },
+    Retention,
+    Reencrypt,
     Exit,
 }
 
+impl Event {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "purge-accounts" => Event::PurgeAccounts,
+            "purge-blobs" => Event::PurgeBlobs,
+            "snapshot-log" => Event::SnapshotLog,
+            "compact-db" => Event::CompactDb,
+            "backup" => Event::Backup { incremental: true },
+            "backup-full" => Event::Backup { incremental: false },
+            "retention" => Event::Retention,
+            "reencrypt" => Event::Reencrypt,
+            _ => return None,
+        }
+        .into()
+    }
+}
+
 enum SimpleCron {
     EveryDay { hour: u32, minute: u32 },
     EveryWeek { day: u32, hour: u32, minute: u32 },
@@ -56,6 +80,9 @@ const TASK_PURGE_ACCOUNTS: usize = 0;
 const TASK_PURGE_BLOBS: usize = 1;
 const TASK_SNAPSHOT_LOG: usize = 2;
 const TASK_COMPACT_DB: usize = 3;
+const TASK_BACKUP: usize = 4;
+const TASK_RETENTION: usize = 5;
+const TASK_REENCRYPT: usize = 6;
 
 pub fn spawn_housekeeper<T>(
     core: web::Data<JMAPServer<T>>,
@@ -84,8 +111,25 @@ pub fn spawn_housekeeper<T>(
             .get("schedule-compact-db")
             .unwrap_or_else(|| "0 4 *".to_string()),
     );
+    let backup_at = settings
+        .get("schedule-backup")
+        .filter(|_| settings.contains_key("backup-path"))
+        .map(|value| SimpleCron::parse(&value));
+    let retention_at = SimpleCron::parse(
+        &settings
+            .get("schedule-retention")
+            .unwrap_or_else(|| "15 4 *".to_string()),
+    );
     let max_log_entries: u64 = settings.parse("max-changelog-entries").unwrap_or(10000);
 
+    // Unencrypted values and those encrypted with previous keys are
+    // re-encrypted on startup
+    if core.store.needs_reencryption() {
+        if let Err(err) = core.housekeeper.try_send(Event::Reencrypt) {
+            error!("Failed to schedule store re-encryption: {}", err);
+        }
+    }
+
     tokio::spawn(async move {
         debug!("Housekeeper task started.");
         loop {
@@ -94,8 +138,14 @@ pub fn spawn_housekeeper<T>(
                 purge_blobs_at.time_to_next(),
                 snapshot_log_at.time_to_next(),
                 compact_db_at.time_to_next(),
+                backup_at
+                    .as_ref()
+                    .map(|backup_at| backup_at.time_to_next())
+                    .unwrap_or_else(|| Duration::from_millis(LONG_SLUMBER_MS)),
+                retention_at.time_to_next(),
             ];
-            let mut tasks_to_run = [false, false, false, false];
+            let mut tasks_to_run = [false, false, false, false, false, false, false];
+            let mut incremental_backup = true;
             let start_time = SystemTime::now()
                 .duration_since(SystemTime::UNIX_EPOCH)
                 .map(|d| d.as_secs())
@@ -108,6 +158,12 @@ pub fn spawn_housekeeper<T>(
                     Event::PurgeBlobs => tasks_to_run[TASK_PURGE_BLOBS] = true,
                     Event::SnapshotLog => tasks_to_run[TASK_SNAPSHOT_LOG] = true,
                     Event::CompactDb => tasks_to_run[TASK_COMPACT_DB] = true,
+                    Event::Backup { incremental } => {
+                        tasks_to_run[TASK_BACKUP] = true;
+                        incremental_backup = incremental;
+                    }
+                    Event::Retention => tasks_to_run[TASK_RETENTION] = true,
+                    Event::Reencrypt => tasks_to_run[TASK_REENCRYPT] = true,
                     Event::Exit => {
                         debug!("Housekeeper task exiting.");
                         return;
@@ -126,7 +182,9 @@ pub fn spawn_housekeeper<T>(
                 .map(|d| d.as_secs())
                 .unwrap_or(0);
             for (pos, time_to_next) in time_to_next.into_iter().enumerate() {
-                if start_time + time_to_next.as_secs() <= now {
+                if start_time + time_to_next.as_secs() <= now
+                    && (pos != TASK_BACKUP || backup_at.is_some())
+                {
                     tasks_to_run[pos] = true;
                 }
             }
@@ -160,6 +218,86 @@ pub fn spawn_housekeeper<T>(
                             core.spawn_worker(move || store.db.compact(ColumnFamily::Bitmaps))
                                 .await
                         }
+                        TASK_BACKUP => {
+                            info!(
+                                "Starting {} backup.",
+                                if incremental_backup {
+                                    "incremental"
+                                } else {
+                                    "full"
+                                }
+                            );
+                            core.spawn_worker(move || {
+                                match store.backup(incremental_backup)? {
+                                    Some(backup) => info!(
+                                        "Backup {} completed ({} files copied, {} files linked).",
+                                        backup.name,
+                                        backup.manifest.files_copied,
+                                        backup.manifest.files_linked
+                                    ),
+                                    None => info!("No changes since last backup, skipping."),
+                                }
+                                Ok(())
+                            })
+                            .await
+                        }
+                        TASK_RETENTION if core.is_leader() => {
+                            info!("Applying mailbox retention policies.");
+                            match core
+                                .spawn_worker(move || store.mailbox_retention_purge())
+                                .await
+                            {
+                                Ok(changes) => {
+                                    // Wait for the changes to be committed before notifying
+                                    if let Some(change_id) =
+                                        changes.iter().map(|(_, changes)| changes.change_id).max()
+                                    {
+                                        if core.is_in_cluster()
+                                            && !core.commit_index(change_id).await
+                                        {
+                                            error!("Failed to commit mailbox retention changes.");
+                                        }
+                                    }
+
+                                    for (account_id, changes) in changes {
+                                        let change_id = changes.change_id;
+                                        let types = changes
+                                            .collections
+                                            .into_iter()
+                                            .filter_map(|c| {
+                                                Some((TypeState::try_from(c).ok()?, change_id))
+                                            })
+                                            .collect::<Vec<_>>();
+                                        if let Err(err) = core
+                                            .publish_state_change(StateChange::new(
+                                                account_id, types,
+                                            ))
+                                            .await
+                                        {
+                                            error!("Failed to publish state change: {}", err);
+                                        }
+                                    }
+                                    Ok(())
+                                }
+                                Err(err) => Err(err),
+                            }
+                        }
+                        TASK_RETENTION => {
+                            debug!("Skipping mailbox retention, not the cluster leader.");
+                            Ok(())
+                        }
+                        TASK_REENCRYPT => {
+                            info!("Re-encrypting store with the current encryption key.");
+                            core.spawn_worker(move || {
+                                let (values, blobs) = store.reencrypt()?;
+                                info!(
+                                    "Re-encryption completed ({} values, {} blob files).",
+                                    values, blobs
+                                );
+                                Ok(())
+                            })
+                            .await
+                        }
                         _ => unreachable!(),
                     };
 
@@ -22,7 +22,9 @@
 */
 
 use super::{push_subscription_ece::ece_encrypt, state_change::StateChange, LONG_SLUMBER_MS};
-use crate::{api::StateChangeResponse, cluster::IPC_CHANNEL_BUFFER, JMAPServer};
+use crate::{
+    api::StateChangeResponse, cluster::IPC_CHANNEL_BUFFER, server::metrics::Metrics, JMAPServer,
+};
 use jmap::{
     base64,
     orm::serialize::JMAPOrm,
@@ -32,11 +34,12 @@ use jmap::{
 use reqwest::header::{CONTENT_ENCODING, CONTENT_TYPE};
 use std::{
     collections::hash_map::Entry,
+    sync::Arc,
     time::{Duration, Instant, SystemTime},
 };
 use store::{
     ahash::{AHashMap, AHashSet},
-    config::env_settings::EnvSettings,
+    config::env_settings::{soft_panic, EnvSettings},
     core::{bitmap::Bitmap, collection::Collection, error::StoreError},
     tracing::debug,
     AccountId, DocumentId, Store,
@@ -85,9 +88,22 @@ pub enum Event {
         id: store::JMAPId,
         state_changes: Vec<StateChange>,
     },
+    Reload {
+        settings: PushSettings,
+    },
     Reset,
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct PushSettings {
+    pub attempt_interval: u64,
+    pub attempts_max: u32,
+    pub retry_interval: u64,
+    pub timeout: u64,
+    pub verify_timeout: u64,
+    pub throttle: u64,
+}
+
 #[derive(Debug)]
 pub enum PushUpdate {
     Verify {
@@ -117,16 +133,18 @@ pub struct PushServer {
     in_flight: bool,
 }
 
-pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
+pub fn spawn_push_manager(settings: &EnvSettings, metrics: Arc<Metrics>) -> mpsc::Sender<Event> {
     let (push_tx_, mut push_rx) = mpsc::channel::<Event>(IPC_CHANNEL_BUFFER);
     let push_tx = push_tx_.clone();
 
-    let push_attempt_interval: u64 = settings.parse("push-attempt-interval").unwrap_or(60 * 1000);
-    let push_attempts_max: u32 = settings.parse("push-attempts-max").unwrap_or(3);
-    let push_retry_interval: u64 = settings.parse("push-retry-interval").unwrap_or(1000);
-    let push_timeout: u64 = settings.parse("push-timeout").unwrap_or(10 * 1000);
-    let push_verify_timeout: u64 = settings.parse("push-verify-timeout").unwrap_or(60 * 1000);
-    let push_throttle: u64 = settings.parse("push-throttle").unwrap_or(1000);
+    let PushSettings {
+        attempt_interval: mut push_attempt_interval,
+        attempts_max: mut push_attempts_max,
+        retry_interval: mut push_retry_interval,
+        timeout: mut push_timeout,
+        verify_timeout: mut push_verify_timeout,
+        throttle: mut push_throttle,
+    } = PushSettings::parse(settings).unwrap_or_else(|err| soft_panic(&err));
 
     tokio::spawn(async move {
         let mut subscriptions = AHashMap::default();
@@ -241,6 +259,14 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
                             }
                         }
                     }
+                    Event::Reload { settings } => {
+                        push_attempt_interval = settings.attempt_interval;
+                        push_attempts_max = settings.attempts_max;
+                        push_retry_interval = settings.retry_interval;
+                        push_timeout = settings.timeout;
+                        push_verify_timeout = settings.verify_timeout;
+                        push_throttle = settings.throttle;
+                    }
                     Event::Reset => {
                         subscriptions.clear();
                     }
@@ -320,12 +346,31 @@ pub fn spawn_push_manager(settings: &EnvSettings) -> mpsc::Sender<Event> {
             } else {
                 Duration::from_millis(LONG_SLUMBER_MS)
             };
+
+            metrics.set_push_queue(retry_ids.len());
         }
     });
 
     push_tx_
 }
 
+impl PushSettings {
+    pub fn parse(settings: &EnvSettings) -> Result<Self, String> {
+        Ok(PushSettings {
+            attempt_interval: settings
+                .try_parse("push-attempt-interval")?
+                .unwrap_or(60 * 1000),
+            attempts_max: settings.try_parse("push-attempts-max")?.unwrap_or(3),
+            retry_interval: settings.try_parse("push-retry-interval")?.unwrap_or(1000),
+            timeout: settings.try_parse("push-timeout")?.unwrap_or(10 * 1000),
+            verify_timeout: settings
+                .try_parse("push-verify-timeout")?
+                .unwrap_or(60 * 1000),
+            throttle: settings.try_parse("push-throttle")?.unwrap_or(1000),
+        })
+    }
+}
+
 impl PushServer {
     fn send(&mut self, id: store::JMAPId, push_tx: mpsc::Sender<Event>, push_timeout: u64) {
         let url = self.url.clone();
@@ -38,7 +38,7 @@ use tokio::sync::mpsc;
 
 use crate::{cluster::IPC_CHANNEL_BUFFER, JMAPServer};
 
-use super::push_subscription::{spawn_push_manager, UpdateSubscription};
+use super::push_subscription::{spawn_push_manager, PushSettings, UpdateSubscription};
 
 #[derive(Debug)]
 pub enum Event {
@@ -60,6 +60,9 @@ pub enum Event {
         account_id: AccountId,
         subscriptions: Vec<UpdateSubscription>,
     },
+    ReloadPush {
+        settings: PushSettings,
+    },
 }
 
 #[derive(Clone, Debug)]
@@ -110,7 +113,7 @@ pub fn spawn_state_manager<T>(
 ) where
     T: for<'x> Store<'x> + 'static,
 {
-    let push_tx = spawn_push_manager(settings);
+    let push_tx = spawn_push_manager(settings, core.metrics.clone());
 
     tokio::spawn(async move {
         let mut subscribers: AHashMap<AccountId, AHashMap<DocumentId, Subscriber>> =
@@ -385,6 +388,14 @@ pub fn spawn_state_manager<T>(
                         }
                     }
                 }
+                Event::ReloadPush { settings } => {
+                    if let Err(err) = push_tx
+                        .send(super::push_subscription::Event::Reload { settings })
+                        .await
+                    {
+                        debug!("Error sending push reload: {}", err);
+                    }
+                }
                 _ => {
                     debug!("Ignoring state event {:?}", event);
                 }
@@ -467,6 +478,18 @@ where
         Ok(())
     }
 
+    pub async fn update_shared_accounts(&self, account_id: AccountId) -> jmap::Result<()> {
+        if let Err(err) = self
+            .state_change
+            .clone()
+            .send(Event::UpdateSharedAccounts { account_id })
+            .await
+        {
+            error!("Channel failure while updating shared accounts: {}", err);
+        }
+        Ok(())
+    }
+
     pub async fn update_push_subscriptions(&self, account_id: AccountId) -> jmap::Result<()> {
         let state_tx = self.state_change.clone();
         for event in [
@@ -0,0 +1,321 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::base64;
+use jmap_sharing::principal::account::JMAPAccountStore;
+use store::{
+    core::error::StoreError,
+    tracing::{debug, error},
+    Store,
+};
+
+use crate::{
+    authorization::{self, auth::RemoteAddress},
+    lmtp::request::State,
+    JMAPServer,
+};
+
+use super::session::Session;
+
+pub const MECHANISMS: &str = "PLAIN LOGIN XOAUTH2";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mechanism {
+    Plain,
+    Login { username: Option<String> },
+    XOAuth2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Credentials {
+    Plain { username: String, secret: String },
+    OAuthBearer { token: String },
+}
+
+impl<T> Session<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub async fn handle_auth(
+        &mut self,
+        mechanism: String,
+        initial_response: Option<String>,
+    ) -> Result<(), ()> {
+        if self.account.is_some() {
+            return self
+                .write_bytes(b"503 5.5.1 Already authenticated.\r\n")
+                .await;
+        } else if self.mail_from.is_some() {
+            return self
+                .write_bytes(b"503 5.5.1 AUTH not permitted during a mail transaction.\r\n")
+                .await;
+        } else if !self.stream.is_tls() && !self.allow_plain_auth {
+            return self
+                .write_bytes(
+                    b"538 5.7.11 Encryption required for requested authentication mechanism.\r\n",
+                )
+                .await;
+        }
+
+        let mechanism = match mechanism.as_str() {
+            "plain" => Mechanism::Plain,
+            "login" => Mechanism::Login { username: None },
+            "xoauth2" => Mechanism::XOAuth2,
+            _ => {
+                return self
+                    .write_bytes(b"504 5.5.4 Mechanism not supported.\r\n")
+                    .await;
+            }
+        };
+
+        if let Some(initial_response) = initial_response {
+            self.handle_sasl_data(mechanism, initial_response).await
+        } else {
+            self.write_bytes(if matches!(mechanism, Mechanism::Login { .. }) {
+                &b"334 VXNlcm5hbWU6\r\n"[..]
+            } else {
+                &b"334 \r\n"[..]
+            })
+            .await?;
+            self.sasl = mechanism.into();
+            self.parser.state = State::SaslResponse;
+            Ok(())
+        }
+    }
+
+    pub async fn handle_sasl_response(&mut self, data: String) -> Result<(), ()> {
+        if let Some(mechanism) = self.sasl.take() {
+            if data != "*" {
+                self.handle_sasl_data(mechanism, data).await
+            } else {
+                self.write_bytes(b"501 5.0.0 Authentication cancelled.\r\n")
+                    .await
+            }
+        } else {
+            self.write_bytes(b"503 5.5.1 No authentication in progress.\r\n")
+                .await
+        }
+    }
+
+    async fn handle_sasl_data(&mut self, mechanism: Mechanism, data: String) -> Result<(), ()> {
+        // A single '=' stands for an empty response
+        let data = if data == "=" {
+            Vec::new()
+        } else if let Ok(data) = base64::decode(data.as_bytes()) {
+            data
+        } else {
+            return self
+                .write_bytes(b"501 5.5.2 Invalid base64 data.\r\n")
+                .await;
+        };
+
+        let credentials = match mechanism {
+            Mechanism::Plain => decode_plain(&data),
+            Mechanism::XOAuth2 => decode_xoauth2(&data),
+            Mechanism::Login { username: None } => {
+                if let Ok(username) = String::from_utf8(data) {
+                    self.write_bytes(b"334 UGFzc3dvcmQ6\r\n").await?;
+                    self.sasl = Mechanism::Login {
+                        username: username.into(),
+                    }
+                    .into();
+                    self.parser.state = State::SaslResponse;
+                    return Ok(());
+                } else {
+                    None
+                }
+            }
+            Mechanism::Login {
+                username: Some(username),
+            } => String::from_utf8(data)
+                .ok()
+                .map(|secret| Credentials::Plain {
+                    username: username.trim().to_lowercase(),
+                    secret,
+                }),
+        };
+
+        if let Some(credentials) = credentials {
+            self.authenticate(credentials).await
+        } else {
+            self.write_bytes(b"501 5.5.2 Invalid authentication data.\r\n")
+                .await
+        }
+    }
+
+    async fn authenticate(&mut self, credentials: Credentials) -> Result<(), ()> {
+        // Enforce rate limit for authentication requests
+        if self
+            .core
+            .is_auth_allowed(RemoteAddress::IpAddress(self.peer_addr.ip()))
+            .await
+            .is_err()
+        {
+            return self
+                .write_bytes(b"454 4.7.0 Too many authentication attempts, try again later.\r\n")
+                .await;
+        }
+
+        match self.core.authenticate_credentials(credentials).await {
+            Ok(Some(account)) => {
+                self.account = account.into();
+                self.write_bytes(b"235 2.7.0 Authentication succeeded.\r\n")
+                    .await
+            }
+            Ok(None) => {
+                self.write_bytes(b"535 5.7.8 Authentication credentials invalid.\r\n")
+                    .await
+            }
+            Err(err) => {
+                error!("Store error during authentication: {}", err);
+                self.write_bytes(b"454 4.7.0 Temporary authentication failure.\r\n")
+                    .await
+            }
+        }
+    }
+}
+
+impl<T> JMAPServer<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    // Resolves SASL credentials to an account, shared by the SMTP and IMAP listeners.
+    pub async fn authenticate_credentials(
+        &self,
+        credentials: Credentials,
+    ) -> store::Result<Option<authorization::Session>> {
+        let account_id = match credentials {
+            Credentials::Plain { username, secret } => {
+                let store = self.store.clone();
+                self.spawn_worker(move || store.authenticate(&username, &secret))
+                    .await
+            }
+            Credentials::OAuthBearer { token } => {
+                match self.validate_access_token("access_token", &token).await {
+                    Ok((account_id, _, _)) => Ok(Some(account_id)),
+                    Err(StoreError::DeserializeError(e)) => {
+                        debug!("Failed to deserialize access token: {}", e);
+                        Ok(None)
+                    }
+                    Err(err) => Err(err),
+                }
+            }
+        };
+
+        match account_id {
+            Ok(Some(account_id)) => {
+                let store = self.store.clone();
+                self.spawn_worker(move || {
+                    Ok(authorization::Session::new(
+                        account_id,
+                        store.get_acl_token(account_id)?.as_ref(),
+                    ))
+                })
+                .await
+                .map(Some)
+            }
+            result => result.map(|_| None),
+        }
+    }
+}
+
+// Decodes a PLAIN response (RFC 4616). Authorizing as a different
+// identity than the one authenticated is not supported.
+pub fn decode_plain(data: &[u8]) -> Option<Credentials> {
+    let mut parts = data.split(|&ch| ch == 0);
+    let authz_id = parts.next()?;
+    let username = std::str::from_utf8(parts.next()?).ok()?;
+    let secret = std::str::from_utf8(parts.next()?).ok()?;
+
+    if parts.next().is_none()
+        && !username.is_empty()
+        && (authz_id.is_empty() || authz_id == username.as_bytes())
+    {
+        Some(Credentials::Plain {
+            username: username.trim().to_lowercase(),
+            secret: secret.to_string(),
+        })
+    } else {
+        None
+    }
+}
+
+// Decodes a XOAUTH2 response, the user name is ignored as the access
+// token already identifies the account.
+pub fn decode_xoauth2(data: &[u8]) -> Option<Credentials> {
+    std::str::from_utf8(data)
+        .ok()?
+        .split('\x01')
+        .find_map(|part| part.strip_prefix("auth="))
+        .and_then(|auth| auth.split_once(' '))
+        .and_then(|(scheme, token)| {
+            if scheme.eq_ignore_ascii_case("bearer") && !token.trim().is_empty() {
+                Some(Credentials::OAuthBearer {
+                    token: token.trim().to_string(),
+                })
+            } else {
+                None
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode_plain, decode_xoauth2, Credentials};
+
+    #[test]
+    fn sasl_decode() {
+        assert_eq!(
+            decode_plain(b"\0John@Example.org\0secret"),
+            Some(Credentials::Plain {
+                username: "john@example.org".to_string(),
+                secret: "secret".to_string()
+            })
+        );
+        assert_eq!(
+            decode_plain(b"john@example.org\0john@example.org\0secret"),
+            Some(Credentials::Plain {
+                username: "john@example.org".to_string(),
+                secret: "secret".to_string()
+            })
+        );
+        assert_eq!(
+            decode_plain(b"jane@example.org\0john@example.org\0secret"),
+            None
+        );
+        assert_eq!(decode_plain(b"\0john@example.org"), None);
+        assert_eq!(decode_plain(b"\0\0secret"), None);
+
+        assert_eq!(
+            decode_xoauth2(b"user=john@example.org\x01auth=Bearer abc123\x01\x01"),
+            Some(Credentials::OAuthBearer {
+                token: "abc123".to_string()
+            })
+        );
+        assert_eq!(
+            decode_xoauth2(b"user=john@example.org\x01auth=Basic abc123\x01\x01"),
+            None
+        );
+        assert_eq!(decode_xoauth2(b"user=john@example.org\x01\x01"), None);
+    }
+}
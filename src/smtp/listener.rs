@@ -0,0 +1,210 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use actix_web::web;
+use store::{
+    config::env_settings::EnvSettings,
+    tracing::{debug, error, info},
+    Store,
+};
+use tokio::{net::TcpListener, sync::watch};
+use tokio_rustls::TlsAcceptor;
+
+use crate::{
+    cluster::rpc::tls::load_tls_server_config_with_resolver, lmtp::session::Stream,
+    server::failed_to, smtp::session::Session, JMAPServer,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5 * 60); // 5 minutes
+const DEFAULT_MAX_RCPT: usize = 100;
+
+pub fn spawn_smtp<T>(
+    core: web::Data<JMAPServer<T>>,
+    settings: &EnvSettings,
+    shutdown_rx: watch::Receiver<bool>,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    // The submission service is only started when a port is configured
+    let bind_ip = settings.parse_ipaddr("smtp-submission-bind-addr", "0.0.0.0");
+    let mut listeners = Vec::new();
+    if let Some(port) = settings.parse::<u16>("smtp-submission-port") {
+        listeners.push((SocketAddr::from((bind_ip, port)), false));
+    }
+    if let Some(port) = settings.parse::<u16>("smtp-submission-tls-port") {
+        listeners.push((SocketAddr::from((bind_ip, port)), true));
+    }
+    if listeners.is_empty() {
+        return;
+    }
+
+    // Build TLS acceptor
+    let tls_acceptor = core.reload.smtp_tls.clone().map(|resolver| {
+        Arc::new(TlsAcceptor::from(Arc::new(
+            load_tls_server_config_with_resolver(resolver),
+        )))
+    });
+    if tls_acceptor.is_none() && listeners.iter().any(|(_, implicit_tls)| *implicit_tls) {
+        failed_to("parse 'smtp-submission-tls-port', no TLS certificate was provided.");
+    }
+    let allow_plain_auth = settings
+        .parse("smtp-submission-plaintext-auth")
+        .unwrap_or(false);
+    let max_rcpt = settings
+        .parse("smtp-submission-max-rcpt")
+        .unwrap_or(DEFAULT_MAX_RCPT);
+
+    let hostname = Arc::new(
+        gethostname::gethostname()
+            .to_str()
+            .unwrap_or("localhost")
+            .to_string(),
+    );
+    let greeting = Arc::new(
+        format!(
+            concat!(
+                "220 {} Stalwart ESMTP v",
+                env!("CARGO_PKG_VERSION"),
+                " at your service.\r\n"
+            ),
+            &hostname
+        )
+        .into_bytes(),
+    );
+
+    for (bind_addr, implicit_tls) in listeners {
+        info!("Starting SMTP submission service at {}...", bind_addr);
+
+        let core = core.clone();
+        let tls_acceptor = tls_acceptor.clone();
+        let hostname = hostname.clone();
+        let greeting = greeting.clone();
+        let mut shutdown_rx = shutdown_rx.clone();
+
+        tokio::spawn(async move {
+            // Start listening for SMTP connections.
+            let listener = match TcpListener::bind(bind_addr).await {
+                Ok(listener) => listener,
+                Err(err) => {
+                    error!(
+                        "Failed to bind SMTP submission service to {}: {}",
+                        bind_addr, err
+                    );
+                    return;
+                }
+            };
+
+            loop {
+                tokio::select! {
+                    stream = listener.accept() => {
+                        match stream {
+                            Ok((stream, peer_addr)) => {
+                                let shutdown_rx = shutdown_rx.clone();
+                                let core = core.clone();
+                                let greeting = greeting.clone();
+                                let tls_acceptor = tls_acceptor.clone();
+                                let hostname = hostname.clone();
+
+                                tokio::spawn(async move {
+                                    let (mut stream, tls_acceptor) = if implicit_tls {
+                                        match tls_acceptor.as_ref().unwrap().accept(stream).await {
+                                            Ok(stream) => (Stream::from(stream), None),
+                                            Err(e) => {
+                                                debug!("Failed to accept TLS connection: {}", e);
+                                                return;
+                                            }
+                                        }
+                                    } else {
+                                        (Stream::from(stream), tls_acceptor)
+                                    };
+
+                                    // Send greeting
+                                    if stream.write_bytes(&greeting).await.is_err() {
+                                        debug!("Failed to send greeting to {}.", peer_addr);
+                                        return;
+                                    }
+
+                                    handle_conn(
+                                        Session::new(core, peer_addr, stream, tls_acceptor, hostname, allow_plain_auth, max_rcpt),
+                                        shutdown_rx
+                                    ).await;
+                                });
+                            }
+                            Err(err) => {
+                                error!("Failed to accept TCP connection: {}", err);
+                            }
+                        }
+                    },
+                    _ = shutdown_rx.changed() => {
+                        debug!("SMTP submission listener shutting down.");
+                        break;
+                    }
+                };
+            }
+        });
+    }
+}
+
+pub async fn handle_conn<T>(mut session: Session<T>, mut shutdown_rx: watch::Receiver<bool>)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut buf = vec![0; 4096];
+
+    loop {
+        tokio::select! {
+            result = tokio::time::timeout(
+                TIMEOUT,
+                session.read_bytes(&mut buf)) => {
+                match result {
+                    Ok(Ok(bytes_read)) => {
+                        if bytes_read > 0 {
+                            if session.ingest(&buf[..bytes_read]).await.is_err() {
+                                debug!("Disconnecting client.");
+                                return;
+                            }
+                        } else {
+                            debug!("SMTP connection closed by {}", session.peer_addr);
+                            break;
+                        }
+                    },
+                    Ok(Err(_)) => {
+                        break;
+                    },
+                    Err(_) => {
+                        session.write_bytes(b"221 2.0.0 Disconnecting inactive client.\r\n").await.ok();
+                        debug!("SMTP connection timed out with {}.", session.peer_addr);
+                        break;
+                    }
+                }
+            },
+            _ = shutdown_rx.changed() => {
+                session.write_bytes(b"421 4.3.0 Server shutting down.\r\n").await.ok();
+                debug!("SMTP connection with peer {} shutting down.", session.peer_addr);
+                return;
+            }
+        };
+    }
+}
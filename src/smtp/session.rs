@@ -0,0 +1,392 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{net::SocketAddr, sync::Arc};
+
+use actix_web::web;
+use jmap::sanitize_email;
+use jmap_mail::identity::get::JMAPGetIdentity;
+use store::{
+    chrono::Local,
+    tracing::{debug, error},
+    DocumentId, Store,
+};
+use tokio_rustls::TlsAcceptor;
+
+use crate::{
+    authorization,
+    lmtp::{
+        request::{Event, Param, Request, RequestParser},
+        response::{Extension, Response},
+        session::Stream,
+    },
+    JMAPServer,
+};
+
+use super::auth::{Mechanism, MECHANISMS};
+
+const MAX_COMMAND_LENGTH: usize = 1024;
+
+pub struct Session<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub core: web::Data<JMAPServer<T>>,
+    pub tls_acceptor: Option<Arc<TlsAcceptor>>,
+    pub hostname: Arc<String>,
+    pub parser: RequestParser,
+    pub peer_addr: SocketAddr,
+    pub stream: Stream,
+    pub allow_plain_auth: bool,
+    pub max_rcpt: usize,
+
+    // State
+    pub remote_hostname: Option<String>,
+    pub sasl: Option<Mechanism>,
+    pub account: Option<authorization::Session>,
+    pub mail_from: Option<String>,
+    pub identity_id: Option<DocumentId>,
+    pub mail_size: Option<usize>,
+    pub rcpt_to: Vec<String>,
+    pub message: Vec<u8>,
+}
+
+impl<T> Session<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub fn new(
+        core: web::Data<JMAPServer<T>>,
+        peer_addr: SocketAddr,
+        stream: Stream,
+        tls_acceptor: Option<Arc<TlsAcceptor>>,
+        hostname: Arc<String>,
+        allow_plain_auth: bool,
+        max_rcpt: usize,
+    ) -> Self {
+        Self {
+            parser: RequestParser::new(MAX_COMMAND_LENGTH, core.store.config.mail_max_size.get()),
+            tls_acceptor,
+            peer_addr,
+            stream,
+            core,
+            hostname,
+            allow_plain_auth,
+            max_rcpt,
+            remote_hostname: None,
+            sasl: None,
+            account: None,
+            mail_from: None,
+            identity_id: None,
+            mail_size: None,
+            rcpt_to: Vec::new(),
+            message: Vec::new(),
+        }
+    }
+
+    pub async fn ingest(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        let mut bytes = bytes.iter();
+
+        loop {
+            match self.parser.parse(&mut bytes) {
+                Ok(request) => match request {
+                    Request::Ehlo { domain } => {
+                        let mut extensions = vec![
+                            Extension::EnhancedStatusCodes,
+                            Extension::Pipelining,
+                            Extension::Chunking,
+                            Extension::EightBitMime,
+                            Extension::BinaryMime,
+                            Extension::SmtpUtf8,
+                            Extension::Help,
+                            Extension::Size(self.core.store.config.mail_max_size.get() as u32),
+                        ];
+                        if !self.stream.is_tls() && self.tls_acceptor.is_some() {
+                            extensions.push(Extension::StartTls);
+                        }
+                        if self.account.is_none() && (self.stream.is_tls() || self.allow_plain_auth)
+                        {
+                            extensions.push(Extension::Auth(MECHANISMS));
+                        }
+                        self.write_bytes(
+                            &Response::Lhlo {
+                                local_host: self.hostname.as_ref().into(),
+                                remote_host: domain.as_str().into(),
+                                extensions,
+                            }
+                            .into_bytes(),
+                        )
+                        .await?;
+                        self.remote_hostname = domain.into();
+                        self.reset();
+                    }
+                    Request::Helo { domain } => {
+                        self.write_bytes(format!("250 {}\r\n", self.hostname).as_bytes())
+                            .await?;
+                        self.remote_hostname = domain.into();
+                        self.reset();
+                    }
+                    Request::Auth {
+                        mechanism,
+                        initial_response,
+                    } => {
+                        self.handle_auth(mechanism, initial_response).await?;
+                    }
+                    Request::SaslResponse { data } => {
+                        self.handle_sasl_response(data).await?;
+                    }
+                    Request::Mail { sender, params } => {
+                        if let Some(account_id) = self.account.as_ref().map(|a| a.account_id()) {
+                            if self.mail_from.is_some() {
+                                self.write_bytes(b"503 5.5.1 Sender already specified.\r\n")
+                                    .await?;
+                                continue;
+                            }
+
+                            // Make sure the sender is one of the account's identities
+                            let store = self.core.store.clone();
+                            let sender_ = sender.clone();
+                            match self
+                                .core
+                                .spawn_worker(move || store.identity_find(account_id, &sender_))
+                                .await
+                            {
+                                Ok(Some(identity_id)) => {
+                                    self.write_bytes(
+                                        format!("250 2.1.0 Sender <{}> accepted.\r\n", sender)
+                                            .as_bytes(),
+                                    )
+                                    .await?;
+                                    self.mail_from = sender.into();
+                                    self.identity_id = identity_id.into();
+                                    self.mail_size = params.iter().find_map(|p| {
+                                        if let Param::Size(size) = p {
+                                            Some(*size as usize)
+                                        } else {
+                                            None
+                                        }
+                                    });
+                                }
+                                Ok(None) => {
+                                    self.write_bytes(
+                                        format!(
+                                            "550 5.7.1 Sender <{}> is not one of your identities.\r\n",
+                                            sender
+                                        )
+                                        .as_bytes(),
+                                    )
+                                    .await?;
+                                }
+                                Err(err) => {
+                                    error!("Failed to obtain identities: {}", err);
+                                    self.write_bytes(b"451 4.3.0 Temporary server failure.\r\n")
+                                        .await?;
+                                }
+                            }
+                        } else {
+                            self.write_bytes(b"530 5.7.0 Authentication required.\r\n")
+                                .await?;
+                        }
+                    }
+                    Request::Rcpt { recipient, .. } => {
+                        if self.mail_from.is_none() {
+                            self.write_bytes(b"503 5.5.1 Missing MAIL FROM.\r\n")
+                                .await?;
+                        } else if self.rcpt_to.len() >= self.max_rcpt {
+                            self.write_bytes(b"452 4.5.3 Too many recipients.\r\n")
+                                .await?;
+                        } else if let Some(recipient) = sanitize_email(&recipient) {
+                            self.write_bytes(
+                                format!("250 2.1.5 Recipient <{}> accepted.\r\n", recipient)
+                                    .as_bytes(),
+                            )
+                            .await?;
+                            if !self.rcpt_to.contains(&recipient) {
+                                self.rcpt_to.push(recipient);
+                            }
+                        } else {
+                            self.write_bytes(b"501 5.1.3 Invalid recipient address.\r\n")
+                                .await?;
+                        }
+                    }
+                    Request::Data { data } => {
+                        self.message = data;
+                        self.submit_message().await?;
+                    }
+                    Request::Bdat { data, is_last } => {
+                        // Chunks are only buffered once a transaction has been started
+                        if self.rcpt_to.is_empty() {
+                            self.write_bytes(b"503 5.5.1 Missing RCPT TO.\r\n").await?;
+                        } else if self.message.len() + data.len()
+                            < self.core.store.config.mail_max_size.get()
+                        {
+                            if self.message.is_empty() {
+                                let received = self.build_received();
+                                self.message = Vec::with_capacity(
+                                    self.mail_size
+                                        .unwrap_or_else(|| std::cmp::max(1024, data.len()))
+                                        + received.len(),
+                                );
+                                self.message.extend_from_slice(received.as_bytes());
+                            }
+                            self.message.extend_from_slice(&data);
+                            if is_last {
+                                self.submit_message().await?;
+                            } else {
+                                self.write_bytes(b"250 2.1.0 Message chunk accepted.\r\n")
+                                    .await?;
+                            }
+                        } else {
+                            self.write_bytes(
+                                format!(
+                                    "500 5.3.4 Message exceeds maximum size of {} bytes.\r\n",
+                                    self.core.store.config.mail_max_size.get()
+                                )
+                                .as_bytes(),
+                            )
+                            .await?;
+                        }
+                    }
+                    Request::Help { .. } => {
+                        self.write_bytes(
+                            b"250 2.0.0 Help can be found at https://stalw.art/jmap/\r\n",
+                        )
+                        .await?;
+                    }
+                    Request::StartTls => match (&self.stream, &self.tls_acceptor) {
+                        (Stream::Clear(_), Some(_)) => {
+                            self.write_bytes(b"220 2.0.0 Ready to start TLS\r\n")
+                                .await?;
+                            match self
+                                .tls_acceptor
+                                .as_ref()
+                                .unwrap()
+                                .accept(std::mem::take(&mut self.stream).unwrap_clear())
+                                .await
+                            {
+                                Ok(stream) => {
+                                    // Discard any knowledge obtained from the client
+                                    self.stream = stream.into();
+                                    self.remote_hostname = None;
+                                    self.reset();
+                                }
+                                Err(e) => {
+                                    debug!("Failed to accept TLS connection: {}", e);
+                                    return Err(());
+                                }
+                            };
+                        }
+                        (Stream::Clear(_), None) => {
+                            self.write_bytes(b"501 5.7.4 TLS not configured on this server.\r\n")
+                                .await?;
+                        }
+                        (Stream::Tls(_), _) => {
+                            self.write_bytes(b"501 5.7.0 Already in TLS mode.\r\n")
+                                .await?;
+                        }
+                        (_, _) => {
+                            unreachable!()
+                        }
+                    },
+                    Request::Rset => {
+                        self.reset();
+                        self.write_bytes(b"250 2.0.0 OK\r\n").await?;
+                    }
+                    Request::Lhlo { .. }
+                    | Request::Vrfy { .. }
+                    | Request::Expn { .. }
+                    | Request::Xclient { .. }
+                    | Request::Xforward { .. } => {
+                        self.write_bytes(b"502 5.5.1 Command not implemented.\r\n")
+                            .await?;
+                    }
+                    Request::Noop => {
+                        self.write_bytes(b"250 2.0.0 OK\r\n").await?;
+                    }
+                    Request::Quit => {
+                        self.write_bytes(b"221 2.0.0 Bye\r\n").await?;
+                        return Err(());
+                    }
+                },
+                Err(Event::NeedsMoreBytes) => {
+                    break;
+                }
+                Err(Event::Data) => {
+                    if !self.rcpt_to.is_empty() {
+                        let received = self.build_received();
+                        self.parser.buf =
+                            Vec::with_capacity(self.mail_size.unwrap_or(1024) + received.len());
+                        self.parser.buf.extend_from_slice(received.as_bytes());
+                        self.write_bytes(
+                            b"354 3.0.0 Start mail input; end with <CRLF>.<CRLF>.\r\n",
+                        )
+                        .await?;
+                    } else {
+                        self.write_bytes(b"503 5.5.1 Missing RCPT TO.\r\n").await?;
+                    }
+                }
+                Err(Event::Message { response }) => {
+                    self.write_bytes(&response.into_bytes()).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.sasl = None;
+        self.mail_from = None;
+        self.identity_id = None;
+        self.mail_size = None;
+        self.rcpt_to.clear();
+        self.message = Vec::new();
+    }
+
+    pub async fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), ()> {
+        self.stream.write_bytes(bytes).await
+    }
+
+    pub async fn read_bytes(&mut self, bytes: &mut [u8]) -> Result<usize, ()> {
+        self.stream.read_bytes(bytes).await
+    }
+
+    fn build_received(&self) -> String {
+        format!(
+            concat!(
+                "Received: from {} ([{}])\r\n",
+                "\tby {} (Stalwart JMAP) with {};\r\n",
+                "\t{}\r\n"
+            ),
+            self.remote_hostname.as_deref().unwrap_or("unknown"),
+            self.peer_addr.ip(),
+            self.hostname.as_ref(),
+            if self.stream.is_tls() {
+                "ESMTPSA"
+            } else {
+                "ESMTPA"
+            },
+            Local::now().to_rfc2822()
+        )
+    }
+}
@@ -0,0 +1,316 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use jmap::{
+    request::{set::SetRequest, MaybeIdReference, MaybeResultReference},
+    types::{blob::JMAPBlob, jmap::JMAPId},
+};
+use jmap_mail::{
+    email_submission::{
+        schema::{
+            Address, EmailSubmission, Envelope, Property as SubmissionProperty,
+            Value as SubmissionValue,
+        },
+        set::SetArguments,
+    },
+    mail::{
+        import::{EmailImport, EmailImportRequest},
+        schema::{Keyword, Property, Value},
+    },
+    mailbox::schema::Property as MailboxProperty,
+    TRASH_ID,
+};
+use store::{
+    blob::BlobId,
+    core::{collection::Collection, tag::Tag, vec_map::VecMap, JMAPIdPrefix},
+    read::{
+        comparator::Comparator,
+        filter::{Filter, Query},
+        FilterMapper,
+    },
+    tracing::{debug, error},
+    Store,
+};
+
+use crate::api::{invocation::handle_method_calls, method, request::Request};
+
+use super::session::Session;
+
+impl<T> Session<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    pub async fn submit_message(&mut self) -> Result<(), ()> {
+        // Validate request
+        if self.message.is_empty() {
+            return self
+                .write_bytes(b"554 5.7.7 Empty message not accepted.\r\n")
+                .await;
+        }
+        if self.rcpt_to.is_empty() {
+            return self.write_bytes(b"503 5.5.1 Missing RCPT TO.\r\n").await;
+        }
+        let (account, mail_from, identity_id) = match (
+            self.account.clone(),
+            self.mail_from.take(),
+            self.identity_id.take(),
+        ) {
+            (Some(account), Some(mail_from), Some(identity_id)) => {
+                (account, mail_from, identity_id)
+            }
+            _ => {
+                return self.write_bytes(b"503 5.5.1 Missing MAIL FROM.\r\n").await;
+            }
+        };
+        let rcpt_to = std::mem::take(&mut self.rcpt_to);
+        let raw_message = std::mem::take(&mut self.message);
+        self.mail_size = None;
+
+        // Changes can only be committed by the leader
+        if !self.core.is_leader() {
+            return self
+                .write_bytes(b"451 4.3.2 Temporary cluster failure, try again later.\r\n")
+                .await;
+        }
+
+        // Store the message as a blob and locate the Sent mailbox
+        let account_id = account.account_id();
+        let store = self.core.store.clone();
+        let (blob_id, mailbox_id) = match self
+            .core
+            .spawn_worker(move || {
+                let blob_id = BlobId::new_external(&raw_message);
+                store.blob_store(&blob_id, raw_message)?;
+                store.blob_link_ephemeral(&blob_id, account_id)?;
+
+                let mailbox_id = store
+                    .query_store::<FilterMapper>(
+                        account_id,
+                        Collection::Mailbox,
+                        Filter::eq(
+                            MailboxProperty::Role.into(),
+                            Query::Keyword("sent".to_string()),
+                        ),
+                        Comparator::None,
+                    )?
+                    .into_iter()
+                    .next()
+                    .map(|id| id.get_document_id());
+
+                Ok((blob_id, mailbox_id))
+            })
+            .await
+        {
+            Ok(result) => result,
+            Err(err) => {
+                error!("Failed to store submitted message: {}", err);
+                return self
+                    .write_bytes(b"451 4.3.0 Temporary server failure.\r\n")
+                    .await;
+            }
+        };
+
+        // Import the message into the Sent mailbox and submit it for delivery.
+        // Without a Sent mailbox no copy is kept, the message is filed in the
+        // Trash only until the submission has been created.
+        let mut mailbox_ids = VecMap::new();
+        mailbox_ids.append(
+            MaybeIdReference::Value(mailbox_id.unwrap_or(TRASH_ID).into()),
+            true,
+        );
+        let mut keywords = VecMap::new();
+        keywords.append(Keyword::new(Tag::Static(Keyword::SEEN)), true);
+        let mut emails = VecMap::new();
+        emails.append(
+            "m".to_string(),
+            EmailImport {
+                blob_id: JMAPBlob::new(blob_id),
+                mailbox_ids: MaybeResultReference::Value(mailbox_ids).into(),
+                keywords: keywords.into(),
+                received_at: None,
+            },
+        );
+
+        let mut submission = EmailSubmission::default();
+        submission.properties.append(
+            SubmissionProperty::EmailId,
+            SubmissionValue::IdReference {
+                value: "m".to_string(),
+            },
+        );
+        submission.properties.append(
+            SubmissionProperty::IdentityId,
+            SubmissionValue::Id {
+                value: identity_id.into(),
+            },
+        );
+        submission.properties.append(
+            SubmissionProperty::Envelope,
+            SubmissionValue::Envelope {
+                value: Envelope {
+                    mail_from: Address {
+                        email: mail_from,
+                        parameters: None,
+                    },
+                    rcpt_to: rcpt_to
+                        .into_iter()
+                        .map(|email| Address {
+                            email,
+                            parameters: None,
+                        })
+                        .collect(),
+                },
+            },
+        );
+        let mut create = VecMap::new();
+        create.append("s".to_string(), submission);
+
+        let response = handle_method_calls(
+            Request {
+                using: Vec::new(),
+                method_calls: vec![
+                    method::Call {
+                        id: "i".to_string(),
+                        method: method::Request::ImportEmail(EmailImportRequest {
+                            acl: None,
+                            account_id: JMAPId::from(account_id),
+                            if_in_state: None,
+                            emails,
+                        }),
+                    },
+                    method::Call {
+                        id: "s".to_string(),
+                        method: method::Request::SetEmailSubmission(SetRequest {
+                            acl: None,
+                            account_id: JMAPId::from(account_id),
+                            if_in_state: None,
+                            create: create.into(),
+                            update: None,
+                            destroy: None,
+                            arguments: SetArguments {
+                                on_success_update_email: None,
+                                on_success_destroy_email: if mailbox_id.is_none() {
+                                    vec![MaybeIdReference::Reference("s".to_string())].into()
+                                } else {
+                                    None
+                                },
+                            },
+                        }),
+                    },
+                ],
+                created_ids: None,
+            },
+            self.core.clone(),
+            account.clone(),
+        )
+        .await;
+
+        // Both methods have to succeed for the message to be accepted
+        let mut email_id = None;
+        let mut failure = None;
+        let mut is_temporary = false;
+        for call in response.method_responses {
+            match call.method {
+                method::Response::ImportEmail(response) => {
+                    if let Some((_, err)) = response.not_created.and_then(|e| e.into_iter().next())
+                    {
+                        failure =
+                            format!("Failed to import message: {}", err.type_.as_str()).into();
+                    } else if let Some(Value::Id { value }) = response
+                        .created
+                        .and_then(|e| e.into_iter().next())
+                        .and_then(|(_, mut email)| email.properties.remove(&Property::Id))
+                    {
+                        email_id = value.into();
+                    }
+                }
+                method::Response::SetEmailSubmission(response) => {
+                    if let Some((_, err)) = response.not_created.into_iter().next() {
+                        failure =
+                            format!("Failed to submit message: {}", err.type_.as_str()).into();
+                    }
+                }
+                method::Response::Error(err) if failure.is_none() => {
+                    debug!("Message submission failed: {}", err);
+                    failure = err.to_string().into();
+                    is_temporary = true;
+                }
+                _ => (),
+            }
+        }
+
+        if let Some(failure) = failure {
+            // Remove the copy of a message that was not submitted
+            if let Some(email_id) = email_id {
+                let response = handle_method_calls(
+                    Request {
+                        using: Vec::new(),
+                        method_calls: vec![method::Call {
+                            id: "d".to_string(),
+                            method: method::Request::SetEmail(SetRequest {
+                                acl: None,
+                                account_id: JMAPId::from(account_id),
+                                if_in_state: None,
+                                create: None,
+                                update: None,
+                                destroy: MaybeResultReference::Value(vec![email_id]).into(),
+                                arguments: (),
+                            }),
+                        }],
+                        created_ids: None,
+                    },
+                    self.core.clone(),
+                    account,
+                )
+                .await;
+                for call in response.method_responses {
+                    match call.method {
+                        method::Response::SetEmail(response)
+                            if response.not_destroyed.is_some() =>
+                        {
+                            error!(
+                                "Failed to remove copy of unsubmitted message: {:?}",
+                                response.not_destroyed
+                            );
+                        }
+                        method::Response::Error(err) => {
+                            error!("Failed to remove copy of unsubmitted message: {}", err);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+
+            if is_temporary {
+                self.write_bytes(b"451 4.3.0 Temporary server failure.\r\n")
+                    .await
+            } else {
+                self.write_bytes(format!("554 5.7.0 {}.\r\n", failure).as_bytes())
+                    .await
+            }
+        } else {
+            self.write_bytes(b"250 2.0.0 Message queued for delivery.\r\n")
+                .await
+        }
+    }
+}
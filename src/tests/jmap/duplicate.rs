@@ -0,0 +1,200 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use actix_web::web;
+use jmap::{
+    principal::schema::Principal, request::set::SetRequest, types::jmap::JMAPId, SUPERUSER_ID,
+};
+use jmap_client::client::Client;
+use jmap_mail::{mail::import::JMAPMailImport, INBOX_ID};
+use jmap_sharing::principal::{account::JMAPAccountStore, set::JMAPSetPrincipal};
+use serde_json::json;
+use store::{blob::BlobId, core::collection::Collection, AccountId, Store};
+
+use crate::{
+    tests::{jmap_mail::lmtp::SmtpConnection, store::utils::StoreCompareWith},
+    JMAPServer,
+};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running duplicate delivery tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let document_id = JMAPId::parse(&account_id).unwrap().get_document_id();
+    let message = |id: &str, body: &str| {
+        format!(
+            concat!(
+                "From: bill@example.com\r\n",
+                "To: jdoe@example.com\r\n",
+                "Message-ID: <{}@example.com>\r\n",
+                "Subject: Delivery\r\n",
+                "\r\n",
+                "{}\r\n"
+            ),
+            id, body
+        )
+    };
+
+    // Duplicate detection is disabled by default
+    let mut lmtp = SmtpConnection::connect().await;
+    for _ in 0..2 {
+        lmtp.ingest(
+            "bill@example.com",
+            &["jdoe@example.com"],
+            &message("first", "Hello"),
+        )
+        .await;
+    }
+    assert_eq!(num_messages(&server, document_id), 2);
+
+    // Copies delivered within the window are discarded
+    set_duplicate_window(&server, &account_id, 3600);
+    for _ in 0..3 {
+        lmtp.ingest(
+            "bill@example.com",
+            &["jdoe@example.com"],
+            &message("second", "Hello"),
+        )
+        .await;
+    }
+    assert_eq!(num_messages(&server, document_id), 3);
+
+    // Same Message-ID with a different body is not a duplicate
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        &message("second", "Hello again"),
+    )
+    .await;
+    assert_eq!(num_messages(&server, document_id), 4);
+
+    // Messages stored by clients, such as the Sent copy of a message
+    // addressed to oneself, do not suppress its delivery
+    let raw_message = message("third", "Sent");
+    import_message(&server, document_id, &raw_message, None);
+    assert_eq!(num_messages(&server, document_id), 5);
+    lmtp.ingest("bill@example.com", &["jdoe@example.com"], &raw_message)
+        .await;
+    assert_eq!(num_messages(&server, document_id), 6);
+
+    // Imported copies of delivered messages are linked to the existing one,
+    // a backdated receivedAt does not move the message out of the window
+    import_message(
+        &server,
+        document_id,
+        &message("second", "Hello"),
+        Some(1000000000),
+    );
+    assert_eq!(num_messages(&server, document_id), 6);
+
+    // Copies delivered after the window has passed are stored
+    set_duplicate_window(&server, &account_id, 1);
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        &message("second", "Hello"),
+    )
+    .await;
+    assert_eq!(num_messages(&server, document_id), 7);
+
+    // Remove test data
+    client.principal_destroy(&account_id).await.unwrap();
+    client.principal_destroy(&domain_id).await.unwrap();
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
+
+fn set_duplicate_window<T>(server: &JMAPServer<T>, account_id: &str, window: u64)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request: SetRequest<Principal> = serde_json::from_value(json!({
+        "accountId": JMAPId::new(SUPERUSER_ID as u64).to_string(),
+        "update": {
+            account_id: {
+                "duplicateWindow": window
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(SUPERUSER_ID).unwrap().into();
+    let response = server.store.principal_set(request).unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+}
+
+fn import_message<T>(
+    server: &JMAPServer<T>,
+    account_id: AccountId,
+    raw_message: &str,
+    received_at: Option<i64>,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    let blob_id = BlobId::new_external(raw_message.as_bytes());
+    server
+        .store
+        .blob_store(&blob_id, raw_message.as_bytes().to_vec())
+        .unwrap();
+    server
+        .store
+        .mail_import_item(
+            account_id,
+            blob_id,
+            raw_message.as_bytes(),
+            vec![INBOX_ID],
+            vec![],
+            received_at,
+        )
+        .unwrap();
+}
+
+fn num_messages<T>(server: &JMAPServer<T>, account_id: AccountId) -> u64
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    server
+        .store
+        .get_document_ids(account_id, Collection::Mail)
+        .unwrap()
+        .map_or(0, |ids| ids.len())
+}
@@ -0,0 +1,212 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::{io::Read, time::Duration};
+
+use actix_web::web;
+use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use jmap_client::{
+    client::Client,
+    email,
+    mailbox::{self, Role},
+};
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use store::{ahash::AHashMap, Store};
+
+use crate::{
+    api::export::ExportJobState,
+    tests::{jmap::import::send_request, store::utils::StoreCompareWith},
+    JMAPServer,
+};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running mailbox export tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    client.set_default_account_id(&account_id);
+
+    // Add a few messages to the Inbox and to a new folder
+    let inbox_id = client
+        .mailbox_query(
+            mailbox::query::Filter::role(Role::Inbox).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    let folder_id = client
+        .mailbox_create("Projects", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    for (subject, mailbox_id, keywords) in [
+        ("first", &inbox_id, vec!["$seen"]),
+        ("second", &inbox_id, vec![]),
+        ("third", &folder_id, vec!["$flagged"]),
+    ] {
+        client
+            .email_import(
+                format!(
+                    "From: bill@example.com\r\nSubject: {}\r\n\r\nFrom the {} message.\r\n",
+                    subject, subject
+                )
+                .into_bytes(),
+                [mailbox_id],
+                Some(keywords),
+                Some(1310126914),
+            )
+            .await
+            .unwrap();
+    }
+
+    // Export the account to a tar archive of mbox files
+    let export_url = format!(
+        "{}/jmap/export/{}",
+        server.base_session.base_url().trim_end_matches('/'),
+        account_id
+    );
+    let (status, job) = send_request(
+        reqwest::Client::new().post(&export_url).body(
+            json!({
+                "format": "mbox",
+                "archive": "tar"
+            })
+            .to_string(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", job);
+    let job_url = format!("{}/{}", export_url, job["id"].as_str().unwrap());
+
+    let mut job = Value::Null;
+    for _ in 0..50 {
+        let (status, response) = send_request(reqwest::Client::new().get(&job_url)).await;
+        assert_eq!(status, StatusCode::OK, "{}", response);
+        job = response;
+        if job["status"] != "running" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(job["status"], "completed", "{}", job);
+    assert_eq!(job["exported"], 3);
+    assert_eq!(job["failed"], 0);
+
+    // The archive contains one mbox file per folder
+    let archive = client
+        .download(job["blobId"].as_str().unwrap())
+        .await
+        .unwrap();
+    let mut files = AHashMap::new();
+    for entry in tar::Archive::new(&archive[..]).entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        files.insert(
+            entry.path().unwrap().to_string_lossy().into_owned(),
+            contents,
+        );
+    }
+    assert_eq!(files.len(), 2, "{:?}", files.keys());
+    let inbox = files.get("INBOX.mbox").unwrap();
+    assert_eq!(
+        inbox
+            .matches("From MAILER-DAEMON Fri Jul  8 12:08:34 2011\n")
+            .count(),
+        2
+    );
+    assert!(inbox.contains("Status: RO\n"), "{}", inbox);
+    assert!(inbox.contains("\n>From the first message."), "{}", inbox);
+    let folder = files.get("Projects.mbox").unwrap();
+    assert!(folder.contains("X-Status: F\n"), "{}", folder);
+    assert!(folder.contains("Subject: third"), "{}", folder);
+
+    // The account is notified once the export is done
+    let mut is_notified = false;
+    for _ in 0..50 {
+        if !client
+            .email_query(
+                email::query::Filter::subject("Your mailbox export is ready").into(),
+                None::<Vec<_>>,
+            )
+            .await
+            .unwrap()
+            .ids()
+            .is_empty()
+        {
+            is_notified = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert!(is_notified);
+
+    // Jobs left running when the server stopped are reported as failed
+    server
+        .set_key(
+            "export/interrupted",
+            serde_json::from_value::<ExportJobState>(json!({
+                "id": "interrupted",
+                "accountId": account_id,
+                "format": "maildir",
+                "archive": "zip",
+                "status": "running",
+                "exported": 1,
+                "failed": 0
+            }))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+    let (status, job) =
+        send_request(reqwest::Client::new().get(format!("{}/interrupted", export_url))).await;
+    assert_eq!(status, StatusCode::OK, "{}", job);
+    assert_eq!(job["status"], "failed");
+    assert_eq!(job["exported"], 1);
+
+    // Remove test data
+    client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .principal_destroy(&account_id)
+        .await
+        .unwrap();
+    client.principal_destroy(&domain_id).await.unwrap();
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
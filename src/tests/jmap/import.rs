@@ -0,0 +1,226 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use actix_web::web;
+use jmap::{error::method::MethodError, types::jmap::JMAPId, SUPERUSER_ID};
+use jmap_client::{client::Client, email, mailbox};
+use jmap_mail::mail::import::{EmailImportRequest, JMAPMailImport};
+use jmap_sharing::principal::account::JMAPAccountStore;
+use reqwest::StatusCode;
+use serde_json::{json, Value};
+use store::Store;
+
+use crate::{api::import::ImportJobState, tests::store::utils::StoreCompareWith, JMAPServer};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running mailbox import tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    client.set_default_account_id(&account_id);
+
+    // Upload an mbox archive with more messages than a single Email/import accepts
+    let num_messages = server.store.config.mail_import_max_items + 2;
+    let blob_id = client
+        .upload(
+            None,
+            (1..=num_messages)
+                .map(|num| {
+                    format!(
+                        concat!(
+                            "From bill@example.com Fri Jul  8 12:08:34 2011\n",
+                            "From: bill@example.com\n",
+                            "Subject: Archived message {}\n",
+                            "\n",
+                            "Message number {}.\n",
+                            "\n"
+                        ),
+                        num, num
+                    )
+                })
+                .collect::<String>()
+                .into_bytes(),
+            None,
+        )
+        .await
+        .unwrap()
+        .take_blob_id();
+
+    // Email/import requests are limited to mail-import-max-items
+    let inbox_id = JMAPId::new(0).to_string();
+    let mut request: EmailImportRequest = serde_json::from_value(json!({
+        "accountId": account_id,
+        "emails": (0..num_messages)
+            .map(|num| {
+                (
+                    format!("m{}", num),
+                    json!({
+                        "blobId": blob_id,
+                        "mailboxIds": { &inbox_id: true }
+                    }),
+                )
+            })
+            .collect::<serde_json::Map<_, _>>()
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(SUPERUSER_ID).unwrap().into();
+    assert!(matches!(
+        server.store.mail_import(request),
+        Err(MethodError::RequestTooLarge)
+    ));
+
+    // Import the archive into a new folder
+    let import_url = format!(
+        "{}/jmap/import/{}",
+        server.base_session.base_url().trim_end_matches('/'),
+        account_id
+    );
+    let (status, job) = send_request(
+        reqwest::Client::new().post(&import_url).body(
+            json!({
+                "format": "mbox",
+                "blobId": blob_id,
+                "mailbox": "Archive/2011"
+            })
+            .to_string(),
+        ),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED, "{}", job);
+    assert_eq!(job["status"], "running");
+    let job_url = format!("{}/{}", import_url, job["id"].as_str().unwrap());
+
+    let mut job = Value::Null;
+    for _ in 0..50 {
+        let (status, response) = send_request(reqwest::Client::new().get(&job_url)).await;
+        assert_eq!(status, StatusCode::OK, "{}", response);
+        job = response;
+        if job["status"] != "running" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    assert_eq!(job["status"], "completed", "{}", job);
+    assert_eq!(job["imported"], num_messages);
+    assert_eq!(job["failed"], 0);
+    assert_eq!(job["mailboxesCreated"], 2);
+
+    // Messages are filed in the new folder with their original received date
+    let mailbox_id = client
+        .mailbox_query(mailbox::query::Filter::name("2011").into(), None::<Vec<_>>)
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    let email_ids = client
+        .email_query(
+            email::query::Filter::in_mailbox(&mailbox_id).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids();
+    assert_eq!(email_ids.len(), num_messages);
+    assert_eq!(
+        client
+            .email_get(&email_ids[0], [email::Property::ReceivedAt].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .received_at()
+            .unwrap(),
+        1310126914
+    );
+
+    // Jobs left running when the server stopped are reported as failed
+    server
+        .set_key(
+            "import/interrupted",
+            serde_json::from_value::<ImportJobState>(json!({
+                "id": "interrupted",
+                "accountId": account_id,
+                "format": "mbox",
+                "status": "running",
+                "imported": 1,
+                "failed": 0,
+                "mailboxesCreated": 0
+            }))
+            .unwrap(),
+        )
+        .await
+        .unwrap();
+    let (status, job) =
+        send_request(reqwest::Client::new().get(format!("{}/interrupted", import_url))).await;
+    assert_eq!(status, StatusCode::OK, "{}", job);
+    assert_eq!(job["status"], "failed");
+    assert_eq!(job["imported"], 1);
+
+    // Jobs can only be queried from the account they belong to
+    let (status, _) = send_request(reqwest::Client::new().get(format!(
+        "{}/jmap/import/{}/interrupted",
+        server.base_session.base_url().trim_end_matches('/'),
+        domain_id
+    )))
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Remove test data
+    client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .principal_destroy(&account_id)
+        .await
+        .unwrap();
+    client.principal_destroy(&domain_id).await.unwrap();
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
+
+pub async fn send_request(request: reqwest::RequestBuilder) -> (StatusCode, Value) {
+    let response = request
+        .bearer_auth("DO_NOT_ATTEMPT_THIS_AT_HOME")
+        .timeout(Duration::from_millis(1000))
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    let bytes = response.bytes().await.unwrap();
+    (
+        status,
+        serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+    )
+}
@@ -0,0 +1,224 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use actix_web::web;
+use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use jmap_client::{client::Client, email::query::Filter};
+use reqwest::{header, StatusCode};
+use store::{ahash::AHashMap, Store};
+
+use crate::{
+    tests::{jmap_mail::lmtp::SmtpConnection, store::utils::StoreCompareWith},
+    JMAPServer,
+};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running metrics tests...");
+
+    let metrics_url = format!(
+        "{}/metrics",
+        server.base_session.base_url().trim_end_matches('/')
+    );
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+
+    // Only the superuser has access to the metrics
+    assert_eq!(
+        get_metrics(&metrics_url, Auth::None).await.0,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        get_metrics(&metrics_url, Auth::Basic("jdoe@example.com", "12345"))
+            .await
+            .0,
+        StatusCode::FORBIDDEN
+    );
+
+    // Generate some activity
+    client
+        .set_default_account_id(&account_id)
+        .email_query(None::<Filter>, None::<Vec<_>>)
+        .await
+        .unwrap();
+    let mut lmtp = SmtpConnection::connect().await;
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Metrics\r\n",
+            "\r\n",
+            "Test message.\r\n"
+        ),
+    )
+    .await;
+
+    // Fetch the metrics as the superuser
+    let (status, content_type, metrics) =
+        get_metrics(&metrics_url, Auth::Bearer("DO_NOT_ATTEMPT_THIS_AT_HOME")).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(
+        content_type.starts_with("text/plain; version=0.0.4"),
+        "{}",
+        content_type
+    );
+
+    // Validate the exposition format
+    let mut types = AHashMap::new();
+    let mut samples = AHashMap::new();
+    for line in metrics.lines() {
+        if let Some(help) = line.strip_prefix("# HELP ") {
+            let (name, text) = help.split_once(' ').unwrap();
+            assert!(!types.contains_key(name), "HELP after TYPE for {}", name);
+            assert!(!text.is_empty(), "Empty HELP for {}", name);
+        } else if let Some(type_) = line.strip_prefix("# TYPE ") {
+            let (name, type_) = type_.split_once(' ').unwrap();
+            assert!(
+                ["counter", "gauge", "histogram"].contains(&type_),
+                "Invalid type {:?}",
+                line
+            );
+            assert!(
+                types.insert(name.to_string(), type_.to_string()).is_none(),
+                "Duplicate TYPE for {}",
+                name
+            );
+        } else {
+            let (sample, value) = line.rsplit_once(' ').unwrap();
+            assert!(value.parse::<f64>().is_ok(), "Invalid value {:?}", line);
+            let name = sample.split_once('{').map_or(sample, |(name, labels)| {
+                assert!(labels.ends_with('}'), "Invalid labels {:?}", line);
+                name
+            });
+            assert!(
+                name.chars()
+                    .all(|ch| ch.is_ascii_alphanumeric() || ch == '_'),
+                "Invalid metric name {:?}",
+                line
+            );
+            let family = ["_bucket", "_sum", "_count"]
+                .iter()
+                .find_map(|suffix| {
+                    name.strip_suffix(suffix).filter(|family| {
+                        types.get(*family).map(|t| t.as_str()) == Some("histogram")
+                    })
+                })
+                .unwrap_or(name);
+            assert!(types.contains_key(family), "Sample without TYPE {:?}", line);
+            assert!(
+                samples
+                    .insert(sample.to_string(), value.to_string())
+                    .is_none(),
+                "Duplicate sample {:?}",
+                line
+            );
+        }
+    }
+
+    // Verify the expected counters
+    for (name, type_) in [
+        ("jmap_method_requests_total", "counter"),
+        ("jmap_method_errors_total", "counter"),
+        ("jmap_method_duration_seconds", "histogram"),
+        ("jmap_lmtp_deliveries_total", "counter"),
+        ("jmap_push_queue_size", "gauge"),
+        ("jmap_delivery_queue_size", "gauge"),
+        ("jmap_cache_requests_total", "counter"),
+        ("jmap_cache_hits_total", "counter"),
+        ("jmap_cache_entries", "gauge"),
+    ] {
+        assert_eq!(types.get(name).map(|t| t.as_str()), Some(type_), "{}", name);
+    }
+    for sample in [
+        "jmap_method_requests_total{method=\"Email/query\"}",
+        "jmap_method_duration_seconds_count{method=\"Email/query\"}",
+        "jmap_lmtp_deliveries_total{code=\"250\"}",
+        "jmap_cache_requests_total{cache=\"acl_tokens\"}",
+    ] {
+        assert!(
+            samples
+                .get(sample)
+                .map_or(false, |value| value.parse::<f64>().unwrap() >= 1.0),
+            "Missing or zero {}",
+            sample
+        );
+    }
+    assert_eq!(
+        samples.get("jmap_method_duration_seconds_bucket{method=\"Email/query\",le=\"+Inf\"}"),
+        samples.get("jmap_method_requests_total{method=\"Email/query\"}")
+    );
+
+    // Remove test data
+    client.set_default_account_id(JMAPId::new(SUPERUSER_ID as u64));
+    client.principal_destroy(&account_id).await.unwrap();
+    client.principal_destroy(&domain_id).await.unwrap();
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
+
+enum Auth<'x> {
+    None,
+    Basic(&'x str, &'x str),
+    Bearer(&'x str),
+}
+
+async fn get_metrics(url: &str, auth: Auth<'_>) -> (StatusCode, String, String) {
+    let mut request = reqwest::Client::builder()
+        .timeout(Duration::from_millis(1000))
+        .build()
+        .unwrap_or_default()
+        .get(url);
+    match auth {
+        Auth::Basic(username, password) => {
+            request = request.basic_auth(username, Some(password));
+        }
+        Auth::Bearer(token) => {
+            request = request.bearer_auth(token);
+        }
+        Auth::None => (),
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    (status, content_type, response.text().await.unwrap())
+}
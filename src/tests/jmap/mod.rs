@@ -40,10 +40,18 @@ use super::store::utils::{destroy_temp_dir, init_settings};
 
 pub mod acl;
 pub mod authorization;
+pub mod duplicate;
 pub mod event_source;
+pub mod export;
+pub mod import;
+pub mod metrics;
 pub mod oauth;
 pub mod push_subscription;
 pub mod references;
+pub mod reload;
+pub mod retention;
+pub mod share_notification;
+pub mod spam_filter;
 pub mod stress_test;
 pub mod websocket;
 
@@ -139,10 +147,18 @@ async fn jmap_core_tests() {
     // Run tests
     oauth::test(server.clone(), &mut client).await;
     acl::test(server.clone(), &mut client).await;
+    share_notification::test(server.clone(), &mut client).await;
     authorization::test(server.clone(), &mut client).await;
+    duplicate::test(server.clone(), &mut client).await;
+    import::test(server.clone(), &mut client).await;
+    export::test(server.clone(), &mut client).await;
+    metrics::test(server.clone(), &mut client).await;
     event_source::test(server.clone(), &mut client).await;
     push_subscription::test(server.clone(), &mut client).await;
+    retention::test(server.clone(), &mut client).await;
+    spam_filter::test(server.clone(), &mut client).await;
     websocket::test(server.clone(), &mut client).await;
+    reload::test(server.clone(), &mut client).await;
 
     destroy_temp_dir(&temp_dir);
 }
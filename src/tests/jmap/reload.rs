@@ -0,0 +1,144 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use actix_web::web;
+use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use jmap_client::client::Client;
+use reqwest::StatusCode;
+use store::Store;
+
+use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running configuration reload tests...");
+    let settings = server.reload.current_settings();
+    let config = &server.store.config;
+    let max_size_upload = config.max_size_upload.get();
+    let mail_max_size = config.mail_max_size.get();
+
+    // Reloadable settings are applied, the rest require a restart
+    let mut new_settings = settings.clone();
+    new_settings.set_value("max-size-upload".to_string(), "1024".to_string());
+    new_settings.set_value("db-path".to_string(), "/tmp/other-db".to_string());
+    let report = server.reload_config(new_settings).await.unwrap();
+    assert_eq!(report.applied, vec!["max-size-upload".to_string()]);
+    assert_eq!(report.restart_required, vec!["db-path".to_string()]);
+    assert_eq!(config.max_size_upload.get(), 1024);
+    client.refresh_session().await.unwrap();
+    assert_eq!(
+        client
+            .session()
+            .core_capabilities()
+            .unwrap()
+            .max_size_upload(),
+        1024
+    );
+
+    // Rate limits and push settings are applied live
+    let mut new_settings = settings.clone();
+    new_settings.set_value("rate-limit-anonymous".to_string(), "5/60".to_string());
+    new_settings.set_value("push-throttle".to_string(), "1000".to_string());
+    let report = server.reload_config(new_settings).await.unwrap();
+    assert_eq!(
+        report.applied,
+        vec![
+            "push-throttle".to_string(),
+            "rate-limit-anonymous".to_string()
+        ]
+    );
+    assert!(report.restart_required.is_empty());
+    assert_eq!(
+        server.reload.current_settings().get("rate-limit-anonymous"),
+        Some("5/60".to_string())
+    );
+
+    // Invalid values are rejected without applying anything
+    let mut new_settings = settings.clone();
+    new_settings.set_value("mail-max-size".to_string(), "1024".to_string());
+    new_settings.set_value("push-attempts-max".to_string(), "many".to_string());
+    assert!(server.reload_config(new_settings).await.is_err());
+    assert_eq!(config.mail_max_size.get(), mail_max_size);
+
+    // Payload limits cannot grow past what the HTTP server was started with
+    let mut new_settings = settings.clone();
+    new_settings.set_value(
+        "max-size-upload".to_string(),
+        (server.reload.payload_limit + 1).to_string(),
+    );
+    let report = server.reload_config(new_settings).await.unwrap();
+    assert_eq!(report.restart_required, vec!["max-size-upload".to_string()]);
+    assert_eq!(config.max_size_upload.get(), 1024);
+
+    // Restore original settings
+    let report = server.reload_config(settings).await.unwrap();
+    assert_eq!(
+        report.applied,
+        vec![
+            "max-size-upload".to_string(),
+            "push-throttle".to_string(),
+            "rate-limit-anonymous".to_string()
+        ]
+    );
+    assert!(report.restart_required.is_empty());
+    assert_eq!(config.max_size_upload.get(), max_size_upload);
+
+    // Only the superuser can request a reload
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let reload_url = format!(
+        "{}/admin/reload",
+        server.base_session.base_url().trim_end_matches('/')
+    );
+    for (credentials, status) in [
+        (None, StatusCode::UNAUTHORIZED),
+        (Some(("jdoe@example.com", "12345")), StatusCode::FORBIDDEN),
+    ] {
+        let mut request = reqwest::Client::builder()
+            .timeout(Duration::from_millis(1000))
+            .build()
+            .unwrap_or_default()
+            .post(&reload_url);
+        if let Some((username, password)) = credentials {
+            request = request.basic_auth(username, Some(password));
+        }
+        assert_eq!(request.send().await.unwrap().status(), status);
+    }
+    client.principal_destroy(&account_id).await.unwrap();
+    client.principal_destroy(&domain_id).await.unwrap();
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
@@ -0,0 +1,307 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::{Duration, SystemTime};
+
+use actix_web::web;
+use jmap::{
+    principal::schema::Principal,
+    request::set::SetRequest,
+    types::{jmap::JMAPId, type_state::TypeState},
+    SUPERUSER_ID,
+};
+use jmap_client::{
+    client::Client,
+    email::{self, Property},
+    mailbox::Role,
+};
+use jmap_mail::{
+    mailbox::{retention::JMAPMailboxRetention, schema::Mailbox, set::JMAPSetMailbox},
+    INBOX_ID, TRASH_ID,
+};
+use jmap_sharing::principal::{account::JMAPAccountStore, set::JMAPSetPrincipal};
+use serde_json::{json, Value};
+use store::{core::bitmap::Bitmap, AccountId, Store};
+
+use crate::{services::housekeeper, tests::store::utils::StoreCompareWith, JMAPServer};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running mailbox retention tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let document_id = account_document_id(&account_id);
+    let inbox_id = JMAPId::new(INBOX_ID as u64).to_string();
+    let trash_id = JMAPId::new(TRASH_ID as u64).to_string();
+    client.set_default_account_id(&account_id);
+
+    // Mailboxes with their own policies, plus an archive that follows the
+    // 'archive max-age=1d move=trash' rule from the configuration.
+    let expire_id = client
+        .mailbox_create("Expire", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let trim_id = client
+        .mailbox_create("Trim", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+    let archive_id = client
+        .mailbox_create("Archive", None::<String>, Role::Archive)
+        .await
+        .unwrap()
+        .take_id();
+    set_mailbox_retention(
+        &server,
+        &account_id,
+        &expire_id,
+        json!({"maxAge": 3600, "action": "destroy"}),
+    );
+    set_mailbox_retention(
+        &server,
+        &account_id,
+        &trim_id,
+        json!({"maxEmails": 2, "action": "destroy"}),
+    );
+
+    // Import messages with different delivery dates
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    let expired_id = import(client, "Expired", [&expire_id], now - 7200).await;
+    let recent_id = import(client, "Recent", [&expire_id], now - 60).await;
+    let shared_id = import(client, "Shared", [&expire_id, &inbox_id], now - 7200).await;
+    let mut trim_ids = Vec::new();
+    for num in 0..4 {
+        trim_ids.push(
+            import(
+                client,
+                &format!("Trim {}", num),
+                [&trim_id],
+                now - 600 + num,
+            )
+            .await,
+        );
+    }
+    let archived_id = import(client, "Archived", [&archive_id], now - 2 * 86400).await;
+    let unarchived_id = import(client, "Unarchived", [&archive_id], now - 3600).await;
+
+    // Apply retention policies and wait for the state change
+    let mut state_rx = server
+        .subscribe_state_manager(document_id, document_id, Bitmap::all())
+        .await
+        .unwrap();
+    server
+        .housekeeper
+        .send(housekeeper::Event::Retention)
+        .await
+        .unwrap();
+    let state_change = tokio::time::timeout(Duration::from_secs(5), state_rx.recv())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(state_change.account_id, document_id);
+    for type_state in [TypeState::Email, TypeState::Mailbox, TypeState::Thread] {
+        assert!(
+            state_change
+                .types
+                .iter()
+                .any(|(changed_type, _)| changed_type == &type_state),
+            "{:?} missing from {:?}",
+            type_state,
+            state_change.types
+        );
+    }
+
+    // Expired messages are destroyed, unless they belong to other mailboxes
+    assert_mailboxes(client, &expired_id, &[]).await;
+    assert_mailboxes(client, &recent_id, &[&expire_id]).await;
+    assert_mailboxes(client, &shared_id, &[&inbox_id]).await;
+
+    // Only the newest messages are kept
+    for (num, id) in trim_ids.iter().enumerate() {
+        assert_mailboxes(client, id, if num < 2 { &[] } else { &[&trim_id] }).await;
+    }
+    assert_eq!(
+        client
+            .email_query(
+                email::query::Filter::in_mailbox(&trim_id).into(),
+                None::<Vec<_>>
+            )
+            .await
+            .unwrap()
+            .ids()
+            .len(),
+        2
+    );
+
+    // Messages are moved to the mailbox with the configured role
+    assert_mailboxes(client, &archived_id, &[&trash_id]).await;
+    assert_mailboxes(client, &unarchived_id, &[&archive_id]).await;
+
+    // Nothing else expires on a second run
+    assert!(server
+        .store
+        .mailbox_retention_apply(document_id)
+        .unwrap()
+        .is_none());
+
+    // Accounts under legal hold are skipped
+    let held_id = import(client, "Held", [&expire_id], now - 7200).await;
+    set_legal_hold(&server, &account_id, true);
+    assert!(server
+        .store
+        .mailbox_retention_apply(document_id)
+        .unwrap()
+        .is_none());
+    assert_mailboxes(client, &held_id, &[&expire_id]).await;
+    set_legal_hold(&server, &account_id, false);
+    assert!(server
+        .store
+        .mailbox_retention_apply(document_id)
+        .unwrap()
+        .is_some());
+    assert_mailboxes(client, &held_id, &[]).await;
+
+    // Remove test data
+    client.set_default_account_id(JMAPId::new(SUPERUSER_ID as u64));
+    client.principal_destroy(&account_id).await.unwrap();
+    client.principal_destroy(&domain_id).await.unwrap();
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
+
+async fn import<const N: usize>(
+    client: &mut Client,
+    subject: &str,
+    mailbox_ids: [&String; N],
+    received_at: i64,
+) -> String {
+    client
+        .email_import(
+            format!(
+                concat!(
+                    "From: bill@example.com\r\n",
+                    "To: jdoe@example.com\r\n",
+                    "Subject: {}\r\n",
+                    "\r\n",
+                    "Retention test.\r\n"
+                ),
+                subject
+            )
+            .into_bytes(),
+            mailbox_ids,
+            None::<Vec<&str>>,
+            received_at.into(),
+        )
+        .await
+        .unwrap()
+        .take_id()
+}
+
+async fn assert_mailboxes(client: &mut Client, email_id: &str, expected: &[&String]) {
+    let email = client
+        .email_get(email_id, [Property::MailboxIds].into())
+        .await
+        .unwrap();
+    if expected.is_empty() {
+        assert!(email.is_none(), "{} was not destroyed", email_id);
+    } else {
+        let email = email.unwrap();
+        let mut mailbox_ids = email.mailbox_ids().to_vec();
+        mailbox_ids.sort_unstable();
+        let mut expected = expected.iter().map(|id| id.as_str()).collect::<Vec<_>>();
+        expected.sort_unstable();
+        assert_eq!(mailbox_ids, expected);
+    }
+}
+
+fn set_mailbox_retention<T>(
+    server: &JMAPServer<T>,
+    account_id: &str,
+    mailbox_id: &str,
+    retention: Value,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request: SetRequest<Mailbox> = serde_json::from_value(json!({
+        "accountId": account_id,
+        "update": {
+            mailbox_id: {
+                "retention": retention
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = server
+        .store
+        .get_acl_token(account_document_id(account_id))
+        .unwrap()
+        .into();
+    let response = server.store.mailbox_set(request).unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+}
+
+fn set_legal_hold<T>(server: &JMAPServer<T>, account_id: &str, legal_hold: bool)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request: SetRequest<Principal> = serde_json::from_value(json!({
+        "accountId": JMAPId::new(SUPERUSER_ID as u64).to_string(),
+        "update": {
+            account_id: {
+                "legalHold": legal_hold
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(SUPERUSER_ID).unwrap().into();
+    let response = server.store.principal_set(request).unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+}
+
+fn account_document_id(account_id: &str) -> AccountId {
+    JMAPId::parse(account_id).unwrap().get_document_id()
+}
@@ -0,0 +1,306 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::web;
+use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use jmap_client::client::Client;
+use jmap_sharing::principal::set::JMAPSetPrincipal;
+use serde_json::{json, Value};
+use store::Store;
+
+use crate::{
+    tests::{
+        jmap_contacts::{created_id, jmap_request},
+        store::utils::StoreCompareWith,
+    },
+    JMAPServer,
+};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Share Notification tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let john_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let jane_id = client
+        .individual_create("jane.smith@example.com", "abcde", "Jane Smith")
+        .await
+        .unwrap()
+        .take_id();
+    let john = Some(("jdoe@example.com", "12345"));
+    let jane = Some(("jane.smith@example.com", "abcde"));
+    let changed_by = json!({
+        "name": "John Doe",
+        "email": "jdoe@example.com",
+        "principalId": john_id
+    });
+
+    // Sharing a mailbox notifies the grantee
+    let response = jmap_request(
+        &server,
+        john,
+        "Mailbox/set",
+        json!({
+            "accountId": john_id,
+            "create": {
+                "mailbox": { "name": "Shared Folder" }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    let mailbox_id = created_id(&response, "mailbox");
+    mailbox_update(
+        &server,
+        john,
+        &john_id,
+        &mailbox_id,
+        json!({ "acl": { "jane.smith@example.com": ["read", "readItems"] } }),
+    )
+    .await;
+    let mut expected = vec![json!({
+        "objectType": "Mailbox",
+        "objectAccountId": john_id,
+        "objectId": mailbox_id,
+        "oldRights": null,
+        "newRights": ["read", "readItems"],
+        "name": "Shared Folder",
+        "changedBy": changed_by
+    })];
+    assert_eq!(notifications(&server, jane, &jane_id).await, expected);
+
+    // Changes that do not affect the grantee's rights are not notified
+    mailbox_update(
+        &server,
+        john,
+        &john_id,
+        &mailbox_id,
+        json!({ "name": "Team" }),
+    )
+    .await;
+    assert_eq!(notifications(&server, jane, &jane_id).await, expected);
+
+    // Changed rights are notified under the object's new name
+    mailbox_update(
+        &server,
+        john,
+        &john_id,
+        &mailbox_id,
+        json!({
+            "name": "Team Folder",
+            "acl": { "jane.smith@example.com": ["read", "readItems", "addItems"] }
+        }),
+    )
+    .await;
+    expected.push(json!({
+        "objectType": "Mailbox",
+        "objectAccountId": john_id,
+        "objectId": mailbox_id,
+        "oldRights": ["read", "readItems"],
+        "newRights": ["read", "readItems", "addItems"],
+        "name": "Team Folder",
+        "changedBy": changed_by
+    }));
+    assert_eq!(notifications(&server, jane, &jane_id).await, expected);
+
+    // Revoked rights are notified as well, using the stored name
+    mailbox_update(&server, john, &john_id, &mailbox_id, json!({ "acl": {} })).await;
+    expected.push(json!({
+        "objectType": "Mailbox",
+        "objectAccountId": john_id,
+        "objectId": mailbox_id,
+        "oldRights": ["read", "readItems", "addItems"],
+        "newRights": null,
+        "name": "Team Folder",
+        "changedBy": changed_by
+    }));
+    assert_eq!(notifications(&server, jane, &jane_id).await, expected);
+
+    // Address books shared on creation are notified too
+    let response = jmap_request(
+        &server,
+        john,
+        "AddressBook/set",
+        json!({
+            "accountId": john_id,
+            "create": {
+                "book": {
+                    "name": "Contacts",
+                    "acl": { "jane.smith@example.com": ["read", "readItems"] }
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    let book_id = created_id(&response, "book");
+    expected.push(json!({
+        "objectType": "AddressBook",
+        "objectAccountId": john_id,
+        "objectId": book_id,
+        "oldRights": null,
+        "newRights": ["read", "readItems"],
+        "name": "Contacts",
+        "changedBy": changed_by
+    }));
+    assert_eq!(notifications(&server, jane, &jane_id).await, expected);
+
+    // The owner does not receive notifications about their own changes
+    assert_eq!(
+        notifications(&server, john, &john_id).await,
+        Vec::<Value>::new()
+    );
+
+    // Grantees can dismiss their notifications
+    let ids = jmap_request(
+        &server,
+        jane,
+        "ShareNotification/query",
+        json!({ "accountId": jane_id }),
+    )
+    .await
+    .unwrap()["ids"]
+        .take();
+    let response = jmap_request(
+        &server,
+        jane,
+        "ShareNotification/set",
+        json!({ "accountId": jane_id, "destroy": ids }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        response["destroyed"].as_array().unwrap().len(),
+        expected.len()
+    );
+    assert_eq!(
+        notifications(&server, jane, &jane_id).await,
+        Vec::<Value>::new()
+    );
+
+    // Remove test data
+    jmap_request(
+        &server,
+        john,
+        "AddressBook/set",
+        json!({ "accountId": john_id, "destroy": [&book_id] }),
+    )
+    .await
+    .unwrap();
+    jmap_request(
+        &server,
+        john,
+        "Mailbox/set",
+        json!({ "accountId": john_id, "destroy": [&mailbox_id] }),
+    )
+    .await
+    .unwrap();
+    for account_id in [&john_id, &jane_id, &domain_id] {
+        client
+            .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+            .principal_destroy(account_id)
+            .await
+            .unwrap();
+    }
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
+
+async fn mailbox_update<T>(
+    server: &web::Data<JMAPServer<T>>,
+    credentials: Option<(&str, &str)>,
+    account_id: &str,
+    mailbox_id: &str,
+    update: Value,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    let response = jmap_request(
+        server,
+        credentials,
+        "Mailbox/set",
+        json!({
+            "accountId": account_id,
+            "update": { mailbox_id: update }
+        }),
+    )
+    .await
+    .unwrap();
+    assert!(
+        response["updated"].get(mailbox_id).is_some(),
+        "{}",
+        response
+    );
+}
+
+// Returns the share notifications of an account, oldest first.
+async fn notifications<T>(
+    server: &web::Data<JMAPServer<T>>,
+    credentials: Option<(&str, &str)>,
+    account_id: &str,
+) -> Vec<Value>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    match jmap_request(
+        server,
+        credentials,
+        "ShareNotification/get",
+        json!({
+            "accountId": account_id,
+            "properties": [
+                "objectType",
+                "objectAccountId",
+                "objectId",
+                "oldRights",
+                "newRights",
+                "name",
+                "changedBy"
+            ]
+        }),
+    )
+    .await
+    .unwrap()["list"]
+        .take()
+    {
+        Value::Array(list) => list
+            .into_iter()
+            .map(|mut item| {
+                item.as_object_mut().unwrap().remove("id");
+                item
+            })
+            .collect(),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,245 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::web;
+use jmap::{
+    principal::schema::Principal, request::set::SetRequest, types::jmap::JMAPId, SUPERUSER_ID,
+};
+use jmap_client::{
+    client::Client,
+    email::{self, Property},
+    mailbox::{self, Role},
+};
+use jmap_mail::INBOX_ID;
+use jmap_sharing::principal::{account::JMAPAccountStore, set::JMAPSetPrincipal};
+use serde_json::{json, Value};
+use store::{ahash::AHashSet, AccountId, Store};
+
+use crate::{
+    lmtp::{
+        ingest::DeliveryStatus,
+        spam_filter::{Action, ScanResult, SpamScore},
+    },
+    tests::store::utils::StoreCompareWith,
+    JMAPServer,
+};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running spam filter tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let document_id = JMAPId::parse(&account_id).unwrap().get_document_id();
+    let inbox_id = JMAPId::new(INBOX_ID as u64).to_string();
+    client.set_default_account_id(&account_id);
+    let junk_id = client
+        .mailbox_query(
+            mailbox::query::Filter::role(Role::Junk).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+
+    // Messages are filed according to the scanner's verdict
+    for (subject, score, action, expected) in [
+        ("Ham", Some(1.0), Action::Accept, Some(&inbox_id)),
+        ("Unscored", None, Action::Junk, Some(&junk_id)),
+        ("Scored", Some(8.0), Action::Junk, Some(&junk_id)),
+        ("Rejected", Some(20.0), Action::Reject, None),
+    ] {
+        deliver(
+            &server,
+            document_id,
+            subject,
+            ScanResult {
+                score: SpamScore { score, action },
+                headers: vec![],
+            },
+        )
+        .await;
+        assert_filed(client, subject, expected).await;
+    }
+
+    // Account thresholds override the scanner's action
+    set_spam_filter(
+        &server,
+        &account_id,
+        json!({"junkScore": 5.0, "rejectScore": 10.0}),
+    );
+    for (subject, score, action, expected) in [
+        ("Lenient", Some(4.0), Action::Junk, Some(&inbox_id)),
+        ("Strict", Some(6.0), Action::Accept, Some(&junk_id)),
+        ("Blocked", Some(12.0), Action::Junk, None),
+    ] {
+        deliver(
+            &server,
+            document_id,
+            subject,
+            ScanResult {
+                score: SpamScore { score, action },
+                headers: vec![],
+            },
+        )
+        .await;
+        assert_filed(client, subject, expected).await;
+    }
+    set_spam_filter(&server, &account_id, Value::Null);
+
+    // Line breaks in scanner headers can't be used to inject new headers
+    deliver(
+        &server,
+        document_id,
+        "Original",
+        ScanResult {
+            score: SpamScore {
+                score: Some(0.0),
+                action: Action::Accept,
+            },
+            headers: vec![(
+                "X-Spamd-Result".to_string(),
+                "default: False\r\nSubject: Injected\r\n\r\nBody".to_string(),
+            )],
+        },
+    )
+    .await;
+    assert_filed(client, "Original", Some(&inbox_id)).await;
+    assert_filed(client, "Injected", None).await;
+
+    // Remove test data
+    client.set_default_account_id(JMAPId::new(SUPERUSER_ID as u64));
+    client.principal_destroy(&account_id).await.unwrap();
+    client.principal_destroy(&domain_id).await.unwrap();
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
+
+async fn deliver<T>(
+    server: &JMAPServer<T>,
+    account_id: AccountId,
+    subject: &str,
+    scan_result: ScanResult,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut message = scan_result.build_headers().into_bytes();
+    message.extend_from_slice(
+        format!(
+            concat!(
+                "From: bill@example.com\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: {}\r\n",
+                "\r\n",
+                "Test message.\r\n"
+            ),
+            subject
+        )
+        .as_bytes(),
+    );
+    let status = server
+        .mail_ingest(
+            "bill@example.com".to_string(),
+            AHashSet::from_iter([account_id]),
+            message,
+            None,
+            Some(scan_result.score),
+        )
+        .await
+        .unwrap();
+    assert!(
+        matches!(
+            status.get(&account_id),
+            Some(DeliveryStatus::Success | DeliveryStatus::PermanentFailure { .. })
+        ),
+        "{:?}",
+        status
+    );
+}
+
+async fn assert_filed(client: &mut Client, subject: &str, expected: Option<&String>) {
+    let mut email_ids = client
+        .email_query(
+            email::query::Filter::subject(subject).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids();
+    match expected {
+        Some(mailbox_id) => {
+            assert_eq!(email_ids.len(), 1, "{}", subject);
+            let email = client
+                .email_get(
+                    &email_ids.pop().unwrap(),
+                    [Property::MailboxIds, Property::Keywords].into(),
+                )
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(email.mailbox_ids(), &[mailbox_id.as_str()], "{}", subject);
+            assert_eq!(
+                email.keywords().contains(&"$junk"),
+                mailbox_id != &JMAPId::new(INBOX_ID as u64).to_string(),
+                "{}",
+                subject
+            );
+        }
+        None => assert!(email_ids.is_empty(), "{}", subject),
+    }
+}
+
+fn set_spam_filter<T>(server: &JMAPServer<T>, account_id: &str, spam_filter: Value)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request: SetRequest<Principal> = serde_json::from_value(json!({
+        "accountId": JMAPId::new(SUPERUSER_ID as u64).to_string(),
+        "update": {
+            account_id: {
+                "spamFilter": spam_filter
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = server.store.get_acl_token(SUPERUSER_ID).unwrap().into();
+    let response = server.store.principal_set(request).unwrap();
+    assert!(
+        response.not_updated.is_empty(),
+        "{:?}",
+        response.not_updated
+    );
+}
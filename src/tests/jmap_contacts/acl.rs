@@ -0,0 +1,440 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::web;
+use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use jmap_client::client::Client;
+use jmap_sharing::principal::set::JMAPSetPrincipal;
+use serde_json::json;
+use store::Store;
+
+use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
+
+use super::{created_id, jmap_request};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running Contacts ACL tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let john_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let jane_id = client
+        .individual_create("jane.smith@example.com", "abcde", "Jane Smith")
+        .await
+        .unwrap()
+        .take_id();
+    let bill_id = client
+        .individual_create("bill@example.com", "098765", "Bill Foobar")
+        .await
+        .unwrap()
+        .take_id();
+    let john = Some(("jdoe@example.com", "12345"));
+    let jane = Some(("jane.smith@example.com", "abcde"));
+    let bill = Some(("bill@example.com", "098765"));
+
+    // John creates a shared and a private address book
+    let response = jmap_request(
+        &server,
+        john,
+        "AddressBook/set",
+        json!({
+            "accountId": john_id,
+            "create": {
+                "shared": { "name": "Shared" },
+                "private": { "name": "Private" }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    let shared_book_id = created_id(&response, "shared");
+    let private_book_id = created_id(&response, "private");
+    let response = jmap_request(
+        &server,
+        john,
+        "ContactCard/set",
+        json!({
+            "accountId": john_id,
+            "create": {
+                "shared": {
+                    "addressBookIds": { &shared_book_id: true },
+                    "name": { "full": "Shared Contact" }
+                },
+                "private": {
+                    "addressBookIds": { &private_book_id: true },
+                    "name": { "full": "Private Contact" }
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    let shared_card_id = created_id(&response, "shared");
+    let private_card_id = created_id(&response, "private");
+
+    // Jane has no access to John's account yet
+    assert_eq!(
+        jmap_request(
+            &server,
+            jane,
+            "AddressBook/get",
+            json!({ "accountId": john_id }),
+        )
+        .await
+        .unwrap_err(),
+        "forbidden"
+    );
+
+    // Share the address book with Jane as read-only
+    let response = jmap_request(
+        &server,
+        john,
+        "AddressBook/set",
+        json!({
+            "accountId": john_id,
+            "update": {
+                &shared_book_id: {
+                    "acl": {
+                        "jane.smith@example.com": ["read", "readItems"]
+                    }
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    assert!(
+        response["updated"]
+            .as_object()
+            .unwrap()
+            .contains_key(&shared_book_id),
+        "{}",
+        response
+    );
+    let response = jmap_request(
+        &server,
+        john,
+        "AddressBook/get",
+        json!({
+            "accountId": john_id,
+            "ids": [&shared_book_id],
+            "properties": ["acl"]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        response["list"],
+        json!([{
+            "id": shared_book_id,
+            "acl": { "jane.smith@example.com": ["read", "readItems"] }
+        }])
+    );
+
+    // Jane can only see the shared address book and its contents
+    let response = jmap_request(
+        &server,
+        jane,
+        "AddressBook/get",
+        json!({
+            "accountId": john_id,
+            "properties": ["name", "myRights"]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        response["list"],
+        json!([{
+            "id": shared_book_id,
+            "name": "Shared",
+            "myRights": {
+                "mayRead": true,
+                "mayWrite": false,
+                "mayShare": false,
+                "mayDelete": false
+            }
+        }])
+    );
+    let response = jmap_request(
+        &server,
+        jane,
+        "ContactCard/get",
+        json!({
+            "accountId": john_id,
+            "ids": [&shared_card_id, &private_card_id],
+            "properties": ["name"]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        response["list"],
+        json!([{
+            "id": shared_card_id,
+            "name": { "full": "Shared Contact" }
+        }])
+    );
+    assert_eq!(response["notFound"], json!([&private_card_id]));
+    let response = jmap_request(
+        &server,
+        jane,
+        "ContactCard/query",
+        json!({ "accountId": john_id }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["ids"], json!([&shared_card_id]));
+
+    // Read-only access does not allow changes
+    let response = jmap_request(
+        &server,
+        jane,
+        "ContactCard/set",
+        json!({
+            "accountId": john_id,
+            "create": {
+                "card": {
+                    "addressBookIds": { &shared_book_id: true },
+                    "name": { "full": "Jane's Contact" }
+                }
+            },
+            "update": {
+                &shared_card_id: {
+                    "name": { "full": "Renamed Contact" }
+                }
+            },
+            "destroy": [&shared_card_id]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["notCreated"]["card"]["type"], "forbidden");
+    assert_eq!(response["notUpdated"][&shared_card_id]["type"], "forbidden");
+    assert_eq!(
+        response["notDestroyed"][&shared_card_id]["type"],
+        "forbidden"
+    );
+    let response = jmap_request(
+        &server,
+        jane,
+        "AddressBook/set",
+        json!({
+            "accountId": john_id,
+            "update": {
+                &shared_book_id: {
+                    "name": "Jane's book"
+                }
+            },
+            "destroy": [&shared_book_id]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["notUpdated"][&shared_book_id]["type"], "forbidden");
+    assert_eq!(
+        response["notDestroyed"][&shared_book_id]["type"],
+        "forbidden"
+    );
+
+    // Grant Jane write access to the contacts
+    jmap_request(
+        &server,
+        john,
+        "AddressBook/set",
+        json!({
+            "accountId": john_id,
+            "update": {
+                &shared_book_id: {
+                    "acl/jane.smith@example.com": [
+                        "read",
+                        "readItems",
+                        "addItems",
+                        "modifyItems",
+                        "removeItems"
+                    ]
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    let response = jmap_request(
+        &server,
+        jane,
+        "AddressBook/get",
+        json!({
+            "accountId": john_id,
+            "properties": ["myRights"]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["list"][0]["myRights"]["mayWrite"], true);
+    let response = jmap_request(
+        &server,
+        jane,
+        "ContactCard/set",
+        json!({
+            "accountId": john_id,
+            "create": {
+                "card": {
+                    "addressBookIds": { &shared_book_id: true },
+                    "name": { "full": "Jane's Contact" }
+                },
+                "private": {
+                    "addressBookIds": { &private_book_id: true },
+                    "name": { "full": "Sneaky Contact" }
+                }
+            },
+            "update": {
+                &shared_card_id: {
+                    "name": { "full": "Renamed Contact" }
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    let jane_card_id = created_id(&response, "card");
+    assert_eq!(response["notCreated"]["private"]["type"], "forbidden");
+    assert!(
+        response["updated"]
+            .as_object()
+            .unwrap()
+            .contains_key(&shared_card_id),
+        "{}",
+        response
+    );
+    let response = jmap_request(
+        &server,
+        john,
+        "ContactCard/get",
+        json!({
+            "accountId": john_id,
+            "ids": [&shared_card_id, &jane_card_id],
+            "properties": ["name"]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        response["list"],
+        json!([
+            {
+                "id": shared_card_id,
+                "name": { "full": "Renamed Contact" }
+            },
+            {
+                "id": jane_card_id,
+                "name": { "full": "Jane's Contact" }
+            }
+        ])
+    );
+    let response = jmap_request(
+        &server,
+        jane,
+        "ContactCard/set",
+        json!({
+            "accountId": john_id,
+            "destroy": [&jane_card_id]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        response["destroyed"],
+        json!([&jane_card_id]),
+        "{}",
+        response
+    );
+
+    // Bill has no access to John's contacts
+    for method in ["AddressBook/get", "ContactCard/get", "ContactCard/query"] {
+        assert_eq!(
+            jmap_request(&server, bill, method, json!({ "accountId": john_id }))
+                .await
+                .unwrap_err(),
+            "forbidden"
+        );
+    }
+
+    // Revoke Jane's access
+    jmap_request(
+        &server,
+        john,
+        "AddressBook/set",
+        json!({
+            "accountId": john_id,
+            "update": {
+                &shared_book_id: {
+                    "acl": {}
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    for method in ["AddressBook/get", "ContactCard/get", "ContactCard/query"] {
+        assert_eq!(
+            jmap_request(&server, jane, method, json!({ "accountId": john_id }))
+                .await
+                .unwrap_err(),
+            "forbidden"
+        );
+    }
+
+    // Remove test data
+    jmap_request(
+        &server,
+        john,
+        "AddressBook/set",
+        json!({
+            "accountId": john_id,
+            "destroy": [&shared_book_id, &private_book_id],
+            "onDestroyRemoveContents": true
+        }),
+    )
+    .await
+    .unwrap();
+    for account_id in [&john_id, &jane_id, &bill_id, &domain_id] {
+        client
+            .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+            .principal_destroy(account_id)
+            .await
+            .unwrap();
+    }
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
@@ -0,0 +1,310 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::web;
+use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use jmap_client::client::Client;
+use jmap_sharing::principal::set::JMAPSetPrincipal;
+use serde_json::json;
+use store::Store;
+
+use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
+
+use super::{created_id, jmap_request};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running AddressBook tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let credentials = Some(("jdoe@example.com", "12345"));
+
+    // Obtain the initial state
+    let response = jmap_request(
+        &server,
+        credentials,
+        "AddressBook/get",
+        json!({ "accountId": account_id }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["list"], json!([]));
+    let initial_state = response["state"].as_str().unwrap().to_string();
+
+    // Create two address books
+    let response = jmap_request(
+        &server,
+        credentials,
+        "AddressBook/set",
+        json!({
+            "accountId": account_id,
+            "create": {
+                "personal": {
+                    "name": "Personal",
+                    "description": "Friends and family",
+                    "sortOrder": 1
+                },
+                "work": {
+                    "name": "Work"
+                },
+                "invalid": {
+                    "description": "Missing name"
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    let personal_id = created_id(&response, "personal");
+    let work_id = created_id(&response, "work");
+    assert_eq!(
+        response["notCreated"]["invalid"]["type"], "invalidProperties",
+        "{}",
+        response
+    );
+
+    let response = jmap_request(
+        &server,
+        credentials,
+        "AddressBook/get",
+        json!({
+            "accountId": account_id,
+            "ids": [&personal_id, &work_id]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        response["list"],
+        json!([
+            {
+                "id": personal_id,
+                "name": "Personal",
+                "description": "Friends and family",
+                "sortOrder": 1,
+                "isDefault": true,
+                "isSubscribed": false,
+                "myRights": {
+                    "mayRead": true,
+                    "mayWrite": true,
+                    "mayShare": true,
+                    "mayDelete": true
+                }
+            },
+            {
+                "id": work_id,
+                "name": "Work",
+                "description": null,
+                "sortOrder": 0,
+                "isDefault": false,
+                "isSubscribed": false,
+                "myRights": {
+                    "mayRead": true,
+                    "mayWrite": true,
+                    "mayShare": true,
+                    "mayDelete": true
+                }
+            }
+        ])
+    );
+
+    // Both address books are reported as created
+    let response = jmap_request(
+        &server,
+        credentials,
+        "AddressBook/changes",
+        json!({
+            "accountId": account_id,
+            "sinceState": initial_state
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["created"], json!([&personal_id, &work_id]));
+    assert_eq!(response["updated"], json!([]));
+    assert_eq!(response["destroyed"], json!([]));
+    let state = response["newState"].as_str().unwrap().to_string();
+
+    // Rename and subscribe to an address book
+    let response = jmap_request(
+        &server,
+        credentials,
+        "AddressBook/set",
+        json!({
+            "accountId": account_id,
+            "update": {
+                &work_id: {
+                    "name": "Office",
+                    "isSubscribed": true
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    assert!(
+        response["updated"]
+            .as_object()
+            .unwrap()
+            .contains_key(&work_id),
+        "{}",
+        response
+    );
+    let response = jmap_request(
+        &server,
+        credentials,
+        "AddressBook/get",
+        json!({
+            "accountId": account_id,
+            "ids": [&work_id],
+            "properties": ["name", "isSubscribed"]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        response["list"],
+        json!([{ "id": work_id, "name": "Office", "isSubscribed": true }])
+    );
+    let response = jmap_request(
+        &server,
+        credentials,
+        "AddressBook/changes",
+        json!({
+            "accountId": account_id,
+            "sinceState": state
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["created"], json!([]));
+    assert_eq!(response["updated"], json!([&work_id]));
+    let state = response["newState"].as_str().unwrap().to_string();
+
+    // Address books with contacts are only destroyed on request
+    let response = jmap_request(
+        &server,
+        credentials,
+        "ContactCard/set",
+        json!({
+            "accountId": account_id,
+            "create": {
+                "card": {
+                    "addressBookIds": { &work_id: true },
+                    "name": { "full": "Jane Smith" }
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    let card_id = created_id(&response, "card");
+    let response = jmap_request(
+        &server,
+        credentials,
+        "AddressBook/set",
+        json!({
+            "accountId": account_id,
+            "destroy": [&work_id]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        response["notDestroyed"][&work_id]["type"], "addressBookHasContents",
+        "{}",
+        response
+    );
+    let response = jmap_request(
+        &server,
+        credentials,
+        "AddressBook/set",
+        json!({
+            "accountId": account_id,
+            "destroy": [&work_id],
+            "onDestroyRemoveContents": true
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["destroyed"], json!([&work_id]), "{}", response);
+    let response = jmap_request(
+        &server,
+        credentials,
+        "ContactCard/get",
+        json!({
+            "accountId": account_id,
+            "ids": [&card_id]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["notFound"], json!([&card_id]));
+
+    let response = jmap_request(
+        &server,
+        credentials,
+        "AddressBook/changes",
+        json!({
+            "accountId": account_id,
+            "sinceState": state
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["destroyed"], json!([&work_id]));
+
+    // Remove test data
+    jmap_request(
+        &server,
+        credentials,
+        "AddressBook/set",
+        json!({
+            "accountId": account_id,
+            "destroy": [&personal_id]
+        }),
+    )
+    .await
+    .unwrap();
+    for account_id in [&account_id, &domain_id] {
+        client
+            .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+            .principal_destroy(account_id)
+            .await
+            .unwrap();
+    }
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
@@ -0,0 +1,434 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::web;
+use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use jmap_client::client::Client;
+use jmap_sharing::principal::set::JMAPSetPrincipal;
+use serde_json::{json, Value};
+use store::Store;
+
+use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
+
+use super::{created_id, jmap_request};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running ContactCard tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let credentials = Some(("jdoe@example.com", "12345"));
+
+    let response = jmap_request(
+        &server,
+        credentials,
+        "AddressBook/set",
+        json!({
+            "accountId": account_id,
+            "create": {
+                "contacts": { "name": "Contacts" },
+                "other": { "name": "Other" }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    let book_id = created_id(&response, "contacts");
+    let other_book_id = created_id(&response, "other");
+
+    // Cards have to belong to an address book
+    let response = jmap_request(
+        &server,
+        credentials,
+        "ContactCard/set",
+        json!({
+            "accountId": account_id,
+            "create": {
+                "orphan": {
+                    "name": { "full": "Nobody" }
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        response["notCreated"]["orphan"]["type"], "invalidProperties",
+        "{}",
+        response
+    );
+
+    // Create contact cards
+    let response = jmap_request(
+        &server,
+        credentials,
+        "ContactCard/set",
+        json!({
+            "accountId": account_id,
+            "create": {
+                "john": {
+                    "addressBookIds": { &book_id: true },
+                    "uid": "urn:uuid:9f8e7d6c-0000-4000-8000-000000000001",
+                    "name": {
+                        "full": "John Doe",
+                        "components": [
+                            { "kind": "given", "value": "John" },
+                            { "kind": "surname", "value": "Doe" }
+                        ]
+                    },
+                    "emails": {
+                        "e1": { "address": "john@example.com" }
+                    }
+                },
+                "jane": {
+                    "addressBookIds": { &book_id: true, &other_book_id: true },
+                    "name": {
+                        "full": "Jane Smith",
+                        "components": [
+                            { "kind": "given", "value": "Jane" },
+                            { "kind": "surname", "value": "Smith" }
+                        ]
+                    },
+                    "phones": {
+                        "p1": { "number": "+1 555 0100" }
+                    },
+                    "keywords": { "vip": true }
+                },
+                "bill": {
+                    "addressBookIds": { &other_book_id: true },
+                    "name": {
+                        "full": "Bill Foobar",
+                        "components": [
+                            { "kind": "given", "value": "Bill" },
+                            { "kind": "surname", "value": "Foobar" }
+                        ]
+                    },
+                    "organizations": {
+                        "o1": { "name": "Acme Corporation" }
+                    }
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    let john_id = created_id(&response, "john");
+    let jane_id = created_id(&response, "jane");
+    let bill_id = created_id(&response, "bill");
+    assert_eq!(
+        response["created"]["john"]["uid"],
+        "urn:uuid:9f8e7d6c-0000-4000-8000-000000000001"
+    );
+    assert!(response["created"]["bill"]["uid"]
+        .as_str()
+        .unwrap()
+        .starts_with("urn:uuid:"));
+
+    // Obtain the current state
+    let response = jmap_request(
+        &server,
+        credentials,
+        "ContactCard/get",
+        json!({
+            "accountId": account_id,
+            "ids": []
+        }),
+    )
+    .await
+    .unwrap();
+    let state = response["state"].as_str().unwrap().to_string();
+
+    // Fetch the cards back
+    let response = jmap_request(
+        &server,
+        credentials,
+        "ContactCard/get",
+        json!({
+            "accountId": account_id,
+            "ids": [&john_id, &jane_id],
+            "properties": ["addressBookIds", "@type", "uid", "name", "emails", "phones", "keywords"]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        response["list"],
+        json!([
+            {
+                "id": john_id,
+                "addressBookIds": { &book_id: true },
+                "@type": "Card",
+                "uid": "urn:uuid:9f8e7d6c-0000-4000-8000-000000000001",
+                "name": {
+                    "full": "John Doe",
+                    "components": [
+                        { "kind": "given", "value": "John" },
+                        { "kind": "surname", "value": "Doe" }
+                    ]
+                },
+                "emails": {
+                    "e1": { "address": "john@example.com" }
+                },
+                "phones": null,
+                "keywords": null
+            },
+            {
+                "id": jane_id,
+                "addressBookIds": { &book_id: true, &other_book_id: true },
+                "@type": "Card",
+                "uid": response["list"][1]["uid"],
+                "name": {
+                    "full": "Jane Smith",
+                    "components": [
+                        { "kind": "given", "value": "Jane" },
+                        { "kind": "surname", "value": "Smith" }
+                    ]
+                },
+                "emails": null,
+                "phones": {
+                    "p1": { "number": "+1 555 0100" }
+                },
+                "keywords": { "vip": true }
+            }
+        ])
+    );
+
+    // Patch a card and make sure the uid is immutable
+    let response = jmap_request(
+        &server,
+        credentials,
+        "ContactCard/set",
+        json!({
+            "accountId": account_id,
+            "update": {
+                &john_id: {
+                    "emails/e1/address": "jdoe@example.org",
+                    "notes": { "n1": { "note": "Met at the conference" } }
+                },
+                &jane_id: {
+                    "uid": "urn:uuid:9f8e7d6c-0000-4000-8000-000000000002"
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    assert!(
+        response["updated"]
+            .as_object()
+            .unwrap()
+            .contains_key(&john_id),
+        "{}",
+        response
+    );
+    assert_eq!(
+        response["notUpdated"][&jane_id]["type"], "invalidProperties",
+        "{}",
+        response
+    );
+    let response = jmap_request(
+        &server,
+        credentials,
+        "ContactCard/get",
+        json!({
+            "accountId": account_id,
+            "ids": [&john_id],
+            "properties": ["emails", "notes"]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        response["list"],
+        json!([{
+            "id": john_id,
+            "emails": {
+                "e1": { "address": "jdoe@example.org" }
+            },
+            "notes": { "n1": { "note": "Met at the conference" } }
+        }])
+    );
+
+    // Query cards
+    for (filter, sort, expected_results) in [
+        (
+            json!({ "name": "smith" }),
+            json!([]),
+            vec![jane_id.as_str()],
+        ),
+        (
+            json!({ "email": "jdoe@example.org" }),
+            json!([]),
+            vec![john_id.as_str()],
+        ),
+        (json!({ "email": "john@example.com" }), json!([]), vec![]),
+        (json!({ "phone": "555" }), json!([]), vec![jane_id.as_str()]),
+        (json!({ "text": "acme" }), json!([]), vec![bill_id.as_str()]),
+        (
+            json!({ "text": "conference" }),
+            json!([]),
+            vec![john_id.as_str()],
+        ),
+        (json!({ "text": "vip" }), json!([]), vec![jane_id.as_str()]),
+        (
+            json!({ "inAddressBook": &book_id }),
+            json!([{ "property": "name/surname" }]),
+            vec![john_id.as_str(), jane_id.as_str()],
+        ),
+        (
+            json!({ "inAddressBook": &other_book_id }),
+            json!([{ "property": "name/given" }]),
+            vec![bill_id.as_str(), jane_id.as_str()],
+        ),
+        (
+            json!({}),
+            json!([{ "property": "name/surname" }]),
+            vec![john_id.as_str(), bill_id.as_str(), jane_id.as_str()],
+        ),
+        (
+            json!({}),
+            json!([{ "property": "name/given", "isAscending": false }]),
+            vec![john_id.as_str(), jane_id.as_str(), bill_id.as_str()],
+        ),
+        (
+            json!({
+                "operator": "AND",
+                "conditions": [
+                    { "inAddressBook": &other_book_id },
+                    { "operator": "NOT", "conditions": [{ "text": "vip" }] }
+                ]
+            }),
+            json!([]),
+            vec![bill_id.as_str()],
+        ),
+    ] {
+        let response = jmap_request(
+            &server,
+            credentials,
+            "ContactCard/query",
+            json!({
+                "accountId": account_id,
+                "filter": filter,
+                "sort": sort
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            response["ids"],
+            json!(expected_results),
+            "filter: {}, sort: {}",
+            filter,
+            sort
+        );
+    }
+    assert_eq!(
+        jmap_request(
+            &server,
+            credentials,
+            "ContactCard/query",
+            json!({
+                "accountId": account_id,
+                "filter": { "nickname": "johnny" }
+            }),
+        )
+        .await
+        .unwrap_err(),
+        "unsupportedFilter"
+    );
+
+    // Destroy a card and check the changes
+    let response = jmap_request(
+        &server,
+        credentials,
+        "ContactCard/set",
+        json!({
+            "accountId": account_id,
+            "destroy": [&bill_id]
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["destroyed"], json!([&bill_id]), "{}", response);
+    let response = jmap_request(
+        &server,
+        credentials,
+        "ContactCard/changes",
+        json!({
+            "accountId": account_id,
+            "sinceState": state
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["created"], json!([]));
+    assert_eq!(response["updated"], json!([&john_id]));
+    assert_eq!(response["destroyed"], json!([&bill_id]));
+
+    // Remove test data, Jane's card is only removed from the first address book
+    for book_id in [&book_id, &other_book_id] {
+        let response = jmap_request(
+            &server,
+            credentials,
+            "AddressBook/set",
+            json!({
+                "accountId": account_id,
+                "destroy": [book_id],
+                "onDestroyRemoveContents": true
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response["destroyed"], json!([book_id]), "{}", response);
+    }
+    let response = jmap_request(
+        &server,
+        credentials,
+        "ContactCard/query",
+        json!({ "accountId": account_id }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["ids"], Value::Array(vec![]));
+    for account_id in [&account_id, &domain_id] {
+        client
+            .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+            .principal_destroy(account_id)
+            .await
+            .unwrap();
+    }
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
@@ -0,0 +1,319 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use actix_web::web;
+use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use jmap_client::client::Client;
+use jmap_sharing::principal::set::JMAPSetPrincipal;
+use reqwest::{header, Method, StatusCode};
+use serde_json::json;
+use store::Store;
+
+use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
+
+use super::{created_id, jmap_request};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running CardDAV tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let john_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let john = ("jdoe@example.com", "12345");
+
+    let response = jmap_request(
+        &server,
+        Some(john),
+        "AddressBook/set",
+        json!({
+            "accountId": john_id,
+            "create": {
+                "book": { "name": "Contacts" }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    let book_id = created_id(&response, "book");
+    let book_href = format!("/dav/card/{}/{}/", john_id, book_id);
+    let card_href = format!("{}custom-name.vcf", book_href);
+    let vcard = |name: &str| {
+        format!(
+            "BEGIN:VCARD\r\nVERSION:4.0\r\nUID:dav-uid-1\r\nFN:{}\r\nEND:VCARD\r\n",
+            name
+        )
+    };
+
+    // Create a resource under a name that is not derived from its uid
+    let (status, _, _) = dav_request(
+        &server,
+        john,
+        "PUT",
+        &card_href,
+        &[("If-None-Match", "*")],
+        vcard("John Smith"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _, _) = dav_request(
+        &server,
+        john,
+        "PUT",
+        &card_href,
+        &[("If-None-Match", "*")],
+        vcard("John Smith"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+
+    // The resource is served under the name chosen by the client
+    let (status, etag, body) =
+        dav_request(&server, john, "GET", &card_href, &[], String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("FN:John Smith"), "{}", body);
+    let etag = etag.unwrap();
+    let (status, _, _) = dav_request(
+        &server,
+        john,
+        "GET",
+        &format!("{}dav-uid-1.vcf", book_href),
+        &[],
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _, body) = dav_request(
+        &server,
+        john,
+        "PROPFIND",
+        &book_href,
+        &[("Depth", "1")],
+        String::new(),
+    )
+    .await;
+    assert_eq!(status.as_u16(), 207);
+    assert!(body.contains(&card_href), "{}", body);
+    let (status, _, body) = dav_request(
+        &server,
+        john,
+        "REPORT",
+        &book_href,
+        &[],
+        format!(
+            concat!(
+                "<C:addressbook-multiget xmlns:D=\"DAV:\" ",
+                "xmlns:C=\"urn:ietf:params:xml:ns:carddav\">",
+                "<D:prop><D:getetag/></D:prop>",
+                "<D:href>{}</D:href></C:addressbook-multiget>"
+            ),
+            card_href
+        ),
+    )
+    .await;
+    assert_eq!(status.as_u16(), 207);
+    assert!(body.contains(etag.trim_matches('"')), "{}", body);
+
+    // The same uid cannot be stored under a second name
+    let (status, _, _) = dav_request(
+        &server,
+        john,
+        "PUT",
+        &format!("{}other-name.vcf", book_href),
+        &[],
+        vcard("John Smith"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // Objects created over JMAP are named after their uid
+    let response = jmap_request(
+        &server,
+        Some(john),
+        "ContactCard/set",
+        json!({
+            "accountId": john_id,
+            "create": {
+                "card": {
+                    "uid": "jmap-uid",
+                    "addressBookIds": { &book_id: true },
+                    "name": { "full": "Jane Smith" }
+                }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    created_id(&response, "card");
+    let (status, _, body) = dav_request(
+        &server,
+        john,
+        "GET",
+        &format!("{}jmap-uid.vcf", book_href),
+        &[],
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("FN:Jane Smith"), "{}", body);
+
+    // Updates require the current etag
+    let (status, _, _) = dav_request(
+        &server,
+        john,
+        "PUT",
+        &card_href,
+        &[("If-Match", "\"0000\"")],
+        vcard("John Doe"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let (status, _, _) = dav_request(
+        &server,
+        john,
+        "PUT",
+        &card_href,
+        &[("If-Match", &etag)],
+        vcard("John Doe"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, new_etag, body) =
+        dav_request(&server, john, "GET", &card_href, &[], String::new()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body.contains("FN:John Doe"), "{}", body);
+    let new_etag = new_etag.unwrap();
+    assert_ne!(etag, new_etag);
+    let (status, _, _) = dav_request(
+        &server,
+        john,
+        "PUT",
+        &card_href,
+        &[("If-Match", &etag)],
+        vcard("John Smith"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+
+    // Deletes require the current etag as well
+    let (status, _, _) = dav_request(
+        &server,
+        john,
+        "DELETE",
+        &card_href,
+        &[("If-Match", &etag)],
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::PRECONDITION_FAILED);
+    let (status, _, _) = dav_request(
+        &server,
+        john,
+        "DELETE",
+        &card_href,
+        &[("If-Match", &new_etag)],
+        String::new(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    for method in ["GET", "DELETE"] {
+        let (status, _, _) =
+            dav_request(&server, john, method, &card_href, &[], String::new()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    // Remove test data
+    jmap_request(
+        &server,
+        Some(john),
+        "AddressBook/set",
+        json!({
+            "accountId": john_id,
+            "destroy": [&book_id],
+            "onDestroyRemoveContents": true
+        }),
+    )
+    .await
+    .unwrap();
+    for account_id in [&john_id, &domain_id] {
+        client
+            .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+            .principal_destroy(account_id)
+            .await
+            .unwrap();
+    }
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
+
+// Sends a DAV request, returning the status code, the etag and the body of
+// the response.
+async fn dav_request<T>(
+    server: &web::Data<JMAPServer<T>>,
+    (username, secret): (&str, &str),
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: String,
+) -> (StatusCode, Option<String>, String)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let url = format!(
+        "{}{}",
+        server
+            .base_session
+            .api_url()
+            .strip_suffix("/jmap/")
+            .unwrap(),
+        path
+    );
+    let mut request = reqwest::Client::new()
+        .request(Method::from_bytes(method.as_bytes()).unwrap(), url)
+        .basic_auth(username, Some(secret))
+        .timeout(Duration::from_millis(1000))
+        .body(body);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    let etag = response
+        .headers()
+        .get(header::ETAG)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from);
+    (status, etag, response.text().await.unwrap())
+}
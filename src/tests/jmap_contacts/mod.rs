@@ -0,0 +1,108 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use actix_web::web;
+use reqwest::{header, StatusCode};
+use serde_json::{json, Value};
+use store::Store;
+use store_rocksdb::RocksDB;
+
+use crate::JMAPServer;
+
+use super::{jmap::init_jmap_tests, store::utils::destroy_temp_dir};
+
+pub mod acl;
+pub mod address_book;
+pub mod contact_card;
+pub mod dav;
+
+#[actix_web::test]
+#[ignore]
+async fn jmap_contacts_tests() {
+    let (server, mut client, temp_dir) = init_jmap_tests::<RocksDB>("jmap_contacts_tests").await;
+
+    // Run tests
+    address_book::test(server.clone(), &mut client).await;
+    contact_card::test(server.clone(), &mut client).await;
+    acl::test(server.clone(), &mut client).await;
+    dav::test(server.clone(), &mut client).await;
+
+    destroy_temp_dir(&temp_dir);
+}
+
+// Sends a single method call to the JMAP API, either as the administrator or
+// as the user with the given credentials, returning the response arguments or
+// the method error type.
+pub async fn jmap_request<T>(
+    server: &web::Data<JMAPServer<T>>,
+    credentials: Option<(&str, &str)>,
+    method: &str,
+    arguments: Value,
+) -> Result<Value, String>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let request = reqwest::Client::new().post(server.base_session.api_url());
+    let request = if let Some((username, secret)) = credentials {
+        request.basic_auth(username, Some(secret))
+    } else {
+        request.bearer_auth("DO_NOT_ATTEMPT_THIS_AT_HOME")
+    };
+    let response = request
+        .header(header::CONTENT_TYPE, "application/json")
+        .timeout(Duration::from_millis(1000))
+        .body(
+            json!({
+                "using": [
+                    "urn:ietf:params:jmap:core",
+                    "urn:ietf:params:jmap:contacts"
+                ],
+                "methodCalls": [[method, arguments, "c0"]]
+            })
+            .to_string(),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let mut response = serde_json::from_slice::<Value>(&response.bytes().await.unwrap()).unwrap();
+    let mut method_response = response["methodResponses"][0].take();
+    let arguments = method_response[1].take();
+    if method_response[0] != "error" {
+        Ok(arguments)
+    } else {
+        Err(arguments["type"].as_str().unwrap_or_default().to_string())
+    }
+}
+
+// Returns the id assigned to a created object, panicking with the full
+// response if it was not created.
+pub fn created_id(response: &Value, create_id: &str) -> String {
+    response["created"][create_id]["id"]
+        .as_str()
+        .unwrap_or_else(|| panic!("{} was not created: {}", create_id, response))
+        .to_string()
+}
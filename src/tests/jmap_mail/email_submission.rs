@@ -33,6 +33,7 @@ use jmap_client::{
     Error,
 };
 use jmap_sharing::principal::set::JMAPSetPrincipal;
+use serde_json::json;
 use store::{ahash::AHashMap, chrono::DateTime, parking_lot::Mutex, Store};
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
@@ -41,7 +42,11 @@ use tokio::{
 };
 
 use crate::{
-    tests::{jmap_mail::email_set::assert_email_properties, store::utils::StoreCompareWith},
+    tests::{
+        jmap_contacts::{created_id, jmap_request},
+        jmap_mail::email_set::assert_email_properties,
+        store::utils::StoreCompareWith,
+    },
     JMAPServer,
 };
 
@@ -461,6 +466,17 @@ where
 
     assert_email_properties(client, &email_id, &[&mailbox_id_2], &["$draft"]).await;
 
+    // Submissions by delegates
+    test_delegation(
+        &server,
+        client,
+        &mut smtp_rx,
+        &account_id,
+        &identity_id,
+        &mailbox_id_2,
+    )
+    .await;
+
     // Verify onSuccessDestroyEmail action
     smtp_settings.lock().do_stop = true;
     let mut request = client.build();
@@ -491,6 +507,230 @@ where
     server.store.assert_is_empty();
 }
 
+async fn test_delegation<T>(
+    server: &web::Data<JMAPServer<T>>,
+    client: &mut Client,
+    smtp_rx: &mut mpsc::Receiver<MockMessage>,
+    account_id: &str,
+    identity_id: &str,
+    mailbox_id: &str,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    let jane_id = client
+        .set_default_account_id(JMAPId::from(SUPERUSER_ID))
+        .individual_create("jane.smith@example.com", "abcde", "Jane Smith")
+        .await
+        .unwrap()
+        .take_id();
+    let bill_id = client
+        .individual_create("bill@example.com", "098765", "Bill Foobar")
+        .await
+        .unwrap()
+        .take_id();
+    let jane = Some(("jane.smith@example.com", "abcde"));
+    let bill = Some(("bill@example.com", "098765"));
+    client.set_default_account_id(account_id);
+
+    // Jane may send as John, Bill only on his behalf
+    for (method, id, acl) in [
+        (
+            "Identity/set",
+            identity_id,
+            json!({
+                "jane.smith@example.com": ["read", "submit"],
+                "bill@example.com": ["read", "sendOnBehalf"]
+            }),
+        ),
+        (
+            "Mailbox/set",
+            mailbox_id,
+            json!({
+                "jane.smith@example.com": ["read", "readItems", "removeItems"],
+                "bill@example.com": ["read", "readItems", "removeItems"]
+            }),
+        ),
+    ] {
+        let response = jmap_request(
+            server,
+            None,
+            method,
+            json!({
+                "accountId": account_id,
+                "update": { id: { "acl": acl } }
+            }),
+        )
+        .await
+        .unwrap();
+        assert!(response["updated"].get(id).is_some(), "{}", response);
+    }
+    for (credentials, rights) in [
+        (jane, json!({ "maySendAs": true, "maySendOnBehalf": true })),
+        (bill, json!({ "maySendAs": false, "maySendOnBehalf": true })),
+    ] {
+        let response = jmap_request(
+            server,
+            credentials,
+            "Identity/get",
+            json!({
+                "accountId": account_id,
+                "ids": [identity_id],
+                "properties": ["myRights"]
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response["list"][0]["myRights"], rights, "{}", response);
+    }
+
+    // Bill has no Sent mailbox
+    let bill_sent_id = sent_mailbox_id(server, bill, &bill_id).await;
+    jmap_request(
+        server,
+        bill,
+        "Mailbox/set",
+        json!({ "accountId": bill_id, "destroy": [bill_sent_id] }),
+    )
+    .await
+    .unwrap();
+
+    // Messages sent as the owner are delivered unchanged and filed in the
+    // delegate's Sent mailbox, replacing the owner's copy
+    let email_body =
+        "From: jdoe@example.com\r\nTo: jane_smith@example.com\r\nSubject: as\r\n\r\ntest";
+    let email_id = client
+        .email_import(
+            email_body.as_bytes().to_vec(),
+            [mailbox_id],
+            None::<Vec<&str>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    submit(server, jane, account_id, &email_id, identity_id).await;
+    assert_message_delivery(
+        smtp_rx,
+        MockMessage::new(
+            "<jdoe@example.com>",
+            ["<jane_smith@example.com>"],
+            email_body,
+        ),
+        false,
+    )
+    .await;
+    let jane_sent_id = sent_mailbox_id(server, jane, &jane_id).await;
+    let response = jmap_request(
+        server,
+        jane,
+        "Email/query",
+        json!({
+            "accountId": jane_id,
+            "filter": { "inMailbox": jane_sent_id }
+        }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(response["ids"].as_array().unwrap().len(), 1, "{}", response);
+    assert!(client
+        .email_get(&email_id, None::<Vec<_>>)
+        .await
+        .unwrap()
+        .is_none());
+
+    // Messages sent on behalf of the owner carry the delegate as the Sender,
+    // and the owner keeps the copy if the delegate has no Sent mailbox
+    let email_body =
+        "From: jdoe@example.com\r\nTo: jane_smith@example.com\r\nSubject: behalf\r\n\r\ntest";
+    let email_id = client
+        .email_import(
+            email_body.as_bytes().to_vec(),
+            [mailbox_id],
+            None::<Vec<&str>>,
+            None,
+        )
+        .await
+        .unwrap()
+        .take_id();
+    submit(server, bill, account_id, &email_id, identity_id).await;
+    assert_message_delivery(
+        smtp_rx,
+        MockMessage::new(
+            "<jdoe@example.com>",
+            ["<jane_smith@example.com>"],
+            format!(
+                "Sender: \"Bill Foobar\" <bill@example.com>\r\n{}",
+                email_body
+            )
+            .as_str(),
+        ),
+        false,
+    )
+    .await;
+    assert!(client
+        .email_get(&email_id, None::<Vec<_>>)
+        .await
+        .unwrap()
+        .is_some());
+
+    // Remove the delegates
+    client.email_destroy(&email_id).await.unwrap();
+    for account_id in [&jane_id, &bill_id] {
+        client
+            .set_default_account_id(JMAPId::from(SUPERUSER_ID))
+            .principal_destroy(account_id)
+            .await
+            .unwrap();
+    }
+    client.set_default_account_id(account_id);
+}
+
+async fn submit<T>(
+    server: &web::Data<JMAPServer<T>>,
+    credentials: Option<(&str, &str)>,
+    account_id: &str,
+    email_id: &str,
+    identity_id: &str,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    let response = jmap_request(
+        server,
+        credentials,
+        "EmailSubmission/set",
+        json!({
+            "accountId": account_id,
+            "create": {
+                "submission": { "emailId": email_id, "identityId": identity_id }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    created_id(&response, "submission");
+}
+
+async fn sent_mailbox_id<T>(
+    server: &web::Data<JMAPServer<T>>,
+    credentials: Option<(&str, &str)>,
+    account_id: &str,
+) -> String
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    jmap_request(
+        server,
+        credentials,
+        "Mailbox/query",
+        json!({ "accountId": account_id, "filter": { "role": "sent" } }),
+    )
+    .await
+    .unwrap()["ids"][0]
+        .as_str()
+        .unwrap()
+        .to_string()
+}
+
 pub fn spawn_mock_smtp_server() -> (mpsc::Receiver<MockMessage>, Arc<Mutex<MockSMTPSettings>>) {
     // Create channels
     let (event_tx, event_rx) = mpsc::channel::<MockMessage>(100);
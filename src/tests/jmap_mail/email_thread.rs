@@ -23,9 +23,26 @@
 
 use actix_web::web;
 
-use jmap::types::jmap::JMAPId;
+use jmap::{
+    request::{
+        get::GetRequest,
+        query::{Comparator as QueryComparator, Filter as QueryFilter, QueryRequest},
+        MaybeResultReference,
+    },
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
 use jmap_client::{client::Client, mailbox::Role};
-use store::Store;
+use jmap_mail::{
+    mail::schema::{Filter, Keyword},
+    thread::{
+        get::JMAPGetThread,
+        query::JMAPThreadQuery,
+        schema::{Comparator, Property, Thread},
+    },
+};
+use jmap_sharing::principal::account::JMAPAccountStore;
+use store::{core::vec_map::VecMap, Store};
 
 use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
 
@@ -69,7 +86,145 @@ where
         expected_result
     );
 
+    // Thread metadata is updated as messages are added, flagged and removed
+    let mut metadata_ids = Vec::new();
+    let mut metadata_thread_id = "".to_string();
+    for (num, (from, keyword)) in [
+        ("alice@example.com", Some("$seen")),
+        ("bob@example.com", Some("$flagged")),
+        ("carol@example.com", None),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let mut email = client
+            .email_import(
+                format!(
+                    "From: {}\nSubject: metadata\nReferences: <5678>\n\n{}",
+                    from, num
+                )
+                .into_bytes(),
+                [&mailbox_id],
+                keyword.map(|keyword| vec![keyword]),
+                Some(20000i64 + num as i64),
+            )
+            .await
+            .unwrap();
+        metadata_thread_id = email.thread_id().unwrap().to_string();
+        metadata_ids.push(email.take_id());
+    }
+    assert_ne!(metadata_thread_id, thread_id);
+
+    let thread = get_thread(&server, &metadata_thread_id);
+    assert_eq!(thread.received_at.unwrap().timestamp(), 20002);
+    assert_eq!(
+        thread.participants.unwrap(),
+        ["alice@example.com", "bob@example.com", "carol@example.com"]
+    );
+    assert_eq!(thread.unread_count, Some(2));
+    assert_eq!(
+        sorted_keywords(thread.keywords.unwrap()),
+        ["$flagged", "$seen"]
+    );
+
+    client
+        .email_set_keyword(&metadata_ids[2], "$seen", true)
+        .await
+        .unwrap();
+    client
+        .email_set_keyword(&metadata_ids[1], "$flagged", false)
+        .await
+        .unwrap();
+    let thread = get_thread(&server, &metadata_thread_id);
+    assert_eq!(thread.unread_count, Some(1));
+    assert_eq!(sorted_keywords(thread.keywords.unwrap()), ["$seen"]);
+
+    // Removing the most recent message recomputes the date and participants
+    client.email_destroy(&metadata_ids[2]).await.unwrap();
+    let thread = get_thread(&server, &metadata_thread_id);
+    assert_eq!(thread.received_at.unwrap().timestamp(), 20001);
+    assert_eq!(
+        thread.participants.unwrap(),
+        ["alice@example.com", "bob@example.com"]
+    );
+    assert_eq!(thread.unread_count, Some(1));
+    assert_eq!(sorted_keywords(thread.keywords.unwrap()), ["$seen"]);
+
+    // Thread/query sorts threads by their most recent message
+    for (is_ascending, expected_ids) in [
+        (false, [metadata_thread_id.clone(), thread_id.clone()]),
+        (true, [thread_id.clone(), metadata_thread_id.clone()]),
+    ] {
+        let response = server
+            .store
+            .thread_query(QueryRequest {
+                acl: server.store.get_acl_token(SUPERUSER_ID).unwrap().into(),
+                account_id: JMAPId::new(1),
+                filter: QueryFilter::FilterCondition(Filter::InMailbox {
+                    value: JMAPId::parse(&mailbox_id).unwrap(),
+                })
+                .into(),
+                sort: vec![QueryComparator {
+                    property: Comparator::ReceivedAt,
+                    is_ascending,
+                    collation: None,
+                }]
+                .into(),
+                position: None,
+                anchor: None,
+                anchor_offset: None,
+                limit: None,
+                calculate_total: true.into(),
+                arguments: (),
+            })
+            .unwrap();
+        assert_eq!(
+            response
+                .ids
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>(),
+            expected_ids
+        );
+        assert_eq!(response.total, Some(2));
+    }
+
     client.mailbox_destroy(&mailbox_id, true).await.unwrap();
 
     server.store.assert_is_empty();
 }
+
+fn get_thread<T>(server: &web::Data<JMAPServer<T>>, thread_id: &str) -> Thread
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    server
+        .store
+        .thread_get(GetRequest {
+            acl: server.store.get_acl_token(SUPERUSER_ID).unwrap().into(),
+            account_id: JMAPId::new(1),
+            ids: MaybeResultReference::Value(vec![JMAPId::parse(thread_id).unwrap()]).into(),
+            properties: MaybeResultReference::Value(vec![
+                Property::Id,
+                Property::Participants,
+                Property::ReceivedAt,
+                Property::UnreadCount,
+                Property::Keywords,
+            ])
+            .into(),
+            arguments: (),
+        })
+        .unwrap()
+        .list
+        .pop()
+        .unwrap()
+}
+
+fn sorted_keywords(keywords: VecMap<Keyword, bool>) -> Vec<String> {
+    let mut keywords = keywords
+        .into_iter()
+        .map(|(keyword, _)| keyword.to_string())
+        .collect::<Vec<_>>();
+    keywords.sort_unstable();
+    keywords
+}
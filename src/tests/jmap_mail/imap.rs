@@ -0,0 +1,250 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use std::time::Duration;
+
+use actix_web::web;
+use jmap::{types::jmap::JMAPId, SUPERUSER_ID};
+use jmap_client::{
+    client::Client,
+    email,
+    mailbox::{self, Role},
+};
+use store::Store;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines, ReadHalf, WriteHalf},
+    net::TcpStream,
+};
+
+use crate::{
+    tests::{
+        jmap_mail::lmtp::{AssertResult, SmtpConnection},
+        store::utils::StoreCompareWith,
+    },
+    JMAPServer,
+};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running IMAP tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    client.set_default_account_id(&account_id);
+    let inbox_id = mailbox_id(client, Role::Inbox).await;
+    let trash_id = mailbox_id(client, Role::Trash).await;
+
+    // Deliver a few messages
+    let mut lmtp = SmtpConnection::connect().await;
+    lmtp.lhlo().await;
+    for subject in ["Alpha", "Bravo", "Charlie"] {
+        deliver(&mut lmtp, subject).await;
+    }
+
+    let mut imap = ImapConnection::connect().await;
+    imap.send_ok("LOGIN jdoe@example.com 12345").await;
+    imap.send_ok("SELECT INBOX")
+        .await
+        .assert_contains("* 3 EXISTS")
+        .assert_contains("[UIDNEXT 4]");
+    imap.send_ok("UID FETCH 1:* (UID)")
+        .await
+        .assert_contains("* 1 FETCH (UID 1)")
+        .assert_contains("* 3 FETCH (UID 3)");
+
+    // UIDs are not reused after an expunge, even when a new message is
+    // stored under the expunged message's document id
+    imap.send_ok("STORE 3 +FLAGS.SILENT (\\Deleted)").await;
+    imap.send_ok("EXPUNGE").await.assert_contains("* 3 EXPUNGE");
+    deliver(&mut lmtp, "Delta").await;
+    imap.send_ok("NOOP").await.assert_contains("* 3 EXISTS");
+    imap.send_ok("UID FETCH 1:* (UID)")
+        .await
+        .assert_contains("* 3 FETCH (UID 4)")
+        .assert_count("(UID 3)", 0);
+    imap.send_ok("STATUS INBOX (MESSAGES UIDNEXT)")
+        .await
+        .assert_contains("(MESSAGES 3 UIDNEXT 5)");
+
+    // Messages moved out and back through JMAP are given a new UID
+    let email_id = email_id(client, "Alpha").await;
+    client
+        .email_set_mailboxes(&email_id, [&trash_id])
+        .await
+        .unwrap();
+    client
+        .email_set_mailboxes(&email_id, [&inbox_id])
+        .await
+        .unwrap();
+    imap.send_ok("NOOP")
+        .await
+        .assert_contains("* 1 EXPUNGE")
+        .assert_contains("* 3 EXISTS");
+    imap.send_ok("UID FETCH 1:* (UID)")
+        .await
+        .assert_contains("* 1 FETCH (UID 2)")
+        .assert_contains("* 3 FETCH (UID 5)");
+
+    // When EXPUNGE responses can't be sent, removed messages keep their
+    // sequence number and new messages are appended at the end
+    client
+        .email_destroy(&email_id(client, "Bravo").await)
+        .await
+        .unwrap();
+    deliver(&mut lmtp, "Echo").await;
+    imap.send_ok("UID COPY 4 \"Deleted Items\"")
+        .await
+        .assert_contains("* 4 EXISTS")
+        .assert_count("EXPUNGE", 0)
+        .assert_contains(" 4 2] COPY completed");
+    imap.send_ok("UID FETCH 1:* (UID)")
+        .await
+        .assert_count("(UID 2)", 0)
+        .assert_contains("* 2 FETCH (UID 4)")
+        .assert_contains("* 4 FETCH (UID 6)");
+    imap.send_ok("NOOP").await.assert_contains("* 1 EXPUNGE");
+    imap.send_ok("SELECT INBOX")
+        .await
+        .assert_contains("* 3 EXISTS")
+        .assert_contains("[UIDNEXT 7]");
+    imap.send_ok("LOGOUT").await;
+    lmtp.quit().await;
+
+    // Remove test data
+    client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .principal_destroy(&account_id)
+        .await
+        .unwrap();
+    client.principal_destroy(&domain_id).await.unwrap();
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
+
+async fn deliver(lmtp: &mut SmtpConnection, subject: &str) {
+    lmtp.ingest(
+        "bill@example.com",
+        &["jdoe@example.com"],
+        &format!(
+            concat!(
+                "From: bill@example.com\r\n",
+                "To: jdoe@example.com\r\n",
+                "Subject: {}\r\n",
+                "\r\n",
+                "{} message.\r\n"
+            ),
+            subject, subject
+        ),
+    )
+    .await;
+}
+
+async fn mailbox_id(client: &mut Client, role: Role) -> String {
+    client
+        .mailbox_query(mailbox::query::Filter::role(role).into(), None::<Vec<_>>)
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap()
+}
+
+async fn email_id(client: &mut Client, subject: &str) -> String {
+    client
+        .email_query(
+            email::query::Filter::subject(subject).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap()
+}
+
+pub struct ImapConnection {
+    reader: Lines<BufReader<ReadHalf<TcpStream>>>,
+    writer: WriteHalf<TcpStream>,
+    tag: usize,
+}
+
+impl ImapConnection {
+    pub async fn connect() -> Self {
+        let (reader, writer) =
+            tokio::io::split(TcpStream::connect("127.0.0.1:11401").await.unwrap());
+        let mut conn = ImapConnection {
+            reader: BufReader::new(reader).lines(),
+            writer,
+            tag: 0,
+        };
+        conn.read_line().await.assert_contains("* OK");
+        conn
+    }
+
+    // Sends a command and returns its responses, including the tagged one.
+    pub async fn send_ok(&mut self, command: &str) -> Vec<String> {
+        self.tag += 1;
+        let tag = format!("A{}", self.tag);
+        println!("-> {:?}", command);
+        self.writer
+            .write_all(format!("{} {}\r\n", tag, command).as_bytes())
+            .await
+            .unwrap();
+
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_line().await.pop().unwrap();
+            let is_done = line.starts_with(&format!("{} ", tag));
+            lines.push(line);
+            if is_done {
+                if !lines.last().unwrap().starts_with(&format!("{} OK", tag)) {
+                    panic!("Expected OK response, got {:?}.", lines);
+                }
+                return lines;
+            }
+        }
+    }
+
+    async fn read_line(&mut self) -> Vec<String> {
+        match tokio::time::timeout(Duration::from_millis(1500), self.reader.next_line()).await {
+            Ok(Ok(Some(line))) => {
+                println!("<- {:?}", line);
+                vec![line]
+            }
+            Ok(Ok(None)) => panic!("Connection closed."),
+            Ok(Err(err)) => panic!("Connection broken: {}", err),
+            Err(_) => panic!("Timeout while waiting for server response."),
+        }
+    }
+}
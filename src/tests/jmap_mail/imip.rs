@@ -0,0 +1,247 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::web;
+use jmap::{
+    request::{get::GetRequest, query::QueryRequest, set::SetRequest},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_calendars::{
+    calendar::{schema::Calendar, set::JMAPSetCalendar},
+    calendar_event::{
+        get::JMAPGetCalendarEvent, query::JMAPCalendarEventQuery, schema::CalendarEvent,
+    },
+};
+use jmap_client::client::Client;
+use jmap_mail::mail::schema::AuthResult;
+use jmap_sharing::principal::{account::JMAPAccountStore, set::JMAPSetPrincipal};
+use serde_json::{json, Value};
+use store::{ahash::AHashSet, core::collection::Collection, AccountId, Store};
+
+use crate::{
+    lmtp::{ingest::DeliveryStatus, mail_auth::AuthOutput},
+    tests::store::utils::StoreCompareWith,
+    JMAPServer,
+};
+
+const SPOOFED: AuthOutput = AuthOutput {
+    result: AuthResult::Fail,
+    from_aligned: false,
+};
+const NO_DMARC: AuthOutput = AuthOutput {
+    result: AuthResult::None,
+    from_aligned: false,
+};
+const ALIGNED: AuthOutput = AuthOutput {
+    result: AuthResult::None,
+    from_aligned: true,
+};
+const DMARC_PASS: AuthOutput = AuthOutput {
+    result: AuthResult::Pass,
+    from_aligned: true,
+};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running iMIP tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let document_id = JMAPId::parse(&account_id).unwrap().get_document_id();
+
+    // Create a calendar to receive invitations
+    let acl = server.store.get_acl_token(SUPERUSER_ID).unwrap();
+    let mut request: SetRequest<Calendar> = serde_json::from_value(json!({
+        "accountId": account_id,
+        "create": {
+            "c1": {
+                "name": "Personal"
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = acl.clone().into();
+    let response = serde_json::to_value(server.store.calendar_set(request).unwrap()).unwrap();
+    let calendar_id = response["created"]["c1"]["id"]
+        .as_str()
+        .unwrap_or_else(|| panic!("Unexpected response: {}", response))
+        .to_string();
+
+    // Invitations with a forged or unauthenticated From are filed
+    // without creating an event
+    for (num, auth_result) in [Some(SPOOFED), Some(NO_DMARC), None]
+        .into_iter()
+        .enumerate()
+    {
+        deliver(&server, document_id, num, "REQUEST", auth_result).await;
+        assert_eq!(get_event(&server, &account_id).await, None);
+    }
+    assert_eq!(count_messages(&server, document_id), 3);
+
+    // Authenticated invitations are added to the calendar
+    deliver(&server, document_id, 3, "REQUEST", Some(ALIGNED)).await;
+    let event = get_event(&server, &account_id).await.unwrap();
+    assert_eq!(event["title"], "Quarterly review");
+    assert_ne!(event["status"], "cancelled");
+
+    // Cancellations are subject to the same checks
+    for (num, auth_result) in [Some(SPOOFED), Some(NO_DMARC), None]
+        .into_iter()
+        .enumerate()
+    {
+        deliver(&server, document_id, num + 4, "CANCEL", auth_result).await;
+        assert_ne!(
+            get_event(&server, &account_id).await.unwrap()["status"],
+            "cancelled"
+        );
+    }
+    deliver(&server, document_id, 7, "CANCEL", Some(DMARC_PASS)).await;
+    assert_eq!(
+        get_event(&server, &account_id).await.unwrap()["status"],
+        "cancelled"
+    );
+    assert_eq!(count_messages(&server, document_id), 8);
+
+    // Remove test data
+    let mut request: SetRequest<Calendar> = serde_json::from_value(json!({
+        "accountId": account_id,
+        "destroy": [calendar_id],
+        "onDestroyRemoveEvents": true
+    }))
+    .unwrap();
+    request.acl = acl.into();
+    server.store.calendar_set(request).unwrap();
+    for account_id in [&account_id, &domain_id] {
+        client
+            .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+            .principal_destroy(account_id)
+            .await
+            .unwrap();
+    }
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
+
+async fn deliver<T>(
+    server: &JMAPServer<T>,
+    account_id: AccountId,
+    num: usize,
+    method: &str,
+    auth_result: Option<AuthOutput>,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    let message = format!(
+        concat!(
+            "From: ceo@example.org\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Quarterly review\r\n",
+            "Message-ID: <imip-{}@example.org>\r\n",
+            "Content-Type: text/calendar; method={}; charset=utf-8\r\n",
+            "\r\n",
+            "BEGIN:VCALENDAR\r\n",
+            "VERSION:2.0\r\n",
+            "PRODID:-//Example//Calendar//EN\r\n",
+            "METHOD:{}\r\n",
+            "BEGIN:VEVENT\r\n",
+            "UID:review-1@example.org\r\n",
+            "SEQUENCE:{}\r\n",
+            "DTSTART:20221020T100000Z\r\n",
+            "DTEND:20221020T110000Z\r\n",
+            "SUMMARY:Quarterly review\r\n",
+            "ORGANIZER:mailto:ceo@example.org\r\n",
+            "ATTENDEE;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:jdoe@example.com\r\n",
+            "END:VEVENT\r\n",
+            "END:VCALENDAR\r\n",
+        ),
+        num, method, method, num,
+    );
+    let status = server
+        .mail_ingest(
+            "ceo@example.org".to_string(),
+            AHashSet::from_iter([account_id]),
+            message.into_bytes(),
+            auth_result,
+            None,
+        )
+        .await
+        .unwrap();
+    assert!(
+        matches!(status.get(&account_id), Some(DeliveryStatus::Success)),
+        "{:?}",
+        status
+    );
+}
+
+async fn get_event<T>(server: &JMAPServer<T>, account_id: &str) -> Option<Value>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let acl = server.store.get_acl_token(SUPERUSER_ID).unwrap();
+    let mut request: QueryRequest<CalendarEvent> = serde_json::from_value(json!({
+        "accountId": account_id,
+        "filter": {"uid": "review-1@example.org"}
+    }))
+    .unwrap();
+    request.acl = acl.clone().into();
+    let event_id = server
+        .store
+        .calendar_event_query(request)
+        .unwrap()
+        .ids
+        .pop()?;
+
+    let mut request: GetRequest<CalendarEvent> = serde_json::from_value(json!({
+        "accountId": account_id,
+        "ids": [event_id],
+        "properties": ["title", "status"]
+    }))
+    .unwrap();
+    request.acl = acl.into();
+    let mut response =
+        serde_json::to_value(server.store.calendar_event_get(request).unwrap()).unwrap();
+    Some(response["list"][0].take())
+}
+
+fn count_messages<T>(server: &JMAPServer<T>, account_id: AccountId) -> u64
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    server
+        .store
+        .get_document_ids(account_id, Collection::Mail)
+        .unwrap()
+        .map_or(0, |ids| ids.len())
+}
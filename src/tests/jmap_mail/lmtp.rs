@@ -357,8 +357,16 @@ impl SmtpConnection {
     }
 
     pub async fn connect_peer(peer_num: usize) -> Self {
+        SmtpConnection::connect_port(11200 + peer_num).await
+    }
+
+    pub async fn connect_submission() -> Self {
+        SmtpConnection::connect_port(11301).await
+    }
+
+    pub async fn connect_port(port: usize) -> Self {
         let (reader, writer) = tokio::io::split(
-            TcpStream::connect(format!("127.0.0.1:{}", 11200 + peer_num))
+            TcpStream::connect(format!("127.0.0.1:{}", port))
                 .await
                 .unwrap(),
         );
@@ -370,6 +378,11 @@ impl SmtpConnection {
         conn
     }
 
+    pub async fn ehlo(&mut self) -> Vec<String> {
+        self.send("EHLO localhost").await;
+        self.read(1, 2).await
+    }
+
     pub async fn lhlo(&mut self) -> Vec<String> {
         self.send("LHLO localhost").await;
         self.read(1, 2).await
@@ -35,9 +35,13 @@ pub mod email_set;
 pub mod email_submission;
 pub mod email_thread;
 pub mod email_thread_merge;
+pub mod imap;
+pub mod imip;
 pub mod lmtp;
 pub mod mailbox;
+pub mod saved_search;
 pub mod search_snippet;
+pub mod smtp_submission;
 pub mod vacation_response;
 
 #[actix_web::test]
@@ -56,10 +60,14 @@ async fn jmap_mail_tests() {
     email_query::test(server.clone(), &mut client).await;
     email_copy::test(server.clone(), &mut client).await;
     email_submission::test(server.clone(), &mut client).await;
+    smtp_submission::test(server.clone(), &mut client).await;
     lmtp::test(server.clone(), &mut client).await;
+    imip::test(server.clone(), &mut client).await;
+    imap::test(server.clone(), &mut client).await;
     vacation_response::test(server.clone(), &mut client).await;
     mailbox::test(server.clone(), &mut client).await;
     search_snippet::test(server.clone(), &mut client).await;
+    saved_search::test(server.clone(), &mut client).await;
 
     destroy_temp_dir(&temp_dir);
 }
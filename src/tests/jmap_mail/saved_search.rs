@@ -0,0 +1,199 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::web;
+use jmap::{
+    error::method::MethodError,
+    request::{query::QueryRequest, query_changes::QueryChangesRequest, set::SetRequest},
+    types::jmap::JMAPId,
+    SUPERUSER_ID,
+};
+use jmap_client::{client::Client, mailbox::Role};
+use jmap_mail::{
+    mail::{changes::JMAPMailChanges, query::JMAPMailQuery, schema::Email},
+    saved_search::{schema::SavedSearch, set::JMAPSetSavedSearch},
+};
+use jmap_sharing::principal::{account::JMAPAccountStore, set::JMAPSetPrincipal};
+use serde_json::json;
+use store::Store;
+
+use crate::{tests::store::utils::StoreCompareWith, JMAPServer};
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running SavedSearch tests...");
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let mailbox_id = client
+        .set_default_account_id(&account_id)
+        .mailbox_create("Saved searches", None::<String>, Role::None)
+        .await
+        .unwrap()
+        .take_id();
+
+    let mut email_ids = Vec::new();
+    for subject in ["Quarterly report", "Lunch on Friday"] {
+        email_ids.push(
+            JMAPId::parse(
+                client
+                    .email_import(
+                        format!("From: bill@example.com\nSubject: {}\n\ntest", subject)
+                            .into_bytes(),
+                        [&mailbox_id],
+                        None::<Vec<&str>>,
+                        None,
+                    )
+                    .await
+                    .unwrap()
+                    .id()
+                    .unwrap(),
+            )
+            .unwrap(),
+        );
+    }
+
+    // Create a saved search
+    let acl = server.store.get_acl_token(SUPERUSER_ID).unwrap();
+    let mut request: SetRequest<SavedSearch> = serde_json::from_value(json!({
+        "accountId": account_id,
+        "create": {
+            "s1": {
+                "name": "Reports",
+                "filter": {"subject": "report"}
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = acl.clone().into();
+    let response = serde_json::to_value(server.store.saved_search_set(request).unwrap()).unwrap();
+    let saved_search_id = response["created"]["s1"]["id"]
+        .as_str()
+        .unwrap_or_else(|| panic!("Unexpected response: {}", response))
+        .to_string();
+
+    // Email/query expands the saved search, but changes to its definition
+    // are not reflected in the query state.
+    let filter = json!({"inSavedSearch": saved_search_id});
+    let mut request: QueryRequest<Email> = serde_json::from_value(json!({
+        "accountId": account_id,
+        "filter": filter
+    }))
+    .unwrap();
+    request.acl = acl.clone().into();
+    let response = server.store.mail_query(request).unwrap();
+    assert_eq!(response.ids, vec![email_ids[0]]);
+    assert!(!response.can_calculate_changes);
+    let query_state = response.query_state;
+
+    for filter in [
+        filter.clone(),
+        json!({"operator": "AND", "conditions": [{"inMailbox": mailbox_id}, filter]}),
+    ] {
+        let mut request: QueryChangesRequest<Email> = serde_json::from_value(json!({
+            "accountId": account_id,
+            "filter": filter,
+            "sinceQueryState": query_state
+        }))
+        .unwrap();
+        request.acl = acl.clone().into();
+        assert!(matches!(
+            server.store.mail_query_changes(request),
+            Err(MethodError::CannotCalculateChanges)
+        ));
+    }
+
+    // Queries without saved searches can still calculate changes
+    let mut request: QueryRequest<Email> = serde_json::from_value(json!({
+        "accountId": account_id,
+        "filter": {"inMailbox": mailbox_id}
+    }))
+    .unwrap();
+    request.acl = acl.clone().into();
+    assert!(
+        server
+            .store
+            .mail_query(request)
+            .unwrap()
+            .can_calculate_changes
+    );
+
+    // Updating the saved search changes the results of the same filter
+    let mut request: SetRequest<SavedSearch> = serde_json::from_value(json!({
+        "accountId": account_id,
+        "update": {
+            &saved_search_id: {
+                "filter": {"subject": "lunch"}
+            }
+        }
+    }))
+    .unwrap();
+    request.acl = acl.clone().into();
+    let response = serde_json::to_value(server.store.saved_search_set(request).unwrap()).unwrap();
+    assert!(
+        response["updated"]
+            .as_object()
+            .map_or(false, |updated| updated.contains_key(&saved_search_id)),
+        "Unexpected response: {}",
+        response
+    );
+    let mut request: QueryRequest<Email> = serde_json::from_value(json!({
+        "accountId": account_id,
+        "filter": {"inSavedSearch": saved_search_id}
+    }))
+    .unwrap();
+    request.acl = acl.clone().into();
+    let response = server.store.mail_query(request).unwrap();
+    assert_eq!(response.ids, vec![email_ids[1]]);
+    assert_eq!(response.query_state, query_state);
+
+    // Remove test data
+    let mut request: SetRequest<SavedSearch> = serde_json::from_value(json!({
+        "accountId": account_id,
+        "destroy": [saved_search_id]
+    }))
+    .unwrap();
+    request.acl = acl.into();
+    server.store.saved_search_set(request).unwrap();
+    client.mailbox_destroy(&mailbox_id, true).await.unwrap();
+    for account_id in [&account_id, &domain_id] {
+        client
+            .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+            .principal_destroy(account_id)
+            .await
+            .unwrap();
+    }
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
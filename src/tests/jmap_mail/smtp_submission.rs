@@ -0,0 +1,382 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use actix_web::web;
+use jmap::{base64, request::set::SetRequest, types::jmap::JMAPId, SUPERUSER_ID};
+use jmap_client::{
+    client::Client,
+    email::{self, Property},
+    mailbox::{self, Role},
+};
+use jmap_mail::identity::{schema::Identity, set::JMAPSetIdentity};
+use jmap_sharing::principal::{account::JMAPAccountStore, set::JMAPSetPrincipal};
+use serde_json::json;
+use store::Store;
+
+use crate::{
+    tests::{
+        jmap_contacts::jmap_request,
+        jmap_mail::{
+            email_submission::{
+                assert_message_delivery, expect_nothing, spawn_mock_smtp_server, MockMessage,
+            },
+            lmtp::{AssertResult, SmtpConnection},
+        },
+        store::utils::StoreCompareWith,
+    },
+    JMAPServer,
+};
+
+const TEST_CERT: &str = r#"-----BEGIN CERTIFICATE-----
+MIIDJjCCAg6gAwIBAgIUaNJnyKFawWdDpBZrmp2VSumHwSgwDQYJKoZIhvcNAQEL
+BQAwEzERMA8GA1UEAwwISm9obiBEb2UwIBcNMjYxMDE4MjIyOTQxWhgPMjEyNjA5
+MjQyMjI5NDFaMBMxETAPBgNVBAMMCEpvaG4gRG9lMIIBIjANBgkqhkiG9w0BAQEF
+AAOCAQ8AMIIBCgKCAQEAxK7GuoxIPHTqnVj5BQRADs6ArwSg8HzOgAALBjTYAB2U
+V63kZvJUMAsY3DNmrT9d8+KZN3gFKqCiOhWWqESxl/OmyAWuYzBEUOfcYuO8IYja
+X4yzcSi7ULA8YCE7iRVZDW3inhukOyPG0oXlkM6rp5VXPA2sP6oNbHlNWGg6S4lw
+1nMtEj7XejDnUiSLnIjzb5yx90JOz938ddaxMxoY1O2gMZQLec+v9qX+Rqhysu7v
+0v641mn8o46+465VI0VPM+M4PKWsvr8nRrPcFxyYdxHHiOjVAOCtvMinBMS2glu2
+GIlzlCT3lRkXUP9pqAvo+xA18RUZq45eg1Eqo0ND+QIDAQABo3AwbjAdBgNVHQ4E
+FgQUc1EV+OHjvPZzjDxb7PhxI+iPAWYwHwYDVR0jBBgwFoAUc1EV+OHjvPZzjDxb
+7PhxI+iPAWYwDwYDVR0TAQH/BAUwAwEB/zAbBgNVHREEFDASgRBqZG9lQGV4YW1w
+bGUuY29tMA0GCSqGSIb3DQEBCwUAA4IBAQCq9DuZfCx9wmOY0X/B9tdyeONdqkVe
+e7Ezccokwyv0KbwZM8CZ4torkCWU+1ThrZ2/ZI7K46HWy/7mLwz9AKxZg7QaViW8
+1pQYTlHiOw84QPyzdLWfmS5Herv3BZShYHKTOwNNGX9ga3FepTdBnfw65/Gcp9Sz
+kLKF6CqYjDwsZIVzCqA6NiYEN1DGr8RYzff+MbPhaK26CRcVYE79OuD4y5k4L1Xt
+UnZCZPxEqwgoOrsLyOIP1KzA7nFgDeT5m0+wCfjkNBnK8MnxvJGpl3hKlvoOMROf
+pwFMlj4fhfvti93Ge8L9puyreOofBImvNg5bR44aK4g7TEXq35f6YwVp
+-----END CERTIFICATE-----"#;
+
+pub async fn test<T>(server: web::Data<JMAPServer<T>>, client: &mut Client)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    println!("Running SMTP submission tests...");
+    let (mut smtp_rx, smtp_settings) = spawn_mock_smtp_server();
+
+    let domain_id = client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .domain_create("example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let account_id = client
+        .individual_create("jdoe@example.com", "12345", "John Doe")
+        .await
+        .unwrap()
+        .take_id();
+    let identity_id = client
+        .set_default_account_id(&account_id)
+        .identity_create("John Doe", "jdoe@example.com")
+        .await
+        .unwrap()
+        .take_id();
+    let message = |subject: &str| {
+        format!(
+            concat!(
+                "From: jdoe@example.com\r\n",
+                "To: bill@remote.org\r\n",
+                "Subject: {}\r\n",
+                "\r\n",
+                "Test message.\r\n"
+            ),
+            subject
+        )
+    };
+
+    // Transactions can't be started before authenticating
+    let mut smtp = SmtpConnection::connect_submission().await;
+    smtp.ehlo().await.assert_contains("AUTH");
+    smtp.bdat("Hello", 5).await;
+    smtp.mail_from("jdoe@example.com", 5).await;
+    smtp.send(&format!(
+        "AUTH PLAIN {}",
+        base64::encode("\0jdoe@example.com\012345")
+    ))
+    .await;
+    smtp.read(1, 2).await;
+
+    // Chunks sent before MAIL FROM and RCPT TO are rejected
+    smtp.bdat("Hello", 5).await;
+    smtp.bdat_last("Hello", 1, 5).await;
+    smtp.mail_from("bill@example.com", 5)
+        .await
+        .assert_contains("not one of your identities");
+    smtp.mail_from("jdoe@example.com", 2).await;
+    smtp.bdat("Hello", 5).await;
+    smtp.rset().await;
+
+    // Without a Sent mailbox the message is relayed but no copy is kept
+    let sent_id = client
+        .mailbox_query(
+            mailbox::query::Filter::role(Role::Sent).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    client.mailbox_destroy(&sent_id, true).await.unwrap();
+    smtp.ingest(
+        "jdoe@example.com",
+        &["bill@remote.org"],
+        &message("No Sent mailbox"),
+    )
+    .await;
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<jdoe@example.com>",
+            ["<bill@remote.org>"],
+            "@Subject: No Sent mailbox",
+        ),
+        false,
+    )
+    .await;
+    assert_eq!(num_emails(client, None).await, 0);
+
+    // Once a Sent mailbox exists, copies are filed there
+    let sent_id = client
+        .mailbox_create("Sent Items", None::<String>, Role::Sent)
+        .await
+        .unwrap()
+        .take_id();
+    smtp.ingest_chunked(
+        "jdoe@example.com",
+        &["bill@remote.org"],
+        &message("Chunked"),
+        10,
+    )
+    .await;
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<jdoe@example.com>",
+            ["<bill@remote.org>"],
+            "@Subject: Chunked",
+        ),
+        false,
+    )
+    .await;
+    assert_eq!(num_emails(client, None).await, 1);
+    assert_eq!(num_emails(client, Some(&sent_id)).await, 1);
+    let email_id = client
+        .email_query(
+            email::query::Filter::in_mailbox(&sent_id).into(),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .pop()
+        .unwrap();
+    assert_eq!(
+        client
+            .email_get(&email_id, [Property::Keywords].into())
+            .await
+            .unwrap()
+            .unwrap()
+            .keywords(),
+        ["$seen"]
+    );
+
+    // Only inbound deliveries are encrypted, relayed messages and their
+    // Sent copies are kept in the clear
+    set_encryption_key(&server, &account_id, TEST_CERT.into()).await;
+    smtp.ingest(
+        "jdoe@example.com",
+        &["bill@remote.org"],
+        &message("Encrypted account"),
+    )
+    .await;
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<jdoe@example.com>",
+            ["<bill@remote.org>"],
+            "@Subject: Encrypted account\r\n\r\nTest message.\r\n",
+        ),
+        false,
+    )
+    .await;
+    let (sent_copy_id, sent_copy) = download_email(client, "Encrypted account").await;
+    assert!(
+        sent_copy.ends_with(&message("Encrypted account")),
+        "{}",
+        sent_copy
+    );
+
+    let mut lmtp = SmtpConnection::connect().await;
+    lmtp.ingest(
+        "bill@remote.org",
+        &["jdoe@example.com"],
+        concat!(
+            "From: bill@remote.org\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: Inbound\r\n",
+            "\r\n",
+            "Test message.\r\n"
+        ),
+    )
+    .await;
+    lmtp.quit().await;
+    let (inbound_id, inbound) = download_email(client, "Inbound").await;
+    assert!(
+        inbound.contains("Content-Type: application/pkcs7-mime")
+            && !inbound.contains("Test message."),
+        "{}",
+        inbound
+    );
+
+    client.email_destroy(&sent_copy_id).await.unwrap();
+    client.email_destroy(&inbound_id).await.unwrap();
+    set_encryption_key(&server, &account_id, None).await;
+
+    // Failed submissions don't leave a copy behind
+    smtp.mail_from("jdoe@example.com", 2).await;
+    smtp.rcpt_to("bill@remote.org", 2).await;
+    destroy_identity(&server, &account_id, &identity_id);
+    smtp.data(3).await;
+    smtp.data_bytes(&message("Unsubmitted"), 1, 5)
+        .await
+        .assert_contains("Failed to submit message");
+    expect_nothing(&mut smtp_rx).await;
+    assert_eq!(num_emails(client, None).await, 1);
+    assert_eq!(num_emails(client, Some(&sent_id)).await, 1);
+    smtp.quit().await;
+
+    // Stop the mock SMTP server
+    smtp_settings.lock().do_stop = true;
+    client
+        .identity_create("John Doe", "jdoe@example.com")
+        .await
+        .unwrap();
+    let mut smtp = SmtpConnection::connect_submission().await;
+    smtp.ehlo().await;
+    smtp.send(&format!(
+        "AUTH PLAIN {}",
+        base64::encode("\0jdoe@example.com\012345")
+    ))
+    .await;
+    smtp.read(1, 2).await;
+    smtp.ingest("jdoe@example.com", &["bill@remote.org"], &message("Stop"))
+        .await;
+    assert_message_delivery(
+        &mut smtp_rx,
+        MockMessage::new(
+            "<jdoe@example.com>",
+            ["<bill@remote.org>"],
+            "@Subject: Stop",
+        ),
+        false,
+    )
+    .await;
+
+    // Remove test data
+    client
+        .set_default_account_id(JMAPId::new(SUPERUSER_ID as u64))
+        .principal_destroy(&account_id)
+        .await
+        .unwrap();
+    client.principal_destroy(&domain_id).await.unwrap();
+    server.store.principal_purge().unwrap();
+    server.store.assert_is_empty();
+}
+
+async fn download_email(client: &mut Client, subject: &str) -> (String, String) {
+    for email_id in client
+        .email_query(None::<email::query::Filter>, None::<Vec<_>>)
+        .await
+        .unwrap()
+        .take_ids()
+    {
+        let email = client
+            .email_get(&email_id, [Property::Subject, Property::BlobId].into())
+            .await
+            .unwrap()
+            .unwrap();
+        if email.subject() == Some(subject) {
+            let blob = client.download(email.blob_id().unwrap()).await.unwrap();
+            return (email_id, String::from_utf8(blob).unwrap());
+        }
+    }
+    panic!("Email with subject {:?} not found.", subject);
+}
+
+async fn set_encryption_key<T>(
+    server: &web::Data<JMAPServer<T>>,
+    account_id: &str,
+    key: Option<&str>,
+) where
+    T: for<'x> Store<'x> + 'static,
+{
+    let response = jmap_request(
+        server,
+        None,
+        "Principal/set",
+        json!({
+            "accountId": JMAPId::new(SUPERUSER_ID as u64).to_string(),
+            "update": {
+                account_id: { "encryptionKey": key }
+            }
+        }),
+    )
+    .await
+    .unwrap();
+    assert!(response["notUpdated"].is_null(), "{}", response);
+}
+
+async fn num_emails(client: &mut Client, mailbox_id: Option<&str>) -> usize {
+    client
+        .email_query(
+            mailbox_id.map(email::query::Filter::in_mailbox),
+            None::<Vec<_>>,
+        )
+        .await
+        .unwrap()
+        .take_ids()
+        .len()
+}
+
+fn destroy_identity<T>(server: &JMAPServer<T>, account_id: &str, identity_id: &str)
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let mut request: SetRequest<Identity> = serde_json::from_value(json!({
+        "accountId": account_id,
+        "destroy": [identity_id]
+    }))
+    .unwrap();
+    request.acl = server
+        .store
+        .get_acl_token(JMAPId::parse(account_id).unwrap().get_document_id())
+        .unwrap()
+        .into();
+    let response = server.store.identity_set(request).unwrap();
+    assert!(
+        response.not_destroyed.is_empty(),
+        "{:?}",
+        response.not_destroyed
+    );
+}
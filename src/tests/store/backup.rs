@@ -0,0 +1,142 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::{
+    backup::list_backups,
+    blob::BlobId,
+    config::jmap::JMAPConfig,
+    core::collection::Collection,
+    log::raft::{LogIndex, RaftId},
+    write::batch::WriteBatch,
+    JMAPStore, Store,
+};
+
+use super::utils::{destroy_temp_dir, init_settings};
+
+pub fn test<T>()
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let (mut settings, temp_dir) = init_settings("strdb_backup", 1, 1, true);
+    let backup_path = temp_dir.join("backups");
+    settings.set_value(
+        "backup-path".to_string(),
+        backup_path.to_str().unwrap().to_string(),
+    );
+    let db = JMAPStore::new(
+        T::open(&settings).unwrap(),
+        JMAPConfig::from(&settings),
+        &settings,
+    );
+
+    // Full backup
+    let blob_1 = BlobId::new_external(&[b'a'; 1024]);
+    db.blob_store(&blob_1, vec![b'a'; 1024]).unwrap();
+    let mut batch = WriteBatch::new(1);
+    batch.log_insert(Collection::Mail, 1);
+    db.write(batch).unwrap();
+    let full_backup = db.backup(false).unwrap().unwrap();
+    assert_eq!(full_backup.manifest.base, None);
+    assert_eq!(full_backup.manifest.files_linked, 0);
+    assert!(db.backup(true).unwrap().is_none());
+
+    // Incremental backup
+    let blob_2 = BlobId::new_external(&[b'b'; 1024]);
+    db.blob_store(&blob_2, vec![b'b'; 1024]).unwrap();
+    let mut batch = WriteBatch::new(1);
+    batch.log_insert(Collection::Mail, 2);
+    db.write(batch).unwrap();
+    let last_raft_id = db
+        .get_prev_raft_id(RaftId::new(LogIndex::MAX, LogIndex::MAX))
+        .unwrap()
+        .unwrap();
+    let incremental_backup = db.backup(true).unwrap().unwrap();
+    assert_eq!(
+        incremental_backup.manifest.base,
+        Some(full_backup.name.clone())
+    );
+    assert_eq!(incremental_backup.manifest.raft_id, last_raft_id);
+    assert!(incremental_backup.manifest.files_linked > 0);
+
+    // Backups taken within the same second do not collide
+    let full_backup_2 = db.backup(false).unwrap().unwrap();
+    let full_backup_3 = db.backup(false).unwrap().unwrap();
+    assert_ne!(full_backup_2.name, full_backup_3.name);
+    assert_eq!(
+        list_backups(&backup_path)
+            .unwrap()
+            .into_iter()
+            .map(|backup| backup.name)
+            .collect::<Vec<_>>(),
+        vec![
+            full_backup.name.clone(),
+            incremental_backup.name.clone(),
+            full_backup_2.name,
+            full_backup_3.name.clone()
+        ]
+    );
+    db.db.close().unwrap();
+    drop(db);
+
+    // Point-in-time restore
+    let backup = JMAPStore::<T>::restore(
+        &settings,
+        &backup_path,
+        full_backup.manifest.raft_id.index.into(),
+    )
+    .unwrap();
+    assert_eq!(backup.name, full_backup.name);
+    let db = JMAPStore::new(
+        T::open(&settings).unwrap(),
+        JMAPConfig::from(&settings),
+        &settings,
+    );
+    assert_eq!(
+        db.get_prev_raft_id(RaftId::new(LogIndex::MAX, LogIndex::MAX))
+            .unwrap()
+            .unwrap(),
+        full_backup.manifest.raft_id
+    );
+    assert!(db.blob_get(&blob_1).unwrap().is_some());
+    assert!(db.blob_get(&blob_2).unwrap().is_none());
+    db.db.close().unwrap();
+    drop(db);
+
+    // Restore latest backup
+    let backup = JMAPStore::<T>::restore(&settings, &backup_path, None).unwrap();
+    assert_eq!(backup.name, full_backup_3.name);
+    let db = JMAPStore::new(
+        T::open(&settings).unwrap(),
+        JMAPConfig::from(&settings),
+        &settings,
+    );
+    assert_eq!(
+        db.get_prev_raft_id(RaftId::new(LogIndex::MAX, LogIndex::MAX))
+            .unwrap()
+            .unwrap(),
+        last_raft_id
+    );
+    assert!(db.blob_get(&blob_2).unwrap().is_some());
+
+    destroy_temp_dir(&temp_dir);
+}
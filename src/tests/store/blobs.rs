@@ -146,11 +146,12 @@ where
         let mut blob_link_count = u32::MAX;
         let mut blob_ephemeral_count = u32::MAX;
 
-        for (key, _) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Blobs, &[], Direction::Forward)
             .unwrap()
         {
+            let (key, _) = item.unwrap();
             if key[..BLOB_HASH_LEN + 1] != blob_id {
                 if blob_link_count != u32::MAX {
                     result.insert(
@@ -0,0 +1,255 @@
+/*
+ * Copyright (c) 2020-2022, Stalwart Labs Ltd.
+ *
+ * This file is part of the Stalwart JMAP Server.
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU Affero General Public License as
+ * published by the Free Software Foundation, either version 3 of
+ * the License, or (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+ * GNU Affero General Public License for more details.
+ * in the LICENSE file at the top-level directory of this distribution.
+ * You should have received a copy of the GNU Affero General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ *
+ * You can be released from the requirements of the AGPLv3 license by
+ * purchasing a commercial license. Please contact licensing@stalw.art
+ * for more details.
+*/
+
+use store::{
+    blob::{local::LocalBlobStore, BlobId, BlobStore},
+    config::{env_settings::EnvSettings, jmap::JMAPConfig},
+    roaring::RoaringBitmap,
+    serialize::bitmap::set_clear_bits,
+    write::operation::WriteOperation,
+    ColumnFamily, Direction, JMAPStore, Store,
+};
+
+use super::utils::{destroy_temp_dir, init_settings};
+
+const OLD_KEY: &str = "old encryption key 0123456789";
+const NEW_KEY: &str = "new encryption key 0123456789";
+
+fn open_db<T>(settings: &EnvSettings) -> JMAPStore<T>
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    JMAPStore::new(
+        T::open(settings).unwrap(),
+        JMAPConfig::from(settings),
+        settings,
+    )
+}
+
+pub fn test<T>()
+where
+    T: for<'x> Store<'x> + 'static,
+{
+    let (mut settings, temp_dir) = init_settings("strdb_encryption", 1, 1, true);
+    settings.set_value("store-encryption-key".to_string(), OLD_KEY.to_string());
+    let db = open_db::<T>(&settings);
+
+    // Write bitmaps, counters and values through the encrypted operators
+    let bitmap_key = b"encrypted_bitmap".to_vec();
+    let counter_key = b"encrypted_counter".to_vec();
+    let value_key = b"encrypted_value".to_vec();
+    db.db
+        .write(vec![
+            WriteOperation::merge(
+                ColumnFamily::Bitmaps,
+                bitmap_key.clone(),
+                set_clear_bits([(1, true), (2, true), (3, true)].into_iter()),
+            ),
+            WriteOperation::merge(
+                ColumnFamily::Values,
+                counter_key.clone(),
+                10i64.to_le_bytes().to_vec(),
+            ),
+            WriteOperation::set(
+                ColumnFamily::Values,
+                value_key.clone(),
+                b"top secret".to_vec(),
+            ),
+        ])
+        .unwrap();
+    db.db
+        .write(vec![
+            WriteOperation::merge(
+                ColumnFamily::Bitmaps,
+                bitmap_key.clone(),
+                set_clear_bits([(2, false), (4, true)].into_iter()),
+            ),
+            WriteOperation::merge(
+                ColumnFamily::Values,
+                counter_key.clone(),
+                (-3i64).to_le_bytes().to_vec(),
+            ),
+        ])
+        .unwrap();
+
+    let blob = (0..50000u32).map(|n| (n % 251) as u8).collect::<Vec<_>>();
+    let blob_id = BlobId::new_external(&blob);
+    db.blob_store(&blob_id, blob.clone()).unwrap();
+
+    let assert_contents = |db: &JMAPStore<T>| {
+        assert_eq!(
+            db.db
+                .get::<RoaringBitmap>(ColumnFamily::Bitmaps, &bitmap_key)
+                .unwrap()
+                .unwrap(),
+            RoaringBitmap::from_iter([1, 3, 4])
+        );
+        assert_eq!(
+            db.db
+                .get::<i64>(ColumnFamily::Values, &counter_key)
+                .unwrap()
+                .unwrap(),
+            7
+        );
+        assert_eq!(
+            db.db
+                .get::<Vec<u8>>(ColumnFamily::Values, &value_key)
+                .unwrap()
+                .unwrap(),
+            b"top secret"
+        );
+        let (key, value) = db
+            .db
+            .iterator(ColumnFamily::Values, &value_key, Direction::Forward)
+            .unwrap()
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(&key[..], &value_key[..]);
+        assert_eq!(&value[..], b"top secret");
+        assert_eq!(db.blob_get(&blob_id).unwrap().unwrap(), blob);
+        assert_eq!(
+            db.blob_get_range(&blob_id, 1000..1500).unwrap().unwrap(),
+            &blob[1000..1500]
+        );
+    };
+    assert_contents(&db);
+    db.db.close().unwrap();
+    drop(db);
+
+    // Rotate the key, values encrypted with the old key remain readable
+    settings.set_value("store-encryption-key".to_string(), NEW_KEY.to_string());
+    settings.set_value(
+        "store-encryption-previous-keys".to_string(),
+        OLD_KEY.to_string(),
+    );
+    let db = open_db::<T>(&settings);
+    assert_contents(&db);
+
+    // Re-encrypt everything with the new key
+    let (values, blobs) = db.reencrypt().unwrap();
+    assert!(values >= 3, "{}", values);
+    assert_eq!(blobs, 1);
+    assert_contents(&db);
+    assert_eq!(db.reencrypt().unwrap(), (0, 0));
+    db.db.close().unwrap();
+    drop(db);
+
+    // The old key is no longer needed
+    settings.set_value("store-encryption-previous-keys".to_string(), "".to_string());
+    let db = open_db::<T>(&settings);
+    assert_contents(&db);
+    db.db.close().unwrap();
+    drop(db);
+
+    // Stores encrypted with an unknown key or without a key can't be opened
+    settings.set_value("store-encryption-key".to_string(), OLD_KEY.to_string());
+    assert!(T::open(&settings).is_err());
+    assert!(LocalBlobStore::new(&settings).is_err());
+    let (settings, _) = init_settings("strdb_encryption", 1, 1, false);
+    assert!(T::open(&settings).is_err());
+    assert!(LocalBlobStore::new(&settings).is_err());
+
+    destroy_temp_dir(&temp_dir);
+
+    // Enabling encryption on an existing store
+    let (mut settings, temp_dir) = init_settings("strdb_encryption_migration", 1, 1, true);
+    let db = open_db::<T>(&settings);
+    assert!(!db.needs_reencryption());
+    let marked_key = b"marked_value".to_vec();
+    let marked_value = vec![0xe5; 64];
+    db.db
+        .write(vec![
+            WriteOperation::set(
+                ColumnFamily::Values,
+                value_key.clone(),
+                b"top secret".to_vec(),
+            ),
+            WriteOperation::set(
+                ColumnFamily::Values,
+                marked_key.clone(),
+                marked_value.clone(),
+            ),
+        ])
+        .unwrap();
+    db.blob_store(&blob_id, blob.clone()).unwrap();
+    db.db.close().unwrap();
+    drop(db);
+
+    // Unencrypted values remain readable until the store is re-encrypted,
+    // including those that look like encrypted values
+    settings.set_value("store-encryption-key".to_string(), NEW_KEY.to_string());
+    let assert_contents = |db: &JMAPStore<T>| {
+        assert_eq!(
+            db.db
+                .get::<Vec<u8>>(ColumnFamily::Values, &value_key)
+                .unwrap()
+                .unwrap(),
+            b"top secret"
+        );
+        assert_eq!(
+            db.db
+                .get::<Vec<u8>>(ColumnFamily::Values, &marked_key)
+                .unwrap()
+                .unwrap(),
+            marked_value
+        );
+        assert_eq!(db.blob_get(&blob_id).unwrap().unwrap(), blob);
+    };
+    let db = open_db::<T>(&settings);
+    assert!(db.needs_reencryption());
+    assert_contents(&db);
+    db.db.close().unwrap();
+    drop(db);
+
+    // The migration is resumed after a restart
+    let db = open_db::<T>(&settings);
+    assert!(db.needs_reencryption());
+    let (values, blobs) = db.reencrypt().unwrap();
+    assert!(values >= 2, "{}", values);
+    assert_eq!(blobs, 1);
+    assert!(!db.needs_reencryption());
+    assert_contents(&db);
+    db.db.close().unwrap();
+    drop(db);
+
+    let db = open_db::<T>(&settings);
+    assert!(!db.needs_reencryption());
+    assert_contents(&db);
+    db.db.close().unwrap();
+    drop(db);
+
+    // Stores that require encryption refuse to start without a key
+    let (mut settings, temp_dir_required) = init_settings("strdb_encryption_required", 1, 1, true);
+    settings.set_value("store-encryption-required".to_string(), "true".to_string());
+    assert!(T::open(&settings).is_err());
+    assert!(LocalBlobStore::new(&settings).is_err());
+    settings.set_value("store-encryption-key".to_string(), NEW_KEY.to_string());
+    let db = open_db::<T>(&settings);
+    assert!(!db.needs_reencryption());
+    db.db.close().unwrap();
+    drop(db);
+
+    destroy_temp_dir(&temp_dir);
+    destroy_temp_dir(&temp_dir_required);
+}
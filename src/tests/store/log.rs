@@ -131,11 +131,12 @@ where
     let mut total_change_entries = 0;
     let mut total_raft_entries = 0;
 
-    for (key, _) in mail_store
+    for item in mail_store
         .db
         .iterator(ColumnFamily::Logs, &[0], Direction::Forward)
         .unwrap()
     {
+        let (key, _) = item.unwrap();
         match key[0] {
             LogKey::CHANGE_KEY_PREFIX => {
                 total_change_entries += 1;
@@ -178,10 +179,11 @@ where
         };
         let prefix = &[LogKey::RAFT_KEY_PREFIX];
 
-        for (key, value) in self
+        for item in self
             .db
             .iterator(ColumnFamily::Logs, &key, Direction::Forward)?
         {
+            let (key, value) = item?;
             if key.starts_with(prefix) {
                 let raft_id = LogKey::deserialize_raft(&key).ok_or_else(|| {
                     StoreError::InternalError(format!("Corrupted raft entry for [{:?}]", key))
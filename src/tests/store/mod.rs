@@ -21,7 +21,9 @@
  * for more details.
 */
 
+pub mod backup;
 pub mod blobs;
+pub mod encryption;
 pub mod log;
 pub mod query;
 pub mod utils;
@@ -73,3 +75,15 @@ fn store_tests() {
 
     destroy_temp_dir(&temp_dir);
 }
+
+#[test]
+#[ignore]
+fn backup_tests() {
+    backup::test::<RocksDB>();
+}
+
+#[test]
+#[ignore]
+fn encryption_tests() {
+    encryption::test::<RocksDB>();
+}
@@ -28,10 +28,16 @@ use flate2::bufread::GzDecoder;
 use jmap::orm::TinyORM;
 use jmap::principal::schema::Principal;
 use jmap::push_subscription::schema::PushSubscription;
+use jmap::share_notification::schema::ShareNotification;
+use jmap_calendars::calendar::schema::Calendar;
+use jmap_calendars::calendar_event::schema::CalendarEvent;
+use jmap_contacts::address_book::schema::AddressBook;
+use jmap_contacts::contact_card::schema::ContactCard;
 use jmap_mail::email_submission::schema::EmailSubmission;
 use jmap_mail::identity::schema::Identity;
 use jmap_mail::mail::schema::Email;
 use jmap_mail::mailbox::schema::Mailbox;
+use jmap_mail::saved_search::schema::SavedSearch;
 use jmap_mail::vacation_response::schema::VacationResponse;
 use store::ahash::AHashSet;
 use store::serialize::key::ValueKey;
@@ -42,7 +48,7 @@ use store::{
     core::collection::Collection,
     roaring::RoaringBitmap,
     serialize::{
-        key::{FOLLOWER_COMMIT_INDEX_KEY, LEADER_COMMIT_INDEX_KEY},
+        key::{ENCRYPTION_STATE_KEY, FOLLOWER_COMMIT_INDEX_KEY, LEADER_COMMIT_INDEX_KEY},
         StoreDeserialize,
     },
     AccountId, ColumnFamily, JMAPStore, Store,
@@ -176,12 +182,23 @@ pub fn init_settings(
                 format!("http://127.0.0.1:{}", 8000 + peer_num),
             ),
             ("lmtp-port".to_string(), (11200 + peer_num).to_string()),
+            (
+                "smtp-submission-port".to_string(),
+                (11300 + peer_num).to_string(),
+            ),
+            (
+                "smtp-submission-plaintext-auth".to_string(),
+                "true".to_string(),
+            ),
+            ("imap-port".to_string(), (11400 + peer_num).to_string()),
+            ("imap-plaintext-auth".to_string(), "true".to_string()),
             ("max-objects-in-set".to_string(), "100000".to_string()),
             ("query-max-results".to_string(), "100000".to_string()),
             ("jmap-port".to_string(), (8000 + peer_num).to_string()),
             ("smtp-relay-host".to_string(), "127.0.0.1".to_string()),
             ("smtp-relay-port".to_string(), "9999".to_string()),
             ("smtp-relay-tls".to_string(), "false".to_string()),
+            ("submission-sent-copy".to_string(), "delegate".to_string()),
             ("max-concurrent-uploads".to_string(), "4".to_string()),
             ("max-concurrent-requests".to_string(), "8".to_string()),
             ("push-attempt-interval".to_string(), "500".to_string()),
@@ -200,6 +217,10 @@ pub fn init_settings(
                 "1000/60".to_string(),
             ),
             ("max-size-upload".to_string(), "50000000".to_string()),
+            (
+                "mailbox-retention".to_string(),
+                "archive max-age=1d move=trash".to_string(),
+            ),
             (
                 "encryption-key".to_string(),
                 "parerga_und_paralipomena".to_string(),
@@ -280,11 +301,12 @@ where
             ColumnFamily::Logs,
             ColumnFamily::Blobs,
         ] {
-            for (key, value) in self
+            for item in self
                 .db
                 .iterator(cf, &[0u8], store::Direction::Forward)
                 .unwrap()
             {
+                let (key, value) = item.unwrap();
                 match cf {
                     ColumnFamily::Bitmaps => {
                         let account_id = key[key.len() - 1] as AccountId;
@@ -342,6 +364,7 @@ where
                         if (0..=9).contains(&key[0])
                             && &key[..] != FOLLOWER_COMMIT_INDEX_KEY
                             && &key[..] != LEADER_COMMIT_INDEX_KEY
+                            && &key[..] != ENCRYPTION_STATE_KEY
                         {
                             let (account_id, pos) = key.read_leb128().unwrap();
                             let collection = key[pos].into();
@@ -431,6 +454,43 @@ where
                                                 )
                                                 .unwrap()
                                             ),
+                                            Collection::SavedSearch => assert_eq!(
+                                                TinyORM::<SavedSearch>::deserialize(&value)
+                                                    .unwrap(),
+                                                TinyORM::<SavedSearch>::deserialize(&other_value)
+                                                    .unwrap()
+                                            ),
+                                            Collection::AddressBook => assert_eq!(
+                                                TinyORM::<AddressBook>::deserialize(&value)
+                                                    .unwrap(),
+                                                TinyORM::<AddressBook>::deserialize(&other_value)
+                                                    .unwrap()
+                                            ),
+                                            Collection::ContactCard => assert_eq!(
+                                                TinyORM::<ContactCard>::deserialize(&value)
+                                                    .unwrap(),
+                                                TinyORM::<ContactCard>::deserialize(&other_value)
+                                                    .unwrap()
+                                            ),
+                                            Collection::Calendar => assert_eq!(
+                                                TinyORM::<Calendar>::deserialize(&value).unwrap(),
+                                                TinyORM::<Calendar>::deserialize(&other_value)
+                                                    .unwrap()
+                                            ),
+                                            Collection::CalendarEvent => assert_eq!(
+                                                TinyORM::<CalendarEvent>::deserialize(&value)
+                                                    .unwrap(),
+                                                TinyORM::<CalendarEvent>::deserialize(&other_value)
+                                                    .unwrap()
+                                            ),
+                                            Collection::ShareNotification => assert_eq!(
+                                                TinyORM::<ShareNotification>::deserialize(&value)
+                                                    .unwrap(),
+                                                TinyORM::<ShareNotification>::deserialize(
+                                                    &other_value
+                                                )
+                                                .unwrap()
+                                            ),
                                             Collection::Thread | Collection::None => unreachable!(),
                                         }
                                     } else if ASSERT {
@@ -619,11 +679,12 @@ where
             ColumnFamily::Indexes,
         ] {
             let mut total_keys = 0;
-            for (key, value) in self
+            for item in self
                 .db
                 .iterator(cf, &[0u8], store::Direction::Forward)
                 .unwrap()
             {
+                let (key, value) = item.unwrap();
                 total_keys += 1;
                 match cf {
                     ColumnFamily::Bitmaps => {